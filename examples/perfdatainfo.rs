@@ -68,7 +68,9 @@ fn main() {
 
     while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
         match record {
-            PerfFileRecord::EventRecord { attr_index, record } => {
+            PerfFileRecord::EventRecord {
+                attr_index, record, ..
+            } => {
                 let record_type = record.record_type;
                 *event_record_map
                     .entry(attr_index)
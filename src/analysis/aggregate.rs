@@ -0,0 +1,191 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::DsoKey;
+
+/// A symbol in an [`AggregatedProfile`].
+///
+/// Symbolication is left to the caller. For frames which couldn't be
+/// symbolicated, `name` can be the hex address or any other placeholder.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ProfileSymbol {
+    /// The DSO which contains the symbol, if known.
+    pub dso: Option<DsoKey>,
+    pub name: String,
+}
+
+/// The self ("exclusive") and total ("inclusive") weight of a symbol or DSO.
+///
+/// The self weight counts the samples whose leaf frame is in the symbol. The
+/// total weight counts the samples with the symbol anywhere on the stack,
+/// counting each sample once even if the symbol appears multiple times, e.g.
+/// due to recursion.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProfileWeight {
+    pub self_weight: u64,
+    pub total_weight: u64,
+}
+
+/// Sample weights aggregated per symbol and per DSO, for one event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AggregatedProfile {
+    sample_count: u64,
+    has_callchains: bool,
+    total_weight: u64,
+    symbols: HashMap<ProfileSymbol, ProfileWeight>,
+    dsos: HashMap<DsoKey, ProfileWeight>,
+    build_ids: HashMap<DsoKey, Vec<u8>>,
+}
+
+impl AggregatedProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the build ID of a DSO, e.g. from
+    /// [`PerfFile::build_ids`](crate::PerfFile::build_ids). Build IDs are
+    /// used to match DSOs when comparing profiles.
+    pub fn set_build_id(&mut self, dso: DsoKey, build_id: Vec<u8>) {
+        self.build_ids.insert(dso, build_id);
+    }
+
+    /// Adds a sample with the weight `weight`. `frames` is the symbolicated
+    /// stack, starting with the leaf frame. If the sample has no callchain,
+    /// pass just the sampled frame.
+    pub fn add_sample(&mut self, weight: u64, frames: &[ProfileSymbol]) {
+        self.sample_count += 1;
+        self.total_weight += weight;
+        let Some(leaf) = frames.first() else {
+            return;
+        };
+        self.has_callchains |= frames.len() > 1;
+        self.symbols.entry(leaf.clone()).or_default().self_weight += weight;
+        if let Some(dso) = &leaf.dso {
+            self.dsos.entry(dso.clone()).or_default().self_weight += weight;
+        }
+        let mut seen_symbols = HashSet::new();
+        let mut seen_dsos = HashSet::new();
+        for frame in frames {
+            if seen_symbols.insert(frame) {
+                self.symbols.entry(frame.clone()).or_default().total_weight += weight;
+            }
+            if let Some(dso) = &frame.dso {
+                if seen_dsos.insert(dso) {
+                    self.dsos.entry(dso.clone()).or_default().total_weight += weight;
+                }
+            }
+        }
+    }
+
+    /// The number of samples.
+    pub fn sample_count(&self) -> u64 {
+        self.sample_count
+    }
+
+    /// Whether any sample had more than one frame. Without callchains, the
+    /// total weights are the same as the self weights.
+    pub fn has_callchains(&self) -> bool {
+        self.has_callchains
+    }
+
+    /// The summed weight of all samples.
+    pub fn total_weight(&self) -> u64 {
+        self.total_weight
+    }
+
+    pub fn symbols(&self) -> &HashMap<ProfileSymbol, ProfileWeight> {
+        &self.symbols
+    }
+
+    pub fn dsos(&self) -> &HashMap<DsoKey, ProfileWeight> {
+        &self.dsos
+    }
+
+    /// The build ID of a DSO, if it was set with [`set_build_id`](Self::set_build_id).
+    pub fn build_id(&self, dso: &DsoKey) -> Option<&[u8]> {
+        self.build_ids.get(dso).map(Vec::as_slice)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AggregatedProfile, ProfileSymbol, ProfileWeight};
+    use crate::DsoKey;
+
+    fn user_dso(path: &str) -> DsoKey {
+        DsoKey::User {
+            file_name: path.rsplit('/').next().unwrap().to_string(),
+            full_path: path.as_bytes().to_vec(),
+        }
+    }
+
+    fn symbol(dso: Option<&DsoKey>, name: &str) -> ProfileSymbol {
+        ProfileSymbol {
+            dso: dso.cloned(),
+            name: name.to_string(),
+        }
+    }
+
+    fn weight(self_weight: u64, total_weight: u64) -> ProfileWeight {
+        ProfileWeight {
+            self_weight,
+            total_weight,
+        }
+    }
+
+    #[test]
+    fn merge_stacks() {
+        let app = user_dso("/bin/app");
+        let libc = user_dso("/usr/lib/libc.so.6");
+        let main = symbol(Some(&app), "main");
+        let parse = symbol(Some(&app), "parse");
+        let memcpy = symbol(Some(&libc), "memcpy");
+
+        let mut profile = AggregatedProfile::new();
+        assert!(!profile.has_callchains());
+        profile.add_sample(2, &[memcpy.clone(), parse.clone(), main.clone()]);
+        // Recursion: parse is only counted once in its total weight.
+        profile.add_sample(3, &[parse.clone(), parse.clone(), main.clone()]);
+        profile.add_sample(5, std::slice::from_ref(&main));
+
+        assert_eq!(profile.sample_count(), 3);
+        assert_eq!(profile.total_weight(), 10);
+        assert!(profile.has_callchains());
+        assert_eq!(profile.symbols().len(), 3);
+        assert_eq!(profile.symbols()[&memcpy], weight(2, 2));
+        assert_eq!(profile.symbols()[&parse], weight(3, 5));
+        assert_eq!(profile.symbols()[&main], weight(5, 10));
+    }
+
+    #[test]
+    fn dso_totals() {
+        let app = user_dso("/bin/app");
+        let libc = user_dso("/usr/lib/libc.so.6");
+
+        let mut profile = AggregatedProfile::new();
+        profile.add_sample(
+            1,
+            &[
+                symbol(Some(&libc), "memcpy"),
+                symbol(Some(&libc), "qsort"),
+                symbol(Some(&app), "main"),
+            ],
+        );
+        profile.add_sample(4, &[symbol(Some(&app), "main")]);
+        // Frames without a DSO only count for their symbol.
+        profile.add_sample(2, &[symbol(None, "0x1234"), symbol(Some(&app), "main")]);
+        // A sample without frames only counts for the totals.
+        profile.add_sample(7, &[]);
+
+        assert_eq!(profile.sample_count(), 4);
+        assert_eq!(profile.total_weight(), 14);
+        assert_eq!(profile.dsos().len(), 2);
+        // libc appears twice on the first stack, but is counted once.
+        assert_eq!(profile.dsos()[&libc], weight(1, 1));
+        assert_eq!(profile.dsos()[&app], weight(4, 7));
+        assert_eq!(profile.symbols()[&symbol(None, "0x1234")], weight(2, 2));
+
+        assert_eq!(profile.build_id(&app), None);
+        profile.set_build_id(app.clone(), vec![1, 2, 3]);
+        assert_eq!(profile.build_id(&app), Some(&[1, 2, 3][..]));
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{AuxRecord, AuxtraceRecord};
+
+/// Identifies an aux buffer. With `perf record` in its default mode there
+/// is one aux buffer per CPU; with `--per-thread` there is one per thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AuxBuffer {
+    Cpu(u32),
+    Thread(i32),
+}
+
+impl AuxBuffer {
+    /// The aux buffer which the data of an AUXTRACE record was copied from.
+    pub fn of_auxtrace(record: &AuxtraceRecord) -> Self {
+        match record.cpu() {
+            Some(cpu) => Self::Cpu(cpu),
+            None => Self::Thread(record.tid),
+        }
+    }
+}
+
+/// A range of aux data from one `PERF_RECORD_AUX` record, together with the
+/// bytes of that range which were found in `PERF_RECORD_AUXTRACE` records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuxSegment {
+    /// The aux buffer which the data was written to.
+    pub buffer: AuxBuffer,
+    /// The timestamp of the AUX record, i.e. when the kernel finished writing
+    /// the data.
+    pub time: Option<u64>,
+    pub aux: AuxRecord,
+    /// The bytes of the range, up to the first byte which wasn't found in the
+    /// AUXTRACE records.
+    pub data: Vec<u8>,
+    /// The number of bytes between the end of the previous segment and the
+    /// start of this one, which the kernel skipped, e.g. because the aux
+    /// buffer was full.
+    pub lost_before: u64,
+}
+
+impl AuxSegment {
+    /// The number of bytes of the range which are missing from [`data`](Self::data).
+    pub fn missing_bytes(&self) -> u64 {
+        self.aux.aux_size.saturating_sub(self.data.len() as u64)
+    }
+
+    /// Returns whether the decoder can decode this segment without a resync:
+    /// there's no gap before it, all its bytes are present, and neither the
+    /// kernel nor the hardware reported lost data.
+    pub fn is_contiguous(&self) -> bool {
+        self.lost_before == 0
+            && self.missing_bytes() == 0
+            && !self.aux.is_truncated()
+            && !self.aux.is_partial()
+            && !self.aux.has_collision()
+    }
+}
+
+/// Combines `PERF_RECORD_AUX` records, which say when and where aux data was
+/// produced, with `PERF_RECORD_AUXTRACE` records, which carry the data, into
+/// a stream of segments per aux buffer, annotated with gaps.
+///
+/// This is the input that aux decoders, e.g. for Intel PT or ARM SPE, need:
+/// the bytes of each segment, its time, and whether the decoder has to
+/// resynchronize before it.
+///
+/// Each segment is returned as soon as its AUX record and its data have both
+/// been added, and the data is dropped once it has been returned. The
+/// segments of each aux buffer are returned in aux offset order. perf copies
+/// the aux data in order, so a segment whose data is incomplete is returned
+/// once data after the gap arrives, or by [`AuxTimelines::finish`].
+#[derive(Debug, Clone, Default)]
+pub struct AuxTimelines {
+    buffers: BTreeMap<AuxBuffer, BufferState>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct BufferState {
+    /// The AUX records whose segments haven't been returned yet, ordered by
+    /// aux offset.
+    pending: VecDeque<(Option<u64>, AuxRecord)>,
+    /// The AUXTRACE data which hasn't been consumed yet, by aux offset.
+    chunks: BTreeMap<u64, Vec<u8>>,
+    /// The end of the previous segment.
+    previous_end: Option<u64>,
+    /// The end of the data which has been returned in segments so far.
+    covered_end: u64,
+    /// The number of bytes from AUXTRACE records.
+    added_bytes: u64,
+    /// The number of bytes from AUXTRACE records which have been returned in
+    /// segments. Bytes in overlapping segments are only counted once.
+    matched_bytes: u64,
+}
+
+impl AuxTimelines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an AUX record, and returns the segments which are complete now.
+    ///
+    /// `buffer` is the aux buffer of the record's event: the CPU from the
+    /// record's sample ID for per-CPU events, or the thread for per-thread
+    /// events.
+    pub fn add_aux(
+        &mut self,
+        buffer: AuxBuffer,
+        time: Option<u64>,
+        record: &AuxRecord,
+    ) -> Vec<AuxSegment> {
+        if record.aux_size == 0 {
+            return Vec::new();
+        }
+        let state = self.buffers.entry(buffer).or_default();
+        let index = state
+            .pending
+            .partition_point(|(_, pending)| pending.aux_offset <= record.aux_offset);
+        state.pending.insert(index, (time, *record));
+        state.take_ready_segments(buffer, false)
+    }
+
+    /// Adds the data of an AUXTRACE record, and returns the segments which
+    /// are complete now.
+    pub fn add_auxtrace(&mut self, record: &AuxtraceRecord) -> Vec<AuxSegment> {
+        if record.data.is_empty() {
+            return Vec::new();
+        }
+        let buffer = AuxBuffer::of_auxtrace(record);
+        let state = self.buffers.entry(buffer).or_default();
+        let data = record.data.as_slice().into_owned();
+        state.added_bytes += data.len() as u64;
+        if let Some(replaced) = state.chunks.insert(record.offset, data) {
+            state.added_bytes -= replaced.len() as u64;
+        }
+        state.take_ready_segments(buffer, false)
+    }
+
+    /// Returns the segments which are still waiting for data, with the data
+    /// which was found for them, ordered by aux buffer. The remaining
+    /// AUXTRACE data is dropped.
+    pub fn finish(&mut self) -> Vec<AuxSegment> {
+        let mut segments = Vec::new();
+        for (buffer, state) in &mut self.buffers {
+            segments.extend(state.take_ready_segments(*buffer, true));
+            state.chunks.clear();
+        }
+        segments
+    }
+
+    /// The aux buffers which records have been added for, in order.
+    pub fn buffers(&self) -> impl Iterator<Item = AuxBuffer> + '_ {
+        self.buffers.keys().copied()
+    }
+
+    /// The number of bytes from AUXTRACE records for `buffer` which haven't
+    /// been returned in any segment, e.g. in snapshot mode, where there are
+    /// no AUX records. Before [`AuxTimelines::finish`], this includes the
+    /// data which is still waiting for its AUX record.
+    pub fn unmatched_bytes(&self, buffer: AuxBuffer) -> u64 {
+        self.buffers
+            .get(&buffer)
+            .map_or(0, |state| state.added_bytes - state.matched_bytes)
+    }
+}
+
+impl BufferState {
+    /// Removes and returns the segments at the front of `pending` whose data
+    /// is complete, or won't become more complete. If `finish` is true, all
+    /// pending segments are returned.
+    fn take_ready_segments(&mut self, buffer: AuxBuffer, finish: bool) -> Vec<AuxSegment> {
+        let mut segments = Vec::new();
+        while let Some(&(time, aux)) = self.pending.front() {
+            let start = aux.aux_offset;
+            let end = start.saturating_add(aux.aux_size);
+            let data = copy_range(&self.chunks, start, end);
+            let data_end = start + data.len() as u64;
+            // The data is copied in order, so if there is data after the
+            // first missing byte, the missing bytes won't arrive anymore.
+            if data_end < end && !finish && self.chunks.range(data_end..).next().is_none() {
+                break;
+            }
+            self.pending.pop_front();
+
+            self.matched_bytes += data_end.saturating_sub(start.max(self.covered_end));
+            self.covered_end = self.covered_end.max(data_end);
+            // Drop the chunks which have been consumed completely.
+            while let Some(entry) = self.chunks.first_entry() {
+                if entry.key().saturating_add(entry.get().len() as u64) > self.covered_end {
+                    break;
+                }
+                entry.remove();
+            }
+
+            let lost_before = self
+                .previous_end
+                .map_or(0, |previous_end| start.saturating_sub(previous_end));
+            self.previous_end = Some(end);
+            segments.push(AuxSegment {
+                buffer,
+                time,
+                aux,
+                data,
+                lost_before,
+            });
+        }
+        segments
+    }
+}
+
+/// Copies the bytes in `start..end` from the chunks, which are keyed by
+/// their offset, stopping at the first byte which isn't in any chunk.
+fn copy_range(chunks: &BTreeMap<u64, Vec<u8>>, start: u64, end: u64) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut pos = start;
+    let first = chunks.range(..=start).next_back();
+    let rest = chunks.range(start.saturating_add(1)..end);
+    for (chunk_offset, chunk) in first.into_iter().chain(rest) {
+        let chunk_end = chunk_offset.saturating_add(chunk.len() as u64);
+        if *chunk_offset > pos {
+            break;
+        }
+        if chunk_end <= pos {
+            continue;
+        }
+        let copy_end = chunk_end.min(end);
+        data.extend_from_slice(
+            &chunk[(pos - chunk_offset) as usize..(copy_end - chunk_offset) as usize],
+        );
+        pos = copy_end;
+        if pos == end {
+            break;
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use linux_perf_event_reader::RawData;
+
+    use super::{AuxBuffer, AuxTimelines};
+    use crate::{AuxRecord, AuxtraceRecord};
+
+    fn auxtrace(cpu: u32, offset: u64, data: &[u8]) -> AuxtraceRecord<'_> {
+        AuxtraceRecord {
+            offset,
+            reference: 0,
+            idx: cpu,
+            tid: -1,
+            cpu,
+            data: RawData::from(data),
+        }
+    }
+
+    fn aux(aux_offset: u64, aux_size: u64, flags: u64) -> AuxRecord {
+        AuxRecord {
+            aux_offset,
+            aux_size,
+            flags,
+        }
+    }
+
+    #[test]
+    fn segments_and_gaps() {
+        let cpu1 = AuxBuffer::Cpu(1);
+        let mut timelines = AuxTimelines::new();
+        assert!(timelines.add_aux(cpu1, Some(10), &aux(0, 4, 0)).is_empty());
+        // The buffer was full after the second segment (TRUNCATED), and the
+        // third segment starts after a gap of 2 bytes.
+        assert!(timelines.add_aux(cpu1, Some(20), &aux(4, 4, 1)).is_empty());
+        assert!(timelines.add_aux(cpu1, Some(30), &aux(10, 4, 0)).is_empty());
+
+        // perf copied the data in chunks which don't match the segments.
+        // Each segment is returned once its data is complete.
+        let segments = timelines.add_auxtrace(&auxtrace(1, 0, &[0, 1, 2, 3, 4, 5]));
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].buffer, cpu1);
+        assert_eq!(segments[0].data, vec![0, 1, 2, 3]);
+        assert!(segments[0].is_contiguous());
+        let segments = timelines.add_auxtrace(&auxtrace(1, 6, &[6, 7]));
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].data, vec![4, 5, 6, 7]);
+        assert!(!segments[0].is_contiguous());
+        // The consumed data has been dropped.
+        assert!(timelines.buffers[&cpu1].chunks.is_empty());
+
+        // The last segment is incomplete, so it is returned by finish().
+        assert!(timelines
+            .add_auxtrace(&auxtrace(1, 10, &[10, 11]))
+            .is_empty());
+        assert!(timelines.add_auxtrace(&auxtrace(2, 0, &[0; 8])).is_empty());
+        let segments = timelines.finish();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].time, Some(30));
+        assert_eq!(segments[0].lost_before, 2);
+        assert_eq!(segments[0].data, vec![10, 11]);
+        assert_eq!(segments[0].missing_bytes(), 2);
+
+        assert_eq!(
+            timelines.buffers().collect::<Vec<_>>(),
+            vec![cpu1, AuxBuffer::Cpu(2)]
+        );
+        assert_eq!(timelines.unmatched_bytes(cpu1), 0);
+        assert_eq!(timelines.unmatched_bytes(AuxBuffer::Cpu(2)), 8);
+    }
+
+    #[test]
+    fn data_after_gap_completes_segment() {
+        let cpu = AuxBuffer::Cpu(0);
+        let mut timelines = AuxTimelines::new();
+        timelines.add_aux(cpu, Some(10), &aux(0, 4, 0));
+        timelines.add_aux(cpu, Some(20), &aux(4, 4, 0));
+        assert!(timelines.add_auxtrace(&auxtrace(0, 0, &[0, 1])).is_empty());
+        // The bytes 2 and 3 are missing, and won't arrive after byte 4.
+        let segments = timelines.add_auxtrace(&auxtrace(0, 4, &[4, 5, 6, 7]));
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].missing_bytes(), 2);
+        assert_eq!(segments[1].data, vec![4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn overlapping_segments() {
+        let cpu = AuxBuffer::Cpu(0);
+        let mut timelines = AuxTimelines::new();
+        timelines.add_aux(cpu, Some(10), &aux(0, 4, 0));
+        timelines.add_aux(cpu, Some(20), &aux(2, 4, 0));
+        let segments = timelines.add_auxtrace(&auxtrace(0, 0, &[0, 1, 2, 3, 4, 5, 6, 7]));
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1].data, vec![2, 3, 4, 5]);
+        timelines.finish();
+        // The bytes 6 and 7 aren't in any segment; the overlap is only
+        // counted once.
+        assert_eq!(timelines.unmatched_bytes(cpu), 2);
+    }
+}
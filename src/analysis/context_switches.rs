@@ -0,0 +1,327 @@
+use std::collections::{BTreeMap, HashMap};
+
+use linux_perf_event_reader::{CommonData, ContextSwitchRecord, TaskWasPreempted};
+
+use super::OffCpuState;
+
+/// Context-switch counts, split into voluntary switches (the task blocked)
+/// and involuntary ones (the task was preempted).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContextSwitchCounts {
+    pub voluntary: u64,
+    pub preempted: u64,
+}
+
+impl ContextSwitchCounts {
+    pub fn total(&self) -> u64 {
+        self.voluntary + self.preempted
+    }
+
+    /// The number of switches per second over `duration` nanoseconds.
+    pub fn rate(&self, duration: u64) -> f64 {
+        if duration == 0 {
+            return 0.0;
+        }
+        self.total() as f64 * 1_000_000_000.0 / duration as f64
+    }
+
+    fn add(&mut self, preempted: bool) {
+        if preempted {
+            self.preempted += 1;
+        } else {
+            self.voluntary += 1;
+        }
+    }
+}
+
+/// The per-thread context-switch counts in a [`ContextSwitchReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadContextSwitches {
+    pub pid: i32,
+    pub tid: i32,
+    pub counts: ContextSwitchCounts,
+}
+
+/// Where the switches in a [`ContextSwitchReport`] came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContextSwitchSource {
+    /// `sched:sched_switch` tracepoint samples.
+    SchedSwitch,
+    /// `PERF_RECORD_SWITCH` or `PERF_RECORD_SWITCH_CPU_WIDE` records.
+    SwitchRecords,
+}
+
+/// A summary of the context switches in a capture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextSwitchReport {
+    pub source: ContextSwitchSource,
+    /// The counts across all threads.
+    pub total: ContextSwitchCounts,
+    /// The counts per thread, sorted by total count in descending order.
+    pub per_thread: Vec<ThreadContextSwitches>,
+    /// The counts per CPU, sorted by CPU. Empty if the records don't
+    /// contain the CPU.
+    pub per_cpu: Vec<(u32, ContextSwitchCounts)>,
+    /// The start time of the first time bucket. The buckets start at
+    /// multiples of the bucket duration.
+    pub start_time: u64,
+    /// The length of each time bucket in nanoseconds.
+    pub bucket_duration: u64,
+    /// The counts per time bucket, for plotting switch rates over time.
+    pub buckets: Vec<ContextSwitchCounts>,
+}
+
+impl ContextSwitchReport {
+    /// The switch rate (switches per second) of each time bucket.
+    pub fn bucket_rates(&self) -> impl Iterator<Item = f64> + '_ {
+        self.buckets
+            .iter()
+            .map(|counts| counts.rate(self.bucket_duration))
+    }
+}
+
+/// The maximum number of time buckets in a [`ContextSwitchReport`].
+pub const MAX_CONTEXT_SWITCH_BUCKET_COUNT: u64 = 1 << 20;
+
+/// An error from [`ContextSwitchReportBuilder`].
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextSwitchReportError {
+    #[error("The bucket duration is zero")]
+    ZeroBucketDuration,
+
+    #[error("The switches span {0} time buckets, more than the maximum of {MAX_CONTEXT_SWITCH_BUCKET_COUNT}")]
+    TooManyBuckets(u64),
+}
+
+#[derive(Debug, Clone, Default)]
+struct SourceCounts {
+    total: ContextSwitchCounts,
+    per_thread: HashMap<i32, (i32, ContextSwitchCounts)>,
+    per_cpu: HashMap<u32, ContextSwitchCounts>,
+    /// The counts of the buckets which have switches, by the index of the
+    /// bucket, i.e. the time divided by the bucket duration.
+    buckets: BTreeMap<u64, ContextSwitchCounts>,
+}
+
+impl SourceCounts {
+    fn add(&mut self, bucket: u64, cpu: Option<u32>, pid: i32, tid: i32, preempted: bool) {
+        self.total.add(preempted);
+        self.per_thread
+            .entry(tid)
+            .or_insert((pid, ContextSwitchCounts::default()))
+            .1
+            .add(preempted);
+        if let Some(cpu) = cpu {
+            self.per_cpu.entry(cpu).or_default().add(preempted);
+        }
+        self.buckets.entry(bucket).or_default().add(preempted);
+    }
+}
+
+/// Computes context-switch counts per thread, per CPU and over time.
+///
+/// Feed it the `PERF_RECORD_SWITCH` / `PERF_RECORD_SWITCH_CPU_WIDE` records
+/// with [`switch_record`](Self::switch_record), and the `sched:sched_switch`
+/// samples with [`sched_switch`](Self::sched_switch). If the capture contains
+/// both, the report is built from the tracepoints, because they also cover
+/// the kernel threads and tasks which were not profiled.
+#[derive(Debug, Clone)]
+pub struct ContextSwitchReportBuilder {
+    bucket_duration: u64,
+    sched_switch: SourceCounts,
+    switch_records: SourceCounts,
+}
+
+impl ContextSwitchReportBuilder {
+    /// Creates a builder which counts switches in time buckets of
+    /// `bucket_duration` nanoseconds.
+    ///
+    /// Returns an error if `bucket_duration` is zero.
+    pub fn new(bucket_duration: u64) -> Result<Self, ContextSwitchReportError> {
+        if bucket_duration == 0 {
+            return Err(ContextSwitchReportError::ZeroBucketDuration);
+        }
+        Ok(Self {
+            bucket_duration,
+            sched_switch: SourceCounts::default(),
+            switch_records: SourceCounts::default(),
+        })
+    }
+
+    /// Handles a `PERF_RECORD_SWITCH` or `PERF_RECORD_SWITCH_CPU_WIDE` record.
+    /// Only switch-out records are counted, so that each switch is counted
+    /// once. Records without pid, tid or timestamp are ignored.
+    pub fn switch_record(&mut self, record: &ContextSwitchRecord, common: &CommonData) {
+        let ContextSwitchRecord::Out { preempted, .. } = record else {
+            return;
+        };
+        let (Some(time), Some(pid), Some(tid)) = (common.timestamp, common.pid, common.tid) else {
+            return;
+        };
+        let preempted = *preempted == TaskWasPreempted::Yes;
+        let bucket = time / self.bucket_duration;
+        self.switch_records
+            .add(bucket, common.cpu, pid, tid, preempted);
+    }
+
+    /// Handles a `sched:sched_switch` sample which switched out `prev_pid`
+    /// with the state `prev_state`. Switches away from the idle task are
+    /// not counted.
+    pub fn sched_switch(
+        &mut self,
+        time: u64,
+        cpu: Option<u32>,
+        prev_pid: i32,
+        prev_tid: i32,
+        prev_state: u64,
+    ) {
+        if prev_tid == 0 {
+            return;
+        }
+        let preempted = OffCpuState::from_prev_state(prev_state) == OffCpuState::Preempted;
+        let bucket = time / self.bucket_duration;
+        self.sched_switch
+            .add(bucket, cpu, prev_pid, prev_tid, preempted);
+    }
+
+    /// Builds the report, or returns `None` if no switches were seen.
+    ///
+    /// Returns an error if the time between the first and the last switch
+    /// spans more than [`MAX_CONTEXT_SWITCH_BUCKET_COUNT`] buckets.
+    pub fn finish(self) -> Result<Option<ContextSwitchReport>, ContextSwitchReportError> {
+        let (source, counts) = if !self.sched_switch.buckets.is_empty() {
+            (ContextSwitchSource::SchedSwitch, self.sched_switch)
+        } else if !self.switch_records.buckets.is_empty() {
+            (ContextSwitchSource::SwitchRecords, self.switch_records)
+        } else {
+            return Ok(None);
+        };
+
+        let (Some((&first_bucket, _)), Some((&last_bucket, _))) = (
+            counts.buckets.first_key_value(),
+            counts.buckets.last_key_value(),
+        ) else {
+            return Ok(None);
+        };
+        let bucket_count = last_bucket - first_bucket + 1;
+        if bucket_count > MAX_CONTEXT_SWITCH_BUCKET_COUNT {
+            return Err(ContextSwitchReportError::TooManyBuckets(bucket_count));
+        }
+        let mut buckets = vec![ContextSwitchCounts::default(); bucket_count as usize];
+        for (bucket, bucket_counts) in counts.buckets {
+            buckets[(bucket - first_bucket) as usize] = bucket_counts;
+        }
+
+        let mut per_thread: Vec<_> = counts
+            .per_thread
+            .into_iter()
+            .map(|(tid, (pid, counts))| ThreadContextSwitches { pid, tid, counts })
+            .collect();
+        per_thread.sort_by(|a, b| {
+            b.counts
+                .total()
+                .cmp(&a.counts.total())
+                .then(a.tid.cmp(&b.tid))
+        });
+        let mut per_cpu: Vec<_> = counts.per_cpu.into_iter().collect();
+        per_cpu.sort_by_key(|(cpu, _)| *cpu);
+
+        Ok(Some(ContextSwitchReport {
+            source,
+            total: counts.total,
+            per_thread,
+            per_cpu,
+            start_time: first_bucket * self.bucket_duration,
+            bucket_duration: self.bucket_duration,
+            buckets,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use linux_perf_event_reader::{CommonData, ContextSwitchRecord, TaskWasPreempted};
+
+    use super::{ContextSwitchReportBuilder, ContextSwitchReportError, ContextSwitchSource};
+
+    #[test]
+    fn report_from_switch_records() {
+        let mut builder = ContextSwitchReportBuilder::new(1000).unwrap();
+        let out = |preempted| ContextSwitchRecord::Out {
+            next_pid: None,
+            next_tid: None,
+            preempted,
+        };
+        let common = |time, tid, cpu| CommonData {
+            pid: Some(1),
+            tid: Some(tid),
+            timestamp: Some(time),
+            cpu: Some(cpu),
+            ..Default::default()
+        };
+        builder.switch_record(&out(TaskWasPreempted::No), &common(100, 2, 0));
+        builder.switch_record(&out(TaskWasPreempted::Yes), &common(500, 2, 1));
+        builder.switch_record(
+            &ContextSwitchRecord::In {
+                prev_pid: None,
+                prev_tid: None,
+            },
+            &common(600, 3, 1),
+        );
+        builder.switch_record(&out(TaskWasPreempted::No), &common(2100, 3, 1));
+
+        let report = builder.finish().unwrap().unwrap();
+        assert_eq!(report.source, ContextSwitchSource::SwitchRecords);
+        assert_eq!(report.start_time, 0);
+        assert_eq!(report.total.voluntary, 2);
+        assert_eq!(report.total.preempted, 1);
+        assert_eq!(report.per_thread[0].tid, 2);
+        assert_eq!(report.per_thread[0].counts.total(), 2);
+        assert_eq!(report.per_cpu.len(), 2);
+        assert_eq!(report.per_cpu[1].1.total(), 2);
+        assert_eq!(report.buckets.len(), 3);
+        assert_eq!(report.buckets[0].total(), 2);
+        assert_eq!(report.buckets[1].total(), 0);
+        assert_eq!(report.bucket_rates().next(), Some(2_000_000.0));
+    }
+
+    #[test]
+    fn prefer_sched_switch() {
+        let mut builder = ContextSwitchReportBuilder::new(1000).unwrap();
+        builder.switch_record(
+            &ContextSwitchRecord::Out {
+                next_pid: None,
+                next_tid: None,
+                preempted: TaskWasPreempted::No,
+            },
+            &CommonData {
+                pid: Some(1),
+                tid: Some(1),
+                timestamp: Some(5),
+                ..Default::default()
+            },
+        );
+        builder.sched_switch(10, Some(0), 4, 5, 1);
+        builder.sched_switch(20, Some(0), 0, 0, 0);
+        let report = builder.finish().unwrap().unwrap();
+        assert_eq!(report.source, ContextSwitchSource::SchedSwitch);
+        assert_eq!(report.total.voluntary, 1);
+        assert_eq!(report.per_cpu[0].1.total(), 1);
+    }
+
+    #[test]
+    fn bucket_limits() {
+        assert_eq!(
+            ContextSwitchReportBuilder::new(0).unwrap_err(),
+            ContextSwitchReportError::ZeroBucketDuration
+        );
+        let mut builder = ContextSwitchReportBuilder::new(1).unwrap();
+        assert_eq!(builder.clone().finish(), Ok(None));
+        builder.sched_switch(10, None, 4, 5, 1);
+        builder.sched_switch(u64::MAX, None, 4, 5, 1);
+        assert_eq!(
+            builder.finish(),
+            Err(ContextSwitchReportError::TooManyBuckets(u64::MAX - 9))
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use crate::ReadValues;
+
+/// One reading of a counter, from a `PERF_RECORD_READ` record or from a
+/// sample with `PERF_SAMPLE_READ`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterReading {
+    pub time: u64,
+    pub value: u64,
+    pub time_enabled: Option<u64>,
+    pub time_running: Option<u64>,
+}
+
+impl CounterReading {
+    /// The value, extrapolated to the entire enabled time if the counter was
+    /// multiplexed, like `perf stat` does.
+    pub fn scaled_value(&self) -> u64 {
+        match (self.time_enabled, self.time_running) {
+            (Some(enabled), Some(running)) if running != 0 && running < enabled => {
+                (u128::from(self.value) * u128::from(enabled) / u128::from(running)) as u64
+            }
+            _ => self.value,
+        }
+    }
+}
+
+/// Per-task, per-event counter time series, built from `PERF_RECORD_READ`
+/// records and from the read values of samples.
+///
+/// With inherited counters and `inherit_stat` (`perf record -s`), the
+/// kernel emits a `PERF_RECORD_READ` record for each child task when it
+/// exits, so the series of a task contains the counts that the task
+/// contributed. Samples with `PERF_SAMPLE_READ` add the running counter
+/// values at the time of the sample.
+///
+/// Feed the readings in time order with [`add_read_values`](Self::add_read_values).
+#[derive(Debug, Clone, Default)]
+pub struct CounterTimelines {
+    series: BTreeMap<(i32, usize), Vec<CounterReading>>,
+}
+
+impl CounterTimelines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the values from a `PERF_RECORD_READ` record or a sample of the
+    /// event `attr_index`, for the task `tid`.
+    ///
+    /// For group reads, each member's value is added to the series of the
+    /// member's event. The member's attr index is looked up from its event
+    /// ID with `attr_index_for_id`; members without an ID, or with an ID
+    /// that isn't found, are assumed to follow `attr_index` in group order.
+    pub fn add_read_values(
+        &mut self,
+        time: u64,
+        tid: i32,
+        attr_index: usize,
+        values: &ReadValues,
+        attr_index_for_id: impl Fn(u64) -> Option<usize>,
+    ) {
+        for (member_index, counter) in values.counters.iter().enumerate() {
+            let member_attr_index = counter
+                .id
+                .and_then(&attr_index_for_id)
+                .unwrap_or(attr_index + member_index);
+            self.add_reading(
+                tid,
+                member_attr_index,
+                CounterReading {
+                    time,
+                    value: counter.value,
+                    time_enabled: values.time_enabled,
+                    time_running: values.time_running,
+                },
+            );
+        }
+    }
+
+    /// Adds a single reading to the series of the task `tid` and the event
+    /// `attr_index`.
+    pub fn add_reading(&mut self, tid: i32, attr_index: usize, reading: CounterReading) {
+        self.series
+            .entry((tid, attr_index))
+            .or_default()
+            .push(reading);
+    }
+
+    /// The readings for a task and an event, in the order they were added.
+    pub fn series(&self, tid: i32, attr_index: usize) -> Option<&[CounterReading]> {
+        self.series.get(&(tid, attr_index)).map(Vec::as_slice)
+    }
+
+    /// Iterates over all series as `(tid, attr_index, readings)`, sorted by
+    /// tid and attr index.
+    pub fn iter(&self) -> impl Iterator<Item = (i32, usize, &[CounterReading])> + '_ {
+        self.series
+            .iter()
+            .map(|((tid, attr_index), readings)| (*tid, *attr_index, readings.as_slice()))
+    }
+
+    /// The total count of an event across all tasks, i.e. the sum of the last
+    /// scaled value of each task's series.
+    pub fn total(&self, attr_index: usize) -> u64 {
+        self.iter()
+            .filter(|(_, series_attr_index, _)| *series_attr_index == attr_index)
+            .filter_map(|(_, _, readings)| readings.last())
+            .map(CounterReading::scaled_value)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::LittleEndian;
+    use linux_perf_event_reader::{RawData, ReadFormat};
+
+    use super::CounterTimelines;
+    use crate::ReadRecord;
+
+    #[test]
+    fn group_read_records() {
+        let read_format = ReadFormat::GROUP | ReadFormat::ID | ReadFormat::TOTAL_TIME_ENABLED;
+        let mut data = Vec::new();
+        // pid, tid
+        data.extend_from_slice(&5i32.to_le_bytes());
+        data.extend_from_slice(&6i32.to_le_bytes());
+        // nr, time_enabled, then (value, id) pairs
+        for value in [2u64, 1000, 300, 11, 400, 12] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let record =
+            ReadRecord::parse::<LittleEndian>(RawData::from(&data[..]), read_format).unwrap();
+        assert_eq!(record.tid, 6);
+        assert_eq!(record.values.time_enabled, Some(1000));
+
+        let mut timelines = CounterTimelines::new();
+        let attr_index_for_id = |id| if id == 12 { Some(3) } else { None };
+        timelines.add_read_values(50, record.tid, 0, &record.values, attr_index_for_id);
+        timelines.add_read_values(60, 7, 0, &record.values, attr_index_for_id);
+        assert_eq!(timelines.series(6, 0).unwrap()[0].value, 300);
+        assert_eq!(timelines.series(6, 1), None);
+        assert_eq!(timelines.series(6, 3).unwrap()[0].value, 400);
+        assert_eq!(timelines.total(3), 800);
+    }
+}
@@ -0,0 +1,218 @@
+use linux_perf_event_reader::PerfEventType;
+
+use crate::{AttributeDescription, EventUpdate, EventUpdateType, PmuMappings};
+
+/// The default scale of RAPL energy counters: one counter unit is 2^-32
+/// Joules. The actual scale is published in sysfs and recorded in an
+/// `EVENT_UPDATE` record if `perf` wrote one.
+pub const RAPL_DEFAULT_SCALE: f64 = 1.0 / (1u64 << 32) as f64;
+
+/// A RAPL-style energy counter, e.g. `power/energy-pkg/`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyEvent {
+    /// The index of the event's attr.
+    pub attr_index: usize,
+    /// The RAPL domain, e.g. "pkg", "cores", "ram", "gpu" or "psys".
+    pub domain: String,
+    /// The unit of the scaled counter values, usually "Joules".
+    pub unit: String,
+    /// The factor to convert counter values into `unit`.
+    pub scale: f64,
+}
+
+impl EnergyEvent {
+    /// Finds the energy events among the attributes of a file.
+    ///
+    /// Events are recognized by their name (e.g. `power/energy-pkg/`), or,
+    /// for unnamed events, by their dynamic PMU type mapping to the "power"
+    /// PMU in `pmu_mappings`. The unit and scale default to Joules and
+    /// [`RAPL_DEFAULT_SCALE`]; apply the file's `EVENT_UPDATE` records with
+    /// [`apply_event_update`](Self::apply_event_update) to use the recorded
+    /// values.
+    pub fn detect(
+        attributes: &[AttributeDescription],
+        pmu_mappings: Option<&PmuMappings>,
+    ) -> Vec<Self> {
+        attributes
+            .iter()
+            .enumerate()
+            .filter_map(|(attr_index, attr)| {
+                let domain = domain_from_name(attr.name.as_deref()?)
+                    .or_else(|| domain_from_pmu(attr, pmu_mappings?))?;
+                Some(Self {
+                    attr_index,
+                    domain,
+                    unit: "Joules".to_string(),
+                    scale: RAPL_DEFAULT_SCALE,
+                })
+            })
+            .collect()
+    }
+
+    /// Applies the unit or scale from an `EVENT_UPDATE` record, if it is for
+    /// one of this event's IDs. Returns whether the update was applied.
+    pub fn apply_event_update(
+        &mut self,
+        attributes: &[AttributeDescription],
+        update: &EventUpdate,
+    ) -> bool {
+        let Some(attr) = attributes.get(self.attr_index) else {
+            return false;
+        };
+        if !attr.event_ids.contains(&update.id) {
+            return false;
+        }
+        match &update.update {
+            EventUpdateType::Unit(unit) => self.unit = unit.clone(),
+            EventUpdateType::Scale(scale) => self.scale = *scale,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Converts a counter delta into Joules (or whatever the unit is).
+    pub fn energy(&self, counter_delta: u64) -> f64 {
+        counter_delta as f64 * self.scale
+    }
+
+    /// Converts a counter delta over `duration` nanoseconds into the average
+    /// power in Watts. Returns 0 for an empty duration.
+    pub fn average_power(&self, counter_delta: u64, duration: u64) -> f64 {
+        if duration == 0 {
+            return 0.0;
+        }
+        self.energy(counter_delta) * 1_000_000_000.0 / duration as f64
+    }
+}
+
+fn domain_from_name(name: &str) -> Option<String> {
+    // "power/energy-pkg/", possibly with modifiers after the last slash.
+    let event = name.strip_prefix("power/")?;
+    let event = event.split('/').next()?;
+    let domain = event.strip_prefix("energy-")?;
+    Some(domain.to_string())
+}
+
+fn domain_from_pmu(attr: &AttributeDescription, pmu_mappings: &PmuMappings) -> Option<String> {
+    let PerfEventType::DynamicPmu(pmu_type, config, ..) = attr.attr.type_ else {
+        return None;
+    };
+    if pmu_mappings.0.get(&pmu_type).map(String::as_str) != Some("power") {
+        return None;
+    }
+    // The config values of the RAPL PMU, from arch/x86/events/rapl.c.
+    let domain = match config {
+        1 => "cores",
+        2 => "pkg",
+        3 => "ram",
+        4 => "gpu",
+        5 => "psys",
+        _ => return None,
+    };
+    Some(domain.to_string())
+}
+
+/// Cumulative energy counter readings over time, for computing the energy
+/// and average power over arbitrary time ranges.
+///
+/// Feed it the counter deltas in time order, e.g. the periods of the
+/// event's samples or the differences between `PERF_RECORD_STAT` values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyTimeline {
+    event: EnergyEvent,
+    /// (time, cumulative counter value) pairs, sorted by time.
+    readings: Vec<(u64, u64)>,
+}
+
+impl EnergyTimeline {
+    /// Creates a timeline which starts at `start_time` with a counter value
+    /// of zero.
+    pub fn new(event: EnergyEvent, start_time: u64) -> Self {
+        Self {
+            event,
+            readings: vec![(start_time, 0)],
+        }
+    }
+
+    pub fn event(&self) -> &EnergyEvent {
+        &self.event
+    }
+
+    /// Adds a counter delta which was accumulated up to `time`, i.e. since
+    /// the previous reading.
+    pub fn add_delta(&mut self, time: u64, counter_delta: u64) {
+        let &(last_time, last_value) = self.readings.last().unwrap();
+        self.readings
+            .push((time.max(last_time), last_value + counter_delta));
+    }
+
+    /// The counter value at `time`, interpolating linearly between readings.
+    fn counter_at(&self, time: u64) -> f64 {
+        let i = self.readings.partition_point(|(t, _)| *t <= time);
+        if i == 0 {
+            return 0.0;
+        }
+        let (t0, v0) = self.readings[i - 1];
+        match self.readings.get(i) {
+            Some(&(t1, v1)) => {
+                let fraction = (time - t0) as f64 / (t1 - t0) as f64;
+                v0 as f64 + (v1 - v0) as f64 * fraction
+            }
+            None => v0 as f64,
+        }
+    }
+
+    /// The energy in Joules consumed in the time range `start..end`.
+    pub fn energy_in_range(&self, start: u64, end: u64) -> f64 {
+        if end <= start {
+            return 0.0;
+        }
+        (self.counter_at(end) - self.counter_at(start)) * self.event.scale
+    }
+
+    /// The average power in Watts in the time range `start..end`.
+    pub fn average_power_in_range(&self, start: u64, end: u64) -> f64 {
+        if end <= start {
+            return 0.0;
+        }
+        self.energy_in_range(start, end) * 1_000_000_000.0 / (end - start) as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{domain_from_name, EnergyEvent, EnergyTimeline};
+
+    #[test]
+    fn energy_and_power() {
+        assert_eq!(
+            domain_from_name("power/energy-pkg/").as_deref(),
+            Some("pkg")
+        );
+        assert_eq!(
+            domain_from_name("power/energy-ram/u").as_deref(),
+            Some("ram")
+        );
+        assert_eq!(domain_from_name("cycles"), None);
+
+        let event = EnergyEvent {
+            attr_index: 0,
+            domain: "pkg".to_string(),
+            unit: "Joules".to_string(),
+            scale: 0.5,
+        };
+        assert_eq!(event.energy(10), 5.0);
+        assert_eq!(event.average_power(10, 500_000_000), 10.0);
+
+        let mut timeline = EnergyTimeline::new(event, 0);
+        timeline.add_delta(1_000_000_000, 20);
+        timeline.add_delta(2_000_000_000, 40);
+        assert_eq!(timeline.energy_in_range(0, 2_000_000_000), 30.0);
+        assert_eq!(timeline.energy_in_range(500_000_000, 1_500_000_000), 15.0);
+        assert_eq!(
+            timeline.average_power_in_range(1_000_000_000, 2_000_000_000),
+            20.0
+        );
+        assert_eq!(timeline.energy_in_range(2_000_000_000, 3_000_000_000), 0.0);
+    }
+}
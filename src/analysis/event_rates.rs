@@ -0,0 +1,160 @@
+use linux_perf_event_reader::{PerfEventAttr, SampleFormat, SamplingPolicy};
+
+use crate::SampleTimeRange;
+
+/// How to get the number of events that a sample stands for, based on the
+/// sampling configuration of its attr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleWeighting {
+    /// Each sample stands for the period stored in the sample. This is the
+    /// case if the sample contains `PERIOD`, which `perf record` always
+    /// requests in frequency mode.
+    PeriodFromSample,
+    /// Each sample stands for a fixed number of events, e.g. with
+    /// `perf record -c 10000`.
+    FixedPeriod(u64),
+    /// The number of events per sample is unknown, because the event was
+    /// sampled by frequency without recording the period. Each sample counts
+    /// as one.
+    SampleCount,
+}
+
+impl SampleWeighting {
+    pub fn from_attr(attr: &PerfEventAttr) -> Self {
+        if attr.sample_format.contains(SampleFormat::PERIOD) {
+            return Self::PeriodFromSample;
+        }
+        match attr.sampling_policy {
+            SamplingPolicy::Period(period) => Self::FixedPeriod(period.get()),
+            SamplingPolicy::Frequency(_) | SamplingPolicy::NoSampling => Self::SampleCount,
+        }
+    }
+
+    /// The number of events that a sample stands for, given the period
+    /// stored in the sample, if any.
+    pub fn sample_weight(&self, sample_period: Option<u64>) -> u64 {
+        match (self, sample_period) {
+            (Self::PeriodFromSample, Some(period)) => period,
+            (Self::FixedPeriod(period), _) => *period,
+            _ => 1,
+        }
+    }
+
+    /// Whether the weights are event counts, as opposed to sample counts.
+    pub fn counts_events(&self) -> bool {
+        !matches!(self, Self::SampleCount)
+    }
+}
+
+/// Converts an event count over `duration` nanoseconds into events per
+/// second. Returns 0 for an empty duration.
+pub fn events_per_second(event_count: u64, duration: u64) -> f64 {
+    if duration == 0 {
+        return 0.0;
+    }
+    event_count as f64 * 1_000_000_000.0 / duration as f64
+}
+
+/// Accumulates sample weights into fixed-size time buckets over a time window
+/// and converts them into rates, e.g. for a chart of cache misses per second.
+///
+/// Samples outside the window are ignored. The last bucket may be shorter
+/// than the others; its rate is computed from its actual length, so that it
+/// doesn't show an artificial drop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventRateSeries {
+    start: u64,
+    end: u64,
+    bucket_duration: u64,
+    weights: Vec<u64>,
+}
+
+impl EventRateSeries {
+    /// Creates a series for the window `start..end` with buckets of
+    /// `bucket_duration` nanoseconds.
+    pub fn new(start: u64, end: u64, bucket_duration: u64) -> Self {
+        let end = end.max(start);
+        let bucket_duration = bucket_duration.max(1);
+        let bucket_count = (end - start).div_ceil(bucket_duration);
+        Self {
+            start,
+            end,
+            bucket_duration,
+            weights: vec![0; bucket_count as usize],
+        }
+    }
+
+    /// Creates a series which covers the capture's sample time range, from
+    /// [`PerfFile::sample_time_range`](crate::PerfFile::sample_time_range).
+    /// The last sample is included.
+    pub fn for_sample_time_range(range: &SampleTimeRange, bucket_duration: u64) -> Self {
+        Self::new(
+            range.first_sample_time,
+            range.last_sample_time.saturating_add(1),
+            bucket_duration,
+        )
+    }
+
+    /// Adds a sample at `time` which stands for `weight` events; see
+    /// [`SampleWeighting::sample_weight`].
+    pub fn add_sample(&mut self, time: u64, weight: u64) {
+        if time < self.start || time >= self.end {
+            return;
+        }
+        let index = ((time - self.start) / self.bucket_duration) as usize;
+        self.weights[index] += weight;
+    }
+
+    /// The time window.
+    pub fn time_range(&self) -> (u64, u64) {
+        (self.start, self.end)
+    }
+
+    pub fn bucket_duration(&self) -> u64 {
+        self.bucket_duration
+    }
+
+    /// The summed weights per bucket.
+    pub fn weights(&self) -> &[u64] {
+        &self.weights
+    }
+
+    /// The start time and the rate (events per second) of each bucket.
+    pub fn rates(&self) -> impl Iterator<Item = (u64, f64)> + '_ {
+        self.weights.iter().enumerate().map(|(i, weight)| {
+            let bucket_start = self.start + i as u64 * self.bucket_duration;
+            let bucket_end = (bucket_start + self.bucket_duration).min(self.end);
+            (
+                bucket_start,
+                events_per_second(*weight, bucket_end - bucket_start),
+            )
+        })
+    }
+
+    /// The average rate over the whole window.
+    pub fn average_rate(&self) -> f64 {
+        events_per_second(self.weights.iter().sum(), self.end - self.start)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EventRateSeries, SampleWeighting};
+
+    #[test]
+    fn rates() {
+        let weighting = SampleWeighting::FixedPeriod(1000);
+        let mut series = EventRateSeries::new(1_000_000_000, 3_500_000_000, 1_000_000_000);
+        series.add_sample(1_000_000_000, weighting.sample_weight(None));
+        series.add_sample(1_500_000_000, weighting.sample_weight(Some(5)));
+        series.add_sample(3_200_000_000, weighting.sample_weight(None));
+        series.add_sample(3_500_000_000, weighting.sample_weight(None));
+        series.add_sample(0, weighting.sample_weight(None));
+        assert_eq!(series.weights(), &[2000, 0, 1000]);
+        let rates: Vec<_> = series.rates().collect();
+        assert_eq!(rates[0], (1_000_000_000, 2000.0));
+        assert_eq!(rates[2], (3_000_000_000, 2000.0));
+        assert_eq!(series.average_rate(), 1200.0);
+        assert_eq!(SampleWeighting::PeriodFromSample.sample_weight(Some(7)), 7);
+    }
+}
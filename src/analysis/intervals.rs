@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Describes which events start and end an interval kind, for
+/// [`IntervalMatcher`].
+///
+/// The begin and end events can be tracepoints, e.g. a pair of USDT probes,
+/// or any other sampled events. The two attr indexes can be the same, for
+/// events which are emitted at the start and the end with different field
+/// values; in that case the caller decides which call to make.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntervalDefinition {
+    /// The name of the marker track, e.g. "GC pause" or "Frame".
+    pub name: String,
+    /// The attr index of the event which starts an interval.
+    pub begin_attr_index: usize,
+    /// The attr index of the event which ends an interval.
+    pub end_attr_index: usize,
+}
+
+/// A matched pair of begin and end events, as emitted by [`IntervalMatcher`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interval<K> {
+    /// The index of the [`IntervalDefinition`] in the matcher.
+    pub definition_index: usize,
+    /// The value which was used to match the begin and end events.
+    pub key: K,
+    pub start_time: u64,
+    pub end_time: u64,
+}
+
+impl<K> Interval<K> {
+    /// The duration in nanoseconds.
+    pub fn duration(&self) -> u64 {
+        self.end_time.saturating_sub(self.start_time)
+    }
+}
+
+/// Pairs begin and end events into named intervals, e.g. frame boundaries,
+/// GC pauses, or custom begin/end probes.
+///
+/// Begin and end events are matched by a key which the caller extracts from
+/// the event, for example an `id` or pointer field of a tracepoint, or the
+/// tid for events which are matched per thread. Begin events with the same
+/// key nest: an end event closes the most recent open interval for its key.
+///
+/// Feed the events in time order with [`begin`](Self::begin) and
+/// [`end`](Self::end), or with [`event`](Self::event), which looks up the
+/// definitions by attr index.
+#[derive(Debug, Clone)]
+pub struct IntervalMatcher<K> {
+    definitions: Vec<IntervalDefinition>,
+    /// The start times of the open intervals, per definition and key.
+    pending: HashMap<(usize, K), Vec<u64>>,
+    intervals: Vec<Interval<K>>,
+    unmatched_end_count: u64,
+}
+
+impl<K: Eq + Hash + Clone> IntervalMatcher<K> {
+    pub fn new(definitions: Vec<IntervalDefinition>) -> Self {
+        Self {
+            definitions,
+            pending: HashMap::new(),
+            intervals: Vec::new(),
+            unmatched_end_count: 0,
+        }
+    }
+
+    /// The interval definitions, in the order in which they were passed to
+    /// [`new`](Self::new).
+    pub fn definitions(&self) -> &[IntervalDefinition] {
+        &self.definitions
+    }
+
+    /// Processes an event of the given attr for every definition which uses
+    /// the attr, as its begin event or as its end event. Returns the
+    /// intervals which were completed by this event.
+    ///
+    /// For definitions where the begin and end attr are the same, each
+    /// event closes the open interval for its key if there is one, and
+    /// starts a new one otherwise.
+    pub fn event(&mut self, time: u64, attr_index: usize, key: K) -> Vec<Interval<K>> {
+        let mut completed = Vec::new();
+        for definition_index in 0..self.definitions.len() {
+            let definition = &self.definitions[definition_index];
+            let is_begin = definition.begin_attr_index == attr_index;
+            let is_end = definition.end_attr_index == attr_index;
+            if is_begin && is_end {
+                let is_open = self
+                    .pending
+                    .get(&(definition_index, key.clone()))
+                    .is_some_and(|starts| !starts.is_empty());
+                if is_open {
+                    completed.extend(self.end(time, definition_index, key.clone()));
+                } else {
+                    self.begin(time, definition_index, key.clone());
+                }
+            } else if is_begin {
+                self.begin(time, definition_index, key.clone());
+            } else if is_end {
+                completed.extend(self.end(time, definition_index, key.clone()));
+            }
+        }
+        completed
+    }
+
+    /// Opens an interval of the given definition.
+    pub fn begin(&mut self, time: u64, definition_index: usize, key: K) {
+        self.pending
+            .entry((definition_index, key))
+            .or_default()
+            .push(time);
+    }
+
+    /// Closes the most recent open interval of the given definition with the
+    /// same key. Returns the interval if a matching begin event was seen.
+    pub fn end(&mut self, time: u64, definition_index: usize, key: K) -> Option<Interval<K>> {
+        let pending_key = (definition_index, key);
+        let Some(start_time) = self
+            .pending
+            .get_mut(&pending_key)
+            .and_then(|starts| starts.pop())
+        else {
+            self.unmatched_end_count += 1;
+            return None;
+        };
+        if self.pending[&pending_key].is_empty() {
+            self.pending.remove(&pending_key);
+        }
+        let interval = Interval {
+            definition_index,
+            key: pending_key.1,
+            start_time,
+            end_time: time,
+        };
+        self.intervals.push(interval.clone());
+        Some(interval)
+    }
+
+    /// All completed intervals, sorted by start time.
+    pub fn intervals(&self) -> Vec<&Interval<K>> {
+        let mut intervals: Vec<_> = self.intervals.iter().collect();
+        intervals.sort_by_key(|interval| (interval.start_time, interval.end_time));
+        intervals
+    }
+
+    /// The marker track of one definition: its completed intervals, sorted
+    /// by start time.
+    pub fn track(&self, definition_index: usize) -> Vec<&Interval<K>> {
+        let mut intervals = self.intervals();
+        intervals.retain(|interval| interval.definition_index == definition_index);
+        intervals
+    }
+
+    /// The number of intervals which have been opened but not closed yet.
+    pub fn pending_count(&self) -> usize {
+        self.pending.values().map(Vec::len).sum()
+    }
+
+    /// The number of end events for which no matching begin event was seen,
+    /// for example because recording started inside the interval.
+    pub fn unmatched_end_count(&self) -> u64 {
+        self.unmatched_end_count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IntervalDefinition, IntervalMatcher};
+
+    #[test]
+    fn match_intervals() {
+        let mut matcher = IntervalMatcher::new(vec![
+            IntervalDefinition {
+                name: "GC".to_string(),
+                begin_attr_index: 0,
+                end_attr_index: 1,
+            },
+            IntervalDefinition {
+                name: "Frame".to_string(),
+                begin_attr_index: 2,
+                end_attr_index: 2,
+            },
+        ]);
+        matcher.event(100, 0, 0xa000u64);
+        matcher.event(110, 0, 0xb000);
+        matcher.event(115, 2, 7);
+        assert_eq!(matcher.event(120, 1, 0xa000)[0].duration(), 20);
+        assert!(matcher.event(130, 1, 0xc000).is_empty());
+        let frame = matcher.event(131, 2, 7);
+        assert_eq!((frame[0].start_time, frame[0].end_time), (115, 131));
+        matcher.event(140, 2, 7);
+
+        let gc = matcher.track(0);
+        assert_eq!(gc.len(), 1);
+        assert_eq!(gc[0].key, 0xa000);
+        assert_eq!(matcher.intervals().len(), 2);
+        assert_eq!(matcher.pending_count(), 2);
+        assert_eq!(matcher.unmatched_end_count(), 1);
+    }
+}
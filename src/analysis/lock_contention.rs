@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The kind of lock, from the `flags` field of `lock:contention_begin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LockContentionFlags(pub u32);
+
+impl LockContentionFlags {
+    pub const SPIN: u32 = 1 << 0;
+    pub const READ: u32 = 1 << 1;
+    pub const WRITE: u32 = 1 << 2;
+    pub const RT: u32 = 1 << 3;
+    pub const PERCPU: u32 = 1 << 4;
+    pub const MUTEX: u32 = 1 << 5;
+
+    /// A short name for the lock type, using the same names as
+    /// `perf lock contention`.
+    pub fn lock_type_name(&self) -> &'static str {
+        let flags = self.0;
+        if flags & Self::PERCPU != 0 {
+            if flags & Self::WRITE != 0 {
+                "pcpu-sem:W"
+            } else {
+                "pcpu-sem:R"
+            }
+        } else if flags & Self::MUTEX != 0 {
+            if flags & Self::SPIN != 0 {
+                "mutex-spin"
+            } else {
+                "mutex"
+            }
+        } else if flags & Self::RT != 0 {
+            "rtmutex"
+        } else if flags & Self::READ != 0 {
+            if flags & Self::SPIN != 0 {
+                "rwlock:R"
+            } else {
+                "rwsem:R"
+            }
+        } else if flags & Self::WRITE != 0 {
+            if flags & Self::SPIN != 0 {
+                "rwlock:W"
+            } else {
+                "rwsem:W"
+            }
+        } else if flags & Self::SPIN != 0 {
+            "spinlock"
+        } else {
+            "unknown"
+        }
+    }
+}
+
+/// Aggregated wait times for a lock or a stack.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockContentionStats {
+    /// The number of contended acquisitions.
+    pub count: u64,
+    /// The total wait time in nanoseconds.
+    pub total_wait: u64,
+    /// The longest wait in nanoseconds.
+    pub max_wait: u64,
+    /// The shortest wait in nanoseconds.
+    pub min_wait: u64,
+}
+
+impl LockContentionStats {
+    fn add(&mut self, wait: u64) {
+        self.min_wait = if self.count == 0 {
+            wait
+        } else {
+            self.min_wait.min(wait)
+        };
+        self.count += 1;
+        self.total_wait += wait;
+        self.max_wait = self.max_wait.max(wait);
+    }
+
+    /// The average wait time in nanoseconds.
+    pub fn avg_wait(&self) -> u64 {
+        self.total_wait.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PendingContention<S> {
+    time: u64,
+    lock_addr: u64,
+    flags: LockContentionFlags,
+    stack: S,
+}
+
+/// Pairs `lock:contention_begin` and `lock:contention_end` tracepoints per
+/// task and aggregates the wait times by lock address and by stack, like
+/// `perf lock contention` does for recorded data.
+///
+/// For each `lock:contention_begin` sample, call
+/// [`contention_begin`](Self::contention_begin) with the `lock_addr` and
+/// `flags` fields and the sample's callchain as the stack. For each
+/// `lock:contention_end` sample, call [`contention_end`](Self::contention_end).
+/// The samples must be fed in time order.
+#[derive(Debug, Clone)]
+pub struct LockContentionAnalysis<S> {
+    pending: HashMap<i32, PendingContention<S>>,
+    by_lock: HashMap<u64, (LockContentionFlags, LockContentionStats)>,
+    by_stack: HashMap<S, LockContentionStats>,
+    by_tid: HashMap<i32, LockContentionStats>,
+    unmatched_end_count: u64,
+}
+
+impl<S> Default for LockContentionAnalysis<S> {
+    fn default() -> Self {
+        Self {
+            pending: HashMap::new(),
+            by_lock: HashMap::new(),
+            by_stack: HashMap::new(),
+            by_tid: HashMap::new(),
+            unmatched_end_count: 0,
+        }
+    }
+}
+
+impl<S: Eq + Hash> LockContentionAnalysis<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The task `tid` started waiting for the lock at `lock_addr`.
+    ///
+    /// A task can only wait for one lock at a time. If a begin event for the
+    /// task is already pending, for example because the matching end event
+    /// was lost, it is replaced.
+    pub fn contention_begin(
+        &mut self,
+        time: u64,
+        tid: i32,
+        lock_addr: u64,
+        flags: LockContentionFlags,
+        stack: S,
+    ) {
+        self.pending.insert(
+            tid,
+            PendingContention {
+                time,
+                lock_addr,
+                flags,
+                stack,
+            },
+        );
+    }
+
+    /// The task `tid` stopped waiting, usually because it acquired the lock.
+    /// Returns the wait time in nanoseconds if the matching begin event was
+    /// seen.
+    ///
+    /// `lock_addr` is checked against the begin event, if given; nested
+    /// contention on a different lock (which can happen with spinning
+    /// mutexes) does not end the outer wait.
+    pub fn contention_end(&mut self, time: u64, tid: i32, lock_addr: Option<u64>) -> Option<u64> {
+        match (self.pending.get(&tid), lock_addr) {
+            (None, _) => {
+                self.unmatched_end_count += 1;
+                return None;
+            }
+            (Some(pending), Some(lock_addr)) if pending.lock_addr != lock_addr => {
+                self.unmatched_end_count += 1;
+                return None;
+            }
+            _ => {}
+        }
+        let pending = self.pending.remove(&tid)?;
+        let wait = time.saturating_sub(pending.time);
+        let (flags, stats) = self
+            .by_lock
+            .entry(pending.lock_addr)
+            .or_insert((pending.flags, LockContentionStats::default()));
+        flags.0 |= pending.flags.0;
+        stats.add(wait);
+        self.by_stack.entry(pending.stack).or_default().add(wait);
+        self.by_tid.entry(tid).or_default().add(wait);
+        Some(wait)
+    }
+
+    /// The stats per lock address, with the lock's flags, sorted by total
+    /// wait time in descending order.
+    pub fn by_lock(&self) -> Vec<(u64, LockContentionFlags, LockContentionStats)> {
+        let mut result: Vec<_> = self
+            .by_lock
+            .iter()
+            .map(|(addr, (flags, stats))| (*addr, *flags, *stats))
+            .collect();
+        result.sort_by(|a, b| b.2.total_wait.cmp(&a.2.total_wait).then(a.0.cmp(&b.0)));
+        result
+    }
+
+    /// The stats per stack, sorted by total wait time in descending order.
+    pub fn by_stack(&self) -> Vec<(&S, LockContentionStats)> {
+        let mut result: Vec<_> = self.by_stack.iter().map(|(s, stats)| (s, *stats)).collect();
+        result.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total_wait));
+        result
+    }
+
+    /// The stats per task, sorted by total wait time in descending order.
+    pub fn by_tid(&self) -> Vec<(i32, LockContentionStats)> {
+        let mut result: Vec<_> = self.by_tid.iter().map(|(t, stats)| (*t, *stats)).collect();
+        result.sort_by(|a, b| b.1.total_wait.cmp(&a.1.total_wait).then(a.0.cmp(&b.0)));
+        result
+    }
+
+    /// The number of tasks which are still waiting, i.e. whose begin event
+    /// has no end event yet.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The number of end events for which no matching begin event was seen,
+    /// for example because recording started while the task was waiting.
+    pub fn unmatched_end_count(&self) -> u64 {
+        self.unmatched_end_count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LockContentionAnalysis, LockContentionFlags};
+
+    #[test]
+    fn aggregate_waits() {
+        let mutex = LockContentionFlags(LockContentionFlags::MUTEX);
+        let spin = LockContentionFlags(LockContentionFlags::SPIN);
+        let mut analysis = LockContentionAnalysis::new();
+        analysis.contention_begin(100, 1, 0xa000, mutex, "open");
+        analysis.contention_begin(105, 2, 0xa000, mutex, "close");
+        analysis.contention_begin(110, 3, 0xb000, spin, "open");
+        assert_eq!(analysis.contention_end(150, 1, Some(0xa000)), Some(50));
+        assert_eq!(analysis.contention_end(160, 3, Some(0xc000)), None);
+        assert_eq!(analysis.contention_end(170, 3, None), Some(60));
+        assert_eq!(analysis.contention_end(180, 2, Some(0xa000)), Some(75));
+        assert_eq!(analysis.contention_end(190, 4, None), None);
+
+        let by_lock = analysis.by_lock();
+        assert_eq!(by_lock[0].0, 0xa000);
+        assert_eq!(by_lock[0].1.lock_type_name(), "mutex");
+        assert_eq!(by_lock[0].2.count, 2);
+        assert_eq!(by_lock[0].2.total_wait, 125);
+        assert_eq!(by_lock[0].2.min_wait, 50);
+        assert_eq!(by_lock[0].2.max_wait, 75);
+        assert_eq!(by_lock[1].1.lock_type_name(), "spinlock");
+        let by_stack = analysis.by_stack();
+        assert_eq!(by_stack[0].0, &"open");
+        assert_eq!(by_stack[0].1.total_wait, 110);
+        assert_eq!(analysis.unmatched_end_count(), 2);
+        assert_eq!(analysis.pending_count(), 0);
+    }
+}
@@ -0,0 +1,39 @@
+//! Analyses which turn a stream of perf.data records into aggregated results,
+//! for example an off-CPU profile.
+//!
+//! The types in this module don't read files themselves. They are fed by the
+//! caller, who reads the records with [`PerfRecordIter`](crate::PerfRecordIter),
+//! parses the information the analysis needs, and passes it on. This keeps
+//! the analyses independent of how the caller represents stacks, threads and
+//! symbols: most builders are generic over the stack type, which can be a
+//! callchain `Vec<u64>`, an interned stack index, or anything else.
+
+mod aggregate;
+mod aux_timeline;
+mod context_switches;
+mod counters;
+mod energy;
+mod event_rates;
+mod intervals;
+mod lock_contention;
+mod off_cpu;
+mod page_faults;
+mod profile_diff;
+mod thread_cpu_time;
+mod timeline;
+mod top;
+
+pub use aggregate::*;
+pub use aux_timeline::*;
+pub use context_switches::*;
+pub use counters::*;
+pub use energy::*;
+pub use event_rates::*;
+pub use intervals::*;
+pub use lock_contention::*;
+pub use off_cpu::*;
+pub use page_faults::*;
+pub use profile_diff::*;
+pub use thread_cpu_time::*;
+pub use timeline::*;
+pub use top::*;
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use linux_perf_event_reader::{ContextSwitchRecord, TaskWasPreempted};
+
+/// The state of a task at the time it was switched out, from the `prev_state`
+/// field of the `sched:sched_switch` tracepoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OffCpuState {
+    /// The task was still runnable, i.e. it was preempted.
+    Preempted,
+    /// `S`: interruptible sleep, e.g. waiting for a lock, a timer or a socket.
+    Sleeping,
+    /// `D`: uninterruptible sleep, usually waiting for disk I/O.
+    UninterruptibleSleep,
+    /// Any other state, with the raw `prev_state` value.
+    Other(u64),
+}
+
+impl OffCpuState {
+    const TASK_INTERRUPTIBLE: u64 = 0x1;
+    const TASK_UNINTERRUPTIBLE: u64 = 0x2;
+
+    /// Interprets the `prev_state` field of `sched:sched_switch`.
+    ///
+    /// Only the low bits are looked at; the meaning of the higher bits has
+    /// changed between kernel versions.
+    pub fn from_prev_state(prev_state: u64) -> Self {
+        match prev_state & 0xff {
+            0 => Self::Preempted,
+            Self::TASK_INTERRUPTIBLE => Self::Sleeping,
+            Self::TASK_UNINTERRUPTIBLE => Self::UninterruptibleSleep,
+            _ => Self::Other(prev_state),
+        }
+    }
+}
+
+/// An interval during which a thread was not running, as emitted by
+/// [`OffCpuProfileBuilder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffCpuSample<S> {
+    pub pid: i32,
+    pub tid: i32,
+    /// The stack of the thread when it was switched out.
+    pub stack: S,
+    pub state: OffCpuState,
+    /// The time at which the thread was switched out.
+    pub switch_out_time: u64,
+    /// The time at which the thread was woken up, if a wakeup was seen.
+    /// The time between the wakeup and `switch_in_time` is spent waiting on
+    /// a run queue ("scheduling delay").
+    pub wakeup_time: Option<u64>,
+    /// The thread which woke up this thread, if known.
+    pub waker_tid: Option<i32>,
+    /// The time at which the thread was switched back in.
+    pub switch_in_time: u64,
+}
+
+impl<S> OffCpuSample<S> {
+    /// The total off-CPU time in nanoseconds. Use this as the sample weight.
+    pub fn duration(&self) -> u64 {
+        self.switch_in_time.saturating_sub(self.switch_out_time)
+    }
+
+    /// The time spent blocked, i.e. from the switch-out until the wakeup.
+    /// If no wakeup was seen, this is the entire off-CPU time.
+    pub fn blocked_duration(&self) -> u64 {
+        self.wakeup_time
+            .unwrap_or(self.switch_in_time)
+            .saturating_sub(self.switch_out_time)
+    }
+
+    /// The time spent runnable but waiting for a CPU, from the wakeup until
+    /// the switch-in.
+    pub fn scheduling_delay(&self) -> u64 {
+        match self.wakeup_time {
+            Some(wakeup_time) => self.switch_in_time.saturating_sub(wakeup_time),
+            None => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SwitchedOutThread<S> {
+    pid: i32,
+    stack: S,
+    state: OffCpuState,
+    switch_out_time: u64,
+    wakeup: Option<(u64, Option<i32>)>,
+}
+
+/// Builds an off-CPU profile by pairing the switch-out and switch-in events of
+/// each thread into blocked intervals, like `perf sched timehist` does.
+///
+/// Feed it the scheduler events in time order:
+///
+///  - For each `sched:sched_switch` sample, call [`switch_out`](Self::switch_out)
+///    for the `prev_*` thread, with the sample's callchain as the stack, and
+///    [`switch_in`](Self::switch_in) for the `next_*` thread.
+///  - For each `sched:sched_wakeup` or `sched:sched_waking` sample, call
+///    [`wakeup`](Self::wakeup).
+///
+/// The fields of these tracepoints can be read with
+/// [`SchedEvent`](crate::tracepoint::SchedEvent).
+///
+/// Files recorded with `perf record --switch-events` contain
+/// `PERF_RECORD_SWITCH` records instead of, or in addition to, the
+/// tracepoints; those can be passed to [`context_switch`](Self::context_switch).
+///
+/// Each completed interval becomes an [`OffCpuSample`], whose duration is
+/// the off-CPU weight of the stack.
+#[derive(Debug, Clone)]
+pub struct OffCpuProfileBuilder<S> {
+    switched_out: HashMap<i32, SwitchedOutThread<S>>,
+    samples: Vec<OffCpuSample<S>>,
+    min_duration: u64,
+    include_preempted: bool,
+}
+
+impl<S> Default for OffCpuProfileBuilder<S> {
+    fn default() -> Self {
+        Self {
+            switched_out: HashMap::new(),
+            samples: Vec::new(),
+            min_duration: 0,
+            include_preempted: true,
+        }
+    }
+}
+
+impl<S> OffCpuProfileBuilder<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Intervals shorter than `min_duration` nanoseconds are dropped.
+    pub fn set_min_duration(&mut self, min_duration: u64) {
+        self.min_duration = min_duration;
+    }
+
+    /// Whether to emit samples for threads which were preempted rather than
+    /// blocked. Defaults to true.
+    pub fn set_include_preempted(&mut self, include_preempted: bool) {
+        self.include_preempted = include_preempted;
+    }
+
+    /// A thread was switched out at `time`, with the stack `stack`.
+    pub fn switch_out(&mut self, time: u64, pid: i32, tid: i32, state: OffCpuState, stack: S) {
+        if tid == 0 {
+            // The idle task.
+            return;
+        }
+        self.switched_out.insert(
+            tid,
+            SwitchedOutThread {
+                pid,
+                stack,
+                state,
+                switch_out_time: time,
+                wakeup: None,
+            },
+        );
+    }
+
+    /// A thread was woken up at `time`, optionally by the thread `waker_tid`.
+    /// Only the first wakeup after a switch-out is recorded.
+    pub fn wakeup(&mut self, time: u64, tid: i32, waker_tid: Option<i32>) {
+        if let Some(thread) = self.switched_out.get_mut(&tid) {
+            thread.wakeup.get_or_insert((time, waker_tid));
+        }
+    }
+
+    /// A thread was switched in at `time`. If the thread's switch-out was
+    /// seen, this completes an off-CPU interval and returns its sample.
+    pub fn switch_in(&mut self, time: u64, tid: i32) -> Option<&OffCpuSample<S>> {
+        let thread = self.switched_out.remove(&tid)?;
+        let sample = OffCpuSample {
+            pid: thread.pid,
+            tid,
+            stack: thread.stack,
+            state: thread.state,
+            switch_out_time: thread.switch_out_time,
+            wakeup_time: thread.wakeup.map(|(time, _)| time),
+            waker_tid: thread.wakeup.and_then(|(_, waker)| waker),
+            switch_in_time: time,
+        };
+        if sample.duration() < self.min_duration
+            || (!self.include_preempted && sample.state == OffCpuState::Preempted)
+        {
+            return None;
+        }
+        self.samples.push(sample);
+        self.samples.last()
+    }
+
+    /// Handles a `PERF_RECORD_SWITCH` or `PERF_RECORD_SWITCH_CPU_WIDE` record
+    /// for the thread `pid`/`tid` at `time`, taken from the record's sample ID.
+    ///
+    /// These records don't say whether the thread was preempted or blocked
+    /// unless the kernel sets the preempt flag, and they have no stack, so
+    /// the caller provides the stack, usually from the thread's most recent
+    /// sample.
+    pub fn context_switch(
+        &mut self,
+        time: u64,
+        pid: i32,
+        tid: i32,
+        record: &ContextSwitchRecord,
+        stack: impl FnOnce() -> S,
+    ) {
+        match record {
+            ContextSwitchRecord::Out { preempted, .. } => {
+                let state = match preempted {
+                    TaskWasPreempted::Yes => OffCpuState::Preempted,
+                    TaskWasPreempted::No => OffCpuState::Sleeping,
+                };
+                self.switch_out(time, pid, tid, state, stack());
+            }
+            ContextSwitchRecord::In { .. } => {
+                self.switch_in(time, tid);
+            }
+        }
+    }
+
+    /// The samples which have been completed so far.
+    pub fn samples(&self) -> &[OffCpuSample<S>] {
+        &self.samples
+    }
+
+    /// Finishes the profile. Threads which are still switched out are
+    /// assumed to be blocked until `end_time`, usually the time of the last
+    /// record in the file.
+    pub fn finish(mut self, end_time: u64) -> Vec<OffCpuSample<S>> {
+        let mut remaining: Vec<_> = self.switched_out.drain().collect();
+        remaining.sort_by_key(|(tid, thread)| (thread.switch_out_time, *tid));
+        for (tid, thread) in remaining {
+            if end_time >= thread.switch_out_time {
+                self.samples.push(OffCpuSample {
+                    pid: thread.pid,
+                    tid,
+                    stack: thread.stack,
+                    state: thread.state,
+                    switch_out_time: thread.switch_out_time,
+                    wakeup_time: thread.wakeup.map(|(time, _)| time),
+                    waker_tid: thread.wakeup.and_then(|(_, waker)| waker),
+                    switch_in_time: end_time,
+                });
+            }
+        }
+        let min_duration = self.min_duration;
+        let include_preempted = self.include_preempted;
+        self.samples.retain(|sample| {
+            sample.duration() >= min_duration
+                && (include_preempted || sample.state != OffCpuState::Preempted)
+        });
+        self.samples
+    }
+
+    /// Sums up the off-CPU time per stack.
+    pub fn total_duration_per_stack(samples: &[OffCpuSample<S>]) -> HashMap<&S, u64>
+    where
+        S: Eq + std::hash::Hash,
+    {
+        let mut totals = HashMap::new();
+        for sample in samples {
+            *totals.entry(&sample.stack).or_insert(0) += sample.duration();
+        }
+        totals
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OffCpuProfileBuilder, OffCpuState};
+
+    #[test]
+    fn pairs_switches() {
+        let mut builder = OffCpuProfileBuilder::new();
+        builder.switch_out(100, 10, 11, OffCpuState::from_prev_state(2), "read");
+        builder.switch_out(110, 10, 12, OffCpuState::from_prev_state(0), "loop");
+        builder.wakeup(150, 11, Some(12));
+        builder.wakeup(160, 11, Some(13));
+        let sample = builder.switch_in(170, 11).unwrap();
+        assert_eq!(sample.state, OffCpuState::UninterruptibleSleep);
+        assert_eq!(sample.duration(), 70);
+        assert_eq!(sample.blocked_duration(), 50);
+        assert_eq!(sample.scheduling_delay(), 20);
+        assert_eq!(sample.waker_tid, Some(12));
+        assert!(builder.switch_in(180, 99).is_none());
+
+        let samples = builder.finish(200);
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1].tid, 12);
+        assert_eq!(samples[1].duration(), 90);
+        let totals = OffCpuProfileBuilder::total_duration_per_stack(&samples);
+        assert_eq!(totals[&"read"], 70);
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use linux_perf_event_reader::{PerfEventAttr, PerfEventType, SoftwareCounterType};
+
+use crate::AttributeDescription;
+
+/// Which page faults a page-fault event counts, from the event's attr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PageFaultKind {
+    /// `page-faults`: all page faults, major and minor.
+    All,
+    /// `minor-faults`: faults which were resolved without I/O.
+    Minor,
+    /// `major-faults`: faults which needed I/O, e.g. reading a file page
+    /// from disk.
+    Major,
+}
+
+impl PageFaultKind {
+    /// Returns the kind of page faults that the event counts, or `None` if the
+    /// event is not a page-fault event.
+    pub fn from_attr(attr: &PerfEventAttr) -> Option<Self> {
+        match attr.type_ {
+            PerfEventType::Software(SoftwareCounterType::PageFaults) => Some(Self::All),
+            PerfEventType::Software(SoftwareCounterType::PageFaultsMin) => Some(Self::Minor),
+            PerfEventType::Software(SoftwareCounterType::PageFaultsMaj) => Some(Self::Major),
+            _ => None,
+        }
+    }
+
+    /// Returns the page-fault kind for each attribute, indexed by attr index.
+    /// Use this with the `attr_index` of each sample.
+    pub fn for_attributes(attributes: &[AttributeDescription]) -> Vec<Option<Self>> {
+        attributes
+            .iter()
+            .map(|attr| Self::from_attr(&attr.attr))
+            .collect()
+    }
+}
+
+/// Page-fault counts, split by kind.
+///
+/// The counts are sample weights, i.e. the sum of the sample periods. If the
+/// events were recorded with a period of 1 (`perf record -c 1`), they are
+/// exact fault counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageFaultCounts {
+    pub minor: u64,
+    pub major: u64,
+    /// Faults from the `page-faults` event, which doesn't distinguish
+    /// between minor and major faults.
+    pub unspecified: u64,
+}
+
+impl PageFaultCounts {
+    pub fn total(&self) -> u64 {
+        self.minor + self.major + self.unspecified
+    }
+
+    fn add(&mut self, kind: PageFaultKind, weight: u64) {
+        match kind {
+            PageFaultKind::All => self.unspecified += weight,
+            PageFaultKind::Minor => self.minor += weight,
+            PageFaultKind::Major => self.major += weight,
+        }
+    }
+}
+
+/// Aggregates page-fault samples by stack and by mapped region, producing a
+/// memory-fault profile.
+///
+/// `S` is the caller's stack type and `M` identifies a mapped region, for
+/// example the mapping's file path or start address. For each sample of a
+/// page-fault event, look up the kind with [`PageFaultKind::for_attributes`],
+/// resolve the mapping which contains the sample's `addr` (the faulting data
+/// address, recorded with `perf record -d`), and call
+/// [`add_sample`](Self::add_sample).
+#[derive(Debug, Clone)]
+pub struct PageFaultProfile<S, M> {
+    by_stack: HashMap<S, PageFaultCounts>,
+    by_region: HashMap<M, PageFaultCounts>,
+    unmapped: PageFaultCounts,
+    total: PageFaultCounts,
+}
+
+impl<S, M> Default for PageFaultProfile<S, M> {
+    fn default() -> Self {
+        Self {
+            by_stack: HashMap::new(),
+            by_region: HashMap::new(),
+            unmapped: PageFaultCounts::default(),
+            total: PageFaultCounts::default(),
+        }
+    }
+}
+
+impl<S: Eq + Hash, M: Eq + Hash> PageFaultProfile<S, M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a page-fault sample with the weight `weight` (its period).
+    /// `region` is `None` if the faulting address is unknown or not covered
+    /// by any mapping, e.g. for faults on anonymous memory without a
+    /// recorded mapping.
+    pub fn add_sample(&mut self, kind: PageFaultKind, weight: u64, stack: S, region: Option<M>) {
+        self.by_stack.entry(stack).or_default().add(kind, weight);
+        match region {
+            Some(region) => self.by_region.entry(region).or_default().add(kind, weight),
+            None => self.unmapped.add(kind, weight),
+        }
+        self.total.add(kind, weight);
+    }
+
+    /// The counts per stack, sorted by total count in descending order.
+    pub fn by_stack(&self) -> Vec<(&S, PageFaultCounts)> {
+        sorted_by_total(&self.by_stack)
+    }
+
+    /// The counts per mapped region, sorted by total count in descending
+    /// order.
+    pub fn by_region(&self) -> Vec<(&M, PageFaultCounts)> {
+        sorted_by_total(&self.by_region)
+    }
+
+    /// The counts for samples whose region was unknown.
+    pub fn unmapped(&self) -> PageFaultCounts {
+        self.unmapped
+    }
+
+    /// The counts across all samples.
+    pub fn total(&self) -> PageFaultCounts {
+        self.total
+    }
+}
+
+fn sorted_by_total<K>(map: &HashMap<K, PageFaultCounts>) -> Vec<(&K, PageFaultCounts)> {
+    let mut result: Vec<_> = map.iter().map(|(k, counts)| (k, *counts)).collect();
+    result.sort_by_key(|(_, counts)| std::cmp::Reverse(counts.total()));
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PageFaultKind, PageFaultProfile};
+
+    #[test]
+    fn aggregate_faults() {
+        let mut profile = PageFaultProfile::new();
+        profile.add_sample(PageFaultKind::Minor, 1, "malloc", Some("[heap]"));
+        profile.add_sample(PageFaultKind::Minor, 1, "malloc", Some("[heap]"));
+        profile.add_sample(PageFaultKind::Major, 1, "read", Some("libc.so"));
+        profile.add_sample(PageFaultKind::All, 3, "read", None);
+
+        let by_stack = profile.by_stack();
+        assert_eq!(by_stack[0].0, &"read");
+        assert_eq!(by_stack[0].1.major, 1);
+        assert_eq!(by_stack[0].1.total(), 4);
+        let by_region = profile.by_region();
+        assert_eq!(by_region[0].0, &"[heap]");
+        assert_eq!(by_region[0].1.minor, 2);
+        assert_eq!(profile.unmapped().unspecified, 3);
+        assert_eq!(profile.total().total(), 6);
+    }
+}
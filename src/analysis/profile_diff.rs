@@ -0,0 +1,295 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{AggregatedProfile, ProfileSymbol, ProfileWeight};
+use crate::DsoKey;
+
+/// The change of a symbol's weight between two profiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolDelta {
+    /// The symbol, using the DSO key from the "after" profile if the symbol
+    /// exists there.
+    pub symbol: ProfileSymbol,
+    pub before: ProfileWeight,
+    pub after: ProfileWeight,
+}
+
+/// The change of a DSO's weight between two profiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DsoDelta {
+    /// The DSO in the "before" profile, or `None` if the DSO is new.
+    pub before_dso: Option<DsoKey>,
+    /// The DSO in the "after" profile, or `None` if the DSO is gone.
+    pub after_dso: Option<DsoKey>,
+    pub before: ProfileWeight,
+    pub after: ProfileWeight,
+    /// True if both profiles have a build ID for the DSO and they differ,
+    /// i.e. if the binary was changed between the two profiles.
+    pub build_id_changed: bool,
+}
+
+/// Weight changes compare the share of the total weight, so that profiles
+/// of different lengths can be compared. This is the change of `before` to
+/// `after` as a fraction of the total weight, e.g. 0.05 if the share grew
+/// from 10% to 15%.
+fn share_delta(before: u64, before_total: u64, after: u64, after_total: u64) -> f64 {
+    let share = |weight: u64, total: u64| {
+        if total == 0 {
+            0.0
+        } else {
+            weight as f64 / total as f64
+        }
+    };
+    share(after, after_total) - share(before, before_total)
+}
+
+/// A comparison of two aggregated profiles, e.g. from before and after a
+/// change, for finding regressions.
+///
+/// DSOs are matched by build ID first, so that a library which was
+/// installed at a different path is still recognized. DSOs without a matching
+/// build ID, usually because the binary was rebuilt, are matched by their
+/// key, and then by their name. Symbols are matched by name within matched
+/// DSOs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileDiff {
+    pub before_total_weight: u64,
+    pub after_total_weight: u64,
+    /// The symbol deltas, sorted by the absolute change of the self weight
+    /// share, largest first.
+    pub symbols: Vec<SymbolDelta>,
+    /// The DSO deltas, sorted by the absolute change of the self weight
+    /// share, largest first.
+    pub dsos: Vec<DsoDelta>,
+}
+
+impl ProfileDiff {
+    pub fn compute(before: &AggregatedProfile, after: &AggregatedProfile) -> Self {
+        let dso_map = match_dsos(before, after);
+
+        let mut dsos: Vec<DsoDelta> = Vec::new();
+        let mut matched_after_dsos = HashSet::new();
+        for (before_dso, before_weight) in before.dsos() {
+            let after_dso = dso_map.get(before_dso);
+            let after_weight = after_dso
+                .and_then(|dso| after.dsos().get(dso))
+                .copied()
+                .unwrap_or_default();
+            let build_id_changed = match (
+                before.build_id(before_dso),
+                after_dso.and_then(|dso| after.build_id(dso)),
+            ) {
+                (Some(before_id), Some(after_id)) => before_id != after_id,
+                _ => false,
+            };
+            if let Some(after_dso) = after_dso {
+                matched_after_dsos.insert(after_dso.clone());
+            }
+            dsos.push(DsoDelta {
+                before_dso: Some(before_dso.clone()),
+                after_dso: after_dso.cloned(),
+                before: *before_weight,
+                after: after_weight,
+                build_id_changed,
+            });
+        }
+        for (after_dso, after_weight) in after.dsos() {
+            if !matched_after_dsos.contains(after_dso) {
+                dsos.push(DsoDelta {
+                    before_dso: None,
+                    after_dso: Some(after_dso.clone()),
+                    before: ProfileWeight::default(),
+                    after: *after_weight,
+                    build_id_changed: false,
+                });
+            }
+        }
+
+        let mut symbols: HashMap<ProfileSymbol, SymbolDelta> = after
+            .symbols()
+            .iter()
+            .map(|(symbol, weight)| {
+                let delta = SymbolDelta {
+                    symbol: symbol.clone(),
+                    before: ProfileWeight::default(),
+                    after: *weight,
+                };
+                (symbol.clone(), delta)
+            })
+            .collect();
+        for (symbol, weight) in before.symbols() {
+            let key = ProfileSymbol {
+                dso: symbol
+                    .dso
+                    .as_ref()
+                    .map(|dso| dso_map.get(dso).unwrap_or(dso).clone()),
+                name: symbol.name.clone(),
+            };
+            symbols
+                .entry(key)
+                .or_insert_with(|| SymbolDelta {
+                    symbol: symbol.clone(),
+                    before: ProfileWeight::default(),
+                    after: ProfileWeight::default(),
+                })
+                .before = *weight;
+        }
+
+        let (before_total, after_total) = (before.total_weight(), after.total_weight());
+        let self_delta = |before: &ProfileWeight, after: &ProfileWeight| {
+            share_delta(
+                before.self_weight,
+                before_total,
+                after.self_weight,
+                after_total,
+            )
+            .abs()
+        };
+        let mut symbols: Vec<SymbolDelta> = symbols.into_values().collect();
+        symbols.sort_by(|a, b| {
+            self_delta(&b.before, &b.after)
+                .total_cmp(&self_delta(&a.before, &a.after))
+                .then_with(|| a.symbol.cmp(&b.symbol))
+        });
+        dsos.sort_by(|a, b| {
+            self_delta(&b.before, &b.after)
+                .total_cmp(&self_delta(&a.before, &a.after))
+                .then_with(|| a.after_dso.cmp(&b.after_dso))
+                .then_with(|| a.before_dso.cmp(&b.before_dso))
+        });
+        Self {
+            before_total_weight: before_total,
+            after_total_weight: after_total,
+            symbols,
+            dsos,
+        }
+    }
+
+    /// The change of the self weight's share of the total weight, e.g. 0.05
+    /// if a symbol went from 10% to 15% of the samples.
+    pub fn self_share_delta(&self, before: &ProfileWeight, after: &ProfileWeight) -> f64 {
+        share_delta(
+            before.self_weight,
+            self.before_total_weight,
+            after.self_weight,
+            self.after_total_weight,
+        )
+    }
+
+    /// The change of the total weight's share of the total weight.
+    pub fn total_share_delta(&self, before: &ProfileWeight, after: &ProfileWeight) -> f64 {
+        share_delta(
+            before.total_weight,
+            self.before_total_weight,
+            after.total_weight,
+            self.after_total_weight,
+        )
+    }
+}
+
+/// Maps the DSOs of `before` to the matching DSOs of `after`.
+fn match_dsos(before: &AggregatedProfile, after: &AggregatedProfile) -> HashMap<DsoKey, DsoKey> {
+    let mut before_dsos: Vec<&DsoKey> = before.dsos().keys().collect();
+    before_dsos.sort();
+    let mut unmatched_after: Vec<&DsoKey> = after.dsos().keys().collect();
+    unmatched_after.sort();
+
+    let mut map = HashMap::new();
+    let mut match_by = |map: &mut HashMap<DsoKey, DsoKey>,
+                        is_match: &dyn Fn(&DsoKey, &DsoKey) -> bool| {
+        for before_dso in &before_dsos {
+            if map.contains_key(*before_dso) {
+                continue;
+            }
+            if let Some(pos) = unmatched_after
+                .iter()
+                .position(|after_dso| is_match(before_dso, after_dso))
+            {
+                let after_dso = unmatched_after.remove(pos);
+                map.insert((*before_dso).clone(), after_dso.clone());
+            }
+        }
+    };
+    match_by(
+        &mut map,
+        &|b, a| matches!((before.build_id(b), after.build_id(a)), (Some(b), Some(a)) if a == b),
+    );
+    match_by(&mut map, &|b, a| b == a);
+    match_by(&mut map, &|b, a| b.name() == a.name());
+    map
+}
+
+#[cfg(test)]
+mod test {
+    use super::ProfileDiff;
+    use crate::analysis::{AggregatedProfile, ProfileSymbol};
+    use crate::DsoKey;
+
+    fn user_dso(path: &str) -> DsoKey {
+        DsoKey::User {
+            file_name: path.rsplit('/').next().unwrap().to_string(),
+            full_path: path.as_bytes().to_vec(),
+        }
+    }
+
+    fn symbol(dso: &DsoKey, name: &str) -> ProfileSymbol {
+        ProfileSymbol {
+            dso: Some(dso.clone()),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn diff_profiles() {
+        let libc_old = user_dso("/usr/lib/libc.so.6");
+        let libc_new = user_dso("/opt/lib/libc.so.6");
+        let app = user_dso("/bin/app");
+
+        let mut before = AggregatedProfile::new();
+        before.set_build_id(libc_old.clone(), vec![1, 2, 3]);
+        before.set_build_id(app.clone(), vec![4]);
+        before.add_sample(1, &[symbol(&libc_old, "memcpy"), symbol(&app, "main")]);
+        before.add_sample(1, &[symbol(&app, "main")]);
+
+        let mut after = AggregatedProfile::new();
+        after.set_build_id(libc_new.clone(), vec![1, 2, 3]);
+        after.set_build_id(app.clone(), vec![5]);
+        after.add_sample(1, &[symbol(&libc_new, "memcpy"), symbol(&app, "main")]);
+        after.add_sample(1, &[symbol(&libc_new, "memcpy"), symbol(&app, "main")]);
+        after.add_sample(1, &[symbol(&app, "parse"), symbol(&app, "main")]);
+        after.add_sample(1, &[symbol(&app, "main")]);
+
+        let diff = ProfileDiff::compute(&before, &after);
+        assert_eq!(diff.symbols.len(), 3);
+        assert_eq!(diff.symbols[0].symbol, symbol(&app, "main"));
+        let memcpy = diff
+            .symbols
+            .iter()
+            .find(|s| s.symbol.name == "memcpy")
+            .unwrap();
+        assert_eq!(memcpy.symbol, symbol(&libc_new, "memcpy"));
+        assert_eq!(memcpy.before.self_weight, 1);
+        assert_eq!(memcpy.after.self_weight, 2);
+        let main = diff
+            .symbols
+            .iter()
+            .find(|s| s.symbol.name == "main")
+            .unwrap();
+        assert_eq!(diff.self_share_delta(&main.before, &main.after), -0.25);
+        assert_eq!(diff.total_share_delta(&main.before, &main.after), 0.0);
+
+        assert_eq!(diff.dsos.len(), 2);
+        let libc = diff
+            .dsos
+            .iter()
+            .find(|d| d.after_dso == Some(libc_new.clone()))
+            .unwrap();
+        assert_eq!(libc.before_dso, Some(libc_old));
+        assert!(!libc.build_id_changed);
+        let app_delta = diff
+            .dsos
+            .iter()
+            .find(|d| d.after_dso == Some(app.clone()))
+            .unwrap();
+        assert!(app_delta.build_id_changed);
+    }
+}
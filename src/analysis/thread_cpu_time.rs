@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use linux_perf_event_reader::{
+    CommonData, ContextSwitchRecord, PerfEventAttr, PerfEventType, SoftwareCounterType,
+};
+
+/// Where the CPU time of a thread in [`ThreadCpuTimes`] was derived from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CpuTimeSource {
+    /// Exact on-CPU intervals from context switches.
+    ContextSwitches,
+    /// An estimate from the weights of time-based samples, e.g. `cpu-clock`.
+    Samples,
+}
+
+/// Returns true if the period of samples from this event is measured in
+/// nanoseconds of CPU time, i.e. if the event is `cpu-clock` or `task-clock`.
+/// The sample period of such events can be passed as the CPU time to
+/// [`ThreadCpuTimeBuilder::sample`].
+pub fn attr_period_is_cpu_time(attr: &PerfEventAttr) -> bool {
+    matches!(
+        attr.type_,
+        PerfEventType::Software(SoftwareCounterType::CpuClock | SoftwareCounterType::TaskClock)
+    )
+}
+
+#[derive(Debug, Clone, Default)]
+struct ThreadState {
+    pid: Option<i32>,
+    /// The start time of the current on-CPU interval.
+    switched_in_at: Option<u64>,
+    /// Whether any switch was seen for this thread.
+    has_switches: bool,
+    /// Completed on-CPU intervals, as (start, end).
+    intervals: Vec<(u64, u64)>,
+    /// (time, attr_index, cpu time in ns) of each sample.
+    samples: Vec<(u64, usize, u64)>,
+}
+
+/// Estimates per-thread on-CPU time, for example for the thread activity
+/// tracks in a profiler UI.
+///
+/// Feed it the records in time order:
+///
+///  - context switches, from `PERF_RECORD_SWITCH` records with
+///    [`context_switch`](Self::context_switch) or from `sched:sched_switch`
+///    samples with [`switch_in`](Self::switch_in) and
+///    [`switch_out`](Self::switch_out),
+///  - samples of time-based events with [`sample`](Self::sample),
+///  - thread exits, from `PERF_RECORD_EXIT`, with
+///    [`thread_exit`](Self::thread_exit).
+///
+/// For threads with context switches, the on-CPU time is exact. For other
+/// threads, it is estimated from the samples of a single event, so that the
+/// time isn't counted twice in captures with multiple time-based events.
+#[derive(Debug, Clone, Default)]
+pub struct ThreadCpuTimeBuilder {
+    threads: HashMap<i32, ThreadState>,
+    sample_count_per_attr: HashMap<usize, u64>,
+    /// The start of the capture, from `set_start_time` or the first
+    /// timestamp which was passed to the builder.
+    start_time: Option<u64>,
+}
+
+impl ThreadCpuTimeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the start time of the capture, e.g. the timestamp of the first
+    /// record. Threads which switch out without a prior switch-in are assumed
+    /// to have been running since then. If no start time is set, the first
+    /// timestamp which is passed to the builder is used.
+    pub fn set_start_time(&mut self, start_time: u64) {
+        self.start_time = Some(start_time);
+    }
+
+    fn see_time(&mut self, time: u64) {
+        self.start_time.get_or_insert(time);
+    }
+
+    /// The thread `tid` started running at `time`.
+    pub fn switch_in(&mut self, time: u64, pid: Option<i32>, tid: i32) {
+        self.see_time(time);
+        let thread = self.threads.entry(tid).or_default();
+        thread.pid = pid.or(thread.pid);
+        thread.has_switches = true;
+        thread.switched_in_at = Some(time);
+    }
+
+    /// The thread `tid` stopped running at `time`.
+    ///
+    /// If no switch-in was seen, the thread is assumed to have been running
+    /// since the start of the capture, see
+    /// [`set_start_time`](Self::set_start_time).
+    pub fn switch_out(&mut self, time: u64, pid: Option<i32>, tid: i32) {
+        self.see_time(time);
+        let capture_start = self.start_time.unwrap_or(time).min(time);
+        let thread = self.threads.entry(tid).or_default();
+        thread.pid = pid.or(thread.pid);
+        let start = match thread.switched_in_at.take() {
+            Some(start) => start,
+            None if !thread.has_switches => capture_start,
+            None => return,
+        };
+        thread.has_switches = true;
+        thread.intervals.push((start, time));
+    }
+
+    /// Handles a `PERF_RECORD_SWITCH` or `PERF_RECORD_SWITCH_CPU_WIDE` record
+    /// for the thread in the record's sample ID. Records without tid or
+    /// timestamp are ignored.
+    pub fn context_switch(&mut self, record: &ContextSwitchRecord, common: &CommonData) {
+        let (Some(time), Some(tid)) = (common.timestamp, common.tid) else {
+            return;
+        };
+        match record {
+            ContextSwitchRecord::In { .. } => self.switch_in(time, common.pid, tid),
+            ContextSwitchRecord::Out { .. } => self.switch_out(time, common.pid, tid),
+        }
+    }
+
+    /// A sample from the event with the attr index `attr_index` hit the
+    /// thread at `time`, and represents `cpu_time` nanoseconds of CPU time.
+    ///
+    /// For `cpu-clock` and `task-clock` events (see
+    /// [`attr_period_is_cpu_time`]), this is the sample period. For other
+    /// events, it can be estimated from the sampling frequency.
+    pub fn sample(
+        &mut self,
+        time: u64,
+        pid: Option<i32>,
+        tid: i32,
+        attr_index: usize,
+        cpu_time: u64,
+    ) {
+        self.see_time(time);
+        let thread = self.threads.entry(tid).or_default();
+        thread.pid = pid.or(thread.pid);
+        thread.samples.push((time, attr_index, cpu_time));
+        *self.sample_count_per_attr.entry(attr_index).or_insert(0) += 1;
+    }
+
+    /// The thread `tid` exited at `time`.
+    pub fn thread_exit(&mut self, time: u64, tid: i32) {
+        self.see_time(time);
+        if let Some(thread) = self.threads.get_mut(&tid) {
+            if let Some(start) = thread.switched_in_at.take() {
+                thread.intervals.push((start, time));
+            }
+        }
+    }
+
+    /// Finishes the accounting. Threads which are still running are assumed
+    /// to run until `end_time`.
+    pub fn finish(self, end_time: u64) -> ThreadCpuTimes {
+        // Use the event with the most samples for the sample-based estimates.
+        let sample_attr = self
+            .sample_count_per_attr
+            .iter()
+            .max_by_key(|(attr_index, count)| (**count, std::cmp::Reverse(**attr_index)))
+            .map(|(attr_index, _)| *attr_index);
+
+        let threads = self
+            .threads
+            .into_iter()
+            .map(|(tid, mut state)| {
+                let thread = if state.has_switches {
+                    if let Some(start) = state.switched_in_at {
+                        state.intervals.push((start, end_time.max(start)));
+                    }
+                    state.intervals.sort_unstable();
+                    ThreadCpuTime::from_spans(
+                        state.pid,
+                        CpuTimeSource::ContextSwitches,
+                        state.intervals,
+                    )
+                } else {
+                    let mut samples: Vec<_> = state
+                        .samples
+                        .into_iter()
+                        .filter(|(_, attr_index, _)| Some(*attr_index) == sample_attr)
+                        .map(|(time, _, cpu_time)| (time, time, cpu_time))
+                        .collect();
+                    samples.sort_unstable();
+                    ThreadCpuTime {
+                        pid: state.pid,
+                        source: CpuTimeSource::Samples,
+                        spans: samples,
+                    }
+                };
+                (tid, thread)
+            })
+            .collect();
+        ThreadCpuTimes { threads }
+    }
+}
+
+/// The on-CPU time of one thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadCpuTime {
+    pub pid: Option<i32>,
+    pub source: CpuTimeSource,
+    /// (start, end, cpu time) of each interval or sample, sorted by start.
+    /// For samples, start and end are both the sample time.
+    spans: Vec<(u64, u64, u64)>,
+}
+
+impl ThreadCpuTime {
+    fn from_spans(pid: Option<i32>, source: CpuTimeSource, intervals: Vec<(u64, u64)>) -> Self {
+        let spans = intervals
+            .into_iter()
+            .map(|(start, end)| (start, end, end.saturating_sub(start)))
+            .collect();
+        Self { pid, source, spans }
+    }
+
+    /// The on-CPU time in nanoseconds within the time range `start..end`.
+    /// Intervals which partially overlap the range are counted partially.
+    pub fn cpu_time_in_range(&self, start: u64, end: u64) -> u64 {
+        let first = self
+            .spans
+            .partition_point(|(_, span_end, _)| *span_end < start);
+        let mut total = 0;
+        for &(span_start, span_end, cpu_time) in &self.spans[first..] {
+            if span_start >= end {
+                break;
+            }
+            total += match self.source {
+                CpuTimeSource::ContextSwitches => {
+                    span_end.min(end).saturating_sub(span_start.max(start))
+                }
+                CpuTimeSource::Samples if span_start >= start => cpu_time,
+                CpuTimeSource::Samples => 0,
+            };
+        }
+        total
+    }
+
+    /// The total on-CPU time in nanoseconds.
+    pub fn total_cpu_time(&self) -> u64 {
+        self.spans.iter().map(|(_, _, cpu_time)| cpu_time).sum()
+    }
+}
+
+/// The result of [`ThreadCpuTimeBuilder`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadCpuTimes {
+    threads: HashMap<i32, ThreadCpuTime>,
+}
+
+impl ThreadCpuTimes {
+    /// The CPU time of the thread `tid`, if anything was seen for it.
+    pub fn thread(&self, tid: i32) -> Option<&ThreadCpuTime> {
+        self.threads.get(&tid)
+    }
+
+    /// All threads, by tid.
+    pub fn threads(&self) -> impl Iterator<Item = (i32, &ThreadCpuTime)> + '_ {
+        self.threads.iter().map(|(tid, thread)| (*tid, thread))
+    }
+
+    /// The on-CPU time in nanoseconds of the thread `tid` within the time
+    /// range `start..end`.
+    pub fn cpu_time_in_range(&self, tid: i32, start: u64, end: u64) -> u64 {
+        self.thread(tid)
+            .map_or(0, |thread| thread.cpu_time_in_range(start, end))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CpuTimeSource, ThreadCpuTimeBuilder};
+
+    #[test]
+    fn switches_and_samples() {
+        let mut builder = ThreadCpuTimeBuilder::new();
+        builder.set_start_time(50);
+        // Thread 1 has switches, and its samples are ignored.
+        builder.switch_out(100, Some(1), 1);
+        builder.switch_in(200, Some(1), 1);
+        builder.sample(250, Some(1), 1, 0, 1000);
+        builder.switch_out(300, Some(1), 1);
+        builder.switch_in(400, Some(1), 1);
+        // Thread 2 only has samples, from two events.
+        builder.sample(150, Some(1), 2, 0, 10);
+        builder.sample(250, Some(1), 2, 0, 10);
+        builder.sample(260, Some(1), 2, 1, 99);
+        builder.sample(350, Some(1), 2, 0, 10);
+        builder.switch_in(420, Some(1), 3);
+        builder.thread_exit(450, 3);
+
+        let times = builder.finish(500);
+        let thread1 = times.thread(1).unwrap();
+        assert_eq!(thread1.source, CpuTimeSource::ContextSwitches);
+        assert_eq!(thread1.total_cpu_time(), 50 + 100 + 100);
+        assert_eq!(times.cpu_time_in_range(1, 50, 250), 50 + 50);
+        let thread2 = times.thread(2).unwrap();
+        assert_eq!(thread2.source, CpuTimeSource::Samples);
+        assert_eq!(thread2.total_cpu_time(), 30);
+        assert_eq!(times.cpu_time_in_range(2, 200, 300), 10);
+        assert_eq!(times.cpu_time_in_range(3, 0, 1000), 30);
+        assert_eq!(times.cpu_time_in_range(4, 0, 1000), 0);
+    }
+
+    #[test]
+    fn switch_out_without_switch_in() {
+        let mut builder = ThreadCpuTimeBuilder::new();
+        builder.sample(1_000_000, Some(1), 2, 0, 10);
+        builder.switch_out(1_000_300, Some(1), 1);
+
+        // Thread 1 ran since the first timestamp, not since time 0.
+        let times = builder.finish(1_000_500);
+        assert_eq!(times.thread(1).unwrap().total_cpu_time(), 300);
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+/// What a series in a [`Timeline`] covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TimelineSeriesKey {
+    /// All samples of the event.
+    All,
+    /// The samples of one thread.
+    Thread(i32),
+    /// The samples on one CPU.
+    Cpu(u32),
+}
+
+/// The binned weights of one event for one [`TimelineSeriesKey`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineSeries {
+    pub attr_index: usize,
+    pub key: TimelineSeriesKey,
+    /// The summed sample weight of each bin. All series of a timeline have
+    /// the same number of bins.
+    pub bins: Vec<u64>,
+}
+
+/// Sample weights in fixed-width time bins, ready for plotting activity
+/// graphs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeline {
+    /// The start time of the first bin.
+    pub start_time: u64,
+    /// The width of each bin in nanoseconds.
+    pub bin_duration: u64,
+    /// The number of bins in each series.
+    pub bin_count: usize,
+    /// The series, sorted by attr index and key.
+    pub series: Vec<TimelineSeries>,
+}
+
+impl Timeline {
+    /// The start time of each bin.
+    pub fn bin_start_times(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.bin_count as u64).map(|i| self.start_time + i * self.bin_duration)
+    }
+
+    /// Returns the series for an event and key, if it had samples.
+    pub fn series(&self, attr_index: usize, key: TimelineSeriesKey) -> Option<&TimelineSeries> {
+        self.series
+            .binary_search_by(|series| (series.attr_index, series.key).cmp(&(attr_index, key)))
+            .ok()
+            .map(|i| &self.series[i])
+    }
+}
+
+/// The default maximum number of bins in a [`Timeline`], see
+/// [`TimelineBuilder::set_max_bin_count`].
+pub const DEFAULT_MAX_TIMELINE_BIN_COUNT: usize = 1 << 20;
+
+/// Bins sample weights per event, and optionally per thread and per CPU,
+/// during a single streaming pass over the samples.
+///
+/// The end time doesn't need to be known in advance; the series grow as
+/// samples arrive.
+#[derive(Debug, Clone)]
+pub struct TimelineBuilder {
+    start_time: Option<u64>,
+    bin_duration: u64,
+    per_thread: bool,
+    per_cpu: bool,
+    series: HashMap<(usize, TimelineSeriesKey), Vec<u64>>,
+    bin_count: usize,
+    max_bin_count: usize,
+    samples_before_start: u64,
+    samples_after_end: u64,
+}
+
+impl TimelineBuilder {
+    /// Creates a builder with bins of `bin_duration` nanoseconds, starting at
+    /// `start_time`. If `start_time` is `None`, the first sample's time is
+    /// used. Pass the first sample time from
+    /// [`PerfFile::sample_time_range`](crate::PerfFile::sample_time_range)
+    /// if it is known, so that bins line up across multiple passes.
+    pub fn new(start_time: Option<u64>, bin_duration: u64) -> Self {
+        Self {
+            start_time,
+            bin_duration: bin_duration.max(1),
+            per_thread: false,
+            per_cpu: false,
+            series: HashMap::new(),
+            bin_count: 0,
+            max_bin_count: DEFAULT_MAX_TIMELINE_BIN_COUNT,
+            samples_before_start: 0,
+            samples_after_end: 0,
+        }
+    }
+
+    /// Also create a series per thread for each event.
+    pub fn set_per_thread(&mut self, per_thread: bool) {
+        self.per_thread = per_thread;
+    }
+
+    /// Also create a series per CPU for each event.
+    pub fn set_per_cpu(&mut self, per_cpu: bool) {
+        self.per_cpu = per_cpu;
+    }
+
+    /// Limits the number of bins in each series, so that a stray timestamp
+    /// far in the future can't make the builder allocate without bound.
+    /// Defaults to [`DEFAULT_MAX_TIMELINE_BIN_COUNT`].
+    pub fn set_max_bin_count(&mut self, max_bin_count: usize) {
+        self.max_bin_count = max_bin_count;
+    }
+
+    /// Adds a sample with the weight `weight`. Samples before the start time
+    /// are counted in [`samples_before_start`](Self::samples_before_start),
+    /// and samples which would need more than the maximum number of bins in
+    /// [`samples_after_end`](Self::samples_after_end), but they are otherwise
+    /// ignored.
+    pub fn add_sample(
+        &mut self,
+        time: u64,
+        attr_index: usize,
+        tid: Option<i32>,
+        cpu: Option<u32>,
+        weight: u64,
+    ) {
+        let start_time = *self.start_time.get_or_insert(time);
+        if time < start_time {
+            self.samples_before_start += 1;
+            return;
+        }
+        let bin = (time - start_time) / self.bin_duration;
+        let bin = match usize::try_from(bin) {
+            Ok(bin) if bin < self.max_bin_count => bin,
+            _ => {
+                self.samples_after_end += 1;
+                return;
+            }
+        };
+        self.bin_count = self.bin_count.max(bin + 1);
+
+        let mut add = |key| {
+            let bins = self.series.entry((attr_index, key)).or_default();
+            if bins.len() <= bin {
+                bins.resize(bin + 1, 0);
+            }
+            bins[bin] += weight;
+        };
+        add(TimelineSeriesKey::All);
+        if let (true, Some(tid)) = (self.per_thread, tid) {
+            add(TimelineSeriesKey::Thread(tid));
+        }
+        if let (true, Some(cpu)) = (self.per_cpu, cpu) {
+            add(TimelineSeriesKey::Cpu(cpu));
+        }
+    }
+
+    /// The number of samples which were dropped because they came before
+    /// the start time.
+    pub fn samples_before_start(&self) -> u64 {
+        self.samples_before_start
+    }
+
+    /// The number of samples which were dropped because they came after the
+    /// last bin allowed by [`set_max_bin_count`](Self::set_max_bin_count).
+    pub fn samples_after_end(&self) -> u64 {
+        self.samples_after_end
+    }
+
+    /// Finishes the timeline, padding all series to the same length.
+    pub fn finish(self) -> Timeline {
+        let bin_count = self.bin_count;
+        let mut series: Vec<_> = self
+            .series
+            .into_iter()
+            .map(|((attr_index, key), mut bins)| {
+                bins.resize(bin_count, 0);
+                TimelineSeries {
+                    attr_index,
+                    key,
+                    bins,
+                }
+            })
+            .collect();
+        series.sort_by_key(|series| (series.attr_index, series.key));
+        Timeline {
+            start_time: self.start_time.unwrap_or(0),
+            bin_duration: self.bin_duration,
+            bin_count,
+            series,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TimelineBuilder, TimelineSeriesKey};
+
+    #[test]
+    fn bin_samples() {
+        let mut builder = TimelineBuilder::new(None, 10);
+        builder.set_per_thread(true);
+        builder.set_per_cpu(true);
+        builder.add_sample(100, 0, Some(1), Some(0), 1);
+        builder.add_sample(105, 0, Some(2), Some(0), 2);
+        builder.add_sample(90, 0, Some(2), Some(0), 2);
+        builder.add_sample(125, 1, Some(2), None, 5);
+        builder.add_sample(131, 0, Some(1), Some(1), 1);
+
+        let timeline = builder.finish();
+        assert_eq!(timeline.start_time, 100);
+        assert_eq!(timeline.bin_count, 4);
+        let all = timeline.series(0, TimelineSeriesKey::All).unwrap();
+        assert_eq!(all.bins, vec![3, 0, 0, 1]);
+        let thread2 = timeline.series(0, TimelineSeriesKey::Thread(2)).unwrap();
+        assert_eq!(thread2.bins, vec![2, 0, 0, 0]);
+        let cpu1 = timeline.series(0, TimelineSeriesKey::Cpu(1)).unwrap();
+        assert_eq!(cpu1.bins, vec![0, 0, 0, 1]);
+        let event1 = timeline.series(1, TimelineSeriesKey::All).unwrap();
+        assert_eq!(event1.bins, vec![0, 0, 5, 0]);
+        assert!(timeline.series(1, TimelineSeriesKey::Cpu(0)).is_none());
+        assert_eq!(timeline.bin_start_times().last(), Some(130));
+    }
+
+    #[test]
+    fn max_bin_count() {
+        let mut builder = TimelineBuilder::new(Some(0), 10);
+        builder.set_max_bin_count(3);
+        builder.add_sample(5, 0, None, None, 1);
+        builder.add_sample(29, 0, None, None, 1);
+        builder.add_sample(30, 0, None, None, 1);
+        builder.add_sample(u64::MAX, 0, None, None, 1);
+        assert_eq!(builder.samples_after_end(), 2);
+
+        let timeline = builder.finish();
+        assert_eq!(timeline.bin_count, 3);
+        let all = timeline.series(0, TimelineSeriesKey::All).unwrap();
+        assert_eq!(all.bins, vec![1, 0, 1]);
+    }
+}
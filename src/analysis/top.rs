@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use super::{AggregatedProfile, ProfileSymbol, ProfileWeight};
+use crate::DsoKey;
+
+/// Which weight to rank symbols and DSOs by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TopWeight {
+    /// Rank by self weight, i.e. where the samples hit, like `perf report
+    /// --no-children`.
+    Exclusive,
+    /// Rank by total weight, i.e. including callees, like `perf report
+    /// --children`. Requires callchains; without them this is the same as
+    /// `Exclusive`.
+    Inclusive,
+}
+
+impl TopWeight {
+    fn get(&self, weight: &ProfileWeight) -> u64 {
+        match self {
+            TopWeight::Exclusive => weight.self_weight,
+            TopWeight::Inclusive => weight.total_weight,
+        }
+    }
+}
+
+impl AggregatedProfile {
+    /// The `n` symbols with the highest weight, highest first.
+    pub fn top_symbols(&self, n: usize, by: TopWeight) -> Vec<(&ProfileSymbol, u64)> {
+        top_n(self.symbols().iter(), n, by)
+    }
+
+    /// The `n` DSOs with the highest weight, highest first.
+    pub fn top_dsos(&self, n: usize, by: TopWeight) -> Vec<(&DsoKey, u64)> {
+        top_n(self.dsos().iter(), n, by)
+    }
+}
+
+fn top_n<'a, K: Ord + 'a>(
+    entries: impl Iterator<Item = (&'a K, &'a ProfileWeight)>,
+    n: usize,
+    by: TopWeight,
+) -> Vec<(&'a K, u64)> {
+    let mut entries: Vec<_> = entries
+        .map(|(key, weight)| (key, by.get(weight)))
+        .filter(|(_, weight)| *weight != 0)
+        .collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    entries.truncate(n);
+    entries
+}
+
+/// The top symbols and DSOs of one event in a [`TopSummary`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventTop {
+    /// The index of the event's attr, see
+    /// [`PerfFile::event_attributes`](crate::PerfFile::event_attributes).
+    pub attr_index: usize,
+    pub sample_count: u64,
+    pub total_weight: u64,
+    /// Whether the samples had callchains, i.e. whether inclusive weights
+    /// are meaningful.
+    pub has_callchains: bool,
+    /// The top symbols with their weights, highest first.
+    pub symbols: Vec<(ProfileSymbol, u64)>,
+    /// The top DSOs with their weights, highest first.
+    pub dsos: Vec<(DsoKey, u64)>,
+}
+
+/// A quick "top" summary: the `n` hottest symbols and DSOs for each event,
+/// for CLI output without a full profile export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopSummary {
+    /// One entry per event which had samples, ordered by attr index.
+    pub events: Vec<EventTop>,
+}
+
+impl TopSummary {
+    /// Aggregates the samples and computes the summary in one call.
+    ///
+    /// Each sample is given as `(attr_index, weight, frames)`, with the
+    /// symbolicated frames starting at the leaf, as for
+    /// [`AggregatedProfile::add_sample`].
+    pub fn compute<I, F>(samples: I, n: usize, by: TopWeight) -> Self
+    where
+        I: IntoIterator<Item = (usize, u64, F)>,
+        F: AsRef<[ProfileSymbol]>,
+    {
+        let mut profiles: BTreeMap<usize, AggregatedProfile> = BTreeMap::new();
+        for (attr_index, weight, frames) in samples {
+            profiles
+                .entry(attr_index)
+                .or_default()
+                .add_sample(weight, frames.as_ref());
+        }
+        Self::from_profiles(&profiles, n, by)
+    }
+
+    /// Computes the summary from profiles which were already aggregated per
+    /// attr index.
+    pub fn from_profiles(
+        profiles: &BTreeMap<usize, AggregatedProfile>,
+        n: usize,
+        by: TopWeight,
+    ) -> Self {
+        let events = profiles
+            .iter()
+            .map(|(attr_index, profile)| EventTop {
+                attr_index: *attr_index,
+                sample_count: profile.sample_count(),
+                total_weight: profile.total_weight(),
+                has_callchains: profile.has_callchains(),
+                symbols: profile
+                    .top_symbols(n, by)
+                    .into_iter()
+                    .map(|(symbol, weight)| (symbol.clone(), weight))
+                    .collect(),
+                dsos: profile
+                    .top_dsos(n, by)
+                    .into_iter()
+                    .map(|(dso, weight)| (dso.clone(), weight))
+                    .collect(),
+            })
+            .collect();
+        Self { events }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TopSummary, TopWeight};
+    use crate::analysis::ProfileSymbol;
+    use crate::DsoKey;
+
+    #[test]
+    fn top_per_event() {
+        let symbol = |name: &str| ProfileSymbol {
+            dso: Some(DsoKey::Kernel),
+            name: name.to_string(),
+        };
+        let samples = vec![
+            (0, 10, vec![symbol("memcpy"), symbol("read")]),
+            (0, 10, vec![symbol("memcpy"), symbol("write")]),
+            (0, 15, vec![symbol("read")]),
+            (1, 1, vec![symbol("write")]),
+        ];
+        let exclusive = TopSummary::compute(samples.clone(), 2, TopWeight::Exclusive);
+        assert_eq!(exclusive.events.len(), 2);
+        assert_eq!(exclusive.events[0].symbols[0], (symbol("memcpy"), 20));
+        assert_eq!(exclusive.events[0].symbols[1], (symbol("read"), 15));
+        assert_eq!(exclusive.events[0].dsos, vec![(DsoKey::Kernel, 35)]);
+        assert!(!exclusive.events[1].has_callchains);
+
+        let inclusive = TopSummary::compute(samples, 1, TopWeight::Inclusive);
+        assert_eq!(inclusive.events[0].symbols, vec![(symbol("read"), 25)]);
+        assert!(inclusive.events[0].has_callchains);
+    }
+}
@@ -0,0 +1,147 @@
+use byteorder::{ByteOrder, WriteBytesExt};
+use linux_perf_event_reader::constants::*;
+use linux_perf_event_reader::{
+    ClockId, HardwareCacheId, HardwareCacheOp, HardwareCacheOpResult, HardwareEventId, PerfClock,
+    PerfEventAttr, PerfEventType, SamplingPolicy, SoftwareCounterType, WakeupPolicy,
+};
+
+/// Encodes a `perf_event_attr` in its `PERF_ATTR_SIZE_VER7` layout, which is
+/// the inverse of [`PerfEventAttr::parse`].
+///
+/// Returns an `InvalidInput` error for event types which
+/// `linux-perf-event-reader` knows about but this function doesn't.
+pub(crate) fn write_attr<T: ByteOrder>(
+    attr: &PerfEventAttr,
+    out: &mut Vec<u8>,
+) -> Result<(), std::io::Error> {
+    let (type_, bp_type, config, config1, config2) = event_type_fields(&attr.type_)?;
+    let sampling_period_or_frequency = match attr.sampling_policy {
+        SamplingPolicy::NoSampling => 0,
+        SamplingPolicy::Period(period) => period.get(),
+        SamplingPolicy::Frequency(frequency) => frequency,
+    };
+    let wakeup_events_or_watermark = match attr.wakeup_policy {
+        WakeupPolicy::EventCount(count) => count,
+        WakeupPolicy::Watermark(watermark) => watermark,
+    };
+    let clockid = match attr.clock {
+        PerfClock::Default => 0,
+        PerfClock::ClockId(clock_id) => clock_id_value(clock_id)?,
+    };
+
+    out.write_u32::<T>(type_)?;
+    out.write_u32::<T>(PERF_ATTR_SIZE_VER7)?;
+    out.write_u64::<T>(config)?;
+    out.write_u64::<T>(sampling_period_or_frequency)?;
+    out.write_u64::<T>(attr.sample_format.bits())?;
+    out.write_u64::<T>(attr.read_format.bits())?;
+    out.write_u64::<T>(attr.flags.bits())?;
+    out.write_u32::<T>(wakeup_events_or_watermark)?;
+    out.write_u32::<T>(bp_type)?;
+    out.write_u64::<T>(config1)?;
+    out.write_u64::<T>(config2)?;
+    out.write_u64::<T>(attr.branch_sample_format.bits())?;
+    out.write_u64::<T>(attr.sample_regs_user)?;
+    out.write_u32::<T>(attr.sample_stack_user)?;
+    out.write_u32::<T>(clockid)?;
+    out.write_u64::<T>(attr.sample_regs_intr)?;
+    out.write_u32::<T>(attr.aux_watermark)?;
+    out.write_u16::<T>(attr.sample_max_stack)?;
+    out.write_u16::<T>(0)?;
+    out.write_u32::<T>(attr.aux_sample_size)?;
+    out.write_u32::<T>(0)?;
+    out.write_u64::<T>(attr.sig_data)?;
+    Ok(())
+}
+
+/// Returns `(type, bp_type, config, config1, config2)`.
+fn event_type_fields(type_: &PerfEventType) -> Result<(u32, u32, u64, u64, u64), std::io::Error> {
+    let fields = match *type_ {
+        PerfEventType::Hardware(id, pmu_type) => {
+            let id = match id {
+                HardwareEventId::CpuCycles => PERF_COUNT_HW_CPU_CYCLES,
+                HardwareEventId::Instructions => PERF_COUNT_HW_INSTRUCTIONS,
+                HardwareEventId::CacheReferences => PERF_COUNT_HW_CACHE_REFERENCES,
+                HardwareEventId::CacheMisses => PERF_COUNT_HW_CACHE_MISSES,
+                HardwareEventId::BranchInstructions => PERF_COUNT_HW_BRANCH_INSTRUCTIONS,
+                HardwareEventId::BranchMisses => PERF_COUNT_HW_BRANCH_MISSES,
+                HardwareEventId::BusCycles => PERF_COUNT_HW_BUS_CYCLES,
+                HardwareEventId::StalledCyclesFrontend => PERF_COUNT_HW_STALLED_CYCLES_FRONTEND,
+                HardwareEventId::StalledCyclesBackend => PERF_COUNT_HW_STALLED_CYCLES_BACKEND,
+                HardwareEventId::RefCpuCycles => PERF_COUNT_HW_REF_CPU_CYCLES,
+                _ => return Err(std::io::ErrorKind::InvalidInput.into()),
+            };
+            let config = u64::from(pmu_type.0) << 32 | u64::from(id);
+            (PERF_TYPE_HARDWARE, 0, config, 0, 0)
+        }
+        PerfEventType::Software(counter_type) => {
+            let config = match counter_type {
+                SoftwareCounterType::CpuClock => PERF_COUNT_SW_CPU_CLOCK,
+                SoftwareCounterType::TaskClock => PERF_COUNT_SW_TASK_CLOCK,
+                SoftwareCounterType::PageFaults => PERF_COUNT_SW_PAGE_FAULTS,
+                SoftwareCounterType::ContextSwitches => PERF_COUNT_SW_CONTEXT_SWITCHES,
+                SoftwareCounterType::CpuMigrations => PERF_COUNT_SW_CPU_MIGRATIONS,
+                SoftwareCounterType::PageFaultsMin => PERF_COUNT_SW_PAGE_FAULTS_MIN,
+                SoftwareCounterType::PageFaultsMaj => PERF_COUNT_SW_PAGE_FAULTS_MAJ,
+                SoftwareCounterType::AlignmentFaults => PERF_COUNT_SW_ALIGNMENT_FAULTS,
+                SoftwareCounterType::EmulationFaults => PERF_COUNT_SW_EMULATION_FAULTS,
+                SoftwareCounterType::Dummy => PERF_COUNT_SW_DUMMY,
+                SoftwareCounterType::BpfOutput => PERF_COUNT_SW_BPF_OUTPUT,
+                SoftwareCounterType::CgroupSwitches => PERF_COUNT_SW_CGROUP_SWITCHES,
+                _ => return Err(std::io::ErrorKind::InvalidInput.into()),
+            };
+            (PERF_TYPE_SOFTWARE, 0, config, 0, 0)
+        }
+        PerfEventType::Tracepoint(config) => (PERF_TYPE_TRACEPOINT, 0, config, 0, 0),
+        PerfEventType::HwCache(cache_id, op, result, pmu_type) => {
+            let cache_id = match cache_id {
+                HardwareCacheId::L1d => PERF_COUNT_HW_CACHE_L1D,
+                HardwareCacheId::L1i => PERF_COUNT_HW_CACHE_L1I,
+                HardwareCacheId::Ll => PERF_COUNT_HW_CACHE_LL,
+                HardwareCacheId::Dtlb => PERF_COUNT_HW_CACHE_DTLB,
+                HardwareCacheId::Itlb => PERF_COUNT_HW_CACHE_ITLB,
+                HardwareCacheId::Bpu => PERF_COUNT_HW_CACHE_BPU,
+                HardwareCacheId::Node => PERF_COUNT_HW_CACHE_NODE,
+                _ => return Err(std::io::ErrorKind::InvalidInput.into()),
+            };
+            let op = match op {
+                HardwareCacheOp::Read => PERF_COUNT_HW_CACHE_OP_READ,
+                HardwareCacheOp::Write => PERF_COUNT_HW_CACHE_OP_WRITE,
+                HardwareCacheOp::Prefetch => PERF_COUNT_HW_CACHE_OP_PREFETCH,
+            };
+            let result = match result {
+                HardwareCacheOpResult::Access => PERF_COUNT_HW_CACHE_RESULT_ACCESS,
+                HardwareCacheOpResult::Miss => PERF_COUNT_HW_CACHE_RESULT_MISS,
+            };
+            let config = u64::from(pmu_type.0) << 32
+                | u64::from(result) << 16
+                | u64::from(op) << 8
+                | u64::from(cache_id);
+            (PERF_TYPE_HW_CACHE, 0, config, 0, 0)
+        }
+        PerfEventType::Breakpoint(bp_type, addr, len) => {
+            (PERF_TYPE_BREAKPOINT, bp_type.bits(), 0, addr.0, len.0)
+        }
+        PerfEventType::DynamicPmu(type_, config, config1, config2) => {
+            (type_, 0, config, config1, config2)
+        }
+    };
+    Ok(fields)
+}
+
+fn clock_id_value(clock_id: ClockId) -> Result<u32, std::io::Error> {
+    let value = match clock_id {
+        ClockId::Realtime => 0,
+        ClockId::Monotonic => 1,
+        ClockId::ProcessCputimeId => 2,
+        ClockId::ThreadCputimeId => 3,
+        ClockId::MonotonicRaw => 4,
+        ClockId::RealtimeCoarse => 5,
+        ClockId::MonotonicCoarse => 6,
+        ClockId::Boottime => 7,
+        ClockId::RealtimeAlarm => 8,
+        ClockId::BoottimeAlarm => 9,
+        _ => return Err(std::io::ErrorKind::InvalidInput.into()),
+    };
+    Ok(value)
+}
@@ -0,0 +1,117 @@
+use byteorder::ByteOrder;
+use linux_perf_event_reader::RawData;
+
+const PERF_AUX_FLAG_TRUNCATED: u64 = 0x01;
+const PERF_AUX_FLAG_OVERWRITE: u64 = 0x02;
+const PERF_AUX_FLAG_PARTIAL: u64 = 0x04;
+const PERF_AUX_FLAG_COLLISION: u64 = 0x08;
+
+/// A `PERF_RECORD_AUX` record, emitted by the kernel when new data has been
+/// written to the aux buffer of an event, e.g. Intel PT or ARM SPE data.
+///
+/// The record only says where in the aux buffer the data is; the data itself
+/// is copied into the file by `perf` in [`AuxtraceRecord`]s. Use
+/// [`AuxTimelines`](crate::analysis::AuxTimelines) to combine the two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuxRecord {
+    /// The offset of the new data in the aux buffer. This offset keeps
+    /// increasing, it doesn't wrap around with the buffer size.
+    pub aux_offset: u64,
+    /// The size of the new data.
+    pub aux_size: u64,
+    /// The `PERF_AUX_FLAG_*` flags.
+    pub flags: u64,
+}
+
+impl AuxRecord {
+    pub fn parse<T: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        let aux_offset = data.read_u64::<T>()?;
+        let aux_size = data.read_u64::<T>()?;
+        let flags = data.read_u64::<T>()?;
+        Ok(Self {
+            aux_offset,
+            aux_size,
+            flags,
+        })
+    }
+
+    /// The aux buffer was full, and data after this record's data was lost.
+    pub fn is_truncated(&self) -> bool {
+        self.flags & PERF_AUX_FLAG_TRUNCATED != 0
+    }
+
+    /// The aux buffer is in overwrite (snapshot) mode, and the data may have
+    /// overwritten older data.
+    pub fn is_overwrite(&self) -> bool {
+        self.flags & PERF_AUX_FLAG_OVERWRITE != 0
+    }
+
+    /// The data contains gaps, e.g. because the hardware dropped packets.
+    pub fn is_partial(&self) -> bool {
+        self.flags & PERF_AUX_FLAG_PARTIAL != 0
+    }
+
+    /// A sample collided with another sample, and data was lost.
+    pub fn has_collision(&self) -> bool {
+        self.flags & PERF_AUX_FLAG_COLLISION != 0
+    }
+}
+
+/// A `PERF_RECORD_AUXTRACE` record, which `perf` writes for each chunk of
+/// data that it copied out of an aux buffer.
+///
+/// In the file, the data follows the record and isn't included in the size
+/// in the record header. [`PerfRecordIter`](crate::PerfRecordIter) reads the
+/// data together with the record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuxtraceRecord<'a> {
+    /// The offset of the data in the aux buffer, in the same units as
+    /// [`AuxRecord::aux_offset`].
+    pub offset: u64,
+    /// An identifier for the data, unique per aux buffer.
+    pub reference: u64,
+    /// The index of the aux buffer mmap.
+    pub idx: u32,
+    /// The thread, for per-thread aux buffers.
+    pub tid: i32,
+    /// The CPU, or `u32::MAX` (-1) for per-thread aux buffers.
+    pub cpu: u32,
+    pub data: RawData<'a>,
+}
+
+impl<'a> AuxtraceRecord<'a> {
+    pub fn parse<T: ByteOrder>(mut data: RawData<'a>) -> Result<Self, std::io::Error> {
+        // struct perf_record_auxtrace {
+        //     struct perf_event_header header;
+        //     __u64 size;
+        //     __u64 offset;
+        //     __u64 reference;
+        //     __u32 idx;
+        //     __u32 tid;
+        //     __u32 cpu;
+        //     __u32 reserved__;
+        // };
+        let size = data.read_u64::<T>()?;
+        let offset = data.read_u64::<T>()?;
+        let reference = data.read_u64::<T>()?;
+        let idx = data.read_u32::<T>()?;
+        let tid = data.read_i32::<T>()?;
+        let cpu = data.read_u32::<T>()?;
+        let _reserved = data.read_u32::<T>()?;
+        let size = usize::try_from(size).map_err(|_| std::io::ErrorKind::InvalidData)?;
+        let data = data.split_off_prefix(size)?;
+        Ok(Self {
+            offset,
+            reference,
+            idx,
+            tid,
+            cpu,
+            data,
+        })
+    }
+
+    /// The CPU of the aux buffer, or `None` for per-thread aux buffers.
+    pub fn cpu(&self) -> Option<u32> {
+        (self.cpu != u32::MAX).then_some(self.cpu)
+    }
+}
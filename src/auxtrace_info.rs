@@ -0,0 +1,311 @@
+use byteorder::ByteOrder;
+use linux_perf_event_reader::RawData;
+
+use crate::TimeConv;
+
+/// A `PERF_RECORD_AUXTRACE_INFO` record, which `perf` writes at the start of
+/// a file with aux tracing data, e.g. from `perf record -e intel_pt//`.
+///
+/// It says which kind of hardware trace is in the [`AuxtraceRecord`](crate::AuxtraceRecord)s
+/// and how the tracer was configured. The configuration is stored as an
+/// array of u64 values whose layout depends on the trace type; `config`
+/// has the values for the trace types which this crate knows about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuxtraceInfo {
+    pub auxtrace_type: AuxtraceType,
+    pub config: AuxtraceConfig,
+    /// The raw values of the `priv` array, for the fields which aren't in
+    /// `config`.
+    pub priv_values: Vec<u64>,
+}
+
+/// The kind of hardware trace, `enum auxtrace_type` in perf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AuxtraceType(pub u32);
+
+impl AuxtraceType {
+    pub const UNKNOWN: Self = Self(0);
+    pub const INTEL_PT: Self = Self(1);
+    pub const INTEL_BTS: Self = Self(2);
+    pub const CS_ETM: Self = Self(3);
+    pub const ARM_SPE: Self = Self(4);
+    pub const S390_CPUMSF: Self = Self(5);
+    pub const HISI_PTT: Self = Self(6);
+}
+
+/// The trace configuration in an [`AuxtraceInfo`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AuxtraceConfig {
+    IntelPt(IntelPtConfig),
+    IntelBts(IntelBtsConfig),
+    CsEtm(CsEtmConfig),
+    ArmSpe(ArmSpeConfig),
+    /// A trace type whose configuration this crate can't interpret. Use
+    /// [`AuxtraceInfo::priv_values`].
+    Unknown,
+}
+
+/// The configuration of an Intel Processor Trace recording.
+///
+/// The fields after `per_cpu_mmaps` were added in later perf versions and
+/// are `None` in files from older versions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntelPtConfig {
+    /// The type of the `intel_pt` PMU, for matching the event attributes.
+    pub pmu_type: u32,
+    /// For converting the TSC values in the trace packets to perf timestamps.
+    pub time_conv: TimeConv,
+    /// The bit of the `tsc` option in the event config.
+    pub tsc_bit: u64,
+    /// The bit of the `noretcomp` option in the event config.
+    pub noretcomp_bit: u64,
+    /// Whether context switches were recorded, and how.
+    pub have_sched_switch: u64,
+    /// Whether the trace was recorded in snapshot mode (`perf record -S`).
+    pub snapshot_mode: bool,
+    /// Whether there was one aux buffer per CPU rather than per thread.
+    pub per_cpu_mmaps: bool,
+    pub mtc_bit: Option<u64>,
+    pub mtc_freq_bits: Option<u64>,
+    /// The TSC to core crystal clock ratio, as numerator and denominator.
+    pub tsc_ctc_ratio: Option<(u64, u64)>,
+    pub cyc_bit: Option<u64>,
+    pub max_non_turbo_ratio: Option<u64>,
+    /// The length of the address filter string which follows the values.
+    pub filter_str_len: Option<u64>,
+}
+
+/// The configuration of an Intel Branch Trace Store recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntelBtsConfig {
+    /// The type of the `intel_bts` PMU, for matching the event attributes.
+    pub pmu_type: u32,
+    /// For converting the TSC values in the trace to perf timestamps.
+    pub time_conv: TimeConv,
+    /// Whether the trace was recorded in snapshot mode (`perf record -S`).
+    pub snapshot_mode: bool,
+}
+
+/// The global configuration of an Arm CoreSight ETM recording. The per-CPU
+/// tracer configurations follow in [`AuxtraceInfo::priv_values`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsEtmConfig {
+    /// The version of the `priv` layout.
+    pub version: u64,
+    /// The type of the `cs_etm` PMU, for matching the event attributes.
+    pub pmu_type: u32,
+    /// The number of CPUs with a per-CPU configuration.
+    pub cpu_count: u32,
+    /// Whether the trace was recorded in snapshot mode (`perf record -S`).
+    pub snapshot_mode: bool,
+}
+
+/// The configuration of an Arm Statistical Profiling Extension recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArmSpeConfig {
+    /// The version of the `priv` layout: 1 for files from older perf
+    /// versions, which only have the PMU type, and 2 or higher for newer
+    /// files, which also have per-CPU information.
+    pub version: u64,
+    /// The type of the `arm_spe` PMU, for matching the event attributes.
+    pub pmu_type: u32,
+}
+
+impl AuxtraceInfo {
+    pub fn parse<T: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        // struct perf_record_auxtrace_info {
+        //     struct perf_event_header header;
+        //     __u32 type;
+        //     __u32 reserved__;
+        //     __u64 priv[];
+        // };
+        let auxtrace_type = AuxtraceType(data.read_u32::<T>()?);
+        let _reserved = data.read_u32::<T>()?;
+        let mut priv_values = Vec::with_capacity(data.len() / 8);
+        while data.len() >= 8 {
+            priv_values.push(data.read_u64::<T>()?);
+        }
+        let config = match auxtrace_type {
+            AuxtraceType::INTEL_PT => IntelPtConfig::from_priv(&priv_values),
+            AuxtraceType::INTEL_BTS => IntelBtsConfig::from_priv(&priv_values),
+            AuxtraceType::CS_ETM => CsEtmConfig::from_priv(&priv_values),
+            AuxtraceType::ARM_SPE => ArmSpeConfig::from_priv(&priv_values),
+            _ => None,
+        }
+        .unwrap_or(AuxtraceConfig::Unknown);
+        Ok(Self {
+            auxtrace_type,
+            config,
+            priv_values,
+        })
+    }
+}
+
+/// The `TimeConv` from the time fields which are shared by the Intel PT and
+/// Intel BTS layouts.
+fn time_conv_from_priv(time_fields: &[u64]) -> TimeConv {
+    TimeConv {
+        time_shift: time_fields[0],
+        time_mult: time_fields[1],
+        time_zero: time_fields[2],
+        time_cycles: 0,
+        time_mask: 0,
+        cap_user_time_zero: time_fields[3] != 0,
+        cap_user_time_short: false,
+    }
+}
+
+impl IntelPtConfig {
+    fn from_priv(values: &[u64]) -> Option<AuxtraceConfig> {
+        // enum {
+        //     INTEL_PT_PMU_TYPE,
+        //     INTEL_PT_TIME_SHIFT,
+        //     INTEL_PT_TIME_MULT,
+        //     INTEL_PT_TIME_ZERO,
+        //     INTEL_PT_CAP_USER_TIME_ZERO,
+        //     INTEL_PT_TSC_BIT,
+        //     INTEL_PT_NORETCOMP_BIT,
+        //     INTEL_PT_HAVE_SCHED_SWITCH,
+        //     INTEL_PT_SNAPSHOT_MODE,
+        //     INTEL_PT_PER_CPU_MMAPS,
+        //     INTEL_PT_MTC_BIT,
+        //     INTEL_PT_MTC_FREQ_BITS,
+        //     INTEL_PT_TSC_CTC_N,
+        //     INTEL_PT_TSC_CTC_D,
+        //     INTEL_PT_CYC_BIT,
+        //     INTEL_PT_MAX_NONTURBO_RATIO,
+        //     INTEL_PT_FILTER_STR_LEN,
+        // };
+        if values.len() < 10 {
+            return None;
+        }
+        let optional = |index: usize| values.get(index).copied();
+        Some(AuxtraceConfig::IntelPt(IntelPtConfig {
+            pmu_type: values[0] as u32,
+            time_conv: time_conv_from_priv(&values[1..5]),
+            tsc_bit: values[5],
+            noretcomp_bit: values[6],
+            have_sched_switch: values[7],
+            snapshot_mode: values[8] != 0,
+            per_cpu_mmaps: values[9] != 0,
+            mtc_bit: optional(10),
+            mtc_freq_bits: optional(11),
+            tsc_ctc_ratio: optional(12).zip(optional(13)),
+            cyc_bit: optional(14),
+            max_non_turbo_ratio: optional(15),
+            filter_str_len: optional(16),
+        }))
+    }
+}
+
+impl IntelBtsConfig {
+    fn from_priv(values: &[u64]) -> Option<AuxtraceConfig> {
+        // enum {
+        //     INTEL_BTS_PMU_TYPE,
+        //     INTEL_BTS_TIME_SHIFT,
+        //     INTEL_BTS_TIME_MULT,
+        //     INTEL_BTS_TIME_ZERO,
+        //     INTEL_BTS_CAP_USER_TIME_ZERO,
+        //     INTEL_BTS_SNAPSHOT_MODE,
+        // };
+        if values.len() < 6 {
+            return None;
+        }
+        Some(AuxtraceConfig::IntelBts(IntelBtsConfig {
+            pmu_type: values[0] as u32,
+            time_conv: time_conv_from_priv(&values[1..5]),
+            snapshot_mode: values[5] != 0,
+        }))
+    }
+}
+
+impl CsEtmConfig {
+    fn from_priv(values: &[u64]) -> Option<AuxtraceConfig> {
+        // enum {
+        //     CS_HEADER_VERSION,
+        //     CS_PMU_TYPE_CPUS, // pmu_type << 32 | cpu_count
+        //     CS_ETM_SNAPSHOT,
+        // };
+        if values.len() < 3 {
+            return None;
+        }
+        Some(AuxtraceConfig::CsEtm(CsEtmConfig {
+            version: values[0],
+            pmu_type: (values[1] >> 32) as u32,
+            cpu_count: values[1] as u32,
+            snapshot_mode: values[2] != 0,
+        }))
+    }
+}
+
+impl ArmSpeConfig {
+    fn from_priv(values: &[u64]) -> Option<AuxtraceConfig> {
+        // Version 1: { ARM_SPE_PMU_TYPE, ARM_SPE_PER_CPU_MMAPS }
+        // Version 2: { ARM_SPE_HEADER_VERSION, ARM_SPE_HEADER_SIZE,
+        //              ARM_SPE_PMU_TYPE_V2, ARM_SPE_CPUS_NUM, <per-CPU data> }
+        let config = match values {
+            [pmu_type, _per_cpu_mmaps] => ArmSpeConfig {
+                version: 1,
+                pmu_type: *pmu_type as u32,
+            },
+            [version, _header_size, pmu_type, ..] => ArmSpeConfig {
+                version: *version,
+                pmu_type: *pmu_type as u32,
+            },
+            _ => return None,
+        };
+        Some(AuxtraceConfig::ArmSpe(config))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::LittleEndian;
+    use linux_perf_event_reader::RawData;
+
+    use super::{AuxtraceConfig, AuxtraceInfo, AuxtraceType};
+
+    fn encode(auxtrace_type: u32, values: &[u64]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&auxtrace_type.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn intel_pt() {
+        // An Intel PT info from an older perf version, without the MTC fields.
+        let data = encode(1, &[8, 10, 410, 1000, 1, 10, 11, 1, 0, 1]);
+        let info = AuxtraceInfo::parse::<LittleEndian>(RawData::from(&data[..])).unwrap();
+        assert_eq!(info.auxtrace_type, AuxtraceType::INTEL_PT);
+        assert_eq!(info.priv_values.len(), 10);
+        let AuxtraceConfig::IntelPt(config) = info.config else {
+            panic!("expected an Intel PT config");
+        };
+        assert_eq!(config.pmu_type, 8);
+        assert_eq!(config.time_conv.tsc_to_perf_time(1024), 1000 + 410);
+        assert!(!config.snapshot_mode);
+        assert!(config.per_cpu_mmaps);
+        assert_eq!(config.mtc_bit, None);
+    }
+
+    #[test]
+    fn cs_etm_and_unknown() {
+        let data = encode(3, &[1, (9 << 32) | 4, 1]);
+        let info = AuxtraceInfo::parse::<LittleEndian>(RawData::from(&data[..])).unwrap();
+        let AuxtraceConfig::CsEtm(config) = info.config else {
+            panic!("expected a CoreSight ETM config");
+        };
+        assert_eq!((config.pmu_type, config.cpu_count), (9, 4));
+        assert!(config.snapshot_mode);
+
+        let data = encode(42, &[1, 2]);
+        let info = AuxtraceInfo::parse::<LittleEndian>(RawData::from(&data[..])).unwrap();
+        assert_eq!(info.config, AuxtraceConfig::Unknown);
+        assert_eq!(info.priv_values, vec![1, 2]);
+    }
+}
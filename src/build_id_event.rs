@@ -1,7 +1,13 @@
-use std::io::Read;
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
 
-use byteorder::{ByteOrder, ReadBytesExt};
-use linux_perf_event_reader::{constants::PERF_RECORD_MISC_BUILD_ID_SIZE, PerfEventHeader};
+use linux_perf_event_reader::constants::{
+    PERF_RECORD_MISC_BUILD_ID_SIZE, PERF_RECORD_MISC_GUEST_KERNEL, PERF_RECORD_MISC_KERNEL,
+    PERF_RECORD_MISC_USER,
+};
+use linux_perf_event_reader::PerfEventHeader;
+
+use crate::{DsoInfo, DsoKey};
 
 /// Old versions of perf did not write down the length of the build ID.
 /// Detect the true length by removing 4-byte chunks of zeros from the end.
@@ -17,35 +23,45 @@ fn detect_build_id_len(build_id_bytes: &[u8]) -> u8 {
     len as u8
 }
 
-/// `build_id_event`
+/// `build_id_event`, an entry in the `BUILD_ID` feature section.
 ///
 /// If PERF_RECORD_MISC_KERNEL is set in header.misc, then this
 /// is the build id for the vmlinux image or a kmod.
+///
+/// The build ID and the path are borrowed from the section data. Use
+/// [`PerfFile::build_id_events`](crate::PerfFile::build_id_events) to get
+/// these entries without copying, or [`PerfFile::build_ids`](crate::PerfFile::build_ids)
+/// to get owned [`DsoInfo`](crate::DsoInfo)s.
 #[derive(Debug, Clone)]
-pub struct BuildIdEvent {
+pub struct BuildIdEvent<'a> {
     pub header: PerfEventHeader,
-    pub _pid: i32,
-    pub build_id: Vec<u8>,
-    pub file_path: Vec<u8>,
+    pub pid: i32,
+    pub build_id: &'a [u8],
+    /// The file path, without the trailing nul bytes.
+    pub file_path: &'a [u8],
 }
 
-impl BuildIdEvent {
-    pub fn parse<R: Read, T: ByteOrder>(mut reader: R) -> Result<Self, std::io::Error> {
+impl<'a> BuildIdEvent<'a> {
+    /// Parses one entry from the start of `data`, and advances `data` past it.
+    pub fn parse<T: ByteOrder>(data: &mut &'a [u8]) -> Result<Self, std::io::Error> {
+        let mut reader = *data;
         let header = PerfEventHeader::parse::<_, T>(&mut reader)?;
-        let _pid = reader.read_i32::<T>()?;
-        let mut build_id_bytes = [0; 24];
-        reader.read_exact(&mut build_id_bytes)?;
+        let pid = reader.read_i32::<T>()?;
+        if reader.len() < 24 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let (build_id_bytes, rest) = reader.split_at(24);
 
         // Followed by file path for the remaining bytes. The total size of the record
         // is given by header.size.
         const BYTES_BEFORE_PATH: usize = PerfEventHeader::STRUCT_SIZE + 4 + 24;
         let path_len = usize::from(header.size).saturating_sub(BYTES_BEFORE_PATH);
-        let mut path_bytes = vec![0; path_len];
-        reader.read_exact(&mut path_bytes)?;
-
-        let path_len = memchr::memchr(0, &path_bytes).unwrap_or(path_len);
-        path_bytes.truncate(path_len);
-        let file_path = path_bytes;
+        if rest.len() < path_len {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let (path_bytes, rest) = rest.split_at(path_len);
+        let path_len = memchr::memchr(0, path_bytes).unwrap_or(path_len);
+        let file_path = &path_bytes[..path_len];
 
         // If PERF_RECORD_MISC_BUILD_ID_SIZE is set in header.misc, then build_id_bytes[20]
         // is the length of the build id (<= 20), and build_id_bytes[21..24] are unused.
@@ -59,13 +75,77 @@ impl BuildIdEvent {
         } else {
             detect_build_id_len(&build_id_bytes[..20])
         };
-        let build_id = build_id_bytes[..build_id_len as usize].to_owned();
+        let build_id = &build_id_bytes[..build_id_len as usize];
 
+        *data = rest;
         Ok(Self {
             header,
-            _pid,
+            pid,
             build_id,
             file_path,
         })
     }
+
+    /// Appends the entry to `out`, in the layout that [`BuildIdEvent::parse`]
+    /// parses, which is the layout perf writes: the build ID size is stored,
+    /// and the path is nul-terminated and padded to a multiple of 64 bytes.
+    /// The size and the `PERF_RECORD_MISC_BUILD_ID_SIZE` flag in the header
+    /// are computed; the other header fields are taken from `self.header`.
+    ///
+    /// Returns an `InvalidInput` error if the build ID is longer than 20
+    /// bytes or the entry is too large for the header's size field.
+    pub fn write<T: ByteOrder>(&self, out: &mut Vec<u8>) -> Result<(), std::io::Error> {
+        if self.build_id.len() > 20 {
+            return Err(std::io::ErrorKind::InvalidInput.into());
+        }
+        let path_len = (self.file_path.len() + 1).next_multiple_of(64);
+        let size = u16::try_from(PerfEventHeader::STRUCT_SIZE + 4 + 24 + path_len)
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        out.write_u32::<T>(self.header.type_)?;
+        out.write_u16::<T>(self.header.misc | PERF_RECORD_MISC_BUILD_ID_SIZE)?;
+        out.write_u16::<T>(size)?;
+        out.write_i32::<T>(self.pid)?;
+        let mut build_id_bytes = [0; 24];
+        build_id_bytes[..self.build_id.len()].copy_from_slice(self.build_id);
+        build_id_bytes[20] = self.build_id.len() as u8;
+        out.extend_from_slice(&build_id_bytes);
+        out.extend_from_slice(self.file_path);
+        out.resize(out.len() + path_len - self.file_path.len(), 0);
+        Ok(())
+    }
+
+    /// The file path as a [`BStr`](bstr::BStr).
+    #[cfg(feature = "bstr")]
+    pub fn file_path_bstr(&self) -> &'a bstr::BStr {
+        bstr::BStr::new(self.file_path)
+    }
+}
+
+/// Encodes the contents of a `BUILD_ID` feature section with one entry per
+/// DSO, ordered by key so that the output is deterministic.
+pub(crate) fn build_id_section<T: ByteOrder>(
+    build_ids: &HashMap<DsoKey, DsoInfo>,
+) -> Result<Vec<u8>, std::io::Error> {
+    let mut entries: Vec<_> = build_ids.iter().collect();
+    entries.sort_unstable_by_key(|(dso_key, _)| *dso_key);
+    let mut data = Vec::new();
+    for (dso_key, dso_info) in entries {
+        let misc = match dso_key {
+            DsoKey::Kernel | DsoKey::KernelModule { .. } => PERF_RECORD_MISC_KERNEL,
+            DsoKey::GuestKernel => PERF_RECORD_MISC_GUEST_KERNEL,
+            _ => PERF_RECORD_MISC_USER,
+        };
+        let event = BuildIdEvent {
+            header: PerfEventHeader {
+                type_: 0,
+                misc,
+                size: 0,
+            },
+            pid: -1,
+            build_id: &dso_info.build_id,
+            file_path: &dso_info.path,
+        };
+        event.write::<T>(&mut data)?;
+    }
+    Ok(data)
 }
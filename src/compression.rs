@@ -0,0 +1,72 @@
+use std::io::Write;
+
+use byteorder::{ByteOrder, WriteBytesExt};
+
+use crate::constants::PERF_RECORD_COMPRESSED2;
+
+/// The largest amount of compressed data which fits into one `COMPRESSED2`
+/// record: the record size is a u16, and the record consists of the 8 byte
+/// header, the 8 byte data size, and the data padded to 8 bytes.
+const MAX_COMPRESSED_DATA_PER_RECORD: usize = (u16::MAX as usize - 8 - 8) / 8 * 8;
+
+/// Collects records and writes them as zstd-compressed `PERF_RECORD_COMPRESSED2`
+/// records, like `perf record -z` does.
+///
+/// Each batch is compressed into one zstd frame. If a frame doesn't fit into
+/// one record, it is split across consecutive records; readers keep the
+/// decompressor state across records, so this is fine.
+pub(crate) struct Compressor {
+    compressor: zstd::bulk::Compressor<'static>,
+    batch_size: usize,
+    /// The encoded records of the current batch, including their headers.
+    pending: Vec<u8>,
+}
+
+impl Compressor {
+    pub fn new(level: i32, batch_size: usize) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            compressor: zstd::bulk::Compressor::new(level)?,
+            batch_size,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// The buffer which the next record should be appended to, including its
+    /// header.
+    pub fn pending_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.pending
+    }
+
+    /// Returns whether the current batch has reached the batch size.
+    pub fn is_batch_full(&self) -> bool {
+        self.pending.len() >= self.batch_size
+    }
+
+    /// Compresses the current batch, if any, and writes it as `COMPRESSED2`
+    /// records.
+    pub fn write_batch<T: ByteOrder, W: Write>(
+        &mut self,
+        writer: &mut W,
+    ) -> Result<(), std::io::Error> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let compressed = self.compressor.compress(&self.pending)?;
+        self.pending.clear();
+        for chunk in compressed.chunks(MAX_COMPRESSED_DATA_PER_RECORD) {
+            let padding = chunk.len().next_multiple_of(8) - chunk.len();
+            let size = 8 + 8 + chunk.len() + padding;
+            writer.write_u32::<T>(PERF_RECORD_COMPRESSED2)?;
+            writer.write_u16::<T>(0)?;
+            writer.write_u16::<T>(size as u16)?;
+            writer.write_u64::<T>(chunk.len() as u64)?;
+            writer.write_all(chunk)?;
+            writer.write_all(&[0; 8][..padding])?;
+        }
+        Ok(())
+    }
+}
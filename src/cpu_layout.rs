@@ -0,0 +1,105 @@
+use crate::feature_sections::{CoreType, CpuTopology, CpuTopologyEntry, HybridTopology, NrCpus};
+
+/// The result of checking a sample's `cpu` field with [`CpuLayout::check_cpu`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CpuIndexStatus {
+    /// The CPU index is in range and the CPU was online.
+    Valid,
+    /// The CPU index is in range, but the topology says the CPU was offline
+    /// when the profile was recorded.
+    Offline,
+    /// The CPU index is larger than the number of available CPUs. This
+    /// indicates a bug in the producer of the file.
+    OutOfRange,
+    /// The file has neither an `NRCPUS` nor a `CPU_TOPOLOGY` section, so the
+    /// CPU index cannot be checked.
+    Unknown,
+}
+
+/// Combines the `NRCPUS`, `CPU_TOPOLOGY` and `HYBRID_TOPOLOGY` information so
+/// that CPU indexes from samples can be checked and mapped to topology entries
+/// cheaply.
+///
+/// Create one with [`PerfFile::cpu_layout`](crate::PerfFile::cpu_layout).
+#[derive(Debug, Clone, Default)]
+pub struct CpuLayout {
+    nr_cpus: Option<NrCpus>,
+    topology: Option<CpuTopology>,
+    hybrid_topology: Option<HybridTopology>,
+}
+
+impl CpuLayout {
+    pub fn new(
+        nr_cpus: Option<NrCpus>,
+        topology: Option<CpuTopology>,
+        hybrid_topology: Option<HybridTopology>,
+    ) -> Self {
+        Self {
+            nr_cpus,
+            topology,
+            hybrid_topology,
+        }
+    }
+
+    /// The `NRCPUS` information, if present.
+    pub fn nr_cpus(&self) -> Option<NrCpus> {
+        self.nr_cpus
+    }
+
+    /// The `CPU_TOPOLOGY` information, if present.
+    pub fn topology(&self) -> Option<&CpuTopology> {
+        self.topology.as_ref()
+    }
+
+    /// The `HYBRID_TOPOLOGY` information, if present.
+    pub fn hybrid_topology(&self) -> Option<&HybridTopology> {
+        self.hybrid_topology.as_ref()
+    }
+
+    /// The kind of core that `cpu` is on a hybrid machine, e.g. P-core vs E-core.
+    ///
+    /// Returns `None` if the file has no hybrid topology, which is the case
+    /// for profiles recorded on non-hybrid machines.
+    pub fn core_type_for_cpu(&self, cpu: u32) -> Option<CoreType> {
+        self.hybrid_topology.as_ref()?.core_type_for_cpu(cpu)
+    }
+
+    /// Checks the CPU index from a sample's `cpu` field.
+    pub fn check_cpu(&self, cpu: u32) -> CpuIndexStatus {
+        let cpu_count = match (&self.nr_cpus, &self.topology) {
+            (Some(nr_cpus), _) => nr_cpus.nr_cpus_available as usize,
+            (None, Some(topology)) if !topology.cpus.is_empty() => topology.cpus.len(),
+            _ => return CpuIndexStatus::Unknown,
+        };
+        if cpu as usize >= cpu_count {
+            return CpuIndexStatus::OutOfRange;
+        }
+        match self.topology_entry(cpu) {
+            Some(entry) if !entry.is_online() => CpuIndexStatus::Offline,
+            _ => CpuIndexStatus::Valid,
+        }
+    }
+
+    /// Returns the topology entry for the logical CPU index `cpu`, if known.
+    pub fn topology_entry(&self, cpu: u32) -> Option<&CpuTopologyEntry> {
+        self.topology.as_ref()?.cpus.get(cpu as usize)
+    }
+
+    /// Returns the socket ID of the CPU, if known and the CPU was online.
+    pub fn socket_for_cpu(&self, cpu: u32) -> Option<u32> {
+        let entry = self.topology_entry(cpu)?;
+        entry.is_online().then_some(entry.socket_id as u32)
+    }
+
+    /// Returns a key identifying the physical core (socket, die, core) of the
+    /// CPU, if known and the CPU was online. Hardware threads of the same core
+    /// share the same key.
+    pub fn physical_core_for_cpu(&self, cpu: u32) -> Option<(u32, u32, u32)> {
+        let entry = self.topology_entry(cpu)?;
+        if !entry.is_online() {
+            return None;
+        }
+        let die_id = entry.die_id.unwrap_or(0).max(0) as u32;
+        Some((entry.socket_id as u32, die_id, entry.core_id as u32))
+    }
+}
@@ -0,0 +1,90 @@
+use byteorder::ByteOrder;
+use linux_perf_event_reader::RawData;
+
+use crate::cpu_set::CpuSet;
+
+const PERF_CPU_MAP_CPUS: u16 = 0;
+const PERF_CPU_MAP_MASK: u16 = 1;
+const PERF_CPU_MAP_RANGE_CPUS: u16 = 2;
+
+/// The set of CPUs which a `perf stat` / `perf record` session was monitoring,
+/// from a `PERF_RECORD_CPU_MAP` record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuMap {
+    /// The CPUs in the map.
+    pub cpus: CpuSet,
+    /// Whether the map contains the "any CPU" entry (-1). This is the case
+    /// for per-thread sessions which are not bound to a CPU.
+    pub any_cpu: bool,
+}
+
+impl CpuMap {
+    pub fn parse<T: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        // The record body is a packed `struct perf_record_cpu_map_data`, which
+        // starts with a u16 type and is followed by one of three encodings.
+        let type_ = data.read_u16::<T>()?;
+        match type_ {
+            PERF_CPU_MAP_CPUS => {
+                // struct { u16 nr; u16 cpu[nr]; }
+                let nr = data.read_u16::<T>()?;
+                let mut any_cpu = false;
+                let mut cpus = Vec::with_capacity(nr as usize);
+                for _ in 0..nr {
+                    match data.read_u16::<T>()? {
+                        u16::MAX => any_cpu = true,
+                        cpu => cpus.push(u32::from(cpu)),
+                    }
+                }
+                Ok(Self {
+                    cpus: CpuSet::from_cpus(cpus),
+                    any_cpu,
+                })
+            }
+            PERF_CPU_MAP_MASK => {
+                // struct { u16 nr; u16 long_size; [4 bytes padding if long_size == 8]; mask[nr]; }
+                let nr = data.read_u16::<T>()?;
+                let long_size = data.read_u16::<T>()?;
+                let mut mask = Vec::new();
+                match long_size {
+                    4 => {
+                        // Combine pairs of u32 words into u64 words.
+                        for i in 0..nr {
+                            let word = u64::from(data.read_u32::<T>()?);
+                            if i % 2 == 0 {
+                                mask.push(word);
+                            } else {
+                                *mask.last_mut().unwrap() |= word << 32;
+                            }
+                        }
+                    }
+                    8 => {
+                        data.skip(4)?;
+                        for _ in 0..nr {
+                            mask.push(data.read_u64::<T>()?);
+                        }
+                    }
+                    _ => return Err(std::io::ErrorKind::InvalidData.into()),
+                }
+                Ok(Self {
+                    cpus: CpuSet::from_mask(&mask),
+                    any_cpu: false,
+                })
+            }
+            PERF_CPU_MAP_RANGE_CPUS => {
+                // struct { u8 any_cpu; u8 pad; u16 start_cpu; u16 end_cpu; }
+                let any_cpu = data.read_u8()? != 0;
+                let _pad = data.read_u8()?;
+                let start_cpu = data.read_u16::<T>()?;
+                let end_cpu = data.read_u16::<T>()?;
+                if end_cpu < start_cpu {
+                    return Err(std::io::ErrorKind::InvalidData.into());
+                }
+                Ok(Self {
+                    cpus: CpuSet::from_range(start_cpu.into(), end_cpu.into()),
+                    any_cpu,
+                })
+            }
+            _ => Err(std::io::ErrorKind::InvalidData.into()),
+        }
+    }
+}
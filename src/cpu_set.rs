@@ -0,0 +1,195 @@
+use std::fmt;
+
+/// The number of CPUs which a cpulist string can describe, see
+/// [`CpuSet::parse_list_str`]. This is far above the number of CPUs of any
+/// real machine; it protects against huge ranges in malformed files.
+const MAX_LIST_STR_CPUS: u32 = 1 << 20;
+
+/// A set of logical CPU indexes.
+///
+/// This type is used wherever the perf.data format describes a group of CPUs,
+/// for example in `PERF_RECORD_CPU_MAP` records and in the topology feature
+/// sections. Those use a few different encodings (explicit lists, bitmasks,
+/// ranges, and the kernel's "cpulist" strings like `0-3,8`); they are all
+/// normalized into this type.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct CpuSet {
+    /// Sorted, no duplicates.
+    cpus: Vec<u32>,
+}
+
+impl CpuSet {
+    /// Create an empty set.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create a set from a list of CPUs, in any order. Duplicates are removed.
+    pub fn from_cpus(cpus: impl IntoIterator<Item = u32>) -> Self {
+        let mut cpus: Vec<u32> = cpus.into_iter().collect();
+        cpus.sort_unstable();
+        cpus.dedup();
+        Self { cpus }
+    }
+
+    /// Create a set containing all CPUs from `start` to `end`, both inclusive.
+    ///
+    /// This allocates 4 bytes per CPU in the range, so the range must not
+    /// come unchecked from untrusted input.
+    pub fn from_range(start: u32, end: u32) -> Self {
+        Self {
+            cpus: (start..=end).collect(),
+        }
+    }
+
+    /// Create a set from a bitmask, where bit `n` of `mask[i]` is set if CPU
+    /// `i * 64 + n` is in the set.
+    pub fn from_mask(mask: &[u64]) -> Self {
+        let mut cpus = Vec::new();
+        for (word_index, word) in mask.iter().enumerate() {
+            let mut word = *word;
+            while word != 0 {
+                let bit = word.trailing_zeros();
+                cpus.push(word_index as u32 * 64 + bit);
+                word &= word - 1;
+            }
+        }
+        Self { cpus }
+    }
+
+    /// Parse a "cpulist" string, as found in sysfs files such as
+    /// `/sys/devices/system/cpu/online` and in the topology feature sections.
+    ///
+    /// Example: `"0-3,8,10-11"`. Returns `None` if the string is malformed,
+    /// or if it contains more than 2^20 CPUs or CPU numbers of 2^20 or more.
+    pub fn parse_list_str(s: &str) -> Option<Self> {
+        let mut cpus = Vec::new();
+        for part in s.trim().split(',') {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            match part.split_once('-') {
+                Some((start, end)) => {
+                    let start: u32 = start.trim().parse().ok()?;
+                    let end: u32 = end.trim().parse().ok()?;
+                    if end < start
+                        || end >= MAX_LIST_STR_CPUS
+                        || cpus.len() + (end - start) as usize >= MAX_LIST_STR_CPUS as usize
+                    {
+                        return None;
+                    }
+                    cpus.extend(start..=end);
+                }
+                None => {
+                    let cpu: u32 = part.parse().ok()?;
+                    if cpu >= MAX_LIST_STR_CPUS || cpus.len() >= MAX_LIST_STR_CPUS as usize {
+                        return None;
+                    }
+                    cpus.push(cpu);
+                }
+            }
+        }
+        Some(Self::from_cpus(cpus))
+    }
+
+    /// Whether the set contains `cpu`.
+    pub fn contains(&self, cpu: u32) -> bool {
+        self.cpus.binary_search(&cpu).is_ok()
+    }
+
+    /// The number of CPUs in this set.
+    pub fn len(&self) -> usize {
+        self.cpus.len()
+    }
+
+    /// Whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.cpus.is_empty()
+    }
+
+    /// The lowest and highest CPU in the set.
+    pub fn bounds(&self) -> Option<(u32, u32)> {
+        Some((*self.cpus.first()?, *self.cpus.last()?))
+    }
+
+    /// The CPUs in this set, in ascending order.
+    pub fn as_slice(&self) -> &[u32] {
+        &self.cpus
+    }
+
+    /// Iterates over the CPUs in this set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.cpus.iter().copied()
+    }
+}
+
+impl FromIterator<u32> for CpuSet {
+    fn from_iter<I: IntoIterator<Item = u32>>(iter: I) -> Self {
+        Self::from_cpus(iter)
+    }
+}
+
+/// Formats the set as a cpulist string, e.g. `0-3,8,10-11`.
+impl fmt::Display for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut iter = self.cpus.iter().copied().peekable();
+        let mut first = true;
+        while let Some(start) = iter.next() {
+            let mut end = start;
+            while iter.peek() == Some(&(end + 1)) {
+                end = iter.next().unwrap();
+            }
+            if !first {
+                f.write_str(",")?;
+            }
+            first = false;
+            if start == end {
+                write!(f, "{start}")?;
+            } else {
+                write!(f, "{start}-{end}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CpuSet({self})")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CpuSet;
+
+    #[test]
+    fn list_str_roundtrip() {
+        let set = CpuSet::parse_list_str("0-3,8,10-11\n").unwrap();
+        assert_eq!(set.as_slice(), &[0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(set.len(), 7);
+        assert!(set.contains(10));
+        assert!(!set.contains(9));
+        assert_eq!(set.to_string(), "0-3,8,10-11");
+        assert_eq!(CpuSet::parse_list_str("").unwrap(), CpuSet::new());
+        assert_eq!(CpuSet::parse_list_str("3-1"), None);
+    }
+
+    #[test]
+    fn list_str_limits() {
+        assert_eq!(CpuSet::parse_list_str("0-1048575").unwrap().len(), 1 << 20);
+        assert_eq!(CpuSet::parse_list_str("0-4294967295"), None);
+        assert_eq!(CpuSet::parse_list_str("1048576"), None);
+        // Repeated ranges count towards the limit, too.
+        assert_eq!(CpuSet::parse_list_str("0-1048575,0"), None);
+        assert_eq!(CpuSet::parse_list_str("0-1048575,0-1"), None);
+    }
+
+    #[test]
+    fn from_mask() {
+        let set = CpuSet::from_mask(&[0b1011, 1]);
+        assert_eq!(set.as_slice(), &[0, 1, 3, 64]);
+        assert_eq!(set.bounds(), Some((0, 64)));
+    }
+}
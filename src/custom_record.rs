@@ -0,0 +1,118 @@
+use std::any::Any;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::{RawUserRecord, UserRecordType};
+
+/// Parses the body of a custom user record into a value of the caller's
+/// choosing, which can be retrieved with [`CustomUserRecord::downcast_ref`].
+pub type CustomRecordParseFn =
+    fn(&RawUserRecord<'_>) -> Result<Arc<dyn Any + Send + Sync>, std::io::Error>;
+
+/// A user record type which was declared in [`CustomRecordTypes`].
+#[derive(Debug, Clone)]
+pub struct CustomRecordType {
+    /// The name, shown in the `Debug` output of [`RawUserRecord`] and in
+    /// [`UnknownRecordType`](crate::UnknownRecordType).
+    pub name: String,
+    /// Turns records of this type into [`UserRecord::Custom`](crate::UserRecord::Custom)
+    /// in [`RawUserRecord::parse`].
+    pub parser: Option<CustomRecordParseFn>,
+}
+
+/// User record types which are defined by tools other than `perf`.
+///
+/// Simpleperf and other tools reserve ranges of user record types for their
+/// own records. Declare them here and pass them to
+/// [`PerfRecordIter::set_custom_record_types`](crate::PerfRecordIter::set_custom_record_types)
+/// so that they have a name and, optionally, a parser. Record types which
+/// this crate knows about take precedence over custom declarations.
+#[derive(Debug, Clone, Default)]
+pub struct CustomRecordTypes {
+    ranges: Vec<(RangeInclusive<u32>, CustomRecordType)>,
+}
+
+impl CustomRecordTypes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares the record types in `range`. If ranges overlap, the range
+    /// which was added first wins.
+    pub fn add_range(
+        &mut self,
+        range: RangeInclusive<u32>,
+        name: impl Into<String>,
+        parser: Option<CustomRecordParseFn>,
+    ) -> &mut Self {
+        let name = name.into();
+        self.ranges.push((range, CustomRecordType { name, parser }));
+        self
+    }
+
+    /// Declares a single record type.
+    pub fn add_type(
+        &mut self,
+        record_type: u32,
+        name: impl Into<String>,
+        parser: Option<CustomRecordParseFn>,
+    ) -> &mut Self {
+        self.add_range(record_type..=record_type, name, parser)
+    }
+
+    /// Looks up a record type. Returns `None` for types which this crate
+    /// knows about.
+    pub fn get(&self, record_type: UserRecordType) -> Option<&CustomRecordType> {
+        if record_type.is_known() {
+            return None;
+        }
+        let record_type = record_type.record_type().0;
+        self.ranges
+            .iter()
+            .find(|(range, _)| range.contains(&record_type))
+            .map(|(_, custom_type)| custom_type)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+/// A user record of a custom type which was parsed by the type's
+/// [`CustomRecordParseFn`].
+#[derive(Clone)]
+pub struct CustomUserRecord<'a> {
+    pub record_type: UserRecordType,
+    /// The name from the [`CustomRecordType`].
+    pub name: &'a str,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+impl<'a> CustomUserRecord<'a> {
+    pub(crate) fn new(
+        record_type: UserRecordType,
+        name: &'a str,
+        value: Arc<dyn Any + Send + Sync>,
+    ) -> Self {
+        Self {
+            record_type,
+            name,
+            value,
+        }
+    }
+
+    /// Returns the parsed value if it is of type `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+}
+
+impl fmt::Debug for CustomUserRecord<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomUserRecord")
+            .field("record_type", &self.record_type.record_type().0)
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,337 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, OwnedPerfFileRecord, PerfFile, PerfFileReader, PerfFileRecord, PerfRecordIter};
+
+/// A perf.data directory, as written by `perf record --kcore` or
+/// `perf record --threads`.
+///
+/// Instead of a single file, perf writes a directory with a `data` file, which
+/// has the header and the feature sections and can be read with
+/// [`PerfFileReader`](crate::PerfFileReader), and additional files next to it.
+/// With `--kcore`, the directory has a `kcore_dir` subdirectory with copies of
+/// `/proc/kallsyms`, `/proc/kcore` and `/proc/modules`, taken while recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerfDataDirectory {
+    path: PathBuf,
+}
+
+impl PerfDataDirectory {
+    /// Returns `None` if `path` isn't a perf.data directory.
+    pub fn open(path: impl Into<PathBuf>) -> Option<Self> {
+        let path = path.into();
+        if !path.join("data").is_file() {
+            return None;
+        }
+        Some(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path of the `data` file, which has the header and the feature
+    /// sections.
+    pub fn header_file_path(&self) -> PathBuf {
+        self.path.join("data")
+    }
+
+    /// The paths of the per-CPU data files (`data.<n>`) from
+    /// `perf record --threads`, ordered by CPU number.
+    pub fn per_cpu_data_file_paths(&self) -> Result<Vec<(u32, PathBuf)>, std::io::Error> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(cpu) = file_name
+                .to_str()
+                .and_then(|name| name.strip_prefix("data."))
+                .and_then(|cpu| cpu.parse().ok())
+            else {
+                continue;
+            };
+            paths.push((cpu, entry.path()));
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// The copies of the kernel's symbol and module information from
+    /// `perf record --kcore`, or `None` if the directory doesn't have them.
+    pub fn kcore_dir(&self) -> Option<KcoreDir> {
+        let path = self.path.join("kcore_dir");
+        if !path.is_dir() {
+            return None;
+        }
+        Some(KcoreDir { path })
+    }
+}
+
+/// The `kcore_dir` subdirectory of a [`PerfDataDirectory`].
+///
+/// Its files are snapshots from the time of recording, so they let you
+/// symbolicate and disassemble the exact kernel text that was running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KcoreDir {
+    path: PathBuf,
+}
+
+impl KcoreDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The copy of `/proc/kallsyms`.
+    pub fn kallsyms_path(&self) -> Option<PathBuf> {
+        self.existing_file("kallsyms")
+    }
+
+    /// The copy of `/proc/kcore`. This is an ELF file which only contains
+    /// the kernel text and the text of the modules.
+    pub fn kcore_path(&self) -> Option<PathBuf> {
+        self.existing_file("kcore")
+    }
+
+    /// The copy of `/proc/modules`, with the load addresses of the modules.
+    pub fn modules_path(&self) -> Option<PathBuf> {
+        self.existing_file("modules")
+    }
+
+    /// Reads the copy of `/proc/kallsyms`.
+    pub fn read_kallsyms(&self) -> Result<Vec<u8>, std::io::Error> {
+        std::fs::read(self.path.join("kallsyms"))
+    }
+
+    /// Reads the copy of `/proc/modules`.
+    pub fn read_modules(&self) -> Result<Vec<u8>, std::io::Error> {
+        std::fs::read(self.path.join("modules"))
+    }
+
+    fn existing_file(&self, name: &str) -> Option<PathBuf> {
+        let path = self.path.join(name);
+        path.is_file().then_some(path)
+    }
+}
+
+/// Reads the records of a perf.data directory from `perf record --threads`
+/// as a single stream, sorted by time.
+///
+/// The `data` file has the header, the attributes and the feature sections,
+/// and the records which perf synthesized at the start of the recording. The
+/// samples are in the `data.<n>` files, which only contain records. The
+/// `data` file is sorted by rounds as usual. The `data.<n>` files don't have
+/// `FINISHED_ROUND` records, but each of them is written from the mmap
+/// buffer of one CPU, whose records are in time order, so their records are
+/// read in file order. The records of all files are then merged by
+/// timestamp, so only one record per file is buffered.
+///
+/// The [`OwnedPerfFileRecord::offset`] of a record is the offset in the file
+/// that it was read from.
+pub struct PerfDirectoryReader {
+    pub perf_file: PerfFile,
+    /// The iterators for the `data` file and for each `data.<n>` file.
+    record_iters: Vec<PerfRecordIter<BufReader<File>>>,
+    /// The next record of each iterator, with its timestamp, or `None` if the
+    /// iterator is exhausted.
+    next_records: Vec<Option<(Option<u64>, OwnedPerfFileRecord)>>,
+}
+
+impl PerfDirectoryReader {
+    pub fn open(dir: &PerfDataDirectory) -> Result<Self, Error> {
+        let file = File::open(dir.header_file_path())?;
+        let PerfFileReader {
+            mut perf_file,
+            record_iter,
+        } = PerfFileReader::parse_file(BufReader::new(file))?;
+        let mut record_iters = Vec::new();
+        for (_, path) in dir.per_cpu_data_file_paths()? {
+            let file = File::open(path)?;
+            let len = file.metadata()?.len();
+            let mut data_file_iter = record_iter.data_file_iter(BufReader::new(file), len);
+            data_file_iter.set_trust_file_order(true);
+            record_iters.push(data_file_iter);
+        }
+        record_iters.insert(0, record_iter);
+
+        let mut next_records = Vec::with_capacity(record_iters.len());
+        for record_iter in &mut record_iters {
+            next_records.push(read_next(record_iter, &mut perf_file)?);
+        }
+        Ok(Self {
+            perf_file,
+            record_iters,
+            next_records,
+        })
+    }
+
+    /// Returns the next record from any of the data files, sorted by time.
+    /// Records without a timestamp are returned as soon as they are read.
+    pub fn next_record(&mut self) -> Result<Option<OwnedPerfFileRecord>, Error> {
+        let next = self
+            .next_records
+            .iter()
+            .enumerate()
+            .filter_map(|(index, next)| Some((next.as_ref()?.0, index)))
+            .min();
+        let Some((_, index)) = next else {
+            return Ok(None);
+        };
+        let next = read_next(&mut self.record_iters[index], &mut self.perf_file)?;
+        let (_, record) = std::mem::replace(&mut self.next_records[index], next)
+            .expect("the chosen iterator has a record");
+        Ok(Some(record))
+    }
+}
+
+fn read_next(
+    record_iter: &mut PerfRecordIter<BufReader<File>>,
+    perf_file: &mut PerfFile,
+) -> Result<Option<(Option<u64>, OwnedPerfFileRecord)>, Error> {
+    let Some(record) = record_iter.next_owned_record(perf_file)? else {
+        return Ok(None);
+    };
+    let timestamp = match record.as_record() {
+        PerfFileRecord::EventRecord { record, .. } => record.timestamp(),
+        PerfFileRecord::UserRecord(_) => None,
+    };
+    Ok(Some((timestamp, record)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use linux_perf_event_reader::{Endianness, RecordType, SoftwareCounterType};
+
+    use super::{PerfDataDirectory, PerfDirectoryReader};
+    use crate::file_writer::test::{sample_body, test_attr};
+    use crate::{Feature, PerfFileRecord, PerfFileWriter};
+
+    #[test]
+    fn kcore_dir() {
+        let dir = std::env::temp_dir().join(format!("perf-data-dir-test-{}", std::process::id()));
+        let kcore_dir = dir.join("kcore_dir");
+        std::fs::create_dir_all(&kcore_dir).unwrap();
+        std::fs::write(dir.join("data"), b"").unwrap();
+        std::fs::write(dir.join("data.1"), b"").unwrap();
+        std::fs::write(dir.join("data.0"), b"").unwrap();
+        std::fs::write(kcore_dir.join("kallsyms"), b"ffffffff81000000 T _text\n").unwrap();
+
+        let perf_dir = PerfDataDirectory::open(&dir).unwrap();
+        let cpus: Vec<u32> = perf_dir
+            .per_cpu_data_file_paths()
+            .unwrap()
+            .into_iter()
+            .map(|(cpu, _)| cpu)
+            .collect();
+        assert_eq!(cpus, vec![0, 1]);
+        let kcore = perf_dir.kcore_dir().unwrap();
+        assert_eq!(
+            kcore.read_kallsyms().unwrap(),
+            b"ffffffff81000000 T _text\n"
+        );
+        assert_eq!(kcore.kcore_path(), None);
+        assert!(PerfDataDirectory::open(&kcore_dir).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn directory_reader() {
+        let dir =
+            std::env::temp_dir().join(format!("perf-data-dir-reader-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer.add_feature(Feature::DIR_FORMAT, 1u64.to_le_bytes().to_vec());
+        for time in [1, 4] {
+            writer
+                .write_record(RecordType::SAMPLE, 2, &sample_body(1, 10, time))
+                .unwrap();
+        }
+        std::fs::write(dir.join("data"), writer.finish().unwrap().into_inner()).unwrap();
+        for (cpu, times) in [(0, &[2, 5][..]), (1, &[3][..])] {
+            let mut data = Vec::new();
+            for time in times {
+                let body = sample_body(1, 20 + cpu, *time);
+                data.write_u32::<LittleEndian>(RecordType::SAMPLE.0)
+                    .unwrap();
+                data.write_u16::<LittleEndian>(2).unwrap();
+                data.write_u16::<LittleEndian>(8 + body.len() as u16)
+                    .unwrap();
+                data.extend_from_slice(&body);
+            }
+            std::fs::write(dir.join(format!("data.{cpu}")), data).unwrap();
+        }
+
+        let perf_dir = PerfDataDirectory::open(&dir).unwrap();
+        let mut reader = PerfDirectoryReader::open(&perf_dir).unwrap();
+        assert_eq!(reader.perf_file.dir_format_version().unwrap(), Some(1));
+        let mut samples = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            let PerfFileRecord::EventRecord { record, .. } = record.as_record() else {
+                panic!("unexpected user record");
+            };
+            let common = record.common_data().unwrap();
+            samples.push((common.pid.unwrap(), common.timestamp.unwrap()));
+        }
+        assert_eq!(samples, vec![(10, 1), (20, 2), (21, 3), (10, 4), (20, 5)]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn large_data_files_are_streamed() {
+        let dir =
+            std::env::temp_dir().join(format!("perf-data-dir-stream-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        std::fs::write(dir.join("data"), writer.finish().unwrap().into_inner()).unwrap();
+        // Far more records than one read of the BufReader.
+        const SAMPLE_COUNT: u64 = 10_000;
+        for cpu in 0..2 {
+            let mut data = Vec::new();
+            for i in 0..SAMPLE_COUNT {
+                let body = sample_body(1, 20 + cpu, i * 2 + u64::from(cpu));
+                data.write_u32::<LittleEndian>(RecordType::SAMPLE.0)
+                    .unwrap();
+                data.write_u16::<LittleEndian>(2).unwrap();
+                data.write_u16::<LittleEndian>(8 + body.len() as u16)
+                    .unwrap();
+                data.extend_from_slice(&body);
+            }
+            std::fs::write(dir.join(format!("data.{cpu}")), data).unwrap();
+        }
+
+        let perf_dir = PerfDataDirectory::open(&dir).unwrap();
+        let mut reader = PerfDirectoryReader::open(&perf_dir).unwrap();
+        let mut timestamps = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            if timestamps.is_empty() {
+                // The first record is returned without reading the files to
+                // the end.
+                for record_iter in &reader.record_iters[1..] {
+                    assert!(record_iter.read_offset() < SAMPLE_COUNT * 32);
+                }
+            }
+            let PerfFileRecord::EventRecord { record, .. } = record.as_record() else {
+                panic!("unexpected user record");
+            };
+            timestamps.push(record.timestamp().unwrap());
+        }
+        assert_eq!(timestamps, (0..SAMPLE_COUNT * 2).collect::<Vec<_>>());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use byteorder::ByteOrder;
+use linux_perf_event_reader::PerfEventHeader;
+use zstd::stream::raw::{Decoder, InBuffer, Operation, OutBuffer};
+
+use crate::record_body::RecordBody;
+use crate::Error;
+
+/// Decompresses the contents of `PERF_RECORD_COMPRESSED` and
+/// `PERF_RECORD_COMPRESSED2` records and splits the decompressed data into
+/// records.
+///
+/// perf compresses the data from each mmap buffer flush as one zstd stream,
+/// so a record can straddle two compressed records. The decompressor state
+/// is kept across compressed records for this reason.
+///
+/// Records are handed out as references into the shared decompressed chunk;
+/// only records which straddle a chunk boundary are copied.
+pub struct Decompressor {
+    decoder: Decoder<'static>,
+    /// The maximum decompressed size of one compressed record.
+    max_chunk_size: usize,
+    /// The decompressed data of the most recent compressed record.
+    chunk: Arc<Vec<u8>>,
+    /// The position of the next record in `chunk`.
+    pos: usize,
+    /// The start of a record which continues in the next chunk, including
+    /// the record header.
+    partial_record: Vec<u8>,
+}
+
+impl Decompressor {
+    /// Creates a decompressor which fails if a compressed record
+    /// decompresses to more than `max_chunk_size` bytes.
+    pub fn new(max_chunk_size: usize) -> Result<Self, Error> {
+        Ok(Self {
+            decoder: Decoder::new().map_err(Error::Decompression)?,
+            max_chunk_size,
+            chunk: Arc::new(Vec::new()),
+            pos: 0,
+            partial_record: Vec::new(),
+        })
+    }
+
+    /// Decompresses `compressed` into a new chunk. Must only be called once
+    /// all records of the previous chunk have been consumed, i.e. once
+    /// `next_record` has returned `None`.
+    ///
+    /// Returns an error if the data decompresses to more than the maximum
+    /// chunk size.
+    pub fn decompress(&mut self, compressed: &[u8]) -> Result<(), Error> {
+        let initial_capacity = (compressed.len() * 4).max(64 * 1024);
+        let mut output = Vec::with_capacity(initial_capacity.min(self.max_chunk_size + 1));
+        let mut input = InBuffer::around(compressed);
+        loop {
+            if output.len() > self.max_chunk_size {
+                return Err(Error::Decompression(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "a compressed record decompresses to more than {} bytes",
+                        self.max_chunk_size
+                    ),
+                )));
+            }
+            if output.len() == output.capacity() {
+                // Grow up to one byte past the limit, so that exceeding it
+                // is detected.
+                let limit = self.max_chunk_size + 1 - output.len();
+                output.reserve(output.capacity().min(limit));
+            }
+            let pos = output.len();
+            let mut out_buffer = OutBuffer::around_pos(&mut output, pos);
+            self.decoder
+                .run(&mut input, &mut out_buffer)
+                .map_err(Error::Decompression)?;
+            // If the output buffer wasn't filled up, the decoder has flushed
+            // everything it could produce from the input.
+            if input.pos() == compressed.len() && output.len() < output.capacity() {
+                break;
+            }
+        }
+        self.chunk = Arc::new(output);
+        self.pos = 0;
+        Ok(())
+    }
+
+    /// Whether all decompressed data has been consumed, i.e. whether a new
+    /// decompressor could take over without losing data.
+    pub fn is_idle(&self) -> bool {
+        self.pos == self.chunk.len() && self.partial_record.is_empty()
+    }
+
+    /// Returns the next complete record from the current chunk. Returns `None`
+    /// once the chunk has been consumed; an incomplete record at the end of
+    /// the chunk is kept until the next call to `decompress`.
+    pub fn next_record<T: ByteOrder>(
+        &mut self,
+    ) -> Result<Option<(PerfEventHeader, RecordBody)>, Error> {
+        let remaining = &self.chunk[self.pos..];
+        if !self.partial_record.is_empty() {
+            // Complete the header of the straddling record first.
+            let header_missing =
+                PerfEventHeader::STRUCT_SIZE.saturating_sub(self.partial_record.len());
+            if remaining.len() < header_missing {
+                self.partial_record.extend_from_slice(remaining);
+                self.pos = self.chunk.len();
+                return Ok(None);
+            }
+            self.partial_record
+                .extend_from_slice(&remaining[..header_missing]);
+            self.pos += header_missing;
+            let header = PerfEventHeader::parse::<_, T>(&self.partial_record[..])?;
+            let size = usize::from(header.size);
+            if size < PerfEventHeader::STRUCT_SIZE {
+                return Err(Error::InvalidPerfEventSize);
+            }
+            let remaining = &self.chunk[self.pos..];
+            let body_missing = size - self.partial_record.len();
+            if remaining.len() < body_missing {
+                self.partial_record.extend_from_slice(remaining);
+                self.pos = self.chunk.len();
+                return Ok(None);
+            }
+            self.partial_record
+                .extend_from_slice(&remaining[..body_missing]);
+            self.pos += body_missing;
+            let mut body = std::mem::take(&mut self.partial_record);
+            body.drain(..PerfEventHeader::STRUCT_SIZE);
+            return Ok(Some((header, RecordBody::Owned(body))));
+        }
+
+        if remaining.len() < PerfEventHeader::STRUCT_SIZE {
+            self.partial_record.extend_from_slice(remaining);
+            self.pos = self.chunk.len();
+            return Ok(None);
+        }
+        let header = PerfEventHeader::parse::<_, T>(remaining)?;
+        let size = usize::from(header.size);
+        if size < PerfEventHeader::STRUCT_SIZE {
+            return Err(Error::InvalidPerfEventSize);
+        }
+        if remaining.len() < size {
+            self.partial_record.extend_from_slice(remaining);
+            self.pos = self.chunk.len();
+            return Ok(None);
+        }
+        let body_range = self.pos + PerfEventHeader::STRUCT_SIZE..self.pos + size;
+        self.pos += size;
+        Ok(Some((
+            header,
+            RecordBody::Shared(self.chunk.clone(), body_range),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Decompressor;
+
+    #[test]
+    fn chunk_size_limit() {
+        let compressed = zstd::encode_all(&[0u8; 4096][..], 3).unwrap();
+        let mut decompressor = Decompressor::new(4096).unwrap();
+        decompressor.decompress(&compressed).unwrap();
+        assert_eq!(decompressor.chunk.len(), 4096);
+
+        let mut decompressor = Decompressor::new(4095).unwrap();
+        assert!(decompressor.decompress(&compressed).is_err());
+    }
+}
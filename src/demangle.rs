@@ -0,0 +1,118 @@
+use std::borrow::Cow;
+
+/// Demangles a function name from a jitdump file or a symbol table.
+///
+/// Rust (legacy and v0) and C++ (Itanium ABI) symbols are demangled with
+/// `rustc-demangle` and `cpp_demangle`. JVM names which use type descriptors,
+/// e.g. `Ljava/lang/String;hashCode()I`, are turned into
+/// `java.lang.String.hashCode()`. Other names are returned unchanged.
+pub fn demangle(name: &str) -> Cow<'_, str> {
+    if let Ok(demangled) = rustc_demangle::try_demangle(name) {
+        // The alternate format omits the hash suffix.
+        return Cow::Owned(format!("{demangled:#}"));
+    }
+    if name.starts_with("_Z") {
+        if let Ok(symbol) = cpp_demangle::Symbol::new(name) {
+            let options = cpp_demangle::DemangleOptions::new();
+            if let Ok(demangled) = symbol.demangle(&options) {
+                return Cow::Owned(demangled);
+            }
+        }
+    }
+    if let Some(prettified) = prettify_jvm_name(name) {
+        return Cow::Owned(prettified);
+    }
+    Cow::Borrowed(name)
+}
+
+/// Turns `Lpkg/Class;method(args)ret`, optionally with a `.` or `::` between
+/// the class and the method name, into `pkg.Class.method(args)`.
+fn prettify_jvm_name(name: &str) -> Option<String> {
+    let rest = name.strip_prefix('L')?;
+    let (class, rest) = rest.split_once(';')?;
+    if class.is_empty() || class.contains(|c: char| c.is_whitespace() || c == '(') {
+        return None;
+    }
+    let rest = rest
+        .strip_prefix("::")
+        .or_else(|| rest.strip_prefix('.'))
+        .unwrap_or(rest);
+    let (method, descriptor) = match rest.split_once('(') {
+        Some((method, descriptor)) => (method, Some(descriptor)),
+        None => (rest, None),
+    };
+
+    let mut pretty = class.replace('/', ".");
+    if !method.is_empty() {
+        pretty.push('.');
+        pretty.push_str(method);
+    }
+    if let Some(descriptor) = descriptor {
+        let (args, _return_type) = descriptor.split_once(')')?;
+        let args = parse_jvm_type_list(args)?;
+        pretty.push('(');
+        pretty.push_str(&args.join(", "));
+        pretty.push(')');
+    }
+    Some(pretty)
+}
+
+/// Parses a sequence of JVM field descriptors, e.g. `I[JLjava/lang/String;`.
+fn parse_jvm_type_list(mut descriptors: &str) -> Option<Vec<String>> {
+    let mut types = Vec::new();
+    while !descriptors.is_empty() {
+        let mut array_depth = 0;
+        while let Some(rest) = descriptors.strip_prefix('[') {
+            array_depth += 1;
+            descriptors = rest;
+        }
+        let mut chars = descriptors.chars();
+        let mut ty = match chars.next()? {
+            'B' => "byte".to_string(),
+            'C' => "char".to_string(),
+            'D' => "double".to_string(),
+            'F' => "float".to_string(),
+            'I' => "int".to_string(),
+            'J' => "long".to_string(),
+            'S' => "short".to_string(),
+            'Z' => "boolean".to_string(),
+            'V' => "void".to_string(),
+            'L' => {
+                let (class, rest) = chars.as_str().split_once(';')?;
+                chars = rest.chars();
+                class.replace('/', ".")
+            }
+            _ => return None,
+        };
+        for _ in 0..array_depth {
+            ty.push_str("[]");
+        }
+        types.push(ty);
+        descriptors = chars.as_str();
+    }
+    Some(types)
+}
+
+#[cfg(test)]
+mod test {
+    use super::demangle;
+
+    #[test]
+    fn demangle_names() {
+        assert_eq!(demangle("_ZN3foo3bar17h05af221e174051e9E"), "foo::bar");
+        assert_eq!(demangle("_ZN3foo3barEv"), "foo::bar()");
+        assert_eq!(
+            demangle("Ljava/util/HashMap;get(Ljava/lang/Object;)Ljava/lang/Object;"),
+            "java.util.HashMap.get(java.lang.Object)"
+        );
+        assert_eq!(
+            demangle("Lcom/example/Foo;::bar([IJ)V"),
+            "com.example.Foo.bar(int[], long)"
+        );
+        assert_eq!(
+            demangle("LazyCompile:~main app.js:1"),
+            "LazyCompile:~main app.js:1"
+        );
+        assert_eq!(demangle("main"), "main");
+    }
+}
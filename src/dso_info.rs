@@ -7,3 +7,11 @@ pub struct DsoInfo {
     /// The build ID.
     pub build_id: Vec<u8>,
 }
+
+impl DsoInfo {
+    /// The file path as a [`BStr`](bstr::BStr).
+    #[cfg(feature = "bstr")]
+    pub fn path_bstr(&self) -> &bstr::BStr {
+        bstr::BStr::new(&self.path)
+    }
+}
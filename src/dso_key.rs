@@ -9,7 +9,8 @@ use linux_perf_event_reader::CpuMode;
 ///
 ///  - Mmap path "[kernel.kallsyms]_text" + build ID map entry path "[kernel.kallsyms]"
 ///  - Mmap path "[kernel.kallsyms]_text" + build ID map entry path "/full/path/to/vmlinux"
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[non_exhaustive]
 pub enum DsoKey {
     Kernel,
     GuestKernel,
@@ -31,6 +32,45 @@ pub enum DsoKey {
         /// there could be multiple DSOs with the same file name at different paths.
         full_path: Vec<u8>,
     },
+    /// A named anonymous mapping, e.g. "[anon:dalvik-jit-code-cache]". Android
+    /// names its anonymous mappings with `prctl(PR_SET_VMA_ANON_NAME)`, and these
+    /// names show up in the mapping paths.
+    ///
+    /// Only produced if [`DsoKeyDetectionOptions::anon_named_mappings`] is set.
+    AnonNamed {
+        /// The full mapping name, including the surrounding "[anon:" and "]".
+        name: String,
+    },
+    /// An executable anonymous mapping (`//anon`), which usually contains JIT code.
+    ///
+    /// Only produced if [`DsoKeyDetectionOptions::anon_exec_as_jit`] is set.
+    JitCandidate,
+    /// A path which was registered in [`DsoKeyDetectionOptions::special_paths`].
+    Special {
+        /// The name which was registered for this path.
+        name: String,
+    },
+}
+
+/// Controls the heuristics used by [`DsoKey::detect_with_options`].
+///
+/// The default options give the same results as [`DsoKey::detect`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DsoKeyDetectionOptions {
+    /// If true, the path "[vdso]" is mapped to [`DsoKey::Vdso32`] instead of
+    /// [`DsoKey::Vdso64`]. Set this when the profile was recorded on a 32 bit machine.
+    pub vdso_is_32_bit: bool,
+    /// If true, executable `//anon` mappings are mapped to [`DsoKey::JitCandidate`]
+    /// instead of being discarded.
+    pub anon_exec_as_jit: bool,
+    /// If true, "[anon:...]" paths are mapped to [`DsoKey::AnonNamed`] instead
+    /// of being treated like other paths.
+    pub anon_named_mappings: bool,
+    /// Exact paths which should be mapped to [`DsoKey::Special`], with the given name.
+    ///
+    /// These are checked before any of the other rules, so they can also be used
+    /// to override the built-in classification.
+    pub special_paths: Vec<(Vec<u8>, String)>,
 }
 
 impl DsoKey {
@@ -38,7 +78,31 @@ impl DsoKey {
     ///
     /// Returns `None` for things which cannot be detected as a DSO, such as `//anon` mappings.
     pub fn detect(path: &[u8], cpu_mode: CpuMode) -> Option<Self> {
-        if path == b"//anon" || path == b"[stack]" || path == b"[heap]" || path == b"[vvar]" {
+        Self::detect_with_options(path, cpu_mode, true, &DsoKeyDetectionOptions::default())
+    }
+
+    /// Make a `DsoKey` from a path and a `CpuMode`, using custom heuristics.
+    ///
+    /// `is_executable` is only used for anonymous mappings, see
+    /// [`DsoKeyDetectionOptions::anon_exec_as_jit`]. Pass `true` if the information
+    /// is not available, for example for entries in the build ID list.
+    pub fn detect_with_options(
+        path: &[u8],
+        cpu_mode: CpuMode,
+        is_executable: bool,
+        options: &DsoKeyDetectionOptions,
+    ) -> Option<Self> {
+        if let Some((_, name)) = options.special_paths.iter().find(|(p, _)| p == path) {
+            return Some(DsoKey::Special { name: name.clone() });
+        }
+
+        if path == b"//anon" {
+            if options.anon_exec_as_jit && is_executable {
+                return Some(DsoKey::JitCandidate);
+            }
+            return None;
+        }
+        if path == b"[stack]" || path == b"[heap]" || path == b"[vvar]" {
             return None;
         }
 
@@ -60,12 +124,19 @@ impl DsoKey {
             return Some(DsoKey::VdsoX32);
         }
         if path == b"[vdso]" {
-            // TODO: I think this could also be Vdso32 when recording on a 32 bit machine.
+            if options.vdso_is_32_bit {
+                return Some(DsoKey::Vdso32);
+            }
             return Some(DsoKey::Vdso64);
         }
         if path == b"[vsyscall]" {
             return Some(DsoKey::Vsyscall);
         }
+        if options.anon_named_mappings && path.starts_with(b"[anon:") && path.ends_with(b"]") {
+            return Some(DsoKey::AnonNamed {
+                name: String::from_utf8_lossy(path).into(),
+            });
+        }
         if (cpu_mode == CpuMode::Kernel || cpu_mode == CpuMode::GuestKernel)
             && path.starts_with(b"[")
         {
@@ -111,6 +182,67 @@ impl DsoKey {
             DsoKey::Vsyscall => "[vsyscall]",
             DsoKey::KernelModule { name } => name,
             DsoKey::User { file_name, .. } => file_name,
+            DsoKey::AnonNamed { name } => name,
+            DsoKey::JitCandidate => "//anon",
+            DsoKey::Special { name } => name,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use linux_perf_event_reader::CpuMode;
+
+    use super::{DsoKey, DsoKeyDetectionOptions};
+
+    #[test]
+    fn default_detection() {
+        assert_eq!(
+            DsoKey::detect(b"[vdso]", CpuMode::User),
+            Some(DsoKey::Vdso64)
+        );
+        assert_eq!(DsoKey::detect(b"//anon", CpuMode::User), None);
+        assert_eq!(
+            DsoKey::detect(b"[anon:dalvik-jit-code-cache]", CpuMode::User),
+            Some(DsoKey::User {
+                file_name: "[anon:dalvik-jit-code-cache]".into(),
+                full_path: b"[anon:dalvik-jit-code-cache]".to_vec(),
+            })
+        );
+        assert_eq!(
+            DsoKey::detect(b"/usr/lib/libc.so.6", CpuMode::User),
+            Some(DsoKey::User {
+                file_name: "libc.so.6".into(),
+                full_path: b"/usr/lib/libc.so.6".to_vec(),
+            })
+        );
+    }
+
+    #[test]
+    fn custom_detection() {
+        let options = DsoKeyDetectionOptions {
+            vdso_is_32_bit: true,
+            anon_exec_as_jit: true,
+            anon_named_mappings: true,
+            special_paths: vec![(b"/tmp/perf-123.map".to_vec(), "perf map".into())],
+        };
+        let detect = |path: &[u8], is_executable| {
+            DsoKey::detect_with_options(path, CpuMode::User, is_executable, &options)
+        };
+        assert_eq!(detect(b"[vdso]", true), Some(DsoKey::Vdso32));
+        assert_eq!(detect(b"//anon", true), Some(DsoKey::JitCandidate));
+        assert_eq!(detect(b"//anon", false), None);
+        assert_eq!(
+            detect(b"[anon:dalvik-jit-code-cache]", true),
+            Some(DsoKey::AnonNamed {
+                name: "[anon:dalvik-jit-code-cache]".into()
+            })
+        );
+        assert_eq!(
+            detect(b"/tmp/perf-123.map", true),
+            Some(DsoKey::Special {
+                name: "perf map".into()
+            })
+        );
+    }
+}
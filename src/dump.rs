@@ -0,0 +1,182 @@
+use std::io::{Read, Write};
+
+use linux_perf_event_reader::{Endianness, RawData};
+
+use crate::{Error, PerfFile, PerfFileReader, PerfFileRecord};
+
+/// The version of the format written by [`write_dump`]. It is increased
+/// whenever the output for the same input file changes.
+///
+///  - 2: Records which simpleperf split into `SIMPLEPERF_SPLIT` records are
+///    dumped as one record instead of as the individual parts.
+///  - 3: The event IDs from `SIMPLEPERF_EVENT_ID` records are used to find
+///    the attribute of event records.
+///  - 4: Unnamed tracepoint attributes are named after their tracepoint
+///    from the `TRACING_DATA` section, e.g. `name="sched:sched_switch"`.
+///  - 5: The bodies of `AUXTRACE` records include the aux data which follows
+///    the record in the file.
+pub const DUMP_FORMAT_VERSION: u32 = 5;
+
+/// Writes a text dump of a perf.data file, for golden-file tests of
+/// recordings in CI, similar to `perf report -D`.
+///
+/// The output only depends on the contents of the file and on
+/// [`DUMP_FORMAT_VERSION`], which is printed in the first line. It doesn't use
+/// the `Debug` output of any types, so it stays the same across versions of
+/// this crate unless the version number changes. The dump contains:
+///
+///  - the header fields,
+///  - the event attributes,
+///  - each feature section, as a hex dump,
+///  - each record in sorted order, with its offset, type, misc field and the
+///    common fields of event records, followed by a hex dump of its body.
+pub fn write_dump<R: Read, W: Write>(reader: PerfFileReader<R>, mut out: W) -> Result<(), Error> {
+    let PerfFileReader {
+        mut perf_file,
+        mut record_iter,
+    } = reader;
+
+    writeln!(out, "# linux-perf-data dump v{DUMP_FORMAT_VERSION}")?;
+    write_metadata(&perf_file, &mut out)?;
+
+    while let Some(record) = record_iter.next_record(&mut perf_file)? {
+        let offset = record.offset();
+        let misc = record.misc();
+        match record {
+            PerfFileRecord::EventRecord {
+                attr_index, record, ..
+            } => {
+                write!(
+                    out,
+                    "record @{offset:#x} type={} misc={:#06x} attr={attr_index}",
+                    record.record_type.0, misc.raw
+                )?;
+                if let Ok(common) = record.common_data() {
+                    write_optional(&mut out, "time", common.timestamp)?;
+                    write_optional(&mut out, "pid", common.pid)?;
+                    write_optional(&mut out, "tid", common.tid)?;
+                    write_optional(&mut out, "cpu", common.cpu)?;
+                    write_optional(&mut out, "id", common.id)?;
+                }
+                writeln!(out)?;
+                write_hex_dump(&mut out, record.data)?;
+            }
+            PerfFileRecord::UserRecord(record) => {
+                writeln!(
+                    out,
+                    "record @{offset:#x} type={} misc={:#06x}",
+                    record.record_type.record_type().0,
+                    misc.raw
+                )?;
+                write_hex_dump(&mut out, record.data)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_metadata<W: Write>(perf_file: &PerfFile, out: &mut W) -> Result<(), Error> {
+    let header = perf_file.header();
+    let endian = match perf_file.endian() {
+        Endianness::LittleEndian => "little",
+        Endianness::BigEndian => "big",
+    };
+    writeln!(
+        out,
+        "header endian={endian} attr_size={} attrs={:#x}+{:#x} data={:#x}+{:#x} event_types={:#x}+{:#x}",
+        header.attr_size,
+        header.attr_section.offset,
+        header.attr_section.size,
+        header.data_section.offset,
+        header.data_section.size,
+        header.event_types_section.offset,
+        header.event_types_section.size,
+    )?;
+
+    for (attr_index, attr) in perf_file.event_attributes().iter().enumerate() {
+        let ids: Vec<String> = attr.ids().iter().map(u64::to_string).collect();
+        writeln!(
+            out,
+            "attr {attr_index} name={:?} sample_format={:#x} read_format={:#x} flags={:#x} ids=[{}]",
+            attr.name().unwrap_or(""),
+            attr.attr.sample_format.bits(),
+            attr.attr.read_format.bits(),
+            attr.attr.flags.bits(),
+            ids.join(","),
+        )?;
+    }
+
+    for feature in perf_file.features() {
+        match perf_file.feature_section_data(feature) {
+            Some(data) => {
+                writeln!(out, "feature {} size={}", feature.0, data.len())?;
+                write_hex_dump(out, RawData::Single(data))?;
+            }
+            None => writeln!(out, "feature {} not loaded", feature.0)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_optional<W: Write, T: std::fmt::Display>(
+    out: &mut W,
+    name: &str,
+    value: Option<T>,
+) -> std::io::Result<()> {
+    match value {
+        Some(value) => write!(out, " {name}={value}"),
+        None => Ok(()),
+    }
+}
+
+/// Writes 16 bytes per line, prefixed with the offset.
+fn write_hex_dump<W: Write>(out: &mut W, data: RawData) -> std::io::Result<()> {
+    let data = data.as_slice();
+    for (line_index, line) in data.chunks(16).enumerate() {
+        write!(out, "  {:06x}:", line_index * 16)?;
+        for byte in line {
+            write!(out, " {byte:02x}")?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::write_dump;
+    use crate::PerfFileReader;
+
+    #[test]
+    fn dump_is_stable() {
+        let mut file = Vec::new();
+        file.extend_from_slice(b"PERFILE2");
+        for value in [104u64, 64, 104, 64, 168, 24, 0, 0, 0, 0, 0, 0] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        // perf_event_attr (PERF_ATTR_SIZE_VER0), with sample_type = PERF_SAMPLE_TIME.
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&64u32.to_le_bytes());
+        for value in [0u64, 1000, 1 << 2, 0, 0, 0, 0] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        // A sample with time 42, and a FINISHED_ROUND record.
+        file.extend_from_slice(&[9, 0, 0, 0, 2, 0, 16, 0]);
+        file.extend_from_slice(&42u64.to_le_bytes());
+        file.extend_from_slice(&[68, 0, 0, 0, 0, 0, 8, 0]);
+
+        let reader = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let mut out = Vec::new();
+        write_dump(reader, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "# linux-perf-data dump v5\n\
+             header endian=little attr_size=64 attrs=0x68+0x40 data=0xa8+0x18 event_types=0x0+0x0\n\
+             attr 0 name=\"\" sample_format=0x4 read_format=0x0 flags=0x0 ids=[]\n\
+             record @0x0 type=9 misc=0x0002 attr=0 time=42\n  \
+             000000: 2a 00 00 00 00 00 00 00\n"
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use std::io;
 
+use crate::tracepoint::TracepointError;
+
 /// The error type used in this crate.
 #[derive(thiserror::Error, Debug)]
 #[non_exhaustive]
@@ -16,7 +18,10 @@ pub enum Error {
     #[error("Did not recognize magic value {0:?}")]
     UnrecognizedMagicValue([u8; 8]),
 
-    #[error("Section size did not fit into usize")]
+    #[error("The sidecar index has the unsupported version {0}")]
+    UnsupportedSidecarIndexVersion(u32),
+
+    #[error("Section size or offset did not fit into usize")]
     SectionSizeTooBig,
 
     #[error("The file declares no perf event attributes, so samples cannot be parsed")]
@@ -40,6 +45,9 @@ pub enum Error {
     #[error("The feature section wasn't big enough")]
     FeatureSectionTooSmall,
 
+    #[error("The {0:?} feature section was too large to be read into memory")]
+    FeatureSectionNotBuffered(crate::Feature),
+
     #[error("No event types found in the simpleperf meta info section")]
     NoEventTypesInSimpleperfMetaInfo,
 
@@ -49,6 +57,9 @@ pub enum Error {
     #[error("Parsing error in Simpleperf file v1 feature: {0}")]
     ParsingSimpleperfFileV1Section(io::Error),
 
+    #[error("Protobuf parsing error in Simpleperf debug unwind feature: {0}")]
+    ProtobufParsingSimpleperfDebugUnwindSection(prost::DecodeError),
+
     #[error("The indicated string length wouldn't fit in the indicated section size")]
     StringLengthTooLong,
 
@@ -63,6 +74,24 @@ pub enum Error {
 
     #[error("The specified size in the perf event header was smaller than the header itself")]
     InvalidPerfEventSize,
+
+    #[error("The record has the event ID {0}, which doesn't belong to any attribute")]
+    UnknownEventId(u64),
+
+    #[error("Could not decompress the data in a COMPRESSED record: {0}")]
+    Decompression(io::Error),
+
+    #[error("The data section contains COMPRESSED records, which can only be read with the zstd feature")]
+    CompressedDataNotSupported,
+
+    #[error("A thread which was building the index of the data section panicked")]
+    IndexThreadPanicked,
+
+    #[error("The string table has reached its maximum number of strings")]
+    TooManyStrings,
+
+    #[error("Could not parse the tracing data: {0}")]
+    TracingData(#[from] TracepointError),
 }
 
 impl From<std::str::Utf8Error> for Error {
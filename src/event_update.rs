@@ -0,0 +1,89 @@
+use byteorder::ByteOrder;
+use linux_perf_event_reader::RawData;
+
+use crate::cpu_map::CpuMap;
+
+const PERF_EVENT_UPDATE_UNIT: u64 = 0;
+const PERF_EVENT_UPDATE_SCALE: u64 = 1;
+const PERF_EVENT_UPDATE_NAME: u64 = 2;
+const PERF_EVENT_UPDATE_CPUS: u64 = 3;
+
+/// A `PERF_RECORD_EVENT_UPDATE` record. `perf` emits these to attach extra
+/// information to an event, for example the unit and scale of a RAPL energy
+/// counter from `/sys/bus/event_source/devices/<pmu>/events/<event>.unit` and
+/// `.scale`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventUpdate {
+    /// The event ID which this update applies to. Use it to look up the attr
+    /// in the `event_ids` of [`AttributeDescription`](crate::AttributeDescription).
+    pub id: u64,
+    pub update: EventUpdateType,
+}
+
+/// The information in an [`EventUpdate`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum EventUpdateType {
+    /// The unit of the counter values, e.g. "Joules".
+    Unit(String),
+    /// The factor to multiply counter values by to get values in the unit.
+    Scale(f64),
+    /// The event name.
+    Name(String),
+    /// The CPUs which the event is monitored on.
+    Cpus(CpuMap),
+    /// An update type which this crate doesn't know about.
+    Unknown(u64),
+}
+
+impl EventUpdate {
+    pub fn parse<T: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        // struct perf_record_event_update {
+        //     struct perf_event_header header;
+        //     __u64 type;
+        //     __u64 id;
+        //     union { ... };
+        // };
+        let type_ = data.read_u64::<T>()?;
+        let id = data.read_u64::<T>()?;
+        let update = match type_ {
+            PERF_EVENT_UPDATE_UNIT => EventUpdateType::Unit(read_padded_string(data)),
+            PERF_EVENT_UPDATE_SCALE => {
+                EventUpdateType::Scale(f64::from_bits(data.read_u64::<T>()?))
+            }
+            PERF_EVENT_UPDATE_NAME => EventUpdateType::Name(read_padded_string(data)),
+            PERF_EVENT_UPDATE_CPUS => EventUpdateType::Cpus(CpuMap::parse::<T>(data)?),
+            other => EventUpdateType::Unknown(other),
+        };
+        Ok(Self { id, update })
+    }
+}
+
+/// Reads a nul-terminated string which is padded to a multiple of 8 bytes.
+fn read_padded_string(data: RawData) -> String {
+    let bytes = data.as_slice();
+    let len = memchr::memchr(0, &bytes).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::LittleEndian;
+    use linux_perf_event_reader::RawData;
+
+    use super::{EventUpdate, EventUpdateType};
+
+    #[test]
+    fn parse_unit_and_scale() {
+        let mut unit = vec![0, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0];
+        unit.extend_from_slice(b"Joules\0\0");
+        let update = EventUpdate::parse::<LittleEndian>(RawData::from(&unit[..])).unwrap();
+        assert_eq!(update.id, 7);
+        assert_eq!(update.update, EventUpdateType::Unit("Joules".to_string()));
+
+        let mut scale = vec![1, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0];
+        scale.extend_from_slice(&2.5f64.to_bits().to_le_bytes());
+        let update = EventUpdate::parse::<LittleEndian>(RawData::from(&scale[..])).unwrap();
+        assert_eq!(update.update, EventUpdateType::Scale(2.5));
+    }
+}
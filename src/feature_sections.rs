@@ -2,10 +2,12 @@ use std::io::{Read, Seek, SeekFrom};
 
 use byteorder::{ByteOrder, ReadBytesExt};
 use linear_map::LinearMap;
-use linux_perf_event_reader::PerfEventAttr;
+use linux_perf_event_reader::{PerfEventAttr, PerfEventType};
 
 use super::section::PerfFileSection;
+use crate::cpu_set::CpuSet;
 use crate::simpleperf::SimplePerfEventType;
+use crate::tracepoint::TracingData;
 use crate::{Error, ReadError};
 
 /// The number of available and online CPUs. (`nr_cpus`)
@@ -30,7 +32,7 @@ impl NrCpus {
 }
 
 /// The timestamps of the first and last sample.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct SampleTimeRange {
     pub first_sample_time: u64,
     pub last_sample_time: u64,
@@ -49,14 +51,118 @@ impl SampleTimeRange {
     }
 }
 
+/// The settings which were used to compress the data in `COMPRESSED` and
+/// `COMPRESSED2` records. (`HEADER_COMPRESSED`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionInfo {
+    pub version: u32,
+    /// The compression algorithm, [`CompressionInfo::ZSTD`] is the only one.
+    pub compression_type: u32,
+    pub level: u32,
+    /// The uncompressed size divided by the compressed size, or 0 if unknown.
+    pub ratio: u32,
+    /// The size of the mmap buffers whose contents were compressed.
+    pub mmap_len: u32,
+}
+
+impl CompressionInfo {
+    pub const STRUCT_SIZE: usize = 4 + 4 + 4 + 4 + 4;
+    pub const ZSTD: u32 = 1;
+
+    pub fn parse<R: Read, T: ByteOrder>(mut reader: R) -> Result<Self, std::io::Error> {
+        let version = reader.read_u32::<T>()?;
+        let compression_type = reader.read_u32::<T>()?;
+        let level = reader.read_u32::<T>()?;
+        let ratio = reader.read_u32::<T>()?;
+        let mmap_len = reader.read_u32::<T>()?;
+        Ok(Self {
+            version,
+            compression_type,
+            level,
+            ratio,
+            mmap_len,
+        })
+    }
+}
+
+/// A pair of reference timestamps which were taken at the same time, one from
+/// the wall clock and one from the clock that was used for the record
+/// timestamps. (`HEADER_CLOCK_DATA`)
+///
+/// This is written by `perf record -k <clockid>`, and lets you convert
+/// record timestamps to wall-clock time with [`ClockData::to_system_time`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockData {
+    /// The clock which was used for the record timestamps, e.g. 1 for
+    /// `CLOCK_MONOTONIC`.
+    pub clockid: u32,
+    /// The wall-clock time, in nanoseconds since the Unix epoch.
+    pub wall_clock_ns: u64,
+    /// The time of the clock `clockid` at the same moment, in nanoseconds.
+    pub clockid_time_ns: u64,
+}
+
+impl ClockData {
+    pub const STRUCT_SIZE: usize = 4 + 4 + 8 + 8;
+
+    pub fn parse<R: Read, T: ByteOrder>(mut reader: R) -> Result<Self, std::io::Error> {
+        // struct {
+        //     u32 version; /* 1 */
+        //     u32 clockid;
+        //     u64 wall_clock_ns;
+        //     u64 clockid_time_ns;
+        // };
+        let version = reader.read_u32::<T>()?;
+        if version != 1 {
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+        let clockid = reader.read_u32::<T>()?;
+        let wall_clock_ns = reader.read_u64::<T>()?;
+        let clockid_time_ns = reader.read_u64::<T>()?;
+        Ok(Self {
+            clockid,
+            wall_clock_ns,
+            clockid_time_ns,
+        })
+    }
+
+    /// Converts a record timestamp, in nanoseconds of the clock `clockid`,
+    /// to nanoseconds since the Unix epoch. Returns `None` if the result is
+    /// before the epoch or doesn't fit into a u64.
+    pub fn to_wall_clock_ns(&self, timestamp: u64) -> Option<u64> {
+        let delta = i128::from(timestamp) - i128::from(self.clockid_time_ns);
+        u64::try_from(i128::from(self.wall_clock_ns) + delta).ok()
+    }
+
+    /// Converts a record timestamp, in nanoseconds of the clock `clockid`,
+    /// to wall-clock time. Returns `None` if the result can't be represented
+    /// as a `SystemTime` on this platform.
+    pub fn to_system_time(&self, timestamp: u64) -> Option<std::time::SystemTime> {
+        let reference = std::time::UNIX_EPOCH
+            .checked_add(std::time::Duration::from_nanos(self.wall_clock_ns))?;
+        if timestamp >= self.clockid_time_ns {
+            reference.checked_add(std::time::Duration::from_nanos(
+                timestamp - self.clockid_time_ns,
+            ))
+        } else {
+            reference.checked_sub(std::time::Duration::from_nanos(
+                self.clockid_time_ns - timestamp,
+            ))
+        }
+    }
+}
+
 pub struct HeaderString;
 
 impl HeaderString {
     /// Parse a string.
     pub fn parse<R: Read, T: ByteOrder>(mut reader: R) -> Result<Option<String>, std::io::Error> {
         let len = reader.read_u32::<T>()?;
-        let mut s = vec![0; len as usize];
-        reader.read_exact(&mut s)?;
+        let mut s = Vec::new();
+        reader.take(len.into()).read_to_end(&mut s)?;
+        if s.len() < len as usize {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
         let actual_len = memchr::memchr(0, &s).unwrap_or(s.len());
         s.truncate(actual_len);
         Ok(String::from_utf8(s).ok())
@@ -69,6 +175,11 @@ pub struct AttributeDescription {
     pub attr: PerfEventAttr,
     pub name: Option<String>,
     pub event_ids: Vec<u64>,
+    /// The event group which this event belongs to, from the `GROUP_DESC`
+    /// feature section. `None` if the event isn't part of a group.
+    pub group: Option<GroupDesc>,
+    /// Whether this event is the leader of its event group.
+    pub is_group_leader: bool,
 }
 
 impl AttributeDescription {
@@ -89,13 +200,13 @@ impl AttributeDescription {
         // };
         // ```
         let nr = cursor.read_u32::<T>()?;
-        let mut attributes = Vec::with_capacity(nr as usize);
+        let mut attributes = Vec::new();
         let attr_size = cursor.read_u32::<T>()? as u64;
         for _ in 0..nr {
             let attr = Self::parse_single_attr::<_, T>(&mut cursor, attr_size)?;
             let nr_ids = cursor.read_u32::<T>()?;
             let event_string = HeaderString::parse::<_, T>(&mut cursor)?;
-            let mut ids = Vec::with_capacity(nr_ids as usize);
+            let mut ids = Vec::new();
             for _ in 0..nr_ids {
                 ids.push(cursor.read_u64::<T>()?);
             }
@@ -103,6 +214,8 @@ impl AttributeDescription {
                 attr,
                 name: event_string,
                 event_ids: ids,
+                group: None,
+                is_group_leader: false,
             });
         }
         Ok(attributes)
@@ -164,9 +277,11 @@ impl AttributeDescription {
         cursor.seek(SeekFrom::Start(section.offset))?;
 
         // Each entry in the event_types section is a PerfEventAttr followed by a PerfFileSection.
-        let entry_size = attr_size + PerfFileSection::STRUCT_SIZE;
+        let entry_size = attr_size
+            .checked_add(PerfFileSection::STRUCT_SIZE)
+            .ok_or(ReadError::PerfEventAttr)?;
         let entry_count = section.size / entry_size;
-        let mut perf_event_event_type_info = Vec::with_capacity(entry_count as usize);
+        let mut perf_event_event_type_info = Vec::new();
         for _ in 0..entry_count {
             let attr = Self::parse_single_attr::<_, T>(&mut cursor, attr_size)?;
             let event_ids = PerfFileSection::parse::<_, T>(&mut cursor)?;
@@ -179,7 +294,7 @@ impl AttributeDescription {
             cursor.seek(SeekFrom::Start(section.offset))?;
             // This section is just a list of u64 event IDs.
             let id_count = section.size / 8;
-            let mut event_ids = Vec::with_capacity(id_count as usize);
+            let mut event_ids = Vec::new();
             for _ in 0..id_count {
                 event_ids.push(cursor.read_u64::<T>()?);
             }
@@ -192,6 +307,8 @@ impl AttributeDescription {
                 attr,
                 name,
                 event_ids,
+                group: None,
+                is_group_leader: false,
             });
         }
         Ok(attributes)
@@ -206,15 +323,20 @@ impl AttributeDescription {
         attr_section: &PerfFileSection,
         attr_size: u64,
     ) -> Result<Vec<Self>, Error> {
+        if attr_size == 0 {
+            return Err(ReadError::PerfEventAttr.into());
+        }
         cursor.seek(SeekFrom::Start(attr_section.offset))?;
         let attr_count = attr_section.size / attr_size;
-        let mut attributes = Vec::with_capacity(attr_count as usize);
+        let mut attributes = Vec::new();
         for _ in 0..attr_count {
             let attr = Self::parse_single_attr::<_, T>(&mut cursor, attr_size)?;
             attributes.push(AttributeDescription {
                 attr,
                 name: None,
                 event_ids: vec![],
+                group: None,
+                is_group_leader: false,
             });
         }
         Ok(attributes)
@@ -242,6 +364,9 @@ impl AttributeDescription {
     }
 
     /// The event name.
+    ///
+    /// Tracepoint events without a name in the file are named after their
+    /// format in the `TRACING_DATA` section, e.g. `sched:sched_switch`.
     pub fn name(&self) -> Option<&str> {
         self.name.as_deref()
     }
@@ -250,6 +375,136 @@ impl AttributeDescription {
     pub fn ids(&self) -> &[u64] {
         &self.event_ids
     }
+
+    /// The attribute index of the leader of this event's group, or `None` if
+    /// the event isn't part of a group.
+    pub fn group_leader_index(&self) -> Option<usize> {
+        self.group.as_ref().map(|group| group.leader_index)
+    }
+
+    /// Whether this event is the leader of an event group.
+    pub fn is_group_leader(&self) -> bool {
+        self.is_group_leader
+    }
+
+    /// Names the tracepoint attributes which don't have a name, e.g. because
+    /// the file has no `EVENT_DESC` section, after the event with the
+    /// attribute's tracepoint ID in the `TRACING_DATA` section.
+    pub(crate) fn apply_tracepoint_names(attributes: &mut [Self], tracing_data: &TracingData) {
+        let names = tracing_data.event_names();
+        for attribute in attributes {
+            if !attribute.is_unnamed_tracepoint() {
+                continue;
+            }
+            if let PerfEventType::Tracepoint(id) = attribute.attr.type_ {
+                if let Some(name) = names.get(&id) {
+                    attribute.name = Some(name.clone());
+                }
+            }
+        }
+    }
+
+    pub(crate) fn is_unnamed_tracepoint(&self) -> bool {
+        matches!(self.attr.type_, PerfEventType::Tracepoint(_))
+            && self.name.as_deref().unwrap_or("").is_empty()
+    }
+
+    /// Attaches the groups from the `GROUP_DESC` feature section to the
+    /// attributes. Groups which refer to attributes that don't exist are
+    /// ignored.
+    pub(crate) fn apply_group_descs(attributes: &mut [Self], groups: &[GroupDesc]) {
+        for group in groups {
+            let members = group.member_indexes();
+            if members.is_empty() || members.end > attributes.len() {
+                continue;
+            }
+            for index in members {
+                attributes[index].group = Some(group.clone());
+                attributes[index].is_group_leader = index == group.leader_index;
+            }
+        }
+    }
+}
+
+/// An event group from the `GROUP_DESC` feature section, e.g. for
+/// `perf record -e '{cycles,instructions}'`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupDesc {
+    /// The name of the group, or `None` for an anonymous group.
+    pub name: Option<String>,
+    /// The attribute index of the group leader.
+    pub leader_index: usize,
+    /// The number of events in the group, including the leader. The members
+    /// follow the leader in the attribute list.
+    pub member_count: usize,
+}
+
+impl GroupDesc {
+    /// Parse the `GROUP_DESC` feature section.
+    pub fn parse_section<R: Read, T: ByteOrder>(
+        mut reader: R,
+    ) -> Result<Vec<Self>, std::io::Error> {
+        // struct {
+        //     uint32_t nr_groups;
+        //     struct {
+        //         struct perf_header_string name;
+        //         uint32_t leader_idx;
+        //         uint32_t nr_members;
+        //     } [nr_groups];
+        // };
+        let nr_groups = reader.read_u32::<T>()?;
+        let mut groups = Vec::new();
+        for _ in 0..nr_groups {
+            let name = HeaderString::parse::<_, T>(&mut reader)?;
+            let leader_index = reader.read_u32::<T>()? as usize;
+            let member_count = reader.read_u32::<T>()? as usize;
+            groups.push(Self {
+                name: name.filter(|name| name != "{anon_group}"),
+                leader_index,
+                member_count,
+            });
+        }
+        Ok(groups)
+    }
+
+    /// The attribute indexes of the group members, including the leader.
+    pub fn member_indexes(&self) -> std::ops::Range<usize> {
+        self.leader_index..self.leader_index.saturating_add(self.member_count)
+    }
+}
+
+/// An event group together with the attributes of its members, returned by
+/// [`PerfFile::event_groups`](crate::PerfFile::event_groups).
+#[derive(Debug, Clone)]
+pub struct EventGroup<'a> {
+    /// The group description from the `GROUP_DESC` feature section.
+    pub desc: GroupDesc,
+    /// The attributes of the group members, starting with the leader.
+    pub members: &'a [AttributeDescription],
+}
+
+impl<'a> EventGroup<'a> {
+    /// The name of the group, or `None` for an anonymous group.
+    pub fn name(&self) -> Option<&str> {
+        self.desc.name.as_deref()
+    }
+
+    /// The attribute index of the group leader.
+    pub fn leader_index(&self) -> usize {
+        self.desc.leader_index
+    }
+
+    /// The attribute of the group leader.
+    pub fn leader(&self) -> &'a AttributeDescription {
+        &self.members[0]
+    }
+
+    /// The attribute indexes of the group members, including the leader.
+    /// These are the indexes into
+    /// [`PerfFile::event_attributes`](crate::PerfFile::event_attributes).
+    pub fn member_indexes(&self) -> std::ops::Range<usize> {
+        self.desc.member_indexes()
+    }
 }
 
 /// The names of the dynamic PMU types used in [`PerfEventType::DynamicPmu`](linux_perf_event_reader::PerfEventType::DynamicPmu).
@@ -257,6 +512,7 @@ impl AttributeDescription {
 /// For example, this allows you to find out whether a `DynamicPmu`
 /// perf event is a kprobe or a uprobe, which then lets you interpret
 /// the meaning of the config fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PmuMappings(pub LinearMap<u32, String>);
 
 impl PmuMappings {
@@ -269,7 +525,7 @@ impl PmuMappings {
         //     } [nr]; /* Variable length records */
         // };
         let nr = reader.read_u32::<T>()?;
-        let mut vec = Vec::with_capacity(nr as usize);
+        let mut vec = Vec::new();
         for _ in 0..nr {
             let pmu_type = reader.read_u32::<T>()?;
             if let Some(pmu_name) = HeaderString::parse::<_, T>(&mut reader)? {
@@ -280,3 +536,628 @@ impl PmuMappings {
         Ok(Self(vec.into_iter().collect()))
     }
 }
+
+/// The CPU topology, from the `HEADER_CPU_TOPOLOGY` feature section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuTopology {
+    /// One entry per package ("socket"), with the CPUs in that package.
+    pub core_siblings: Vec<CpuSet>,
+    /// One entry per physical core, with the hardware threads of that core.
+    pub thread_siblings: Vec<CpuSet>,
+    /// The per-CPU topology information, indexed by logical CPU index.
+    ///
+    /// This can be empty if the file was written by an old version of perf.
+    pub cpus: Vec<CpuTopologyEntry>,
+    /// One entry per die, with the CPUs on that die. Empty if the file was
+    /// written by a perf version without die support.
+    pub die_siblings: Vec<CpuSet>,
+}
+
+/// The topology information for a single logical CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CpuTopologyEntry {
+    /// The core ID, or -1 if the CPU was offline.
+    pub core_id: i32,
+    /// The socket ID, or -1 if the CPU was offline.
+    pub socket_id: i32,
+    /// The die ID, if known. -1 if the CPU was offline.
+    pub die_id: Option<i32>,
+}
+
+impl CpuTopologyEntry {
+    /// Whether the CPU was online when the profile was recorded.
+    pub fn is_online(&self) -> bool {
+        self.core_id != -1 && self.socket_id != -1
+    }
+}
+
+impl CpuTopology {
+    /// Parse the section. `nr_cpus_available` comes from the `NRCPUS` section.
+    pub fn parse<R: Read, T: ByteOrder>(
+        mut reader: R,
+        nr_cpus_available: u32,
+    ) -> Result<Self, std::io::Error> {
+        // struct {
+        //     uint32_t nr_cores;
+        //     struct perf_header_string cores[]; /* variable length records */
+        //     uint32_t nr_threads;
+        //     struct perf_header_string threads[]; /* variable length records */
+        //     struct {
+        //         uint32_t core_id;
+        //         uint32_t socket_id;
+        //     } cpus[nr]; /* 'nr' comes from previously processed HEADER_NRCPU section */
+        //     uint32_t nr_dies;
+        //     struct perf_header_string dies[]; /* variable length records */
+        //     uint32_t die_id[nr_cpus];
+        // };
+        let core_siblings = parse_cpu_list_strings::<_, T>(&mut reader)?;
+        let thread_siblings = parse_cpu_list_strings::<_, T>(&mut reader)?;
+
+        // The remaining parts were added in later perf versions, so the
+        // section can end at any of these points.
+        let mut cpus = Vec::new();
+        for _ in 0..nr_cpus_available {
+            let Some(core_id) = read_u32_or_eof::<_, T>(&mut reader)? else {
+                break;
+            };
+            let socket_id = reader.read_u32::<T>()?;
+            cpus.push(CpuTopologyEntry {
+                core_id: core_id as i32,
+                socket_id: socket_id as i32,
+                die_id: None,
+            });
+        }
+
+        let mut die_siblings = Vec::new();
+        if cpus.len() == nr_cpus_available as usize {
+            if let Some(nr_dies) = read_u32_or_eof::<_, T>(&mut reader)? {
+                for _ in 0..nr_dies {
+                    die_siblings.push(parse_cpu_list_string::<_, T>(&mut reader)?);
+                }
+                for entry in &mut cpus {
+                    entry.die_id = Some(reader.read_u32::<T>()? as i32);
+                }
+            }
+        }
+
+        Ok(Self {
+            core_siblings,
+            thread_siblings,
+            cpus,
+            die_siblings,
+        })
+    }
+}
+
+/// The kind of core on a hybrid CPU, see [`HybridTopology::core_type_for_cpu`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CoreType {
+    /// A performance core ("P-core"), e.g. the `cpu_core` PMU on Intel hybrid CPUs.
+    Performance,
+    /// An efficiency core ("E-core"), e.g. the `cpu_atom` PMU on Intel hybrid CPUs.
+    Efficiency,
+    /// A core whose PMU name we don't know how to classify.
+    Other,
+}
+
+impl CoreType {
+    /// Classify a core based on the name of its PMU.
+    pub fn from_pmu_name(pmu_name: &str) -> Self {
+        match pmu_name {
+            "cpu_core" => CoreType::Performance,
+            "cpu_atom" | "cpu_lowpower" => CoreType::Efficiency,
+            _ => CoreType::Other,
+        }
+    }
+}
+
+/// The topology of a hybrid CPU, from the `HEADER_HYBRID_TOPOLOGY` feature section.
+///
+/// Hybrid CPUs (e.g. Intel Alder Lake, or ARM big.LITTLE) have different kinds
+/// of cores, each with its own PMU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HybridTopology {
+    /// One entry per core PMU.
+    pub nodes: Vec<HybridTopologyNode>,
+}
+
+/// A core PMU and the CPUs it covers. Used in [`HybridTopology`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HybridTopologyNode {
+    /// The PMU name, e.g. "cpu_core" or "cpu_atom".
+    pub pmu_name: String,
+    /// The CPUs covered by this PMU.
+    pub cpus: CpuSet,
+}
+
+impl HybridTopology {
+    pub fn parse<R: Read, T: ByteOrder>(mut reader: R) -> Result<Self, std::io::Error> {
+        // struct {
+        //     u32 nr;
+        //     struct {
+        //         struct perf_header_string pmu_name;
+        //         struct perf_header_string cpus;
+        //     } [nr];
+        // };
+        let nr = reader.read_u32::<T>()?;
+        let mut nodes = Vec::new();
+        for _ in 0..nr {
+            let pmu_name =
+                HeaderString::parse::<_, T>(&mut reader)?.ok_or(std::io::ErrorKind::InvalidData)?;
+            let cpus = parse_cpu_list_string::<_, T>(&mut reader)?;
+            nodes.push(HybridTopologyNode { pmu_name, cpus });
+        }
+        Ok(Self { nodes })
+    }
+
+    /// The name of the PMU which covers `cpu`, e.g. "cpu_core".
+    pub fn pmu_name_for_cpu(&self, cpu: u32) -> Option<&str> {
+        self.nodes
+            .iter()
+            .find(|node| node.cpus.contains(cpu))
+            .map(|node| node.pmu_name.as_str())
+    }
+
+    /// The kind of core that `cpu` is, based on the name of its PMU.
+    ///
+    /// Returns `None` if `cpu` isn't covered by any of the PMUs.
+    pub fn core_type_for_cpu(&self, cpu: u32) -> Option<CoreType> {
+        self.pmu_name_for_cpu(cpu).map(CoreType::from_pmu_name)
+    }
+}
+
+/// The NUMA nodes of the machine, from the `HEADER_NUMA_TOPOLOGY` feature section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumaTopology {
+    /// One entry per NUMA node.
+    pub nodes: Vec<NumaNode>,
+}
+
+/// A NUMA node, its memory and its CPUs. Used in [`NumaTopology`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumaNode {
+    /// The node ID, as in `/sys/devices/system/node/node<id>`.
+    pub node_id: u32,
+    /// The total memory of this node, in kB.
+    pub mem_total: u64,
+    /// The free memory of this node when the profile was recorded, in kB.
+    pub mem_free: u64,
+    /// The CPUs of this node.
+    pub cpus: CpuSet,
+}
+
+impl NumaTopology {
+    pub fn parse<R: Read, T: ByteOrder>(mut reader: R) -> Result<Self, std::io::Error> {
+        // struct {
+        //     u32 nr;
+        //     struct {
+        //         u32 node_id;
+        //         u64 mem_total;
+        //         u64 mem_free;
+        //         struct perf_header_string cpus;
+        //     } [nr];
+        // };
+        let nr = reader.read_u32::<T>()?;
+        let mut nodes = Vec::new();
+        for _ in 0..nr {
+            let node_id = reader.read_u32::<T>()?;
+            let mem_total = reader.read_u64::<T>()?;
+            let mem_free = reader.read_u64::<T>()?;
+            let cpus = parse_cpu_list_string::<_, T>(&mut reader)?;
+            nodes.push(NumaNode {
+                node_id,
+                mem_total,
+                mem_free,
+                cpus,
+            });
+        }
+        Ok(Self { nodes })
+    }
+
+    /// The node which `cpu` belongs to.
+    pub fn node_for_cpu(&self, cpu: u32) -> Option<&NumaNode> {
+        self.nodes.iter().find(|node| node.cpus.contains(cpu))
+    }
+}
+
+/// The CPU caches of the machine, from the `HEADER_CACHE` feature section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheTopology {
+    /// One entry per distinct cache, ordered by level.
+    pub caches: Vec<CacheLevel>,
+}
+
+/// A CPU cache and the CPUs which share it. Used in [`CacheTopology`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheLevel {
+    /// The cache level, e.g. 1 for the L1 caches.
+    pub level: u32,
+    /// The cache line size, in bytes.
+    pub line_size: u32,
+    /// The number of sets.
+    pub sets: u32,
+    /// The associativity.
+    pub ways: u32,
+    /// The cache type, e.g. "Data", "Instruction" or "Unified".
+    pub cache_type: String,
+    /// The size as perf stored it, e.g. "32K". See [`CacheLevel::size_in_bytes`].
+    pub size: String,
+    /// The CPUs which share this cache.
+    pub shared_cpus: CpuSet,
+}
+
+impl CacheLevel {
+    /// The cache size in bytes, parsed from [`CacheLevel::size`].
+    pub fn size_in_bytes(&self) -> Option<u64> {
+        let size = self.size.trim();
+        let (number, multiplier) = match size.as_bytes().last()? {
+            b'K' => (&size[..size.len() - 1], 1024),
+            b'M' => (&size[..size.len() - 1], 1024 * 1024),
+            b'G' => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+            _ => (size, 1),
+        };
+        number.parse::<u64>().ok()?.checked_mul(multiplier)
+    }
+}
+
+impl CacheTopology {
+    pub fn parse<R: Read, T: ByteOrder>(mut reader: R) -> Result<Self, std::io::Error> {
+        // struct {
+        //     u32 version; /* 1 */
+        //     u32 cnt;
+        //     struct {
+        //         u32 level;
+        //         u32 line_size;
+        //         u32 sets;
+        //         u32 ways;
+        //         struct perf_header_string type;
+        //         struct perf_header_string size;
+        //         struct perf_header_string map;
+        //     } [cnt];
+        // };
+        let version = reader.read_u32::<T>()?;
+        if version != 1 {
+            return Err(std::io::ErrorKind::InvalidData.into());
+        }
+        let cnt = reader.read_u32::<T>()?;
+        let mut caches = Vec::new();
+        for _ in 0..cnt {
+            let level = reader.read_u32::<T>()?;
+            let line_size = reader.read_u32::<T>()?;
+            let sets = reader.read_u32::<T>()?;
+            let ways = reader.read_u32::<T>()?;
+            let cache_type = HeaderString::parse::<_, T>(&mut reader)?.unwrap_or_default();
+            let size = HeaderString::parse::<_, T>(&mut reader)?.unwrap_or_default();
+            let shared_cpus = parse_cpu_list_string::<_, T>(&mut reader)?;
+            caches.push(CacheLevel {
+                level,
+                line_size,
+                sets,
+                ways,
+                cache_type,
+                size,
+                shared_cpus,
+            });
+        }
+        Ok(Self { caches })
+    }
+
+    /// The caches which `cpu` uses, from the lowest to the highest level.
+    pub fn caches_for_cpu(&self, cpu: u32) -> impl Iterator<Item = &CacheLevel> + '_ {
+        self.caches
+            .iter()
+            .filter(move |cache| cache.shared_cpus.contains(cpu))
+    }
+}
+
+/// The capabilities of a PMU, from the `HEADER_CPU_PMU_CAPS` or the
+/// `HEADER_HYBRID_CPU_PMU_CAPS` feature section.
+///
+/// These are the files in `/sys/bus/event_source/devices/<pmu>/caps/`, e.g.
+/// `branches=32` for the depth of the branch stack, or `max_precise=3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PmuCaps {
+    /// The PMU name, e.g. "cpu", or "cpu_core" and "cpu_atom" on hybrid CPUs.
+    pub pmu_name: String,
+    /// The capabilities as (name, value) pairs, in the order in which perf
+    /// wrote them.
+    pub caps: Vec<(String, String)>,
+}
+
+impl PmuCaps {
+    /// Parse the `HEADER_CPU_PMU_CAPS` feature section, which has the
+    /// capabilities of the "cpu" PMU.
+    pub fn parse<R: Read, T: ByteOrder>(reader: R) -> Result<Self, std::io::Error> {
+        // struct {
+        //     u32 nr_cpu_pmu_caps;
+        //     {
+        //         struct perf_header_string cap_name;
+        //         struct perf_header_string cap_value;
+        //     } [nr_cpu_pmu_caps];
+        // };
+        let caps = Self::parse_caps::<_, T>(reader)?;
+        Ok(Self {
+            pmu_name: "cpu".to_owned(),
+            caps,
+        })
+    }
+
+    /// Parse the `HEADER_HYBRID_CPU_PMU_CAPS` feature section, which has one
+    /// entry per core PMU.
+    pub fn parse_hybrid<R: Read, T: ByteOrder>(mut reader: R) -> Result<Vec<Self>, std::io::Error> {
+        // struct {
+        //     u32 nr_pmu;
+        //     struct {
+        //         u32 nr_cpu_pmu_caps;
+        //         {
+        //             struct perf_header_string cap_name;
+        //             struct perf_header_string cap_value;
+        //         } [nr_cpu_pmu_caps];
+        //         struct perf_header_string pmu_name;
+        //     } [nr_pmu];
+        // };
+        let nr_pmu = reader.read_u32::<T>()?;
+        let mut pmus = Vec::new();
+        for _ in 0..nr_pmu {
+            let caps = Self::parse_caps::<_, T>(&mut reader)?;
+            let pmu_name =
+                HeaderString::parse::<_, T>(&mut reader)?.ok_or(std::io::ErrorKind::InvalidData)?;
+            pmus.push(Self { pmu_name, caps });
+        }
+        Ok(pmus)
+    }
+
+    fn parse_caps<R: Read, T: ByteOrder>(
+        mut reader: R,
+    ) -> Result<Vec<(String, String)>, std::io::Error> {
+        let nr_caps = reader.read_u32::<T>()?;
+        let mut caps = Vec::new();
+        for _ in 0..nr_caps {
+            let name =
+                HeaderString::parse::<_, T>(&mut reader)?.ok_or(std::io::ErrorKind::InvalidData)?;
+            let value = HeaderString::parse::<_, T>(&mut reader)?.unwrap_or_default();
+            caps.push((name, value));
+        }
+        Ok(caps)
+    }
+
+    /// The value of the capability `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.caps
+            .iter()
+            .find(|(cap_name, _)| cap_name == name)
+            .map(|(_, value)| value.trim())
+    }
+
+    /// The number of entries in the branch stack (LBR), from the `branches`
+    /// capability.
+    pub fn branches(&self) -> Option<u32> {
+        self.get("branches")?.parse().ok()
+    }
+
+    /// The highest supported `precise_ip` level, from the `max_precise`
+    /// capability.
+    pub fn max_precise(&self) -> Option<u32> {
+        self.get("max_precise")?.parse().ok()
+    }
+}
+
+/// Reads a u32, or returns `None` if the reader is exhausted.
+fn read_u32_or_eof<R: Read, T: ByteOrder>(mut reader: R) -> Result<Option<u32>, std::io::Error> {
+    match reader.read_u32::<T>() {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Parses a perf_header_string which contains a cpulist, e.g. "0-3,8".
+fn parse_cpu_list_string<R: Read, T: ByteOrder>(reader: R) -> Result<CpuSet, std::io::Error> {
+    HeaderString::parse::<_, T>(reader)?
+        .and_then(|s| CpuSet::parse_list_str(&s))
+        .ok_or_else(|| std::io::ErrorKind::InvalidData.into())
+}
+
+/// Parses a u32 count followed by that many cpulist perf_header_strings.
+fn parse_cpu_list_strings<R: Read, T: ByteOrder>(
+    mut reader: R,
+) -> Result<Vec<CpuSet>, std::io::Error> {
+    let nr = reader.read_u32::<T>()?;
+    let mut sets = Vec::new();
+    for _ in 0..nr {
+        sets.push(parse_cpu_list_string::<_, T>(&mut reader)?);
+    }
+    Ok(sets)
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    use super::*;
+
+    fn push_header_string(buf: &mut Vec<u8>, s: &str) {
+        // perf pads strings to a multiple of 64 bytes, including the nul byte.
+        let padded_len = (s.len() + 1).next_multiple_of(64);
+        buf.write_u32::<LittleEndian>(padded_len as u32).unwrap();
+        buf.extend_from_slice(s.as_bytes());
+        buf.resize(buf.len() + padded_len - s.len(), 0);
+    }
+
+    #[test]
+    fn cpu_topology() {
+        let mut buf = Vec::new();
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        push_header_string(&mut buf, "0-3");
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        push_header_string(&mut buf, "0,2");
+        push_header_string(&mut buf, "1,3");
+        for (core_id, socket_id) in [(0, 0), (1, 0), (0, 0), (1, 0)] {
+            buf.write_u32::<LittleEndian>(core_id).unwrap();
+            buf.write_u32::<LittleEndian>(socket_id).unwrap();
+        }
+        let topology = CpuTopology::parse::<_, LittleEndian>(&buf[..], 4).unwrap();
+        assert_eq!(topology.core_siblings, vec![CpuSet::from_range(0, 3)]);
+        assert_eq!(topology.thread_siblings[1], CpuSet::from_cpus([1, 3]));
+        assert_eq!(topology.cpus.len(), 4);
+        assert_eq!(topology.cpus[3].core_id, 1);
+        assert_eq!(topology.cpus[3].die_id, None);
+        assert!(topology.die_siblings.is_empty());
+
+        // A topology section with die information.
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        push_header_string(&mut buf, "0-3");
+        for _ in 0..4 {
+            buf.write_u32::<LittleEndian>(0).unwrap();
+        }
+        let topology = CpuTopology::parse::<_, LittleEndian>(&buf[..], 4).unwrap();
+        assert_eq!(topology.die_siblings.len(), 1);
+        assert_eq!(topology.cpus[2].die_id, Some(0));
+    }
+
+    #[test]
+    fn numa_topology() {
+        let mut buf = Vec::new();
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        for (node_id, mem_total, mem_free, cpus) in
+            [(0, 1000, 500, "0-3"), (1, 2000, 1500, "4-7,12")]
+        {
+            buf.write_u32::<LittleEndian>(node_id).unwrap();
+            buf.write_u64::<LittleEndian>(mem_total).unwrap();
+            buf.write_u64::<LittleEndian>(mem_free).unwrap();
+            push_header_string(&mut buf, cpus);
+        }
+        let topology = NumaTopology::parse::<_, LittleEndian>(&buf[..]).unwrap();
+        assert_eq!(topology.nodes.len(), 2);
+        assert_eq!(topology.nodes[1].mem_total, 2000);
+        assert_eq!(topology.nodes[1].mem_free, 1500);
+        assert_eq!(topology.nodes[0].cpus, CpuSet::from_range(0, 3));
+        assert_eq!(topology.node_for_cpu(12).map(|node| node.node_id), Some(1));
+        assert_eq!(topology.node_for_cpu(9), None);
+    }
+
+    #[test]
+    fn cache_topology() {
+        let mut buf = Vec::new();
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        buf.write_u32::<LittleEndian>(3).unwrap();
+        for (level, cache_type, size, map) in [
+            (1, "Data", "48K", "0,4"),
+            (1, "Instruction", "32K", "0,4"),
+            (3, "Unified", "12288K", "0-7"),
+        ] {
+            buf.write_u32::<LittleEndian>(level).unwrap();
+            buf.write_u32::<LittleEndian>(64).unwrap();
+            buf.write_u32::<LittleEndian>(64).unwrap();
+            buf.write_u32::<LittleEndian>(12).unwrap();
+            push_header_string(&mut buf, cache_type);
+            push_header_string(&mut buf, size);
+            push_header_string(&mut buf, map);
+        }
+        let topology = CacheTopology::parse::<_, LittleEndian>(&buf[..]).unwrap();
+        assert_eq!(topology.caches.len(), 3);
+        assert_eq!(topology.caches[0].cache_type, "Data");
+        assert_eq!(topology.caches[0].size_in_bytes(), Some(48 * 1024));
+        assert_eq!(topology.caches[2].size_in_bytes(), Some(12 * 1024 * 1024));
+        assert_eq!(topology.caches[2].shared_cpus, CpuSet::from_range(0, 7));
+        let levels: Vec<_> = topology.caches_for_cpu(4).map(|c| c.level).collect();
+        assert_eq!(levels, vec![1, 1, 3]);
+        assert_eq!(topology.caches_for_cpu(5).count(), 1);
+    }
+
+    #[test]
+    fn pmu_caps() {
+        let push_caps = |buf: &mut Vec<u8>, caps: &[(&str, &str)]| {
+            buf.write_u32::<LittleEndian>(caps.len() as u32).unwrap();
+            for (name, value) in caps {
+                push_header_string(buf, name);
+                push_header_string(buf, value);
+            }
+        };
+        let mut buf = Vec::new();
+        push_caps(&mut buf, &[("branches", "32"), ("max_precise", "3")]);
+        let caps = PmuCaps::parse::<_, LittleEndian>(&buf[..]).unwrap();
+        assert_eq!(caps.pmu_name, "cpu");
+        assert_eq!(caps.branches(), Some(32));
+        assert_eq!(caps.max_precise(), Some(3));
+        assert_eq!(caps.get("pmu_name"), None);
+
+        let mut buf = Vec::new();
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        push_caps(
+            &mut buf,
+            &[("branches", "32"), ("pmu_name", "alderlake_hybrid")],
+        );
+        push_header_string(&mut buf, "cpu_core");
+        push_caps(&mut buf, &[("max_precise", "2")]);
+        push_header_string(&mut buf, "cpu_atom");
+        let pmus = PmuCaps::parse_hybrid::<_, LittleEndian>(&buf[..]).unwrap();
+        assert_eq!(pmus.len(), 2);
+        assert_eq!(pmus[0].pmu_name, "cpu_core");
+        assert_eq!(pmus[0].get("pmu_name"), Some("alderlake_hybrid"));
+        assert_eq!(pmus[1].pmu_name, "cpu_atom");
+        assert_eq!(pmus[1].branches(), None);
+        assert_eq!(pmus[1].max_precise(), Some(2));
+    }
+
+    #[test]
+    fn hybrid_topology() {
+        let mut buf = Vec::new();
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        push_header_string(&mut buf, "cpu_core");
+        push_header_string(&mut buf, "0-7");
+        push_header_string(&mut buf, "cpu_atom");
+        push_header_string(&mut buf, "8-15");
+        let topology = HybridTopology::parse::<_, LittleEndian>(&buf[..]).unwrap();
+        assert_eq!(topology.nodes.len(), 2);
+        assert_eq!(topology.nodes[1].cpus, CpuSet::from_range(8, 15));
+        assert_eq!(topology.pmu_name_for_cpu(3), Some("cpu_core"));
+        assert_eq!(topology.core_type_for_cpu(3), Some(CoreType::Performance));
+        assert_eq!(topology.core_type_for_cpu(12), Some(CoreType::Efficiency));
+        assert_eq!(topology.core_type_for_cpu(16), None);
+    }
+
+    #[test]
+    fn clock_data() {
+        let mut buf = Vec::new();
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        buf.write_u64::<LittleEndian>(1_700_000_000_000_000_000)
+            .unwrap();
+        buf.write_u64::<LittleEndian>(5_000_000_000).unwrap();
+        let clock_data = ClockData::parse::<_, LittleEndian>(&buf[..]).unwrap();
+        assert_eq!(clock_data.clockid, 1);
+        assert_eq!(
+            clock_data.to_wall_clock_ns(6_000_000_000),
+            Some(1_700_000_001_000_000_000)
+        );
+        assert_eq!(
+            clock_data.to_system_time(4_000_000_000),
+            Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_699_999_999))
+        );
+        assert_eq!(
+            ClockData::parse::<_, LittleEndian>(&[2, 0, 0, 0][..])
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn group_desc() {
+        let mut buf = Vec::new();
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        push_header_string(&mut buf, "{anon_group}");
+        buf.write_u32::<LittleEndian>(0).unwrap();
+        buf.write_u32::<LittleEndian>(2).unwrap();
+        push_header_string(&mut buf, "mem");
+        buf.write_u32::<LittleEndian>(3).unwrap();
+        buf.write_u32::<LittleEndian>(3).unwrap();
+        let groups = GroupDesc::parse_section::<_, LittleEndian>(&buf[..]).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, None);
+        assert_eq!(groups[0].member_indexes(), 0..2);
+        assert_eq!(groups[1].name.as_deref(), Some("mem"));
+        assert_eq!(groups[1].member_indexes(), 3..6);
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+
+use byteorder::{BigEndian, LittleEndian};
+use linux_perf_event_reader::Endianness;
+
+use crate::attr_encoding::write_attr;
+use crate::build_id_event::build_id_section;
+use crate::{
+    AttributeDescription, ClockData, CompressionInfo, DsoInfo, DsoKey, Feature, GroupDesc, NrCpus,
+    PmuMappings, SampleTimeRange,
+};
+
+/// Builds the contents of feature sections from typed values, in the layout
+/// which perf writes and which [`PerfFile`](crate::PerfFile) parses.
+///
+/// Pass the builder to [`PerfFileWriter::add_feature_sections`](crate::PerfFileWriter::add_feature_sections)
+/// or [`PerfPipeWriter::write_feature_sections`](crate::PerfPipeWriter::write_feature_sections).
+/// Setting a feature again replaces the earlier value.
+///
+/// ```
+/// use linux_perf_data::linux_perf_event_reader::Endianness;
+/// use linux_perf_data::{FeatureSectionsBuilder, NrCpus};
+///
+/// let mut builder = FeatureSectionsBuilder::new(Endianness::LittleEndian);
+/// builder
+///     .set_hostname("myhost")
+///     .set_arch("x86_64")
+///     .set_cmdline(&["/usr/bin/perf", "record", "-g", "./myprogram"])
+///     .set_nr_cpus(NrCpus {
+///         nr_cpus_available: 8,
+///         nr_cpus_online: 8,
+///     });
+/// ```
+#[derive(Debug, Clone)]
+pub struct FeatureSectionsBuilder {
+    endian: Endianness,
+    /// Sorted by feature.
+    sections: Vec<(Feature, Vec<u8>)>,
+}
+
+impl FeatureSectionsBuilder {
+    pub fn new(endian: Endianness) -> Self {
+        Self {
+            endian,
+            sections: Vec::new(),
+        }
+    }
+
+    /// The byte order of the section contents.
+    pub fn endian(&self) -> Endianness {
+        self.endian
+    }
+
+    /// The host name. (`HOSTNAME`)
+    pub fn set_hostname(&mut self, hostname: &str) -> &mut Self {
+        self.set_string(Feature::HOSTNAME, hostname)
+    }
+
+    /// The kernel release, i.e. `uname -r`. (`OSRELEASE`)
+    pub fn set_os_release(&mut self, os_release: &str) -> &mut Self {
+        self.set_string(Feature::OSRELEASE, os_release)
+    }
+
+    /// The version of the tool which wrote the file. (`VERSION`)
+    pub fn set_perf_version(&mut self, version: &str) -> &mut Self {
+        self.set_string(Feature::VERSION, version)
+    }
+
+    /// The CPU architecture, i.e. `uname -m`. (`ARCH`)
+    pub fn set_arch(&mut self, arch: &str) -> &mut Self {
+        self.set_string(Feature::ARCH, arch)
+    }
+
+    /// The CPU description, e.g. the model name from `/proc/cpuinfo`.
+    /// (`CPUDESC`)
+    pub fn set_cpu_desc(&mut self, cpu_desc: &str) -> &mut Self {
+        self.set_string(Feature::CPUDESC, cpu_desc)
+    }
+
+    /// The CPU identifier, e.g. `GenuineIntel,6,85,4`. (`CPUID`)
+    pub fn set_cpu_id(&mut self, cpu_id: &str) -> &mut Self {
+        self.set_string(Feature::CPUID, cpu_id)
+    }
+
+    /// The command line which the profile was recorded with. perf stores the
+    /// path of the perf binary as the first argument. (`CMDLINE`)
+    pub fn set_cmdline(&mut self, args: &[&str]) -> &mut Self {
+        let mut data = Vec::new();
+        push_u32(&mut data, self.endian, args.len() as u32);
+        for arg in args {
+            push_header_string(&mut data, self.endian, arg.as_bytes());
+        }
+        self.set_raw(Feature::CMDLINE, data)
+    }
+
+    /// The number of available and online CPUs. (`NRCPUS`)
+    pub fn set_nr_cpus(&mut self, nr_cpus: NrCpus) -> &mut Self {
+        let mut data = Vec::with_capacity(NrCpus::STRUCT_SIZE);
+        push_u32(&mut data, self.endian, nr_cpus.nr_cpus_available);
+        push_u32(&mut data, self.endian, nr_cpus.nr_cpus_online);
+        self.set_raw(Feature::NRCPUS, data)
+    }
+
+    /// The total memory in kilobytes. (`TOTAL_MEM`)
+    pub fn set_total_mem(&mut self, total_mem: u64) -> &mut Self {
+        let mut data = Vec::with_capacity(8);
+        push_u64(&mut data, self.endian, total_mem);
+        self.set_raw(Feature::TOTAL_MEM, data)
+    }
+
+    /// The event attributes with their names and IDs. (`EVENT_DESC`)
+    ///
+    /// Returns an `InvalidInput` error for event types which can't be
+    /// encoded.
+    pub fn set_event_desc(
+        &mut self,
+        attributes: &[AttributeDescription],
+    ) -> Result<&mut Self, std::io::Error> {
+        let mut encoded_attrs = Vec::with_capacity(attributes.len());
+        for attribute in attributes {
+            let mut attr_bytes = Vec::new();
+            match self.endian {
+                Endianness::LittleEndian => {
+                    write_attr::<LittleEndian>(&attribute.attr, &mut attr_bytes)?
+                }
+                Endianness::BigEndian => write_attr::<BigEndian>(&attribute.attr, &mut attr_bytes)?,
+            }
+            encoded_attrs.push(attr_bytes);
+        }
+        let entries: Vec<_> = attributes
+            .iter()
+            .zip(&encoded_attrs)
+            .map(|(attribute, attr_bytes)| EventDescEntry {
+                attr_bytes,
+                name: attribute.name().unwrap_or(""),
+                event_ids: &attribute.event_ids,
+            })
+            .collect();
+        let data = event_desc_section(self.endian, &entries);
+        Ok(self.set_raw(Feature::EVENT_DESC, data))
+    }
+
+    /// The event groups. (`GROUP_DESC`)
+    pub fn set_group_descs(&mut self, groups: &[GroupDesc]) -> &mut Self {
+        let mut data = Vec::new();
+        push_u32(&mut data, self.endian, groups.len() as u32);
+        for group in groups {
+            let name = group.name.as_deref().unwrap_or("{anon_group}");
+            push_header_string(&mut data, self.endian, name.as_bytes());
+            push_u32(&mut data, self.endian, group.leader_index as u32);
+            push_u32(&mut data, self.endian, group.member_count as u32);
+        }
+        self.set_raw(Feature::GROUP_DESC, data)
+    }
+
+    /// The names of the PMUs by PMU type. (`PMU_MAPPINGS`)
+    pub fn set_pmu_mappings(&mut self, pmu_mappings: &PmuMappings) -> &mut Self {
+        let mut data = Vec::new();
+        push_u32(&mut data, self.endian, pmu_mappings.0.len() as u32);
+        for (pmu_type, name) in pmu_mappings.0.iter() {
+            push_u32(&mut data, self.endian, *pmu_type);
+            push_header_string(&mut data, self.endian, name.as_bytes());
+        }
+        self.set_raw(Feature::PMU_MAPPINGS, data)
+    }
+
+    /// The timestamps of the first and the last sample. (`SAMPLE_TIME`)
+    pub fn set_sample_time(&mut self, time_range: SampleTimeRange) -> &mut Self {
+        let mut data = Vec::with_capacity(SampleTimeRange::STRUCT_SIZE);
+        push_u64(&mut data, self.endian, time_range.first_sample_time);
+        push_u64(&mut data, self.endian, time_range.last_sample_time);
+        self.set_raw(Feature::SAMPLE_TIME, data)
+    }
+
+    /// The build IDs of the DSOs, see [`PerfFileWriter::add_build_ids`](crate::PerfFileWriter::add_build_ids).
+    /// (`BUILD_ID`)
+    ///
+    /// Returns an `InvalidInput` error for build IDs which are longer than 20
+    /// bytes.
+    pub fn set_build_ids(
+        &mut self,
+        build_ids: &HashMap<DsoKey, DsoInfo>,
+    ) -> Result<&mut Self, std::io::Error> {
+        let data = match self.endian {
+            Endianness::LittleEndian => build_id_section::<LittleEndian>(build_ids)?,
+            Endianness::BigEndian => build_id_section::<BigEndian>(build_ids)?,
+        };
+        Ok(self.set_raw(Feature::BUILD_ID, data))
+    }
+
+    /// The compression settings. Only set this if the data is actually
+    /// compressed. (`COMPRESSED`)
+    pub fn set_compression_info(&mut self, info: CompressionInfo) -> &mut Self {
+        let mut data = Vec::with_capacity(CompressionInfo::STRUCT_SIZE);
+        for value in [
+            info.version,
+            info.compression_type,
+            info.level,
+            info.ratio,
+            info.mmap_len,
+        ] {
+            push_u32(&mut data, self.endian, value);
+        }
+        self.set_raw(Feature::COMPRESSED, data)
+    }
+
+    /// The reference timestamps for converting record timestamps to
+    /// wall-clock time. (`CLOCK_DATA`)
+    pub fn set_clock_data(&mut self, clock_data: ClockData) -> &mut Self {
+        let mut data = Vec::with_capacity(ClockData::STRUCT_SIZE);
+        push_u32(&mut data, self.endian, 1);
+        push_u32(&mut data, self.endian, clock_data.clockid);
+        push_u64(&mut data, self.endian, clock_data.wall_clock_ns);
+        push_u64(&mut data, self.endian, clock_data.clockid_time_ns);
+        self.set_raw(Feature::CLOCK_DATA, data)
+    }
+
+    /// Sets the contents of a feature section which doesn't have a typed
+    /// setter. The data must be in the builder's byte order.
+    pub fn set_raw(&mut self, feature: Feature, data: Vec<u8>) -> &mut Self {
+        match self
+            .sections
+            .binary_search_by_key(&feature.0, |(feature, _)| feature.0)
+        {
+            Ok(index) => self.sections[index].1 = data,
+            Err(index) => self.sections.insert(index, (feature, data)),
+        }
+        self
+    }
+
+    /// Removes the section for `feature`, if it was set.
+    pub fn remove(&mut self, feature: Feature) -> &mut Self {
+        self.sections.retain(|(f, _)| *f != feature);
+        self
+    }
+
+    /// Returns the contents of the section for `feature`, if it was set.
+    pub fn get(&self, feature: Feature) -> Option<&[u8]> {
+        self.sections
+            .iter()
+            .find(|(f, _)| *f == feature)
+            .map(|(_, data)| &data[..])
+    }
+
+    /// The sections, ordered by feature.
+    pub fn sections(&self) -> impl Iterator<Item = (Feature, &[u8])> + '_ {
+        self.sections
+            .iter()
+            .map(|(feature, data)| (*feature, &data[..]))
+    }
+
+    /// The sections, ordered by feature.
+    pub fn into_sections(self) -> Vec<(Feature, Vec<u8>)> {
+        self.sections
+    }
+
+    /// For features whose section is just a perf_header_string.
+    fn set_string(&mut self, feature: Feature, s: &str) -> &mut Self {
+        let mut data = Vec::new();
+        push_header_string(&mut data, self.endian, s.as_bytes());
+        self.set_raw(feature, data)
+    }
+}
+
+/// One event in the `EVENT_DESC` section.
+pub(crate) struct EventDescEntry<'a> {
+    /// The encoded `perf_event_attr`.
+    pub attr_bytes: &'a [u8],
+    pub name: &'a str,
+    pub event_ids: &'a [u64],
+}
+
+/// Encodes the contents of the `EVENT_DESC` feature section, in the format
+/// which [`AttributeDescription::parse_event_desc_section`] parses.
+pub(crate) fn event_desc_section(endian: Endianness, entries: &[EventDescEntry]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let attr_size = entries.first().map_or(0, |entry| entry.attr_bytes.len());
+    push_u32(&mut data, endian, entries.len() as u32);
+    push_u32(&mut data, endian, attr_size as u32);
+    for entry in entries {
+        data.extend_from_slice(entry.attr_bytes);
+        push_u32(&mut data, endian, entry.event_ids.len() as u32);
+        push_header_string(&mut data, endian, entry.name.as_bytes());
+        for id in entry.event_ids {
+            push_u64(&mut data, endian, *id);
+        }
+    }
+    data
+}
+
+/// Appends a `perf_header_string`: the length, and the nul-terminated string
+/// padded to a multiple of 64 bytes, like perf does.
+fn push_header_string(out: &mut Vec<u8>, endian: Endianness, s: &[u8]) {
+    let len = (s.len() + 1).next_multiple_of(64);
+    push_u32(out, endian, len as u32);
+    out.extend_from_slice(s);
+    out.resize(out.len() + len - s.len(), 0);
+}
+
+fn push_u32(out: &mut Vec<u8>, endian: Endianness, value: u32) {
+    match endian {
+        Endianness::LittleEndian => out.extend_from_slice(&value.to_le_bytes()),
+        Endianness::BigEndian => out.extend_from_slice(&value.to_be_bytes()),
+    }
+}
+
+fn push_u64(out: &mut Vec<u8>, endian: Endianness, value: u64) {
+    match endian {
+        Endianness::LittleEndian => out.extend_from_slice(&value.to_le_bytes()),
+        Endianness::BigEndian => out.extend_from_slice(&value.to_be_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use linear_map::LinearMap;
+    use linux_perf_event_reader::{Endianness, SoftwareCounterType};
+
+    use super::FeatureSectionsBuilder;
+    use crate::file_writer::test::test_attr;
+    use crate::{
+        AttributeDescription, ClockData, FeatureValue, GroupDesc, NrCpus, PerfFileReader,
+        PerfFileWriter, PmuMappings, SampleTimeRange,
+    };
+
+    #[test]
+    fn round_trip() {
+        for endian in [Endianness::LittleEndian, Endianness::BigEndian] {
+            let attributes: Vec<_> = [
+                ("cycles", SoftwareCounterType::CpuClock, 1),
+                ("faults", SoftwareCounterType::PageFaults, 2),
+            ]
+            .into_iter()
+            .map(|(name, counter_type, id)| AttributeDescription {
+                attr: test_attr(counter_type),
+                name: Some(name.to_owned()),
+                event_ids: vec![id],
+                group: None,
+                is_group_leader: false,
+            })
+            .collect();
+            let mut pmu_mappings = LinearMap::new();
+            pmu_mappings.insert(1, "software".to_owned());
+            pmu_mappings.insert(4, "cpu".to_owned());
+
+            let mut builder = FeatureSectionsBuilder::new(endian);
+            builder
+                .set_hostname("myhost")
+                .set_os_release("6.1.0")
+                .set_perf_version("6.1")
+                .set_arch("x86_64")
+                .set_cpu_desc("Some CPU")
+                .set_cpu_id("GenuineIntel,6,85,4")
+                .set_cmdline(&["/usr/bin/perf", "record", "-g"])
+                .set_nr_cpus(NrCpus {
+                    nr_cpus_available: 8,
+                    nr_cpus_online: 4,
+                })
+                .set_total_mem(16 * 1024 * 1024)
+                .set_group_descs(&[GroupDesc {
+                    name: None,
+                    leader_index: 0,
+                    member_count: 2,
+                }])
+                .set_pmu_mappings(&PmuMappings(pmu_mappings.clone()))
+                .set_sample_time(SampleTimeRange {
+                    first_sample_time: 10,
+                    last_sample_time: 20,
+                })
+                .set_clock_data(ClockData {
+                    clockid: 1,
+                    wall_clock_ns: 1_700_000_000_000_000_000,
+                    clockid_time_ns: 5_000_000_000,
+                })
+                .set_event_desc(&attributes)
+                .unwrap();
+
+            let mut writer = PerfFileWriter::new(Cursor::new(Vec::new()), endian).unwrap();
+            writer.add_feature_sections(builder).unwrap();
+            let file = writer.finish().unwrap().into_inner();
+            let perf_file = PerfFileReader::parse_file(Cursor::new(&file[..]))
+                .unwrap()
+                .perf_file;
+
+            assert_eq!(perf_file.hostname().unwrap(), Some("myhost"));
+            assert_eq!(perf_file.os_release().unwrap(), Some("6.1.0"));
+            assert_eq!(perf_file.perf_version().unwrap(), Some("6.1"));
+            assert_eq!(perf_file.arch().unwrap(), Some("x86_64"));
+            assert_eq!(perf_file.cpu_desc().unwrap(), Some("Some CPU"));
+            assert_eq!(perf_file.cpu_id().unwrap(), Some("GenuineIntel,6,85,4"));
+            assert_eq!(
+                perf_file.cmdline().unwrap(),
+                Some(vec!["/usr/bin/perf", "record", "-g"])
+            );
+            let nr_cpus = perf_file.nr_cpus().unwrap().unwrap();
+            assert_eq!((nr_cpus.nr_cpus_available, nr_cpus.nr_cpus_online), (8, 4));
+            assert_eq!(perf_file.total_mem().unwrap(), Some(16 * 1024 * 1024));
+            assert_eq!(
+                perf_file.pmu_mappings().unwrap(),
+                Some(PmuMappings(pmu_mappings))
+            );
+            assert_eq!(
+                perf_file.sample_time_range().unwrap(),
+                Some(SampleTimeRange {
+                    first_sample_time: 10,
+                    last_sample_time: 20,
+                })
+            );
+            assert_eq!(
+                perf_file.clock_data().unwrap().map(|c| c.clockid_time_ns),
+                Some(5_000_000_000)
+            );
+            for feature in perf_file.features() {
+                let value = perf_file.parsed_feature(feature).unwrap();
+                assert!(
+                    !matches!(value, None | Some(FeatureValue::Raw(_))),
+                    "{feature} wasn't parsed"
+                );
+            }
+            let attrs = perf_file.event_attributes();
+            assert_eq!(attrs.len(), 2);
+            assert_eq!(attrs[1].name(), Some("faults"));
+            assert_eq!(attrs[1].ids(), &[2]);
+            assert_eq!(attrs[1].group_leader_index(), Some(0));
+            assert!(attrs[0].is_group_leader());
+            let groups = perf_file.event_groups().unwrap();
+            assert_eq!(groups.len(), 1);
+            assert_eq!(groups[0].name(), None);
+            assert_eq!(groups[0].member_indexes(), 0..2);
+            assert_eq!(groups[0].leader().name(), Some("cycles"));
+            assert_eq!(groups[0].members[1].name(), Some("faults"));
+        }
+    }
+}
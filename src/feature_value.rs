@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use crate::simpleperf::{SimpleperfDebugUnwindFile, SimpleperfFileRecord};
+use crate::tracepoint::TracingData;
+use crate::{
+    AttributeDescription, CacheTopology, ClockData, CompressionInfo, CpuTopology, DsoInfo, DsoKey,
+    GroupDesc, HybridTopology, NrCpus, NumaTopology, PmuCaps, PmuMappings, SampleTimeRange,
+};
+
+/// The parsed contents of a feature section, returned by
+/// [`PerfFile::parsed_feature`](crate::PerfFile::parsed_feature).
+///
+/// This lets generic tools display every feature of a file without calling
+/// the individual accessor for each feature.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum FeatureValue<'a> {
+    /// A single string, e.g. for `HOSTNAME`, `OSRELEASE`, `VERSION`, `ARCH`,
+    /// `CPUDESC` and `CPUID`.
+    String(&'a str),
+    /// A list of strings, e.g. the `CMDLINE` arguments.
+    StringList(Vec<&'a str>),
+    /// `NRCPUS`
+    NrCpus(NrCpus),
+    /// `TOTAL_MEM`, in kB.
+    TotalMem(u64),
+    /// `CPU_TOPOLOGY`
+    CpuTopology(CpuTopology),
+    /// `HYBRID_TOPOLOGY`
+    HybridTopology(HybridTopology),
+    /// `NUMA_TOPOLOGY`
+    NumaTopology(NumaTopology),
+    /// `CACHE`
+    CacheTopology(CacheTopology),
+    /// `COMPRESSED`
+    CompressionInfo(CompressionInfo),
+    /// A feature without data, whose presence is the information, e.g.
+    /// `BRANCH_STACK` or `STAT`.
+    Flag,
+    /// `CLOCKID`: the resolution of the clock, in nanoseconds.
+    ClockResolutionNs(u64),
+    /// `DIR_FORMAT`: the version of the `perf record --threads` directory
+    /// layout.
+    DirFormatVersion(u64),
+    /// `CLOCK_DATA`
+    ClockData(ClockData),
+    /// `CPU_PMU_CAPS`
+    PmuCaps(PmuCaps),
+    /// `HYBRID_CPU_PMU_CAPS`
+    HybridPmuCaps(Vec<PmuCaps>),
+    /// `BUILD_ID`
+    BuildIds(HashMap<DsoKey, DsoInfo>),
+    /// `SAMPLE_TIME`
+    SampleTimeRange(SampleTimeRange),
+    /// `PMU_MAPPINGS`
+    PmuMappings(PmuMappings),
+    /// `TRACING_DATA`
+    TracingData(TracingData),
+    /// `EVENT_DESC`: the event attributes, which are parsed when the file is
+    /// opened.
+    EventDesc(&'a [AttributeDescription]),
+    /// `GROUP_DESC`: the event groups. They are also attached to the event
+    /// attributes, see [`AttributeDescription::group`].
+    GroupDesc(Vec<GroupDesc>),
+    /// Simpleperf's `META_INFO` key-value map.
+    SimpleperfMetaInfo(HashMap<&'a str, &'a str>),
+    /// Simpleperf's `FILE` or `FILE2` symbol tables.
+    SimpleperfSymbolTables(Vec<SimpleperfFileRecord>),
+    /// Simpleperf's `DEBUG_UNWIND` list of files for offline unwinding. The
+    /// file contents are in the `DEBUG_UNWIND_FILE` section, which is
+    /// returned as [`FeatureValue::Raw`].
+    SimpleperfDebugUnwindFiles(Vec<SimpleperfDebugUnwindFile>),
+    /// A feature which this crate doesn't parse, with the raw section data.
+    Raw(&'a [u8]),
+}
@@ -92,46 +92,66 @@ impl Feature {
     pub const SIMPLEPERF_FILE2: Self = Self(HEADER_SIMPLEPERF_FILE2);
 }
 
+impl Feature {
+    /// The name of this feature, e.g. `"BUILD_ID"`, or `None` if this crate
+    /// doesn't know the feature.
+    ///
+    /// Unknown features, e.g. from a newer version of perf, are still
+    /// represented as a `Feature` with their raw bit number.
+    pub fn name(&self) -> Option<&'static str> {
+        let name = match *self {
+            Self::TRACING_DATA => "TRACING_DATA",
+            Self::BUILD_ID => "BUILD_ID",
+            Self::HOSTNAME => "HOSTNAME",
+            Self::OSRELEASE => "OSRELEASE",
+            Self::VERSION => "VERSION",
+            Self::ARCH => "ARCH",
+            Self::NRCPUS => "NRCPUS",
+            Self::CPUDESC => "CPUDESC",
+            Self::CPUID => "CPUID",
+            Self::TOTAL_MEM => "TOTAL_MEM",
+            Self::CMDLINE => "CMDLINE",
+            Self::EVENT_DESC => "EVENT_DESC",
+            Self::CPU_TOPOLOGY => "CPU_TOPOLOGY",
+            Self::NUMA_TOPOLOGY => "NUMA_TOPOLOGY",
+            Self::BRANCH_STACK => "BRANCH_STACK",
+            Self::PMU_MAPPINGS => "PMU_MAPPINGS",
+            Self::GROUP_DESC => "GROUP_DESC",
+            Self::AUXTRACE => "AUXTRACE",
+            Self::STAT => "STAT",
+            Self::CACHE => "CACHE",
+            Self::SAMPLE_TIME => "SAMPLE_TIME",
+            Self::SAMPLE_TOPOLOGY => "SAMPLE_TOPOLOGY",
+            Self::CLOCKID => "CLOCKID",
+            Self::DIR_FORMAT => "DIR_FORMAT",
+            Self::BPF_PROG_INFO => "BPF_PROG_INFO",
+            Self::BPF_BTF => "BPF_BTF",
+            Self::COMPRESSED => "COMPRESSED",
+            Self::CPU_PMU_CAPS => "CPU_PMU_CAPS",
+            Self::CLOCK_DATA => "CLOCK_DATA",
+            Self::HYBRID_TOPOLOGY => "HYBRID_TOPOLOGY",
+            Self::HYBRID_CPU_PMU_CAPS => "HYBRID_CPU_PMU_CAPS",
+            Self::SIMPLEPERF_FILE => "SIMPLEPERF_FILE",
+            Self::SIMPLEPERF_META_INFO => "SIMPLEPERF_META_INFO",
+            Self::SIMPLEPERF_DEBUG_UNWIND => "SIMPLEPERF_DEBUG_UNWIND",
+            Self::SIMPLEPERF_DEBUG_UNWIND_FILE => "SIMPLEPERF_DEBUG_UNWIND_FILE",
+            Self::SIMPLEPERF_FILE2 => "SIMPLEPERF_FILE2",
+            _ => return None,
+        };
+        Some(name)
+    }
+
+    /// Whether this crate knows this feature.
+    pub fn is_known(&self) -> bool {
+        self.name().is_some()
+    }
+}
+
 impl fmt::Display for Feature {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
-            Self::TRACING_DATA => "TRACING_DATA".fmt(f),
-            Self::BUILD_ID => "BUILD_ID".fmt(f),
-            Self::HOSTNAME => "HOSTNAME".fmt(f),
-            Self::OSRELEASE => "OSRELEASE".fmt(f),
-            Self::VERSION => "VERSION".fmt(f),
-            Self::ARCH => "ARCH".fmt(f),
-            Self::NRCPUS => "NRCPUS".fmt(f),
-            Self::CPUDESC => "CPUDESC".fmt(f),
-            Self::CPUID => "CPUID".fmt(f),
-            Self::TOTAL_MEM => "TOTAL_MEM".fmt(f),
-            Self::CMDLINE => "CMDLINE".fmt(f),
-            Self::EVENT_DESC => "EVENT_DESC".fmt(f),
-            Self::CPU_TOPOLOGY => "CPU_TOPOLOGY".fmt(f),
-            Self::NUMA_TOPOLOGY => "NUMA_TOPOLOGY".fmt(f),
-            Self::BRANCH_STACK => "BRANCH_STACK".fmt(f),
-            Self::PMU_MAPPINGS => "PMU_MAPPINGS".fmt(f),
-            Self::GROUP_DESC => "GROUP_DESC".fmt(f),
-            Self::AUXTRACE => "AUXTRACE".fmt(f),
-            Self::STAT => "STAT".fmt(f),
-            Self::CACHE => "CACHE".fmt(f),
-            Self::SAMPLE_TIME => "SAMPLE_TIME".fmt(f),
-            Self::SAMPLE_TOPOLOGY => "SAMPLE_TOPOLOGY".fmt(f),
-            Self::CLOCKID => "CLOCKID".fmt(f),
-            Self::DIR_FORMAT => "DIR_FORMAT".fmt(f),
-            Self::BPF_PROG_INFO => "BPF_PROG_INFO".fmt(f),
-            Self::BPF_BTF => "BPF_BTF".fmt(f),
-            Self::COMPRESSED => "COMPRESSED".fmt(f),
-            Self::CPU_PMU_CAPS => "CPU_PMU_CAPS".fmt(f),
-            Self::CLOCK_DATA => "CLOCK_DATA".fmt(f),
-            Self::HYBRID_TOPOLOGY => "HYBRID_TOPOLOGY".fmt(f),
-            Self::HYBRID_CPU_PMU_CAPS => "HYBRID_CPU_PMU_CAPS".fmt(f),
-            Self::SIMPLEPERF_FILE => "SIMPLEPERF_FILE".fmt(f),
-            Self::SIMPLEPERF_META_INFO => "SIMPLEPERF_META_INFO".fmt(f),
-            Self::SIMPLEPERF_DEBUG_UNWIND => "SIMPLEPERF_DEBUG_UNWIND".fmt(f),
-            Self::SIMPLEPERF_DEBUG_UNWIND_FILE => "SIMPLEPERF_DEBUG_UNWIND_FILE".fmt(f),
-            Self::SIMPLEPERF_FILE2 => "SIMPLEPERF_FILE2".fmt(f),
-            _ => f.write_fmt(format_args!("Unknown Feature {}", &self.0)),
+        match self.name() {
+            Some(name) => name.fmt(f),
+            None => f.write_fmt(format_args!("Unknown Feature {}", &self.0)),
         }
     }
 }
@@ -184,6 +204,23 @@ impl FeatureSet {
         let features_chunk = self.0[features_chunk_index];
         (features_chunk & (1 << feature_bit)) != 0
     }
+
+    /// Adds the feature to this set. Features which don't fit into the set
+    /// are ignored.
+    pub(crate) fn insert(&mut self, feature: Feature) {
+        if feature.0 < Self::MAX_BITS {
+            self.0[(feature.0 / 64) as usize] |= 1 << (feature.0 % 64);
+        }
+    }
+}
+
+impl IntoIterator for FeatureSet {
+    type Item = Feature;
+    type IntoIter = FeatureSetIter;
+
+    fn into_iter(self) -> FeatureSetIter {
+        self.iter()
+    }
 }
 
 impl fmt::Debug for FeatureSet {
@@ -197,10 +234,12 @@ impl fmt::Debug for FeatureSet {
 }
 
 /// An iterator over all the features that are included in a [`FeatureSet`],
-/// ordered from low to high feature bit.
+/// ordered from low to high feature bit, including the features which this
+/// crate doesn't know (see [`Feature::is_known`]).
 ///
 /// The iteration order is the order in which the feature sections are stored
 /// in a perf.data file.
+#[derive(Debug, Clone)]
 pub struct FeatureSetIter {
     current_feature: Feature,
     set: FeatureSet,
@@ -221,3 +260,24 @@ impl Iterator for FeatureSetIter {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Feature, FeatureSet};
+
+    #[test]
+    fn iterate_known_and_unknown_features() {
+        let set = FeatureSet([1 << 2, 0, 1, 1 << 8]);
+        let features: Vec<Feature> = set.into_iter().collect();
+        assert_eq!(
+            features,
+            vec![Feature::BUILD_ID, Feature::SIMPLEPERF_FILE, Feature(200)]
+        );
+        assert_eq!(Feature::BUILD_ID.name(), Some("BUILD_ID"));
+        assert!(!Feature(200).is_known());
+        assert_eq!(
+            format!("{:?}", set),
+            "{BUILD_ID, SIMPLEPERF_FILE, Unknown Feature 200}"
+        );
+    }
+}
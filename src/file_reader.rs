@@ -1,23 +1,43 @@
-use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
 use linear_map::LinearMap;
+use linux_perf_event_reader::constants::PERF_RECORD_AUX_OUTPUT_HW_ID;
 use linux_perf_event_reader::{
     get_record_id, get_record_identifier, get_record_timestamp, AttrFlags, Endianness,
     PerfEventHeader, RawData, RawEventRecord, RecordIdParseInfo, RecordParseInfo, RecordType,
     SampleFormat,
 };
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
 
+use super::custom_record::CustomRecordTypes;
+#[cfg(feature = "zstd")]
+use super::decompression::Decompressor;
 use super::error::{Error, ReadError};
-use super::feature_sections::AttributeDescription;
-use super::features::Feature;
+use super::event_update::{EventUpdate, EventUpdateType};
+#[cfg(feature = "zstd")]
+use super::feature_sections::CompressionInfo;
+use super::feature_sections::{AttributeDescription, GroupDesc, SampleTimeRange};
+use super::features::{Feature, FeatureSet};
 use super::header::PerfHeader;
+use super::id_index::IdIndex;
+use super::index::{IndexBuilder, PerfFileIndex};
 use super::perf_file::PerfFile;
-use super::record::{PerfFileRecord, RawUserRecord, UserRecordType};
+use super::pipe_reader::{parse_feature_record, read_pipe_metadata, PIPE_HEADER_SIZE};
+use super::push_parser::{PerfRecordPushParser, PushBuffer};
+use super::record::{OwnedPerfFileRecord, PerfFileRecord, RawUserRecord, UserRecordType};
+use super::record_body::RecordBody;
 use super::section::PerfFileSection;
-use super::simpleperf;
+use super::simpleperf::{self, SimpleperfEventIdRecord};
 use super::sorter::Sorter;
+use super::stat::StatMetadata;
+use super::tracepoint::TracingData;
+
+/// The default size limit for feature sections which are read into memory
+/// when a file is parsed, see
+/// [`PerfFileReader::parse_file_with_max_feature_section_size`].
+pub const DEFAULT_MAX_FEATURE_SECTION_SIZE: u64 = 256 * 1024 * 1024;
 
 /// A parser for the perf.data file format.
 ///
@@ -36,7 +56,7 @@ use super::sorter::Sorter;
 ///
 /// while let Some(record) = record_iter.next_record(&mut perf_file)? {
 ///     match record {
-///         PerfFileRecord::EventRecord { attr_index, record } => {
+///         PerfFileRecord::EventRecord { attr_index, record, .. } => {
 ///             let record_type = record.record_type;
 ///             let parsed_record = record.parse()?;
 ///             println!("{:?} for event {}: {:?}", record_type, attr_index, parsed_record);
@@ -57,15 +77,34 @@ pub struct PerfFileReader<R: Read> {
 }
 
 impl<C: Read + Seek> PerfFileReader<C> {
-    pub fn parse_file(mut cursor: C) -> Result<Self, Error> {
+    pub fn parse_file(cursor: C) -> Result<Self, Error> {
+        Self::parse_file_with_max_feature_section_size(cursor, DEFAULT_MAX_FEATURE_SECTION_SIZE)
+    }
+
+    /// Like [`PerfFileReader::parse_file`], but only feature sections of up
+    /// to `max_section_size` bytes are read into memory. Larger sections are
+    /// not buffered: [`PerfFile::feature_section_data`] returns `None` for
+    /// them, and they can be read with [`PerfFile::feature_section_reader`]
+    /// instead. [`PerfFileReader::parse_file`] uses
+    /// [`DEFAULT_MAX_FEATURE_SECTION_SIZE`].
+    pub fn parse_file_with_max_feature_section_size(
+        mut cursor: C,
+        max_section_size: u64,
+    ) -> Result<Self, Error> {
         let header = PerfHeader::parse(&mut cursor)?;
         match &header.magic {
-            b"PERFILE2" => {
-                Self::parse_file_impl::<LittleEndian>(cursor, header, Endianness::LittleEndian)
-            }
-            b"2ELIFREP" => {
-                Self::parse_file_impl::<BigEndian>(cursor, header, Endianness::BigEndian)
-            }
+            b"PERFILE2" => Self::parse_file_impl::<LittleEndian>(
+                cursor,
+                header,
+                Endianness::LittleEndian,
+                max_section_size,
+            ),
+            b"2ELIFREP" => Self::parse_file_impl::<BigEndian>(
+                cursor,
+                header,
+                Endianness::BigEndian,
+                max_section_size,
+            ),
             _ => Err(Error::UnrecognizedMagicValue(header.magic)),
         }
     }
@@ -74,12 +113,17 @@ impl<C: Read + Seek> PerfFileReader<C> {
         mut cursor: C,
         header: PerfHeader,
         endian: Endianness,
+        max_section_size: u64,
     ) -> Result<Self, Error>
     where
         T: ByteOrder,
     {
         // Read the section information for each feature, starting just after the data section.
-        let feature_pos = header.data_section.offset + header.data_section.size;
+        let feature_pos = header
+            .data_section
+            .offset
+            .checked_add(header.data_section.size)
+            .ok_or(ReadError::PerfHeader)?;
         cursor.seek(SeekFrom::Start(feature_pos))?;
         let mut feature_sections_info = Vec::new();
         for feature in header.features.iter() {
@@ -88,12 +132,20 @@ impl<C: Read + Seek> PerfFileReader<C> {
         }
 
         let mut feature_sections = LinearMap::new();
+        let mut feature_section_locations = LinearMap::new();
         for (feature, section) in feature_sections_info {
-            let offset = section.offset;
-            let size = usize::try_from(section.size).map_err(|_| Error::SectionSizeTooBig)?;
-            let mut data = vec![0; size];
-            cursor.seek(SeekFrom::Start(offset))?;
-            cursor.read_exact(&mut data)?;
+            feature_section_locations.insert(feature, section);
+            // Large sections are not buffered. They can still be read with
+            // PerfFile::feature_section_reader.
+            if section.size > max_section_size {
+                continue;
+            }
+            let mut data = Vec::new();
+            cursor.seek(SeekFrom::Start(section.offset))?;
+            (&mut cursor).take(section.size).read_to_end(&mut data)?;
+            if (data.len() as u64) < section.size {
+                return Err(ReadError::FeatureSection.into());
+            }
             feature_sections.insert(feature, data);
         }
 
@@ -128,6 +180,145 @@ impl<C: Read + Seek> PerfFileReader<C> {
                 )?
             };
 
+        let raw_attrs = read_raw_attrs(&mut cursor, &header, attributes.len(), max_section_size)?;
+
+        // Move the cursor to the start of the data section so that we can start
+        // reading records from it.
+        cursor.seek(SeekFrom::Start(header.data_section.offset))?;
+
+        Self::from_parts::<T>(
+            cursor,
+            header,
+            endian,
+            feature_sections,
+            feature_section_locations,
+            attributes,
+            raw_attrs,
+        )
+    }
+
+    /// The timestamps of the first and the last sample in this file.
+    ///
+    /// This uses the `SAMPLE_TIME` feature section if the file has one. For
+    /// files without it, e.g. files from `perf record -o -` or from
+    /// simpleperf, the range is computed with
+    /// [`PerfRecordIter::scan_sample_time_range`].
+    pub fn sample_time_range(&mut self) -> Result<Option<SampleTimeRange>, Error> {
+        if self.perf_file.features().has_feature(Feature::SAMPLE_TIME) {
+            return self.perf_file.sample_time_range();
+        }
+        self.record_iter.scan_sample_time_range()
+    }
+}
+
+impl<R: Read> PerfFileReader<R> {
+    /// Parses a perf.data stream in pipe mode, i.e. the format which
+    /// `perf record -o -` writes to stdout, and which
+    /// [`PerfPipeWriter`](crate::PerfPipeWriter) writes.
+    ///
+    /// A pipe-mode stream has no attr section and no feature sections. The
+    /// attributes, the feature sections and the tracing data are read from
+    /// the `HEADER_ATTR`, `HEADER_FEATURE` and `HEADER_TRACING_DATA` records
+    /// at the start of the stream instead. `HEADER_FEATURE` records which
+    /// come later are added to the [`PerfFile`] when
+    /// [`PerfRecordIter::next_record`] returns them. `HEADER_ATTR` records
+    /// after the first event record are returned, but their attributes aren't
+    /// used to parse the records.
+    ///
+    /// The stream doesn't declare its length, so records are read until the
+    /// reader ends. The scans which need the size of the data section, like
+    /// [`PerfRecordIter::build_index`], aren't supported for streams.
+    pub fn parse_pipe(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        match &magic {
+            b"PERFILE2" => {
+                Self::parse_pipe_impl::<LittleEndian>(reader, magic, Endianness::LittleEndian)
+            }
+            b"2ELIFREP" => Self::parse_pipe_impl::<BigEndian>(reader, magic, Endianness::BigEndian),
+            _ => Err(Error::UnrecognizedMagicValue(magic)),
+        }
+    }
+
+    fn parse_pipe_impl<T: ByteOrder>(
+        mut reader: R,
+        magic: [u8; 8],
+        endian: Endianness,
+    ) -> Result<Self, Error> {
+        let metadata = read_pipe_metadata::<_, T>(&mut reader)?;
+        let mut features = FeatureSet([0; 4]);
+        for feature in metadata.feature_sections.keys() {
+            features.insert(*feature);
+        }
+        let empty_section = PerfFileSection { offset: 0, size: 0 };
+        let header = PerfHeader {
+            magic,
+            header_size: PIPE_HEADER_SIZE,
+            attr_size: 0,
+            attr_section: empty_section,
+            data_section: PerfFileSection {
+                offset: metadata.len,
+                size: 0,
+            },
+            event_types_section: empty_section,
+            features,
+        };
+        let mut file_reader = Self::from_parts::<T>(
+            reader,
+            header,
+            endian,
+            metadata.feature_sections,
+            LinearMap::new(),
+            metadata.attributes,
+            metadata.raw_attrs,
+        )?;
+        let record_iter = &mut file_reader.record_iter;
+        match metadata.next_record_header {
+            Some(header) => {
+                record_iter.partial_record.header = header;
+                record_iter.partial_record.header_len = header.len();
+                record_iter.set_data_len(u64::MAX, false);
+                record_iter.reads_until_end_of_stream = true;
+            }
+            None => record_iter.set_data_len(0, true),
+        }
+        Ok(file_reader)
+    }
+
+    /// Creates the reader from the parsed file header, feature sections and
+    /// attributes. `reader` must be positioned at the start of the data
+    /// section.
+    fn from_parts<T: ByteOrder>(
+        reader: R,
+        header: PerfHeader,
+        endian: Endianness,
+        feature_sections: LinearMap<Feature, Vec<u8>>,
+        feature_section_locations: LinearMap<Feature, PerfFileSection>,
+        mut attributes: Vec<AttributeDescription>,
+        raw_attrs: Vec<Vec<u8>>,
+    ) -> Result<Self, Error> {
+        if let Some(group_desc_section) = feature_sections.get(&Feature::GROUP_DESC) {
+            let groups = GroupDesc::parse_section::<_, T>(&group_desc_section[..])?;
+            AttributeDescription::apply_group_descs(&mut attributes, &groups);
+        }
+
+        if attributes
+            .iter()
+            .any(AttributeDescription::is_unnamed_tracepoint)
+        {
+            // A broken TRACING_DATA section only means that the names stay
+            // unknown; PerfFile::tracing_data reports the error.
+            if let Some(Ok(tracing_data)) = feature_sections
+                .get(&Feature::TRACING_DATA)
+                .map(|section| TracingData::parse(section))
+            {
+                AttributeDescription::apply_tracepoint_names(&mut attributes, &tracing_data);
+            }
+        }
+
+        #[cfg(feature = "zstd")]
+        let max_decompressed_size = max_decompressed_size::<T>(&feature_sections);
+
         let mut event_id_to_attr_index = HashMap::new();
         for (attr_index, AttributeDescription { event_ids, .. }) in attributes.iter().enumerate() {
             for event_id in event_ids {
@@ -140,19 +331,25 @@ impl<C: Read + Seek> PerfFileReader<C> {
             .map(|attr| RecordParseInfo::new(&attr.attr, endian))
             .collect();
 
-        let first_attr = attributes.first().ok_or(Error::NoAttributes)?;
-
-        let first_has_sample_id_all = first_attr.attr.flags.contains(AttrFlags::SAMPLE_ID_ALL);
-        let (first_parse_info, remaining_parse_infos) = parse_infos.split_first().unwrap();
-
-        let id_parse_infos = if remaining_parse_infos.is_empty() {
+        let id_parse_infos = if parse_infos.is_empty() {
+            // Files from `perf stat record` can come without attributes, if
+            // the events are only described by EVENT_UPDATE and STAT_CONFIG
+            // records. Such files don't contain samples, so they can still be
+            // read; event records cause an error when they're encountered.
+            if !header.features.has_feature(Feature::STAT) {
+                return Err(Error::NoAttributes);
+            }
             IdParseInfos::OnlyOneEvent
-        } else if remaining_parse_infos
+        } else if parse_infos.len() == 1 {
+            IdParseInfos::OnlyOneEvent
+        } else if parse_infos
             .iter()
-            .all(|parse_info| parse_info.id_parse_info == first_parse_info.id_parse_info)
+            .all(|parse_info| parse_info.id_parse_info == parse_infos[0].id_parse_info)
         {
-            IdParseInfos::Same(first_parse_info.id_parse_info)
+            IdParseInfos::Same(parse_infos[0].id_parse_info)
         } else {
+            let first_has_sample_id_all =
+                attributes[0].attr.flags.contains(AttrFlags::SAMPLE_ID_ALL);
             // Make sure that all attributes have IDENTIFIER and the same SAMPLE_ID_ALL setting.
             // Otherwise we won't be able to know which attr a record belongs to; we need to know
             // the record's ID for that, and we can only read the ID if it's in the same location
@@ -173,28 +370,51 @@ impl<C: Read + Seek> PerfFileReader<C> {
             IdParseInfos::PerAttribute(first_has_sample_id_all)
         };
 
-        // Move the cursor to the start of the data section so that we can start
-        // reading records from it.
-        cursor.seek(SeekFrom::Start(header.data_section.offset))?;
-
         let perf_file = PerfFile {
             endian,
+            header,
             features: header.features,
             feature_sections,
+            feature_section_locations,
             attributes,
+            event_id_to_attr_index: event_id_to_attr_index.clone(),
+            id_index_entries: HashMap::new(),
+            raw_attrs,
+            record_build_id_events: Vec::new(),
         };
 
         let record_iter = PerfRecordIter {
-            reader: cursor,
+            reader,
             endian,
             id_parse_infos,
             parse_infos,
             event_id_to_attr_index,
+            data_section_offset: header.data_section.offset,
             read_offset: 0,
+            data_section_size: header.data_section.size,
             record_data_len: header.data_section.size,
+            time_range_filter: None,
+            record_type_filter: None,
+            filtered_perf_file_updates: Vec::new(),
+            attr_index_filter: None,
+            trust_file_order: false,
+            untrusted_input: false,
+            data_is_complete: true,
+            following: false,
+            reads_until_end_of_stream: false,
             sorter: Sorter::new(),
             buffers_for_recycling: VecDeque::new(),
-            current_event_body: Vec::new(),
+            current_event_body: RecordBody::default(),
+            partial_record: PartialRecord::default(),
+            #[cfg(feature = "zstd")]
+            decompressor: None,
+            #[cfg(feature = "zstd")]
+            compressed_record_offset: 0,
+            #[cfg(feature = "zstd")]
+            max_decompressed_size,
+            split_record: None,
+            unknown_record_types: BTreeMap::new(),
+            custom_record_types: CustomRecordTypes::default(),
         };
 
         Ok(Self {
@@ -208,15 +428,69 @@ impl<C: Read + Seek> PerfFileReader<C> {
 pub struct PerfRecordIter<R: Read> {
     reader: R,
     endian: Endianness,
+    /// The file offset of the start of the data section.
+    data_section_offset: u64,
+    /// The offset of the next record, relative to the start of the data section.
     read_offset: u64,
+    /// The size of the data section.
+    data_section_size: u64,
+    /// The offset, relative to the start of the data section, at which reading stops.
     record_data_len: u64,
-    current_event_body: Vec<u8>,
+    /// If set, records with timestamps outside this range are dropped.
+    time_range_filter: Option<(u64, u64)>,
+    /// If set, only records of these types are returned.
+    record_type_filter: Option<Vec<RecordType>>,
+    /// Records which were dropped by the record type filter but whose
+    /// information still needs to be applied to the [`PerfFile`], see
+    /// [`PerfRecordIter::update_perf_file`].
+    filtered_perf_file_updates: Vec<PendingRecord>,
+    /// If set, only event records of the attributes with these indexes, and
+    /// user records, are returned.
+    attr_index_filter: Option<Vec<usize>>,
+    /// If true, records are emitted in file order without sorting.
+    trust_file_order: bool,
+    /// If true, inconsistencies which are usually tolerated are reported as
+    /// errors, see [`PerfRecordIter::set_untrusted_input`].
+    untrusted_input: bool,
+    /// False if more data can be appended to the data section, see
+    /// [`PerfRecordIter::set_data_len`].
+    data_is_complete: bool,
+    /// True if [`PerfRecordIter::follow`] has been called. Records which are
+    /// cut off at the end of the file are then waited for instead of being
+    /// reported as errors.
+    following: bool,
+    /// True for pipe-mode streams, whose length isn't known. The data ends
+    /// when the reader ends at a record boundary.
+    reads_until_end_of_stream: bool,
+    current_event_body: RecordBody,
+    /// The record which is currently being read.
+    partial_record: PartialRecord,
+    /// Created when the first `COMPRESSED` record is found.
+    #[cfg(feature = "zstd")]
+    decompressor: Option<Decompressor>,
+    /// The offset of the most recent `COMPRESSED` record. Used as the offset
+    /// of the records which are decompressed from it.
+    #[cfg(feature = "zstd")]
+    compressed_record_offset: u64,
+    /// The maximum size of the decompressed data of one `COMPRESSED` record,
+    /// see [`max_decompressed_size`].
+    #[cfg(feature = "zstd")]
+    max_decompressed_size: usize,
+    /// The offset of the first `SIMPLEPERF_SPLIT` record and the bytes of
+    /// the split record which have been read so far, until the
+    /// `SIMPLEPERF_SPLIT_END` record is found.
+    split_record: Option<(u64, Vec<u8>)>,
     id_parse_infos: IdParseInfos,
-    /// Guaranteed to have at least one element
+    /// Only empty for `perf stat record` files without attributes.
     parse_infos: Vec<RecordParseInfo>,
     event_id_to_attr_index: HashMap<u64, usize>,
-    sorter: Sorter<RecordSortKey, PendingRecord>,
+    /// Sorts by timestamp. Records without a timestamp, and records with the
+    /// same timestamp, stay in file order.
+    sorter: Sorter<Option<u64>, PendingRecord>,
     buffers_for_recycling: VecDeque<Vec<u8>>,
+    /// The record types which this crate doesn't know about, by numeric type.
+    unknown_record_types: BTreeMap<u32, UnknownRecordType>,
+    custom_record_types: CustomRecordTypes,
 }
 
 impl<R: Read> PerfRecordIter<R> {
@@ -226,163 +500,3287 @@ impl<R: Read> PerfRecordIter<R> {
     /// `next_record` does some internal buffering so that the sort order can
     /// be guaranteed. This buffering takes advantage of `FINISHED_ROUND`
     /// records so that we don't buffer more records than necessary.
+    ///
+    /// Records which simpleperf has split into `SIMPLEPERF_SPLIT` records
+    /// are returned as one record, with the offset of the first part.
     pub fn next_record(
         &mut self,
-        _perf_file: &mut PerfFile,
-    ) -> Result<Option<PerfFileRecord>, Error> {
+        perf_file: &mut PerfFile,
+    ) -> Result<Option<PerfFileRecord<'_>>, Error> {
         if !self.sorter.has_more() {
-            self.read_next_round()?;
+            self.read_available_round()?;
         }
+        self.apply_filtered_perf_file_updates(perf_file);
         if let Some(pending_record) = self.sorter.get_next() {
+            self.update_perf_file(perf_file, &pending_record);
             let record = self.convert_pending_record(pending_record);
             return Ok(Some(record));
         }
         Ok(None)
     }
 
-    /// Reads events into self.sorter until a FINISHED_ROUND record is found
-    /// and self.sorter is non-empty, or until we've run out of records to read.
-    fn read_next_round(&mut self) -> Result<(), Error> {
-        if self.endian == Endianness::LittleEndian {
-            self.read_next_round_impl::<byteorder::LittleEndian>()
-        } else {
-            self.read_next_round_impl::<byteorder::BigEndian>()
+    /// A non-blocking variant of [`PerfRecordIter::next_record`], for readers
+    /// which can return [`std::io::ErrorKind::WouldBlock`], such as a
+    /// non-blocking pipe or socket which receives the output of a live
+    /// `perf record` session.
+    ///
+    /// If the reader would block, this returns [`PollRecord::NeedMoreData`].
+    /// The bytes which were read so far are kept, so you can call this
+    /// method again once the reader has more data, and reading will continue
+    /// where it stopped.
+    pub fn poll_next_record(&mut self, perf_file: &mut PerfFile) -> Result<PollRecord<'_>, Error> {
+        if !self.sorter.has_more() {
+            match self.read_next_round() {
+                Ok(()) => {}
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    return Ok(PollRecord::NeedMoreData);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        self.apply_filtered_perf_file_updates(perf_file);
+        match self.sorter.get_next() {
+            Some(pending_record) => {
+                self.update_perf_file(perf_file, &pending_record);
+                Ok(PollRecord::Record(
+                    self.convert_pending_record(pending_record),
+                ))
+            }
+            None => Ok(PollRecord::EndOfData),
         }
     }
 
-    /// Reads events into self.sorter until a FINISHED_ROUND record is found
-    /// and self.sorter is non-empty, or until we've run out of records to read.
-    fn read_next_round_impl<T: ByteOrder>(&mut self) -> Result<(), Error> {
-        while self.read_offset < self.record_data_len {
-            let offset = self.read_offset;
-            let header = PerfEventHeader::parse::<_, T>(&mut self.reader)?;
-            let size = header.size as usize;
-            if size < PerfEventHeader::STRUCT_SIZE {
-                return Err(Error::InvalidPerfEventSize);
-            }
-            self.read_offset += u64::from(header.size);
+    /// Like [`PerfRecordIter::next_record`], but returns a record which owns
+    /// its body, so that it can be kept alive across iterations.
+    ///
+    /// The body is read into a buffer from the iterator's buffer pool if one
+    /// is available, see [`PerfRecordIter::provide_buffer`], and into a newly
+    /// allocated buffer otherwise.
+    pub fn next_owned_record(
+        &mut self,
+        perf_file: &mut PerfFile,
+    ) -> Result<Option<OwnedPerfFileRecord>, Error> {
+        if !self.sorter.has_more() {
+            self.read_available_round()?;
+        }
+        self.apply_filtered_perf_file_updates(perf_file);
+        Ok(self.sorter.get_next().map(|pending_record| {
+            self.update_perf_file(perf_file, &pending_record);
+            self.to_owned_record(pending_record)
+        }))
+    }
 
-            if UserRecordType::try_from(RecordType(header.type_))
-                == Some(UserRecordType::PERF_FINISHED_ROUND)
-            {
-                self.sorter.finish_round();
-                if self.sorter.has_more() {
-                    // The sorter is non-empty. We're done.
-                    return Ok(());
+    /// Like [`PerfRecordIter::next_owned_record`], but returns `Ok(None)` if
+    /// the reader would block before the next record can be emitted.
+    pub(crate) fn poll_next_owned_record(
+        &mut self,
+        perf_file: &mut PerfFile,
+    ) -> Result<Option<OwnedPerfFileRecord>, Error> {
+        if !self.sorter.has_more() {
+            match self.read_next_round() {
+                Ok(()) => {}
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    return Ok(None);
                 }
-
-                // Keep going so that we never exit the loop with sorter
-                // being empty, unless we've truly run out of data to read.
-                continue;
+                Err(e) => return Err(e),
             }
+        }
+        self.apply_filtered_perf_file_updates(perf_file);
+        Ok(self.sorter.get_next().map(|pending_record| {
+            self.update_perf_file(perf_file, &pending_record);
+            self.to_owned_record(pending_record)
+        }))
+    }
 
-            let event_body_len = size - PerfEventHeader::STRUCT_SIZE;
-            let mut buffer = self.buffers_for_recycling.pop_front().unwrap_or_default();
-            buffer.resize(event_body_len, 0);
-            self.reader
-                .read_exact(&mut buffer)
-                .map_err(|_| ReadError::PerfEventData)?;
+    /// Turns this iterator into a [`PerfRecordPushParser`], which is fed the
+    /// bytes of the data section by the caller instead of reading them from a
+    /// reader.
+    ///
+    /// The pushed bytes continue the data section at the current read
+    /// offset, i.e. at the start of the data section for a fresh iterator.
+    /// Bytes which the old reader hasn't returned yet are not read.
+    pub fn into_push_parser(self) -> PerfRecordPushParser {
+        PerfRecordPushParser::new(self.with_reader(PushBuffer::default()))
+    }
 
-            let data = RawData::from(&buffer[..]);
+    /// Replaces the reader. Everything else, including the partially read
+    /// record and the buffered records, is kept.
+    fn with_reader<R2: Read>(self, reader: R2) -> PerfRecordIter<R2> {
+        let Self {
+            reader: _,
+            endian,
+            data_section_offset,
+            read_offset,
+            data_section_size,
+            record_data_len,
+            time_range_filter,
+            record_type_filter,
+            filtered_perf_file_updates,
+            attr_index_filter,
+            trust_file_order,
+            untrusted_input,
+            data_is_complete,
+            following,
+            reads_until_end_of_stream,
+            current_event_body,
+            partial_record,
+            #[cfg(feature = "zstd")]
+            decompressor,
+            #[cfg(feature = "zstd")]
+            compressed_record_offset,
+            #[cfg(feature = "zstd")]
+            max_decompressed_size,
+            split_record,
+            id_parse_infos,
+            parse_infos,
+            event_id_to_attr_index,
+            sorter,
+            buffers_for_recycling,
+            unknown_record_types,
+            custom_record_types,
+        } = self;
+        PerfRecordIter {
+            reader,
+            endian,
+            data_section_offset,
+            read_offset,
+            data_section_size,
+            record_data_len,
+            time_range_filter,
+            record_type_filter,
+            filtered_perf_file_updates,
+            attr_index_filter,
+            trust_file_order,
+            untrusted_input,
+            data_is_complete,
+            following,
+            reads_until_end_of_stream,
+            current_event_body,
+            partial_record,
+            #[cfg(feature = "zstd")]
+            decompressor,
+            #[cfg(feature = "zstd")]
+            compressed_record_offset,
+            #[cfg(feature = "zstd")]
+            max_decompressed_size,
+            split_record,
+            id_parse_infos,
+            parse_infos,
+            event_id_to_attr_index,
+            sorter,
+            buffers_for_recycling,
+            unknown_record_types,
+            custom_record_types,
+        }
+    }
 
-            let record_type = RecordType(header.type_);
-            let (attr_index, timestamp) = if record_type.is_builtin_type() {
-                let attr_index = match &self.id_parse_infos {
-                    IdParseInfos::OnlyOneEvent => 0,
-                    IdParseInfos::Same(id_parse_info) => {
-                        get_record_id::<T>(record_type, data, id_parse_info)
-                            .and_then(|id| self.event_id_to_attr_index.get(&id).cloned())
-                            .unwrap_or(0)
+    /// The offset of the next record which will be read, relative to the
+    /// start of the data section.
+    pub(crate) fn read_offset(&self) -> u64 {
+        self.read_offset
+    }
+
+    /// The number of bytes of the partially read record.
+    pub(crate) fn partial_record_len(&self) -> usize {
+        self.partial_record.header_len + self.partial_record.body_len
+    }
+
+    pub(crate) fn reader_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Returns an iterator over the records of a `data.<n>` file from a
+    /// `perf record --threads` directory, which contains only records. The
+    /// records are parsed with this iterator's attributes.
+    pub(crate) fn data_file_iter<R2: Read>(&self, reader: R2, len: u64) -> PerfRecordIter<R2> {
+        self.sub_iter(reader, 0, len, 0..len)
+    }
+
+    /// Returns an iterator over the records in `reader`, with the same
+    /// attributes, filters and custom record types as this iterator.
+    /// `reader` must be positioned at `data_section_offset + range.start`.
+    fn sub_iter<R2: Read>(
+        &self,
+        reader: R2,
+        data_section_offset: u64,
+        data_section_size: u64,
+        range: Range<u64>,
+    ) -> PerfRecordIter<R2> {
+        PerfRecordIter {
+            reader,
+            endian: self.endian,
+            data_section_offset,
+            read_offset: range.start,
+            data_section_size,
+            record_data_len: range.end,
+            time_range_filter: self.time_range_filter,
+            record_type_filter: self.record_type_filter.clone(),
+            filtered_perf_file_updates: Vec::new(),
+            attr_index_filter: self.attr_index_filter.clone(),
+            trust_file_order: self.trust_file_order,
+            untrusted_input: self.untrusted_input,
+            data_is_complete: true,
+            following: false,
+            reads_until_end_of_stream: false,
+            current_event_body: RecordBody::default(),
+            partial_record: PartialRecord::default(),
+            #[cfg(feature = "zstd")]
+            decompressor: None,
+            #[cfg(feature = "zstd")]
+            compressed_record_offset: 0,
+            #[cfg(feature = "zstd")]
+            max_decompressed_size: self.max_decompressed_size,
+            split_record: None,
+            id_parse_infos: self.id_parse_infos.clone(),
+            parse_infos: self.parse_infos.clone(),
+            event_id_to_attr_index: self.event_id_to_attr_index.clone(),
+            sorter: Sorter::new(),
+            buffers_for_recycling: VecDeque::new(),
+            unknown_record_types: BTreeMap::new(),
+            custom_record_types: self.custom_record_types.clone(),
+        }
+    }
+
+    /// Applies the information from `EVENT_UPDATE`, `ID_INDEX`,
+    /// `SIMPLEPERF_EVENT_ID`, `HEADER_BUILD_ID` and `HEADER_FEATURE` records
+    /// to `perf_file`: the event names, the `ID_INDEX` entries, the event IDs
+    /// of the attributes, the build IDs and the feature sections. In pipe
+    /// mode, these records are the only place where this information is
+    /// stored.
+    ///
+    /// Units and scales from `EVENT_UPDATE` records are only collected by
+    /// [`StatMetadata`], because they're only used by `perf stat`.
+    fn update_perf_file(&self, perf_file: &mut PerfFile, pending_record: &PendingRecord) {
+        match UserRecordType::try_from(pending_record.record_type) {
+            Some(UserRecordType::PERF_EVENT_UPDATE) => {
+                self.apply_event_update(perf_file, &pending_record.body)
+            }
+            Some(UserRecordType::PERF_HEADER_BUILD_ID) => {
+                perf_file.add_build_id_record(pending_record.misc, pending_record.body.as_slice())
+            }
+            Some(UserRecordType::PERF_HEADER_FEATURE) => {
+                let body = pending_record.body.as_slice();
+                let feature_record = match self.endian {
+                    Endianness::LittleEndian => parse_feature_record::<LittleEndian>(body),
+                    Endianness::BigEndian => parse_feature_record::<BigEndian>(body),
+                };
+                // Malformed records are reported when the caller parses them.
+                if let Ok((feature, data)) = feature_record {
+                    perf_file.features.insert(feature);
+                    perf_file.feature_sections.insert(feature, data.to_vec());
+                }
+            }
+            Some(UserRecordType::PERF_ID_INDEX) => {
+                let data = RawData::from(pending_record.body.as_slice());
+                let id_index = match self.endian {
+                    Endianness::LittleEndian => IdIndex::parse::<LittleEndian>(data),
+                    Endianness::BigEndian => IdIndex::parse::<BigEndian>(data),
+                };
+                // Malformed records are reported when the caller parses them.
+                if let Ok(id_index) = id_index {
+                    for entry in id_index.entries {
+                        perf_file.id_index_entries.insert(entry.id, entry);
                     }
-                    IdParseInfos::PerAttribute(sample_id_all) => {
-                        // We have IDENTIFIER (guaranteed by PerAttribute).
-                        get_record_identifier::<T>(record_type, data, *sample_id_all)
-                            .and_then(|id| self.event_id_to_attr_index.get(&id).cloned())
-                            .unwrap_or(0)
+                }
+            }
+            Some(record_type) => {
+                for (event_id, attr_index) in
+                    self.event_id_mapping(record_type, &pending_record.body)
+                {
+                    let attr = &mut perf_file.attributes[attr_index];
+                    if !attr.event_ids.contains(&event_id) {
+                        attr.event_ids.push(event_id);
                     }
-                };
-                let parse_info = self.parse_infos[attr_index];
-                let timestamp = get_record_timestamp::<T>(record_type, data, &parse_info);
-                (Some(attr_index), timestamp)
-            } else {
-                // user type
-                (None, None)
-            };
+                    perf_file
+                        .event_id_to_attr_index
+                        .insert(event_id, attr_index);
+                }
+            }
+            None => {}
+        }
+    }
 
-            let sort_key = RecordSortKey { timestamp, offset };
-            let misc = header.misc;
-            let pending_record = PendingRecord {
-                record_type,
-                misc,
-                buffer,
-                attr_index,
-            };
-            self.sorter.insert_unordered(sort_key, pending_record);
+    /// Applies the records which were dropped by the record type filter to
+    /// `perf_file`, so that the filter doesn't affect the [`PerfFile`].
+    fn apply_filtered_perf_file_updates(&mut self, perf_file: &mut PerfFile) {
+        for pending_record in std::mem::take(&mut self.filtered_perf_file_updates) {
+            self.update_perf_file(perf_file, &pending_record);
+            self.recycle(pending_record.body);
         }
+    }
 
-        // Everything has been read.
-        self.sorter.finish();
+    /// Sets the event name from an `EVENT_UPDATE` record on the event's
+    /// attribute in `perf_file`.
+    fn apply_event_update(&self, perf_file: &mut PerfFile, body: &RecordBody) {
+        let data = RawData::from(body.as_slice());
+        let update = match self.endian {
+            Endianness::LittleEndian => EventUpdate::parse::<LittleEndian>(data),
+            Endianness::BigEndian => EventUpdate::parse::<BigEndian>(data),
+        };
+        // Malformed records are reported when the caller parses them.
+        let Ok(EventUpdate {
+            id,
+            update: EventUpdateType::Name(name),
+        }) = update
+        else {
+            return;
+        };
+        let attr_index = match self.event_id_to_attr_index.get(&id) {
+            Some(attr_index) => *attr_index,
+            None if perf_file.attributes.len() == 1 => 0,
+            None => return,
+        };
+        if let Some(attr) = perf_file.attributes.get_mut(attr_index) {
+            attr.name = Some(name);
+        }
+    }
 
-        Ok(())
+    /// Returns the `(event_id, attr_index)` pairs from the body of a
+    /// `SIMPLEPERF_EVENT_ID` record, and nothing for other
+    /// records. Entries with an invalid attr index are skipped, and
+    /// malformed records are ignored here and reported when the caller
+    /// parses them.
+    fn event_id_mapping(
+        &self,
+        record_type: UserRecordType,
+        body: &RecordBody,
+    ) -> Vec<(u64, usize)> {
+        let data = RawData::from(body.as_slice());
+        let entries: Vec<(u64, u64)> = match record_type {
+            UserRecordType::SIMPLEPERF_EVENT_ID => {
+                let event_ids = match self.endian {
+                    Endianness::LittleEndian => {
+                        SimpleperfEventIdRecord::parse::<LittleEndian>(data)
+                    }
+                    Endianness::BigEndian => SimpleperfEventIdRecord::parse::<BigEndian>(data),
+                };
+                let Ok(event_ids) = event_ids else {
+                    return Vec::new();
+                };
+                event_ids
+                    .entries
+                    .iter()
+                    .map(|entry| (entry.event_id, entry.attr_index))
+                    .collect()
+            }
+            _ => return Vec::new(),
+        };
+        entries
+            .into_iter()
+            .filter_map(|(event_id, attr_index)| {
+                let attr_index = usize::try_from(attr_index).ok()?;
+                (attr_index < self.parse_infos.len()).then_some((event_id, attr_index))
+            })
+            .collect()
     }
 
-    /// Converts pending_record into an RawRecord which references the data in self.current_event_body.
-    fn convert_pending_record(&mut self, pending_record: PendingRecord) -> PerfFileRecord {
+    fn to_owned_record(&self, pending_record: PendingRecord) -> OwnedPerfFileRecord {
         let PendingRecord {
             record_type,
             misc,
-            buffer,
+            body,
             attr_index,
-            ..
+            offset,
         } = pending_record;
-        let prev_buffer = std::mem::replace(&mut self.current_event_body, buffer);
-        self.buffers_for_recycling.push_back(prev_buffer);
+        OwnedPerfFileRecord {
+            record_type,
+            misc,
+            body: body.into_vec(),
+            attr_index,
+            offset,
+            parse_info: attr_index.map(|attr_index| self.parse_infos[attr_index]),
+            endian: self.endian,
+        }
+    }
 
-        let data = RawData::from(&self.current_event_body[..]);
+    /// The record types which this crate doesn't know about and which were
+    /// encountered so far, sorted by their numeric type.
+    ///
+    /// Check this after iterating to find out whether a file contains records
+    /// that this crate can't interpret, for example from a newer version of
+    /// `perf`. Records which were skipped, e.g. by
+    /// [`PerfRecordIter::seek_to_range`] or because of
+    /// [`PerfRecordIter::set_record_type_filter`], are not counted.
+    pub fn unknown_record_types(&self) -> impl Iterator<Item = &UnknownRecordType> + '_ {
+        self.unknown_record_types.values()
+    }
 
-        if let Some(record_type) = UserRecordType::try_from(record_type) {
-            let endian = self.endian;
-            PerfFileRecord::UserRecord(RawUserRecord {
-                record_type,
-                misc,
-                data,
-                endian,
-            })
+    /// Declares user record types which are defined by other tools, so that
+    /// records of these types get a name and can be parsed into
+    /// [`UserRecord::Custom`](crate::UserRecord::Custom). Call this before
+    /// reading any records.
+    pub fn set_custom_record_types(&mut self, custom_record_types: CustomRecordTypes) {
+        self.custom_record_types = custom_record_types;
+    }
+
+    /// Adds a buffer to the pool of buffers into which record bodies are read.
+    ///
+    /// Use this to hand back the bodies of records returned by
+    /// [`PerfRecordIter::next_owned_record`] once you're done with them, or
+    /// to supply buffers from your own allocation strategy. Buffers are
+    /// resized as needed, so buffers of any capacity can be provided.
+    pub fn provide_buffer(&mut self, buffer: Vec<u8>) {
+        self.buffers_for_recycling.push_back(buffer);
+    }
+
+    /// Sets the size of the data section, for files which are still being
+    /// written. Pass `is_complete = false` while more data can be appended.
+    ///
+    /// The data section size in the header of a perf.data file is only updated
+    /// once `perf record` is done. To process a file which is still being
+    /// written, call this method with the number of bytes which are currently
+    /// available in the data section, and read records until `next_record`
+    /// returns `None`. Records from incomplete rounds stay buffered until more
+    /// data is available, so that they can be emitted in the correct order.
+    /// Call it again with a larger size once the file has grown, or with
+    /// `is_complete = true` once the file is complete.
+    ///
+    /// If the new size ends in the middle of a record, reading stops with an
+    /// `UnexpectedEof` I/O error; the partially read record is kept, and
+    /// reading can continue after the file has grown.
+    pub fn set_data_len(&mut self, data_len: u64, is_complete: bool) {
+        self.data_section_size = data_len;
+        self.record_data_len = data_len;
+        self.data_is_complete = is_complete;
+    }
+
+    /// The range of file offsets which is covered by the data section.
+    pub fn data_section_range(&self) -> Range<u64> {
+        self.data_section_offset
+            ..self
+                .data_section_offset
+                .saturating_add(self.data_section_size)
+    }
+
+    /// Returns the current iteration state, which can be used to continue
+    /// iterating later with [`PerfRecordIter::restore_state`], for example
+    /// after reopening a file which is still being written.
+    ///
+    /// The state includes the records which are buffered for sorting. It
+    /// can't be taken while a compressed record is only partially consumed,
+    /// or while a simpleperf split record is only partially read; `None` is
+    /// returned in that case. Try again after reading more records.
+    pub fn state(&self) -> Option<PerfRecordIterState> {
+        #[cfg(feature = "zstd")]
+        if self
+            .decompressor
+            .as_ref()
+            .is_some_and(|decompressor| !decompressor.is_idle())
+        {
+            return None;
+        }
+        if self.split_record.is_some() {
+            return None;
+        }
+        Some(PerfRecordIterState {
+            read_offset: self.read_offset,
+            sorter: self.sorter.clone(),
+            partial_record: self.partial_record.clone(),
+        })
+    }
+
+    /// Only returns records of the given types, or all records if
+    /// `record_types` is `None`.
+    ///
+    /// Records of other types are skipped right after their header has been
+    /// read: their bodies aren't buffered, parsed or sorted. The records which
+    /// the iterator needs itself, such as `FINISHED_ROUND`,
+    /// `SIMPLEPERF_EVENT_ID` and `COMPRESSED` records, are still read, but only returned if their type
+    /// is in `record_types`. Records inside `COMPRESSED` records are filtered
+    /// after decompression. `EVENT_UPDATE`, `ID_INDEX`, `SIMPLEPERF_EVENT_ID`
+    /// and `HEADER_BUILD_ID` records are applied to the [`PerfFile`] even if
+    /// they aren't returned.
+    ///
+    /// This can be changed at any time; records which are already buffered
+    /// are not affected.
+    pub fn set_record_type_filter(&mut self, record_types: Option<&[RecordType]>) {
+        self.record_type_filter = record_types.map(<[RecordType]>::to_vec);
+    }
+
+    /// Only returns records with timestamps in `start..=end`. Records without
+    /// a timestamp, e.g. user records, or `MMAP` and `COMM` records of events
+    /// without `sample_id_all`, are always returned.
+    ///
+    /// `MMAP`, `MMAP2`, `COMM`, `FORK` and `EXIT` records from before `start`
+    /// are returned as well, because they're needed to symbolicate the
+    /// samples in the range and to attribute them to threads.
+    ///
+    /// Records outside the range are dropped before they're sorted, but they
+    /// still need to be read. To skip the parts of the file before and after
+    /// the range, use [`PerfRecordIter::seek_to_range`] with an index instead.
+    ///
+    /// This can be changed at any time; records which are already buffered
+    /// are not affected.
+    pub fn set_time_range(&mut self, start: u64, end: u64) {
+        self.time_range_filter = Some((start, end));
+    }
+
+    /// Returns records regardless of their timestamp, undoing
+    /// [`PerfRecordIter::set_time_range`].
+    pub fn clear_time_range(&mut self) {
+        self.time_range_filter = None;
+    }
+
+    /// Only returns the event records of the attributes with the given
+    /// indexes, or the records of all attributes if `attr_indexes` is `None`.
+    /// User records, which don't belong to an attribute, are not affected.
+    ///
+    /// Use [`PerfFile::attr_index_for_name`] to find the index of an event
+    /// by name, e.g. `cycles` or `sched:sched_switch`. The records of other
+    /// events are dropped before they're sorted, but unlike with
+    /// [`PerfRecordIter::set_record_type_filter`], their bodies still need to
+    /// be read to find out which event they belong to.
+    ///
+    /// This can be changed at any time; records which are already buffered
+    /// are not affected.
+    pub fn set_attr_index_filter(&mut self, attr_indexes: Option<&[usize]>) {
+        self.attr_index_filter = attr_indexes.map(<[usize]>::to_vec);
+    }
+
+    /// Treat the records in the file as already sorted by time, and emit each
+    /// record as soon as it is read, without waiting for the end of its round.
+    ///
+    /// This is the case for files which have been post-processed by
+    /// `perf inject`, and for files recorded on a single CPU. For such files,
+    /// this reduces memory usage and latency when streaming records. If the
+    /// file is not actually sorted, records are returned out of order.
+    ///
+    /// This can be changed at any time; records which are already buffered
+    /// are emitted first.
+    pub fn set_trust_file_order(&mut self, trust_file_order: bool) {
+        if trust_file_order && !self.trust_file_order {
+            self.sorter.finish();
+        }
+        self.trust_file_order = trust_file_order;
+    }
+
+    /// Treat the file as untrusted input, e.g. a file uploaded by a user to a
+    /// server which ingests it.
+    ///
+    /// Malformed input never causes a panic, regardless of this setting. By
+    /// default, the reader is lenient with files that are slightly
+    /// inconsistent, as they are produced by some versions of perf: for
+    /// example, event records with an event ID that doesn't belong to any
+    /// attribute are attributed to the first attribute. In untrusted mode,
+    /// such records are reported as errors instead, so that the records
+    /// which are returned are guaranteed to be consistent with the attributes.
+    pub fn set_untrusted_input(&mut self, untrusted_input: bool) {
+        self.untrusted_input = untrusted_input;
+    }
+
+    /// Builds a [`PerfFileIndex`] from the in-memory contents of the entire
+    /// file, using up to `thread_count` threads. This produces the same index
+    /// as [`PerfRecordIter::build_index`], but is much faster for large files.
+    ///
+    /// `file_data` is usually a memory map of the file which this iterator
+    /// was created from. The data section is split into chunks at record
+    /// boundaries, the chunks are scanned in parallel, and the partial
+    /// indexes are merged. Finding the boundaries only requires walking the
+    /// record headers, and each chunk starts being scanned as soon as its
+    /// end has been found. If `thread_count` is zero, the number of threads
+    /// is chosen based on the available parallelism.
+    ///
+    /// This does not affect the iteration state.
+    pub fn build_index_from_slice(
+        &self,
+        file_data: &[u8],
+        thread_count: usize,
+    ) -> Result<PerfFileIndex, Error> {
+        let data_start =
+            usize::try_from(self.data_section_offset).map_err(|_| Error::SectionSizeTooBig)?;
+        let data_len =
+            usize::try_from(self.data_section_size).map_err(|_| Error::SectionSizeTooBig)?;
+        let data = data_start
+            .checked_add(data_len)
+            .and_then(|data_end| file_data.get(data_start..data_end))
+            .ok_or(ReadError::PerfEventData)?;
+        let thread_count = match thread_count {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        if self.endian == Endianness::LittleEndian {
+            self.build_index_from_slice_impl::<byteorder::LittleEndian>(data, thread_count)
         } else {
-            let attr_index = attr_index.unwrap();
-            let parse_info = self.parse_infos[attr_index];
-            let record = RawEventRecord {
-                record_type,
-                misc,
-                data,
-                parse_info,
-            };
-            PerfFileRecord::EventRecord { attr_index, record }
+            self.build_index_from_slice_impl::<byteorder::BigEndian>(data, thread_count)
         }
     }
-}
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-struct PendingRecord {
-    record_type: RecordType,
-    misc: u16,
-    buffer: Vec<u8>,
-    attr_index: Option<usize>,
-}
+    fn build_index_from_slice_impl<T: ByteOrder>(
+        &self,
+        data: &[u8],
+        thread_count: usize,
+    ) -> Result<PerfFileIndex, Error> {
+        let classifier = self.record_classifier();
+        let target_chunk_size = (data.len() / thread_count.max(1)).max(1);
+        let chunk_results: Vec<Result<IndexBuilder, Error>> = std::thread::scope(|scope| {
+            // Each chunk is handed to its thread as soon as its end is found,
+            // so the chunks are indexed while the record headers of the later
+            // chunks are still being walked.
+            let mut handles = Vec::new();
+            let mut walk_error = None;
+            let mut start = 0;
+            while start < data.len() {
+                let end = match find_chunk_end::<T>(data, start, target_chunk_size) {
+                    Ok(end) => end,
+                    Err(e) => {
+                        walk_error = Some(e);
+                        break;
+                    }
+                };
+                let chunk = &data[start..end];
+                handles.push(scope.spawn(move || classifier.index_chunk::<T>(chunk, start as u64)));
+                start = end;
+            }
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or(Err(Error::IndexThreadPanicked)))
+                .chain(walk_error.map(Err))
+                .collect()
+        });
 
-#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
-struct RecordSortKey {
-    timestamp: Option<u64>,
-    offset: u64,
-}
+        let mut builder = IndexBuilder::new(self.parse_infos.len(), 0);
+        for chunk in chunk_results {
+            builder.append(chunk?);
+        }
+        Ok(builder.finish())
+    }
 
-#[derive(Debug, Clone)]
-enum IdParseInfos {
-    /// There is only one event.
-    OnlyOneEvent,
-    /// There are multiple events, but all events are parsed the same way.
-    Same(RecordIdParseInfo),
-    /// All elements are guaranteed to have [`SampleFormat::IDENTIFIER`] set in `attr.sample_format`.
-    /// The inner element indicates sample_id_all.
-    PerAttribute(bool),
+    /// Like `read_next_round`, but when following a growing file, a record
+    /// which has only been partially written isn't an error; it is kept and
+    /// completed by a later call.
+    fn read_available_round(&mut self) -> Result<(), Error> {
+        match self.read_next_round() {
+            Err(Error::IoError(e))
+                if self.following && e.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                Ok(())
+            }
+            Err(Error::Read(ReadError::PerfEventData)) if self.following => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Reads events into self.sorter until a FINISHED_ROUND record is found
+    /// and self.sorter is non-empty, or until we've run out of records to read.
+    fn read_next_round(&mut self) -> Result<(), Error> {
+        if self.endian == Endianness::LittleEndian {
+            self.read_next_round_impl::<byteorder::LittleEndian>()
+        } else {
+            self.read_next_round_impl::<byteorder::BigEndian>()
+        }
+    }
+
+    /// Reads events into self.sorter until a FINISHED_ROUND record is found
+    /// and self.sorter is non-empty, or until we've run out of records to read.
+    fn read_next_round_impl<T: ByteOrder>(&mut self) -> Result<(), Error> {
+        loop {
+            #[cfg(feature = "zstd")]
+            if let Some(decompressor) = &mut self.decompressor {
+                if let Some((header, body)) = decompressor.next_record::<T>()? {
+                    let offset = self.compressed_record_offset;
+                    if self.process_record::<T>(
+                        RecordType(header.type_),
+                        header.misc,
+                        body,
+                        offset,
+                    )? {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            }
+
+            if self.read_offset >= self.record_data_len {
+                if !self.data_is_complete {
+                    // More records can still be appended to the file, so we
+                    // can't flush the sorter yet.
+                    return Ok(());
+                }
+                break;
+            }
+            if self.reads_until_end_of_stream
+                && self.partial_record.header_len == 0
+                && self.is_at_end_of_stream()?
+            {
+                self.set_data_len(self.read_offset, true);
+                self.reads_until_end_of_stream = false;
+                break;
+            }
+            let (header, buffer) = self.read_raw_record::<T>()?;
+            let offset = self.read_offset;
+            let Some(buffer) = buffer else {
+                // Skipped because of the record type filter.
+                self.read_offset += u64::from(header.size);
+                continue;
+            };
+            // This includes the aux data which follows AUXTRACE records.
+            self.read_offset += (PerfEventHeader::STRUCT_SIZE + buffer.len()) as u64;
+
+            #[cfg(feature = "zstd")]
+            {
+                let user_record_type = UserRecordType::try_from(RecordType(header.type_));
+                if user_record_type == Some(UserRecordType::PERF_COMPRESSED)
+                    || user_record_type == Some(UserRecordType::PERF_COMPRESSED2)
+                {
+                    self.compressed_record_offset = offset;
+                    self.decompress::<T>(user_record_type, &buffer)?;
+                    self.buffers_for_recycling.push_back(buffer);
+                    continue;
+                }
+            }
+
+            let user_record_type = UserRecordType::try_from(RecordType(header.type_));
+            if user_record_type == Some(UserRecordType::SIMPLEPERF_SPLIT) {
+                let (_, data) = self
+                    .split_record
+                    .get_or_insert_with(|| (offset, Vec::new()));
+                if data.len().saturating_add(buffer.len()) > MAX_SPLIT_RECORD_SIZE {
+                    return Err(Error::InvalidPerfEventSize);
+                }
+                data.extend_from_slice(&buffer);
+                self.buffers_for_recycling.push_back(buffer);
+                continue;
+            }
+            if user_record_type == Some(UserRecordType::SIMPLEPERF_SPLIT_END) {
+                self.buffers_for_recycling.push_back(buffer);
+                // Ignore a SPLIT_END record without SPLIT records.
+                if let Some((offset, data)) = self.split_record.take() {
+                    let (record_type, body) = join_split_record::<T>(data)?;
+                    if self.process_record::<T>(record_type, 0, RecordBody::Owned(body), offset)? {
+                        return Ok(());
+                    }
+                }
+                continue;
+            }
+
+            if self.process_record::<T>(
+                RecordType(header.type_),
+                header.misc,
+                RecordBody::Owned(buffer),
+                offset,
+            )? {
+                return Ok(());
+            }
+        }
+
+        // Everything has been read.
+        self.sorter.finish();
+
+        Ok(())
+    }
+
+    /// Reads the first byte of the next record header into the partial
+    /// record. Returns true if the reader has ended instead.
+    fn is_at_end_of_stream(&mut self) -> Result<bool, Error> {
+        let partial = &mut self.partial_record;
+        loop {
+            match self.reader.read(&mut partial.header[..1]) {
+                Ok(0) => return Ok(true),
+                Ok(n) => {
+                    partial.header_len = n;
+                    return Ok(false);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Reads the next record from the reader.
+    ///
+    /// If the reader returns an error, for example `WouldBlock` for a
+    /// non-blocking pipe, the bytes which have been read so far are kept,
+    /// and the next call continues where this call stopped.
+    ///
+    /// Returns `None` instead of the body if the record was skipped because
+    /// of the record type filter.
+    fn read_raw_record<T: ByteOrder>(
+        &mut self,
+    ) -> Result<(PerfEventHeader, Option<Vec<u8>>), Error> {
+        let partial = &mut self.partial_record;
+        fill_buffer(
+            &mut self.reader,
+            &mut partial.header,
+            &mut partial.header_len,
+        )?;
+        let header = PerfEventHeader::parse::<_, T>(&partial.header[..])?;
+        let size = header.size as usize;
+        if size < PerfEventHeader::STRUCT_SIZE {
+            return Err(Error::InvalidPerfEventSize);
+        }
+        if partial.skipping_body
+            || (partial.body.is_none()
+                && !reads_record_type(self.record_type_filter.as_deref(), RecordType(header.type_)))
+        {
+            partial.skipping_body = true;
+            let body_size = size - PerfEventHeader::STRUCT_SIZE;
+            skip_body(&mut self.reader, body_size, &mut partial.body_len)?;
+            *partial = PartialRecord::default();
+            return Ok((header, None));
+        }
+        let body = partial.body.get_or_insert_with(|| {
+            let mut buffer = self.buffers_for_recycling.pop_front().unwrap_or_default();
+            buffer.resize(size - PerfEventHeader::STRUCT_SIZE, 0);
+            buffer
+        });
+        fill_body(&mut self.reader, body, &mut partial.body_len)?;
+        let aux_data_size = auxtrace_data_size::<T>(RecordType(header.type_), body);
+        if aux_data_size != 0 && body.len() == size - PerfEventHeader::STRUCT_SIZE {
+            // The aux data follows the AUXTRACE record. Append it to the body.
+            // While the data section can still grow, the aux data may not
+            // have been written yet, so only a sanity limit can be checked.
+            let max_aux_data_size = if self.data_is_complete {
+                self.record_data_len
+                    .saturating_sub(self.read_offset.saturating_add(size as u64))
+            } else {
+                MAX_PENDING_AUX_DATA_SIZE
+            };
+            if aux_data_size > max_aux_data_size {
+                return Err(Error::InvalidPerfEventSize);
+            }
+            let body_len = usize::try_from(aux_data_size)
+                .ok()
+                .and_then(|aux_data_size| body.len().checked_add(aux_data_size))
+                .ok_or(Error::InvalidPerfEventSize)?;
+            body.resize(body_len, 0);
+            fill_body(&mut self.reader, body, &mut partial.body_len)?;
+        }
+        let body = partial.body.take().unwrap();
+        *partial = PartialRecord::default();
+        Ok((header, Some(body)))
+    }
+
+    /// Adds a record to the sorter, unless it is filtered out. Returns true
+    /// if ordered records are available and reading should stop for now.
+    fn process_record<T: ByteOrder>(
+        &mut self,
+        record_type: RecordType,
+        misc: u16,
+        body: RecordBody,
+        offset: u64,
+    ) -> Result<bool, Error> {
+        if !is_known_record_type(record_type) {
+            let custom_record_types = &self.custom_record_types;
+            self.unknown_record_types
+                .entry(record_type.0)
+                .or_insert_with(|| UnknownRecordType {
+                    record_type,
+                    name: UserRecordType::try_from(record_type)
+                        .and_then(|record_type| custom_record_types.get(record_type))
+                        .map(|custom_type| custom_type.name.clone()),
+                    count: 0,
+                    first_offset: offset,
+                })
+                .count += 1;
+        }
+        if UserRecordType::try_from(record_type) == Some(UserRecordType::PERF_FINISHED_ROUND) {
+            self.recycle(body);
+            self.sorter.finish_round();
+            // If the sorter is non-empty, we're done. Otherwise, keep going
+            // so that we never stop reading with the sorter being empty,
+            // unless we've truly run out of data to read.
+            return Ok(self.sorter.has_more());
+        }
+        if let Some(user_record_type) = UserRecordType::try_from(record_type) {
+            // Apply SIMPLEPERF_EVENT_ID records right away,
+            // because the records after them need them to find their
+            // attr_index.
+            for (event_id, attr_index) in self.event_id_mapping(user_record_type, &body) {
+                self.event_id_to_attr_index.insert(event_id, attr_index);
+            }
+        }
+        if let Some(record_types) = &self.record_type_filter {
+            if !record_types.contains(&record_type) {
+                if updates_perf_file(record_type) {
+                    self.filtered_perf_file_updates.push(PendingRecord {
+                        record_type,
+                        misc,
+                        body,
+                        attr_index: None,
+                        offset,
+                    });
+                } else {
+                    self.recycle(body);
+                }
+                return Ok(false);
+            }
+        }
+
+        let data = RawData::from(body.as_slice());
+        let (attr_index, timestamp) = self.attr_index_and_timestamp::<T>(record_type, data)?;
+
+        if let (Some(attr_indexes), Some(attr_index)) = (&self.attr_index_filter, attr_index) {
+            if !attr_indexes.contains(&attr_index) {
+                self.recycle(body);
+                return Ok(false);
+            }
+        }
+
+        if let (Some((start, end)), Some(timestamp)) = (self.time_range_filter, timestamp) {
+            if (timestamp < start && !is_process_metadata_record(record_type)) || timestamp > end {
+                self.recycle(body);
+                return Ok(false);
+            }
+        }
+
+        let pending_record = PendingRecord {
+            record_type,
+            misc,
+            body,
+            attr_index,
+            offset,
+        };
+        if self.trust_file_order {
+            self.sorter.push_ordered(pending_record);
+            return Ok(true);
+        }
+        self.sorter.insert_unordered(timestamp, pending_record);
+        Ok(false)
+    }
+
+    /// Decompresses the body of a `COMPRESSED` or `COMPRESSED2` record. The
+    /// records in it are returned by the decompressor.
+    #[cfg(feature = "zstd")]
+    fn decompress<T: ByteOrder>(
+        &mut self,
+        record_type: Option<UserRecordType>,
+        body: &[u8],
+    ) -> Result<(), Error> {
+        let compressed = compressed_data::<T>(record_type, body)?;
+        let decompressor = match &mut self.decompressor {
+            Some(decompressor) => decompressor,
+            None => self
+                .decompressor
+                .insert(Decompressor::new(self.max_decompressed_size)?),
+        };
+        decompressor.decompress(compressed)
+    }
+
+    fn recycle(&mut self, body: RecordBody) {
+        if let Some(buffer) = body.into_recyclable_buffer() {
+            self.buffers_for_recycling.push_back(buffer);
+        }
+    }
+
+    fn attr_index_and_timestamp<T: ByteOrder>(
+        &self,
+        record_type: RecordType,
+        data: RawData,
+    ) -> Result<(Option<usize>, Option<u64>), Error> {
+        self.record_classifier()
+            .attr_index_and_timestamp::<T>(record_type, data)
+    }
+
+    fn record_classifier(&self) -> RecordClassifier<'_> {
+        RecordClassifier {
+            id_parse_infos: &self.id_parse_infos,
+            parse_infos: &self.parse_infos,
+            event_id_to_attr_index: &self.event_id_to_attr_index,
+            untrusted_input: self.untrusted_input,
+        }
+    }
+
+    /// Converts pending_record into an RawRecord which references the data in self.current_event_body.
+    fn convert_pending_record(&mut self, pending_record: PendingRecord) -> PerfFileRecord<'_> {
+        let PendingRecord {
+            record_type,
+            misc,
+            body,
+            attr_index,
+            offset,
+        } = pending_record;
+        let prev_body = std::mem::replace(&mut self.current_event_body, body);
+        self.recycle(prev_body);
+
+        let data = RawData::from(self.current_event_body.as_slice());
+
+        if let Some(record_type) = UserRecordType::try_from(record_type) {
+            let endian = self.endian;
+            PerfFileRecord::UserRecord(RawUserRecord {
+                record_type,
+                misc,
+                data,
+                endian,
+                offset,
+                custom_type: self.custom_record_types.get(record_type),
+            })
+        } else {
+            let attr_index = attr_index.unwrap();
+            let parse_info = self.parse_infos[attr_index];
+            let record = RawEventRecord {
+                record_type,
+                misc,
+                data,
+                parse_info,
+            };
+            PerfFileRecord::EventRecord {
+                attr_index,
+                record,
+                offset,
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> PerfRecordIter<R> {
+    /// Follows a perf.data file which is still being written by
+    /// `perf record`, similar to `tail -f`.
+    ///
+    /// While `perf record` is running, the data section size in the file
+    /// header is zero, and the records go up to the end of the file. Call
+    /// this method to pick up the records which have been written since the
+    /// last call, and then call [`PerfRecordIter::next_record`] until it
+    /// returns `None`. Records at the end of the file which are still being
+    /// written are kept and completed by later calls, and records from
+    /// incomplete rounds stay buffered so that they're emitted in order.
+    ///
+    /// Once `perf record` has finished, it writes the final data section size
+    /// into the header, and this method returns [`FollowStatus::Finished`];
+    /// the remaining records can then be read as usual. Call
+    /// [`PerfRecordIter::finish_following`] if the writer went away without
+    /// finishing the file, e.g. because it was killed.
+    ///
+    /// The feature sections are only written when `perf record` finishes; to
+    /// get them, parse the file again.
+    pub fn follow(&mut self) -> Result<FollowStatus, Error> {
+        let position = self.reader.stream_position()?;
+        self.reader.seek(SeekFrom::Start(0))?;
+        let header = PerfHeader::parse(&mut self.reader)?;
+        let file_len = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(position))?;
+
+        if header.data_section.size != 0 {
+            self.set_data_len(header.data_section.size, true);
+            self.following = false;
+            return Ok(FollowStatus::Finished {
+                data_len: header.data_section.size,
+            });
+        }
+        let data_len = file_len.saturating_sub(self.data_section_offset);
+        self.set_data_len(data_len, false);
+        self.following = true;
+        Ok(FollowStatus::Growing { data_len })
+    }
+
+    /// Signals that the file which is being followed won't grow anymore,
+    /// for example because `perf record` was killed before it could write
+    /// the final header. The records up to the current end of the file are
+    /// emitted, and a record which is cut off at the end is an error.
+    pub fn finish_following(&mut self) -> Result<(), Error> {
+        let position = self.reader.stream_position()?;
+        let file_len = self.reader.seek(SeekFrom::End(0))?;
+        self.reader.seek(SeekFrom::Start(position))?;
+        self.set_data_len(file_len.saturating_sub(self.data_section_offset), true);
+        self.following = false;
+        Ok(())
+    }
+
+    /// Continues iterating from a state returned by [`PerfRecordIter::state`].
+    ///
+    /// The state can come from a different `PerfRecordIter`, as long as both
+    /// were created from the same file, for example before and after the
+    /// file was reopened.
+    pub fn restore_state(&mut self, state: PerfRecordIterState) -> Result<(), Error> {
+        let PerfRecordIterState {
+            read_offset,
+            sorter,
+            partial_record,
+        } = state;
+        let partial_len = (partial_record.header_len + partial_record.body_len) as u64;
+        self.reader.seek(SeekFrom::Start(
+            self.data_section_offset + read_offset + partial_len,
+        ))?;
+        self.read_offset = read_offset;
+        self.sorter = sorter;
+        self.partial_record = partial_record;
+        self.split_record = None;
+        #[cfg(feature = "zstd")]
+        {
+            self.decompressor = None;
+        }
+        Ok(())
+    }
+
+    /// A fingerprint of the file contents, for
+    /// [`SidecarIndex`](crate::SidecarIndex): a hash of the file length, of
+    /// the start of the file, and of the first and the last block of the data
+    /// section. This catches files which were rewritten with the same layout.
+    pub(crate) fn content_fingerprint(&mut self) -> Result<u64, Error> {
+        const BLOCK_SIZE: u64 = 4096;
+
+        let position = self.reader.stream_position()?;
+        let file_len = self.reader.seek(SeekFrom::End(0))?;
+        let data_start = self.data_section_offset.min(file_len);
+        let data_end = data_start
+            .saturating_add(self.data_section_size)
+            .min(file_len);
+        let ranges = [
+            (0, data_start.min(BLOCK_SIZE)),
+            (data_start, data_end.min(data_start + BLOCK_SIZE)),
+            (
+                data_end.saturating_sub(BLOCK_SIZE).max(data_start),
+                data_end,
+            ),
+        ];
+        let mut hash = fnv1a(FNV_OFFSET_BASIS, &file_len.to_le_bytes());
+        let mut buffer = Vec::new();
+        for (start, end) in ranges {
+            buffer.resize((end - start) as usize, 0);
+            self.reader.seek(SeekFrom::Start(start))?;
+            self.reader.read_exact(&mut buffer)?;
+            hash = fnv1a(hash, &buffer);
+        }
+        self.reader.seek(SeekFrom::Start(position))?;
+        Ok(hash)
+    }
+
+    /// Moves the reader back to where reading continues after a scan of the
+    /// data section: after the bytes of the partially read record, if any.
+    fn seek_to_read_position(&mut self) -> Result<(), Error> {
+        let partial_len = self.partial_record_len() as u64;
+        self.reader.seek(SeekFrom::Start(
+            self.data_section_offset + self.read_offset + partial_len,
+        ))?;
+        Ok(())
+    }
+
+    /// Scans the entire data section and builds a [`PerfFileIndex`], without
+    /// parsing any records.
+    ///
+    /// This is the first pass of a two-pass load: the index gives you the time
+    /// range and the record counts of the file, and it can be passed to
+    /// [`PerfRecordIter::seek_to_range`] to parse just a part of the file in
+    /// the second pass.
+    ///
+    /// This does not affect the iteration state: after the scan, the reader
+    /// is moved back to the position it was at before the call.
+    pub fn build_index(&mut self) -> Result<PerfFileIndex, Error> {
+        self.reader
+            .seek(SeekFrom::Start(self.data_section_offset))?;
+        let index = if self.endian == Endianness::LittleEndian {
+            self.build_index_impl::<byteorder::LittleEndian>()
+        } else {
+            self.build_index_impl::<byteorder::BigEndian>()
+        };
+        self.seek_to_read_position()?;
+        index
+    }
+
+    /// Scans the entire data section for the timestamps of the first and the
+    /// last sample, for files without a `SAMPLE_TIME` feature section.
+    ///
+    /// This is faster than [`PerfRecordIter::build_index`]: only the headers
+    /// of the records are read, plus the bodies of the samples. `COMPRESSED`
+    /// records are decompressed to find the samples in them; without the
+    /// `zstd` feature, they cause an [`Error::CompressedDataNotSupported`].
+    ///
+    /// This does not affect the iteration state: after the scan, the reader
+    /// is moved back to the position it was at before the call.
+    pub fn scan_sample_time_range(&mut self) -> Result<Option<SampleTimeRange>, Error> {
+        self.reader
+            .seek(SeekFrom::Start(self.data_section_offset))?;
+        let time_range = if self.endian == Endianness::LittleEndian {
+            self.scan_sample_time_range_impl::<byteorder::LittleEndian>()
+        } else {
+            self.scan_sample_time_range_impl::<byteorder::BigEndian>()
+        };
+        self.seek_to_read_position()?;
+        time_range
+    }
+
+    fn scan_sample_time_range_impl<T: ByteOrder>(
+        &mut self,
+    ) -> Result<Option<SampleTimeRange>, Error> {
+        let mut time_range: Option<SampleTimeRange> = None;
+        let mut buffer = Vec::new();
+        let mut offset = 0;
+        #[cfg(feature = "zstd")]
+        let mut decompressor: Option<Decompressor> = None;
+        while offset < self.data_section_size {
+            let header = PerfEventHeader::parse::<_, T>(&mut self.reader)?;
+            let size = header.size as usize;
+            if size < PerfEventHeader::STRUCT_SIZE {
+                return Err(Error::InvalidPerfEventSize);
+            }
+            let body_size = size - PerfEventHeader::STRUCT_SIZE;
+            offset += u64::from(header.size);
+            let record_type = RecordType(header.type_);
+            let user_record_type = UserRecordType::try_from(record_type);
+            let is_auxtrace = user_record_type == Some(UserRecordType::PERF_AUXTRACE);
+            let is_compressed = user_record_type == Some(UserRecordType::PERF_COMPRESSED)
+                || user_record_type == Some(UserRecordType::PERF_COMPRESSED2);
+            if record_type != RecordType::SAMPLE && !is_auxtrace && !is_compressed {
+                self.reader.seek(SeekFrom::Current(body_size as i64))?;
+                continue;
+            }
+
+            buffer.resize(body_size, 0);
+            self.reader
+                .read_exact(&mut buffer)
+                .map_err(|_| ReadError::PerfEventData)?;
+            if is_auxtrace {
+                let aux_data_size = auxtrace_data_size::<T>(record_type, &buffer);
+                offset = self.skip_aux_data(offset, aux_data_size)?;
+                continue;
+            }
+            if is_compressed {
+                #[cfg(not(feature = "zstd"))]
+                return Err(Error::CompressedDataNotSupported);
+                #[cfg(feature = "zstd")]
+                {
+                    let decompressor = match &mut decompressor {
+                        Some(decompressor) => decompressor,
+                        None => decompressor.insert(Decompressor::new(self.max_decompressed_size)?),
+                    };
+                    decompressor.decompress(compressed_data::<T>(user_record_type, &buffer)?)?;
+                    while let Some((header, body)) = decompressor.next_record::<T>()? {
+                        let record_type = RecordType(header.type_);
+                        if record_type == RecordType::SAMPLE {
+                            let data = RawData::from(body.as_slice());
+                            let (_, timestamp) =
+                                self.attr_index_and_timestamp::<T>(record_type, data)?;
+                            extend_sample_time_range(&mut time_range, timestamp);
+                        }
+                    }
+                    continue;
+                }
+            }
+            let data = RawData::from(&buffer[..]);
+            let (_, timestamp) = self.attr_index_and_timestamp::<T>(record_type, data)?;
+            extend_sample_time_range(&mut time_range, timestamp);
+        }
+        Ok(time_range)
+    }
+
+    /// Reads the [`StatMetadata`] of a `perf stat record` file, i.e. of a file
+    /// with the `STAT` feature, from the user records before the first
+    /// `STAT` record.
+    ///
+    /// This does not affect the iteration state: after the scan, the reader
+    /// is moved back to the position it was at before the call.
+    pub fn read_stat_metadata(&mut self, perf_file: &PerfFile) -> Result<StatMetadata, Error> {
+        self.reader
+            .seek(SeekFrom::Start(self.data_section_offset))?;
+        let metadata = if self.endian == Endianness::LittleEndian {
+            self.read_stat_metadata_impl::<byteorder::LittleEndian>(perf_file)
+        } else {
+            self.read_stat_metadata_impl::<byteorder::BigEndian>(perf_file)
+        };
+        self.seek_to_read_position()?;
+        metadata
+    }
+
+    fn read_stat_metadata_impl<T: ByteOrder>(
+        &mut self,
+        perf_file: &PerfFile,
+    ) -> Result<StatMetadata, Error> {
+        let mut metadata = StatMetadata::new(perf_file);
+        let mut buffer = Vec::new();
+        let mut offset = 0;
+        while offset < self.data_section_size {
+            let header = PerfEventHeader::parse::<_, T>(&mut self.reader)?;
+            let size = header.size as usize;
+            if size < PerfEventHeader::STRUCT_SIZE {
+                return Err(Error::InvalidPerfEventSize);
+            }
+            buffer.resize(size - PerfEventHeader::STRUCT_SIZE, 0);
+            self.reader
+                .read_exact(&mut buffer)
+                .map_err(|_| ReadError::PerfEventData)?;
+            let record_offset = self.data_section_offset + offset;
+            let record_type = RecordType(header.type_);
+            let aux_data_size = auxtrace_data_size::<T>(record_type, &buffer);
+            offset = self.skip_aux_data(offset + u64::from(header.size), aux_data_size)?;
+
+            let Some(record_type) = UserRecordType::try_from(record_type) else {
+                continue;
+            };
+            if StatMetadata::is_end_of_metadata(record_type) {
+                break;
+            }
+            let record = RawUserRecord {
+                record_type,
+                endian: self.endian,
+                misc: header.misc,
+                data: RawData::from(&buffer[..]),
+                offset: record_offset,
+                custom_type: None,
+            };
+            metadata.apply_user_record(&record.parse()?)?;
+        }
+        Ok(metadata)
+    }
+
+    fn build_index_impl<T: ByteOrder>(&mut self) -> Result<PerfFileIndex, Error> {
+        let mut builder = IndexBuilder::new(self.parse_infos.len(), 0);
+        let mut buffer = Vec::new();
+        let mut offset = 0;
+        while offset < self.data_section_size {
+            let header = PerfEventHeader::parse::<_, T>(&mut self.reader)?;
+            let size = header.size as usize;
+            if size < PerfEventHeader::STRUCT_SIZE {
+                return Err(Error::InvalidPerfEventSize);
+            }
+            let record_type = RecordType(header.type_);
+            if UserRecordType::try_from(record_type) == Some(UserRecordType::PERF_FINISHED_ROUND) {
+                offset += u64::from(header.size);
+                builder.add_finished_round(header.size.into());
+                continue;
+            }
+
+            buffer.resize(size - PerfEventHeader::STRUCT_SIZE, 0);
+            self.reader
+                .read_exact(&mut buffer)
+                .map_err(|_| ReadError::PerfEventData)?;
+            let aux_data_size = auxtrace_data_size::<T>(record_type, &buffer);
+            let record_end = offset + u64::from(header.size);
+            let next_offset = self.skip_aux_data(record_end, aux_data_size)?;
+            let record_size = next_offset - offset;
+            offset = next_offset;
+            let data = RawData::from(&buffer[..]);
+            let (attr_index, timestamp) = self.attr_index_and_timestamp::<T>(record_type, data)?;
+            builder.add_record(record_type, record_size, attr_index, timestamp);
+        }
+        Ok(builder.finish())
+    }
+
+    /// Skips the aux data which follows an `AUXTRACE` record, for the
+    /// scanners which read the data section directly. `record_end` is the
+    /// offset after the record, relative to the start of the data section.
+    /// Returns the offset after the aux data.
+    ///
+    /// The aux data size comes from the file, so it is checked against the
+    /// size of the data section before seeking.
+    fn skip_aux_data(&mut self, record_end: u64, aux_data_size: u64) -> Result<u64, Error> {
+        if aux_data_size == 0 {
+            return Ok(record_end);
+        }
+        let aux_data_end = record_end
+            .checked_add(aux_data_size)
+            .filter(|aux_data_end| *aux_data_end <= self.data_section_size)
+            .ok_or(Error::InvalidPerfEventSize)?;
+        let len = i64::try_from(aux_data_size).map_err(|_| Error::InvalidPerfEventSize)?;
+        self.reader.seek(SeekFrom::Current(len))?;
+        Ok(aux_data_end)
+    }
+
+    /// Splits the data section into about `chunk_count` byte ranges of
+    /// similar size, at record boundaries, for processing the chunks in
+    /// parallel with [`PerfRecordIter::chunk_iter`].
+    ///
+    /// The returned ranges are file offsets; they are contiguous and cover
+    /// the entire data section. Only the record headers are read. This does
+    /// not affect the iteration state.
+    pub fn split_data_section(&mut self, chunk_count: usize) -> Result<Vec<Range<u64>>, Error> {
+        self.reader
+            .seek(SeekFrom::Start(self.data_section_offset))?;
+        let boundaries = if self.endian == Endianness::LittleEndian {
+            self.split_data_section_impl::<byteorder::LittleEndian>(chunk_count)
+        } else {
+            self.split_data_section_impl::<byteorder::BigEndian>(chunk_count)
+        };
+        self.seek_to_read_position()?;
+        let data_section_offset = self.data_section_offset;
+        Ok(boundaries?
+            .windows(2)
+            .map(|w| data_section_offset + w[0]..data_section_offset + w[1])
+            .collect())
+    }
+
+    /// Returns the chunk boundaries relative to the start of the data section,
+    /// starting with 0 and ending with the data section size.
+    fn split_data_section_impl<T: ByteOrder>(
+        &mut self,
+        chunk_count: usize,
+    ) -> Result<Vec<u64>, Error> {
+        let target_chunk_size = (self.data_section_size / chunk_count.max(1) as u64).max(1);
+        let mut boundaries = vec![0];
+        let mut next_boundary = target_chunk_size;
+        let mut offset = 0;
+        while offset < self.data_section_size {
+            if offset >= next_boundary {
+                boundaries.push(offset);
+                next_boundary = offset + target_chunk_size;
+            }
+            let header = PerfEventHeader::parse::<_, T>(&mut self.reader)?;
+            let size = u64::from(header.size);
+            if size < PerfEventHeader::STRUCT_SIZE as u64 {
+                return Err(Error::InvalidPerfEventSize);
+            }
+            let mut body_size = size - PerfEventHeader::STRUCT_SIZE as u64;
+            let mut aux_data_size = 0;
+            if UserRecordType::try_from(RecordType(header.type_))
+                == Some(UserRecordType::PERF_AUXTRACE)
+                && body_size >= 8
+            {
+                aux_data_size = self.reader.read_u64::<T>()?;
+                body_size -= 8;
+            }
+            self.reader.seek(SeekFrom::Current(body_size as i64))?;
+            // Skip the aux data which follows the record, too.
+            offset = self.skip_aux_data(offset + size, aux_data_size)?;
+        }
+        if offset > self.data_section_size {
+            return Err(Error::InvalidPerfEventSize);
+        }
+        boundaries.push(self.data_section_size);
+        Ok(boundaries)
+    }
+
+    /// Creates an iterator over the records in `range`, which is one of the
+    /// ranges returned by [`PerfRecordIter::split_data_section`]. `reader`
+    /// must read the same file as this iterator, usually it's a separate file
+    /// handle for each thread.
+    ///
+    /// The returned iterator uses the same attributes, filters and custom
+    /// record types as this iterator. Records are only sorted within the
+    /// chunk; the caller is responsible for merging the results of the
+    /// chunks, e.g. by timestamp. Compressed records are only supported if
+    /// each `COMPRESSED` record can be decompressed on its own.
+    pub fn chunk_iter<R2: Read + Seek>(
+        &self,
+        mut reader: R2,
+        range: Range<u64>,
+    ) -> Result<PerfRecordIter<R2>, Error> {
+        let data_section_end = self.data_section_offset + self.data_section_size;
+        if range.start < self.data_section_offset
+            || range.end > data_section_end
+            || range.start > range.end
+        {
+            return Err(ReadError::PerfEventData.into());
+        }
+        reader.seek(SeekFrom::Start(range.start))?;
+        Ok(self.sub_iter(
+            reader,
+            self.data_section_offset,
+            self.data_section_size,
+            range.start - self.data_section_offset..range.end - self.data_section_offset,
+        ))
+    }
+
+    /// Restricts iteration to the records with timestamps in `start..=end` and,
+    /// optionally, to the given record types. This is the second pass of a
+    /// two-pass load; `index` must have been created by
+    /// [`PerfRecordIter::build_index`] for the same file.
+    ///
+    /// The reader is moved to the first round which can contain records from
+    /// the requested time range, and iteration stops after the last such round.
+    /// Records without a timestamp, such as user records, are returned if they
+    /// are located in the scanned part of the file. The `MMAP`, `COMM` etc.
+    /// records before `start` are returned as well, as with
+    /// [`PerfRecordIter::set_time_range`]; to find them, the headers of the
+    /// records before the scanned part are read.
+    ///
+    /// Any records which were buffered for sorting are discarded, so this can
+    /// be called at any time, also multiple times with different ranges.
+    pub fn seek_to_range(
+        &mut self,
+        index: &PerfFileIndex,
+        start: u64,
+        end: u64,
+        record_types: Option<&[RecordType]>,
+    ) -> Result<(), Error> {
+        let (range_start, range_end) = index.data_range_for_time_range(start, end);
+        self.time_range_filter = Some((start, end));
+        self.record_type_filter = record_types.map(<[RecordType]>::to_vec);
+        self.partial_record = PartialRecord::default();
+        self.split_record = None;
+        self.sorter = Sorter::new();
+        self.reader
+            .seek(SeekFrom::Start(self.data_section_offset))?;
+        if self.endian == Endianness::LittleEndian {
+            self.replay_process_metadata_impl::<byteorder::LittleEndian>(range_start)?;
+        } else {
+            self.replay_process_metadata_impl::<byteorder::BigEndian>(range_start)?;
+        }
+        self.reader
+            .seek(SeekFrom::Start(self.data_section_offset + range_start))?;
+        self.read_offset = range_start;
+        self.record_data_len = range_end;
+        Ok(())
+    }
+
+    /// Queues the `MMAP`, `COMM` etc. records in the first `len` bytes of the
+    /// data section, which [`PerfRecordIter::seek_to_range`] skips. Only the
+    /// headers of the other records are read.
+    fn replay_process_metadata_impl<T: ByteOrder>(&mut self, len: u64) -> Result<(), Error> {
+        let mut offset = 0;
+        #[cfg(feature = "zstd")]
+        let mut decompressor: Option<Decompressor> = None;
+        while offset < len {
+            let header = PerfEventHeader::parse::<_, T>(&mut self.reader)?;
+            let size = header.size as usize;
+            if size < PerfEventHeader::STRUCT_SIZE {
+                return Err(Error::InvalidPerfEventSize);
+            }
+            let body_size = size - PerfEventHeader::STRUCT_SIZE;
+            let record_offset = offset;
+            offset += u64::from(header.size);
+            let record_type = RecordType(header.type_);
+            let user_record_type = UserRecordType::try_from(record_type);
+            let is_auxtrace = user_record_type == Some(UserRecordType::PERF_AUXTRACE);
+            let is_compressed = user_record_type == Some(UserRecordType::PERF_COMPRESSED)
+                || user_record_type == Some(UserRecordType::PERF_COMPRESSED2);
+            if !is_process_metadata_record(record_type) && !is_auxtrace && !is_compressed {
+                self.reader.seek(SeekFrom::Current(body_size as i64))?;
+                continue;
+            }
+
+            let mut body = vec![0; body_size];
+            self.reader
+                .read_exact(&mut body)
+                .map_err(|_| ReadError::PerfEventData)?;
+            if is_auxtrace {
+                let aux_data_size = auxtrace_data_size::<T>(record_type, &body);
+                offset = self.skip_aux_data(offset, aux_data_size)?;
+                continue;
+            }
+            if is_compressed {
+                #[cfg(not(feature = "zstd"))]
+                return Err(Error::CompressedDataNotSupported);
+                #[cfg(feature = "zstd")]
+                {
+                    let decompressor = match &mut decompressor {
+                        Some(decompressor) => decompressor,
+                        None => decompressor.insert(Decompressor::new(self.max_decompressed_size)?),
+                    };
+                    decompressor.decompress(compressed_data::<T>(user_record_type, &body)?)?;
+                    while let Some((header, body)) = decompressor.next_record::<T>()? {
+                        if is_process_metadata_record(RecordType(header.type_)) {
+                            self.process_record::<T>(
+                                RecordType(header.type_),
+                                header.misc,
+                                body,
+                                record_offset,
+                            )?;
+                        }
+                    }
+                    continue;
+                }
+            }
+            self.process_record::<T>(
+                RecordType(header.type_),
+                header.misc,
+                RecordBody::Owned(body),
+                record_offset,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The parts of [`PerfRecordIter`] which are needed to find out which event a
+/// record belongs to. Unlike the iterator, this can be shared across threads.
+#[derive(Clone, Copy)]
+struct RecordClassifier<'a> {
+    id_parse_infos: &'a IdParseInfos,
+    /// Only empty for `perf stat record` files without attributes.
+    parse_infos: &'a [RecordParseInfo],
+    event_id_to_attr_index: &'a HashMap<u64, usize>,
+    untrusted_input: bool,
+}
+
+impl RecordClassifier<'_> {
+    /// Returns the attr index and the timestamp of the record, if available.
+    /// User records return `(None, None)`.
+    fn attr_index_and_timestamp<T: ByteOrder>(
+        &self,
+        record_type: RecordType,
+        data: RawData,
+    ) -> Result<(Option<usize>, Option<u64>), Error> {
+        if !record_type.is_builtin_type() {
+            return Ok((None, None));
+        }
+        if self.parse_infos.is_empty() {
+            return Err(Error::NoAttributes);
+        }
+        let id = match self.id_parse_infos {
+            IdParseInfos::OnlyOneEvent => None,
+            IdParseInfos::Same(id_parse_info) => {
+                get_record_id::<T>(record_type, data, id_parse_info)
+            }
+            IdParseInfos::PerAttribute(sample_id_all) => {
+                // We have IDENTIFIER (guaranteed by PerAttribute).
+                get_record_identifier::<T>(record_type, data, *sample_id_all)
+            }
+        };
+        let attr_index = match id {
+            Some(id) => match self.event_id_to_attr_index.get(&id) {
+                Some(attr_index) => *attr_index,
+                None if self.untrusted_input && !self.event_id_to_attr_index.is_empty() => {
+                    return Err(Error::UnknownEventId(id))
+                }
+                None => 0,
+            },
+            None => 0,
+        };
+        let parse_info = self.parse_infos[attr_index];
+        let timestamp = get_record_timestamp::<T>(record_type, data, &parse_info);
+        Ok((Some(attr_index), timestamp))
+    }
+
+    /// Builds the index for the records in `data`, which is a part of the
+    /// data section starting at `offset`. `data` must start at a record
+    /// boundary and must only contain complete records.
+    fn index_chunk<T: ByteOrder>(&self, data: &[u8], offset: u64) -> Result<IndexBuilder, Error> {
+        let mut builder = IndexBuilder::new(self.parse_infos.len(), offset);
+        let mut pos = 0;
+        while pos < data.len() {
+            let header = PerfEventHeader::parse::<_, T>(&data[pos..])?;
+            let size = header.size as usize;
+            if size < PerfEventHeader::STRUCT_SIZE || pos + size > data.len() {
+                return Err(Error::InvalidPerfEventSize);
+            }
+            let record_type = RecordType(header.type_);
+            let body = &data[pos + PerfEventHeader::STRUCT_SIZE..pos + size];
+            let record_size = usize::try_from(auxtrace_data_size::<T>(record_type, body))
+                .ok()
+                .and_then(|aux_data_size| size.checked_add(aux_data_size))
+                .filter(|record_size| *record_size <= data.len() - pos)
+                .ok_or(Error::InvalidPerfEventSize)?;
+            if UserRecordType::try_from(record_type) == Some(UserRecordType::PERF_FINISHED_ROUND) {
+                builder.add_finished_round(header.size.into());
+            } else {
+                let (attr_index, timestamp) =
+                    self.attr_index_and_timestamp::<T>(record_type, RawData::from(body))?;
+                builder.add_record(record_type, record_size as u64, attr_index, timestamp);
+            }
+            pos += record_size;
+        }
+        Ok(builder)
+    }
+}
+
+/// Returns the offset, relative to the start of `data`, of the end of the
+/// chunk which starts at the record at `start`: the first record boundary at
+/// least `target_chunk_size` bytes after `start`, or `data.len()`. Only the
+/// record headers are looked at.
+fn find_chunk_end<T: ByteOrder>(
+    data: &[u8],
+    start: usize,
+    target_chunk_size: usize,
+) -> Result<usize, Error> {
+    let target_end = start.saturating_add(target_chunk_size);
+    let mut pos = start;
+    while pos < data.len() && pos < target_end {
+        // The size is the u16 at the end of the 8-byte record header.
+        let size_bytes = data
+            .get(pos + 6..pos + 8)
+            .ok_or(Error::InvalidPerfEventSize)?;
+        let size = T::read_u16(size_bytes) as usize;
+        if size < PerfEventHeader::STRUCT_SIZE {
+            return Err(Error::InvalidPerfEventSize);
+        }
+        let record_type = RecordType(T::read_u32(&data[pos..pos + 4]));
+        let body = data.get(pos + PerfEventHeader::STRUCT_SIZE..pos + size);
+        let aux_data_size = auxtrace_data_size::<T>(record_type, body.unwrap_or_default());
+        pos = usize::try_from(aux_data_size)
+            .ok()
+            .and_then(|aux_data_size| (pos + size).checked_add(aux_data_size))
+            .ok_or(Error::InvalidPerfEventSize)?;
+    }
+    if pos > data.len() {
+        return Err(Error::InvalidPerfEventSize);
+    }
+    Ok(pos)
+}
+
+/// The saved iteration state of a [`PerfRecordIter`], see
+/// [`PerfRecordIter::state`] and [`PerfRecordIter::restore_state`].
+#[derive(Debug, Clone)]
+pub struct PerfRecordIterState {
+    read_offset: u64,
+    sorter: Sorter<Option<u64>, PendingRecord>,
+    partial_record: PartialRecord,
+}
+
+impl PerfRecordIterState {
+    /// The offset of the next record which will be read, relative to the
+    /// start of the data section.
+    pub fn read_offset(&self) -> u64 {
+        self.read_offset
+    }
+}
+
+/// The result of [`PerfRecordIter::follow`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FollowStatus {
+    /// `perf record` is still writing the file. `data_len` is the number of
+    /// bytes in the data section so far.
+    Growing { data_len: u64 },
+    /// `perf record` has finished and written the final data section size.
+    Finished { data_len: u64 },
+}
+
+/// The result of [`PerfRecordIter::poll_next_record`].
+pub enum PollRecord<'a> {
+    /// The next record.
+    Record(PerfFileRecord<'a>),
+    /// The reader doesn't have enough data for the next record yet.
+    NeedMoreData,
+    /// All records have been read.
+    EndOfData,
+}
+
+/// Like [`fill_buffer`], but reports a truncated record body as
+/// [`ReadError::PerfEventData`].
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Continues a 64-bit FNV-1a hash with `bytes`. Unlike the std hashers, this
+/// is stable across Rust versions, so the hash can be stored in files.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Adds a sample timestamp to the range found by
+/// [`PerfRecordIter::scan_sample_time_range`].
+fn extend_sample_time_range(time_range: &mut Option<SampleTimeRange>, timestamp: Option<u64>) {
+    let Some(timestamp) = timestamp else {
+        return;
+    };
+    let range = time_range.get_or_insert(SampleTimeRange {
+        first_sample_time: timestamp,
+        last_sample_time: timestamp,
+    });
+    range.first_sample_time = range.first_sample_time.min(timestamp);
+    range.last_sample_time = range.last_sample_time.max(timestamp);
+}
+
+/// Returns the zstd data in the body of a `COMPRESSED` or `COMPRESSED2`
+/// record.
+#[cfg(feature = "zstd")]
+fn compressed_data<T: ByteOrder>(
+    record_type: Option<UserRecordType>,
+    body: &[u8],
+) -> Result<&[u8], Error> {
+    if record_type != Some(UserRecordType::PERF_COMPRESSED2) {
+        return Ok(body);
+    }
+    // struct { u64 data_size; char data[]; }, padded to 8 bytes.
+    let data_size = body
+        .get(..8)
+        .map(T::read_u64)
+        .ok_or(ReadError::PerfEventData)?;
+    let data = usize::try_from(data_size)
+        .ok()
+        .and_then(|data_size| body.get(8..8usize.checked_add(data_size)?))
+        .ok_or(ReadError::PerfEventData)?;
+    Ok(data)
+}
+
+/// The decompressed size of a `COMPRESSED` record if the file has no
+/// `COMPRESSED` feature section which says how large the compressed mmap
+/// buffers were.
+#[cfg(feature = "zstd")]
+const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+/// The maximum size of the decompressed data of one `COMPRESSED` record. perf
+/// compresses at most one mmap buffer into each record, so like perf, this
+/// uses the mmap buffer size from the `COMPRESSED` feature section, with room
+/// for one more record for writers which finish a batch after the record
+/// which crosses the size.
+#[cfg(feature = "zstd")]
+fn max_decompressed_size<T: ByteOrder>(feature_sections: &LinearMap<Feature, Vec<u8>>) -> usize {
+    feature_sections
+        .get(&Feature::COMPRESSED)
+        .and_then(|section| CompressionInfo::parse::<_, T>(&section[..]).ok())
+        .filter(|info| info.mmap_len != 0)
+        .map_or(DEFAULT_MAX_DECOMPRESSED_SIZE, |info| {
+            info.mmap_len as usize + usize::from(u16::MAX)
+        })
+}
+
+/// Reads the encoded `perf_event_attr` of each entry of the attr section,
+/// without its trailing IDs section, so that [`crate::transcode`] can copy the
+/// attributes without re-encoding them.
+///
+/// Returns an empty list if the attr section is larger than
+/// `max_section_size`, cut off, or doesn't have one entry per attribute.
+fn read_raw_attrs<C: Read + Seek>(
+    cursor: &mut C,
+    header: &PerfHeader,
+    attr_count: usize,
+    max_section_size: u64,
+) -> Result<Vec<Vec<u8>>, Error> {
+    let section = &header.attr_section;
+    let Some(attr_len) = header.attr_size.checked_sub(PerfFileSection::STRUCT_SIZE) else {
+        return Ok(Vec::new());
+    };
+    if attr_len == 0
+        || section.size > max_section_size
+        || Some(section.size) != header.attr_size.checked_mul(attr_count as u64)
+    {
+        return Ok(Vec::new());
+    }
+    let mut data = Vec::new();
+    cursor.seek(SeekFrom::Start(section.offset))?;
+    (&mut *cursor).take(section.size).read_to_end(&mut data)?;
+    if (data.len() as u64) < section.size {
+        return Ok(Vec::new());
+    }
+    Ok(data
+        .chunks_exact(header.attr_size as usize)
+        .map(|entry| entry[..attr_len as usize].to_vec())
+        .collect())
+}
+
+fn fill_body<R: Read>(reader: &mut R, body: &mut [u8], filled: &mut usize) -> Result<(), Error> {
+    fill_buffer(reader, body, filled).map_err(|e| match e {
+        Error::IoError(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            ReadError::PerfEventData.into()
+        }
+        e => e,
+    })
+}
+
+/// Splits the bytes of a record which simpleperf has written as a sequence
+/// of `SIMPLEPERF_SPLIT` records into its record type and its body.
+///
+/// Records which are too large for the 16 bit size field of the
+/// `perf_event_header` are split by simpleperf. They are always simpleperf
+/// record types, whose header has a 32 bit size: the high 16 bits are where
+/// `misc` would be, and the low 16 bits are in the `size` field. So these
+/// records have no `misc` value, and their size is the length of the body
+/// plus the header size.
+fn join_split_record<T: ByteOrder>(mut data: Vec<u8>) -> Result<(RecordType, Vec<u8>), Error> {
+    if data.len() < PerfEventHeader::STRUCT_SIZE {
+        return Err(Error::InvalidPerfEventSize);
+    }
+    let type_ = T::read_u32(&data[0..4]);
+    let size_high = T::read_u16(&data[4..6]);
+    let size_low = T::read_u16(&data[6..8]);
+    let size = (usize::from(size_high) << 16) | usize::from(size_low);
+    if size < PerfEventHeader::STRUCT_SIZE || size > data.len() {
+        return Err(Error::InvalidPerfEventSize);
+    }
+    data.truncate(size);
+    data.drain(..PerfEventHeader::STRUCT_SIZE);
+    Ok((RecordType(type_), data))
+}
+
+/// The largest record which is accepted from a sequence of
+/// `SIMPLEPERF_SPLIT` records. The parts are buffered in memory until the
+/// `SIMPLEPERF_SPLIT_END` record, so a corrupt file without one must not lead
+/// to an unbounded allocation.
+const MAX_SPLIT_RECORD_SIZE: usize = 1 << 30;
+
+/// The largest aux data size which is accepted for an `AUXTRACE` record
+/// while the size of the data section isn't known yet, i.e. for a file which
+/// is still being written or for pushed data. The aux data is buffered in
+/// memory, so a corrupt size must not lead to an unbounded allocation. Aux
+/// buffers are much smaller than this in practice.
+const MAX_PENDING_AUX_DATA_SIZE: u64 = 1 << 30;
+
+/// Returns the size of the aux data which follows a `PERF_RECORD_AUXTRACE`
+/// record in the data section, or 0 for other records. The aux data isn't
+/// included in the size in the record header; its size is the first field of
+/// the record body.
+fn auxtrace_data_size<T: ByteOrder>(record_type: RecordType, body: &[u8]) -> u64 {
+    match body.get(..8) {
+        Some(size)
+            if UserRecordType::try_from(record_type) == Some(UserRecordType::PERF_AUXTRACE) =>
+        {
+            T::read_u64(size)
+        }
+        _ => 0,
+    }
+}
+
+/// Reads and discards `len` bytes of a record body, updating `skipped` as
+/// data arrives so that skipping can be resumed after an error.
+fn skip_body<R: Read>(reader: &mut R, len: usize, skipped: &mut usize) -> Result<(), Error> {
+    let mut scratch = [0; 4096];
+    while *skipped < len {
+        let chunk_len = (len - *skipped).min(scratch.len());
+        let mut filled = 0;
+        let result = fill_body(reader, &mut scratch[..chunk_len], &mut filled);
+        *skipped += filled;
+        result?;
+    }
+    Ok(())
+}
+
+/// Whether records of type `record_type` describe the memory mappings and
+/// the threads of processes. These records are kept by
+/// [`PerfRecordIter::set_time_range`] even before the start of the range.
+fn is_process_metadata_record(record_type: RecordType) -> bool {
+    matches!(
+        record_type,
+        RecordType::MMAP
+            | RecordType::MMAP2
+            | RecordType::COMM
+            | RecordType::FORK
+            | RecordType::EXIT
+    )
+}
+
+/// Whether the records of type `record_type` are read with the record type
+/// filter `filter`, rather than being skipped after their header. The
+/// records which the iterator needs itself are always read.
+fn reads_record_type(filter: Option<&[RecordType]>, record_type: RecordType) -> bool {
+    let Some(record_types) = filter else {
+        return true;
+    };
+    record_types.contains(&record_type)
+        || matches!(
+            UserRecordType::try_from(record_type),
+            Some(
+                UserRecordType::PERF_FINISHED_ROUND
+                    | UserRecordType::PERF_COMPRESSED
+                    | UserRecordType::PERF_COMPRESSED2
+                    | UserRecordType::SIMPLEPERF_SPLIT
+                    | UserRecordType::SIMPLEPERF_SPLIT_END
+                    // The size of the aux data which follows the record is
+                    // in the body.
+                    | UserRecordType::PERF_AUXTRACE
+            )
+        )
+        || updates_perf_file(record_type)
+}
+
+/// Whether the records of type `record_type` are applied to the [`PerfFile`]
+/// by [`PerfRecordIter::update_perf_file`]. They are read and applied even
+/// if the record type filter drops them.
+fn updates_perf_file(record_type: RecordType) -> bool {
+    matches!(
+        UserRecordType::try_from(record_type),
+        Some(
+            UserRecordType::PERF_EVENT_UPDATE
+                | UserRecordType::PERF_HEADER_BUILD_ID
+                | UserRecordType::PERF_ID_INDEX
+                | UserRecordType::SIMPLEPERF_EVENT_ID
+        )
+    )
+}
+
+/// Reads into `buffer[*filled..]` until the buffer is full, updating `filled`
+/// as data arrives so that reading can be resumed after an error.
+fn fill_buffer<R: Read>(
+    reader: &mut R,
+    buffer: &mut [u8],
+    filled: &mut usize,
+) -> Result<(), Error> {
+    while *filled < buffer.len() {
+        match reader.read(&mut buffer[*filled..]) {
+            Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+            Ok(n) => *filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// A record which has been partially read from the reader.
+#[derive(Debug, Clone, Default)]
+struct PartialRecord {
+    header: [u8; PerfEventHeader::STRUCT_SIZE],
+    header_len: usize,
+    /// Allocated once the header has been read.
+    body: Option<Vec<u8>>,
+    body_len: usize,
+    /// True if the body is skipped because of the record type filter.
+    /// `body_len` is then the number of skipped bytes.
+    skipping_body: bool,
+}
+
+/// A record type which this crate doesn't know about, returned by
+/// [`PerfRecordIter::unknown_record_types`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownRecordType {
+    pub record_type: RecordType,
+    /// The name of the type if it was declared with
+    /// [`PerfRecordIter::set_custom_record_types`].
+    pub name: Option<String>,
+    /// The number of records of this type.
+    pub count: u64,
+    /// The offset of the first record of this type, see
+    /// [`PerfFileRecord::offset`].
+    pub first_offset: u64,
+}
+
+fn is_known_record_type(record_type: RecordType) -> bool {
+    match UserRecordType::try_from(record_type) {
+        Some(user_record_type) => user_record_type.is_known(),
+        None => (1..=PERF_RECORD_AUX_OUTPUT_HW_ID).contains(&record_type.0),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PendingRecord {
+    record_type: RecordType,
+    misc: u16,
+    body: RecordBody,
+    attr_index: Option<usize>,
+    /// See [`PerfFileRecord::offset`].
+    offset: u64,
+}
+
+#[derive(Debug, Clone)]
+enum IdParseInfos {
+    /// There is only one event.
+    OnlyOneEvent,
+    /// There are multiple events, but all events are parsed the same way.
+    Same(RecordIdParseInfo),
+    /// All elements are guaranteed to have [`SampleFormat::IDENTIFIER`] set in `attr.sample_format`.
+    /// The inner element indicates sample_id_all.
+    PerAttribute(bool),
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read};
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use linux_perf_event_reader::{Endianness, RecordType, SoftwareCounterType};
+
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::{FollowStatus, PerfFileReader, PerfRecordIter, PollRecord};
+    use crate::constants::PERF_RECORD_THREAD_MAP;
+    use crate::file_writer::test::{sample_body, test_attr};
+    use crate::{
+        CustomRecordTypes, Error, Feature, OwnedPerfFileRecord, PerfFile, PerfFileRecord,
+        PerfFileWriter, PerfRecordPushParser, RawUserRecord, UserRecord,
+    };
+
+    const SAMPLE: u32 = 9;
+    const FINISHED_ROUND: u32 = 68;
+
+    /// Encodes a list of (record type, timestamp) pairs into records for an
+    /// event whose samples only contain a timestamp. The timestamp is ignored
+    /// for FINISHED_ROUND records.
+    fn encode_records(records: &[(u32, u64)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &(record_type, timestamp) in records {
+            data.write_u32::<LittleEndian>(record_type).unwrap();
+            data.write_u16::<LittleEndian>(0).unwrap();
+            if record_type == FINISHED_ROUND {
+                data.write_u16::<LittleEndian>(8).unwrap();
+            } else {
+                data.write_u16::<LittleEndian>(16).unwrap();
+                data.write_u64::<LittleEndian>(timestamp).unwrap();
+            }
+        }
+        data
+    }
+
+    /// Builds a perf.data file with a single event whose samples only contain
+    /// a timestamp.
+    fn build_file(records: &[(u32, u64)]) -> Vec<u8> {
+        build_file_with_data(&encode_records(records))
+    }
+
+    fn build_file_with_data(data: &[u8]) -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(b"PERFILE2");
+        for value in [104, 64, 104, 64, 168, data.len() as u64, 0, 0, 0, 0, 0, 0] {
+            file.write_u64::<LittleEndian>(value).unwrap();
+        }
+        // perf_event_attr (PERF_ATTR_SIZE_VER0), with sample_type = PERF_SAMPLE_TIME.
+        file.write_u32::<LittleEndian>(0).unwrap();
+        file.write_u32::<LittleEndian>(64).unwrap();
+        for value in [0, 1000, 1 << 2, 0, 0, 0, 0] {
+            file.write_u64::<LittleEndian>(value).unwrap();
+        }
+        file.extend_from_slice(data);
+        file
+    }
+
+    fn timestamps(file: &[u8], range: Option<(u64, u64)>) -> Vec<u64> {
+        let PerfFileReader {
+            perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(file)).unwrap();
+        if let Some((start, end)) = range {
+            let index = record_iter.build_index().unwrap();
+            record_iter.seek_to_range(&index, start, end, None).unwrap();
+        }
+        collect_timestamps(perf_file, record_iter)
+    }
+
+    fn collect_timestamps<R: Read>(
+        mut perf_file: PerfFile,
+        mut record_iter: PerfRecordIter<R>,
+    ) -> Vec<u64> {
+        let mut timestamps = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            if let PerfFileRecord::EventRecord { record, .. } = record {
+                timestamps.push(record.timestamp().unwrap());
+            }
+        }
+        timestamps
+    }
+
+    #[test]
+    fn raw_header() {
+        let file = build_file(&[(SAMPLE, 10)]);
+        let reader = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let header = reader.perf_file.header();
+        assert_eq!(&header.magic, b"PERFILE2");
+        assert_eq!(header.header_size, 104);
+        assert_eq!(header.attr_size, 64);
+        assert_eq!(header.attr_section.offset, 104);
+        assert_eq!(header.data_section.offset, 168);
+        assert_eq!(header.data_section.size, 16);
+        assert_eq!(header.event_types_section.size, 0);
+        assert_eq!(header.features.0, [0; 4]);
+    }
+
+    #[test]
+    fn zero_attr_size_is_an_error() {
+        let mut file = build_file(&[(SAMPLE, 10)]);
+        file[16..24].copy_from_slice(&0u64.to_le_bytes());
+        assert!(PerfFileReader::parse_file(Cursor::new(&file[..])).is_err());
+    }
+
+    #[test]
+    fn stat_file_without_attributes() {
+        let mut file = build_file(&[(SAMPLE, 10)]);
+        // Remove the attribute.
+        file[32..40].copy_from_slice(&0u64.to_le_bytes());
+        assert!(matches!(
+            PerfFileReader::parse_file(Cursor::new(&file[..])),
+            Err(Error::NoAttributes)
+        ));
+
+        // With the STAT feature, the file can be opened, but event records
+        // can't be read.
+        file[72..80].copy_from_slice(&(1u64 << 19).to_le_bytes());
+        file.write_u64::<LittleEndian>(0).unwrap();
+        file.write_u64::<LittleEndian>(0).unwrap();
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        assert!(perf_file.event_attributes().is_empty());
+        assert!(matches!(
+            record_iter.next_record(&mut perf_file),
+            Err(Error::NoAttributes)
+        ));
+    }
+
+    #[test]
+    fn stat_file_user_records_without_attributes() {
+        // A THREAD_MAP record with one thread.
+        let mut data = Vec::new();
+        data.write_u32::<LittleEndian>(PERF_RECORD_THREAD_MAP)
+            .unwrap();
+        data.write_u16::<LittleEndian>(0).unwrap();
+        data.write_u16::<LittleEndian>(40).unwrap();
+        data.write_u64::<LittleEndian>(1).unwrap();
+        data.write_u64::<LittleEndian>(542572).unwrap();
+        data.extend_from_slice(&[0; 16]);
+        let mut file = build_file_with_data(&data);
+        // Remove the attribute and set the STAT feature.
+        file[32..40].copy_from_slice(&0u64.to_le_bytes());
+        file[72..80].copy_from_slice(&(1u64 << 19).to_le_bytes());
+        file.write_u64::<LittleEndian>(0).unwrap();
+        file.write_u64::<LittleEndian>(0).unwrap();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        assert!(perf_file.event_attributes().is_empty());
+        let Some(PerfFileRecord::UserRecord(record)) =
+            record_iter.next_record(&mut perf_file).unwrap()
+        else {
+            panic!("expected a user record");
+        };
+        let UserRecord::ThreadMap(thread_map) = record.parse().unwrap() else {
+            panic!("expected a THREAD_MAP record");
+        };
+        assert_eq!(
+            thread_map.iter().map(|entry| entry.tid).collect::<Vec<_>>(),
+            vec![542572]
+        );
+        assert!(record_iter.next_record(&mut perf_file).unwrap().is_none());
+    }
+
+    #[test]
+    fn untrusted_input() {
+        // Two events with sample_type = PERF_SAMPLE_IDENTIFIER | PERF_SAMPLE_TIME,
+        // described by an event_types section, with the event IDs 1 and 2.
+        let mut data = Vec::new();
+        for (id, timestamp) in [(1, 10), (3, 20)] {
+            data.write_u32::<LittleEndian>(SAMPLE).unwrap();
+            data.write_u16::<LittleEndian>(0).unwrap();
+            data.write_u16::<LittleEndian>(24).unwrap();
+            data.write_u64::<LittleEndian>(id).unwrap();
+            data.write_u64::<LittleEndian>(timestamp).unwrap();
+        }
+        let mut file = Vec::new();
+        file.extend_from_slice(b"PERFILE2");
+        for value in [
+            104,
+            64,
+            104,
+            0,
+            280,
+            data.len() as u64,
+            104,
+            160,
+            0,
+            0,
+            0,
+            0,
+        ] {
+            file.write_u64::<LittleEndian>(value).unwrap();
+        }
+        for ids_offset in [264, 272] {
+            file.write_u32::<LittleEndian>(0).unwrap();
+            file.write_u32::<LittleEndian>(64).unwrap();
+            for value in [0, 1000, 1 << 16 | 1 << 2, 0, 0, 0, 0] {
+                file.write_u64::<LittleEndian>(value).unwrap();
+            }
+            file.write_u64::<LittleEndian>(ids_offset).unwrap();
+            file.write_u64::<LittleEndian>(8).unwrap();
+        }
+        file.write_u64::<LittleEndian>(1).unwrap();
+        file.write_u64::<LittleEndian>(2).unwrap();
+        file.extend_from_slice(&data);
+
+        // By default, the record with the unknown event ID 3 is attributed
+        // to the first event.
+        assert_eq!(timestamps(&file, None), vec![10, 20]);
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        record_iter.set_untrusted_input(true);
+        assert!(matches!(
+            record_iter.next_record(&mut perf_file),
+            Err(Error::UnknownEventId(3))
+        ));
+    }
+
+    #[test]
+    fn unknown_feature_section_is_kept() {
+        let mut file = build_file(&[(SAMPLE, 10)]);
+        // Set the bit for feature 200, which this crate doesn't know about,
+        // and append its section after the data section.
+        file[96..104].copy_from_slice(&(1u64 << (200 - 192)).to_le_bytes());
+        let section_offset = file.len() as u64 + 16;
+        file.write_u64::<LittleEndian>(section_offset).unwrap();
+        file.write_u64::<LittleEndian>(3).unwrap();
+        file.extend_from_slice(b"abc");
+
+        let reader = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let perf_file = reader.perf_file;
+        let unknown = Feature(200);
+        assert!(perf_file.features().has_feature(unknown));
+        assert_eq!(perf_file.feature_section_data(unknown), Some(&b"abc"[..]));
+        assert_eq!(
+            perf_file.unknown_feature_sections().collect::<Vec<_>>(),
+            vec![(200, &b"abc"[..])]
+        );
+        assert_eq!(
+            perf_file.feature_section_location(unknown),
+            Some((section_offset, 3))
+        );
+
+        // Sections above the size limit are only available as a reader.
+        let reader =
+            PerfFileReader::parse_file_with_max_feature_section_size(Cursor::new(&file[..]), 2)
+                .unwrap();
+        let perf_file = reader.perf_file;
+        assert!(perf_file.features().has_feature(unknown));
+        assert_eq!(perf_file.feature_section_data(unknown), None);
+        let mut data = Vec::new();
+        perf_file
+            .feature_section_reader(unknown, Cursor::new(&file[..]))
+            .unwrap()
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, b"abc");
+    }
+
+    #[test]
+    fn simpleperf_split_records() {
+        // A simpleperf TRACING_DATA record which is too large for a 16 bit
+        // size, split into two SPLIT records and a SPLIT_END record.
+        let record_size: usize = 70000;
+        let mut record = Vec::new();
+        record
+            .write_u32::<LittleEndian>(crate::constants::SIMPLE_PERF_RECORD_TRACING_DATA)
+            .unwrap();
+        record
+            .write_u16::<LittleEndian>((record_size >> 16) as u16)
+            .unwrap();
+        record
+            .write_u16::<LittleEndian>(record_size as u16)
+            .unwrap();
+        record.extend((8..record_size).map(|i| i as u8));
+
+        let mut data = Vec::new();
+        for chunk in record.chunks(65535 - 8) {
+            data.write_u32::<LittleEndian>(crate::constants::SIMPLE_PERF_RECORD_SPLIT)
+                .unwrap();
+            data.write_u16::<LittleEndian>(0).unwrap();
+            data.write_u16::<LittleEndian>(8 + chunk.len() as u16)
+                .unwrap();
+            data.extend_from_slice(chunk);
+        }
+        data.write_u32::<LittleEndian>(crate::constants::SIMPLE_PERF_RECORD_SPLIT_END)
+            .unwrap();
+        data.write_u16::<LittleEndian>(0).unwrap();
+        data.write_u16::<LittleEndian>(8).unwrap();
+        data.extend_from_slice(&encode_records(&[(SAMPLE, 10), (SAMPLE, 20)]));
+        let file = build_file_with_data(&data);
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            match record {
+                PerfFileRecord::EventRecord { record, .. } => {
+                    records.push((record.record_type.0, record.data.len(), 0))
+                }
+                PerfFileRecord::UserRecord(record) => records.push((
+                    record.record_type.record_type().0,
+                    record.data.len(),
+                    record.data.as_slice()[100],
+                )),
+            }
+        }
+        assert_eq!(
+            records,
+            vec![
+                (
+                    crate::constants::SIMPLE_PERF_RECORD_TRACING_DATA,
+                    record_size - 8,
+                    108
+                ),
+                (SAMPLE, 8, 0),
+                (SAMPLE, 8, 0),
+            ]
+        );
+        assert_eq!(record_iter.unknown_record_types().count(), 0);
+    }
+
+    #[test]
+    fn scan_sample_time_range() {
+        let file = build_file(&[
+            (SAMPLE, 10),
+            (SAMPLE, 5),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 20),
+            (SAMPLE, 15),
+        ]);
+        let mut reader = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        assert!(reader.perf_file.sample_time_range().unwrap().is_none());
+        let Some(PerfFileRecord::EventRecord { record, .. }) = reader
+            .record_iter
+            .next_record(&mut reader.perf_file)
+            .unwrap()
+        else {
+            panic!("expected a sample");
+        };
+        assert_eq!(record.timestamp(), Some(5));
+
+        let time_range = reader.sample_time_range().unwrap().unwrap();
+        assert_eq!(time_range.first_sample_time, 5);
+        assert_eq!(time_range.last_sample_time, 20);
+        // The scan doesn't affect the iteration.
+        assert_eq!(
+            collect_timestamps(reader.perf_file, reader.record_iter),
+            vec![10, 15, 20]
+        );
+    }
+
+    #[test]
+    fn index_and_seek() {
+        let file = build_file(&[
+            (SAMPLE, 10),
+            (SAMPLE, 5),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 20),
+            (SAMPLE, 15),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 30),
+            (SAMPLE, 25),
+        ]);
+
+        let PerfFileReader {
+            perf_file: _,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let index = record_iter.build_index().unwrap();
+        assert_eq!(index.rounds.len(), 3);
+        assert_eq!(index.record_count(), 6);
+        assert_eq!(index.record_counts[&RecordType::SAMPLE], 6);
+        assert_eq!(index.sample_counts_per_attr, vec![6]);
+        let sample_time_range = index.sample_time_range.unwrap();
+        assert_eq!(sample_time_range.first_sample_time, 5);
+        assert_eq!(sample_time_range.last_sample_time, 30);
+        assert_eq!(index.data_range_for_time_range(16, 26), (40, 112));
+        assert_eq!(index.data_range_for_time_range(0, 12), (0, 40));
+        assert_eq!(index.data_range_for_time_range(40, 50), (112, 112));
+
+        assert_eq!(timestamps(&file, None), vec![5, 10, 15, 20, 25, 30]);
+        assert_eq!(timestamps(&file, Some((16, 26))), vec![20, 25]);
+        assert_eq!(timestamps(&file, Some((40, 50))), Vec::<u64>::new());
+
+        for thread_count in 0..8 {
+            let parallel_index = record_iter
+                .build_index_from_slice(&file, thread_count)
+                .unwrap();
+            assert_eq!(parallel_index, index, "thread_count: {thread_count}");
+        }
+    }
+
+    #[test]
+    fn seek_to_range_replays_process_metadata() {
+        const COMM: u32 = 3;
+        let file = build_file(&[
+            (COMM, 0),
+            (SAMPLE, 10),
+            (FINISHED_ROUND, 0),
+            (COMM, 0),
+            (SAMPLE, 20),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 30),
+        ]);
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let index = record_iter.build_index().unwrap();
+        assert_eq!(index.data_range_for_time_range(25, 35).0, 80);
+        record_iter.seek_to_range(&index, 25, 35, None).unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            let offset = record.offset();
+            if let PerfFileRecord::EventRecord { record, .. } = record {
+                records.push((record.record_type, offset));
+            }
+        }
+        assert_eq!(
+            records,
+            vec![
+                (RecordType::COMM, 0),
+                (RecordType::COMM, 40),
+                (RecordType::SAMPLE, 80),
+            ]
+        );
+    }
+
+    #[test]
+    fn chunked_iteration() {
+        let file = build_file(&[
+            (SAMPLE, 20),
+            (SAMPLE, 10),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 30),
+            (SAMPLE, 40),
+        ]);
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let chunks = record_iter.split_data_section(2).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].start, record_iter.data_section_range().start);
+        assert_eq!(chunks[1].end, record_iter.data_section_range().end);
+
+        let mut timestamps = Vec::new();
+        for chunk in chunks {
+            let mut chunk_iter = record_iter
+                .chunk_iter(Cursor::new(&file[..]), chunk)
+                .unwrap();
+            while let Some(record) = chunk_iter.next_record(&mut perf_file).unwrap() {
+                if let PerfFileRecord::EventRecord { record, .. } = record {
+                    timestamps.push(record.timestamp().unwrap());
+                }
+            }
+        }
+        assert_eq!(timestamps, vec![10, 20, 30, 40]);
+    }
+
+    /// Encodes an AUXTRACE record for CPU 3, without the aux data which
+    /// follows it and which isn't included in the record size.
+    fn encode_auxtrace_record(aux_data_size: u64) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.write_u32::<LittleEndian>(71).unwrap();
+        data.write_u16::<LittleEndian>(0).unwrap();
+        data.write_u16::<LittleEndian>(48).unwrap();
+        for value in [aux_data_size, 0x1000, 7] {
+            data.write_u64::<LittleEndian>(value).unwrap();
+        }
+        for value in [3u32, u32::MAX, 3, 0] {
+            data.write_u32::<LittleEndian>(value).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn auxtrace_data() {
+        let mut data = encode_records(&[(SAMPLE, 10)]);
+        data.extend_from_slice(&encode_auxtrace_record(5));
+        data.extend_from_slice(&[1, 2, 3, 4, 5]);
+        data.extend_from_slice(&encode_records(&[(SAMPLE, 20)]));
+        let file = build_file_with_data(&data);
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        assert_eq!(record_iter.build_index().unwrap().record_count(), 3);
+        // The second chunk starts after the aux data.
+        let chunks = record_iter.split_data_section(3).unwrap();
+        assert_eq!(chunks, vec![168..168 + 69, 168 + 69..168 + 85]);
+        let mut timestamps = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            match record {
+                PerfFileRecord::EventRecord { record, .. } => {
+                    timestamps.push(record.timestamp().unwrap())
+                }
+                PerfFileRecord::UserRecord(record) => match record.parse().unwrap() {
+                    UserRecord::Auxtrace(auxtrace) => {
+                        assert_eq!(auxtrace.offset, 0x1000);
+                        assert_eq!(auxtrace.cpu(), Some(3));
+                        assert_eq!(&auxtrace.data.as_slice()[..], &[1, 2, 3, 4, 5]);
+                    }
+                    _ => panic!("unexpected user record"),
+                },
+            }
+        }
+        assert_eq!(timestamps, vec![10, 20]);
+    }
+
+    #[test]
+    fn malformed_auxtrace_data() {
+        for aux_data_size in [6, 1 << 40, i64::MAX as u64 + 1, u64::MAX - 8, u64::MAX] {
+            let mut data = encode_records(&[(SAMPLE, 10)]);
+            data.extend_from_slice(&encode_auxtrace_record(aux_data_size));
+            data.extend_from_slice(&[1, 2, 3, 4, 5]);
+            let file = build_file_with_data(&data);
+            let is_invalid_size =
+                |result: Result<_, Error>| matches!(result, Err(Error::InvalidPerfEventSize));
+
+            let PerfFileReader {
+                mut perf_file,
+                mut record_iter,
+            } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+            assert!(is_invalid_size(record_iter.build_index().map(|_| ())));
+            assert!(is_invalid_size(
+                record_iter.split_data_section(3).map(|_| ())
+            ));
+            assert!(is_invalid_size(
+                record_iter.read_stat_metadata(&perf_file).map(|_| ())
+            ));
+            assert!(is_invalid_size(
+                record_iter.scan_sample_time_range().map(|_| ())
+            ));
+            assert!(is_invalid_size(
+                record_iter.next_record(&mut perf_file).map(|_| ())
+            ));
+
+            // While the data section is growing, the aux data may not be
+            // there yet, but it still can't be arbitrarily large.
+            let PerfFileReader {
+                mut perf_file,
+                mut record_iter,
+            } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+            record_iter.set_data_len(data.len() as u64, false);
+            let result = record_iter.next_record(&mut perf_file).map(|_| ());
+            if aux_data_size == 6 {
+                // Waits for the missing byte.
+                assert!(result.is_err());
+            } else {
+                assert!(is_invalid_size(result));
+            }
+        }
+    }
+
+    #[test]
+    fn trust_file_order() {
+        let file = build_file(&[(SAMPLE, 10), (SAMPLE, 5), (FINISHED_ROUND, 0), (SAMPLE, 20)]);
+        let PerfFileReader {
+            perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        record_iter.set_trust_file_order(true);
+        assert_eq!(collect_timestamps(perf_file, record_iter), vec![10, 5, 20]);
+    }
+
+    #[test]
+    fn unknown_record_types() {
+        let file = build_file(&[(SAMPLE, 10), (40, 11), (200, 12), (40, 13)]);
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        while record_iter.next_record(&mut perf_file).unwrap().is_some() {}
+        let unknown: Vec<_> = record_iter
+            .unknown_record_types()
+            .map(|entry| (entry.record_type.0, entry.count, entry.first_offset))
+            .collect();
+        assert_eq!(unknown, vec![(40, 2, 16), (200, 1, 32)]);
+    }
+
+    #[test]
+    fn custom_record_types() {
+        fn parse_timestamp(
+            record: &RawUserRecord<'_>,
+        ) -> Result<std::sync::Arc<dyn std::any::Any + Send + Sync>, std::io::Error> {
+            let mut cursor = record.data;
+            Ok(std::sync::Arc::new(cursor.read_u64::<LittleEndian>()?))
+        }
+
+        let file = build_file(&[(SAMPLE, 10), (40000, 11), (40002, 12)]);
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let mut custom_record_types = CustomRecordTypes::new();
+        custom_record_types
+            .add_type(40000, "vendor_clock", Some(parse_timestamp))
+            .add_range(40000..=40031, "vendor_reserved", None);
+        record_iter.set_custom_record_types(custom_record_types);
+
+        let mut parsed = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            if let PerfFileRecord::UserRecord(record) = record {
+                let name = record
+                    .custom_type
+                    .map(|custom_type| custom_type.name.clone());
+                let value = match record.parse().unwrap() {
+                    UserRecord::Custom(custom) => custom.downcast_ref::<u64>().copied(),
+                    _ => None,
+                };
+                parsed.push((name, value));
+            }
+        }
+        assert_eq!(
+            parsed,
+            vec![
+                (Some("vendor_clock".to_string()), Some(11)),
+                (Some("vendor_reserved".to_string()), None)
+            ]
+        );
+        let unknown: Vec<_> = record_iter
+            .unknown_record_types()
+            .map(|entry| entry.name.clone())
+            .collect();
+        assert_eq!(
+            unknown,
+            vec![
+                Some("vendor_clock".to_string()),
+                Some("vendor_reserved".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn owned_records() {
+        let file = build_file(&[(SAMPLE, 10), (SAMPLE, 5), (FINISHED_ROUND, 0)]);
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_owned_record(&mut perf_file).unwrap() {
+            records.push(record);
+        }
+        let timestamps: Vec<_> = records
+            .iter()
+            .map(|record| match record.as_record() {
+                PerfFileRecord::EventRecord {
+                    attr_index, record, ..
+                } => {
+                    assert_eq!(attr_index, 0);
+                    record.timestamp().unwrap()
+                }
+                PerfFileRecord::UserRecord(_) => panic!("unexpected user record"),
+            })
+            .collect();
+        assert_eq!(timestamps, vec![5, 10]);
+        let offsets: Vec<_> = records
+            .iter()
+            .map(|record| record.as_record().offset())
+            .collect();
+        assert_eq!(offsets, vec![16, 0]);
+        for record in records {
+            record_iter.provide_buffer(record.into_body());
+        }
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_records() {
+        use std::io::Write;
+
+        const COMPRESSED: u32 = 81;
+        const COMPRESSED2: u32 = 83;
+
+        // Compress the records as one stream, and split it into three chunks
+        // so that records straddle the chunk boundaries, like perf does.
+        let uncompressed = encode_records(&[
+            (SAMPLE, 10),
+            (SAMPLE, 5),
+            (SAMPLE, 20),
+            (SAMPLE, 15),
+            (SAMPLE, 30),
+        ]);
+        let mut encoder = zstd::stream::Encoder::new(Vec::new(), 3).unwrap();
+        let mut chunks = Vec::new();
+        for part in [
+            &uncompressed[..20],
+            &uncompressed[20..36],
+            &uncompressed[36..],
+        ] {
+            encoder.write_all(part).unwrap();
+            encoder.flush().unwrap();
+            chunks.push(std::mem::take(encoder.get_mut()));
+        }
+
+        let mut data = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            if i == 1 {
+                let padded_len = chunk.len().next_multiple_of(8);
+                data.write_u32::<LittleEndian>(COMPRESSED2).unwrap();
+                data.write_u16::<LittleEndian>(0).unwrap();
+                data.write_u16::<LittleEndian>(16 + padded_len as u16)
+                    .unwrap();
+                data.write_u64::<LittleEndian>(chunk.len() as u64).unwrap();
+                data.extend_from_slice(chunk);
+                data.resize(data.len() + padded_len - chunk.len(), 0);
+            } else {
+                data.write_u32::<LittleEndian>(COMPRESSED).unwrap();
+                data.write_u16::<LittleEndian>(0).unwrap();
+                data.write_u16::<LittleEndian>(8 + chunk.len() as u16)
+                    .unwrap();
+                data.extend_from_slice(chunk);
+            }
+        }
+        data.extend_from_slice(&encode_records(&[(FINISHED_ROUND, 0), (SAMPLE, 40)]));
+
+        let file = build_file_with_data(&data);
+        assert_eq!(timestamps(&file, None), vec![5, 10, 15, 20, 30, 40]);
+
+        let mut reader = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let time_range = reader
+            .record_iter
+            .scan_sample_time_range()
+            .unwrap()
+            .unwrap();
+        assert_eq!(time_range.first_sample_time, 5);
+        assert_eq!(time_range.last_sample_time, 40);
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn scan_compressed_records_without_zstd() {
+        const COMPRESSED: u32 = 81;
+
+        let mut data = encode_records(&[(SAMPLE, 10)]);
+        data.write_u32::<LittleEndian>(COMPRESSED).unwrap();
+        data.write_u16::<LittleEndian>(0).unwrap();
+        data.write_u16::<LittleEndian>(16).unwrap();
+        data.extend_from_slice(&[0; 8]);
+        let file = build_file_with_data(&data);
+
+        let mut reader = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        assert!(matches!(
+            reader.record_iter.scan_sample_time_range(),
+            Err(Error::CompressedDataNotSupported)
+        ));
+    }
+
+    /// Once `trickle` is set, returns one byte per read call, and `WouldBlock`
+    /// before every byte.
+    struct TrickleReader<'a> {
+        data: Cursor<&'a [u8]>,
+        trickle: Rc<Cell<bool>>,
+        would_block: bool,
+    }
+
+    impl std::io::Read for TrickleReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if !self.trickle.get() {
+                return self.data.read(buf);
+            }
+            self.would_block = !self.would_block;
+            if self.would_block {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            let len = buf.len().min(1);
+            self.data.read(&mut buf[..len])
+        }
+    }
+
+    impl std::io::Seek for TrickleReader<'_> {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    #[test]
+    fn poll_with_would_block() {
+        let file = build_file(&[(SAMPLE, 10), (SAMPLE, 5), (FINISHED_ROUND, 0), (SAMPLE, 20)]);
+        // Parse the headers normally, then start trickling for the records.
+        let trickle = Rc::new(Cell::new(false));
+        let reader = TrickleReader {
+            data: Cursor::new(&file[..]),
+            trickle: trickle.clone(),
+            would_block: false,
+        };
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(reader).unwrap();
+        trickle.set(true);
+        let mut timestamps = Vec::new();
+        let mut need_more_data_count = 0;
+        loop {
+            match record_iter.poll_next_record(&mut perf_file).unwrap() {
+                PollRecord::Record(PerfFileRecord::EventRecord { record, .. }) => {
+                    timestamps.push(record.timestamp().unwrap())
+                }
+                PollRecord::Record(_) => panic!("unexpected user record"),
+                PollRecord::NeedMoreData => need_more_data_count += 1,
+                PollRecord::EndOfData => break,
+            }
+        }
+        assert_eq!(timestamps, vec![5, 10, 20]);
+        assert!(need_more_data_count > 40);
+    }
+
+    #[test]
+    fn scans_keep_partial_record() {
+        let file = build_file(&[(SAMPLE, 10), (SAMPLE, 5), (FINISHED_ROUND, 0), (SAMPLE, 20)]);
+        let trickle = Rc::new(Cell::new(false));
+        let reader = TrickleReader {
+            data: Cursor::new(&file[..]),
+            trickle: trickle.clone(),
+            would_block: false,
+        };
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(reader).unwrap();
+        // Read a few bytes of the first record.
+        trickle.set(true);
+        for _ in 0..10 {
+            assert!(matches!(
+                record_iter.poll_next_record(&mut perf_file).unwrap(),
+                PollRecord::NeedMoreData
+            ));
+        }
+        assert!(record_iter.partial_record_len() > 0);
+
+        trickle.set(false);
+        let index = record_iter.build_index().unwrap();
+        assert_eq!(index.record_count(), 3);
+        assert_eq!(record_iter.split_data_section(2).unwrap().len(), 2);
+        assert!(record_iter.scan_sample_time_range().unwrap().is_some());
+        // The scans don't affect the partially read record.
+        assert_eq!(collect_timestamps(perf_file, record_iter), vec![5, 10, 20]);
+    }
+
+    #[test]
+    fn record_type_filter() {
+        const COMM: u32 = 3;
+        let file = build_file(&[
+            (SAMPLE, 10),
+            (COMM, 0),
+            (SAMPLE, 5),
+            (FINISHED_ROUND, 0),
+            (COMM, 0),
+            (SAMPLE, 20),
+        ]);
+        let PerfFileReader {
+            perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        record_iter.set_record_type_filter(Some(&[RecordType::SAMPLE]));
+        assert_eq!(collect_timestamps(perf_file, record_iter), vec![5, 10, 20]);
+
+        // Skipping record bodies is resumed after WouldBlock errors.
+        let trickle = Rc::new(Cell::new(false));
+        let reader = TrickleReader {
+            data: Cursor::new(&file[..]),
+            trickle: trickle.clone(),
+            would_block: false,
+        };
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(reader).unwrap();
+        trickle.set(true);
+        record_iter.set_record_type_filter(Some(&[RecordType::COMM]));
+        let mut offsets = Vec::new();
+        loop {
+            match record_iter.poll_next_record(&mut perf_file).unwrap() {
+                PollRecord::Record(PerfFileRecord::EventRecord { record, offset, .. }) => {
+                    assert_eq!(record.record_type, RecordType::COMM);
+                    offsets.push(offset);
+                }
+                PollRecord::Record(_) => panic!("unexpected user record"),
+                PollRecord::NeedMoreData => {}
+                PollRecord::EndOfData => break,
+            }
+        }
+        assert_eq!(offsets, vec![16, 56]);
+        assert_eq!(record_iter.read_offset(), file.len() as u64 - 168);
+
+        // Records which update the PerfFile are applied even if they're
+        // filtered out.
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::TaskClock), "", &[2])
+            .unwrap();
+        // An EVENT_UPDATE record with type = PERF_EVENT_UPDATE__NAME, id = 2.
+        let mut body = Vec::new();
+        body.write_u64::<LittleEndian>(2).unwrap();
+        body.write_u64::<LittleEndian>(2).unwrap();
+        body.extend_from_slice(b"my-clock\0\0\0\0\0\0\0\0");
+        writer.write_record(RecordType(78), 0, &body).unwrap();
+        writer
+            .write_record(RecordType::SAMPLE, 0, &sample_body(2, 10, 100))
+            .unwrap();
+        let file = writer.finish().unwrap().into_inner();
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        record_iter.set_record_type_filter(Some(&[RecordType::SAMPLE]));
+        let mut record_count = 0;
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            assert!(matches!(record, PerfFileRecord::EventRecord { .. }));
+            record_count += 1;
+        }
+        assert_eq!(record_count, 1);
+        assert_eq!(perf_file.attr_index_for_name("my-clock"), Some(1));
+    }
+
+    #[test]
+    fn set_time_range() {
+        const COMM: u32 = 3;
+        let file = build_file(&[
+            (SAMPLE, 10),
+            (SAMPLE, 30),
+            (COMM, 0),
+            (SAMPLE, 20),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 40),
+        ]);
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        record_iter.set_time_range(15, 30);
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            if let PerfFileRecord::EventRecord { record, .. } = record {
+                records.push((record.record_type, record.timestamp()));
+            }
+        }
+        // The COMM record has no timestamp because the event doesn't have
+        // sample_id_all, so it is returned first.
+        assert_eq!(
+            records,
+            vec![
+                (RecordType::COMM, None),
+                (RecordType::SAMPLE, Some(20)),
+                (RecordType::SAMPLE, Some(30)),
+            ]
+        );
+
+        // With sample_id_all, the COMM records have a timestamp. The one
+        // before the range is still returned, the one after it isn't.
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        for (record_type, time) in [(COMM, 5), (SAMPLE, 10), (SAMPLE, 20), (COMM, 50)] {
+            let body = if record_type == COMM {
+                let mut body = Vec::new();
+                body.write_i32::<LittleEndian>(10).unwrap();
+                body.write_i32::<LittleEndian>(10).unwrap();
+                body.extend_from_slice(b"cc1\0\0\0\0\0");
+                // The sample_id fields: pid, tid, time, identifier.
+                body.write_u32::<LittleEndian>(10).unwrap();
+                body.write_u32::<LittleEndian>(10).unwrap();
+                body.write_u64::<LittleEndian>(time).unwrap();
+                body.write_u64::<LittleEndian>(1).unwrap();
+                body
+            } else {
+                sample_body(1, 10, time)
+            };
+            writer
+                .write_record(RecordType(record_type), 0, &body)
+                .unwrap();
+        }
+        let file = writer.finish().unwrap().into_inner();
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        record_iter.set_time_range(15, 30);
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            if let PerfFileRecord::EventRecord { record, .. } = record {
+                records.push((record.record_type, record.timestamp()));
+            }
+        }
+        assert_eq!(
+            records,
+            vec![(RecordType::COMM, Some(5)), (RecordType::SAMPLE, Some(20)),]
+        );
+
+        let file = build_file(&[(SAMPLE, 10), (SAMPLE, 20)]);
+        let PerfFileReader {
+            perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        record_iter.set_time_range(15, 30);
+        record_iter.clear_time_range();
+        assert_eq!(collect_timestamps(perf_file, record_iter), vec![10, 20]);
+    }
+
+    #[test]
+    fn attr_index_filter() {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::PageFaults), "faults", &[2])
+            .unwrap();
+        for (id, time) in [(1, 10), (2, 11), (1, 12), (2, 13)] {
+            writer
+                .write_record(RecordType::SAMPLE, 2, &sample_body(id, 10, time))
+                .unwrap();
+        }
+        writer.write_finished_round().unwrap();
+        let file = writer.finish().unwrap().into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let faults = perf_file.attr_index_for_name("faults").unwrap();
+        assert_eq!(faults, 1);
+        assert_eq!(perf_file.attr_index_for_name("cycles"), None);
+        record_iter.set_attr_index_filter(Some(&[faults]));
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            if let PerfFileRecord::EventRecord {
+                attr_index, record, ..
+            } = record
+            {
+                records.push((attr_index, record.timestamp().unwrap()));
+            }
+        }
+        assert_eq!(records, vec![(1, 11), (1, 13)]);
+    }
+
+    #[test]
+    fn push_parser() {
+        let records = [
+            (SAMPLE, 10),
+            (SAMPLE, 5),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 20),
+            (SAMPLE, 15),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 30),
+        ];
+        // An EVENT_UPDATE record which names the event.
+        let mut data = Vec::new();
+        data.write_u32::<LittleEndian>(78).unwrap();
+        data.write_u16::<LittleEndian>(0).unwrap();
+        data.write_u16::<LittleEndian>(32).unwrap();
+        data.write_u64::<LittleEndian>(2).unwrap();
+        data.write_u64::<LittleEndian>(1).unwrap();
+        data.extend_from_slice(b"renamed\0");
+        data.extend_from_slice(&encode_records(&records));
+        let file = build_file_with_data(&data);
+        // Only the metadata is needed to create the parser.
+        let metadata = &file[..file.len() - data.len()];
+        let (mut perf_file, mut parser) =
+            PerfRecordPushParser::from_file_metadata(metadata).unwrap();
+
+        let timestamps = |records: Vec<OwnedPerfFileRecord>| -> Vec<u64> {
+            records
+                .iter()
+                .filter_map(|record| match record.as_record() {
+                    PerfFileRecord::EventRecord { record, .. } => record.timestamp(),
+                    PerfFileRecord::UserRecord(_) => None,
+                })
+                .collect()
+        };
+        let mut emitted = Vec::new();
+        for chunk in data.chunks(3) {
+            emitted.extend(timestamps(parser.push(&mut perf_file, chunk).unwrap()));
+        }
+        assert_eq!(emitted, vec![5, 10]);
+        assert_eq!(perf_file.event_attributes()[0].name(), Some("renamed"));
+        emitted.extend(timestamps(parser.finish(&mut perf_file).unwrap()));
+        assert_eq!(emitted, vec![5, 10, 15, 20, 30]);
+
+        // Ending in the middle of a record is an error.
+        let (mut perf_file, mut parser) =
+            PerfRecordPushParser::from_file_metadata(metadata).unwrap();
+        parser.push(&mut perf_file, &data[..20]).unwrap();
+        assert!(parser.finish(&mut perf_file).is_err());
+
+        // The aux data size can't be checked against the size of the data
+        // section, but it is limited.
+        let (mut perf_file, mut parser) =
+            PerfRecordPushParser::from_file_metadata(metadata).unwrap();
+        let result = parser.push(&mut perf_file, &encode_auxtrace_record(1 << 40));
+        assert!(matches!(result, Err(Error::InvalidPerfEventSize)));
+    }
+
+    /// A file which is shared with a writer who appends to it.
+    struct GrowingFile {
+        data: Rc<std::cell::RefCell<Vec<u8>>>,
+        position: u64,
+    }
+
+    impl std::io::Read for GrowingFile {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let data = self.data.borrow();
+            let start = (self.position as usize).min(data.len());
+            let len = buf.len().min(data.len() - start);
+            buf[..len].copy_from_slice(&data[start..start + len]);
+            self.position += len as u64;
+            Ok(len)
+        }
+    }
+
+    impl std::io::Seek for GrowingFile {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.position = match pos {
+                std::io::SeekFrom::Start(offset) => offset,
+                std::io::SeekFrom::End(offset) => (self.data.borrow().len() as i64 + offset) as u64,
+                std::io::SeekFrom::Current(offset) => (self.position as i64 + offset) as u64,
+            };
+            Ok(self.position)
+        }
+    }
+
+    #[test]
+    fn follow_growing_file() {
+        let records = [
+            (SAMPLE, 10),
+            (SAMPLE, 5),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 20),
+            (SAMPLE, 15),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 30),
+            (SAMPLE, 25),
+        ];
+        let mut file = build_file(&records);
+        // While perf record is running, the data section size is zero.
+        file[48..56].copy_from_slice(&0u64.to_le_bytes());
+        let data = Rc::new(std::cell::RefCell::new(file[..168 + 60].to_vec()));
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(GrowingFile {
+            data: data.clone(),
+            position: 0,
+        })
+        .unwrap();
+
+        let mut read_available = |record_iter: &mut PerfRecordIter<GrowingFile>| {
+            let mut timestamps = Vec::new();
+            while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+                if let PerfFileRecord::EventRecord { record, .. } = record {
+                    timestamps.push(record.timestamp().unwrap());
+                }
+            }
+            timestamps
+        };
+
+        assert_eq!(
+            record_iter.follow().unwrap(),
+            FollowStatus::Growing { data_len: 60 }
+        );
+        assert_eq!(read_available(&mut record_iter), Vec::<u64>::new());
+
+        data.borrow_mut().extend_from_slice(&file[168 + 60..]);
+        assert_eq!(
+            record_iter.follow().unwrap(),
+            FollowStatus::Growing { data_len: 112 }
+        );
+        assert_eq!(read_available(&mut record_iter), vec![5, 10]);
+
+        // perf record writes the final size into the header when it's done.
+        data.borrow_mut()[48..56].copy_from_slice(&112u64.to_le_bytes());
+        assert_eq!(
+            record_iter.follow().unwrap(),
+            FollowStatus::Finished { data_len: 112 }
+        );
+        assert_eq!(read_available(&mut record_iter), vec![15, 20, 25, 30]);
+    }
+
+    #[test]
+    fn resume_growing_file() {
+        let records = [
+            (SAMPLE, 10),
+            (SAMPLE, 5),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 20),
+            (SAMPLE, 15),
+            (FINISHED_ROUND, 0),
+            (SAMPLE, 30),
+            (SAMPLE, 25),
+        ];
+        let file = build_file(&records);
+
+        // Only the first 60 bytes of the data section have been written so
+        // far. This ends in the middle of the fifth record.
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..168 + 60])).unwrap();
+        record_iter.set_data_len(60, false);
+        let mut timestamps = Vec::new();
+        loop {
+            match record_iter.next_record(&mut perf_file) {
+                Ok(Some(PerfFileRecord::EventRecord { record, .. })) => {
+                    timestamps.push(record.timestamp().unwrap())
+                }
+                Ok(Some(_)) => panic!("unexpected user record"),
+                Ok(None) => panic!("should stop in the middle of a record"),
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => panic!("unexpected error {e}"),
+            }
+        }
+        assert_eq!(timestamps, Vec::<u64>::new());
+        let state = record_iter.state().unwrap();
+        assert_eq!(state.read_offset(), 56);
+
+        // Reopen the grown file and continue.
+        let PerfFileReader {
+            perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        record_iter.restore_state(state).unwrap();
+        record_iter.set_data_len(112, true);
+        timestamps.extend(collect_timestamps(perf_file, record_iter));
+        assert_eq!(timestamps, vec![5, 10, 15, 20, 25, 30]);
+    }
 }
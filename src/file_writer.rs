@@ -0,0 +1,727 @@
+use std::collections::HashMap;
+use std::io::{Seek, SeekFrom, Write};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+use linux_perf_event_reader::{Endianness, PerfEventAttr, RecordType};
+
+use crate::attr_encoding::write_attr;
+use crate::constants::PERF_RECORD_FINISHED_ROUND;
+use crate::feature_sections_builder::{event_desc_section, EventDescEntry};
+use crate::pipe_writer::write_record;
+use crate::{
+    DsoInfo, DsoKey, Feature, FeatureSectionsBuilder, FeatureSet, PerfFileRecord, PerfFileSection,
+};
+
+const HEADER_SIZE: u64 = 104;
+
+/// Writes a perf.data file in the regular (non-pipe) format.
+///
+/// The records are written to the data section as they come in. The event
+/// attributes and the feature sections are collected and written by
+/// [`finish`](Self::finish), after the data section, followed by the file
+/// header at the start of the file. This is why the writer needs [`Seek`].
+///
+/// ```
+/// use std::io::Cursor;
+///
+/// use linux_perf_data::linux_perf_event_reader::{Endianness, RecordType};
+/// use linux_perf_data::{Feature, PerfFileWriter};
+///
+/// # fn wrapper(attr: &linux_perf_data::linux_perf_event_reader::PerfEventAttr) -> Result<(), std::io::Error> {
+/// let mut writer = PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian)?;
+/// writer.add_attr(attr, "cpu-clock", &[1])?;
+/// writer.add_feature(Feature::HOSTNAME, b"\x08\0\0\0myhost\0\0".to_vec());
+/// writer.write_record(RecordType::SAMPLE, 2, &[0; 16])?;
+/// let file: Vec<u8> = writer.finish()?.into_inner();
+/// # Ok(())
+/// # }
+/// ```
+pub struct PerfFileWriter<W: Write + Seek> {
+    writer: W,
+    endian: Endianness,
+    /// The position of the file header in `writer`. All offsets in the file
+    /// are relative to it.
+    start: u64,
+    attrs: Vec<EncodedAttr>,
+    features: FeatureSectionsBuilder,
+}
+
+impl<W: Write + Seek> PerfFileWriter<W> {
+    /// Reserves space for the file header at the current position of
+    /// `writer`, which is usually the start of the file. All values are
+    /// written in the byte order `endian`; record bodies passed to
+    /// [`write_record`](Self::write_record) must use the same byte order.
+    pub fn new(mut writer: W, endian: Endianness) -> Result<Self, std::io::Error> {
+        let start = writer.stream_position()?;
+        writer.write_all(&[0; HEADER_SIZE as usize])?;
+        Ok(Self {
+            writer,
+            endian,
+            start,
+            attrs: Vec::new(),
+            features: FeatureSectionsBuilder::new(endian),
+        })
+    }
+
+    /// Adds an event attribute, its name, and the IDs which its records
+    /// carry. The attributes are stored in the order in which they're added,
+    /// so the index of each attribute is the same as in
+    /// [`PerfFile::event_attributes`](crate::PerfFile::event_attributes) of
+    /// the written file.
+    ///
+    /// The attributes are written to the attr section, and to the
+    /// `EVENT_DESC` feature section unless one is added with
+    /// [`add_feature`](Self::add_feature).
+    ///
+    /// Returns an `InvalidInput` error for event types which can't be
+    /// encoded.
+    pub fn add_attr(
+        &mut self,
+        attr: &PerfEventAttr,
+        name: &str,
+        event_ids: &[u64],
+    ) -> Result<(), std::io::Error> {
+        let mut attr_bytes = Vec::new();
+        match self.endian {
+            Endianness::LittleEndian => write_attr::<LittleEndian>(attr, &mut attr_bytes)?,
+            Endianness::BigEndian => write_attr::<BigEndian>(attr, &mut attr_bytes)?,
+        }
+        self.add_raw_attr(attr_bytes, name, event_ids)
+    }
+
+    /// Like [`add_attr`](Self::add_attr), but with an already encoded
+    /// `perf_event_attr` in the byte order of the file, which is written
+    /// unchanged. This keeps fields which [`PerfEventAttr`] doesn't know
+    /// about.
+    ///
+    /// All attributes of a file must have the same size. Returns an
+    /// `InvalidInput` error if `attr_bytes` has a different size than the
+    /// attributes which were added before.
+    pub fn add_raw_attr(
+        &mut self,
+        attr_bytes: Vec<u8>,
+        name: &str,
+        event_ids: &[u64],
+    ) -> Result<(), std::io::Error> {
+        if let Some(first) = self.attrs.first() {
+            if first.attr_bytes.len() != attr_bytes.len() {
+                return Err(std::io::ErrorKind::InvalidInput.into());
+            }
+        }
+        self.attrs.push(EncodedAttr {
+            attr_bytes,
+            name: name.to_owned(),
+            event_ids: event_ids.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Adds a feature section with the given contents, replacing an earlier
+    /// section for the same feature.
+    pub fn add_feature(&mut self, feature: Feature, data: Vec<u8>) {
+        self.features.set_raw(feature, data);
+    }
+
+    /// Adds the sections of `sections`, replacing earlier sections for the
+    /// same features.
+    ///
+    /// Returns an `InvalidInput` error if the byte order of `sections`
+    /// doesn't match the byte order of the file.
+    pub fn add_feature_sections(
+        &mut self,
+        sections: FeatureSectionsBuilder,
+    ) -> Result<(), std::io::Error> {
+        if sections.endian() != self.endian {
+            return Err(std::io::ErrorKind::InvalidInput.into());
+        }
+        for (feature, data) in sections.into_sections() {
+            self.features.set_raw(feature, data);
+        }
+        Ok(())
+    }
+
+    /// The feature sections which will be written, for setting them with the
+    /// typed setters of [`FeatureSectionsBuilder`].
+    pub fn feature_sections_mut(&mut self) -> &mut FeatureSectionsBuilder {
+        &mut self.features
+    }
+
+    /// Adds a `BUILD_ID` feature section with an entry for each DSO, like the
+    /// section which `perf record` writes for the DSOs that were hit by
+    /// samples. The entries of the kernel and of kernel modules are marked as
+    /// kernel entries.
+    ///
+    /// Returns an `InvalidInput` error for build IDs which are longer than 20
+    /// bytes.
+    pub fn add_build_ids(
+        &mut self,
+        build_ids: &HashMap<DsoKey, DsoInfo>,
+    ) -> Result<(), std::io::Error> {
+        self.features.set_build_ids(build_ids)?;
+        Ok(())
+    }
+
+    /// Returns whether a feature section was added for `feature`.
+    pub fn has_feature(&self, feature: Feature) -> bool {
+        self.features.get(feature).is_some()
+    }
+
+    /// Removes the feature section for `feature`, if it was added.
+    pub fn remove_feature(&mut self, feature: Feature) {
+        self.features.remove(feature);
+    }
+
+    /// Writes a `PERF_RECORD_FINISHED_ROUND` record. Readers can sort and
+    /// flush all records before a round boundary.
+    pub fn write_finished_round(&mut self) -> Result<(), std::io::Error> {
+        self.write_record(RecordType(PERF_RECORD_FINISHED_ROUND), 0, &[])
+    }
+
+    /// Writes a record with the given body, i.e. the bytes after the record
+    /// header. For `PERF_RECORD_AUXTRACE` records, the body includes the aux
+    /// data, like the body returned by [`PerfRecordIter`](crate::PerfRecordIter).
+    ///
+    /// Returns an `InvalidInput` error if the record is too large for the
+    /// 16-bit size field of the record header.
+    pub fn write_record(
+        &mut self,
+        record_type: RecordType,
+        misc: u16,
+        body: &[u8],
+    ) -> Result<(), std::io::Error> {
+        match self.endian {
+            Endianness::LittleEndian => {
+                write_record::<LittleEndian, W>(&mut self.writer, record_type, misc, body)
+            }
+            Endianness::BigEndian => {
+                write_record::<BigEndian, W>(&mut self.writer, record_type, misc, body)
+            }
+        }
+    }
+
+    /// Writes a record which was read with [`PerfRecordIter`](crate::PerfRecordIter)
+    /// from a file with the same byte order.
+    pub fn write_perf_file_record(
+        &mut self,
+        record: &PerfFileRecord,
+    ) -> Result<(), std::io::Error> {
+        let (record_type, misc, data) = match record {
+            PerfFileRecord::EventRecord { record, .. } => {
+                (record.record_type, record.misc, record.data)
+            }
+            PerfFileRecord::UserRecord(record) => {
+                (record.record_type.into(), record.misc, record.data)
+            }
+        };
+        self.write_record(record_type, misc, &data.as_slice())
+    }
+
+    /// Writes the feature sections and the attributes after the data section,
+    /// and then the file header. Returns the writer, positioned at the end of
+    /// the file.
+    pub fn finish(self) -> Result<W, std::io::Error> {
+        match self.endian {
+            Endianness::LittleEndian => self.finish_impl::<LittleEndian>(),
+            Endianness::BigEndian => self.finish_impl::<BigEndian>(),
+        }
+    }
+
+    fn finish_impl<T: ByteOrder>(mut self) -> Result<W, std::io::Error> {
+        if !self.attrs.is_empty() && !self.has_feature(Feature::EVENT_DESC) {
+            let entries: Vec<_> = self
+                .attrs
+                .iter()
+                .map(|attr| EventDescEntry {
+                    attr_bytes: &attr.attr_bytes,
+                    name: &attr.name,
+                    event_ids: &attr.event_ids,
+                })
+                .collect();
+            let event_desc = event_desc_section(self.endian, &entries);
+            self.features.set_raw(Feature::EVENT_DESC, event_desc);
+        }
+        if self
+            .features
+            .sections()
+            .any(|(feature, _)| feature.0 >= FeatureSet::MAX_BITS)
+        {
+            return Err(std::io::ErrorKind::InvalidInput.into());
+        }
+        let data_end = self.writer.stream_position()? - self.start;
+        let data_section = PerfFileSection {
+            offset: HEADER_SIZE,
+            size: data_end - HEADER_SIZE,
+        };
+
+        // The feature sections directly follow the data section: first one
+        // perf_file_section per feature, then the contents.
+        let mut feature_bits = [0u64; 4];
+        let feature_count = self.features.sections().count() as u64;
+        let mut offset = data_end + feature_count * PerfFileSection::STRUCT_SIZE;
+        for (feature, data) in self.features.sections() {
+            let bit = feature.0 as usize;
+            feature_bits[bit / 64] |= 1 << (bit % 64);
+            self.writer.write_u64::<T>(offset)?;
+            self.writer.write_u64::<T>(data.len() as u64)?;
+            offset += data.len() as u64;
+        }
+        for (_, data) in self.features.sections() {
+            self.writer.write_all(data)?;
+        }
+
+        // Then the event IDs of each attribute, and the attr section, which
+        // consists of one perf_event_attr and one perf_file_section for the
+        // IDs per attribute.
+        let mut id_sections = Vec::with_capacity(self.attrs.len());
+        for EncodedAttr { event_ids, .. } in &self.attrs {
+            id_sections.push(PerfFileSection {
+                offset,
+                size: event_ids.len() as u64 * 8,
+            });
+            for id in event_ids {
+                self.writer.write_u64::<T>(*id)?;
+            }
+            offset += event_ids.len() as u64 * 8;
+        }
+        let attr_size = match self.attrs.first() {
+            Some(attr) => attr.attr_bytes.len() as u64 + PerfFileSection::STRUCT_SIZE,
+            None => 0,
+        };
+        let attr_section = PerfFileSection {
+            offset,
+            size: attr_size * self.attrs.len() as u64,
+        };
+        for (attr, id_section) in self.attrs.iter().zip(&id_sections) {
+            self.writer.write_all(&attr.attr_bytes)?;
+            self.writer.write_u64::<T>(id_section.offset)?;
+            self.writer.write_u64::<T>(id_section.size)?;
+        }
+        let file_end = self.writer.stream_position()?;
+
+        self.writer.seek(SeekFrom::Start(self.start))?;
+        match self.endian {
+            Endianness::LittleEndian => self.writer.write_all(b"PERFILE2")?,
+            Endianness::BigEndian => self.writer.write_all(b"2ELIFREP")?,
+        }
+        self.writer.write_u64::<T>(HEADER_SIZE)?;
+        self.writer.write_u64::<T>(attr_size)?;
+        for section in [
+            attr_section,
+            data_section,
+            PerfFileSection { offset: 0, size: 0 },
+        ] {
+            self.writer.write_u64::<T>(section.offset)?;
+            self.writer.write_u64::<T>(section.size)?;
+        }
+        for bits in feature_bits {
+            self.writer.write_u64::<T>(bits)?;
+        }
+        self.writer.seek(SeekFrom::Start(file_end))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+struct EncodedAttr {
+    /// The encoded `perf_event_attr`.
+    attr_bytes: Vec<u8>,
+    name: String,
+    event_ids: Vec<u64>,
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use std::num::NonZeroU64;
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use linux_perf_event_reader::{
+        AttrFlags, BranchSampleFormat, ClockId, CpuMode, Endianness, PerfClock, PerfEventAttr,
+        PerfEventHeader, PerfEventType, ReadFormat, RecordType, SampleFormat, SamplingPolicy,
+        SoftwareCounterType, WakeupPolicy,
+    };
+
+    use super::PerfFileWriter;
+    use crate::{DsoInfo, DsoKey, Feature, PerfFileReader, PerfFileRecord, PERF_FORMAT_LOST};
+
+    pub(crate) fn test_attr(counter_type: SoftwareCounterType) -> PerfEventAttr {
+        PerfEventAttr {
+            type_: PerfEventType::Software(counter_type),
+            sampling_policy: SamplingPolicy::Period(NonZeroU64::new(1000).unwrap()),
+            sample_format: SampleFormat::IDENTIFIER | SampleFormat::TID | SampleFormat::TIME,
+            read_format: ReadFormat::empty(),
+            flags: AttrFlags::SAMPLE_ID_ALL,
+            wakeup_policy: WakeupPolicy::EventCount(0),
+            branch_sample_format: BranchSampleFormat::empty(),
+            sample_regs_user: 0,
+            sample_stack_user: 0,
+            clock: PerfClock::Default,
+            sample_regs_intr: 0,
+            aux_watermark: 0,
+            sample_max_stack: 0,
+            aux_sample_size: 0,
+            sig_data: 0,
+        }
+    }
+
+    /// The body of a sample for [`test_attr`].
+    pub(crate) fn sample_body(id: u64, pid: u32, time: u64) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.write_u64::<LittleEndian>(id).unwrap();
+        body.write_u32::<LittleEndian>(pid).unwrap();
+        body.write_u32::<LittleEndian>(pid).unwrap();
+        body.write_u64::<LittleEndian>(time).unwrap();
+        body
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(
+                &test_attr(SoftwareCounterType::CpuClock),
+                "cpu-clock",
+                &[1, 2],
+            )
+            .unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::PageFaults), "faults", &[3])
+            .unwrap();
+        writer.add_feature(Feature::HOSTNAME, b"\x08\0\0\0myhost\0\0".to_vec());
+        writer.add_feature(Feature::ARCH, b"\x08\0\0\0x86_64\0\0".to_vec());
+        writer
+            .write_record(RecordType::SAMPLE, 2, &sample_body(3, 10, 200))
+            .unwrap();
+        writer
+            .write_record(RecordType::SAMPLE, 2, &sample_body(2, 10, 100))
+            .unwrap();
+        writer.write_finished_round().unwrap();
+        let file = writer.finish().unwrap().into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        assert_eq!(perf_file.hostname().unwrap(), Some("myhost"));
+        assert_eq!(perf_file.arch().unwrap(), Some("x86_64"));
+        let attrs = perf_file.event_attributes();
+        assert_eq!(attrs.len(), 2);
+        assert_eq!(attrs[0].name(), Some("cpu-clock"));
+        assert_eq!(attrs[0].ids(), &[1, 2]);
+        assert_eq!(attrs[1].name(), Some("faults"));
+        assert_eq!(attrs[1].ids(), &[3]);
+        assert_eq!(perf_file.header().data_section.offset, 104);
+        assert_eq!(perf_file.header().data_section.size, 2 * 32 + 8);
+
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            if let PerfFileRecord::EventRecord {
+                attr_index, record, ..
+            } = record
+            {
+                records.push((attr_index, record.common_data().unwrap().timestamp));
+            }
+        }
+        assert_eq!(records, vec![(0, Some(100)), (1, Some(200))]);
+    }
+
+    #[test]
+    fn build_ids() {
+        let mut build_ids = HashMap::new();
+        build_ids.insert(
+            DsoKey::Kernel,
+            DsoInfo {
+                path: b"[kernel.kallsyms]".to_vec(),
+                build_id: vec![0xab; 20],
+            },
+        );
+        let libc_path = b"/usr/lib/x86_64-linux-gnu/libc.so.6".to_vec();
+        build_ids.insert(
+            DsoKey::detect(&libc_path, CpuMode::User).unwrap(),
+            DsoInfo {
+                path: libc_path,
+                // Build IDs don't have to be 20 bytes long.
+                build_id: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            },
+        );
+
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer.add_build_ids(&build_ids).unwrap();
+        let file = writer.finish().unwrap().into_inner();
+
+        let perf_file = PerfFileReader::parse_file(Cursor::new(&file[..]))
+            .unwrap()
+            .perf_file;
+        assert_eq!(perf_file.build_ids().unwrap(), build_ids);
+        let events: Vec<_> = perf_file.build_id_events().collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(CpuMode::from_misc(events[0].header.misc), CpuMode::Kernel);
+        assert_eq!(events[0].header.size, 8 + 4 + 24 + 64);
+        assert_eq!(CpuMode::from_misc(events[1].header.misc), CpuMode::User);
+    }
+
+    #[test]
+    fn clockid() {
+        let mut attr = test_attr(SoftwareCounterType::CpuClock);
+        attr.clock = PerfClock::ClockId(ClockId::MonotonicRaw);
+        attr.flags |= AttrFlags::USE_CLOCKID;
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::BigEndian).unwrap();
+        writer.add_attr(&attr, "cpu-clock", &[1]).unwrap();
+        writer.add_feature(Feature::CLOCKID, 1u64.to_be_bytes().to_vec());
+        let file = writer.finish().unwrap().into_inner();
+
+        let perf_file = PerfFileReader::parse_file(Cursor::new(&file[..]))
+            .unwrap()
+            .perf_file;
+        assert_eq!(perf_file.clockid().unwrap(), Some(ClockId::MonotonicRaw));
+        assert_eq!(perf_file.clock_resolution_ns().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn build_id_records() {
+        let libc_path = b"/usr/lib/x86_64-linux-gnu/libc.so.6";
+        let mut event = Vec::new();
+        crate::BuildIdEvent {
+            header: PerfEventHeader {
+                type_: crate::constants::PERF_RECORD_HEADER_BUILD_ID,
+                misc: linux_perf_event_reader::constants::PERF_RECORD_MISC_USER,
+                size: 0,
+            },
+            pid: -1,
+            build_id: &[1, 2, 3, 4],
+            file_path: libc_path,
+        }
+        .write::<LittleEndian>(&mut event)
+        .unwrap();
+
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        let misc = u16::from_le_bytes([event[4], event[5]]);
+        writer
+            .write_record(
+                RecordType(crate::constants::PERF_RECORD_HEADER_BUILD_ID),
+                misc,
+                &event[8..],
+            )
+            .unwrap();
+        let file = writer.finish().unwrap().into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        assert!(perf_file.build_ids().unwrap().is_empty());
+        while record_iter.next_record(&mut perf_file).unwrap().is_some() {}
+        let build_ids = perf_file.build_ids().unwrap();
+        let dso_key = DsoKey::detect(libc_path, CpuMode::User).unwrap();
+        assert_eq!(build_ids[&dso_key].build_id, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn event_update_sets_attr_name() {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::TaskClock), "", &[2])
+            .unwrap();
+        // type = PERF_EVENT_UPDATE__NAME, id = 2
+        let mut body = Vec::new();
+        body.write_u64::<LittleEndian>(2).unwrap();
+        body.write_u64::<LittleEndian>(2).unwrap();
+        body.extend_from_slice(b"my-clock\0\0\0\0\0\0\0\0");
+        writer
+            .write_record(
+                RecordType(crate::constants::PERF_RECORD_EVENT_UPDATE),
+                0,
+                &body,
+            )
+            .unwrap();
+        let file = writer.finish().unwrap().into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        assert_eq!(perf_file.event_attributes()[1].name(), Some(""));
+        while record_iter.next_record(&mut perf_file).unwrap().is_some() {}
+        assert_eq!(perf_file.event_attributes()[0].name(), Some("cpu-clock"));
+        assert_eq!(perf_file.event_attributes()[1].name(), Some("my-clock"));
+    }
+
+    #[test]
+    fn read_format_keeps_unknown_bits() {
+        let read_format = ReadFormat::from_bits_retain(ReadFormat::ID.bits() | PERF_FORMAT_LOST);
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        let attr = PerfEventAttr {
+            read_format,
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+        writer.add_attr(&attr, "cpu-clock", &[1]).unwrap();
+        let file = writer.finish().unwrap().into_inner();
+
+        let perf_file = PerfFileReader::parse_file(Cursor::new(&file[..]))
+            .unwrap()
+            .perf_file;
+        assert_eq!(
+            perf_file.event_attributes()[0].attr.read_format,
+            ReadFormat::ID
+        );
+        assert_eq!(perf_file.read_format(0), Some(read_format));
+    }
+
+    #[test]
+    fn id_index() {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[5])
+            .unwrap();
+        writer
+            .add_attr(
+                &test_attr(SoftwareCounterType::TaskClock),
+                "task-clock",
+                &[7],
+            )
+            .unwrap();
+        // nr = 2; (id, idx, cpu, tid) entries. The mmap buffer indexes are
+        // the other way around than the attribute indexes.
+        let mut body = Vec::new();
+        for value in [2, 5, 1, 0, u64::MAX, 7, 0, 1, u64::MAX] {
+            body.write_u64::<LittleEndian>(value).unwrap();
+        }
+        writer
+            .write_record(RecordType(crate::constants::PERF_RECORD_ID_INDEX), 0, &body)
+            .unwrap();
+        for (id, time) in [(7, 1), (5, 2)] {
+            writer
+                .write_record(RecordType::SAMPLE, 2, &sample_body(id, 100, time))
+                .unwrap();
+        }
+        let file = writer.finish().unwrap().into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let mut attr_indexes = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            if let PerfFileRecord::EventRecord { attr_index, .. } = record {
+                attr_indexes.push(attr_index);
+            }
+        }
+        // The records are attributed using the IDs from the attributes, not
+        // using the ID_INDEX idx.
+        assert_eq!(attr_indexes, vec![1, 0]);
+        assert_eq!(perf_file.attr_index_for_event_id(7), Some(1));
+        assert_eq!(perf_file.event_attributes()[0].event_ids, vec![5]);
+        let entry = perf_file.id_index_entry(7).unwrap();
+        assert_eq!((entry.idx, entry.cpu, entry.tid), (0, 1, u64::MAX));
+        assert!(perf_file.id_index_entry(9).is_none());
+    }
+
+    #[test]
+    fn simpleperf_event_id() {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[])
+            .unwrap();
+        writer
+            .add_attr(
+                &test_attr(SoftwareCounterType::TaskClock),
+                "task-clock",
+                &[],
+            )
+            .unwrap();
+        // count = 2; (attr_id, event_id) entries.
+        let mut body = Vec::new();
+        for value in [2, 0, 5, 1, 7] {
+            body.write_u64::<LittleEndian>(value).unwrap();
+        }
+        writer
+            .write_record(
+                RecordType(crate::constants::SIMPLE_PERF_RECORD_EVENT_ID),
+                0,
+                &body,
+            )
+            .unwrap();
+        for (id, time) in [(7, 1), (5, 2)] {
+            writer
+                .write_record(RecordType::SAMPLE, 2, &sample_body(id, 100, time))
+                .unwrap();
+        }
+        let file = writer.finish().unwrap().into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let mut attr_indexes = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            if let PerfFileRecord::EventRecord { attr_index, .. } = record {
+                attr_indexes.push(attr_index);
+            }
+        }
+        assert_eq!(attr_indexes, vec![1, 0]);
+        assert_eq!(perf_file.event_attributes()[1].event_ids, vec![7]);
+    }
+
+    #[test]
+    fn simpleperf_debug_unwind() {
+        use prost::Message;
+
+        use crate::{SimpleperfDebugUnwindFeature, SimpleperfDebugUnwindFile};
+
+        let files = vec![
+            SimpleperfDebugUnwindFile {
+                path: "/data/app/lib/libfoo.so".to_owned(),
+                size: 3,
+            },
+            SimpleperfDebugUnwindFile {
+                path: "/system/lib64/libc.so".to_owned(),
+                size: 5,
+            },
+        ];
+        let feature = SimpleperfDebugUnwindFeature {
+            file: files.clone(),
+        };
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer.add_feature(Feature::SIMPLEPERF_DEBUG_UNWIND, feature.encode_to_vec());
+        writer.add_feature(Feature::SIMPLEPERF_DEBUG_UNWIND_FILE, b"fooc-lib".to_vec());
+        let file = writer.finish().unwrap().into_inner();
+
+        let perf_file = PerfFileReader::parse_file(Cursor::new(&file[..]))
+            .unwrap()
+            .perf_file;
+        assert_eq!(
+            perf_file.simpleperf_debug_unwind_files().unwrap(),
+            Some(files.clone())
+        );
+        let contents = perf_file
+            .simpleperf_debug_unwind_file_contents()
+            .unwrap()
+            .unwrap();
+        assert_eq!(contents[0].file, files[0]);
+        assert_eq!(contents[0].data, b"foo");
+        assert_eq!(contents[1].file, files[1]);
+        assert_eq!(contents[1].data, b"c-lib");
+    }
+}
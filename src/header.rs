@@ -12,18 +12,25 @@ use super::section::PerfFileSection;
 /// is not described here. The magic number also identifies the endian. When the
 /// magic value is 64bit byte swapped compared the file is in non-native
 /// endian.
-#[derive(Debug, Clone, Copy)]
+///
+/// Available from [`PerfFile::header`](crate::PerfFile::header), for tools
+/// which index, split or validate files and need the raw layout information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PerfHeader {
+    /// The magic value, `PERFILE2` in the file's byte order.
     pub magic: [u8; 8],
     /// size of the header
-    #[allow(dead_code)]
     pub header_size: u64,
     /// size of an attribute in attrs
     pub attr_size: u64,
+    /// The section with the `perf_file_attr` entries.
     pub attr_section: PerfFileSection,
+    /// The section with the records.
     pub data_section: PerfFileSection,
+    /// The section with the legacy event type names. Usually empty.
     pub event_types_section: PerfFileSection,
-    /// Feature flags
+    /// Feature flags, i.e. the raw bitmap of the feature sections which
+    /// follow the data section.
     pub features: FeatureSet,
 }
 
@@ -0,0 +1,64 @@
+use byteorder::ByteOrder;
+use linux_perf_event_reader::RawData;
+
+/// A `PERF_RECORD_ID_INDEX` record, which describes where each event ID was
+/// opened: on which CPU or for which thread, and into which mmap buffer its
+/// records are written.
+///
+/// `perf` writes this record for AUX area tracing, e.g. for Intel PT, so that
+/// the decoder knows which buffer an event's records came from. It doesn't
+/// say which event an ID belongs to; that comes from the attributes.
+/// [`PerfRecordIter`](crate::PerfRecordIter) stores the entries in the
+/// [`PerfFile`](crate::PerfFile), see
+/// [`PerfFile::id_index_entry`](crate::PerfFile::id_index_entry).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdIndex {
+    pub entries: Vec<IdIndexEntry>,
+}
+
+/// An entry of an [`IdIndex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdIndexEntry {
+    /// The event ID.
+    pub id: u64,
+    /// The index of the mmap buffer which the event's records are written
+    /// to, i.e. perf's `sid->idx`. This is not an attribute index.
+    pub idx: u64,
+    /// The CPU which the event was opened on, or -1 cast to u64 for all CPUs.
+    pub cpu: u64,
+    /// The thread which the event was opened for, or -1 cast to u64 for all
+    /// threads.
+    pub tid: u64,
+}
+
+impl IdIndex {
+    pub fn parse<T: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        // struct perf_record_id_index {
+        //     struct perf_event_header header;
+        //     __u64 nr;
+        //     struct id_index_entry {
+        //         __u64 id;
+        //         __u64 idx;
+        //         __u64 cpu;
+        //         __u64 tid;
+        //     } entries[nr];
+        //     // Followed by nr struct id_index_entry_2 { machine_pid, vcpu }
+        //     // for guest events, which we ignore.
+        // };
+        let nr = data.read_u64::<T>()?;
+        let nr = usize::try_from(nr).map_err(|_| std::io::ErrorKind::InvalidData)?;
+        if nr > data.len() / 32 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut entries = Vec::with_capacity(nr);
+        for _ in 0..nr {
+            entries.push(IdIndexEntry {
+                id: data.read_u64::<T>()?,
+                idx: data.read_u64::<T>()?,
+                cpu: data.read_u64::<T>()?,
+                tid: data.read_u64::<T>()?,
+            });
+        }
+        Ok(Self { entries })
+    }
+}
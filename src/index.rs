@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use linux_perf_event_reader::RecordType;
+
+use crate::feature_sections::SampleTimeRange;
+
+/// A summary of the data section of a perf.data file, created by a fast scan
+/// over the records with [`PerfRecordIter::build_index`](crate::PerfRecordIter::build_index).
+///
+/// The scan only looks at the record headers and at the fields needed for
+/// sorting (timestamp and event ID); the records are not parsed. This makes
+/// it cheap enough to run when a file is opened, for example to display the
+/// time range of the profile in a GUI before loading any details.
+///
+/// The index can then be used with
+/// [`PerfRecordIter::seek_to_range`](crate::PerfRecordIter::seek_to_range)
+/// to parse only the parts of the file that are needed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PerfFileIndex {
+    /// The timestamps of the first and the last sample, if any samples with
+    /// timestamps were found.
+    pub sample_time_range: Option<SampleTimeRange>,
+    /// The lowest and the highest timestamp of any record.
+    pub record_time_range: Option<(u64, u64)>,
+    /// The number of records per record type. This includes user record types.
+    pub record_counts: HashMap<RecordType, u64>,
+    /// The number of sample records per event, indexed by attr index.
+    pub sample_counts_per_attr: Vec<u64>,
+    /// One entry for each "round" in the file, in file order.
+    ///
+    /// Rounds are delimited by `FINISHED_ROUND` records. If the file doesn't
+    /// have any of those, the entire data section is one round.
+    pub rounds: Vec<RoundIndexEntry>,
+}
+
+/// Information about one "round" of records, i.e. about the records between
+/// two `FINISHED_ROUND` records. Used in [`PerfFileIndex`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RoundIndexEntry {
+    /// The offset of the first record of this round, relative to the start
+    /// of the data section.
+    pub offset: u64,
+    /// The size of this round in bytes, including the terminating
+    /// `FINISHED_ROUND` record if there is one.
+    pub size: u64,
+    /// The number of records in this round, not counting `FINISHED_ROUND`.
+    pub record_count: u64,
+    /// The lowest timestamp of any record in this round.
+    pub min_timestamp: Option<u64>,
+    /// The highest timestamp of any record in this round.
+    pub max_timestamp: Option<u64>,
+}
+
+impl PerfFileIndex {
+    /// The total number of records in the file, not counting `FINISHED_ROUND` records.
+    pub fn record_count(&self) -> u64 {
+        self.rounds.iter().map(|round| round.record_count).sum()
+    }
+
+    /// Returns the byte range in the data section, relative to the data section
+    /// start, which contains all records with timestamps in `start..=end`.
+    ///
+    /// The returned range is aligned to round boundaries, so that records
+    /// read from it can be sorted correctly. It usually also contains some
+    /// records outside of the requested time range.
+    pub fn data_range_for_time_range(&self, start: u64, end: u64) -> (u64, u64) {
+        // Rounds have the property that the records in round N + 2 are never
+        // older than the newest record in round N. So we can start reading at
+        // the first round which has a record at or after `start`: all earlier
+        // rounds only have records before `start`.
+        let first_round = self
+            .rounds
+            .iter()
+            .position(|round| round.max_timestamp.is_none_or(|max| max >= start))
+            .unwrap_or(self.rounds.len());
+
+        // Stop before the first round from which on all rounds only contain
+        // records after `end`.
+        let mut end_round = self.rounds.len();
+        while end_round > first_round {
+            let round = &self.rounds[end_round - 1];
+            match round.min_timestamp {
+                Some(min) if min > end => end_round -= 1,
+                _ => break,
+            }
+        }
+
+        let range_start = match self.rounds.get(first_round) {
+            Some(round) => round.offset,
+            None => self
+                .rounds
+                .last()
+                .map_or(0, |round| round.offset + round.size),
+        };
+        let range_end = match end_round.checked_sub(1).and_then(|i| self.rounds.get(i)) {
+            Some(round) if end_round > first_round => round.offset + round.size,
+            _ => range_start,
+        };
+        (range_start, range_end)
+    }
+}
+
+/// Accumulates a [`PerfFileIndex`] from a sequence of records.
+///
+/// A builder can cover just a part of the data section; the partial indexes
+/// of adjacent parts are then combined with [`IndexBuilder::append`].
+pub(crate) struct IndexBuilder {
+    index: PerfFileIndex,
+    round: RoundIndexEntry,
+}
+
+impl IndexBuilder {
+    /// Creates a builder for records starting at `offset`, relative to the
+    /// start of the data section.
+    pub fn new(attr_count: usize, offset: u64) -> Self {
+        Self {
+            index: PerfFileIndex {
+                sample_counts_per_attr: vec![0; attr_count],
+                ..Default::default()
+            },
+            round: RoundIndexEntry {
+                offset,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Adds a record other than `FINISHED_ROUND`.
+    pub fn add_record(
+        &mut self,
+        record_type: RecordType,
+        size: u64,
+        attr_index: Option<usize>,
+        timestamp: Option<u64>,
+    ) {
+        let index = &mut self.index;
+        let round = &mut self.round;
+        round.size += size;
+        round.record_count += 1;
+        *index.record_counts.entry(record_type).or_insert(0) += 1;
+        let is_sample = record_type == RecordType::SAMPLE;
+        if is_sample {
+            if let Some(attr_index) = attr_index {
+                index.sample_counts_per_attr[attr_index] += 1;
+            }
+        }
+        if let Some(timestamp) = timestamp {
+            round.min_timestamp = Some(round.min_timestamp.map_or(timestamp, |t| t.min(timestamp)));
+            round.max_timestamp = Some(round.max_timestamp.map_or(timestamp, |t| t.max(timestamp)));
+            index.record_time_range =
+                Some(extend_range(index.record_time_range, timestamp, timestamp));
+            if is_sample {
+                index.sample_time_range = Some(extend_sample_time_range(
+                    index.sample_time_range,
+                    timestamp,
+                    timestamp,
+                ));
+            }
+        }
+    }
+
+    /// Adds a `FINISHED_ROUND` record, which ends the current round.
+    pub fn add_finished_round(&mut self, size: u64) {
+        self.round.size += size;
+        let next_offset = self.round.offset + self.round.size;
+        let round = std::mem::replace(
+            &mut self.round,
+            RoundIndexEntry {
+                offset: next_offset,
+                ..Default::default()
+            },
+        );
+        self.index.rounds.push(round);
+    }
+
+    /// Appends the records from `other`, which must start where the records
+    /// of `self` end. A round which is still open at the end of `self` is
+    /// continued by the first round of `other`.
+    pub fn append(&mut self, other: IndexBuilder) {
+        let IndexBuilder {
+            index: other_index,
+            round: other_round,
+        } = other;
+        let mut other_rounds = other_index.rounds.into_iter().chain(Some(other_round));
+        let first = other_rounds.next().unwrap();
+        merge_round(&mut self.round, &first);
+        for round in other_rounds {
+            let finished = std::mem::replace(&mut self.round, round);
+            self.index.rounds.push(finished);
+        }
+
+        let index = &mut self.index;
+        for (record_type, count) in other_index.record_counts {
+            *index.record_counts.entry(record_type).or_insert(0) += count;
+        }
+        for (count, other_count) in index
+            .sample_counts_per_attr
+            .iter_mut()
+            .zip(other_index.sample_counts_per_attr)
+        {
+            *count += other_count;
+        }
+        if let Some((first, last)) = other_index.record_time_range {
+            index.record_time_range = Some(extend_range(index.record_time_range, first, last));
+        }
+        if let Some(range) = other_index.sample_time_range {
+            index.sample_time_range = Some(extend_sample_time_range(
+                index.sample_time_range,
+                range.first_sample_time,
+                range.last_sample_time,
+            ));
+        }
+    }
+
+    /// Returns the index. A trailing round without a `FINISHED_ROUND` record
+    /// is included if it is non-empty.
+    pub fn finish(mut self) -> PerfFileIndex {
+        if self.round.size != 0 {
+            self.index.rounds.push(self.round);
+        }
+        self.index
+    }
+}
+
+fn merge_round(round: &mut RoundIndexEntry, other: &RoundIndexEntry) {
+    round.size += other.size;
+    round.record_count += other.record_count;
+    round.min_timestamp = match (round.min_timestamp, other.min_timestamp) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+    round.max_timestamp = match (round.max_timestamp, other.max_timestamp) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    };
+}
+
+fn extend_range(range: Option<(u64, u64)>, first: u64, last: u64) -> (u64, u64) {
+    match range {
+        Some((range_first, range_last)) => (range_first.min(first), range_last.max(last)),
+        None => (first, last),
+    }
+}
+
+fn extend_sample_time_range(
+    range: Option<SampleTimeRange>,
+    first: u64,
+    last: u64,
+) -> SampleTimeRange {
+    let (first_sample_time, last_sample_time) = extend_range(
+        range.map(|range| (range.first_sample_time, range.last_sample_time)),
+        first,
+        last,
+    );
+    SampleTimeRange {
+        first_sample_time,
+        last_sample_time,
+    }
+}
@@ -42,7 +42,7 @@ impl JitDumpHeader {
             Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
                 return Err(JitDumpError::NotEnoughBytesForHeader)
             }
-            Err(e) => panic!("Unexpected error type {e}"),
+            Err(e) => return Err(JitDumpError::Io(e)),
         };
         if header.total_size < Self::SIZE as u32 {
             return Err(JitDumpError::InvalidHeaderSize(header.total_size));
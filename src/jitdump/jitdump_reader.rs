@@ -43,7 +43,7 @@ impl<R: Read> JitDumpReader<R> {
         let endian = match &header.magic {
             b"DTiJ" => Endianness::LittleEndian,
             b"JiTD" => Endianness::BigEndian,
-            _ => panic!(),
+            _ => return Err(JitDumpError::InvalidMagicBytes(header.magic)),
         };
 
         Ok(Self {
@@ -76,8 +76,17 @@ impl<R: Read> JitDumpReader<R> {
             if let Some(record_header_bytes) =
                 self.reader.consume_data(JitDumpRecordHeader::SIZE)?
             {
-                self.pending_record_header =
-                    Some(JitDumpRecordHeader::parse(self.endian, record_header_bytes).unwrap());
+                let record_header = JitDumpRecordHeader::parse(self.endian, record_header_bytes)?;
+                if (record_header.total_size as usize) < JitDumpRecordHeader::SIZE {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "jitdump record size {} is smaller than the record header",
+                            record_header.total_size
+                        ),
+                    ));
+                }
+                self.pending_record_header = Some(record_header);
             }
         };
         Ok(self.pending_record_header.clone())
@@ -110,7 +119,7 @@ impl<R: Read> JitDumpReader<R> {
     /// When operating on partial files, this will return `Ok(None)` if the entire record is
     /// not available yet. Future calls to `next_record` may return `Ok(Some)` if the
     /// data has become available in the meantime, because they will call `read` on `R` again.
-    pub fn next_record(&mut self) -> Result<Option<JitDumpRawRecord>, std::io::Error> {
+    pub fn next_record(&mut self) -> Result<Option<JitDumpRawRecord<'_>>, std::io::Error> {
         let record_size = match self.next_record_header()? {
             Some(header) => header.total_size,
             None => return Ok(None),
@@ -119,7 +128,9 @@ impl<R: Read> JitDumpReader<R> {
 
         match self.reader.consume_data(body_size)? {
             Some(record_body_data) => {
-                let record_header = self.pending_record_header.take().unwrap();
+                let Some(record_header) = self.pending_record_header.take() else {
+                    return Ok(None);
+                };
                 let start_offset = self.current_record_start_offset;
                 self.current_record_start_offset += record_size as u64;
                 Ok(Some(JitDumpRawRecord {
@@ -151,7 +162,7 @@ impl<R: Read + Seek> JitDumpReader<R> {
             Some(record_header) => record_header.total_size,
             None => return Ok(false),
         };
-        let body_size = record_size as usize - JitDumpRecordHeader::SIZE; // TODO: Handle underflow
+        let body_size = record_size as usize - JitDumpRecordHeader::SIZE;
 
         self.reader.skip_bytes(body_size)?;
         self.pending_record_header.take();
@@ -159,3 +170,26 @@ impl<R: Read + Seek> JitDumpReader<R> {
         Ok(true)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::JitDumpReader;
+
+    #[test]
+    fn record_smaller_than_header_is_an_error() {
+        let mut file = Vec::new();
+        file.extend_from_slice(b"DTiJ");
+        for value in [1u32, 40, 62, 0, 1234] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        file.extend_from_slice(&[0; 16]);
+        // A record header with total_size = 8.
+        for value in [0u32, 8] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        file.extend_from_slice(&[0; 8]);
+
+        let mut reader = JitDumpReader::new(&file[..]).unwrap();
+        assert!(reader.next_record().is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use std::io::Write;
+
+use byteorder::{NativeEndian, WriteBytesExt};
+
+use super::record::{JitDumpRecordHeader, JitDumpRecordType};
+use super::records::*;
+use super::JitDumpHeader;
+
+/// The `JITDUMP_FLAGS_ARCH_TIMESTAMP` header flag: the record timestamps are
+/// from the CPU's timestamp counter (e.g. `rdtsc`) instead of from the clock
+/// which `perf record -k` uses.
+pub const JITDUMP_FLAGS_ARCH_TIMESTAMP: u64 = 1;
+
+/// Writes a jitdump file, in the byte order of the current machine.
+///
+/// The records are passed in the same types that [`JitDumpReader`](super::JitDumpReader)
+/// returns; the writer computes the record sizes and the padding. The
+/// timestamps must come from the same clock as the timestamps in the
+/// perf.data file, usually `CLOCK_MONOTONIC` (`perf record -k mono`).
+///
+/// ```
+/// use linux_perf_data::jitdump::{JitCodeLoadRecord, JitDumpWriter};
+/// use linux_perf_data::linux_perf_event_reader::RawData;
+///
+/// # fn wrapper() -> Result<(), std::io::Error> {
+/// let code = [0xc3]; // ret
+/// let mut writer = JitDumpWriter::new(Vec::new(), 62, 1234, 1000, 0)?;
+/// writer.write_code_load(
+///     1001,
+///     &JitCodeLoadRecord {
+///         pid: 1234,
+///         tid: 1234,
+///         vma: 0x7000_0000,
+///         code_addr: 0x7000_0000,
+///         code_index: 0,
+///         function_name: RawData::from(&b"jitted_function"[..]),
+///         code_bytes: RawData::from(&code[..]),
+///     },
+/// )?;
+/// writer.write_code_close(1002)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct JitDumpWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JitDumpWriter<W> {
+    /// Writes the file header. `elf_machine_arch` is the ELF `e_machine`
+    /// value of the code, e.g. 62 (`EM_X86_64`) or 183 (`EM_AARCH64`).
+    pub fn new(
+        mut writer: W,
+        elf_machine_arch: u32,
+        pid: u32,
+        timestamp: u64,
+        flags: u64,
+    ) -> Result<Self, std::io::Error> {
+        writer.write_u32::<NativeEndian>(0x4A695444)?;
+        writer.write_u32::<NativeEndian>(1)?;
+        writer.write_u32::<NativeEndian>(JitDumpHeader::SIZE as u32)?;
+        writer.write_u32::<NativeEndian>(elf_machine_arch)?;
+        writer.write_u32::<NativeEndian>(0)?;
+        writer.write_u32::<NativeEndian>(pid)?;
+        writer.write_u64::<NativeEndian>(timestamp)?;
+        writer.write_u64::<NativeEndian>(flags)?;
+        Ok(Self { writer })
+    }
+
+    /// Writes a `JIT_CODE_LOAD` record. The code size is the length of
+    /// `code_bytes`.
+    pub fn write_code_load(
+        &mut self,
+        timestamp: u64,
+        record: &JitCodeLoadRecord,
+    ) -> Result<(), std::io::Error> {
+        let function_name = record.function_name.as_slice();
+        let code_bytes = record.code_bytes.as_slice();
+        let body_size = 4 + 4 + 8 + 8 + 8 + 8 + function_name.len() + 1 + code_bytes.len();
+        self.write_record_header(JitDumpRecordType::JIT_CODE_LOAD, timestamp, body_size)?;
+        let w = &mut self.writer;
+        w.write_u32::<NativeEndian>(record.pid)?;
+        w.write_u32::<NativeEndian>(record.tid)?;
+        w.write_u64::<NativeEndian>(record.vma)?;
+        w.write_u64::<NativeEndian>(record.code_addr)?;
+        w.write_u64::<NativeEndian>(code_bytes.len() as u64)?;
+        w.write_u64::<NativeEndian>(record.code_index)?;
+        w.write_all(&function_name)?;
+        w.write_u8(0)?;
+        w.write_all(&code_bytes)
+    }
+
+    /// Writes a `JIT_CODE_MOVE` record.
+    pub fn write_code_move(
+        &mut self,
+        timestamp: u64,
+        record: &JitCodeMoveRecord,
+    ) -> Result<(), std::io::Error> {
+        self.write_record_header(JitDumpRecordType::JIT_CODE_MOVE, timestamp, 4 + 4 + 8 * 5)?;
+        let w = &mut self.writer;
+        w.write_u32::<NativeEndian>(record.pid)?;
+        w.write_u32::<NativeEndian>(record.tid)?;
+        w.write_u64::<NativeEndian>(record.vma)?;
+        w.write_u64::<NativeEndian>(record.old_code_addr)?;
+        w.write_u64::<NativeEndian>(record.new_code_addr)?;
+        w.write_u64::<NativeEndian>(record.code_size)?;
+        w.write_u64::<NativeEndian>(record.code_index)
+    }
+
+    /// Writes a `JIT_CODE_DEBUG_INFO` record. It must be written before the
+    /// `JIT_CODE_LOAD` record of the function it describes.
+    pub fn write_code_debug_info(
+        &mut self,
+        timestamp: u64,
+        record: &JitCodeDebugInfoRecord,
+    ) -> Result<(), std::io::Error> {
+        let entries_size: usize = record
+            .entries
+            .iter()
+            .map(|entry| 8 + 4 + 4 + entry.file_path.len() + 1)
+            .sum();
+        let size = 8 + 8 + entries_size;
+        let padding = padding_to_8(size);
+        self.write_record_header(
+            JitDumpRecordType::JIT_CODE_DEBUG_INFO,
+            timestamp,
+            size + padding,
+        )?;
+        let w = &mut self.writer;
+        w.write_u64::<NativeEndian>(record.code_addr)?;
+        w.write_u64::<NativeEndian>(record.entries.len() as u64)?;
+        for entry in &record.entries {
+            w.write_u64::<NativeEndian>(entry.code_addr)?;
+            w.write_u32::<NativeEndian>(entry.line)?;
+            w.write_u32::<NativeEndian>(entry.column)?;
+            w.write_all(&entry.file_path.as_slice())?;
+            w.write_u8(0)?;
+        }
+        w.write_all(&[0; 8][..padding])
+    }
+
+    /// Writes a `JIT_CODE_UNWINDING_INFO` record. It must be written before
+    /// the `JIT_CODE_LOAD` record of the function it describes.
+    pub fn write_code_unwinding_info(
+        &mut self,
+        timestamp: u64,
+        record: &JitCodeUnwindingInfoRecord,
+    ) -> Result<(), std::io::Error> {
+        let eh_frame_hdr = record.eh_frame_hdr.as_slice();
+        let eh_frame = record.eh_frame.as_slice();
+        let unwind_data_size = eh_frame_hdr.len() + eh_frame.len();
+        let padding = padding_to_8(unwind_data_size);
+        self.write_record_header(
+            JitDumpRecordType::JIT_CODE_UNWINDING_INFO,
+            timestamp,
+            8 + 8 + 8 + unwind_data_size + padding,
+        )?;
+        let w = &mut self.writer;
+        w.write_u64::<NativeEndian>(unwind_data_size as u64)?;
+        w.write_u64::<NativeEndian>(eh_frame_hdr.len() as u64)?;
+        w.write_u64::<NativeEndian>(record.mapped_size)?;
+        w.write_all(&eh_frame_hdr)?;
+        w.write_all(&eh_frame)?;
+        w.write_all(&[0; 8][..padding])
+    }
+
+    /// Writes a `JIT_CODE_CLOSE` record, which marks the end of the file.
+    pub fn write_code_close(&mut self, timestamp: u64) -> Result<(), std::io::Error> {
+        self.write_record_header(JitDumpRecordType::JIT_CODE_CLOSE, timestamp, 0)
+    }
+
+    pub fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_record_header(
+        &mut self,
+        record_type: JitDumpRecordType,
+        timestamp: u64,
+        body_size: usize,
+    ) -> Result<(), std::io::Error> {
+        let total_size = u32::try_from(JitDumpRecordHeader::SIZE + body_size)
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        self.writer.write_u32::<NativeEndian>(record_type.0)?;
+        self.writer.write_u32::<NativeEndian>(total_size)?;
+        self.writer.write_u64::<NativeEndian>(timestamp)
+    }
+}
+
+fn padding_to_8(size: usize) -> usize {
+    size.next_multiple_of(8) - size
+}
+
+#[cfg(test)]
+mod test {
+    use linux_perf_event_reader::RawData;
+
+    use super::JitDumpWriter;
+    use crate::jitdump::{
+        JitCodeDebugInfoEntry, JitCodeDebugInfoRecord, JitCodeLoadRecord, JitCodeMoveRecord,
+        JitCodeUnwindingInfoRecord, JitDumpReader, JitDumpRecord,
+    };
+
+    #[test]
+    fn round_trip() {
+        let code = [0x55, 0x48, 0x89, 0xe5, 0xc3];
+        let mut writer = JitDumpWriter::new(Vec::new(), 62, 1234, 100, 0).unwrap();
+        writer
+            .write_code_debug_info(
+                101,
+                &JitCodeDebugInfoRecord {
+                    code_addr: 0x1000,
+                    entries: vec![JitCodeDebugInfoEntry {
+                        code_addr: 0x1000,
+                        line: 7,
+                        column: 3,
+                        file_path: RawData::from(&b"main.js"[..]),
+                    }],
+                },
+            )
+            .unwrap();
+        writer
+            .write_code_unwinding_info(
+                102,
+                &JitCodeUnwindingInfoRecord {
+                    mapped_size: 0,
+                    eh_frame_hdr: RawData::from(&[1, 2, 3][..]),
+                    eh_frame: RawData::from(&[4, 5][..]),
+                },
+            )
+            .unwrap();
+        writer
+            .write_code_load(
+                103,
+                &JitCodeLoadRecord {
+                    pid: 1234,
+                    tid: 1235,
+                    vma: 0x1000,
+                    code_addr: 0x1000,
+                    code_index: 1,
+                    function_name: RawData::from(&b"foo"[..]),
+                    code_bytes: RawData::from(&code[..]),
+                },
+            )
+            .unwrap();
+        writer
+            .write_code_move(
+                104,
+                &JitCodeMoveRecord {
+                    pid: 1234,
+                    tid: 1235,
+                    vma: 0x2000,
+                    old_code_addr: 0x1000,
+                    new_code_addr: 0x2000,
+                    code_size: 5,
+                    code_index: 1,
+                },
+            )
+            .unwrap();
+        writer.write_code_close(105).unwrap();
+        let file = writer.into_inner();
+
+        let mut reader = JitDumpReader::new(&file[..]).unwrap();
+        assert_eq!(reader.header().pid, 1234);
+        let mut timestamps = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            timestamps.push(record.timestamp);
+            match record.parse().unwrap() {
+                JitDumpRecord::CodeDebugInfo(info) => {
+                    assert_eq!(info.entries[0].line, 7);
+                    assert_eq!(&info.entries[0].file_path.as_slice()[..], b"main.js");
+                }
+                JitDumpRecord::CodeUnwindingInfo(info) => {
+                    assert_eq!(&info.eh_frame_hdr.as_slice()[..], &[1, 2, 3]);
+                    assert_eq!(&info.eh_frame.as_slice()[..], &[4, 5]);
+                }
+                JitDumpRecord::CodeLoad(load) => {
+                    assert_eq!(load.tid, 1235);
+                    assert_eq!(&load.function_name.as_slice()[..], b"foo");
+                    assert_eq!(&load.code_bytes.as_slice()[..], &code);
+                }
+                JitDumpRecord::CodeMove(code_move) => assert_eq!(code_move.new_code_addr, 0x2000),
+                JitDumpRecord::CodeClose => {}
+                JitDumpRecord::Other(_) => panic!("unexpected record"),
+            }
+        }
+        assert_eq!(timestamps, vec![101, 102, 103, 104, 105]);
+    }
+}
@@ -8,6 +8,9 @@
 //! is followed by a sequence of records.  Each record starts with a record header
 //! with the record type, a timestamp, and the full size of the record.
 //!
+//! [`JitDumpWriter`] writes jitdump files, e.g. for tests or for tools which
+//! generate code themselves.
+//!
 //! [jitdump]: https://raw.githubusercontent.com/torvalds/linux/master/tools/perf/Documentation/jitdump-specification.txt
 //!
 //! # Example
@@ -51,12 +54,20 @@ mod buffered_reader;
 mod error;
 mod header;
 mod jitdump_reader;
+mod jitdump_writer;
 mod read_exact;
 mod record;
 mod records;
+#[cfg(feature = "sourcemap")]
+mod source_map;
+mod wasm;
 
 pub use error::*;
 pub use header::*;
 pub use jitdump_reader::*;
+pub use jitdump_writer::*;
 pub use record::*;
 pub use records::*;
+#[cfg(feature = "sourcemap")]
+pub use source_map::*;
+pub use wasm::*;
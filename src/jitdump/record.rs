@@ -79,7 +79,7 @@ pub struct JitDumpRawRecord<'a> {
 }
 
 impl<'a> JitDumpRawRecord<'a> {
-    pub fn parse(&self) -> Result<JitDumpRecord, std::io::Error> {
+    pub fn parse(&self) -> Result<JitDumpRecord<'_>, std::io::Error> {
         match self.record_type {
             JitDumpRecordType::JIT_CODE_LOAD => {
                 let record = JitCodeLoadRecord::parse(self.endian, self.body)?;
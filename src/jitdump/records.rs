@@ -59,6 +59,13 @@ impl<'a> JitCodeLoadRecord<'a> {
         })
     }
 
+    /// The function name, demangled with [`demangle`](crate::demangle).
+    #[cfg(feature = "demangle")]
+    pub fn demangled_function_name(&self) -> String {
+        let name = self.function_name.as_slice();
+        crate::demangle(&String::from_utf8_lossy(&name)).into_owned()
+    }
+
     /// The offset, in bytes, between the start of the record header and
     /// the start of the code bytes.
     ///
@@ -158,7 +165,7 @@ impl<'a> JitCodeDebugInfoRecord<'a> {
         let mut cur = data;
         let code_addr = cur.read_u64::<O>()?;
         let nr_entry = cur.read_u64::<O>()?;
-        let mut entries = Vec::with_capacity(nr_entry as usize);
+        let mut entries = Vec::new();
         for _ in 0..nr_entry {
             let code_addr = cur.read_u64::<O>()?;
             let line = cur.read_u32::<O>()?;
@@ -175,7 +182,7 @@ impl<'a> JitCodeDebugInfoRecord<'a> {
         Ok(Self { code_addr, entries })
     }
 
-    pub fn lookup(&self, addr: u64) -> Option<&JitCodeDebugInfoEntry> {
+    pub fn lookup(&self, addr: u64) -> Option<&JitCodeDebugInfoEntry<'a>> {
         let index = match self
             .entries
             .binary_search_by_key(&addr, |entry| entry.code_addr)
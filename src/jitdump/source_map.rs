@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use sourcemap::DecodedMap;
+
+use super::JitCodeDebugInfoEntry;
+
+/// A location in an original source file, found via a source map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalLocation {
+    /// The original source file, as listed in the source map.
+    pub file_path: String,
+    /// The line number (1-based).
+    pub line: u32,
+    /// The column number (1-based).
+    pub column: u32,
+    /// The original function or identifier name, if the source map has one.
+    pub name: Option<String>,
+}
+
+/// Reads a file, see [`JsSourceMaps::set_file_loader`].
+type FileLoader = dyn FnMut(&Path) -> Option<Vec<u8>>;
+
+/// Maps the debug info of JavaScript runtimes like Node / V8, which refers
+/// to generated JS files, back to the original sources, e.g. TypeScript.
+///
+/// Source maps can be added with [`JsSourceMaps::add_source_map`]. The file
+/// system is only accessed if a file loader is set with
+/// [`JsSourceMaps::set_file_loader`]: then the source map of a generated file
+/// is found via its `//# sourceMappingURL=` comment, which can refer to a
+/// file relative to the generated file or contain the source map as a
+/// `data:` URL. URLs which are absolute or contain `..` are never followed.
+/// Source maps are loaded when a generated file is first looked up and are
+/// cached.
+///
+/// ```
+/// use linux_perf_data::jitdump::JsSourceMaps;
+///
+/// let mut maps = JsSourceMaps::new();
+/// maps.set_file_loader(|path| std::fs::read(path).ok());
+/// ```
+#[derive(Default)]
+pub struct JsSourceMaps {
+    /// Indexed by the generated file path. `None` if the file has no source
+    /// map or if it couldn't be loaded.
+    maps: HashMap<Vec<u8>, Option<DecodedMap>>,
+    loader: Option<Box<FileLoader>>,
+}
+
+impl JsSourceMaps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets source maps be found via the generated files. `loader` is called
+    /// with the path of a generated file, and with the path of the source
+    /// map file it refers to, and returns the file contents, or `None` if the
+    /// file shouldn't or can't be read.
+    pub fn set_file_loader(&mut self, loader: impl FnMut(&Path) -> Option<Vec<u8>> + 'static) {
+        self.loader = Some(Box::new(loader));
+    }
+
+    /// Uses `source_map` for the generated file at `generated_path` instead of
+    /// loading it with the file loader.
+    pub fn add_source_map(
+        &mut self,
+        generated_path: &[u8],
+        source_map: &[u8],
+    ) -> Result<(), sourcemap::Error> {
+        let map = sourcemap::decode_slice(source_map)?;
+        self.maps.insert(generated_path.to_vec(), Some(map));
+        Ok(())
+    }
+
+    /// Maps a debug info entry to its location in the original source.
+    /// Returns `None` if there's no source map for the entry's file or if the
+    /// source map doesn't cover the location.
+    pub fn map_entry(&mut self, entry: &JitCodeDebugInfoEntry) -> Option<OriginalLocation> {
+        self.map_location(&entry.file_path.as_slice(), entry.line, entry.column)
+    }
+
+    /// Maps a location in a generated file, with a 1-based line and a
+    /// 1-based column (0 if unknown), to its location in the original source.
+    pub fn map_location(
+        &mut self,
+        generated_path: &[u8],
+        line: u32,
+        column: u32,
+    ) -> Option<OriginalLocation> {
+        let loader = &mut self.loader;
+        let map = self
+            .maps
+            .entry(generated_path.to_vec())
+            .or_insert_with(|| load_source_map(loader.as_mut()?, generated_path))
+            .as_ref()?;
+        let token = map.lookup_token(line.checked_sub(1)?, column.saturating_sub(1))?;
+        Some(OriginalLocation {
+            file_path: token.get_source()?.to_string(),
+            line: token.get_src_line() + 1,
+            column: token.get_src_col() + 1,
+            name: token.get_name().map(ToString::to_string),
+        })
+    }
+}
+
+/// Finds the source map for a generated file via its `sourceMappingURL`
+/// comment.
+fn load_source_map(loader: &mut FileLoader, generated_path: &[u8]) -> Option<DecodedMap> {
+    let path = generated_file_path(generated_path)?;
+    let contents = loader(&path)?;
+    let reference = sourcemap::locate_sourcemap_reference_slice(&contents).ok()??;
+    if let Ok(Some(map)) = reference.get_embedded_sourcemap() {
+        return Some(map);
+    }
+    let url = Path::new(reference.get_url());
+    // Only follow plain relative paths, which stay next to the generated file.
+    if reference.get_url().contains(':')
+        || !url
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return None;
+    }
+    let map_path = path.parent().unwrap_or(Path::new("")).join(url);
+    sourcemap::decode_slice(&loader(&map_path)?).ok()
+}
+
+/// Turns the file path from the debug info into a file system path. V8 uses
+/// `file://` URLs for user scripts, and `node:` paths for built-in modules,
+/// which don't exist on disk.
+fn generated_file_path(generated_path: &[u8]) -> Option<PathBuf> {
+    let path = std::str::from_utf8(generated_path).ok()?;
+    let path = path.strip_prefix("file://").unwrap_or(path);
+    if !path.starts_with('/') {
+        return None;
+    }
+    Some(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::rc::Rc;
+
+    use linux_perf_event_reader::RawData;
+
+    use super::{JsSourceMaps, OriginalLocation};
+    use crate::jitdump::JitCodeDebugInfoEntry;
+
+    #[test]
+    fn map_entry() {
+        // Generated line 1, column 1 maps to app.ts line 3, column 5, with the name "main".
+        let source_map =
+            br#"{"version":3,"sources":["app.ts"],"names":["main"],"mappings":"AAEIA"}"#;
+        let mut maps = JsSourceMaps::new();
+        maps.add_source_map(b"/srv/app.js", source_map).unwrap();
+
+        let entry = JitCodeDebugInfoEntry {
+            code_addr: 0x1000,
+            line: 1,
+            column: 1,
+            file_path: RawData::Single(b"/srv/app.js"),
+        };
+        assert_eq!(
+            maps.map_entry(&entry),
+            Some(OriginalLocation {
+                file_path: "app.ts".to_string(),
+                line: 3,
+                column: 5,
+                name: Some("main".to_string()),
+            })
+        );
+        assert_eq!(maps.map_location(b"node:internal/main", 1, 1), None);
+    }
+
+    #[test]
+    fn file_loader() {
+        let source_map =
+            br#"{"version":3,"sources":["app.ts"],"names":["main"],"mappings":"AAEIA"}"#;
+        let files: HashMap<PathBuf, Vec<u8>> = [
+            (
+                "/srv/app.js",
+                &b"main();\n//# sourceMappingURL=app.js.map\n"[..],
+            ),
+            ("/srv/app.js.map", source_map),
+            (
+                "/srv/up.js",
+                b"main();\n//# sourceMappingURL=../etc/app.js.map\n",
+            ),
+            (
+                "/srv/abs.js",
+                b"main();\n//# sourceMappingURL=/srv/app.js.map\n",
+            ),
+        ]
+        .into_iter()
+        .map(|(path, contents)| (PathBuf::from(path), contents.to_vec()))
+        .collect();
+
+        // Without a loader, nothing is read.
+        let mut maps = JsSourceMaps::new();
+        assert_eq!(maps.map_location(b"/srv/app.js", 1, 1), None);
+
+        let requested = Rc::new(RefCell::new(Vec::new()));
+        let mut maps = JsSourceMaps::new();
+        maps.set_file_loader({
+            let requested = requested.clone();
+            move |path| {
+                requested.borrow_mut().push(path.to_owned());
+                files.get(path).cloned()
+            }
+        });
+        let location = maps.map_location(b"file:///srv/app.js", 1, 1).unwrap();
+        assert_eq!((location.file_path.as_str(), location.line), ("app.ts", 3));
+        assert_eq!(maps.map_location(b"/srv/up.js", 1, 1), None);
+        assert_eq!(maps.map_location(b"/srv/abs.js", 1, 1), None);
+        assert_eq!(
+            *requested.borrow(),
+            [
+                "/srv/app.js",
+                "/srv/app.js.map",
+                "/srv/up.js",
+                "/srv/abs.js"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+}
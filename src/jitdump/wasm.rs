@@ -0,0 +1,86 @@
+use super::JitCodeDebugInfoEntry;
+
+/// A code location in a WebAssembly module, as a byte offset into the module.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WasmLocation {
+    /// The module, as named in the debug info: a file path or a URL.
+    pub module: String,
+    /// The byte offset in the module's binary, which can be mapped to the
+    /// `.wat` text or to DWARF in the module.
+    pub offset: u64,
+}
+
+impl WasmLocation {
+    /// Recognizes the conventions that Wasm runtimes use for the file paths
+    /// in jitdump debug info, which aren't actual file system paths:
+    ///
+    ///  - `<module>.wasm@0x<offset>` or `<module>.wasm:0x<offset>`, with the
+    ///    byte offset in the path, as emitted by Wasmtime and Wasmer.
+    ///  - `wasm://<module>`, where the column is the byte offset plus one, as
+    ///    emitted by V8.
+    ///
+    /// Returns `None` for other entries.
+    pub fn from_debug_info_entry(entry: &JitCodeDebugInfoEntry) -> Option<Self> {
+        let path = entry.file_path.as_slice();
+        let path = std::str::from_utf8(&path).ok()?;
+        Self::parse(path, entry.column)
+    }
+
+    fn parse(path: &str, column: u32) -> Option<Self> {
+        if path.starts_with("wasm://") {
+            let offset = column.checked_sub(1)?;
+            return Some(Self {
+                module: path.to_string(),
+                offset: u64::from(offset),
+            });
+        }
+        let wasm_end = path.find(".wasm")? + ".wasm".len();
+        let (module, suffix) = path.split_at(wasm_end);
+        let hex = suffix
+            .strip_prefix("@0x")
+            .or_else(|| suffix.strip_prefix(":0x"))?;
+        let offset = u64::from_str_radix(hex, 16).ok()?;
+        Some(Self {
+            module: module.to_string(),
+            offset,
+        })
+    }
+}
+
+impl JitCodeDebugInfoEntry<'_> {
+    /// The location in a Wasm module, if this entry's file path uses one of
+    /// the conventions of Wasm runtimes. See [`WasmLocation::from_debug_info_entry`].
+    pub fn wasm_location(&self) -> Option<WasmLocation> {
+        WasmLocation::from_debug_info_entry(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::WasmLocation;
+
+    #[test]
+    fn parse_wasm_paths() {
+        assert_eq!(
+            WasmLocation::parse("/app/module.wasm@0x1a2b", 0),
+            Some(WasmLocation {
+                module: "/app/module.wasm".to_string(),
+                offset: 0x1a2b,
+            })
+        );
+        assert_eq!(
+            WasmLocation::parse("module.wasm:0x10", 0).map(|l| l.offset),
+            Some(0x10)
+        );
+        assert_eq!(
+            WasmLocation::parse("wasm://wasm/4c3d0f6e", 101),
+            Some(WasmLocation {
+                module: "wasm://wasm/4c3d0f6e".to_string(),
+                offset: 100,
+            })
+        );
+        assert_eq!(WasmLocation::parse("wasm://wasm/4c3d0f6e", 0), None);
+        assert_eq!(WasmLocation::parse("/app/module.wasm", 3), None);
+        assert_eq!(WasmLocation::parse("/app/main.js", 3), None);
+    }
+}
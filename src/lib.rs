@@ -10,6 +10,9 @@
 //! The [`jitdump`] module lets you parse jitdump files, which are used in
 //! conjunction with perf.data files when profiling JIT runtimes.
 //!
+//! The [`tracepoint`] module parses the formats of tracepoint events, which
+//! describe the raw data of tracepoint samples.
+//!
 //! # Example
 //!
 //! ```
@@ -25,7 +28,7 @@
 //!
 //! while let Some(record) = record_iter.next_record(&mut perf_file)? {
 //!     match record {
-//!         PerfFileRecord::EventRecord { attr_index, record } => {
+//!         PerfFileRecord::EventRecord { attr_index, record, .. } => {
 //!             let record_type = record.record_type;
 //!             let parsed_record = record.parse()?;
 //!             println!("{:?} for event {}: {:?}", record_type, attr_index, parsed_record);
@@ -41,22 +44,60 @@
 //! # }
 //! ```
 
+pub mod analysis;
+mod attr_encoding;
+mod auxtrace;
+mod auxtrace_info;
 mod build_id_event;
+#[cfg(feature = "zstd")]
+mod compression;
 mod constants;
+mod cpu_layout;
+mod cpu_map;
+mod cpu_set;
+mod custom_record;
+mod data_directory;
+#[cfg(feature = "zstd")]
+mod decompression;
+#[cfg(feature = "demangle")]
+mod demangle;
 mod dso_info;
 mod dso_key;
+mod dump;
 mod error;
+mod event_update;
 mod feature_sections;
+mod feature_sections_builder;
+mod feature_value;
 mod features;
 mod file_reader;
+mod file_writer;
 mod header;
+mod id_index;
+mod index;
 pub mod jitdump;
 mod perf_file;
+mod pipe_reader;
+mod pipe_writer;
+mod push_parser;
+mod read_values;
 mod record;
+mod record_body;
+mod record_misc;
+mod redact;
 mod section;
+mod session;
+mod sidecar_index;
 mod simpleperf;
 mod sorter;
+mod stat;
+mod string_table;
+mod thread_demux;
 mod thread_map;
+mod time_conv;
+pub mod tracepoint;
+mod transcode;
+mod unwind;
 
 /// This is a re-export of the linux-perf-event-reader crate. We use its types
 /// in our public API.
@@ -65,18 +106,76 @@ pub use linux_perf_event_reader;
 /// This is a re-export of the `prost` crate. We use its types in our public API.
 pub use prost;
 
+/// This is a re-export of the `bstr` crate. We use its types in our public API.
+#[cfg(feature = "bstr")]
+pub use bstr;
+
+/// This is a re-export of the `sourcemap` crate. We use its types in our public API.
+#[cfg(feature = "sourcemap")]
+pub use sourcemap;
+
 pub use linux_perf_event_reader::Endianness;
 
+pub use auxtrace::{AuxRecord, AuxtraceRecord};
+pub use auxtrace_info::{
+    ArmSpeConfig, AuxtraceConfig, AuxtraceInfo, AuxtraceType, CsEtmConfig, IntelBtsConfig,
+    IntelPtConfig,
+};
+pub use build_id_event::BuildIdEvent;
+pub use cpu_layout::{CpuIndexStatus, CpuLayout};
+pub use cpu_map::CpuMap;
+pub use cpu_set::CpuSet;
+pub use custom_record::{
+    CustomRecordParseFn, CustomRecordType, CustomRecordTypes, CustomUserRecord,
+};
+pub use data_directory::{KcoreDir, PerfDataDirectory, PerfDirectoryReader};
+#[cfg(feature = "demangle")]
+pub use demangle::demangle;
 pub use dso_info::DsoInfo;
-pub use dso_key::DsoKey;
+pub use dso_key::{DsoKey, DsoKeyDetectionOptions};
+pub use dump::{write_dump, DUMP_FORMAT_VERSION};
 pub use error::{Error, ReadError};
-pub use feature_sections::{AttributeDescription, NrCpus, SampleTimeRange};
+pub use event_update::{EventUpdate, EventUpdateType};
+pub use feature_sections::{
+    AttributeDescription, CacheLevel, CacheTopology, ClockData, CompressionInfo, CoreType,
+    CpuTopology, CpuTopologyEntry, EventGroup, GroupDesc, HybridTopology, HybridTopologyNode,
+    NrCpus, NumaNode, NumaTopology, PmuCaps, PmuMappings, SampleTimeRange,
+};
+pub use feature_sections_builder::FeatureSectionsBuilder;
+pub use feature_value::FeatureValue;
 pub use features::{Feature, FeatureSet, FeatureSetIter};
-pub use file_reader::{PerfFileReader, PerfRecordIter};
+pub use file_reader::{
+    FollowStatus, PerfFileReader, PerfRecordIter, PerfRecordIterState, PollRecord,
+    UnknownRecordType, DEFAULT_MAX_FEATURE_SECTION_SIZE,
+};
+pub use file_writer::PerfFileWriter;
+pub use header::PerfHeader;
+pub use id_index::{IdIndex, IdIndexEntry};
+pub use index::{PerfFileIndex, RoundIndexEntry};
 pub use perf_file::PerfFile;
-pub use record::{PerfFileRecord, RawUserRecord, UserRecord, UserRecordType};
+pub use pipe_writer::PerfPipeWriter;
+pub use push_parser::PerfRecordPushParser;
+pub use read_values::{ReadCounterValue, ReadRecord, ReadValues, PERF_FORMAT_LOST};
+pub use record::{OwnedPerfFileRecord, PerfFileRecord, RawUserRecord, UserRecord, UserRecordType};
+pub use record_misc::RecordMisc;
+pub use redact::Redactor;
+pub use section::PerfFileSection;
+pub use session::PerfSession;
+pub use sidecar_index::{SidecarBuildId, SidecarIndex, SIDECAR_INDEX_VERSION};
 pub use simpleperf::{
-    simpleperf_dso_type, SimpleperfDexFileInfo, SimpleperfElfFileInfo, SimpleperfFileRecord,
-    SimpleperfKernelModuleInfo, SimpleperfSymbol, SimpleperfTypeSpecificInfo,
+    simpleperf_dso_type, SimpleperfDebugUnwindFeature, SimpleperfDebugUnwindFile,
+    SimpleperfDebugUnwindFileContents, SimpleperfDexFileInfo, SimpleperfDsoRecord,
+    SimpleperfDsoTable, SimpleperfElfFileInfo, SimpleperfEventId, SimpleperfEventIdRecord,
+    SimpleperfFileRecord, SimpleperfKernelModuleInfo, SimpleperfSymbol, SimpleperfSymbolRecord,
+    SimpleperfTypeSpecificInfo,
+};
+pub use sorter::Sorter;
+pub use stat::{AggregationMode, Stat, StatConfig, StatEvent, StatMetadata};
+pub use string_table::{StringId, StringTable};
+pub use thread_demux::{record_thread, ThreadDemux, ThreadRecord, ThreadRecords};
+pub use thread_map::{ThreadMap, ThreadMapEntry, ThreadMapIter, ThreadMapOwned, ThreadMapTryIter};
+pub use time_conv::TimeConv;
+pub use transcode::{extract_time_range, transcode, TranscodeAction};
+pub use unwind::{
+    UnwindArch, UnwindInfoSource, UnwindModule, UserStack, UserStackUnwindDriver, UserStackUnwinder,
 };
-pub use thread_map::ThreadMap;
@@ -1,32 +1,120 @@
-use byteorder::{BigEndian, LittleEndian};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
 use linear_map::LinearMap;
-use linux_perf_event_reader::{CpuMode, Endianness};
+use linux_perf_event_reader::{ClockId, CpuMode, Endianness, PerfClock, PerfEventHeader};
 
 use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Take};
 use std::ops::Deref;
 
 use super::build_id_event::BuildIdEvent;
+use super::constants::PERF_RECORD_HEADER_BUILD_ID;
+use super::cpu_layout::CpuLayout;
 use super::dso_info::DsoInfo;
-use super::dso_key::DsoKey;
+use super::dso_key::{DsoKey, DsoKeyDetectionOptions};
 use super::error::Error;
-use super::feature_sections::{AttributeDescription, NrCpus, PmuMappings, SampleTimeRange};
+use super::feature_sections::{
+    AttributeDescription, CacheTopology, ClockData, CompressionInfo, CpuTopology, EventGroup,
+    GroupDesc, HybridTopology, NrCpus, NumaTopology, PmuCaps, PmuMappings, SampleTimeRange,
+};
+use super::feature_value::FeatureValue;
 use super::features::{Feature, FeatureSet};
+use super::header::PerfHeader;
+use super::id_index::IdIndexEntry;
+use super::section::PerfFileSection;
 use super::simpleperf;
+use super::tracepoint::{TracepointDecoder, TracingData};
 
 /// Contains the information from the perf.data file header and feature sections.
 pub struct PerfFile {
     pub(crate) endian: Endianness,
+    pub(crate) header: PerfHeader,
     pub(crate) features: FeatureSet,
     pub(crate) feature_sections: LinearMap<Feature, Vec<u8>>,
-    /// Guaranteed to have at least one element
+    pub(crate) feature_section_locations: LinearMap<Feature, PerfFileSection>,
+    /// Only empty for `perf stat record` files, see [`PerfFile::event_attributes`].
     pub(crate) attributes: Vec<AttributeDescription>,
+    pub(crate) event_id_to_attr_index: HashMap<u64, usize>,
+    /// The entries of the `ID_INDEX` records which were returned by the
+    /// record iterator so far, by event ID.
+    pub(crate) id_index_entries: HashMap<u64, IdIndexEntry>,
+    /// The encoded `perf_event_attr` of each attribute, as stored in the attr
+    /// section. Empty if the attr section doesn't match the attributes.
+    pub(crate) raw_attrs: Vec<Vec<u8>>,
+    /// The `HEADER_BUILD_ID` records which were returned by the record
+    /// iterator so far, with their headers, in the same format as the
+    /// `BUILD_ID` section.
+    pub(crate) record_build_id_events: Vec<u8>,
 }
 
 impl PerfFile {
     /// The attributes which were requested for each perf event, along with the IDs.
+    ///
+    /// This is only empty for files from `perf stat record` which have the
+    /// `STAT` feature, and whose events are only described by
+    /// `EVENT_UPDATE` and `STAT_CONFIG` records.
+    ///
+    /// The event names and IDs are updated from `EVENT_UPDATE` and
+    /// `SIMPLEPERF_EVENT_ID` records when [`PerfRecordIter::next_record`](crate::PerfRecordIter::next_record)
+    /// returns such a record.
     pub fn event_attributes(&self) -> &[AttributeDescription] {
         &self.attributes
     }
+
+    /// Returns the index of the event attribute which the event ID belongs
+    /// to, i.e. the attribute whose [`AttributeDescription::event_ids`]
+    /// contains the ID.
+    ///
+    /// This is the mapping that [`PerfRecordIter`](crate::PerfRecordIter)
+    /// uses to find the `attr_index` of each record. Use it together with
+    /// [`PerfFileRecord::event_id`](crate::PerfFileRecord::event_id), or
+    /// with the IDs in group read values.
+    pub fn attr_index_for_event_id(&self, event_id: u64) -> Option<usize> {
+        self.event_id_to_attr_index.get(&event_id).copied()
+    }
+
+    /// The bytes of the attribute's `perf_event_attr` in the attr section, in
+    /// the byte order of the file.
+    pub(crate) fn raw_attr(&self, attr_index: usize) -> Option<&[u8]> {
+        self.raw_attrs.get(attr_index).map(Vec::as_slice)
+    }
+
+    /// The `read_format` of the attribute, as stored in the attr section.
+    ///
+    /// Unlike the `read_format` of [`AttributeDescription::attr`], this keeps
+    /// the bits which [`ReadFormat`](linux_perf_event_reader::ReadFormat)
+    /// doesn't know about, like [`PERF_FORMAT_LOST`](crate::PERF_FORMAT_LOST).
+    /// Use it to parse [`ReadValues`](crate::ReadValues). Returns `None` if
+    /// the raw attribute isn't available.
+    pub fn read_format(&self, attr_index: usize) -> Option<linux_perf_event_reader::ReadFormat> {
+        let bytes = self.raw_attr(attr_index)?.get(32..40)?;
+        let bits = match self.endian {
+            Endianness::LittleEndian => LittleEndian::read_u64(bytes),
+            Endianness::BigEndian => BigEndian::read_u64(bytes),
+        };
+        Some(linux_perf_event_reader::ReadFormat::from_bits_retain(bits))
+    }
+
+    /// Returns the `ID_INDEX` entry for the event ID, with the mmap buffer
+    /// index, the CPU and the thread of the event.
+    ///
+    /// The entries are added when [`PerfRecordIter::next_record`](crate::PerfRecordIter::next_record)
+    /// returns an `ID_INDEX` record, so this returns `None` until then.
+    pub fn id_index_entry(&self, event_id: u64) -> Option<&IdIndexEntry> {
+        self.id_index_entries.get(&event_id)
+    }
+
+    /// Returns the index of the first event attribute with the name `name`,
+    /// e.g. `cycles` or `sched:sched_switch`, see
+    /// [`AttributeDescription::name`].
+    ///
+    /// Use it with [`PerfRecordIter::set_attr_index_filter`](crate::PerfRecordIter::set_attr_index_filter)
+    /// to only read the records of some events.
+    pub fn attr_index_for_name(&self, name: &str) -> Option<usize> {
+        self.attributes
+            .iter()
+            .position(|attribute| attribute.name() == Some(name))
+    }
+
     /// Returns a map of build ID entries. `perf record` creates these records for any DSOs
     /// which it thinks have been "hit" in the profile. They supplement Mmap records, which
     /// usually don't come with build IDs.
@@ -57,33 +145,87 @@ impl PerfFile {
     /// This method is a bit lossy. We discard the pid, because it seems to be always -1 in
     /// the files I've tested. We also discard any entries for which we fail to create a `DsoKey`.
     pub fn build_ids(&self) -> Result<HashMap<DsoKey, DsoInfo>, Error> {
-        let section_data = match self.feature_section_data(Feature::BUILD_ID) {
-            Some(section) => section,
-            None => return Ok(HashMap::new()),
-        };
-        let mut cursor = section_data;
+        self.build_ids_with_options(&DsoKeyDetectionOptions::default())
+    }
+
+    /// Like [`PerfFile::build_ids`], but uses the supplied options to create
+    /// the `DsoKey` for each entry.
+    pub fn build_ids_with_options(
+        &self,
+        options: &DsoKeyDetectionOptions,
+    ) -> Result<HashMap<DsoKey, DsoInfo>, Error> {
         let mut build_ids = HashMap::new();
-        loop {
-            let event = match self.endian {
-                Endianness::LittleEndian => BuildIdEvent::parse::<_, LittleEndian>(&mut cursor),
-                Endianness::BigEndian => BuildIdEvent::parse::<_, BigEndian>(&mut cursor),
-            };
-            let event = match event {
-                Ok(e) => e,
-                Err(_) => break,
-            };
-            let misc = event.header.misc;
-            let path = event.file_path;
-            let build_id = event.build_id;
-            let dso_key = match DsoKey::detect(&path, CpuMode::from_misc(misc)) {
-                Some(dso_key) => dso_key,
-                None => continue,
-            };
+        for event in self.build_id_events() {
+            let cpu_mode = CpuMode::from_misc(event.header.misc);
+            let dso_key =
+                match DsoKey::detect_with_options(event.file_path, cpu_mode, true, options) {
+                    Some(dso_key) => dso_key,
+                    None => continue,
+                };
+            let path = event.file_path.to_owned();
+            let build_id = event.build_id.to_owned();
             build_ids.insert(dso_key, DsoInfo { path, build_id });
         }
         Ok(build_ids)
     }
 
+    /// The raw entries of the `BUILD_ID` section, in file order, borrowing
+    /// the paths and build IDs from the section data.
+    ///
+    /// These are followed by the entries from the `HEADER_BUILD_ID` records
+    /// which [`PerfRecordIter::next_record`](crate::PerfRecordIter::next_record)
+    /// has returned so far. Streams from `perf record -o -` and files from
+    /// `perf inject --buildid-all` have their build IDs in such records
+    /// rather than in the section.
+    ///
+    /// Unlike [`PerfFile::build_ids`], this doesn't allocate, and it keeps
+    /// all entries, including the ones for which no `DsoKey` can be created.
+    /// Parsing stops at the first malformed entry.
+    pub fn build_id_events(&self) -> impl Iterator<Item = BuildIdEvent<'_>> + '_ {
+        let section_data = self.feature_section_data(Feature::BUILD_ID).unwrap_or(&[]);
+        self.parse_build_id_events(section_data)
+            .chain(self.parse_build_id_events(&self.record_build_id_events))
+    }
+
+    fn parse_build_id_events<'a>(
+        &self,
+        mut data: &'a [u8],
+    ) -> impl Iterator<Item = BuildIdEvent<'a>> + 'a {
+        let endian = self.endian;
+        std::iter::from_fn(move || {
+            if data.is_empty() {
+                return None;
+            }
+            let event = match endian {
+                Endianness::LittleEndian => BuildIdEvent::parse::<LittleEndian>(&mut data),
+                Endianness::BigEndian => BuildIdEvent::parse::<BigEndian>(&mut data),
+            };
+            event.ok()
+        })
+    }
+
+    /// Appends a `HEADER_BUILD_ID` record to the entries returned by
+    /// [`PerfFile::build_id_events`].
+    pub(crate) fn add_build_id_record(&mut self, misc: u16, body: &[u8]) {
+        let Ok(size) = u16::try_from(PerfEventHeader::STRUCT_SIZE + body.len()) else {
+            return;
+        };
+        let events = &mut self.record_build_id_events;
+        match self.endian {
+            Endianness::LittleEndian => {
+                events.extend_from_slice(&PERF_RECORD_HEADER_BUILD_ID.to_le_bytes());
+                events.extend_from_slice(&misc.to_le_bytes());
+                events.extend_from_slice(&size.to_le_bytes());
+            }
+            Endianness::BigEndian => {
+                events.extend_from_slice(&PERF_RECORD_HEADER_BUILD_ID.to_be_bytes());
+                events.extend_from_slice(&misc.to_be_bytes());
+                events.extend_from_slice(&size.to_be_bytes());
+            }
+        }
+        events.extend_from_slice(body);
+    }
+
     /// The timestamp of the first and the last sample in this file.
     pub fn sample_time_range(&self) -> Result<Option<SampleTimeRange>, Error> {
         let section_data = match self.feature_section_data(Feature::SAMPLE_TIME) {
@@ -97,6 +239,23 @@ impl PerfFile {
         Ok(Some(time_range))
     }
 
+    /// The tracepoint event formats from the `TRACING_DATA` section, which
+    /// `perf record` writes when tracepoint events were recorded.
+    pub fn tracing_data(&self) -> Result<Option<TracingData>, Error> {
+        self.feature_section_data(Feature::TRACING_DATA)
+            .map(|section| Ok(TracingData::parse(section)?))
+            .transpose()
+    }
+
+    /// A decoder for the raw data of the tracepoint samples in this file,
+    /// using the event formats from the `TRACING_DATA` section. The raw data
+    /// is decoded in the byte order of the file, see [`PerfFile::endian`].
+    pub fn tracepoint_decoder(&self) -> Result<Option<TracepointDecoder>, Error> {
+        Ok(self.tracing_data()?.map(|tracing_data| {
+            TracepointDecoder::from_tracing_data(tracing_data).with_endian(self.endian)
+        }))
+    }
+
     /// Only call this for features whose section is just a perf_header_string.
     fn feature_string(&self, feature: Feature) -> Result<Option<&str>, Error> {
         match self.feature_section_data(feature) {
@@ -105,6 +264,25 @@ impl PerfFile {
         }
     }
 
+    /// The raw bytes of a feature section which consists of a single string,
+    /// such as `HOSTNAME`, `OSRELEASE`, `VERSION`, `ARCH`, `CPUDESC` and
+    /// `CPUID`, without the trailing nul bytes.
+    ///
+    /// Unlike the `&str`-returning accessors such as [`PerfFile::hostname`],
+    /// this doesn't fail if the string is not valid UTF-8.
+    pub fn feature_string_bytes(&self, feature: Feature) -> Result<Option<&[u8]>, Error> {
+        match self.feature_section_data(feature) {
+            Some(section) => Ok(Some(self.read_string_bytes(section)?.0)),
+            None => Ok(None),
+        }
+    }
+
+    /// Like [`PerfFile::feature_string_bytes`], but returns a [`BStr`](bstr::BStr).
+    #[cfg(feature = "bstr")]
+    pub fn feature_string_bstr(&self, feature: Feature) -> Result<Option<&bstr::BStr>, Error> {
+        Ok(self.feature_string_bytes(feature)?.map(bstr::BStr::new))
+    }
+
     /// The hostname where the data was collected (`uname -n`).
     pub fn hostname(&self) -> Result<Option<&str>, Error> {
         self.feature_string(Feature::HOSTNAME)
@@ -138,6 +316,189 @@ impl PerfFile {
             .transpose()
     }
 
+    /// The settings which were used to compress the data of this file, for
+    /// files recorded with `perf record -z`.
+    pub fn compression_info(&self) -> Result<Option<CompressionInfo>, Error> {
+        self.feature_section_data(Feature::COMPRESSED)
+            .map(|section| {
+                Ok(match self.endian {
+                    Endianness::LittleEndian => CompressionInfo::parse::<_, LittleEndian>(section),
+                    Endianness::BigEndian => CompressionInfo::parse::<_, BigEndian>(section),
+                }?)
+            })
+            .transpose()
+    }
+
+    /// The CPU topology: which CPUs share a package, die or core.
+    ///
+    /// Parsing this section requires the number of CPUs from the `NRCPUS`
+    /// section; if that section is missing, `None` is returned.
+    pub fn cpu_topology(&self) -> Result<Option<CpuTopology>, Error> {
+        let section = match self.feature_section_data(Feature::CPU_TOPOLOGY) {
+            Some(section) => section,
+            None => return Ok(None),
+        };
+        let nr_cpus = match self.nr_cpus()? {
+            Some(nr_cpus) => nr_cpus,
+            None => return Ok(None),
+        };
+        let topology = match self.endian {
+            Endianness::LittleEndian => {
+                CpuTopology::parse::<_, LittleEndian>(section, nr_cpus.nr_cpus_available)
+            }
+            Endianness::BigEndian => {
+                CpuTopology::parse::<_, BigEndian>(section, nr_cpus.nr_cpus_available)
+            }
+        }?;
+        Ok(Some(topology))
+    }
+
+    /// Combines [`PerfFile::nr_cpus`], [`PerfFile::cpu_topology`] and
+    /// [`PerfFile::hybrid_topology`] into a [`CpuLayout`], which can be used to
+    /// validate the `cpu` field of samples and to map CPUs to sockets, cores
+    /// and core types.
+    pub fn cpu_layout(&self) -> Result<CpuLayout, Error> {
+        Ok(CpuLayout::new(
+            self.nr_cpus()?,
+            self.cpu_topology()?,
+            self.hybrid_topology()?,
+        ))
+    }
+
+    /// The hybrid CPU topology, i.e. which CPUs are covered by which core PMU.
+    /// Only present on hybrid machines, e.g. Intel Alder Lake.
+    pub fn hybrid_topology(&self) -> Result<Option<HybridTopology>, Error> {
+        self.feature_section_data(Feature::HYBRID_TOPOLOGY)
+            .map(|section| {
+                Ok(match self.endian {
+                    Endianness::LittleEndian => HybridTopology::parse::<_, LittleEndian>(section),
+                    Endianness::BigEndian => HybridTopology::parse::<_, BigEndian>(section),
+                }?)
+            })
+            .transpose()
+    }
+
+    /// The NUMA nodes, with their memory and CPUs.
+    pub fn numa_topology(&self) -> Result<Option<NumaTopology>, Error> {
+        self.feature_section_data(Feature::NUMA_TOPOLOGY)
+            .map(|section| {
+                Ok(match self.endian {
+                    Endianness::LittleEndian => NumaTopology::parse::<_, LittleEndian>(section),
+                    Endianness::BigEndian => NumaTopology::parse::<_, BigEndian>(section),
+                }?)
+            })
+            .transpose()
+    }
+
+    /// The CPU caches, with their sizes and the CPUs which share them.
+    pub fn cache_topology(&self) -> Result<Option<CacheTopology>, Error> {
+        self.feature_section_data(Feature::CACHE)
+            .map(|section| {
+                Ok(match self.endian {
+                    Endianness::LittleEndian => CacheTopology::parse::<_, LittleEndian>(section),
+                    Endianness::BigEndian => CacheTopology::parse::<_, BigEndian>(section),
+                }?)
+            })
+            .transpose()
+    }
+
+    /// The clock which was used for the record timestamps, e.g.
+    /// [`ClockId::Monotonic`] for `perf record -k mono`. Returns `None` if
+    /// perf's default clock was used.
+    ///
+    /// The clock is taken from the event attributes, or from the
+    /// `CLOCK_DATA` section if the attributes don't have one. (Despite its
+    /// name, the `CLOCKID` section only has the clock's resolution, see
+    /// [`PerfFile::clock_resolution_ns`].)
+    pub fn clockid(&self) -> Result<Option<ClockId>, Error> {
+        let attr_clock = self
+            .attributes
+            .iter()
+            .find_map(|attr| match attr.attr.clock {
+                PerfClock::ClockId(clockid) => Some(clockid),
+                PerfClock::Default => None,
+            });
+        if let Some(clockid) = attr_clock {
+            return Ok(Some(clockid));
+        }
+        Ok(self
+            .clock_data()?
+            .and_then(|clock_data| ClockId::from_u32(clock_data.clockid)))
+    }
+
+    /// The resolution of the clock from [`PerfFile::clockid`], in
+    /// nanoseconds. (`CLOCKID`)
+    pub fn clock_resolution_ns(&self) -> Result<Option<u64>, Error> {
+        let data = match self.feature_section_data(Feature::CLOCKID) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let mut reader = data;
+        let resolution = match self.endian {
+            Endianness::LittleEndian => reader.read_u64::<LittleEndian>(),
+            Endianness::BigEndian => reader.read_u64::<BigEndian>(),
+        }
+        .map_err(|_| Error::FeatureSectionTooSmall)?;
+        Ok(Some(resolution))
+    }
+
+    /// The version of the directory layout, for files which are the `data`
+    /// file of a `perf record --threads` directory, see
+    /// [`PerfDirectoryReader`](crate::PerfDirectoryReader). (`DIR_FORMAT`)
+    pub fn dir_format_version(&self) -> Result<Option<u64>, Error> {
+        let data = match self.feature_section_data(Feature::DIR_FORMAT) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let mut reader = data;
+        let version = match self.endian {
+            Endianness::LittleEndian => reader.read_u64::<LittleEndian>(),
+            Endianness::BigEndian => reader.read_u64::<BigEndian>(),
+        }
+        .map_err(|_| Error::FeatureSectionTooSmall)?;
+        Ok(Some(version))
+    }
+
+    /// The reference timestamps for converting record timestamps to
+    /// wall-clock time, see [`ClockData::to_system_time`]. Only present if
+    /// the profile was recorded with `perf record -k <clockid>`.
+    pub fn clock_data(&self) -> Result<Option<ClockData>, Error> {
+        self.feature_section_data(Feature::CLOCK_DATA)
+            .map(|section| {
+                Ok(match self.endian {
+                    Endianness::LittleEndian => ClockData::parse::<_, LittleEndian>(section),
+                    Endianness::BigEndian => ClockData::parse::<_, BigEndian>(section),
+                }?)
+            })
+            .transpose()
+    }
+
+    /// The capabilities of the "cpu" PMU, e.g. the depth of the branch stack.
+    /// On hybrid machines, see [`PerfFile::hybrid_cpu_pmu_caps`] instead.
+    pub fn cpu_pmu_caps(&self) -> Result<Option<PmuCaps>, Error> {
+        self.feature_section_data(Feature::CPU_PMU_CAPS)
+            .map(|section| {
+                Ok(match self.endian {
+                    Endianness::LittleEndian => PmuCaps::parse::<_, LittleEndian>(section),
+                    Endianness::BigEndian => PmuCaps::parse::<_, BigEndian>(section),
+                }?)
+            })
+            .transpose()
+    }
+
+    /// The capabilities of each core PMU on hybrid machines, e.g. "cpu_core"
+    /// and "cpu_atom".
+    pub fn hybrid_cpu_pmu_caps(&self) -> Result<Option<Vec<PmuCaps>>, Error> {
+        self.feature_section_data(Feature::HYBRID_CPU_PMU_CAPS)
+            .map(|section| {
+                Ok(match self.endian {
+                    Endianness::LittleEndian => PmuCaps::parse_hybrid::<_, LittleEndian>(section),
+                    Endianness::BigEndian => PmuCaps::parse_hybrid::<_, BigEndian>(section),
+                }?)
+            })
+            .transpose()
+    }
+
     /// The description of the CPU. On x86 this is the model name
     /// from `/proc/cpuinfo`.
     pub fn cpu_desc(&self) -> Result<Option<&str>, Error> {
@@ -163,6 +524,23 @@ impl PerfFile {
         }
     }
 
+    /// Like [`PerfFile::cmdline`], but returns the raw bytes of each argument,
+    /// which don't need to be valid UTF-8.
+    pub fn cmdline_bytes(&self) -> Result<Option<Vec<&[u8]>>, Error> {
+        match self.feature_section_data(Feature::CMDLINE) {
+            Some(section) => Ok(Some(self.read_string_list_bytes(section)?.0)),
+            None => Ok(None),
+        }
+    }
+
+    /// Like [`PerfFile::cmdline_bytes`], but returns [`BStr`](bstr::BStr)s.
+    #[cfg(feature = "bstr")]
+    pub fn cmdline_bstr(&self) -> Result<Option<Vec<&bstr::BStr>>, Error> {
+        Ok(self
+            .cmdline_bytes()?
+            .map(|args| args.into_iter().map(bstr::BStr::new).collect()))
+    }
+
     /// The total memory in kilobytes. (MemTotal from /proc/meminfo)
     pub fn total_mem(&self) -> Result<Option<u64>, Error> {
         let data = match self.feature_section_data(Feature::TOTAL_MEM) {
@@ -206,6 +584,41 @@ impl PerfFile {
         Ok(None)
     }
 
+    /// The files which simpleperf stored for offline unwinding, from the
+    /// `SIMPLEPERF_DEBUG_UNWIND` section.
+    ///
+    /// Simpleperf writes this section when recording with
+    /// `--keep-failed-unwinding-debug-info`. Use
+    /// [`PerfFile::simpleperf_debug_unwind_file_contents`] to get the
+    /// contents of the files.
+    pub fn simpleperf_debug_unwind_files(
+        &self,
+    ) -> Result<Option<Vec<simpleperf::SimpleperfDebugUnwindFile>>, Error> {
+        self.feature_section_data(Feature::SIMPLEPERF_DEBUG_UNWIND)
+            .map(simpleperf::parse_debug_unwind_section)
+            .transpose()
+    }
+
+    /// The files from [`PerfFile::simpleperf_debug_unwind_files`], each with
+    /// its contents from the `SIMPLEPERF_DEBUG_UNWIND_FILE` section. These
+    /// are the ELF files which are needed to unwind the samples for which
+    /// unwinding failed on the device.
+    ///
+    /// Returns `None` if either of the two sections is missing.
+    pub fn simpleperf_debug_unwind_file_contents(
+        &self,
+    ) -> Result<Option<Vec<simpleperf::SimpleperfDebugUnwindFileContents<'_>>>, Error> {
+        let Some(files) = self.simpleperf_debug_unwind_files()? else {
+            return Ok(None);
+        };
+        let Some(section) = self.feature_section_data(Feature::SIMPLEPERF_DEBUG_UNWIND_FILE) else {
+            return Ok(None);
+        };
+        Ok(Some(simpleperf::split_debug_unwind_file_section(
+            files, section,
+        )?))
+    }
+
     /// The names of the dynamic PMU types used in [`PerfEventType::DynamicPmu`](linux_perf_event_reader::PerfEventType::DynamicPmu).
     ///
     /// This mapping allows you to interpret the perf event type field of the perf event
@@ -236,22 +649,168 @@ impl PerfFile {
             .transpose()
     }
 
+    /// The event groups from the `GROUP_DESC` feature section. Each
+    /// attribute's group is also available from [`AttributeDescription::group`].
+    pub fn group_descs(&self) -> Result<Option<Vec<GroupDesc>>, Error> {
+        self.feature_section_data(Feature::GROUP_DESC)
+            .map(|section| {
+                Ok(match self.endian {
+                    Endianness::LittleEndian => {
+                        GroupDesc::parse_section::<_, LittleEndian>(section)
+                    }
+                    Endianness::BigEndian => GroupDesc::parse_section::<_, BigEndian>(section),
+                }?)
+            })
+            .transpose()
+    }
+
+    /// The event groups from the `GROUP_DESC` feature section, with the
+    /// attributes of their members. The values of a `PERF_FORMAT_GROUP` read
+    /// are in the order of the group members.
+    ///
+    /// Groups which refer to attributes that don't exist are left out.
+    /// Returns an empty list if the file has no `GROUP_DESC` section.
+    pub fn event_groups(&self) -> Result<Vec<EventGroup<'_>>, Error> {
+        let groups = self.group_descs()?.unwrap_or_default();
+        Ok(groups
+            .into_iter()
+            .filter_map(|desc| {
+                let members = self.attributes.get(desc.member_indexes())?;
+                if members.is_empty() {
+                    return None;
+                }
+                Some(EventGroup { desc, members })
+            })
+            .collect())
+    }
+
+    /// Parses the feature section for `feature`, returning `None` if the file
+    /// doesn't have it.
+    ///
+    /// This covers all features which have a typed accessor on `PerfFile`.
+    /// Features which this crate doesn't parse are returned as
+    /// [`FeatureValue::Raw`]. To display all features of a file, call this
+    /// for each feature in [`features`](Self::features).
+    pub fn parsed_feature(&self, feature: Feature) -> Result<Option<FeatureValue<'_>>, Error> {
+        if !self.features.has_feature(feature) {
+            return Ok(None);
+        }
+        let value = match feature {
+            Feature::HOSTNAME
+            | Feature::OSRELEASE
+            | Feature::VERSION
+            | Feature::ARCH
+            | Feature::CPUDESC
+            | Feature::CPUID => self.feature_string(feature)?.map(FeatureValue::String),
+            Feature::CMDLINE => self.cmdline()?.map(FeatureValue::StringList),
+            Feature::NRCPUS => self.nr_cpus()?.map(FeatureValue::NrCpus),
+            Feature::TOTAL_MEM => self.total_mem()?.map(FeatureValue::TotalMem),
+            Feature::CPU_TOPOLOGY => self.cpu_topology()?.map(FeatureValue::CpuTopology),
+            Feature::HYBRID_TOPOLOGY => self.hybrid_topology()?.map(FeatureValue::HybridTopology),
+            Feature::NUMA_TOPOLOGY => self.numa_topology()?.map(FeatureValue::NumaTopology),
+            Feature::CACHE => self.cache_topology()?.map(FeatureValue::CacheTopology),
+            Feature::CLOCKID => self
+                .clock_resolution_ns()?
+                .map(FeatureValue::ClockResolutionNs),
+            Feature::DIR_FORMAT => self
+                .dir_format_version()?
+                .map(FeatureValue::DirFormatVersion),
+            Feature::CLOCK_DATA => self.clock_data()?.map(FeatureValue::ClockData),
+            Feature::CPU_PMU_CAPS => self.cpu_pmu_caps()?.map(FeatureValue::PmuCaps),
+            Feature::HYBRID_CPU_PMU_CAPS => {
+                self.hybrid_cpu_pmu_caps()?.map(FeatureValue::HybridPmuCaps)
+            }
+            Feature::BUILD_ID => Some(FeatureValue::BuildIds(self.build_ids()?)),
+            Feature::SAMPLE_TIME => self.sample_time_range()?.map(FeatureValue::SampleTimeRange),
+            Feature::TRACING_DATA => self.tracing_data()?.map(FeatureValue::TracingData),
+            Feature::PMU_MAPPINGS => self.pmu_mappings()?.map(FeatureValue::PmuMappings),
+            Feature::EVENT_DESC => Some(FeatureValue::EventDesc(&self.attributes)),
+            Feature::GROUP_DESC => self.group_descs()?.map(FeatureValue::GroupDesc),
+            Feature::SIMPLEPERF_META_INFO => self
+                .simpleperf_meta_info()?
+                .map(FeatureValue::SimpleperfMetaInfo),
+            Feature::SIMPLEPERF_FILE | Feature::SIMPLEPERF_FILE2 => self
+                .simpleperf_symbol_tables()?
+                .map(FeatureValue::SimpleperfSymbolTables),
+            Feature::COMPRESSED => self.compression_info()?.map(FeatureValue::CompressionInfo),
+            Feature::BRANCH_STACK | Feature::STAT => Some(FeatureValue::Flag),
+            Feature::SIMPLEPERF_DEBUG_UNWIND => self
+                .simpleperf_debug_unwind_files()?
+                .map(FeatureValue::SimpleperfDebugUnwindFiles),
+            _ => self.feature_section_data(feature).map(FeatureValue::Raw),
+        };
+        Ok(value)
+    }
+
+    /// The raw file header, with the attr size, the locations of the attr,
+    /// data and event types sections, and the feature bitmap.
+    pub fn header(&self) -> &PerfHeader {
+        &self.header
+    }
+
     /// The set of features used in this perf file.
     pub fn features(&self) -> FeatureSet {
         self.features
     }
 
     /// The raw data of a feature section.
+    ///
+    /// This includes the sections of features which this crate doesn't know
+    /// about, e.g. from a newer version of perf, so that their bytes and
+    /// feature bits can be carried over when the file is rewritten.
+    ///
+    /// Returns `None` for sections which were too large to be read into
+    /// memory, see
+    /// [`PerfFileReader::parse_file_with_max_feature_section_size`](crate::PerfFileReader::parse_file_with_max_feature_section_size);
+    /// use [`PerfFile::feature_section_reader`] to read those.
     pub fn feature_section_data(&self, feature: Feature) -> Option<&[u8]> {
         self.feature_sections.get(&feature).map(Deref::deref)
     }
 
+    /// The feature sections whose features this crate doesn't know, e.g.
+    /// private feature bits of a perf fork, as pairs of the feature bit and
+    /// the raw section data.
+    ///
+    /// Sections which were too large to be read into memory are left out.
+    pub fn unknown_feature_sections(&self) -> impl Iterator<Item = (u32, &[u8])> + '_ {
+        self.features
+            .into_iter()
+            .filter(|feature| !feature.is_known())
+            .filter_map(|feature| Some((feature.0, self.feature_section_data(feature)?)))
+    }
+
+    /// The file offset and the size of a feature section, in bytes.
+    pub fn feature_section_location(&self, feature: Feature) -> Option<(u64, u64)> {
+        let section = self.feature_section_locations.get(&feature)?;
+        Some((section.offset, section.size))
+    }
+
+    /// Returns a reader for the contents of a feature section, for streaming
+    /// access to sections which are too large to buffer. `reader` must read
+    /// from the same file that this `PerfFile` was parsed from.
+    pub fn feature_section_reader<R: Read + Seek>(
+        &self,
+        feature: Feature,
+        mut reader: R,
+    ) -> Result<Option<Take<R>>, Error> {
+        let Some(section) = self.feature_section_locations.get(&feature) else {
+            return Ok(None);
+        };
+        reader.seek(SeekFrom::Start(section.offset))?;
+        Ok(Some(reader.take(section.size)))
+    }
+
     /// The file endian.
     pub fn endian(&self) -> Endianness {
         self.endian
     }
 
     fn read_string<'s>(&self, s: &'s [u8]) -> Result<(&'s str, &'s [u8]), Error> {
+        let (s, rest) = self.read_string_bytes(s)?;
+        Ok((std::str::from_utf8(s)?, rest))
+    }
+
+    fn read_string_bytes<'s>(&self, s: &'s [u8]) -> Result<(&'s [u8], &'s [u8]), Error> {
         if s.len() < 4 {
             return Err(Error::NotEnoughSpaceForStringLen);
         }
@@ -267,11 +826,19 @@ impl PerfFile {
         }
         let (s, rest) = rest.split_at(len);
         let actual_len = memchr::memchr(0, s).unwrap_or(s.len());
-        let s = std::str::from_utf8(&s[..actual_len])?;
-        Ok((s, rest))
+        Ok((&s[..actual_len], rest))
     }
 
     fn read_string_list<'s>(&self, s: &'s [u8]) -> Result<(Vec<&'s str>, &'s [u8]), Error> {
+        let (list, rest) = self.read_string_list_bytes(s)?;
+        let list = list
+            .into_iter()
+            .map(std::str::from_utf8)
+            .collect::<Result<_, _>>()?;
+        Ok((list, rest))
+    }
+
+    fn read_string_list_bytes<'s>(&self, s: &'s [u8]) -> Result<(Vec<&'s [u8]>, &'s [u8]), Error> {
         if s.len() < 4 {
             return Err(Error::NotEnoughSpaceForStringListLen);
         }
@@ -282,10 +849,11 @@ impl PerfFile {
             Endianness::BigEndian => u32::from_be_bytes(len_bytes),
         };
         let len = usize::try_from(len).map_err(|_| Error::StringListLengthBiggerThanUsize)?;
-        let mut vec = Vec::with_capacity(len);
+        // Each string takes up at least four bytes, for its length.
+        let mut vec = Vec::with_capacity(len.min(rest.len() / 4));
         for _ in 0..len {
             let s;
-            (s, rest) = self.read_string(rest)?;
+            (s, rest) = self.read_string_bytes(rest)?;
             vec.push(s);
         }
 
@@ -0,0 +1,230 @@
+use std::io::Read;
+
+use byteorder::{ByteOrder, ReadBytesExt};
+use linear_map::LinearMap;
+use linux_perf_event_reader::{PerfEventAttr, PerfEventHeader};
+
+use super::constants::{
+    PERF_RECORD_HEADER_ATTR, PERF_RECORD_HEADER_EVENT_TYPE, PERF_RECORD_HEADER_FEATURE,
+    PERF_RECORD_HEADER_TRACING_DATA,
+};
+use super::error::{Error, ReadError};
+use super::feature_sections::AttributeDescription;
+use super::features::Feature;
+
+/// The size of the header of a pipe-mode stream: the magic value and the
+/// header size.
+pub(crate) const PIPE_HEADER_SIZE: u64 = 16;
+
+/// The information from the records at the start of a pipe-mode stream,
+/// which take the place of the attr section and the feature sections of the
+/// regular file format.
+pub(crate) struct PipeMetadata {
+    pub attributes: Vec<AttributeDescription>,
+    /// The encoded `perf_event_attr` of each attribute.
+    pub raw_attrs: Vec<Vec<u8>>,
+    pub feature_sections: LinearMap<Feature, Vec<u8>>,
+    /// The number of bytes which were consumed before the first record after
+    /// the metadata, including the pipe header.
+    pub len: u64,
+    /// The header of the first record after the metadata, which has already
+    /// been read. `None` if the stream ended after the metadata.
+    pub next_record_header: Option<[u8; PerfEventHeader::STRUCT_SIZE]>,
+}
+
+/// Reads the `HEADER_ATTR`, `HEADER_FEATURE`, `HEADER_TRACING_DATA` and
+/// `HEADER_EVENT_TYPE` records at the start of a pipe-mode stream, up to and
+/// including the header of the first other record. `reader` must be
+/// positioned after the magic value.
+pub(crate) fn read_pipe_metadata<R: Read, T: ByteOrder>(
+    reader: &mut R,
+) -> Result<PipeMetadata, Error> {
+    let header_size = reader.read_u64::<T>()?;
+    if header_size < PIPE_HEADER_SIZE {
+        return Err(ReadError::PerfHeader.into());
+    }
+    std::io::copy(
+        &mut reader.take(header_size - PIPE_HEADER_SIZE),
+        &mut std::io::sink(),
+    )?;
+
+    let mut metadata = PipeMetadata {
+        attributes: Vec::new(),
+        raw_attrs: Vec::new(),
+        feature_sections: LinearMap::new(),
+        len: header_size,
+        next_record_header: None,
+    };
+    while let Some(header_bytes) = read_record_header(reader)? {
+        let header = PerfEventHeader::parse::<_, T>(&header_bytes[..])?;
+        let size = usize::from(header.size);
+        if size < PerfEventHeader::STRUCT_SIZE {
+            return Err(Error::InvalidPerfEventSize);
+        }
+        if !matches!(
+            header.type_,
+            PERF_RECORD_HEADER_ATTR
+                | PERF_RECORD_HEADER_FEATURE
+                | PERF_RECORD_HEADER_TRACING_DATA
+                | PERF_RECORD_HEADER_EVENT_TYPE
+        ) {
+            metadata.next_record_header = Some(header_bytes);
+            break;
+        }
+        let mut body = vec![0; size - PerfEventHeader::STRUCT_SIZE];
+        reader
+            .read_exact(&mut body)
+            .map_err(|_| ReadError::PerfEventData)?;
+        metadata.len += size as u64;
+        match header.type_ {
+            PERF_RECORD_HEADER_ATTR => {
+                // struct { struct perf_event_attr attr; u64 id[]; }
+                let (attr, attr_size) = PerfEventAttr::parse::<_, T>(&body[..])
+                    .map_err(|_| ReadError::PerfEventAttr)?;
+                let raw_attr = usize::try_from(attr_size)
+                    .ok()
+                    .and_then(|attr_size| body.get(..attr_size))
+                    .ok_or(ReadError::PerfEventAttr)?;
+                let event_ids = body[raw_attr.len()..]
+                    .chunks_exact(8)
+                    .map(T::read_u64)
+                    .collect();
+                metadata.raw_attrs.push(raw_attr.to_vec());
+                metadata.attributes.push(AttributeDescription {
+                    attr,
+                    name: None,
+                    event_ids,
+                    group: None,
+                    is_group_leader: false,
+                });
+            }
+            PERF_RECORD_HEADER_FEATURE => {
+                let (feature, data) = parse_feature_record::<T>(&body)?;
+                metadata.feature_sections.insert(feature, data.to_vec());
+            }
+            PERF_RECORD_HEADER_TRACING_DATA => {
+                // struct { u32 size; }, followed by the tracing data, which
+                // isn't included in the record size.
+                let data_size = body
+                    .get(..4)
+                    .map(T::read_u32)
+                    .ok_or(ReadError::PerfEventData)?;
+                let mut data = Vec::new();
+                reader.take(data_size.into()).read_to_end(&mut data)?;
+                if data.len() < data_size as usize {
+                    return Err(ReadError::PerfEventData.into());
+                }
+                metadata.len += u64::from(data_size);
+                metadata
+                    .feature_sections
+                    .insert(Feature::TRACING_DATA, data);
+            }
+            _ => {
+                // HEADER_EVENT_TYPE records are obsolete.
+            }
+        }
+    }
+    Ok(metadata)
+}
+
+/// Splits the body of a `HEADER_FEATURE` record into the feature and the
+/// section data.
+pub(crate) fn parse_feature_record<T: ByteOrder>(body: &[u8]) -> Result<(Feature, &[u8]), Error> {
+    let feature = body
+        .get(..8)
+        .map(T::read_u64)
+        .and_then(|feature| u32::try_from(feature).ok())
+        .ok_or(ReadError::PerfEventData)?;
+    Ok((Feature(feature), &body[8..]))
+}
+
+/// Reads a record header. Returns `None` if the reader ends before the
+/// header.
+fn read_record_header<R: Read>(
+    reader: &mut R,
+) -> Result<Option<[u8; PerfEventHeader::STRUCT_SIZE]>, Error> {
+    let mut header = [0; PerfEventHeader::STRUCT_SIZE];
+    let mut filled = 0;
+    while filled < header.len() {
+        match reader.read(&mut header[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(ReadError::PerfEventHeader.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(Some(header))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use linux_perf_event_reader::{Endianness, RecordType, SoftwareCounterType};
+
+    use crate::file_writer::test::{sample_body, test_attr};
+    use crate::{Feature, PerfFileReader, PerfFileRecord, PerfPipeWriter};
+
+    #[test]
+    fn read_stream() {
+        let mut writer = PerfPipeWriter::new(Vec::new(), Endianness::LittleEndian).unwrap();
+        writer
+            .write_attr(&test_attr(SoftwareCounterType::CpuClock), &[5])
+            .unwrap();
+        writer
+            .write_feature(Feature::HOSTNAME, b"\x08\0\0\0myhost\0\0")
+            .unwrap();
+        writer
+            .write_record(RecordType::SAMPLE, 2, &sample_body(5, 1, 20))
+            .unwrap();
+        writer
+            .write_record(RecordType::SAMPLE, 2, &sample_body(5, 1, 10))
+            .unwrap();
+        writer.write_finished_round().unwrap();
+        let mut os_release = Vec::new();
+        os_release.write_u32::<LittleEndian>(8).unwrap();
+        os_release.extend_from_slice(b"6.1\0\0\0\0\0");
+        writer
+            .write_feature(Feature::OSRELEASE, &os_release)
+            .unwrap();
+        let stream = writer.into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_pipe(&stream[..]).unwrap();
+        assert_eq!(perf_file.event_attributes()[0].event_ids, vec![5]);
+        assert_eq!(perf_file.hostname().unwrap(), Some("myhost"));
+        assert!(!perf_file.features().has_feature(Feature::OSRELEASE));
+
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            let timestamp = match &record {
+                PerfFileRecord::EventRecord { record, .. } => record.timestamp(),
+                PerfFileRecord::UserRecord(_) => None,
+            };
+            records.push((record.offset(), timestamp));
+        }
+        // The offsets are relative to the first record after the metadata.
+        let metadata_len = 16 + (8 + 128 + 8) + (8 + 8 + 16);
+        assert_eq!(perf_file.header().data_section.offset, metadata_len as u64);
+        assert_eq!(records, vec![(72, None), (32, Some(10)), (0, Some(20))]);
+        assert_eq!(perf_file.os_release().unwrap(), Some("6.1"));
+
+        // A stream which ends after the metadata, and a cut-off stream.
+        let mut reader = PerfFileReader::parse_pipe(&stream[..metadata_len]).unwrap();
+        assert!(reader
+            .record_iter
+            .next_record(&mut reader.perf_file)
+            .unwrap()
+            .is_none());
+        let mut reader =
+            PerfFileReader::parse_pipe(Cursor::new(&stream[..metadata_len + 40])).unwrap();
+        assert!(reader
+            .record_iter
+            .next_record(&mut reader.perf_file)
+            .is_err());
+    }
+}
@@ -0,0 +1,414 @@
+use std::io::Write;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+use linux_perf_event_reader::{Endianness, PerfEventAttr, RecordType};
+
+use crate::attr_encoding::write_attr;
+#[cfg(feature = "zstd")]
+use crate::compression::Compressor;
+use crate::constants::{
+    PERF_RECORD_AUXTRACE, PERF_RECORD_FINISHED_ROUND, PERF_RECORD_HEADER_ATTR,
+    PERF_RECORD_HEADER_FEATURE,
+};
+use crate::pipe_reader::PIPE_HEADER_SIZE;
+#[cfg(feature = "zstd")]
+use crate::CompressionInfo;
+use crate::{Feature, FeatureSectionsBuilder, PerfFileRecord};
+
+const RECORD_HEADER_SIZE: usize = 8;
+/// How many bytes of records are compressed together, the same as the
+/// default mmap buffer size of `perf record`.
+#[cfg(feature = "zstd")]
+const COMPRESSION_BATCH_SIZE: usize = 516 * 1024;
+
+/// Writes a perf.data stream in pipe mode, i.e. the format that
+/// `perf record -o -` writes to stdout.
+///
+/// Unlike the regular file format, the pipe format doesn't have a header with
+/// section offsets, so it can be written to any [`Write`] without seeking,
+/// e.g. to a socket. The event attributes and the feature sections are sent
+/// as `PERF_RECORD_HEADER_ATTR` and `PERF_RECORD_HEADER_FEATURE` records,
+/// which should come before the event records that refer to them.
+///
+/// With `enable_compression`, which needs the `zstd` feature, the event
+/// records are written zstd-compressed, like `perf record -z` does.
+///
+/// Pipe-mode streams can be read with
+/// [`PerfFileReader::parse_pipe`](crate::PerfFileReader::parse_pipe), or with
+/// `perf report -i -` or `perf script -i -`, for example.
+///
+/// ```
+/// use linux_perf_data::linux_perf_event_reader::Endianness;
+/// use linux_perf_data::{Feature, PerfPipeWriter};
+///
+/// # fn wrapper() -> Result<(), std::io::Error> {
+/// let mut writer = PerfPipeWriter::new(Vec::new(), Endianness::LittleEndian)?;
+/// writer.write_feature(Feature::HOSTNAME, b"\x08\0\0\0myhost\0\0")?;
+/// let stream: Vec<u8> = writer.into_inner();
+/// # Ok(())
+/// # }
+/// ```
+pub struct PerfPipeWriter<W: Write> {
+    writer: W,
+    endian: Endianness,
+    buffer: Vec<u8>,
+    #[cfg(feature = "zstd")]
+    compressor: Option<Compressor>,
+}
+
+impl<W: Write> PerfPipeWriter<W> {
+    /// Writes the pipe header. All values are written in the byte order
+    /// `endian`; record bodies passed to [`write_record`](Self::write_record)
+    /// must use the same byte order.
+    pub fn new(mut writer: W, endian: Endianness) -> Result<Self, std::io::Error> {
+        match endian {
+            Endianness::LittleEndian => {
+                writer.write_all(b"PERFILE2")?;
+                writer.write_u64::<LittleEndian>(PIPE_HEADER_SIZE)?;
+            }
+            Endianness::BigEndian => {
+                writer.write_all(b"2ELIFREP")?;
+                writer.write_u64::<BigEndian>(PIPE_HEADER_SIZE)?;
+            }
+        }
+        Ok(Self {
+            writer,
+            endian,
+            buffer: Vec::new(),
+            #[cfg(feature = "zstd")]
+            compressor: None,
+        })
+    }
+
+    /// Writes a `COMPRESSED` feature record, and compresses all event records
+    /// which are written afterwards with zstd at the given level. The records
+    /// are collected into batches, and each batch is written as one or more
+    /// `PERF_RECORD_COMPRESSED2` records. Other records, e.g. `HEADER_ATTR` or
+    /// `AUXTRACE` records, are written uncompressed, after the pending batch.
+    ///
+    /// Call [`flush`](Self::flush) before [`into_inner`](Self::into_inner),
+    /// otherwise the last batch is lost.
+    #[cfg(feature = "zstd")]
+    pub fn enable_compression(&mut self, level: u32) -> Result<(), std::io::Error> {
+        let zstd_level = i32::try_from(level)
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        let compressor = Compressor::new(zstd_level, COMPRESSION_BATCH_SIZE)?;
+        let info = CompressionInfo {
+            version: 1,
+            compression_type: CompressionInfo::ZSTD,
+            level,
+            ratio: 0,
+            mmap_len: compressor.batch_size() as u32,
+        };
+        let mut sections = FeatureSectionsBuilder::new(self.endian);
+        sections.set_compression_info(info);
+        self.write_feature_sections(&sections)?;
+        self.compressor = Some(compressor);
+        Ok(())
+    }
+
+    /// Writes a `PERF_RECORD_HEADER_ATTR` record, which declares an event and
+    /// the IDs which its records carry.
+    pub fn write_attr(
+        &mut self,
+        attr: &PerfEventAttr,
+        event_ids: &[u64],
+    ) -> Result<(), std::io::Error> {
+        let mut body = std::mem::take(&mut self.buffer);
+        body.clear();
+        match self.endian {
+            Endianness::LittleEndian => encode_attr::<LittleEndian>(attr, event_ids, &mut body)?,
+            Endianness::BigEndian => encode_attr::<BigEndian>(attr, event_ids, &mut body)?,
+        }
+        let result = self.write_record(RecordType(PERF_RECORD_HEADER_ATTR), 0, &body);
+        self.buffer = body;
+        result
+    }
+
+    /// Writes a `PERF_RECORD_HEADER_FEATURE` record with the contents of a
+    /// feature section, in the same format as in the regular file format.
+    /// The data is padded to a multiple of 8 bytes.
+    pub fn write_feature(&mut self, feature: Feature, data: &[u8]) -> Result<(), std::io::Error> {
+        let mut body = std::mem::take(&mut self.buffer);
+        body.clear();
+        match self.endian {
+            Endianness::LittleEndian => body.write_u64::<LittleEndian>(feature.0.into())?,
+            Endianness::BigEndian => body.write_u64::<BigEndian>(feature.0.into())?,
+        }
+        body.extend_from_slice(data);
+        body.resize(body.len().next_multiple_of(8), 0);
+        let result = self.write_record(RecordType(PERF_RECORD_HEADER_FEATURE), 0, &body);
+        self.buffer = body;
+        result
+    }
+
+    /// Writes a `PERF_RECORD_HEADER_FEATURE` record for each section of
+    /// `sections`.
+    ///
+    /// Returns an `InvalidInput` error if the byte order of `sections`
+    /// doesn't match the byte order of the stream.
+    pub fn write_feature_sections(
+        &mut self,
+        sections: &FeatureSectionsBuilder,
+    ) -> Result<(), std::io::Error> {
+        if sections.endian() != self.endian {
+            return Err(std::io::ErrorKind::InvalidInput.into());
+        }
+        for (feature, data) in sections.sections() {
+            self.write_feature(feature, data)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a `PERF_RECORD_FINISHED_ROUND` record. Readers can sort and
+    /// flush all records before a round boundary.
+    pub fn write_finished_round(&mut self) -> Result<(), std::io::Error> {
+        self.write_record(RecordType(PERF_RECORD_FINISHED_ROUND), 0, &[])
+    }
+
+    /// Writes a record with the given body, i.e. the bytes after the record
+    /// header. For `PERF_RECORD_AUXTRACE` records, the body includes the aux
+    /// data, like the body returned by [`PerfRecordIter`](crate::PerfRecordIter).
+    ///
+    /// Returns an `InvalidInput` error if the record is too large for the
+    /// 16-bit size field of the record header.
+    pub fn write_record(
+        &mut self,
+        record_type: RecordType,
+        misc: u16,
+        body: &[u8],
+    ) -> Result<(), std::io::Error> {
+        match self.endian {
+            Endianness::LittleEndian => {
+                self.write_record_impl::<LittleEndian>(record_type, misc, body)
+            }
+            Endianness::BigEndian => self.write_record_impl::<BigEndian>(record_type, misc, body),
+        }
+    }
+
+    fn write_record_impl<T: ByteOrder>(
+        &mut self,
+        record_type: RecordType,
+        misc: u16,
+        body: &[u8],
+    ) -> Result<(), std::io::Error> {
+        #[cfg(feature = "zstd")]
+        if let Some(compressor) = &mut self.compressor {
+            if record_type.is_builtin_type() {
+                write_record::<T, _>(compressor.pending_mut(), record_type, misc, body)?;
+                if compressor.is_batch_full() {
+                    compressor.write_batch::<T, W>(&mut self.writer)?;
+                }
+                return Ok(());
+            }
+            compressor.write_batch::<T, W>(&mut self.writer)?;
+        }
+        write_record::<T, W>(&mut self.writer, record_type, misc, body)
+    }
+
+    /// Writes a record which was read with [`PerfRecordIter`](crate::PerfRecordIter)
+    /// from a file with the same byte order.
+    pub fn write_perf_file_record(
+        &mut self,
+        record: &PerfFileRecord,
+    ) -> Result<(), std::io::Error> {
+        let (record_type, misc, data) = match record {
+            PerfFileRecord::EventRecord { record, .. } => {
+                (record.record_type, record.misc, record.data)
+            }
+            PerfFileRecord::UserRecord(record) => {
+                (record.record_type.into(), record.misc, record.data)
+            }
+        };
+        self.write_record(record_type, misc, &data.as_slice())
+    }
+
+    /// Writes the pending batch of compressed records, if compression is
+    /// enabled, and flushes the underlying writer.
+    pub fn flush(&mut self) -> Result<(), std::io::Error> {
+        #[cfg(feature = "zstd")]
+        if let Some(compressor) = &mut self.compressor {
+            match self.endian {
+                Endianness::LittleEndian => {
+                    compressor.write_batch::<LittleEndian, W>(&mut self.writer)?
+                }
+                Endianness::BigEndian => {
+                    compressor.write_batch::<BigEndian, W>(&mut self.writer)?
+                }
+            }
+        }
+        self.writer.flush()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn encode_attr<T: ByteOrder>(
+    attr: &PerfEventAttr,
+    event_ids: &[u64],
+    out: &mut Vec<u8>,
+) -> Result<(), std::io::Error> {
+    write_attr::<T>(attr, out)?;
+    for id in event_ids {
+        out.write_u64::<T>(*id)?;
+    }
+    Ok(())
+}
+
+/// Writes the record header and the body. The size in the header doesn't
+/// include the aux data of `PERF_RECORD_AUXTRACE` records.
+pub(crate) fn write_record<T: ByteOrder, W: Write>(
+    writer: &mut W,
+    record_type: RecordType,
+    misc: u16,
+    body: &[u8],
+) -> Result<(), std::io::Error> {
+    let mut record_body_len = body.len();
+    if record_type.0 == PERF_RECORD_AUXTRACE && body.len() >= 8 {
+        let aux_data_size = usize::try_from(T::read_u64(&body[..8]))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        record_body_len = body
+            .len()
+            .checked_sub(aux_data_size)
+            .ok_or(std::io::ErrorKind::InvalidInput)?;
+    }
+    let size = u16::try_from(RECORD_HEADER_SIZE + record_body_len)
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+    writer.write_u32::<T>(record_type.0)?;
+    writer.write_u16::<T>(misc)?;
+    writer.write_u16::<T>(size)?;
+    writer.write_all(body)
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroU64;
+
+    use linux_perf_event_reader::{
+        AttrFlags, BranchSampleFormat, Endianness, PerfClock, PerfEventAttr, PerfEventType,
+        ReadFormat, RecordType, SampleFormat, SamplingPolicy, SoftwareCounterType, WakeupPolicy,
+    };
+
+    use super::PerfPipeWriter;
+    use crate::feature_sections::HeaderString;
+    use crate::Feature;
+
+    #[test]
+    fn write_stream() {
+        let attr = PerfEventAttr {
+            type_: PerfEventType::Software(SoftwareCounterType::CpuClock),
+            sampling_policy: SamplingPolicy::Period(NonZeroU64::new(1000).unwrap()),
+            sample_format: SampleFormat::IDENTIFIER | SampleFormat::TIME,
+            read_format: ReadFormat::empty(),
+            flags: AttrFlags::SAMPLE_ID_ALL,
+            wakeup_policy: WakeupPolicy::EventCount(0),
+            branch_sample_format: BranchSampleFormat::empty(),
+            sample_regs_user: 0,
+            sample_stack_user: 0,
+            clock: PerfClock::Default,
+            sample_regs_intr: 0,
+            aux_watermark: 0,
+            sample_max_stack: 0,
+            aux_sample_size: 0,
+            sig_data: 0,
+        };
+        let mut writer = PerfPipeWriter::new(Vec::new(), Endianness::LittleEndian).unwrap();
+        writer.write_attr(&attr, &[17, 18]).unwrap();
+        // A perf_header_string: the padded length, then the nul-terminated
+        // string.
+        writer
+            .write_feature(Feature::HOSTNAME, b"\x08\0\0\0myhost\0\0")
+            .unwrap();
+        writer
+            .write_record(RecordType::SAMPLE, 2, &[0; 16])
+            .unwrap();
+        writer.write_finished_round().unwrap();
+        let stream = writer.into_inner();
+
+        assert_eq!(&stream[..8], b"PERFILE2");
+        assert_eq!(stream[8], 16);
+        // HEADER_ATTR: 8 byte header, 128 byte attr, 2 IDs.
+        let attr_record = &stream[16..16 + 152];
+        assert_eq!(attr_record[..4], 64u32.to_le_bytes());
+        assert_eq!(attr_record[6..8], 152u16.to_le_bytes());
+        let (parsed, size) =
+            PerfEventAttr::parse::<_, byteorder::LittleEndian>(&attr_record[8..]).unwrap();
+        assert_eq!(size, 128);
+        assert_eq!(parsed.sample_format, attr.sample_format);
+        assert!(matches!(
+            parsed.type_,
+            PerfEventType::Software(SoftwareCounterType::CpuClock)
+        ));
+        assert_eq!(
+            attr_record[136..],
+            [17, 0, 0, 0, 0, 0, 0, 0, 18, 0, 0, 0, 0, 0, 0, 0]
+        );
+        // HEADER_FEATURE: the feature ID and the data, padded to 8 bytes.
+        let feature_record = &stream[168..200];
+        assert_eq!(feature_record[..4], 80u32.to_le_bytes());
+        assert_eq!(feature_record[6..8], 32u16.to_le_bytes());
+        assert_eq!(
+            feature_record[8..16],
+            u64::from(Feature::HOSTNAME.0).to_le_bytes()
+        );
+        assert_eq!(
+            HeaderString::parse::<_, byteorder::LittleEndian>(&feature_record[16..]).unwrap(),
+            Some("myhost".to_string())
+        );
+        assert_eq!(feature_record[28..], [0; 4]);
+        // SAMPLE and FINISHED_ROUND.
+        assert_eq!(stream[200..208], [9, 0, 0, 0, 2, 0, 24, 0]);
+        assert_eq!(stream[224..], [68, 0, 0, 0, 0, 0, 8, 0]);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn write_compressed_stream() {
+        use byteorder::{ByteOrder, LittleEndian};
+
+        let mut writer = PerfPipeWriter::new(Vec::new(), Endianness::LittleEndian).unwrap();
+        writer.enable_compression(3).unwrap();
+        writer
+            .write_record(RecordType::SAMPLE, 2, &[1; 16])
+            .unwrap();
+        writer.write_record(RecordType::COMM, 0, &[2; 24]).unwrap();
+        writer.write_finished_round().unwrap();
+        writer.write_record(RecordType::SAMPLE, 2, &[3; 8]).unwrap();
+        writer.flush().unwrap();
+        let stream = writer.into_inner();
+
+        // HEADER_FEATURE with the compression settings.
+        let feature_record = &stream[16..56];
+        assert_eq!(feature_record[..4], 80u32.to_le_bytes());
+        assert_eq!(LittleEndian::read_u64(&feature_record[8..]), 27);
+        assert_eq!(LittleEndian::read_u32(&feature_record[20..]), 1);
+        assert_eq!(LittleEndian::read_u32(&feature_record[24..]), 3);
+
+        // COMPRESSED2, FINISHED_ROUND, COMPRESSED2.
+        let mut records = Vec::new();
+        let mut pos = 56;
+        while pos < stream.len() {
+            let record_type = LittleEndian::read_u32(&stream[pos..]);
+            let size = LittleEndian::read_u16(&stream[pos + 6..]) as usize;
+            records.push((record_type, &stream[pos + 8..pos + size]));
+            pos += size;
+        }
+        let types: Vec<u32> = records.iter().map(|(t, _)| *t).collect();
+        assert_eq!(types, vec![83, 68, 83]);
+        let decompress = |body: &[u8]| {
+            let data_size = LittleEndian::read_u64(body) as usize;
+            zstd::decode_all(&body[8..8 + data_size]).unwrap()
+        };
+        let first = decompress(records[0].1);
+        assert_eq!(first.len(), 24 + 32);
+        assert_eq!(first[..8], [9, 0, 0, 0, 2, 0, 24, 0]);
+        assert_eq!(first[24..32], [3, 0, 0, 0, 0, 0, 32, 0]);
+        assert_eq!(decompress(records[2].1)[8..], [3; 8]);
+    }
+}
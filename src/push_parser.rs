@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+use std::io::{Cursor, Read};
+
+use crate::{Error, OwnedPerfFileRecord, PerfFile, PerfFileReader, PerfRecordIter};
+
+/// A non-blocking adapter around [`PerfRecordIter`] for data which arrives
+/// in chunks: the caller pushes chunks of bytes as they arrive, e.g. from a
+/// network connection, and gets back the records which are ready.
+///
+/// This isn't a separate parser. The pushed bytes are buffered in memory and
+/// read by a [`PerfRecordIter`], which stops with
+/// [`PollRecord::NeedMoreData`](crate::PollRecord::NeedMoreData) when the
+/// buffer runs dry, the same way as with a non-blocking reader. Chunks can be
+/// split anywhere, even in the middle of a record header.
+/// Records are sorted the same way as by [`PerfRecordIter::next_record`], so
+/// records are returned once their round is complete, and the records of the
+/// last round are returned by [`PerfRecordPushParser::finish`]. As with
+/// [`PerfRecordIter::next_record`], the information from records such as
+/// `EVENT_UPDATE` and `SIMPLEPERF_EVENT_ID` is applied to the [`PerfFile`] which is
+/// passed in.
+///
+/// The size of the data section isn't known until the parser is finished,
+/// so the aux data size of `AUXTRACE` records can't be checked against it.
+/// Instead, records with more than 1 GiB of aux data are rejected, because
+/// the aux data has to be buffered until it has arrived completely.
+///
+/// Create it with [`PerfRecordPushParser::from_file_metadata`] or
+/// [`PerfRecordIter::into_push_parser`].
+pub struct PerfRecordPushParser {
+    iter: PerfRecordIter<PushBuffer>,
+    finished: bool,
+}
+
+impl PerfRecordPushParser {
+    pub(crate) fn new(mut iter: PerfRecordIter<PushBuffer>) -> Self {
+        // The size of the data section isn't known until finish() is called.
+        iter.set_data_len(u64::MAX, false);
+        Self {
+            iter,
+            finished: false,
+        }
+    }
+
+    /// Creates a push parser from the parts of a perf.data file that come
+    /// before and after the data section: the header, the attributes, and
+    /// the feature sections, at their offsets in the file. The bytes of the
+    /// data section itself don't need to be present.
+    pub fn from_file_metadata(file_metadata: &[u8]) -> Result<(PerfFile, Self), Error> {
+        let PerfFileReader {
+            perf_file,
+            record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(file_metadata))?;
+        Ok((perf_file, record_iter.into_push_parser()))
+    }
+
+    /// Adds the next chunk of the data section and returns the records which
+    /// can now be emitted in order.
+    pub fn push(
+        &mut self,
+        perf_file: &mut PerfFile,
+        data: &[u8],
+    ) -> Result<Vec<OwnedPerfFileRecord>, Error> {
+        self.iter.reader_mut().data.extend(data);
+        self.drain(perf_file)
+    }
+
+    /// Signals that the data section is complete and returns the remaining
+    /// records.
+    ///
+    /// Returns an error if the pushed data ends in the middle of a record.
+    pub fn finish(&mut self, perf_file: &mut PerfFile) -> Result<Vec<OwnedPerfFileRecord>, Error> {
+        if !self.finished {
+            self.finished = true;
+            let buffered_len = self.iter.partial_record_len() + self.iter.reader_mut().data.len();
+            let data_len = self.iter.read_offset() + buffered_len as u64;
+            self.iter.set_data_len(data_len, true);
+            self.iter.reader_mut().is_complete = true;
+        }
+        self.drain(perf_file)
+    }
+
+    /// The record iterator which is fed by this parser, e.g. for
+    /// [`PerfRecordIter::unknown_record_types`] or
+    /// [`PerfRecordIter::provide_buffer`].
+    pub fn record_iter(&mut self) -> &mut PerfRecordIter<impl Read> {
+        &mut self.iter
+    }
+
+    fn drain(&mut self, perf_file: &mut PerfFile) -> Result<Vec<OwnedPerfFileRecord>, Error> {
+        let mut records = Vec::new();
+        while let Some(record) = self.iter.poll_next_owned_record(perf_file)? {
+            records.push(record);
+        }
+        Ok(records)
+    }
+}
+
+/// The bytes which have been pushed but not read yet. Reading from an empty
+/// buffer returns [`std::io::ErrorKind::WouldBlock`].
+#[derive(Debug, Default)]
+pub(crate) struct PushBuffer {
+    data: VecDeque<u8>,
+    /// Set by [`PerfRecordPushParser::finish`]. Reading from an empty
+    /// complete buffer returns EOF.
+    is_complete: bool,
+}
+
+impl Read for PushBuffer {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.data.is_empty() && !buf.is_empty() && !self.is_complete {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        self.data.read(buf)
+    }
+}
@@ -0,0 +1,220 @@
+use byteorder::ByteOrder;
+use linux_perf_event_reader::{RawData, ReadFormat, SampleFormat};
+
+/// The `read_format` bit which adds the number of lost samples to each
+/// value. [`ReadFormat`] doesn't have a flag for it, so it is only present in
+/// the `read_format` from [`PerfFile::read_format`](crate::PerfFile::read_format).
+pub const PERF_FORMAT_LOST: u64 = 1 << 4;
+
+/// The value of one counter in [`ReadValues`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadCounterValue {
+    pub value: u64,
+    /// The event ID of the counter, if the attr has `PERF_FORMAT_ID`. Use it
+    /// to look up the attr in the `event_ids` of
+    /// [`AttributeDescription`](crate::AttributeDescription).
+    pub id: Option<u64>,
+    /// The number of samples of the counter which were lost, if the attr
+    /// has [`PERF_FORMAT_LOST`].
+    pub lost: Option<u64>,
+}
+
+/// A `PERF_RECORD_READ` record. These are emitted for inherited counters with
+/// `inherit_stat` (`perf record -s`) when a child task exits, with the
+/// values which the task contributed to the counter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadRecord {
+    pub pid: i32,
+    pub tid: i32,
+    pub values: ReadValues,
+}
+
+impl ReadRecord {
+    /// Parses the body of a `PERF_RECORD_READ` record, with the
+    /// `read_format` of the record's attr from
+    /// [`PerfFile::read_format`](crate::PerfFile::read_format).
+    pub fn parse<T: ByteOrder>(
+        mut data: RawData,
+        read_format: ReadFormat,
+    ) -> Result<Self, std::io::Error> {
+        // struct {
+        //     struct perf_event_header header;
+        //     u32 pid, tid;
+        //     struct read_format values;
+        //     struct sample_id sample_id;
+        // };
+        let pid = data.read_i32::<T>()?;
+        let tid = data.read_i32::<T>()?;
+        let values = ReadValues::parse::<T>(&mut data, read_format)?;
+        Ok(Self { pid, tid, values })
+    }
+}
+
+/// Counter values in the layout given by the `read_format` of an attr, as
+/// found in `PERF_RECORD_READ` records and in samples with
+/// `PERF_SAMPLE_READ`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadValues {
+    /// The time in nanoseconds during which the counter was enabled, if the
+    /// attr has `PERF_FORMAT_TOTAL_TIME_ENABLED`.
+    pub time_enabled: Option<u64>,
+    /// The time in nanoseconds during which the counter was actually
+    /// counting, if the attr has `PERF_FORMAT_TOTAL_TIME_RUNNING`. This is
+    /// less than `time_enabled` if the counter was multiplexed.
+    pub time_running: Option<u64>,
+    /// One value, or one value per group member if the attr has
+    /// `PERF_FORMAT_GROUP`.
+    pub counters: Vec<ReadCounterValue>,
+}
+
+impl ReadValues {
+    /// Parses the values from the start of `data` and advances `data` past
+    /// them. `read_format` must keep the [`PERF_FORMAT_LOST`] bit, see
+    /// [`PerfFile::read_format`](crate::PerfFile::read_format).
+    pub fn parse<T: ByteOrder>(
+        data: &mut RawData,
+        read_format: ReadFormat,
+    ) -> Result<Self, std::io::Error> {
+        // struct read_format {
+        //     { u64 value;
+        //       { u64 time_enabled; } && PERF_FORMAT_TOTAL_TIME_ENABLED
+        //       { u64 time_running; } && PERF_FORMAT_TOTAL_TIME_RUNNING
+        //       { u64 id;           } && PERF_FORMAT_ID
+        //       { u64 lost;         } && PERF_FORMAT_LOST
+        //     } && !PERF_FORMAT_GROUP
+        //     { u64 nr;
+        //       { u64 time_enabled; } && PERF_FORMAT_TOTAL_TIME_ENABLED
+        //       { u64 time_running; } && PERF_FORMAT_TOTAL_TIME_RUNNING
+        //       { u64 value;
+        //         { u64 id;         } && PERF_FORMAT_ID
+        //         { u64 lost;       } && PERF_FORMAT_LOST
+        //       } cntr[nr];
+        //     } && PERF_FORMAT_GROUP
+        // };
+        let group = read_format.contains(ReadFormat::GROUP);
+        let first = data.read_u64::<T>()?;
+        let time_enabled = if read_format.contains(ReadFormat::TOTAL_TIME_ENABLED) {
+            Some(data.read_u64::<T>()?)
+        } else {
+            None
+        };
+        let time_running = if read_format.contains(ReadFormat::TOTAL_TIME_RUNNING) {
+            Some(data.read_u64::<T>()?)
+        } else {
+            None
+        };
+        let has_id = read_format.contains(ReadFormat::ID);
+        let has_lost = read_format.bits() & PERF_FORMAT_LOST != 0;
+        let counters = if group {
+            let mut counters = Vec::new();
+            for _ in 0..first {
+                let value = data.read_u64::<T>()?;
+                let id = read_optional_u64::<T>(data, has_id)?;
+                let lost = read_optional_u64::<T>(data, has_lost)?;
+                counters.push(ReadCounterValue { value, id, lost });
+            }
+            counters
+        } else {
+            let id = read_optional_u64::<T>(data, has_id)?;
+            let lost = read_optional_u64::<T>(data, has_lost)?;
+            vec![ReadCounterValue {
+                value: first,
+                id,
+                lost,
+            }]
+        };
+        Ok(Self {
+            time_enabled,
+            time_running,
+            counters,
+        })
+    }
+
+    /// Parses the read values from the body of a `PERF_RECORD_SAMPLE`
+    /// record. Returns `None` if the sample format doesn't include
+    /// `PERF_SAMPLE_READ`.
+    ///
+    /// Use the `sample_format` of the record's parse info, and the
+    /// `read_format` from [`PerfFile::read_format`](crate::PerfFile::read_format).
+    pub fn parse_from_sample<T: ByteOrder>(
+        mut data: RawData,
+        sample_format: SampleFormat,
+        read_format: ReadFormat,
+    ) -> Result<Option<Self>, std::io::Error> {
+        if !sample_format.contains(SampleFormat::READ) {
+            return Ok(None);
+        }
+        // All fields before the read values are 8 bytes large.
+        let fields_before_read = [
+            SampleFormat::IDENTIFIER,
+            SampleFormat::IP,
+            SampleFormat::TID,
+            SampleFormat::TIME,
+            SampleFormat::ADDR,
+            SampleFormat::ID,
+            SampleFormat::STREAM_ID,
+            SampleFormat::CPU,
+            SampleFormat::PERIOD,
+        ];
+        let skip_len = fields_before_read
+            .iter()
+            .filter(|field| sample_format.contains(**field))
+            .count()
+            * 8;
+        data.skip(skip_len)?;
+        Self::parse::<T>(&mut data, read_format).map(Some)
+    }
+}
+
+fn read_optional_u64<T: ByteOrder>(
+    data: &mut RawData,
+    present: bool,
+) -> Result<Option<u64>, std::io::Error> {
+    if present {
+        Ok(Some(data.read_u64::<T>()?))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use linux_perf_event_reader::{RawData, ReadFormat};
+
+    use super::{ReadCounterValue, ReadValues, PERF_FORMAT_LOST};
+
+    #[test]
+    fn lost_values() {
+        let read_format = ReadFormat::from_bits_retain(
+            ReadFormat::GROUP.bits() | ReadFormat::ID.bits() | PERF_FORMAT_LOST,
+        );
+        let mut data = Vec::new();
+        for value in [2, 100, 1, 3, 200, 2, 0, 7] {
+            data.write_u64::<LittleEndian>(value).unwrap();
+        }
+        let mut raw = RawData::from(&data[..]);
+        let values = ReadValues::parse::<LittleEndian>(&mut raw, read_format).unwrap();
+        assert_eq!(
+            values.counters,
+            vec![
+                ReadCounterValue {
+                    value: 100,
+                    id: Some(1),
+                    lost: Some(3),
+                },
+                ReadCounterValue {
+                    value: 200,
+                    id: Some(2),
+                    lost: Some(0),
+                },
+            ]
+        );
+        assert_eq!(raw.len(), 8);
+
+        let read_format = ReadFormat::from_bits_retain(PERF_FORMAT_LOST);
+        let mut raw = RawData::from(&data[8..]);
+        let values = ReadValues::parse::<LittleEndian>(&mut raw, read_format).unwrap();
+        assert_eq!(values.counters[0].lost, Some(1));
+    }
+}
@@ -1,9 +1,19 @@
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
-use linux_perf_event_reader::RawEventRecord;
 use linux_perf_event_reader::{Endianness, RawData, RecordType};
+use linux_perf_event_reader::{RawEventRecord, RecordParseInfo};
 
+use crate::auxtrace::AuxtraceRecord;
+use crate::auxtrace_info::AuxtraceInfo;
 use crate::constants::*;
+use crate::cpu_map::CpuMap;
+use crate::custom_record::{CustomRecordType, CustomUserRecord};
+use crate::event_update::EventUpdate;
+use crate::id_index::IdIndex;
+use crate::record_misc::RecordMisc;
+use crate::simpleperf::{SimpleperfDsoRecord, SimpleperfEventIdRecord, SimpleperfSymbolRecord};
+use crate::stat::{Stat, StatConfig};
 use crate::thread_map::ThreadMap;
+use crate::time_conv::TimeConv;
 
 /// A record from a perf.data file's data stream.
 ///
@@ -16,16 +26,135 @@ pub enum PerfFileRecord<'a> {
         attr_index: usize,
         /// The record.
         record: RawEventRecord<'a>,
+        /// See [`PerfFileRecord::offset`].
+        offset: u64,
     },
     /// Synthesized by a user space tool, for example by `perf` or by `simpleperf`.
     UserRecord(RawUserRecord<'a>),
 }
 
+impl PerfFileRecord<'_> {
+    /// The offset of the record, relative to the start of the data section.
+    /// Add [`PerfHeader::data_section`](crate::PerfHeader::data_section)'s
+    /// offset to get the position in the file.
+    ///
+    /// Records which were decompressed from a `COMPRESSED` record don't have
+    /// a position of their own in the file; they report the offset of the
+    /// compressed record.
+    pub fn offset(&self) -> u64 {
+        match self {
+            PerfFileRecord::EventRecord { offset, .. } => *offset,
+            PerfFileRecord::UserRecord(record) => record.offset,
+        }
+    }
+
+    /// The event ID of an event record, if the event's `sample_type` has
+    /// `ID` or `IDENTIFIER` and, for non-sample records, `sample_id_all` is
+    /// set. `None` for user records.
+    ///
+    /// Look up the event's attribute with
+    /// [`PerfFile::attr_index_for_event_id`](crate::PerfFile::attr_index_for_event_id).
+    pub fn event_id(&self) -> Option<u64> {
+        match self {
+            PerfFileRecord::EventRecord { record, .. } => record.id(),
+            PerfFileRecord::UserRecord(_) => None,
+        }
+    }
+
+    /// The decoded `misc` field of the record header.
+    pub fn misc(&self) -> RecordMisc {
+        match self {
+            PerfFileRecord::EventRecord { record, .. } => {
+                RecordMisc::decode(record.record_type, record.misc)
+            }
+            PerfFileRecord::UserRecord(record) => record.decoded_misc(),
+        }
+    }
+
+    /// The record type, from the record header.
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            PerfFileRecord::EventRecord { record, .. } => record.record_type,
+            PerfFileRecord::UserRecord(record) => record.record_type.into(),
+        }
+    }
+}
+
+/// A record from a perf.data file whose body is owned by the caller, returned
+/// by [`PerfRecordIter::next_owned_record`](crate::PerfRecordIter::next_owned_record).
+///
+/// Unlike [`PerfFileRecord`], this does not borrow from the iterator, so it
+/// can be kept around while more records are read, for example to process
+/// records in batches. Once the record is no longer needed, its body buffer
+/// can be handed back to the iterator with
+/// [`PerfRecordIter::provide_buffer`](crate::PerfRecordIter::provide_buffer).
+#[derive(Debug, Clone)]
+pub struct OwnedPerfFileRecord {
+    pub record_type: RecordType,
+    pub misc: u16,
+    /// The record body, i.e. the bytes following the record header.
+    pub body: Vec<u8>,
+    /// For event records, the index of the record's event attr. `None` for
+    /// user records.
+    pub attr_index: Option<usize>,
+    /// See [`PerfFileRecord::offset`].
+    pub offset: u64,
+    pub(crate) parse_info: Option<RecordParseInfo>,
+    pub(crate) endian: Endianness,
+}
+
+impl OwnedPerfFileRecord {
+    /// Returns a [`PerfFileRecord`] which borrows the body of this record.
+    pub fn as_record(&self) -> PerfFileRecord<'_> {
+        let data = RawData::from(&self.body[..]);
+        match (self.attr_index, self.parse_info) {
+            (Some(attr_index), Some(parse_info)) => PerfFileRecord::EventRecord {
+                attr_index,
+                offset: self.offset,
+                record: RawEventRecord {
+                    record_type: self.record_type,
+                    misc: self.misc,
+                    data,
+                    parse_info,
+                },
+            },
+            _ => PerfFileRecord::UserRecord(RawUserRecord {
+                record_type: UserRecordType(self.record_type),
+                endian: self.endian,
+                misc: self.misc,
+                data,
+                offset: self.offset,
+                custom_type: None,
+            }),
+        }
+    }
+
+    /// Consumes the record and returns its body buffer.
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+}
+
 /// A record emitted by a user space tool, for example by `perf` or by `simpleperf`.
 #[derive(Debug, Clone)]
 #[non_exhaustive]
 pub enum UserRecord<'a> {
+    /// A `PERF_RECORD_AUXTRACE` record, with the aux data that follows it.
+    Auxtrace(AuxtraceRecord<'a>),
+    AuxtraceInfo(AuxtraceInfo),
     ThreadMap(ThreadMap<'a>),
+    CpuMap(CpuMap),
+    EventUpdate(EventUpdate),
+    IdIndex(IdIndex),
+    StatConfig(StatConfig),
+    Stat(Stat),
+    TimeConv(TimeConv),
+    SimpleperfDso(SimpleperfDsoRecord<'a>),
+    SimpleperfSymbol(SimpleperfSymbolRecord<'a>),
+    SimpleperfEventId(SimpleperfEventIdRecord),
+    /// A record of a type declared in
+    /// [`CustomRecordTypes`](crate::CustomRecordTypes) with a parser.
+    Custom(CustomUserRecord<'a>),
     Raw(RawUserRecord<'a>),
 }
 
@@ -52,6 +181,8 @@ impl UserRecordType {
     pub const PERF_TIME_CONV: Self = Self(RecordType(PERF_RECORD_TIME_CONV));
     pub const PERF_HEADER_FEATURE: Self = Self(RecordType(PERF_RECORD_HEADER_FEATURE));
     pub const PERF_COMPRESSED: Self = Self(RecordType(PERF_RECORD_COMPRESSED));
+    pub const PERF_FINISHED_INIT: Self = Self(RecordType(PERF_RECORD_FINISHED_INIT));
+    pub const PERF_COMPRESSED2: Self = Self(RecordType(PERF_RECORD_COMPRESSED2));
 
     pub const SIMPLEPERF_KERNEL_SYMBOL: Self = Self(RecordType(SIMPLE_PERF_RECORD_KERNEL_SYMBOL));
     pub const SIMPLEPERF_DSO: Self = Self(RecordType(SIMPLE_PERF_RECORD_DSO));
@@ -75,6 +206,49 @@ impl UserRecordType {
     pub fn record_type(&self) -> RecordType {
         self.0
     }
+
+    /// The name of the record type, or `None` if this crate doesn't know
+    /// about this record type.
+    pub fn name(&self) -> Option<&'static str> {
+        let name = match *self {
+            Self::PERF_HEADER_ATTR => "PERF_HEADER_ATTR",
+            Self::PERF_HEADER_EVENT_TYPE => "PERF_HEADER_EVENT_TYPE",
+            Self::PERF_HEADER_TRACING_DATA => "PERF_HEADER_TRACING_DATA",
+            Self::PERF_HEADER_BUILD_ID => "PERF_HEADER_BUILD_ID",
+            Self::PERF_FINISHED_ROUND => "PERF_FINISHED_ROUND",
+            Self::PERF_ID_INDEX => "PERF_ID_INDEX",
+            Self::PERF_AUXTRACE_INFO => "PERF_AUXTRACE_INFO",
+            Self::PERF_AUXTRACE => "PERF_AUXTRACE",
+            Self::PERF_AUXTRACE_ERROR => "PERF_AUXTRACE_ERROR",
+            Self::PERF_THREAD_MAP => "PERF_THREAD_MAP",
+            Self::PERF_CPU_MAP => "PERF_CPU_MAP",
+            Self::PERF_STAT_CONFIG => "PERF_STAT_CONFIG",
+            Self::PERF_STAT => "PERF_STAT",
+            Self::PERF_STAT_ROUND => "PERF_STAT_ROUND",
+            Self::PERF_EVENT_UPDATE => "PERF_EVENT_UPDATE",
+            Self::PERF_TIME_CONV => "PERF_TIME_CONV",
+            Self::PERF_HEADER_FEATURE => "PERF_HEADER_FEATURE",
+            Self::PERF_COMPRESSED => "PERF_COMPRESSED",
+            Self::PERF_FINISHED_INIT => "PERF_FINISHED_INIT",
+            Self::PERF_COMPRESSED2 => "PERF_COMPRESSED2",
+            Self::SIMPLEPERF_KERNEL_SYMBOL => "SIMPLEPERF_KERNEL_SYMBOL",
+            Self::SIMPLEPERF_DSO => "SIMPLEPERF_DSO",
+            Self::SIMPLEPERF_SYMBOL => "SIMPLEPERF_SYMBOL",
+            Self::SIMPLEPERF_SPLIT => "SIMPLEPERF_SPLIT",
+            Self::SIMPLEPERF_SPLIT_END => "SIMPLEPERF_SPLIT_END",
+            Self::SIMPLEPERF_EVENT_ID => "SIMPLEPERF_EVENT_ID",
+            Self::SIMPLEPERF_CALLCHAIN => "SIMPLEPERF_CALLCHAIN",
+            Self::SIMPLEPERF_UNWINDING_RESULT => "SIMPLEPERF_UNWINDING_RESULT",
+            Self::SIMPLEPERF_TRACING_DATA => "SIMPLEPERF_TRACING_DATA",
+            _ => return None,
+        };
+        Some(name)
+    }
+
+    /// Whether this crate knows about this record type.
+    pub fn is_known(&self) -> bool {
+        self.name().is_some()
+    }
 }
 
 impl From<UserRecordType> for RecordType {
@@ -85,35 +259,9 @@ impl From<UserRecordType> for RecordType {
 
 impl std::fmt::Debug for UserRecordType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            Self::PERF_HEADER_ATTR => "PERF_HEADER_ATTR".fmt(f),
-            Self::PERF_HEADER_EVENT_TYPE => "PERF_HEADER_EVENT_TYPE".fmt(f),
-            Self::PERF_HEADER_TRACING_DATA => "PERF_HEADER_TRACING_DATA".fmt(f),
-            Self::PERF_HEADER_BUILD_ID => "PERF_HEADER_BUILD_ID".fmt(f),
-            Self::PERF_FINISHED_ROUND => "PERF_FINISHED_ROUND".fmt(f),
-            Self::PERF_ID_INDEX => "PERF_ID_INDEX".fmt(f),
-            Self::PERF_AUXTRACE_INFO => "PERF_AUXTRACE_INFO".fmt(f),
-            Self::PERF_AUXTRACE => "PERF_AUXTRACE".fmt(f),
-            Self::PERF_AUXTRACE_ERROR => "PERF_AUXTRACE_ERROR".fmt(f),
-            Self::PERF_THREAD_MAP => "PERF_THREAD_MAP".fmt(f),
-            Self::PERF_CPU_MAP => "PERF_CPU_MAP".fmt(f),
-            Self::PERF_STAT_CONFIG => "PERF_STAT_CONFIG".fmt(f),
-            Self::PERF_STAT => "PERF_STAT".fmt(f),
-            Self::PERF_STAT_ROUND => "PERF_STAT_ROUND".fmt(f),
-            Self::PERF_EVENT_UPDATE => "PERF_EVENT_UPDATE".fmt(f),
-            Self::PERF_TIME_CONV => "PERF_TIME_CONV".fmt(f),
-            Self::PERF_HEADER_FEATURE => "PERF_HEADER_FEATURE".fmt(f),
-            Self::PERF_COMPRESSED => "PERF_COMPRESSED".fmt(f),
-            Self::SIMPLEPERF_KERNEL_SYMBOL => "SIMPLEPERF_KERNEL_SYMBOL".fmt(f),
-            Self::SIMPLEPERF_DSO => "SIMPLEPERF_DSO".fmt(f),
-            Self::SIMPLEPERF_SYMBOL => "SIMPLEPERF_SYMBOL".fmt(f),
-            Self::SIMPLEPERF_SPLIT => "SIMPLEPERF_SPLIT".fmt(f),
-            Self::SIMPLEPERF_SPLIT_END => "SIMPLEPERF_SPLIT_END".fmt(f),
-            Self::SIMPLEPERF_EVENT_ID => "SIMPLEPERF_EVENT_ID".fmt(f),
-            Self::SIMPLEPERF_CALLCHAIN => "SIMPLEPERF_CALLCHAIN".fmt(f),
-            Self::SIMPLEPERF_UNWINDING_RESULT => "SIMPLEPERF_UNWINDING_RESULT".fmt(f),
-            Self::SIMPLEPERF_TRACING_DATA => "SIMPLEPERF_TRACING_DATA".fmt(f),
-            other => f.write_fmt(format_args!("Unknown UserRecordType {}", other.0 .0)),
+        match self.name() {
+            Some(name) => name.fmt(f),
+            None => f.write_fmt(format_args!("Unknown UserRecordType {}", self.0 .0)),
         }
     }
 }
@@ -127,9 +275,20 @@ pub struct RawUserRecord<'a> {
     pub endian: Endianness,
     pub misc: u16,
     pub data: RawData<'a>,
+    /// See [`PerfFileRecord::offset`].
+    pub offset: u64,
+    /// The declaration of this record's type, if it is a custom type from
+    /// [`CustomRecordTypes`](crate::CustomRecordTypes).
+    pub custom_type: Option<&'a CustomRecordType>,
 }
 
 impl<'a> RawUserRecord<'a> {
+    /// The decoded `misc` field of the record header. Most user records
+    /// don't set any `misc` bits other than the CPU mode.
+    pub fn decoded_misc(&self) -> RecordMisc {
+        RecordMisc::decode(self.record_type.0, self.misc)
+    }
+
     pub fn parse(&self) -> Result<UserRecord<'a>, std::io::Error> {
         match self.endian {
             Endianness::LittleEndian => self.parse_impl::<LittleEndian>(),
@@ -146,31 +305,53 @@ impl<'a> RawUserRecord<'a> {
             // UserRecordType::PERF_HEADER_TRACING_DATA => {},
             // UserRecordType::PERF_HEADER_BUILD_ID => {},
             // UserRecordType::PERF_FINISHED_ROUND => {},
-            // UserRecordType::PERF_ID_INDEX => {},
-            // UserRecordType::PERF_AUXTRACE_INFO => {},
-            // UserRecordType::PERF_AUXTRACE => {},
+            UserRecordType::PERF_ID_INDEX => UserRecord::IdIndex(IdIndex::parse::<T>(self.data)?),
+            UserRecordType::PERF_AUXTRACE_INFO => {
+                UserRecord::AuxtraceInfo(AuxtraceInfo::parse::<T>(self.data)?)
+            }
+            UserRecordType::PERF_AUXTRACE => {
+                UserRecord::Auxtrace(AuxtraceRecord::parse::<T>(self.data)?)
+            }
             // UserRecordType::PERF_AUXTRACE_ERROR => {},
             UserRecordType::PERF_THREAD_MAP => {
                 UserRecord::ThreadMap(ThreadMap::parse::<T>(self.data)?)
             }
-            // UserRecordType::PERF_CPU_MAP => {},
-            // UserRecordType::PERF_STAT_CONFIG => {},
-            // UserRecordType::PERF_STAT => {},
+            UserRecordType::PERF_CPU_MAP => UserRecord::CpuMap(CpuMap::parse::<T>(self.data)?),
+            UserRecordType::PERF_STAT_CONFIG => {
+                UserRecord::StatConfig(StatConfig::parse::<T>(self.data)?)
+            }
+            UserRecordType::PERF_STAT => UserRecord::Stat(Stat::parse::<T>(self.data)?),
             // UserRecordType::PERF_STAT_ROUND => {},
-            // UserRecordType::PERF_EVENT_UPDATE => {},
-            // UserRecordType::PERF_TIME_CONV => {},
+            UserRecordType::PERF_EVENT_UPDATE => {
+                UserRecord::EventUpdate(EventUpdate::parse::<T>(self.data)?)
+            }
+            UserRecordType::PERF_TIME_CONV => {
+                UserRecord::TimeConv(TimeConv::parse::<T>(self.data)?)
+            }
             // UserRecordType::PERF_HEADER_FEATURE => {},
             // UserRecordType::PERF_COMPRESSED => {},
             // UserRecordType::SIMPLEPERF_KERNEL_SYMBOL => {},
-            // UserRecordType::SIMPLEPERF_DSO => {},
-            // UserRecordType::SIMPLEPERF_SYMBOL => {},
+            UserRecordType::SIMPLEPERF_DSO => {
+                UserRecord::SimpleperfDso(SimpleperfDsoRecord::parse::<T>(self.data)?)
+            }
+            UserRecordType::SIMPLEPERF_SYMBOL => {
+                UserRecord::SimpleperfSymbol(SimpleperfSymbolRecord::parse::<T>(self.data)?)
+            }
             // UserRecordType::SIMPLEPERF_SPLIT => {},
             // UserRecordType::SIMPLEPERF_SPLIT_END => {},
-            // UserRecordType::SIMPLEPERF_EVENT_ID => {},
+            UserRecordType::SIMPLEPERF_EVENT_ID => {
+                UserRecord::SimpleperfEventId(SimpleperfEventIdRecord::parse::<T>(self.data)?)
+            }
             // UserRecordType::SIMPLEPERF_CALLCHAIN => {},
             // UserRecordType::SIMPLEPERF_UNWINDING_RESULT => {},
             // UserRecordType::SIMPLEPERF_TRACING_DATA => {},
-            _ => UserRecord::Raw(self.clone()),
+            _ => match self.custom_type {
+                Some(CustomRecordType {
+                    name,
+                    parser: Some(parser),
+                }) => UserRecord::Custom(CustomUserRecord::new(record_type, name, parser(self)?)),
+                _ => UserRecord::Raw(self.clone()),
+            },
         };
         Ok(record)
     }
@@ -0,0 +1,48 @@
+#[cfg(feature = "zstd")]
+use std::ops::Range;
+#[cfg(feature = "zstd")]
+use std::sync::Arc;
+
+/// The body of a record which has been read but not yet handed out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordBody {
+    /// The body was read into its own buffer.
+    Owned(Vec<u8>),
+    /// The body is a range in a shared chunk of decompressed data.
+    #[cfg(feature = "zstd")]
+    Shared(Arc<Vec<u8>>, Range<usize>),
+}
+
+impl Default for RecordBody {
+    fn default() -> Self {
+        Self::Owned(Vec::new())
+    }
+}
+
+impl RecordBody {
+    pub fn as_slice(&self) -> &[u8] {
+        match self {
+            Self::Owned(buffer) => buffer,
+            #[cfg(feature = "zstd")]
+            Self::Shared(chunk, range) => &chunk[range.clone()],
+        }
+    }
+
+    /// Returns the body as an owned buffer, copying it if needed.
+    pub fn into_vec(self) -> Vec<u8> {
+        match self {
+            Self::Owned(buffer) => buffer,
+            #[cfg(feature = "zstd")]
+            Self::Shared(chunk, range) => chunk[range].to_vec(),
+        }
+    }
+
+    /// Returns the buffer if it can be reused for reading other records.
+    pub fn into_recyclable_buffer(self) -> Option<Vec<u8>> {
+        match self {
+            Self::Owned(buffer) => Some(buffer),
+            #[cfg(feature = "zstd")]
+            Self::Shared(..) => None,
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use linux_perf_event_reader::constants::*;
+use linux_perf_event_reader::{CpuMode, RecordType};
+
+/// The decoded `misc` field of a record header.
+///
+/// Most of the `misc` bits mean different things for different record types,
+/// so they are decoded based on the record type; flags which don't apply to
+/// the record type are always false.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordMisc {
+    /// The CPU mode, e.g. user or kernel, in which the record was generated.
+    pub cpu_mode: CpuMode,
+    /// `MMAP` / `MMAP2`: the mapping is not executable (`PERF_RECORD_MISC_MMAP_DATA`).
+    pub mmap_data: bool,
+    /// `MMAP2`: the record contains a build ID instead of the device and
+    /// inode numbers (`PERF_RECORD_MISC_MMAP_BUILD_ID`).
+    pub mmap_build_id: bool,
+    /// `COMM`: the name change was caused by an exec (`PERF_RECORD_MISC_COMM_EXEC`).
+    pub comm_exec: bool,
+    /// `FORK`: the fork was part of an exec (`PERF_RECORD_MISC_FORK_EXEC`).
+    pub fork_exec: bool,
+    /// `SWITCH` / `SWITCH_CPU_WIDE`: this is a switch out of the task
+    /// rather than into it (`PERF_RECORD_MISC_SWITCH_OUT`).
+    pub switch_out: bool,
+    /// `SWITCH` / `SWITCH_CPU_WIDE`: the task was preempted while runnable
+    /// (`PERF_RECORD_MISC_SWITCH_OUT_PREEMPT`).
+    pub switch_out_preempt: bool,
+    /// `SAMPLE`: the sampled IP is the exact instruction which caused the
+    /// event (`PERF_RECORD_MISC_EXACT_IP`).
+    pub exact_ip: bool,
+    /// The record was synthesized by `perf` while the parsing of
+    /// `/proc/<pid>/maps` timed out, so the mappings may be incomplete
+    /// (`PERF_RECORD_MISC_PROC_MAP_PARSE_TIMEOUT`).
+    pub proc_map_parse_timeout: bool,
+    /// The raw `misc` value.
+    pub raw: u16,
+}
+
+impl RecordMisc {
+    /// Decodes `misc` for a record of type `record_type`.
+    pub fn decode(record_type: RecordType, misc: u16) -> Self {
+        let is_switch = matches!(
+            record_type,
+            RecordType::SWITCH | RecordType::SWITCH_CPU_WIDE
+        );
+        Self {
+            cpu_mode: CpuMode::from_misc(misc),
+            mmap_data: matches!(record_type, RecordType::MMAP | RecordType::MMAP2)
+                && misc & PERF_RECORD_MISC_MMAP_DATA != 0,
+            mmap_build_id: record_type == RecordType::MMAP2
+                && misc & PERF_RECORD_MISC_MMAP_BUILD_ID != 0,
+            comm_exec: record_type == RecordType::COMM && misc & PERF_RECORD_MISC_COMM_EXEC != 0,
+            fork_exec: record_type == RecordType::FORK && misc & PERF_RECORD_MISC_FORK_EXEC != 0,
+            switch_out: is_switch && misc & PERF_RECORD_MISC_SWITCH_OUT != 0,
+            switch_out_preempt: is_switch && misc & PERF_RECORD_MISC_SWITCH_OUT_PREEMPT != 0,
+            exact_ip: record_type == RecordType::SAMPLE && misc & PERF_RECORD_MISC_EXACT_IP != 0,
+            proc_map_parse_timeout: misc & PERF_RECORD_MISC_PROC_MAP_PARSE_TIMEOUT != 0,
+            raw: misc,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use linux_perf_event_reader::constants::*;
+    use linux_perf_event_reader::{CpuMode, RecordType};
+
+    use super::RecordMisc;
+
+    #[test]
+    fn decode_per_record_type() {
+        let misc = PERF_RECORD_MISC_USER | PERF_RECORD_MISC_SWITCH_OUT | PERF_RECORD_MISC_EXACT_IP;
+        let sample = RecordMisc::decode(RecordType::SAMPLE, misc);
+        assert_eq!(sample.cpu_mode, CpuMode::User);
+        assert!(sample.exact_ip);
+        assert!(!sample.switch_out && !sample.mmap_data && !sample.comm_exec);
+
+        let switch = RecordMisc::decode(RecordType::SWITCH_CPU_WIDE, misc);
+        assert!(switch.switch_out && switch.switch_out_preempt);
+        assert!(!switch.exact_ip);
+
+        let mmap2 = RecordMisc::decode(
+            RecordType::MMAP2,
+            PERF_RECORD_MISC_KERNEL | PERF_RECORD_MISC_MMAP_DATA,
+        );
+        assert_eq!(mmap2.cpu_mode, CpuMode::Kernel);
+        assert!(mmap2.mmap_data && !mmap2.mmap_build_id);
+
+        let mmap2 = RecordMisc::decode(
+            RecordType::MMAP2,
+            PERF_RECORD_MISC_USER | PERF_RECORD_MISC_MMAP_BUILD_ID,
+        );
+        assert!(mmap2.mmap_build_id && !mmap2.mmap_data);
+    }
+}
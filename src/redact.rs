@@ -0,0 +1,465 @@
+use std::io::{Read, Seek, Write};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use linux_perf_event_reader::constants::PERF_RECORD_MISC_MMAP_BUILD_ID;
+use linux_perf_event_reader::{Endianness, PerfEventHeader, RecordType};
+
+use crate::constants::PERF_RECORD_HEADER_BUILD_ID;
+use crate::transcode::transcode_impl;
+use crate::{BuildIdEvent, Error, Feature, PerfFile, PerfFileReader, PerfFileRecord};
+use crate::{FeatureSectionsBuilder, RawUserRecord, TranscodeAction, UserRecordType};
+
+/// The offset of the file name in the body of an `MMAP` record.
+const MMAP_FILENAME_OFFSET: usize = 32;
+/// The offset of the file name in the body of an `MMAP2` record.
+const MMAP2_FILENAME_OFFSET: usize = 64;
+/// The offset of the build ID size, reserved bytes and build ID in the body
+/// of an `MMAP2` record with `PERF_RECORD_MISC_MMAP_BUILD_ID`. These 24 bytes
+/// hold the device and inode numbers otherwise.
+const MMAP2_BUILD_ID_RANGE: std::ops::Range<usize> = 40..64;
+/// The offset of the command name in the body of a `COMM` record.
+const COMM_NAME_OFFSET: usize = 8;
+
+/// Rewrites a perf.data file so that it can be shared without revealing
+/// details about the machine it was recorded on, such as the host name, the
+/// command line, user names in file paths, or build IDs.
+///
+/// Configure the redaction with the setters, and then call
+/// [`Redactor::redact`]. The output is a valid perf.data file which is
+/// written like [`transcode`](crate::transcode) writes it.
+///
+/// Only the data which the setters describe is changed. The raw payloads of
+/// tracepoint samples are copied unchanged, even though they can contain
+/// file names or command names, e.g. in `sched:sched_process_exec` events.
+///
+/// ```
+/// # fn wrapper(reader: linux_perf_data::PerfFileReader<std::fs::File>) -> Result<(), linux_perf_data::Error> {
+/// use linux_perf_data::{Feature, Redactor};
+///
+/// let output = Redactor::new()
+///     .set_hostname("localhost")
+///     .replace_path_prefix("/home/alice/", "/home/user/")
+///     .remove_feature(Feature::CPUID)
+///     .redact(reader, std::io::Cursor::new(Vec::new()))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    hostname: Option<String>,
+    cmdline: Option<Vec<String>>,
+    removed_features: Vec<Feature>,
+    path_prefixes: Vec<(Vec<u8>, Vec<u8>)>,
+    comm_names: Vec<(Vec<u8>, Vec<u8>)>,
+    strip_build_ids: bool,
+}
+
+impl Redactor {
+    /// Creates a redactor which doesn't change anything yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the contents of the `HOSTNAME` feature section, if the input
+    /// has one.
+    pub fn set_hostname(&mut self, hostname: &str) -> &mut Self {
+        self.hostname = Some(hostname.to_owned());
+        self
+    }
+
+    /// Replaces the contents of the `CMDLINE` feature section, if the input
+    /// has one. Without this, only the path prefixes in the command line
+    /// arguments are replaced.
+    pub fn set_cmdline(&mut self, args: &[&str]) -> &mut Self {
+        self.cmdline = Some(args.iter().map(|arg| (*arg).to_owned()).collect());
+        self
+    }
+
+    /// Leaves out the given feature section.
+    pub fn remove_feature(&mut self, feature: Feature) -> &mut Self {
+        self.removed_features.push(feature);
+        self
+    }
+
+    /// Replaces `from` with `to` at the start of the file paths in `MMAP`,
+    /// `MMAP2` and `HEADER_BUILD_ID` records, in the `BUILD_ID` feature
+    /// section and in the command line arguments. Only the first matching prefix is replaced.
+    pub fn replace_path_prefix(&mut self, from: &str, to: &str) -> &mut Self {
+        self.path_prefixes
+            .push((from.as_bytes().to_owned(), to.as_bytes().to_owned()));
+        self
+    }
+
+    /// Replaces the command name `from` with `to` in `COMM` records.
+    pub fn replace_comm(&mut self, from: &str, to: &str) -> &mut Self {
+        self.comm_names
+            .push((from.as_bytes().to_owned(), to.as_bytes().to_owned()));
+        self
+    }
+
+    /// Leaves out the `BUILD_ID` feature section and the `HEADER_BUILD_ID`
+    /// records, and clears the build IDs in `MMAP2` records.
+    pub fn strip_build_ids(&mut self) -> &mut Self {
+        self.strip_build_ids = true;
+        self
+    }
+
+    /// Reads a perf.data file and writes the redacted file to `writer`.
+    pub fn redact<R, W>(&self, reader: PerfFileReader<R>, writer: W) -> Result<W, Error>
+    where
+        R: Read,
+        W: Write + Seek,
+    {
+        let mut features = FeatureSectionsBuilder::new(reader.perf_file.endian());
+        self.redact_feature_sections(&reader.perf_file, &mut features)?;
+
+        let mut writer = transcode_impl(reader, writer, |_, record| {
+            let record = match record {
+                PerfFileRecord::EventRecord { record, .. } => record,
+                PerfFileRecord::UserRecord(record) => return self.redact_user_record(record),
+            };
+            let body = record.data.as_slice();
+            let mut misc = record.misc;
+            let new_body = match record.record_type {
+                RecordType::MMAP => self.redact_path(&body, MMAP_FILENAME_OFFSET),
+                RecordType::MMAP2 => {
+                    let mut new_body = self.redact_path(&body, MMAP2_FILENAME_OFFSET);
+                    if self.strip_build_ids && misc & PERF_RECORD_MISC_MMAP_BUILD_ID != 0 {
+                        let new_body = new_body.get_or_insert_with(|| body.to_vec());
+                        if let Some(build_id) = new_body.get_mut(MMAP2_BUILD_ID_RANGE) {
+                            build_id.fill(0);
+                            misc &= !PERF_RECORD_MISC_MMAP_BUILD_ID;
+                        }
+                    }
+                    new_body
+                }
+                RecordType::COMM => self.redact_comm(&body),
+                _ => None,
+            };
+            match new_body {
+                Some(body) => TranscodeAction::Replace { misc, body },
+                None => TranscodeAction::Keep,
+            }
+        })?;
+
+        let sections = writer.feature_sections_mut();
+        for feature in &self.removed_features {
+            sections.remove(*feature);
+        }
+        for (feature, data) in features.into_sections() {
+            if sections.get(feature).is_some() {
+                sections.set_raw(feature, data);
+            }
+        }
+        if self.strip_build_ids {
+            sections.remove(Feature::BUILD_ID);
+        }
+        Ok(writer.finish()?)
+    }
+
+    /// Applies the build ID options to `HEADER_BUILD_ID` records, which
+    /// carry the same entries as the `BUILD_ID` feature section in pipe mode.
+    fn redact_user_record(&self, record: &RawUserRecord) -> TranscodeAction {
+        if record.record_type != UserRecordType::PERF_HEADER_BUILD_ID {
+            return TranscodeAction::Keep;
+        }
+        if self.strip_build_ids {
+            return TranscodeAction::Drop;
+        }
+        let body = record.data.as_slice();
+        let new_record = match record.endian {
+            Endianness::LittleEndian => {
+                self.redact_build_id_record::<LittleEndian>(record.misc, &body)
+            }
+            Endianness::BigEndian => self.redact_build_id_record::<BigEndian>(record.misc, &body),
+        };
+        match new_record {
+            Some((misc, body)) => TranscodeAction::Replace { misc, body },
+            None => TranscodeAction::Keep,
+        }
+    }
+
+    /// Returns the new misc field and body of a `HEADER_BUILD_ID` record, or
+    /// `None` if no path prefix matches.
+    fn redact_build_id_record<T: ByteOrder>(
+        &self,
+        misc: u16,
+        body: &[u8],
+    ) -> Option<(u16, Vec<u8>)> {
+        let size = u16::try_from(PerfEventHeader::STRUCT_SIZE + body.len()).ok()?;
+        let mut event_bytes = vec![0; PerfEventHeader::STRUCT_SIZE];
+        T::write_u32(&mut event_bytes[0..4], PERF_RECORD_HEADER_BUILD_ID);
+        T::write_u16(&mut event_bytes[4..6], misc);
+        T::write_u16(&mut event_bytes[6..8], size);
+        event_bytes.extend_from_slice(body);
+        let event = BuildIdEvent::parse::<T>(&mut &event_bytes[..]).ok()?;
+        let path = self.replace_prefix(event.file_path)?;
+        let mut new_event = Vec::new();
+        BuildIdEvent {
+            file_path: &path,
+            ..event
+        }
+        .write::<T>(&mut new_event)
+        .ok()?;
+        let new_misc = T::read_u16(&new_event[4..6]);
+        Some((new_misc, new_event.split_off(PerfEventHeader::STRUCT_SIZE)))
+    }
+
+    /// Computes the new contents of the feature sections which this redactor
+    /// changes. The sections are only used if the input has them.
+    fn redact_feature_sections(
+        &self,
+        perf_file: &PerfFile,
+        features: &mut FeatureSectionsBuilder,
+    ) -> Result<(), Error> {
+        if let Some(hostname) = &self.hostname {
+            features.set_hostname(hostname);
+        }
+        if let Some(cmdline) = &self.cmdline {
+            let args: Vec<&str> = cmdline.iter().map(String::as_str).collect();
+            features.set_cmdline(&args);
+        } else if let Some(args) = perf_file.cmdline_bytes()? {
+            let args: Vec<String> = args
+                .into_iter()
+                .map(|arg| {
+                    let arg = self.replace_prefix(arg).unwrap_or_else(|| arg.to_owned());
+                    String::from_utf8_lossy(&arg).into_owned()
+                })
+                .collect();
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            features.set_cmdline(&args);
+        }
+        if !self.strip_build_ids && !self.path_prefixes.is_empty() {
+            let data = match perf_file.endian() {
+                Endianness::LittleEndian => self.redact_build_ids::<LittleEndian>(perf_file)?,
+                Endianness::BigEndian => self.redact_build_ids::<BigEndian>(perf_file)?,
+            };
+            features.set_raw(Feature::BUILD_ID, data);
+        }
+        Ok(())
+    }
+
+    fn redact_build_ids<T: ByteOrder>(&self, perf_file: &PerfFile) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+        for event in perf_file.build_id_events() {
+            let path = self.replace_prefix(event.file_path);
+            BuildIdEvent {
+                file_path: path.as_deref().unwrap_or(event.file_path),
+                ..event
+            }
+            .write::<T>(&mut data)?;
+        }
+        Ok(data)
+    }
+
+    /// Returns the path with the first matching prefix replaced, or `None` if
+    /// no prefix matches.
+    fn replace_prefix(&self, path: &[u8]) -> Option<Vec<u8>> {
+        self.path_prefixes.iter().find_map(|(from, to)| {
+            let rest = path.strip_prefix(&from[..])?;
+            Some([&to[..], rest].concat())
+        })
+    }
+
+    fn redact_path(&self, body: &[u8], offset: usize) -> Option<Vec<u8>> {
+        let path = string_at(body, offset)?;
+        let new_path = self.replace_prefix(path)?;
+        replace_string_at(body, offset, &new_path)
+    }
+
+    fn redact_comm(&self, body: &[u8]) -> Option<Vec<u8>> {
+        let name = string_at(body, COMM_NAME_OFFSET)?;
+        let (_, new_name) = self.comm_names.iter().find(|(from, _)| from == name)?;
+        replace_string_at(body, COMM_NAME_OFFSET, new_name)
+    }
+}
+
+/// Returns the nul-terminated string at `offset` in a record body, without
+/// the nul byte.
+fn string_at(body: &[u8], offset: usize) -> Option<&[u8]> {
+    let rest = body.get(offset..)?;
+    let len = memchr::memchr(0, rest)?;
+    Some(&rest[..len])
+}
+
+/// Replaces the nul-terminated string at `offset` in a record body. Strings
+/// in records are padded with nul bytes to a multiple of 8 bytes, and are
+/// followed by the `sample_id_all` fields, which are kept.
+fn replace_string_at(body: &[u8], offset: usize, new_string: &[u8]) -> Option<Vec<u8>> {
+    let old_len = string_at(body, offset)?.len();
+    let rest = body.get(offset + (old_len + 1).next_multiple_of(8)..)?;
+    let mut new_body = body[..offset].to_vec();
+    new_body.extend_from_slice(new_string);
+    new_body.resize(offset + (new_string.len() + 1).next_multiple_of(8), 0);
+    new_body.extend_from_slice(rest);
+    Some(new_body)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use linux_perf_event_reader::constants::PERF_RECORD_MISC_USER;
+    use linux_perf_event_reader::{
+        CpuMode, Endianness, PerfEventHeader, RecordType, SoftwareCounterType,
+    };
+
+    use super::{string_at, Redactor, COMM_NAME_OFFSET, MMAP_FILENAME_OFFSET};
+    use crate::constants::PERF_RECORD_HEADER_BUILD_ID;
+    use crate::file_writer::test::test_attr;
+    use crate::{
+        BuildIdEvent, DsoInfo, DsoKey, Feature, PerfFileReader, PerfFileRecord, PerfFileWriter,
+    };
+
+    /// The `sample_id_all` fields of `test_attr`.
+    fn sample_id(body: &mut Vec<u8>, pid: u32, time: u64) {
+        body.write_u32::<LittleEndian>(pid).unwrap();
+        body.write_u32::<LittleEndian>(pid).unwrap();
+        body.write_u64::<LittleEndian>(time).unwrap();
+        body.write_u64::<LittleEndian>(1).unwrap();
+    }
+
+    #[test]
+    fn redact() {
+        let path = b"/home/alice/bin/app";
+        let mut mmap_body = Vec::new();
+        mmap_body.write_u32::<LittleEndian>(10).unwrap();
+        mmap_body.write_u32::<LittleEndian>(10).unwrap();
+        mmap_body.write_u64::<LittleEndian>(0x1000).unwrap();
+        mmap_body.write_u64::<LittleEndian>(0x2000).unwrap();
+        mmap_body.write_u64::<LittleEndian>(0).unwrap();
+        mmap_body.extend_from_slice(path);
+        mmap_body.resize(MMAP_FILENAME_OFFSET + 24, 0);
+        sample_id(&mut mmap_body, 10, 2);
+        let mut comm_body = Vec::new();
+        comm_body.write_u32::<LittleEndian>(10).unwrap();
+        comm_body.write_u32::<LittleEndian>(10).unwrap();
+        comm_body.extend_from_slice(b"secret\0\0");
+        sample_id(&mut comm_body, 10, 1);
+
+        let mut build_ids = HashMap::new();
+        build_ids.insert(
+            DsoKey::detect(path, CpuMode::User).unwrap(),
+            DsoInfo {
+                path: path.to_vec(),
+                build_id: vec![0xab; 20],
+            },
+        );
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer
+            .feature_sections_mut()
+            .set_hostname("alice-laptop")
+            .set_cpu_id("GenuineIntel,6,85,4")
+            .set_cmdline(&["perf", "record", "/home/alice/bin/app"])
+            .set_build_ids(&build_ids)
+            .unwrap();
+        writer
+            .write_record(RecordType::COMM, 0, &comm_body)
+            .unwrap();
+        writer
+            .write_record(RecordType::MMAP, 2, &mmap_body)
+            .unwrap();
+        let input = writer.finish().unwrap().into_inner();
+
+        let reader = PerfFileReader::parse_file(Cursor::new(&input[..])).unwrap();
+        let output = Redactor::new()
+            .set_hostname("localhost")
+            .remove_feature(Feature::CPUID)
+            .replace_path_prefix("/home/alice/", "/home/user/")
+            .replace_comm("secret", "app")
+            .redact(reader, Cursor::new(Vec::new()))
+            .unwrap()
+            .into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&output[..])).unwrap();
+        assert_eq!(perf_file.hostname().unwrap(), Some("localhost"));
+        assert_eq!(perf_file.cpu_id().unwrap(), None);
+        assert_eq!(
+            perf_file.cmdline().unwrap(),
+            Some(vec!["perf", "record", "/home/user/bin/app"])
+        );
+        let build_ids: Vec<_> = perf_file.build_ids().unwrap().into_values().collect();
+        assert_eq!(build_ids[0].path, b"/home/user/bin/app");
+        assert_eq!(build_ids[0].build_id, vec![0xab; 20]);
+
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            let PerfFileRecord::EventRecord { record, .. } = record else {
+                panic!("unexpected user record");
+            };
+            let body = record.data.as_slice();
+            let offset = match record.record_type {
+                RecordType::COMM => COMM_NAME_OFFSET,
+                _ => MMAP_FILENAME_OFFSET,
+            };
+            let string = string_at(&body, offset).unwrap().to_owned();
+            let common = record.common_data().unwrap();
+            records.push((string, common.pid, common.timestamp));
+        }
+        assert_eq!(
+            records,
+            vec![
+                (b"app".to_vec(), Some(10), Some(1)),
+                (b"/home/user/bin/app".to_vec(), Some(10), Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn build_id_records() {
+        let mut event = Vec::new();
+        BuildIdEvent {
+            header: PerfEventHeader {
+                type_: PERF_RECORD_HEADER_BUILD_ID,
+                misc: PERF_RECORD_MISC_USER,
+                size: 0,
+            },
+            pid: -1,
+            build_id: &[0xab; 20],
+            file_path: b"/home/alice/bin/app",
+        }
+        .write::<LittleEndian>(&mut event)
+        .unwrap();
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        let misc = u16::from_le_bytes([event[4], event[5]]);
+        writer
+            .write_record(RecordType(PERF_RECORD_HEADER_BUILD_ID), misc, &event[8..])
+            .unwrap();
+        let input = writer.finish().unwrap().into_inner();
+
+        let redacted_build_ids = |redactor: &Redactor| {
+            let reader = PerfFileReader::parse_file(Cursor::new(&input[..])).unwrap();
+            let output = redactor
+                .redact(reader, Cursor::new(Vec::new()))
+                .unwrap()
+                .into_inner();
+            let PerfFileReader {
+                mut perf_file,
+                mut record_iter,
+            } = PerfFileReader::parse_file(Cursor::new(&output[..])).unwrap();
+            while record_iter.next_record(&mut perf_file).unwrap().is_some() {}
+            perf_file
+                .build_id_events()
+                .map(|event| (event.file_path.to_vec(), event.build_id.to_vec()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            redacted_build_ids(Redactor::new().replace_path_prefix("/home/alice/", "/home/user/")),
+            vec![(b"/home/user/bin/app".to_vec(), vec![0xab; 20])]
+        );
+        assert!(redacted_build_ids(Redactor::new().strip_build_ids()).is_empty());
+    }
+}
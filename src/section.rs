@@ -6,7 +6,7 @@ use std::io::Read;
 ///
 /// A PerfFileSection contains a pointer to another section of the perf file.
 /// The header contains three such pointers: for attributes, data and event types.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PerfFileSection {
     /// offset from start of file
     pub offset: u64,
@@ -0,0 +1,106 @@
+use std::io::{Read, Seek};
+
+use crate::{Error, PerfFile, PerfFileReader, PerfFileRecord, PerfRecordIter};
+
+/// A perf.data file which is being read, combining the [`PerfFile`] with the
+/// file's metadata and the [`PerfRecordIter`] for its records.
+///
+/// This is an alternative to destructuring a [`PerfFileReader`] and passing
+/// the `PerfFile` to each call of [`PerfRecordIter::next_record`].
+///
+/// # Example
+///
+/// ```
+/// use linux_perf_data::{PerfFileRecord, PerfSession};
+///
+/// # fn wrapper() -> Result<(), linux_perf_data::Error> {
+/// let file = std::fs::File::open("perf.data")?;
+/// let mut session = PerfSession::open(std::io::BufReader::new(file))?;
+/// println!("recorded on {:?}", session.perf_file().hostname()?);
+///
+/// session.for_each_record(|perf_file, record| {
+///     if let PerfFileRecord::EventRecord { attr_index, record, .. } = record {
+///         let name = perf_file.event_attributes()[attr_index].name();
+///         println!("{:?} for event {:?}", record.record_type, name);
+///     }
+///     Ok::<_, linux_perf_data::Error>(())
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct PerfSession<R: Read> {
+    perf_file: PerfFile,
+    record_iter: PerfRecordIter<R>,
+}
+
+impl<C: Read + Seek> PerfSession<C> {
+    /// Parses the header and the metadata of the file. The records are read
+    /// on demand.
+    pub fn open(cursor: C) -> Result<Self, Error> {
+        Ok(Self::from(PerfFileReader::parse_file(cursor)?))
+    }
+}
+
+impl<R: Read> PerfSession<R> {
+    /// The file's metadata: the header, the event attributes and the feature
+    /// sections.
+    pub fn perf_file(&self) -> &PerfFile {
+        &self.perf_file
+    }
+
+    /// The record iterator, e.g. for changing its settings or for building
+    /// an index.
+    pub fn record_iter(&mut self) -> &mut PerfRecordIter<R> {
+        &mut self.record_iter
+    }
+
+    /// Returns the next record, in the same order as
+    /// [`PerfRecordIter::next_record`].
+    pub fn next_record(&mut self) -> Result<Option<PerfFileRecord<'_>>, Error> {
+        self.record_iter.next_record(&mut self.perf_file)
+    }
+
+    /// Like [`PerfSession::next_record`], but also returns the file's
+    /// metadata, which can be used while the record is borrowed, e.g. to look
+    /// up the event attributes of the record.
+    pub fn next_record_with_file(
+        &mut self,
+    ) -> Result<Option<(&PerfFile, PerfFileRecord<'_>)>, Error> {
+        let record = self.record_iter.next_record(&mut self.perf_file)?;
+        Ok(record.map(|record| (&self.perf_file, record)))
+    }
+
+    /// Calls `f` for each remaining record, together with the file's
+    /// metadata. Stops at the first error, either from reading or from `f`.
+    pub fn for_each_record<E, F>(&mut self, mut f: F) -> Result<(), E>
+    where
+        E: From<Error>,
+        F: FnMut(&PerfFile, PerfFileRecord) -> Result<(), E>,
+    {
+        while let Some((perf_file, record)) = self.next_record_with_file()? {
+            f(perf_file, record)?;
+        }
+        Ok(())
+    }
+
+    /// Splits the session into the metadata and the record iterator.
+    pub fn into_reader(self) -> PerfFileReader<R> {
+        PerfFileReader {
+            perf_file: self.perf_file,
+            record_iter: self.record_iter,
+        }
+    }
+}
+
+impl<R: Read> From<PerfFileReader<R>> for PerfSession<R> {
+    fn from(reader: PerfFileReader<R>) -> Self {
+        let PerfFileReader {
+            perf_file,
+            record_iter,
+        } = reader;
+        Self {
+            perf_file,
+            record_iter,
+        }
+    }
+}
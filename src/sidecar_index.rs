@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use linux_perf_event_reader::{CpuMode, RecordType};
+
+use crate::{
+    DsoInfo, DsoKey, DsoKeyDetectionOptions, Error, PerfFile, PerfFileIndex, PerfRecordIter,
+    RoundIndexEntry, SampleTimeRange,
+};
+
+const MAGIC: &[u8; 8] = b"PERFIDX\0";
+
+/// The version of the format written by [`SidecarIndex::write_to`]. Files
+/// with a different version are rejected by [`SidecarIndex::read_from`].
+pub const SIDECAR_INDEX_VERSION: u32 = 2;
+
+/// An entry of the `BUILD_ID` feature section, as stored in a [`SidecarIndex`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SidecarBuildId {
+    /// The `misc` field of the entry, which contains the CPU mode.
+    pub misc: u16,
+    pub dso_info: DsoInfo,
+}
+
+/// The results of the initial scan of a perf.data file, stored next to the
+/// file (e.g. as `perf.data.idx`) so that reopening a large file doesn't
+/// require scanning it again.
+///
+/// The sidecar stores the [`PerfFileIndex`], with the time ranges, the
+/// record counts and the round offsets, and the build ID entries. Use
+/// [`SidecarIndex::is_valid_for`] after loading a sidecar to check that it
+/// still belongs to the file; it is invalid if the file has been rewritten,
+/// e.g. by `perf inject`, which is detected with a fingerprint of the file
+/// contents.
+///
+/// The format is a small little-endian binary format, versioned with
+/// [`SIDECAR_INDEX_VERSION`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SidecarIndex {
+    /// The file offset of the data section of the indexed file.
+    pub data_section_offset: u64,
+    /// The size of the data section of the indexed file.
+    pub data_section_size: u64,
+    /// The number of event attributes in the indexed file.
+    pub attr_count: u64,
+    /// A hash of the length of the indexed file, of its header and of the
+    /// first and the last block of its data section.
+    pub content_fingerprint: u64,
+    pub index: PerfFileIndex,
+    pub build_ids: Vec<SidecarBuildId>,
+}
+
+impl SidecarIndex {
+    /// Scans the file with [`PerfRecordIter::build_index`] and collects the
+    /// build IDs.
+    pub fn build<R: Read + Seek>(
+        perf_file: &PerfFile,
+        record_iter: &mut PerfRecordIter<R>,
+    ) -> Result<Self, Error> {
+        let index = record_iter.build_index()?;
+        let content_fingerprint = record_iter.content_fingerprint()?;
+        let build_ids = perf_file
+            .build_id_events()
+            .map(|event| SidecarBuildId {
+                misc: event.header.misc,
+                dso_info: DsoInfo {
+                    path: event.file_path.to_owned(),
+                    build_id: event.build_id.to_owned(),
+                },
+            })
+            .collect();
+        let header = perf_file.header();
+        Ok(Self {
+            data_section_offset: header.data_section.offset,
+            data_section_size: header.data_section.size,
+            attr_count: perf_file.event_attributes().len() as u64,
+            content_fingerprint,
+            index,
+            build_ids,
+        })
+    }
+
+    /// The path of the sidecar file for a perf.data file, which is the path
+    /// with `.idx` appended.
+    pub fn path_for(perf_data_path: &Path) -> PathBuf {
+        let mut path = perf_data_path.as_os_str().to_owned();
+        path.push(".idx");
+        PathBuf::from(path)
+    }
+
+    /// Returns whether this sidecar was created for the file which is read
+    /// by `perf_file` and `record_iter`: the file must have the same layout
+    /// and the same content fingerprint.
+    ///
+    /// This does not affect the iteration state of `record_iter`.
+    pub fn is_valid_for<R: Read + Seek>(
+        &self,
+        perf_file: &PerfFile,
+        record_iter: &mut PerfRecordIter<R>,
+    ) -> Result<bool, Error> {
+        let header = perf_file.header();
+        let same_layout = self.data_section_offset == header.data_section.offset
+            && self.data_section_size == header.data_section.size
+            && self.attr_count == perf_file.event_attributes().len() as u64
+            && self.index.sample_counts_per_attr.len() as u64 == self.attr_count;
+        Ok(same_layout && self.content_fingerprint == record_iter.content_fingerprint()?)
+    }
+
+    /// The build IDs by [`DsoKey`], like [`PerfFile::build_ids_with_options`].
+    pub fn build_ids(&self, options: &DsoKeyDetectionOptions) -> HashMap<DsoKey, DsoInfo> {
+        self.build_ids
+            .iter()
+            .filter_map(|entry| {
+                let cpu_mode = CpuMode::from_misc(entry.misc);
+                let path = &entry.dso_info.path;
+                let dso_key = DsoKey::detect_with_options(path, cpu_mode, true, options)?;
+                Some((dso_key, entry.dso_info.clone()))
+            })
+            .collect()
+    }
+
+    /// Writes the sidecar.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        let w = &mut writer;
+        w.write_all(MAGIC)?;
+        w.write_u32::<LittleEndian>(SIDECAR_INDEX_VERSION)?;
+        w.write_u64::<LittleEndian>(self.data_section_offset)?;
+        w.write_u64::<LittleEndian>(self.data_section_size)?;
+        w.write_u64::<LittleEndian>(self.attr_count)?;
+        w.write_u64::<LittleEndian>(self.content_fingerprint)?;
+
+        let index = &self.index;
+        let sample_time_range = index
+            .sample_time_range
+            .map(|range| (range.first_sample_time, range.last_sample_time));
+        write_optional_range(w, sample_time_range)?;
+        write_optional_range(w, index.record_time_range)?;
+        let mut record_counts: Vec<_> = index.record_counts.iter().collect();
+        record_counts.sort();
+        w.write_u64::<LittleEndian>(record_counts.len() as u64)?;
+        for (record_type, count) in record_counts {
+            w.write_u32::<LittleEndian>(record_type.0)?;
+            w.write_u64::<LittleEndian>(*count)?;
+        }
+        w.write_u64::<LittleEndian>(index.sample_counts_per_attr.len() as u64)?;
+        for count in &index.sample_counts_per_attr {
+            w.write_u64::<LittleEndian>(*count)?;
+        }
+        w.write_u64::<LittleEndian>(index.rounds.len() as u64)?;
+        for round in &index.rounds {
+            w.write_u64::<LittleEndian>(round.offset)?;
+            w.write_u64::<LittleEndian>(round.size)?;
+            w.write_u64::<LittleEndian>(round.record_count)?;
+            write_optional(w, round.min_timestamp)?;
+            write_optional(w, round.max_timestamp)?;
+        }
+
+        w.write_u64::<LittleEndian>(self.build_ids.len() as u64)?;
+        for entry in &self.build_ids {
+            w.write_u16::<LittleEndian>(entry.misc)?;
+            write_bytes(w, &entry.dso_info.path)?;
+            write_bytes(w, &entry.dso_info.build_id)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a sidecar which was written by [`SidecarIndex::write_to`].
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let r = &mut reader;
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::UnrecognizedMagicValue(magic));
+        }
+        let version = r.read_u32::<LittleEndian>()?;
+        if version != SIDECAR_INDEX_VERSION {
+            return Err(Error::UnsupportedSidecarIndexVersion(version));
+        }
+        let data_section_offset = r.read_u64::<LittleEndian>()?;
+        let data_section_size = r.read_u64::<LittleEndian>()?;
+        let attr_count = r.read_u64::<LittleEndian>()?;
+        let content_fingerprint = r.read_u64::<LittleEndian>()?;
+
+        let sample_time_range =
+            read_optional_range(r)?.map(|(first_sample_time, last_sample_time)| SampleTimeRange {
+                first_sample_time,
+                last_sample_time,
+            });
+        let record_time_range = read_optional_range(r)?;
+        let mut record_counts = HashMap::new();
+        for _ in 0..r.read_u64::<LittleEndian>()? {
+            let record_type = RecordType(r.read_u32::<LittleEndian>()?);
+            record_counts.insert(record_type, r.read_u64::<LittleEndian>()?);
+        }
+        let mut sample_counts_per_attr = Vec::new();
+        for _ in 0..r.read_u64::<LittleEndian>()? {
+            sample_counts_per_attr.push(r.read_u64::<LittleEndian>()?);
+        }
+        let mut rounds = Vec::new();
+        for _ in 0..r.read_u64::<LittleEndian>()? {
+            rounds.push(RoundIndexEntry {
+                offset: r.read_u64::<LittleEndian>()?,
+                size: r.read_u64::<LittleEndian>()?,
+                record_count: r.read_u64::<LittleEndian>()?,
+                min_timestamp: read_optional(r)?,
+                max_timestamp: read_optional(r)?,
+            });
+        }
+
+        let mut build_ids = Vec::new();
+        for _ in 0..r.read_u64::<LittleEndian>()? {
+            let misc = r.read_u16::<LittleEndian>()?;
+            let path = read_bytes(r)?;
+            let build_id = read_bytes(r)?;
+            build_ids.push(SidecarBuildId {
+                misc,
+                dso_info: DsoInfo { path, build_id },
+            });
+        }
+
+        Ok(Self {
+            data_section_offset,
+            data_section_size,
+            attr_count,
+            content_fingerprint,
+            index: PerfFileIndex {
+                sample_time_range,
+                record_time_range,
+                record_counts,
+                sample_counts_per_attr,
+                rounds,
+            },
+            build_ids,
+        })
+    }
+}
+
+fn write_optional<W: Write>(w: &mut W, value: Option<u64>) -> Result<(), std::io::Error> {
+    match value {
+        Some(value) => {
+            w.write_u8(1)?;
+            w.write_u64::<LittleEndian>(value)
+        }
+        None => w.write_u8(0),
+    }
+}
+
+fn read_optional<R: Read>(r: &mut R) -> Result<Option<u64>, std::io::Error> {
+    match r.read_u8()? {
+        0 => Ok(None),
+        _ => Ok(Some(r.read_u64::<LittleEndian>()?)),
+    }
+}
+
+fn write_optional_range<W: Write>(
+    w: &mut W,
+    range: Option<(u64, u64)>,
+) -> Result<(), std::io::Error> {
+    write_optional(w, range.map(|(first, _)| first))?;
+    write_optional(w, range.map(|(_, last)| last))
+}
+
+fn read_optional_range<R: Read>(r: &mut R) -> Result<Option<(u64, u64)>, std::io::Error> {
+    let first = read_optional(r)?;
+    let last = read_optional(r)?;
+    Ok(first.zip(last))
+}
+
+fn write_bytes<W: Write>(w: &mut W, bytes: &[u8]) -> Result<(), std::io::Error> {
+    w.write_u32::<LittleEndian>(bytes.len() as u32)?;
+    w.write_all(bytes)
+}
+
+fn read_bytes<R: Read>(r: &mut R) -> Result<Vec<u8>, std::io::Error> {
+    let len = r.read_u32::<LittleEndian>()?;
+    let mut bytes = Vec::new();
+    r.take(len.into()).read_to_end(&mut bytes)?;
+    if bytes.len() < len as usize {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::SidecarIndex;
+    use crate::{Error, PerfFileReader};
+
+    #[test]
+    fn round_trip() {
+        let mut file = Vec::new();
+        file.extend_from_slice(b"PERFILE2");
+        for value in [104u64, 64, 104, 64, 168, 24, 0, 0, 0, 0, 0, 0] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        // perf_event_attr (PERF_ATTR_SIZE_VER0), with sample_type = PERF_SAMPLE_TIME.
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(&64u32.to_le_bytes());
+        for value in [0u64, 1000, 1 << 2, 0, 0, 0, 0] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        // A sample with time 42, and a FINISHED_ROUND record.
+        file.extend_from_slice(&[9, 0, 0, 0, 2, 0, 16, 0]);
+        file.extend_from_slice(&42u64.to_le_bytes());
+        file.extend_from_slice(&[68, 0, 0, 0, 0, 0, 8, 0]);
+
+        let PerfFileReader {
+            perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let sidecar = SidecarIndex::build(&perf_file, &mut record_iter).unwrap();
+        assert_eq!(sidecar.index.record_count(), 1);
+
+        let mut bytes = Vec::new();
+        sidecar.write_to(&mut bytes).unwrap();
+        let loaded = SidecarIndex::read_from(&bytes[..]).unwrap();
+        assert_eq!(loaded, sidecar);
+        assert!(loaded.is_valid_for(&perf_file, &mut record_iter).unwrap());
+
+        // A file with the same layout but a different sample time.
+        let mut rewritten = file.clone();
+        let time_offset = rewritten.len() - 16;
+        rewritten[time_offset] = 43;
+        let PerfFileReader {
+            perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&rewritten[..])).unwrap();
+        assert!(!loaded.is_valid_for(&perf_file, &mut record_iter).unwrap());
+
+        bytes[8] = 1;
+        assert!(matches!(
+            SidecarIndex::read_from(&bytes[..]),
+            Err(Error::UnsupportedSidecarIndexVersion(1))
+        ));
+    }
+}
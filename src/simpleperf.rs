@@ -1,7 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
-use linux_perf_event_reader::Endianness;
+use linux_perf_event_reader::{Endianness, RawData};
 use prost::Message;
 
 use crate::Error;
@@ -113,6 +113,14 @@ pub struct SimpleperfSymbol {
     pub name: ::prost::alloc::string::String,
 }
 
+impl SimpleperfSymbol {
+    /// The symbol name, demangled with [`demangle`](crate::demangle).
+    #[cfg(feature = "demangle")]
+    pub fn demangled_name(&self) -> std::borrow::Cow<'_, str> {
+        crate::demangle(&self.name)
+    }
+}
+
 /// DEX-specific info inside a [`SimpleperfFileRecord`].
 #[derive(Clone, PartialEq, Eq, ::prost_derive::Message)]
 pub struct SimpleperfDexFileInfo {
@@ -148,6 +156,66 @@ pub enum SimpleperfTypeSpecificInfo {
     KernelModule(SimpleperfKernelModuleInfo),
 }
 
+/// Used in the `SIMPLEPERF_DEBUG_UNWIND` section.
+///
+/// Lists the files whose contents are stored in the
+/// `SIMPLEPERF_DEBUG_UNWIND_FILE` section. Simpleperf stores these files when
+/// recording with `--keep-failed-unwinding-debug-info`, so that the samples
+/// for which unwinding failed on the device can be unwound again on the host.
+#[derive(Clone, PartialEq, Eq, ::prost_derive::Message)]
+pub struct SimpleperfDebugUnwindFeature {
+    #[prost(message, repeated, tag = "1")]
+    pub file: ::prost::alloc::vec::Vec<SimpleperfDebugUnwindFile>,
+}
+
+/// A file in the `SIMPLEPERF_DEBUG_UNWIND_FILE` section.
+#[derive(Clone, PartialEq, Eq, ::prost_derive::Message)]
+pub struct SimpleperfDebugUnwindFile {
+    /// The path of the file on the device.
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+    /// The size of the file contents in the `SIMPLEPERF_DEBUG_UNWIND_FILE`
+    /// section.
+    #[prost(uint64, tag = "2")]
+    pub size: u64,
+}
+
+/// Parses the `SIMPLEPERF_DEBUG_UNWIND` section, which is a single
+/// protobuf-encoded [`SimpleperfDebugUnwindFeature`].
+pub fn parse_debug_unwind_section(bytes: &[u8]) -> Result<Vec<SimpleperfDebugUnwindFile>, Error> {
+    let feature = SimpleperfDebugUnwindFeature::decode(bytes)
+        .map_err(Error::ProtobufParsingSimpleperfDebugUnwindSection)?;
+    Ok(feature.file)
+}
+
+/// A file from the `SIMPLEPERF_DEBUG_UNWIND` section, with its contents from
+/// the `SIMPLEPERF_DEBUG_UNWIND_FILE` section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleperfDebugUnwindFileContents<'a> {
+    pub file: SimpleperfDebugUnwindFile,
+    pub data: &'a [u8],
+}
+
+/// Splits the `SIMPLEPERF_DEBUG_UNWIND_FILE` section into the contents of
+/// the files from the `SIMPLEPERF_DEBUG_UNWIND` section. The files are
+/// stored back to back, in the same order.
+pub fn split_debug_unwind_file_section(
+    files: Vec<SimpleperfDebugUnwindFile>,
+    mut bytes: &[u8],
+) -> Result<Vec<SimpleperfDebugUnwindFileContents<'_>>, Error> {
+    let mut contents = Vec::with_capacity(files.len());
+    for file in files {
+        let size = usize::try_from(file.size).map_err(|_| Error::FeatureSectionTooSmall)?;
+        if size > bytes.len() {
+            return Err(Error::FeatureSectionTooSmall);
+        }
+        let (data, rest) = bytes.split_at(size);
+        bytes = rest;
+        contents.push(SimpleperfDebugUnwindFileContents { file, data });
+    }
+    Ok(contents)
+}
+
 pub fn parse_file2_section(
     mut bytes: &[u8],
     endian: Endianness,
@@ -282,6 +350,169 @@ impl SimpleperfFileRecord {
     }
 }
 
+/// A `SIMPLEPERF_DSO` record. Old simpleperf versions write these records,
+/// together with [`SimpleperfSymbolRecord`]s, instead of the
+/// `SIMPLEPERF_FILE` and `SIMPLEPERF_FILE2` sections.
+///
+/// Use [`SimpleperfDsoTable`] to combine them into [`SimpleperfFileRecord`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleperfDsoRecord<'a> {
+    /// Uses constants from [`simpleperf_dso_type`].
+    pub dso_type: u64,
+    /// The ID which [`SimpleperfSymbolRecord::dso_id`] refers to.
+    pub dso_id: u64,
+    pub min_vaddr: u64,
+    /// The path of the DSO, without the trailing nul bytes.
+    pub dso_name: RawData<'a>,
+}
+
+impl<'a> SimpleperfDsoRecord<'a> {
+    pub fn parse<T: ByteOrder>(mut data: RawData<'a>) -> Result<Self, std::io::Error> {
+        // struct DsoRecord {
+        //     perf_event_header header;
+        //     uint64_t dso_type;
+        //     uint64_t dso_id;
+        //     uint64_t min_vaddr;
+        //     char dso_name[]; // nul-terminated, padded to 8 bytes
+        // };
+        let dso_type = data.read_u64::<T>()?;
+        let dso_id = data.read_u64::<T>()?;
+        let min_vaddr = data.read_u64::<T>()?;
+        let dso_name = data
+            .read_string()
+            .ok_or(std::io::ErrorKind::UnexpectedEof)?;
+        Ok(Self {
+            dso_type,
+            dso_id,
+            min_vaddr,
+            dso_name,
+        })
+    }
+}
+
+/// A `SIMPLEPERF_SYMBOL` record, a symbol of the DSO from the
+/// [`SimpleperfDsoRecord`] with the same `dso_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleperfSymbolRecord<'a> {
+    pub addr: u64,
+    pub len: u64,
+    pub dso_id: u64,
+    /// The symbol name, without the trailing nul bytes.
+    pub name: RawData<'a>,
+}
+
+impl<'a> SimpleperfSymbolRecord<'a> {
+    pub fn parse<T: ByteOrder>(mut data: RawData<'a>) -> Result<Self, std::io::Error> {
+        // struct SymbolRecord {
+        //     perf_event_header header;
+        //     uint64_t addr;
+        //     uint64_t len;
+        //     uint64_t dso_id;
+        //     char name[]; // nul-terminated, padded to 8 bytes
+        // };
+        let addr = data.read_u64::<T>()?;
+        let len = data.read_u64::<T>()?;
+        let dso_id = data.read_u64::<T>()?;
+        let name = data
+            .read_string()
+            .ok_or(std::io::ErrorKind::UnexpectedEof)?;
+        Ok(Self {
+            addr,
+            len,
+            dso_id,
+            name,
+        })
+    }
+}
+
+/// A `SIMPLEPERF_EVENT_ID` record, which maps event IDs to events, for files
+/// whose attribute section doesn't have the IDs.
+///
+/// [`PerfRecordIter`](crate::PerfRecordIter) applies it automatically, like
+/// the [`IdIndex`](crate::IdIndex) of perf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleperfEventIdRecord {
+    pub entries: Vec<SimpleperfEventId>,
+}
+
+/// An entry of a [`SimpleperfEventIdRecord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimpleperfEventId {
+    /// The index of the event in [`PerfFile::event_attributes`](crate::PerfFile::event_attributes).
+    pub attr_index: u64,
+    pub event_id: u64,
+}
+
+impl SimpleperfEventIdRecord {
+    pub fn parse<T: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        // struct EventIdRecord {
+        //     perf_event_header header;
+        //     uint64_t count;
+        //     struct {
+        //         uint64_t attr_id;
+        //         uint64_t event_id;
+        //     } data[count];
+        // };
+        let count = data.read_u64::<T>()?;
+        let count = usize::try_from(count).map_err(|_| std::io::ErrorKind::InvalidData)?;
+        if count > data.len() / 16 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            entries.push(SimpleperfEventId {
+                attr_index: data.read_u64::<T>()?,
+                event_id: data.read_u64::<T>()?,
+            });
+        }
+        Ok(Self { entries })
+    }
+}
+
+/// Collects the `SIMPLEPERF_DSO` and `SIMPLEPERF_SYMBOL` records of a file
+/// from an old simpleperf version into [`SimpleperfFileRecord`]s, i.e. into
+/// the same representation as the `SIMPLEPERF_FILE2` section of newer files.
+#[derive(Debug, Clone, Default)]
+pub struct SimpleperfDsoTable {
+    files: BTreeMap<u64, SimpleperfFileRecord>,
+}
+
+impl SimpleperfDsoTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_dso(&mut self, record: &SimpleperfDsoRecord) {
+        let file = self.files.entry(record.dso_id).or_default();
+        file.path = String::from_utf8_lossy(&record.dso_name.as_slice()).into_owned();
+        file.r#type =
+            u32::try_from(record.dso_type).unwrap_or(simpleperf_dso_type::DSO_UNKNOWN_FILE);
+        file.min_vaddr = record.min_vaddr;
+    }
+
+    /// Adds a symbol to the DSO with the record's `dso_id`. simpleperf
+    /// writes the symbols after their DSO, but symbols for a DSO which
+    /// hasn't been added yet are kept as well.
+    pub fn add_symbol(&mut self, record: &SimpleperfSymbolRecord) {
+        let file = self.files.entry(record.dso_id).or_default();
+        file.symbol.push(SimpleperfSymbol {
+            vaddr: record.addr,
+            len: u32::try_from(record.len).unwrap_or(u32::MAX),
+            name: String::from_utf8_lossy(&record.name.as_slice()).into_owned(),
+        });
+    }
+
+    /// The file record for a `dso_id`.
+    pub fn get(&self, dso_id: u64) -> Option<&SimpleperfFileRecord> {
+        self.files.get(&dso_id)
+    }
+
+    /// Returns the file records, ordered by `dso_id`.
+    pub fn into_file_records(self) -> Vec<SimpleperfFileRecord> {
+        self.files.into_values().collect()
+    }
+}
+
 trait SliceReadStringExt<'a> {
     fn read_nul_terminated_str(&mut self) -> std::io::Result<&'a str>;
 }
@@ -300,3 +531,45 @@ impl<'a> SliceReadStringExt<'a> for &'a [u8] {
         Ok(s)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use byteorder::LittleEndian;
+    use linux_perf_event_reader::RawData;
+
+    use super::{
+        simpleperf_dso_type, SimpleperfDsoRecord, SimpleperfDsoTable, SimpleperfSymbolRecord,
+    };
+
+    #[test]
+    fn dso_and_symbol_records() {
+        let mut dso = Vec::new();
+        for value in [simpleperf_dso_type::DSO_ELF_FILE as u64, 3, 0x1000] {
+            dso.extend_from_slice(&value.to_le_bytes());
+        }
+        dso.extend_from_slice(b"/system/lib64/libc.so\0\0\0");
+        let dso = SimpleperfDsoRecord::parse::<LittleEndian>(RawData::from(&dso[..])).unwrap();
+        assert_eq!(dso.dso_id, 3);
+        assert_eq!(&dso.dso_name.as_slice()[..], b"/system/lib64/libc.so");
+
+        let mut symbol = Vec::new();
+        for value in [0x1234u64, 0x20, 3] {
+            symbol.extend_from_slice(&value.to_le_bytes());
+        }
+        symbol.extend_from_slice(b"malloc\0\0");
+        let symbol =
+            SimpleperfSymbolRecord::parse::<LittleEndian>(RawData::from(&symbol[..])).unwrap();
+
+        let mut table = SimpleperfDsoTable::new();
+        table.add_dso(&dso);
+        table.add_symbol(&symbol);
+        let files = table.into_file_records();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "/system/lib64/libc.so");
+        assert_eq!(files[0].r#type, simpleperf_dso_type::DSO_ELF_FILE);
+        assert_eq!(files[0].min_vaddr, 0x1000);
+        assert_eq!(files[0].symbol[0].vaddr, 0x1234);
+        assert_eq!(files[0].symbol[0].len, 0x20);
+        assert_eq!(files[0].symbol[0].name, "malloc");
+    }
+}
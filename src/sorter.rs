@@ -1,6 +1,12 @@
 use std::collections::VecDeque;
 
 /// Accumulates unordered key-value pairs and emits them in order, sorted by the key.
+/// Values with equal keys are emitted in insertion order.
+///
+/// This is what [`PerfRecordIter`](crate::PerfRecordIter) uses to sort the
+/// records of a perf.data file. It can also be used by consumers who want to
+/// apply the same ordering to their own records, or who want to merge their
+/// own pre-sorted records into the stream with [`Sorter::insert_presorted`].
 ///
 /// The caller can indicate "rounds" with the property that round N cannot
 /// overlap with round N + 2. In other words, the lowest key in round N + 2
@@ -35,14 +41,19 @@ use std::collections::VecDeque;
 pub struct Sorter<K: Ord + Default, V> {
     /// This list is ordered and all values are <= prev_max.
     outgoing: VecDeque<V>,
-    /// Unsorted values.
-    incoming: VecDeque<(K, V)>,
+    /// Unsorted values, with their insertion sequence number.
+    incoming: VecDeque<(K, u64, V)>,
+    /// Values from an external stream which is already sorted.
+    presorted: VecDeque<(K, V)>,
     /// The maximum key of incoming in previous round.
     prev_max: K,
     /// The maximum key of incoming in the current round.
     cur_max: K,
     /// The number of values in incoming which are <= prev_max.
     incoming_lte_prev_max_count: usize,
+    /// The sequence number for the next value in incoming. Used to keep values
+    /// with the same key in insertion order.
+    next_sequence_number: u64,
 }
 
 impl<K: Ord + Clone + Default, V> Default for Sorter<K, V> {
@@ -50,9 +61,11 @@ impl<K: Ord + Clone + Default, V> Default for Sorter<K, V> {
         Self {
             outgoing: VecDeque::new(),
             incoming: VecDeque::new(),
+            presorted: VecDeque::new(),
             prev_max: Default::default(),
             cur_max: Default::default(),
             incoming_lte_prev_max_count: 0,
+            next_sequence_number: 0,
         }
     }
 }
@@ -69,7 +82,8 @@ impl<K: Ord + Clone + Default, V> Sorter<K, V> {
         !self.outgoing.is_empty()
     }
 
-    /// Returns values in order.
+    /// Returns values in order. Values with the same key are returned in the
+    /// order in which they were inserted.
     ///
     /// The order is only guaranteed if the caller respected the contract for
     /// `insert_unordered`.
@@ -86,27 +100,60 @@ impl<K: Ord + Clone + Default, V> Sorter<K, V> {
         } else if key > self.cur_max {
             self.cur_max = key.clone();
         }
-        self.incoming.push_back((key, value));
+        let sequence_number = self.next_sequence_number;
+        self.next_sequence_number += 1;
+        self.incoming.push_back((key, sequence_number, value));
+    }
+
+    /// Insert a value which is known to be ordered after all previously inserted
+    /// values, making it available from `get_next()` immediately.
+    ///
+    /// Must only be called when no unordered or presorted values are pending,
+    /// i.e. when no `insert_unordered` or `insert_presorted` calls have been
+    /// made since the last `finish()`. Values from `push_ordered` have no key,
+    /// so they can't be merged with either stream; the two modes of
+    /// insertion are exclusive, and switching between them requires a call
+    /// to `finish()`.
+    pub fn push_ordered(&mut self, value: V) {
+        debug_assert!(self.incoming.is_empty() && self.presorted.is_empty());
+        self.outgoing.push_back(value);
+    }
+
+    /// Insert an element from an external stream which is already sorted, for
+    /// example a stream of synthesized records. The caller guarantees that the
+    /// keys passed to this method are non-decreasing, and that `key` is not
+    /// smaller than the key of any value which has already been returned
+    /// from `get_next`.
+    ///
+    /// The value is merged with the values from `insert_unordered`, and is
+    /// returned once the rounds have progressed past `key`. If the values of
+    /// both streams have the same key, the values from `insert_unordered`
+    /// come first.
+    ///
+    /// This can't be combined with `push_ordered`; see there.
+    pub fn insert_presorted(&mut self, key: K, value: V) {
+        debug_assert!(self.presorted.back().is_none_or(|(last, _)| *last <= key));
+        self.presorted.push_back((key, value));
     }
 
     /// Finish the current round. This makes some of the inserted values available
     /// from `get_next`, specifically any values which cannot have their order affected
     /// by values from the next round.
     pub fn finish_round(&mut self) {
-        if let Some(n) = self.incoming_lte_prev_max_count.checked_sub(1) {
+        let ready_count = self.incoming_lte_prev_max_count;
+        if let Some(n) = ready_count.checked_sub(1) {
             let (new_outgoing, _middle, _remaining) = self
                 .incoming
                 .make_contiguous()
-                .select_nth_unstable_by_key(n, |(key, _value)| key.clone());
-            new_outgoing.sort_unstable_by_key(|(key, _value)| key.clone());
-
-            // Move everything <= prev_max from incoming into outgoing.
-            for _ in 0..self.incoming_lte_prev_max_count {
-                let (_key, value) = self.incoming.pop_front().unwrap();
-                self.outgoing.push_back(value);
-            }
+                .select_nth_unstable_by(n, compare_incoming);
+            new_outgoing.sort_unstable_by(compare_incoming);
         }
 
+        // Move everything <= prev_max from incoming into outgoing.
+        let prev_max = self.prev_max.clone();
+        let ready: Vec<_> = self.incoming.drain(..ready_count).collect();
+        self.move_to_outgoing(ready, Some(&prev_max));
+
         self.prev_max = self.cur_max.clone();
         self.incoming_lte_prev_max_count = self.incoming.len();
     }
@@ -116,15 +163,42 @@ impl<K: Ord + Clone + Default, V> Sorter<K, V> {
     pub fn finish(&mut self) {
         self.incoming
             .make_contiguous()
-            .sort_unstable_by_key(|(key, _value)| key.clone());
+            .sort_unstable_by(compare_incoming);
+
+        let ready: Vec<_> = self.incoming.drain(..).collect();
+        self.move_to_outgoing(ready, None);
+        self.prev_max = self.cur_max.clone();
+        self.incoming_lte_prev_max_count = 0;
+    }
 
-        while let Some((_key, value)) = self.incoming.pop_front() {
+    /// Appends the sorted values in `ready` to outgoing, merged with the
+    /// presorted values whose keys are at most `limit`, or with all presorted
+    /// values if `limit` is `None`.
+    fn move_to_outgoing(&mut self, ready: Vec<(K, u64, V)>, limit: Option<&K>) {
+        for (key, _sequence_number, value) in ready {
+            while let Some((presorted_key, _)) = self.presorted.front() {
+                if *presorted_key >= key {
+                    break;
+                }
+                let (_key, presorted_value) = self.presorted.pop_front().unwrap();
+                self.outgoing.push_back(presorted_value);
+            }
             self.outgoing.push_back(value);
         }
-        self.prev_max = self.cur_max.clone();
+        while let Some((presorted_key, _)) = self.presorted.front() {
+            if limit.is_some_and(|limit| presorted_key > limit) {
+                break;
+            }
+            let (_key, presorted_value) = self.presorted.pop_front().unwrap();
+            self.outgoing.push_back(presorted_value);
+        }
     }
 }
 
+fn compare_incoming<K: Ord, V>(a: &(K, u64, V), b: &(K, u64, V)) -> std::cmp::Ordering {
+    (&a.0, a.1).cmp(&(&b.0, b.1))
+}
+
 #[cfg(test)]
 mod test {
     use super::Sorter;
@@ -206,4 +280,25 @@ mod test {
         assert_eq!(sorter.get_next(), Some("10"));
         assert_eq!(sorter.get_next(), None);
     }
+
+    #[test]
+    fn stable_and_presorted() {
+        let mut sorter = Sorter::new();
+        sorter.insert_presorted(1, "p1");
+        sorter.insert_presorted(3, "p3");
+        sorter.insert_presorted(9, "p9");
+        sorter.insert_unordered(3, "a3");
+        sorter.insert_unordered(2, "a2");
+        sorter.insert_unordered(3, "b3");
+        sorter.finish_round();
+        assert_eq!(sorter.get_next(), None);
+        sorter.insert_unordered(5, "a5");
+        sorter.insert_unordered(3, "c3");
+        sorter.finish_round();
+        let values: Vec<_> = std::iter::from_fn(|| sorter.get_next()).collect();
+        assert_eq!(values, vec!["p1", "a2", "a3", "b3", "c3", "p3"]);
+        sorter.finish();
+        let values: Vec<_> = std::iter::from_fn(|| sorter.get_next()).collect();
+        assert_eq!(values, vec!["a5", "p9"]);
+    }
 }
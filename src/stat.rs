@@ -0,0 +1,368 @@
+use byteorder::ByteOrder;
+use linux_perf_event_reader::RawData;
+
+use crate::{
+    CpuMap, Error, EventUpdate, EventUpdateType, PerfFile, ThreadMapOwned, UserRecord,
+    UserRecordType,
+};
+
+const PERF_STAT_CONFIG_TERM_AGGR_MODE: u64 = 0;
+const PERF_STAT_CONFIG_TERM_INTERVAL: u64 = 1;
+const PERF_STAT_CONFIG_TERM_SCALE: u64 = 2;
+const PERF_STAT_CONFIG_TERM_AGGR_LEVEL: u64 = 3;
+
+/// The metadata of a `perf stat record` file, i.e. of a file with the `STAT`
+/// feature: the events which were counted, with their names, units and
+/// scales, and the threads and CPUs they were counted on.
+///
+/// The `STAT` feature section itself is empty. The metadata is in the event
+/// attributes and in the `EVENT_UPDATE`, `THREAD_MAP` and `CPU_MAP` records
+/// which perf writes before the first `STAT` record. Use
+/// [`PerfRecordIter::read_stat_metadata`](crate::PerfRecordIter::read_stat_metadata)
+/// to read them without consuming any records, or create the metadata with
+/// [`StatMetadata::new`] and pass the user records to
+/// [`StatMetadata::apply_user_record`] while iterating.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatMetadata {
+    /// One entry per counted event. These are the event attributes, in
+    /// attribute order, followed by the events which are only described by
+    /// `EVENT_UPDATE` records.
+    pub events: Vec<StatEvent>,
+    /// The threads which were counted, from the `THREAD_MAP` record.
+    pub threads: Option<ThreadMapOwned>,
+    /// The CPUs which were counted, from the `CPU_MAP` record.
+    pub cpus: Option<CpuMap>,
+    /// The options of `perf stat record`, from the `STAT_CONFIG` record.
+    pub config: Option<StatConfig>,
+}
+
+/// A counted event in [`StatMetadata`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatEvent {
+    /// The event name, e.g. "cycles".
+    pub name: Option<String>,
+    /// The event IDs. The `id` of a `STAT` record refers to one of these.
+    pub ids: Vec<u64>,
+    /// The unit of the scaled counter values, e.g. "Joules" or "msec".
+    pub unit: Option<String>,
+    /// The factor to multiply counter values by to get values in `unit`.
+    /// 1.0 if the event has no scale.
+    pub scale: f64,
+    /// The CPUs which this event was counted on, if different from
+    /// [`StatMetadata::cpus`].
+    pub cpus: Option<CpuMap>,
+}
+
+impl StatMetadata {
+    /// Creates the metadata for the event attributes of `perf_file`.
+    pub fn new(perf_file: &PerfFile) -> Self {
+        let events = perf_file
+            .event_attributes()
+            .iter()
+            .map(|attr| StatEvent {
+                name: attr.name().map(ToOwned::to_owned),
+                ids: attr.ids().to_vec(),
+                unit: None,
+                scale: 1.0,
+                cpus: None,
+            })
+            .collect();
+        Self {
+            events,
+            threads: None,
+            cpus: None,
+            config: None,
+        }
+    }
+
+    /// Updates the metadata with the information from an `EVENT_UPDATE`,
+    /// `THREAD_MAP`, `CPU_MAP` or `STAT_CONFIG` record. Other records are
+    /// ignored.
+    pub fn apply_user_record(&mut self, record: &UserRecord) -> Result<(), Error> {
+        match record {
+            UserRecord::EventUpdate(update) => self.apply_event_update(update),
+            UserRecord::ThreadMap(thread_map) => self.threads = Some(thread_map.to_owned_map()?),
+            UserRecord::CpuMap(cpu_map) => self.cpus = Some(cpu_map.clone()),
+            UserRecord::StatConfig(config) => self.config = Some(config.clone()),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Applies the name, unit, scale or CPUs from an `EVENT_UPDATE` record to
+    /// the event with the record's ID. If no event has this ID, a new event
+    /// is added.
+    pub fn apply_event_update(&mut self, update: &EventUpdate) {
+        let index = match self
+            .events
+            .iter()
+            .position(|event| event.ids.contains(&update.id))
+        {
+            Some(index) => index,
+            None => {
+                self.events.push(StatEvent {
+                    name: None,
+                    ids: vec![update.id],
+                    unit: None,
+                    scale: 1.0,
+                    cpus: None,
+                });
+                self.events.len() - 1
+            }
+        };
+        let event = &mut self.events[index];
+        match &update.update {
+            EventUpdateType::Unit(unit) => event.unit = Some(unit.clone()),
+            EventUpdateType::Scale(scale) => event.scale = *scale,
+            EventUpdateType::Name(name) => event.name = Some(name.clone()),
+            EventUpdateType::Cpus(cpus) => event.cpus = Some(cpus.clone()),
+            EventUpdateType::Unknown(_) => {}
+        }
+    }
+
+    /// The event which the event ID of a `STAT` record belongs to.
+    pub fn event_for_id(&self, id: u64) -> Option<&StatEvent> {
+        self.events.iter().find(|event| event.ids.contains(&id))
+    }
+
+    /// Whether records of this type end the metadata at the start of a
+    /// `perf stat record` file.
+    pub(crate) fn is_end_of_metadata(record_type: UserRecordType) -> bool {
+        record_type == UserRecordType::PERF_STAT || record_type == UserRecordType::PERF_STAT_ROUND
+    }
+}
+
+/// The options of `perf stat record`, from a `PERF_RECORD_STAT_CONFIG` record.
+///
+/// Each field is `None` if the record doesn't have an entry for it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatConfig {
+    /// How the counter values are aggregated, e.g. per socket for
+    /// `perf stat --per-socket`.
+    pub aggr_mode: Option<AggregationMode>,
+    /// The interval between `STAT_ROUND` records in milliseconds, for
+    /// `perf stat -I`, or 0 if only the final values were recorded.
+    pub interval_ms: Option<u64>,
+    /// Whether the counter values should be scaled by `enabled / running`
+    /// if the event was multiplexed.
+    pub scale: Option<bool>,
+    /// The cache level for [`AggregationMode::CACHE`], from
+    /// `perf stat --per-cache=L<n>`.
+    pub aggr_level: Option<u64>,
+    /// The entries with tags which this crate doesn't know about, as
+    /// `(tag, value)` pairs.
+    pub unknown_entries: Vec<(u64, u64)>,
+}
+
+/// The aggregation mode of `perf stat`, `enum aggr_mode` in perf.
+///
+/// Only the first few values are stable: perf has inserted new modes in the
+/// middle of the enum over time, so the numbering of the other modes depends
+/// on the perf version that wrote the file. The constants are the values
+/// used by current perf versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AggregationMode(pub u64);
+
+impl AggregationMode {
+    /// No aggregation, one value per CPU (`perf stat -A`).
+    pub const NONE: Self = Self(0);
+    /// One value for the whole system.
+    pub const GLOBAL: Self = Self(1);
+    /// One value per socket (`--per-socket`).
+    pub const SOCKET: Self = Self(2);
+    /// One value per die (`--per-die`).
+    pub const DIE: Self = Self(3);
+    /// One value per CPU cluster (`--per-cluster`).
+    pub const CLUSTER: Self = Self(4);
+    /// One value per cache instance (`--per-cache`).
+    pub const CACHE: Self = Self(5);
+    /// One value per core (`--per-core`).
+    pub const CORE: Self = Self(6);
+    /// One value per thread (`--per-thread`).
+    pub const THREAD: Self = Self(7);
+    /// One value per NUMA node (`--per-node`).
+    pub const NODE: Self = Self(9);
+}
+
+impl StatConfig {
+    pub fn parse<T: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        // struct perf_record_stat_config {
+        //     struct perf_event_header header;
+        //     __u64 nr;
+        //     struct perf_record_stat_config_entry {
+        //         __u64 tag;
+        //         __u64 val;
+        //     } data[];
+        // };
+        let nr = data.read_u64::<T>()?;
+        let mut config = Self::default();
+        for _ in 0..nr {
+            let tag = data.read_u64::<T>()?;
+            let value = data.read_u64::<T>()?;
+            match tag {
+                PERF_STAT_CONFIG_TERM_AGGR_MODE => config.aggr_mode = Some(AggregationMode(value)),
+                PERF_STAT_CONFIG_TERM_INTERVAL => config.interval_ms = Some(value),
+                PERF_STAT_CONFIG_TERM_SCALE => config.scale = Some(value != 0),
+                PERF_STAT_CONFIG_TERM_AGGR_LEVEL => config.aggr_level = Some(value),
+                _ => config.unknown_entries.push((tag, value)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// A counter value from a `PERF_RECORD_STAT` record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stat {
+    /// The event ID. Use [`StatMetadata::event_for_id`] to find the event.
+    pub id: u64,
+    /// The index of the CPU in the CPU map, not the CPU number.
+    pub cpu: u32,
+    /// The index of the thread in the thread map, not the tid.
+    pub thread: u32,
+    /// The counter value.
+    pub value: u64,
+    /// The time in nanoseconds during which the event was enabled.
+    pub enabled: u64,
+    /// The time in nanoseconds during which the event was running, i.e.
+    /// scheduled on the PMU. This is less than `enabled` if the event was
+    /// multiplexed.
+    pub running: u64,
+}
+
+impl Stat {
+    pub fn parse<T: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        // struct perf_record_stat {
+        //     struct perf_event_header header;
+        //     __u64 id;
+        //     __u32 cpu;
+        //     __u32 thread;
+        //     __u64 val;
+        //     __u64 ena;
+        //     __u64 run;
+        // };
+        Ok(Self {
+            id: data.read_u64::<T>()?,
+            cpu: data.read_u32::<T>()?,
+            thread: data.read_u32::<T>()?,
+            value: data.read_u64::<T>()?,
+            enabled: data.read_u64::<T>()?,
+            running: data.read_u64::<T>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use linux_perf_event_reader::{Endianness, RecordType, SoftwareCounterType};
+
+    use super::{AggregationMode, Stat};
+    use crate::constants::{PERF_RECORD_EVENT_UPDATE, PERF_RECORD_STAT, PERF_RECORD_STAT_CONFIG};
+    use crate::file_writer::test::test_attr;
+    use crate::{Feature, PerfFileReader, PerfFileRecord, PerfFileWriter, UserRecord};
+
+    fn event_update(type_: u64, id: u64, data: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&type_.to_le_bytes());
+        body.extend_from_slice(&id.to_le_bytes());
+        body.extend_from_slice(data);
+        body
+    }
+
+    #[test]
+    fn read_stat_metadata() {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(
+                &test_attr(SoftwareCounterType::TaskClock),
+                "task-clock",
+                &[1],
+            )
+            .unwrap();
+        writer.add_feature(Feature::STAT, Vec::new());
+        // nr = 2; AGGR_MODE = GLOBAL, INTERVAL = 100.
+        let config: Vec<u8> = [2u64, 0, 1, 1, 100]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        writer
+            .write_record(RecordType(PERF_RECORD_STAT_CONFIG), 0, &config)
+            .unwrap();
+        let event_update_type = RecordType(PERF_RECORD_EVENT_UPDATE);
+        writer
+            .write_record(event_update_type, 0, &event_update(0, 1, b"msec\0\0\0\0"))
+            .unwrap();
+        writer
+            .write_record(
+                event_update_type,
+                0,
+                &event_update(1, 1, &1e-6f64.to_bits().to_le_bytes()),
+            )
+            .unwrap();
+        writer
+            .write_record(event_update_type, 0, &event_update(2, 2, b"cycles\0\0"))
+            .unwrap();
+        // A STAT record: id, cpu and thread, val, ena, run. Updates after it
+        // are not part of the metadata.
+        let stat: Vec<u8> = [1u64, 3 << 32, 1500, 2000, 1000]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        writer
+            .write_record(RecordType(PERF_RECORD_STAT), 0, &stat)
+            .unwrap();
+        writer
+            .write_record(event_update_type, 0, &event_update(2, 1, b"other\0\0\0"))
+            .unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&data[..])).unwrap();
+        let metadata = record_iter.read_stat_metadata(&perf_file).unwrap();
+        assert_eq!(metadata.events.len(), 2);
+        let config = metadata.config.as_ref().unwrap();
+        assert_eq!(config.aggr_mode, Some(AggregationMode::GLOBAL));
+        assert_eq!(config.interval_ms, Some(100));
+        assert_eq!(config.scale, None);
+        let task_clock = metadata.event_for_id(1).unwrap();
+        assert_eq!(task_clock.name.as_deref(), Some("task-clock"));
+        assert_eq!(task_clock.unit.as_deref(), Some("msec"));
+        assert_eq!(task_clock.scale, 1e-6);
+        let cycles = metadata.event_for_id(2).unwrap();
+        assert_eq!(cycles.name.as_deref(), Some("cycles"));
+        assert_eq!(cycles.scale, 1.0);
+
+        // Reading the metadata doesn't consume any records.
+        let Some(PerfFileRecord::UserRecord(record)) =
+            record_iter.next_record(&mut perf_file).unwrap()
+        else {
+            panic!("expected a user record");
+        };
+        assert!(matches!(record.parse().unwrap(), UserRecord::StatConfig(_)));
+
+        let mut stats = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            if let PerfFileRecord::UserRecord(record) = record {
+                if let UserRecord::Stat(stat) = record.parse().unwrap() {
+                    stats.push(stat);
+                }
+            }
+        }
+        assert_eq!(
+            stats,
+            vec![Stat {
+                id: 1,
+                cpu: 0,
+                thread: 3,
+                value: 1500,
+                enabled: 2000,
+                running: 1000,
+            }]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::Error;
+
+/// The index of a string in a [`StringTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StringId(u32);
+
+impl StringId {
+    /// The index of the string in [`StringTable::strings`].
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Interns byte strings, such as DSO paths, thread names and symbol names,
+/// so that each distinct string is only stored once.
+///
+/// System-wide captures can contain tens of thousands of mappings and
+/// threads, but usually only a few distinct paths and names. Storing a
+/// [`StringId`] instead of the string keeps the memory use proportional to
+/// the number of distinct strings. The ids are assigned in insertion order,
+/// so the table can be written out as a plain list, e.g. as the string table
+/// of a profile format.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StringTable {
+    strings: Vec<Arc<[u8]>>,
+    ids: HashMap<Arc<[u8]>, StringId>,
+}
+
+impl StringTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id of `s`, adding it to the table if it isn't in the table
+    /// yet. Returns [`Error::TooManyStrings`] if the table already contains
+    /// `u32::MAX + 1` strings.
+    pub fn intern(&mut self, s: &[u8]) -> Result<StringId, Error> {
+        if let Some(id) = self.ids.get(s) {
+            return Ok(*id);
+        }
+        let id = StringId(u32::try_from(self.strings.len()).map_err(|_| Error::TooManyStrings)?);
+        let s: Arc<[u8]> = Arc::from(s);
+        self.strings.push(s.clone());
+        self.ids.insert(s, id);
+        Ok(id)
+    }
+
+    /// Returns the id of `s`, if it is in the table.
+    pub fn id(&self, s: &[u8]) -> Option<StringId> {
+        self.ids.get(s).copied()
+    }
+
+    /// Returns the string for an id which was returned by this table.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is from a different table with more strings.
+    pub fn get(&self, id: StringId) -> &[u8] {
+        &self.strings[id.index()]
+    }
+
+    /// Returns the string as a `&str`, if it is valid UTF-8.
+    pub fn get_str(&self, id: StringId) -> Option<&str> {
+        std::str::from_utf8(self.get(id)).ok()
+    }
+
+    /// All strings, indexed by [`StringId::index`].
+    pub fn strings(&self) -> impl ExactSizeIterator<Item = &[u8]> + '_ {
+        self.strings.iter().map(|s| &s[..])
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StringTable;
+
+    #[test]
+    fn intern() {
+        let mut table = StringTable::new();
+        let libc = table.intern(b"/usr/lib/libc.so.6").unwrap();
+        let kernel = table.intern(b"[kernel.kallsyms]").unwrap();
+        assert_eq!(table.intern(b"/usr/lib/libc.so.6").unwrap(), libc);
+        assert_ne!(libc, kernel);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(kernel), b"[kernel.kallsyms]");
+        assert_eq!(table.get_str(libc), Some("/usr/lib/libc.so.6"));
+        assert_eq!(table.id(b"[vdso]"), None);
+        let strings: Vec<_> = table.strings().collect();
+        assert_eq!(strings[libc.index()], b"/usr/lib/libc.so.6");
+    }
+}
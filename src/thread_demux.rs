@@ -0,0 +1,339 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use linux_perf_event_reader::{EventRecord, RecordType};
+
+use crate::{Error, OwnedPerfFileRecord, PerfFile, PerfFileRecord, PerfRecordIter};
+
+/// Splits the sorted record stream of a file into one stream per thread,
+/// keyed by `(pid, tid)`, for analyses which process each thread on its own.
+///
+/// [`ThreadDemux::next_record`] reads the next record from a
+/// [`PerfRecordIter`] and returns it together with its thread and with the
+/// state which the analysis keeps for that thread, of type `S`. The state is
+/// created with `S::default()` when the first record of a thread is read.
+/// The records of each thread are returned in order, i.e. sorted by time,
+/// and nothing but the per-thread states is kept in memory. See
+/// [`record_thread`] for which thread a record belongs to.
+///
+/// The state of a thread is kept until it is removed with
+/// [`ThreadDemux::remove_thread`], e.g. after its `EXIT` record.
+///
+/// ```
+/// use linux_perf_data::{PerfFileReader, ThreadDemux};
+///
+/// # fn wrapper() -> Result<(), linux_perf_data::Error> {
+/// let file = std::fs::File::open("perf.data")?;
+/// let reader = std::io::BufReader::new(file);
+/// let PerfFileReader { mut perf_file, mut record_iter } = PerfFileReader::parse_file(reader)?;
+/// // Count the records of each thread.
+/// let mut demux = ThreadDemux::<u64>::new();
+/// while let Some(thread_record) = demux.next_record(&mut record_iter, &mut perf_file)? {
+///     if let Some((_thread, count)) = thread_record.thread {
+///         *count += 1;
+///     }
+/// }
+/// for ((pid, tid), count) in demux.threads() {
+///     println!("{pid}/{tid}: {count} records");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ThreadDemux<S> {
+    threads: BTreeMap<(i32, i32), S>,
+}
+
+/// A record returned by [`ThreadDemux::next_record`].
+pub struct ThreadRecord<'a, 's, S> {
+    /// The `(pid, tid)` of the thread which the record belongs to, and the
+    /// state of that thread. `None` for records without a thread, e.g. user
+    /// records.
+    pub thread: Option<((i32, i32), &'s mut S)>,
+    pub record: PerfFileRecord<'a>,
+}
+
+impl<S: Default> ThreadDemux<S> {
+    pub fn new() -> Self {
+        Self {
+            threads: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the next record from `record_iter`, and returns it with its
+    /// thread and the state of that thread. Returns `None` once all records
+    /// have been read.
+    pub fn next_record<'a, 's, R: Read>(
+        &'s mut self,
+        record_iter: &'a mut PerfRecordIter<R>,
+        perf_file: &mut PerfFile,
+    ) -> Result<Option<ThreadRecord<'a, 's, S>>, Error> {
+        let Some(record) = record_iter.next_record(perf_file)? else {
+            return Ok(None);
+        };
+        let thread =
+            record_thread(&record).map(|thread| (thread, self.threads.entry(thread).or_default()));
+        Ok(Some(ThreadRecord { thread, record }))
+    }
+}
+
+impl<S: Default> Default for ThreadDemux<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> ThreadDemux<S> {
+    /// The `(pid, tid)` and the state of all threads which have a state,
+    /// sorted by `(pid, tid)`.
+    pub fn threads(&self) -> impl Iterator<Item = ((i32, i32), &S)> + '_ {
+        self.threads.iter().map(|(thread, state)| (*thread, state))
+    }
+
+    /// The state of the thread `tid` of the process `pid`, if any of its
+    /// records have been read and it hasn't been removed.
+    pub fn thread_state(&self, pid: i32, tid: i32) -> Option<&S> {
+        self.threads.get(&(pid, tid))
+    }
+
+    /// Like [`ThreadDemux::thread_state`], but returns a mutable reference.
+    pub fn thread_state_mut(&mut self, pid: i32, tid: i32) -> Option<&mut S> {
+        self.threads.get_mut(&(pid, tid))
+    }
+
+    /// Removes the state of a thread and returns it. If more records of the
+    /// thread are read afterwards, a new state is created for them.
+    pub fn remove_thread(&mut self, pid: i32, tid: i32) -> Option<S> {
+        self.threads.remove(&(pid, tid))
+    }
+
+    /// Returns the states of all threads, keyed by `(pid, tid)`.
+    pub fn into_thread_states(self) -> BTreeMap<(i32, i32), S> {
+        self.threads
+    }
+}
+
+/// Collects all records of a file, grouped by thread.
+///
+/// This is a convenience for small files: all records are kept in memory
+/// as [`OwnedPerfFileRecord`]s. For large files, use [`ThreadDemux`], which
+/// processes the records of each thread while they are read.
+///
+/// Feed it with the records from
+/// [`PerfRecordIter::next_owned_record`], or use [`ThreadRecords::read_all`].
+/// The records of each thread stay in the order in which they were pushed,
+/// i.e. sorted by time. See [`record_thread`] for which thread a record
+/// belongs to; records without a thread, e.g. user records, are kept
+/// separately.
+#[derive(Debug, Clone, Default)]
+pub struct ThreadRecords {
+    threads: BTreeMap<(i32, i32), Vec<OwnedPerfFileRecord>>,
+    unattributed: Vec<OwnedPerfFileRecord>,
+}
+
+impl ThreadRecords {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads all remaining records from `record_iter` and splits them up by
+    /// thread.
+    pub fn read_all<R: Read>(
+        record_iter: &mut PerfRecordIter<R>,
+        perf_file: &mut PerfFile,
+    ) -> Result<Self, Error> {
+        let mut records = Self::new();
+        while let Some(record) = record_iter.next_owned_record(perf_file)? {
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// Appends `record` to the stream of its thread, and returns the thread.
+    /// Returns `None` if the record doesn't belong to a thread.
+    pub fn push(&mut self, record: OwnedPerfFileRecord) -> Option<(i32, i32)> {
+        match record_thread(&record.as_record()) {
+            Some(thread) => {
+                self.threads.entry(thread).or_default().push(record);
+                Some(thread)
+            }
+            None => {
+                self.unattributed.push(record);
+                None
+            }
+        }
+    }
+
+    /// The `(pid, tid)` of all threads with records, sorted.
+    pub fn threads(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.threads.keys().copied()
+    }
+
+    /// The records of the thread `tid` of the process `pid`, in order.
+    pub fn thread_records(&self, pid: i32, tid: i32) -> &[OwnedPerfFileRecord] {
+        self.threads
+            .get(&(pid, tid))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The records which don't belong to a thread, in order.
+    pub fn unattributed_records(&self) -> &[OwnedPerfFileRecord] {
+        &self.unattributed
+    }
+
+    /// Removes the records of a thread and returns them, e.g. to process the
+    /// threads one at a time without keeping all records alive.
+    pub fn take_thread(&mut self, pid: i32, tid: i32) -> Vec<OwnedPerfFileRecord> {
+        self.threads.remove(&(pid, tid)).unwrap_or_default()
+    }
+
+    /// Removes the records which don't belong to a thread and returns them.
+    pub fn take_unattributed(&mut self) -> Vec<OwnedPerfFileRecord> {
+        std::mem::take(&mut self.unattributed)
+    }
+
+    /// Returns the records of all threads, keyed by `(pid, tid)`. The records
+    /// which don't belong to a thread are dropped, see
+    /// [`ThreadRecords::take_unattributed`].
+    pub fn into_threads(self) -> BTreeMap<(i32, i32), Vec<OwnedPerfFileRecord>> {
+        self.threads
+    }
+}
+
+/// The `(pid, tid)` of the thread which a record belongs to.
+///
+/// For `COMM`, `FORK` and `EXIT` records, this is the thread in the record
+/// body, i.e. the new thread for `FORK` records. For other event records,
+/// it's the sample's `pid` and `tid`, which non-sample records only have if
+/// the event has `sample_id_all`. User records don't belong to a thread.
+pub fn record_thread(record: &PerfFileRecord) -> Option<(i32, i32)> {
+    let PerfFileRecord::EventRecord { record, .. } = record else {
+        return None;
+    };
+    if matches!(
+        record.record_type,
+        RecordType::COMM | RecordType::FORK | RecordType::EXIT
+    ) {
+        match record.parse().ok()? {
+            EventRecord::Comm(comm) => return Some((comm.pid, comm.tid)),
+            EventRecord::Fork(task) | EventRecord::Exit(task) => return Some((task.pid, task.tid)),
+            _ => {}
+        }
+    }
+    let common = record.common_data().ok()?;
+    Some((common.pid?, common.tid?))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use linux_perf_event_reader::{Endianness, RecordType, SoftwareCounterType};
+
+    use super::{ThreadDemux, ThreadRecords};
+    use crate::file_writer::test::{sample_body, test_attr};
+    use crate::{PerfFileReader, PerfFileRecord, PerfFileWriter};
+
+    /// A file with a COMM record for thread 11 of process 10, written by
+    /// thread 10, and samples of the threads 10/10 and 20/20.
+    fn build_file() -> Vec<u8> {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        let mut comm = Vec::new();
+        comm.write_i32::<LittleEndian>(10).unwrap();
+        comm.write_i32::<LittleEndian>(11).unwrap();
+        comm.extend_from_slice(b"worker\0\0");
+        comm.write_u32::<LittleEndian>(10).unwrap();
+        comm.write_u32::<LittleEndian>(10).unwrap();
+        comm.write_u64::<LittleEndian>(150).unwrap();
+        comm.write_u64::<LittleEndian>(1).unwrap();
+        writer.write_record(RecordType::COMM, 0, &comm).unwrap();
+        for (pid, time) in [(10, 100), (20, 200), (10, 300)] {
+            writer
+                .write_record(RecordType::SAMPLE, 2, &sample_body(1, pid, time))
+                .unwrap();
+        }
+        writer.write_finished_round().unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn demux_threads() {
+        let file = build_file();
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        // Collect the record types and timestamps of each thread.
+        let mut demux = ThreadDemux::<Vec<(RecordType, Option<u64>)>>::new();
+        while let Some(thread_record) = demux.next_record(&mut record_iter, &mut perf_file).unwrap()
+        {
+            let PerfFileRecord::EventRecord { record, .. } = thread_record.record else {
+                panic!("unexpected user record");
+            };
+            let (_, records) = thread_record.thread.unwrap();
+            records.push((record.record_type, record.timestamp()));
+        }
+        assert_eq!(
+            demux
+                .threads()
+                .map(|(thread, _)| thread)
+                .collect::<Vec<_>>(),
+            vec![(10, 10), (10, 11), (20, 20)]
+        );
+        assert_eq!(
+            demux.thread_state(10, 10).unwrap(),
+            &[
+                (RecordType::SAMPLE, Some(100)),
+                (RecordType::SAMPLE, Some(300))
+            ]
+        );
+        assert_eq!(
+            demux.thread_state(10, 11).unwrap(),
+            &[(RecordType::COMM, Some(150))]
+        );
+        assert!(demux.thread_state(30, 30).is_none());
+        assert_eq!(demux.remove_thread(20, 20).unwrap().len(), 1);
+        let states = demux.into_thread_states();
+        assert_eq!(states.keys().collect::<Vec<_>>(), [&(10, 10), &(10, 11)]);
+    }
+
+    #[test]
+    fn collect_thread_records() {
+        let file = build_file();
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        let mut records = ThreadRecords::read_all(&mut record_iter, &mut perf_file).unwrap();
+        assert_eq!(
+            records.threads().collect::<Vec<_>>(),
+            vec![(10, 10), (10, 11), (20, 20)]
+        );
+        let times: Vec<_> = records
+            .thread_records(10, 10)
+            .iter()
+            .map(|record| match record.as_record() {
+                PerfFileRecord::EventRecord { record, .. } => record.timestamp(),
+                PerfFileRecord::UserRecord(_) => None,
+            })
+            .collect();
+        assert_eq!(times, vec![Some(100), Some(300)]);
+        assert_eq!(
+            records.thread_records(10, 11)[0].record_type,
+            RecordType::COMM
+        );
+        assert!(records.thread_records(30, 30).is_empty());
+        assert!(records.unattributed_records().is_empty());
+
+        assert_eq!(records.take_thread(20, 20).len(), 1);
+        assert!(records.take_unattributed().is_empty());
+        let threads = records.into_threads();
+        assert_eq!(threads.keys().collect::<Vec<_>>(), [&(10, 10), &(10, 11)]);
+    }
+}
@@ -1,8 +1,11 @@
+use std::collections::BTreeMap;
 use std::fmt;
 
 use byteorder::{ByteOrder, NativeEndian};
 
-use linux_perf_event_reader::{is_swapped_endian, RawData};
+use linux_perf_event_reader::{is_swapped_endian, CommOrExecRecord, RawData};
+
+use crate::{Error, StringId, StringTable};
 
 /// A list of threads, usually without names.
 ///
@@ -51,6 +54,108 @@ impl<'a> ThreadMap<'a> {
             data: self.data,
         }
     }
+
+    /// Like [`ThreadMap::iter`], but returns the entries as results, so that
+    /// malformed entries are reported as errors.
+    pub fn try_iter(&self) -> ThreadMapTryIter<'a> {
+        ThreadMapTryIter {
+            swap_endian: self.swap_endian,
+            index: 0,
+            len: self.len(),
+            data: self.data,
+        }
+    }
+
+    /// Copies the thread map into a [`ThreadMapOwned`], which can be enriched
+    /// with the thread names from COMM records.
+    pub fn to_owned_map(&self) -> Result<ThreadMapOwned, Error> {
+        let mut owned = ThreadMapOwned::new();
+        for entry in self.try_iter() {
+            let entry = entry?;
+            owned.set_name(entry.tid, &entry.name.as_slice())?;
+        }
+        Ok(owned)
+    }
+}
+
+/// An owned table from tid to thread name.
+///
+/// Create it from a [`ThreadMap`] with [`ThreadMap::to_owned_map`], and then
+/// call [`ThreadMapOwned::add_comm`] for the COMM records in the file, to fill
+/// in the names which the thread map is usually missing. Threads which only
+/// appear in COMM records are added to the table.
+///
+/// The names are interned in a [`StringTable`], because many threads share
+/// the same name, e.g. the worker threads of a thread pool.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadMapOwned {
+    threads: BTreeMap<u64, StringId>,
+    names: StringTable,
+}
+
+impl ThreadMapOwned {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.threads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    pub fn contains(&self, tid: u64) -> bool {
+        self.threads.contains_key(&tid)
+    }
+
+    /// The name of the thread, or `None` if the thread is unknown or has no
+    /// name.
+    pub fn name(&self, tid: u64) -> Option<&[u8]> {
+        self.name_id(tid)
+            .map(|id| self.names.get(id))
+            .filter(|name| !name.is_empty())
+    }
+
+    /// The id of the thread's name in [`ThreadMapOwned::names`], or `None` if
+    /// the thread is unknown.
+    pub fn name_id(&self, tid: u64) -> Option<StringId> {
+        self.threads.get(&tid).copied()
+    }
+
+    /// The table of all thread names.
+    pub fn names(&self) -> &StringTable {
+        &self.names
+    }
+
+    /// Sets the name of a thread, adding the thread if it isn't in the table
+    /// yet. An empty name doesn't replace an existing name.
+    pub fn set_name(&mut self, tid: u64, name: &[u8]) -> Result<(), Error> {
+        if name.is_empty() && self.threads.contains_key(&tid) {
+            return Ok(());
+        }
+        let id = self.names.intern(name)?;
+        self.threads.insert(tid, id);
+        Ok(())
+    }
+
+    /// Takes the thread name from a COMM record. Call this in file order, so
+    /// that the name after the last `exec` or `prctl(PR_SET_NAME)` wins.
+    pub fn add_comm(&mut self, comm: &CommOrExecRecord) -> Result<(), Error> {
+        match u64::try_from(comm.tid) {
+            Ok(tid) => self.set_name(tid, &comm.name.as_slice()),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Iterates over the threads and their names, ordered by tid. The name
+    /// is empty for threads without a name.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &[u8])> + '_ {
+        self.threads
+            .iter()
+            .map(|(tid, id)| (*tid, self.names.get(*id)))
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -109,10 +214,53 @@ impl<'a> Iterator for ThreadMapIter<'a> {
     }
 }
 
+/// Like [`ThreadMapIter`], but returns an error instead of panicking if an
+/// entry can't be read. After an error, the iteration ends.
+pub struct ThreadMapTryIter<'a> {
+    swap_endian: bool,
+    data: RawData<'a>,
+    index: usize,
+    len: usize,
+}
+
+impl<'a> ThreadMapTryIter<'a> {
+    fn read_entry(&mut self) -> Result<ThreadMapEntry<'a>, std::io::Error> {
+        let mut tid = self.data.read_u64::<NativeEndian>()?;
+        if self.swap_endian {
+            tid = tid.swap_bytes();
+        }
+        let mut name = self.data.split_off_prefix(16)?;
+        let name = name.read_string().unwrap_or(name);
+        Ok(ThreadMapEntry { tid, name })
+    }
+}
+
+impl<'a> Iterator for ThreadMapTryIter<'a> {
+    type Item = Result<ThreadMapEntry<'a>, std::io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.len {
+            return None;
+        }
+
+        let entry = self.read_entry();
+        self.index = if entry.is_ok() {
+            self.index + 1
+        } else {
+            self.len
+        };
+        Some(entry)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.len))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use byteorder::LittleEndian;
-    use linux_perf_event_reader::RawData;
+    use linux_perf_event_reader::{CommOrExecRecord, RawData};
 
     use super::ThreadMap;
 
@@ -149,7 +297,55 @@ mod test {
         assert_eq!(map.len(), 12);
         let vec: Vec<_> = map.iter().collect();
         assert_eq!(vec.len(), 12);
+        let entries: Vec<_> = map.try_iter().collect::<Result<_, _>>().unwrap();
+        assert_eq!(entries, vec);
         assert_eq!(vec[8].tid, 95167);
         assert_eq!(&vec[8].name.as_slice()[..], b"");
     }
+
+    #[test]
+    fn enrich_with_comm() {
+        let data = RawData::Single(&[
+            1, 0, 0, 0, 0, 0, 0, 0, 108, 71, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0,
+        ]);
+        let map = ThreadMap::parse::<LittleEndian>(data).unwrap();
+        let mut owned = map.to_owned_map().unwrap();
+        assert_eq!(owned.len(), 1);
+        assert_eq!(owned.name(542572), None);
+
+        owned
+            .add_comm(&CommOrExecRecord {
+                pid: 542572,
+                tid: 542572,
+                name: RawData::Single(b"perf-exec"),
+                is_execve: false,
+            })
+            .unwrap();
+        owned
+            .add_comm(&CommOrExecRecord {
+                pid: 542572,
+                tid: 542572,
+                name: RawData::Single(b"firefox"),
+                is_execve: true,
+            })
+            .unwrap();
+        owned
+            .add_comm(&CommOrExecRecord {
+                pid: 542572,
+                tid: 542580,
+                name: RawData::Single(b"Renderer"),
+                is_execve: false,
+            })
+            .unwrap();
+        assert_eq!(owned.name(542572), Some(&b"firefox"[..]));
+        let threads: Vec<_> = owned.iter().collect();
+        assert_eq!(
+            threads,
+            vec![(542572, &b"firefox"[..]), (542580, &b"Renderer"[..])]
+        );
+
+        owned.set_name(542581, b"Renderer").unwrap();
+        assert_eq!(owned.name_id(542581), owned.name_id(542580));
+    }
 }
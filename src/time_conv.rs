@@ -0,0 +1,155 @@
+use byteorder::ByteOrder;
+use linux_perf_event_reader::RawData;
+
+/// The parameters for converting TSC values to perf timestamps, from a
+/// `PERF_RECORD_TIME_CONV` record.
+///
+/// `perf` writes this record when recording with Intel PT or other aux
+/// tracing, whose packets contain raw TSC values instead of perf clock
+/// timestamps. The values are copied from the kernel's
+/// `perf_event_mmap_page`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeConv {
+    pub time_shift: u64,
+    pub time_mult: u64,
+    pub time_zero: u64,
+    /// The reference TSC value for `time_mask`. Only used if
+    /// `cap_user_time_short` is set.
+    pub time_cycles: u64,
+    /// The mask of the TSC bits which are valid, if the TSC is shorter than
+    /// 64 bits. Only used if `cap_user_time_short` is set.
+    pub time_mask: u64,
+    /// Whether `time_zero` is valid.
+    pub cap_user_time_zero: bool,
+    /// Whether the TSC is shorter than 64 bits, see `time_mask`.
+    pub cap_user_time_short: bool,
+}
+
+impl TimeConv {
+    pub fn parse<T: ByteOrder>(mut data: RawData) -> Result<Self, std::io::Error> {
+        // struct perf_record_time_conv {
+        //     struct perf_event_header header;
+        //     __u64 time_shift;
+        //     __u64 time_mult;
+        //     __u64 time_zero;
+        //     __u64 time_cycles;
+        //     __u64 time_mask;
+        //     __u8 cap_user_time_zero;
+        //     __u8 cap_user_time_short;
+        //     __u8 reserved[6];
+        // };
+        let time_shift = data.read_u64::<T>()?;
+        let time_mult = data.read_u64::<T>()?;
+        let time_zero = data.read_u64::<T>()?;
+        if data.len() < 18 {
+            // Written by perf before 5.10, without the fields for short TSCs.
+            return Ok(Self {
+                time_shift,
+                time_mult,
+                time_zero,
+                time_cycles: 0,
+                time_mask: 0,
+                cap_user_time_zero: time_mult != 0,
+                cap_user_time_short: false,
+            });
+        }
+        let time_cycles = data.read_u64::<T>()?;
+        let time_mask = data.read_u64::<T>()?;
+        let mut caps = [0; 2];
+        data.read_exact(&mut caps)?;
+        Ok(Self {
+            time_shift,
+            time_mult,
+            time_zero,
+            time_cycles,
+            time_mask,
+            cap_user_time_zero: caps[0] != 0,
+            cap_user_time_short: caps[1] != 0,
+        })
+    }
+
+    /// Converts a raw TSC value, e.g. from an Intel PT packet, into a
+    /// timestamp in the perf clock, in nanoseconds.
+    pub fn tsc_to_perf_time(&self, tsc: u64) -> u64 {
+        let tsc = if self.cap_user_time_short {
+            self.time_cycles
+                .wrapping_add(tsc.wrapping_sub(self.time_cycles) & self.time_mask)
+        } else {
+            tsc
+        };
+        let shift = self.time_shift as u32;
+        let quot = tsc.checked_shr(shift).unwrap_or(0);
+        let rem = tsc & 1u64.checked_shl(shift).unwrap_or(0).wrapping_sub(1);
+        self.time_zero
+            .wrapping_add(quot.wrapping_mul(self.time_mult))
+            .wrapping_add(
+                rem.wrapping_mul(self.time_mult)
+                    .checked_shr(shift)
+                    .unwrap_or(0),
+            )
+    }
+
+    /// Converts a timestamp in the perf clock into a TSC value. This is the
+    /// inverse of [`TimeConv::tsc_to_perf_time`], up to rounding.
+    ///
+    /// Returns `None` if `time_mult` is zero.
+    pub fn perf_time_to_tsc(&self, ns: u64) -> Option<u64> {
+        if self.time_mult == 0 {
+            return None;
+        }
+        let shift = self.time_shift as u32;
+        let t = ns.wrapping_sub(self.time_zero);
+        let quot = t / self.time_mult;
+        let rem = t % self.time_mult;
+        Some(
+            quot.checked_shl(shift)
+                .unwrap_or(0)
+                .wrapping_add(rem.checked_shl(shift).unwrap_or(0) / self.time_mult),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::LittleEndian;
+    use linux_perf_event_reader::RawData;
+
+    use super::TimeConv;
+
+    fn encode(values: &[u64]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn convert() {
+        // A 2.5 GHz TSC: 0.4 ns per cycle, i.e. time_mult / 2^time_shift = 0.4.
+        let mut data = encode(&[10, 410, 1000, 0, 0]);
+        data.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        let time_conv = TimeConv::parse::<LittleEndian>(RawData::from(&data[..])).unwrap();
+        assert!(time_conv.cap_user_time_zero);
+        assert!(!time_conv.cap_user_time_short);
+        assert_eq!(time_conv.tsc_to_perf_time(0), 1000);
+        assert_eq!(time_conv.tsc_to_perf_time(1024 * 1000), 1000 + 410 * 1000);
+        assert_eq!(
+            time_conv.perf_time_to_tsc(1000 + 410 * 1000),
+            Some(1024 * 1000)
+        );
+
+        // Records from older perf versions only have the first three fields.
+        let data = encode(&[10, 410, 1000]);
+        let old = TimeConv::parse::<LittleEndian>(RawData::from(&data[..])).unwrap();
+        assert_eq!(old.tsc_to_perf_time(2048), time_conv.tsc_to_perf_time(2048));
+    }
+
+    #[test]
+    fn short_tsc() {
+        // A 32-bit TSC which wrapped around after time_cycles.
+        let mut data = encode(&[0, 1, 0, 0x1_ffff_fff0, 0xffff_ffff]);
+        data.extend_from_slice(&[1, 1, 0, 0, 0, 0, 0, 0]);
+        let time_conv = TimeConv::parse::<LittleEndian>(RawData::from(&data[..])).unwrap();
+        assert_eq!(time_conv.tsc_to_perf_time(0x10), 0x2_0000_0010);
+    }
+}
@@ -0,0 +1,682 @@
+use std::collections::HashMap;
+
+use byteorder::{BigEndian, LittleEndian};
+use linux_perf_event_reader::{Endianness, PerfEventAttr, PerfEventType, RawData};
+
+use super::{EventFormat, FieldValue, FormatField, PrintFmt, TracepointError, TracingData};
+
+/// Decodes the raw data of tracepoint samples, using the formats of the
+/// recorded tracepoint events.
+///
+/// Create one with [`PerfFile::tracepoint_decoder`](crate::PerfFile::tracepoint_decoder),
+/// which uses the formats from the `TRACING_DATA` section, or with
+/// [`TracepointDecoder::new`].
+#[derive(Debug, Clone)]
+pub struct TracepointDecoder {
+    formats: HashMap<u64, EventFormat>,
+    /// The parsed print fmts, for the formats whose print fmt could be
+    /// parsed.
+    print_fmts: HashMap<u64, PrintFmt>,
+    endian: Endianness,
+    long_size: usize,
+}
+
+impl TracepointDecoder {
+    /// Creates a decoder for raw data in the byte order `endian`, which is
+    /// the byte order of the machine on which the samples were recorded.
+    /// `long` is assumed to have 8 bytes, see [`with_long_size`](Self::with_long_size).
+    pub fn new(formats: impl IntoIterator<Item = EventFormat>, endian: Endianness) -> Self {
+        let formats: HashMap<u64, EventFormat> = formats
+            .into_iter()
+            .map(|format| (format.id, format))
+            .collect();
+        Self {
+            print_fmts: parse_print_fmts(&formats, 8),
+            formats,
+            endian,
+            long_size: 8,
+        }
+    }
+
+    /// Creates a decoder for the event formats in `tracing_data`, for raw
+    /// data in the byte order and with the size of `long` of the tracing data.
+    pub fn from_tracing_data(tracing_data: TracingData) -> Self {
+        let long_size = usize::from(tracing_data.long_size);
+        let formats = tracing_data
+            .ftrace_formats
+            .into_iter()
+            .chain(tracing_data.event_formats);
+        Self::new(formats, tracing_data.endian).with_long_size(long_size)
+    }
+
+    /// Returns this decoder for raw data in the byte order `endian`, e.g.
+    /// [`PerfFile::endian`](crate::PerfFile::endian) for a decoder which was
+    /// created from the tracefs of a machine with a different byte order.
+    pub fn with_endian(mut self, endian: Endianness) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// Returns this decoder for raw data which was recorded on a machine
+    /// where `long` and pointers have `long_size` bytes, e.g. 4 for 32-bit
+    /// machines. This affects `long` arrays and the print fmts.
+    pub fn with_long_size(mut self, long_size: usize) -> Self {
+        if long_size != self.long_size {
+            self.print_fmts = parse_print_fmts(&self.formats, long_size);
+            self.long_size = long_size;
+        }
+        self
+    }
+
+    /// The byte order of the raw data.
+    pub fn endian(&self) -> Endianness {
+        self.endian
+    }
+
+    /// The size of `long` on the machine on which the samples were recorded.
+    pub fn long_size(&self) -> usize {
+        self.long_size
+    }
+
+    /// The format of the event with the tracepoint ID `id`.
+    pub fn format(&self, id: u64) -> Option<&EventFormat> {
+        self.formats.get(&id)
+    }
+
+    /// The formats of all events known to this decoder, in no particular
+    /// order.
+    pub fn formats(&self) -> impl Iterator<Item = &EventFormat> + '_ {
+        self.formats.values()
+    }
+
+    /// Decodes the raw data of a sample of the event `attr`, i.e. the `raw`
+    /// field of the [`SampleRecord`](linux_perf_event_reader::SampleRecord).
+    ///
+    /// Returns `Ok(None)` if `attr` is not a tracepoint event, and an error
+    /// if the tracepoint is unknown or if `raw` is too short for the
+    /// tracepoint's fields.
+    pub fn decode<'a>(
+        &'a self,
+        attr: &PerfEventAttr,
+        raw: RawData<'a>,
+    ) -> Result<Option<TracepointRecord<'a>>, TracepointError> {
+        let PerfEventType::Tracepoint(id) = attr.type_ else {
+            return Ok(None);
+        };
+        let format = self
+            .format(id)
+            .ok_or(TracepointError::UnknownTracepoint(id))?;
+        let mut required_len = 0;
+        for field in format.common_fields.iter().chain(format.fields.iter()) {
+            let end = field.offset.checked_add(field.size).ok_or_else(|| {
+                TracepointError::FieldOutOfRange {
+                    event: format.name.clone(),
+                    field: field.name.clone(),
+                }
+            })?;
+            required_len = required_len.max(end);
+        }
+        if raw.len() < required_len {
+            return Err(TracepointError::RawDataTooShort {
+                event: format.name.clone(),
+                len: raw.len(),
+                required_len,
+            });
+        }
+        Ok(Some(TracepointRecord {
+            format,
+            print_fmt: self.print_fmts.get(&id),
+            raw,
+            endian: self.endian,
+            long_size: self.long_size,
+        }))
+    }
+}
+
+/// Parses the print fmts of `formats`, leaving out the ones which can't be
+/// parsed.
+fn parse_print_fmts(
+    formats: &HashMap<u64, EventFormat>,
+    long_size: usize,
+) -> HashMap<u64, PrintFmt> {
+    formats
+        .iter()
+        .filter_map(|(id, format)| {
+            let print_fmt = PrintFmt::parse_with_long_size(&format.print_fmt, long_size).ok()?;
+            Some((*id, print_fmt))
+        })
+        .collect()
+}
+
+/// The decoded raw data of a tracepoint sample, returned by
+/// [`TracepointDecoder::decode`].
+#[derive(Debug, Clone, Copy)]
+pub struct TracepointRecord<'a> {
+    /// The format of the tracepoint event.
+    pub format: &'a EventFormat,
+    /// The parsed print fmt of the event, or `None` if it couldn't be
+    /// parsed.
+    pub print_fmt: Option<&'a PrintFmt>,
+    /// The raw data of the sample.
+    pub raw: RawData<'a>,
+    /// The byte order of the raw data.
+    pub endian: Endianness,
+    /// The size of `long` on the machine on which the sample was recorded.
+    pub long_size: usize,
+}
+
+impl<'a> TracepointRecord<'a> {
+    /// The event name, e.g. `sched_switch`.
+    pub fn name(&self) -> &'a str {
+        &self.format.name
+    }
+
+    /// The subsystem of the event, e.g. `sched`.
+    pub fn system(&self) -> &'a str {
+        &self.format.system
+    }
+
+    /// Formats the event the way `perf script` displays it, using the
+    /// event's print fmt. Returns `None` if the print fmt isn't supported.
+    pub fn formatted(&self) -> Option<String> {
+        self.print_fmt?.format(self)
+    }
+
+    /// The `common_pid` field: the pid of the task which hit the tracepoint.
+    /// This is the thread ID, not the process ID.
+    pub fn common_pid(&self) -> Option<i32> {
+        Some(self.common_field("common_pid")? as i32)
+    }
+
+    /// The `common_flags` field, with the `TRACE_FLAG_*` bits, e.g. whether
+    /// interrupts were disabled.
+    pub fn common_flags(&self) -> Option<u8> {
+        Some(self.common_field("common_flags")? as u8)
+    }
+
+    /// The `common_preempt_count` field: the preemption count of the task
+    /// which hit the tracepoint.
+    pub fn common_preempt_count(&self) -> Option<u8> {
+        Some(self.common_field("common_preempt_count")? as u8)
+    }
+
+    /// Reads the integer value of a common field. The common fields are taken
+    /// from the event's format rather than assumed, because their layout
+    /// differs between kernel versions, e.g. kernels before 3.x had an
+    /// additional `common_lock_depth` field.
+    fn common_field(&self, name: &str) -> Option<u64> {
+        let field = self
+            .format
+            .common_fields
+            .iter()
+            .find(|field| field.name == name)?;
+        self.value_for(field)?.as_u64()
+    }
+
+    /// The event-specific fields, with their values. Fields whose dynamic
+    /// data is outside of the raw data are left out.
+    pub fn fields(&self) -> impl Iterator<Item = (&'a FormatField, FieldValue<'a>)> + '_ {
+        self.format
+            .fields
+            .iter()
+            .filter_map(|field| Some((field, self.value_for(field)?)))
+    }
+
+    /// The names and values of the event-specific fields, in the order of
+    /// the format, like [`fields`](Self::fields). The common fields are
+    /// available by name via [`field`](Self::field).
+    pub fn field_values(&self) -> impl Iterator<Item = (&'a str, FieldValue<'a>)> + '_ {
+        self.fields()
+            .map(|(field, value)| (field.name.as_str(), value))
+    }
+
+    /// The value of the field `name`, which can be a common field or an
+    /// event-specific field. Returns `None` if the event has no such field,
+    /// or if its data is outside of the raw data.
+    pub fn field(&self, name: &str) -> Option<FieldValue<'a>> {
+        self.value_for(self.format.field(name)?)
+    }
+
+    /// The bytes of the value of the field `name`, which can be a common
+    /// field or an event-specific field.
+    pub fn field_data(&self, name: &str) -> Option<RawData<'a>> {
+        self.field_data_for(self.format.field(name)?)
+    }
+
+    /// The value of the string field `name`, without the nul terminator.
+    pub fn field_string(&self, name: &str) -> Option<RawData<'a>> {
+        let field = self.format.field(name)?;
+        match self.endian {
+            Endianness::LittleEndian => field.string::<LittleEndian>(self.raw),
+            Endianness::BigEndian => field.string::<BigEndian>(self.raw),
+        }
+    }
+
+    fn value_for(&self, field: &FormatField) -> Option<FieldValue<'a>> {
+        match self.endian {
+            Endianness::LittleEndian => field.value::<LittleEndian>(self.raw, self.long_size),
+            Endianness::BigEndian => field.value::<BigEndian>(self.raw, self.long_size),
+        }
+    }
+
+    fn field_data_for(&self, field: &FormatField) -> Option<RawData<'a>> {
+        match self.endian {
+            Endianness::LittleEndian => field.data::<LittleEndian>(self.raw),
+            Endianness::BigEndian => field.data::<BigEndian>(self.raw),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::io::Cursor;
+
+    use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+    use linux_perf_event_reader::{
+        Endianness, EventRecord, PerfEventAttr, PerfEventType, RecordType, SampleFormat,
+        SoftwareCounterType,
+    };
+
+    use crate::file_writer::test::test_attr;
+    use crate::tracepoint::tracing_data::test::{
+        tracing_data_section, IRQ_HANDLER_ENTRY, SCHED_SWITCH, SCHED_WAKEUP,
+    };
+    use crate::tracepoint::{
+        EventFormat, FieldValue, SchedSwitch, TracepointDecoder, TracepointError, TracingData,
+    };
+    use crate::{Feature, PerfFileReader, PerfFileRecord, PerfFileWriter};
+
+    /// A format with the common fields of old kernels, which had
+    /// `common_lock_depth` and a different offset for `common_pid`.
+    const OLD_KERNEL_FORMAT: &str = "name: test_event
+ID: 7
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;
+\tfield:int common_pid;\toffset:4;\tsize:4;
+\tfield:int common_lock_depth;\toffset:8;\tsize:4;
+
+\tfield:u64 value;\toffset:16;\tsize:8;
+
+print fmt: \"value=%llu\", REC->value
+";
+
+    /// A format whose field ends beyond `usize::MAX`.
+    const OVERFLOWING_FORMAT: &str = "name: overflowing_event
+ID: 8
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;
+\tfield:u64 value;\toffset:18446744073709551615;\tsize:8;
+
+print fmt: \"value=%llu\", REC->value
+";
+
+    /// The raw data of a little-endian `sched:sched_switch` sample.
+    pub(crate) fn sched_switch_raw(
+        prev: (&[u8], i32),
+        prev_state: i64,
+        next: (&[u8], i32),
+    ) -> Vec<u8> {
+        sched_switch_raw_with_order::<LittleEndian>(prev, prev_state, next)
+    }
+
+    /// The raw data of a `sched:sched_switch` sample in the byte order `T`.
+    fn sched_switch_raw_with_order<T: ByteOrder>(
+        prev: (&[u8], i32),
+        prev_state: i64,
+        next: (&[u8], i32),
+    ) -> Vec<u8> {
+        let mut raw = Vec::new();
+        raw.write_u16::<T>(316).unwrap();
+        raw.write_u8(0).unwrap();
+        raw.write_u8(0).unwrap();
+        raw.write_i32::<T>(prev.1).unwrap();
+        for (comm, pid) in [prev, next] {
+            let mut comm_bytes = [0; 16];
+            comm_bytes[..comm.len()].copy_from_slice(comm);
+            raw.extend_from_slice(&comm_bytes);
+            raw.write_i32::<T>(pid).unwrap();
+            raw.write_i32::<T>(120).unwrap();
+            if pid == prev.1 {
+                raw.write_i64::<T>(prev_state).unwrap();
+            }
+        }
+        raw
+    }
+
+    /// The raw data of a `sched:sched_wakeup` sample.
+    pub(crate) fn sched_wakeup_raw(comm: &[u8], pid: i32, prio: i32, target_cpu: i32) -> Vec<u8> {
+        let mut raw = Vec::new();
+        raw.write_u16::<LittleEndian>(318).unwrap();
+        raw.write_u8(0).unwrap();
+        raw.write_u8(0).unwrap();
+        raw.write_i32::<LittleEndian>(pid).unwrap();
+        let mut comm_bytes = [0; 16];
+        comm_bytes[..comm.len()].copy_from_slice(comm);
+        raw.extend_from_slice(&comm_bytes);
+        raw.write_i32::<LittleEndian>(pid).unwrap();
+        raw.write_i32::<LittleEndian>(prio).unwrap();
+        raw.write_i32::<LittleEndian>(target_cpu).unwrap();
+        raw
+    }
+
+    #[test]
+    fn decode() {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer.add_feature(
+            Feature::TRACING_DATA,
+            tracing_data_section(Endianness::LittleEndian, &[("sched", SCHED_WAKEUP)]),
+        );
+        let file = writer.finish().unwrap().into_inner();
+        let perf_file = PerfFileReader::parse_file(Cursor::new(&file[..]))
+            .unwrap()
+            .perf_file;
+        let decoder = perf_file.tracepoint_decoder().unwrap().unwrap();
+
+        let attr = PerfEventAttr {
+            type_: PerfEventType::Tracepoint(318),
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+        let raw = sched_wakeup_raw(b"kworker/0:1", 42, 120, 3);
+        let record = decoder.decode(&attr, (&raw[..]).into()).unwrap().unwrap();
+        assert_eq!((record.system(), record.name()), ("sched", "sched_wakeup"));
+        let fields: Vec<_> = record.field_values().collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("comm", FieldValue::String(b"kworker/0:1"[..].into())),
+                ("pid", FieldValue::I32(42)),
+                ("prio", FieldValue::I32(120)),
+                ("target_cpu", FieldValue::I32(3)),
+            ]
+        );
+        assert_eq!(
+            record.field_string("comm").unwrap().as_slice(),
+            &b"kworker/0:1"[..]
+        );
+        assert_eq!(
+            record.field_data("target_cpu").unwrap().as_slice(),
+            &3i32.to_le_bytes()[..]
+        );
+        assert_eq!(record.field_data("common_pid").unwrap().len(), 4);
+        assert_eq!(record.common_pid(), Some(42));
+        assert_eq!(record.field("prio"), Some(FieldValue::I32(120)));
+        assert_eq!(record.field("common_type"), Some(FieldValue::U16(318)));
+        assert!(record.field("nonexistent").is_none());
+        assert!(record.field_data("nonexistent").is_none());
+
+        // Not a tracepoint.
+        let cpu_clock = test_attr(SoftwareCounterType::CpuClock);
+        assert!(decoder
+            .decode(&cpu_clock, (&raw[..]).into())
+            .unwrap()
+            .is_none());
+        // An unknown tracepoint.
+        let unknown = PerfEventAttr {
+            type_: PerfEventType::Tracepoint(1),
+            ..cpu_clock
+        };
+        assert_eq!(
+            decoder.decode(&unknown, (&raw[..]).into()).unwrap_err(),
+            TracepointError::UnknownTracepoint(1)
+        );
+        // Truncated raw data.
+        assert!(matches!(
+            decoder.decode(&attr, (&raw[..30]).into()),
+            Err(TracepointError::RawDataTooShort {
+                len: 30,
+                required_len: 36,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn field_out_of_range() {
+        let tracing_data = TracingData::parse(&tracing_data_section(
+            Endianness::LittleEndian,
+            &[("test", OVERFLOWING_FORMAT)],
+        ))
+        .unwrap();
+        let decoder = TracepointDecoder::from_tracing_data(tracing_data);
+        let attr = PerfEventAttr {
+            type_: PerfEventType::Tracepoint(8),
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+        assert_eq!(
+            decoder.decode(&attr, (&[0; 16][..]).into()).unwrap_err(),
+            TracepointError::FieldOutOfRange {
+                event: "overflowing_event".to_owned(),
+                field: "value".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn common_fields() {
+        let tracing_data = TracingData::parse(&tracing_data_section(
+            Endianness::BigEndian,
+            &[("sched", SCHED_WAKEUP), ("test", OLD_KERNEL_FORMAT)],
+        ))
+        .unwrap();
+        let decoder = TracepointDecoder::from_tracing_data(tracing_data);
+        assert_eq!(decoder.endian(), Endianness::BigEndian);
+
+        let mut raw = Vec::new();
+        raw.write_u16::<BigEndian>(7).unwrap();
+        raw.write_u8(0x1).unwrap();
+        raw.write_u8(2).unwrap();
+        raw.write_i32::<BigEndian>(1234).unwrap();
+        raw.write_i32::<BigEndian>(-1).unwrap();
+        raw.write_u32::<BigEndian>(0).unwrap();
+        raw.write_u64::<BigEndian>(99).unwrap();
+        let attr = PerfEventAttr {
+            type_: PerfEventType::Tracepoint(7),
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+        let record = decoder.decode(&attr, (&raw[..]).into()).unwrap().unwrap();
+        assert_eq!(record.common_pid(), Some(1234));
+        assert_eq!(record.common_flags(), Some(1));
+        assert_eq!(record.common_preempt_count(), Some(2));
+        assert_eq!(record.format.common_fields.len(), 5);
+        assert_eq!(record.fields().count(), 1);
+    }
+
+    #[test]
+    fn formatted() {
+        let tracing_data = TracingData::parse(&tracing_data_section(
+            Endianness::LittleEndian,
+            &[("sched", SCHED_SWITCH), ("irq", IRQ_HANDLER_ENTRY)],
+        ))
+        .unwrap();
+        let decoder = TracepointDecoder::from_tracing_data(tracing_data);
+        let sched_switch = PerfEventAttr {
+            type_: PerfEventType::Tracepoint(316),
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+        let format = |raw: &[u8]| {
+            decoder
+                .decode(&sched_switch, raw.into())
+                .unwrap()
+                .unwrap()
+                .formatted()
+                .unwrap()
+        };
+        assert_eq!(
+            format(&sched_switch_raw((b"bash", 10), 1, (b"swapper/0", 0))),
+            "prev_comm=bash prev_pid=10 prev_prio=120 prev_state=S ==> next_comm=swapper/0 next_pid=0 next_prio=120"
+        );
+        assert_eq!(
+            format(&sched_switch_raw((b"bash", 10), 0x102, (b"cc1", 11))),
+            "prev_comm=bash prev_pid=10 prev_prio=120 prev_state=D+ ==> next_comm=cc1 next_pid=11 next_prio=120"
+        );
+        assert_eq!(
+            format(&sched_switch_raw((b"bash", 10), 0, (b"cc1", 11))),
+            "prev_comm=bash prev_pid=10 prev_prio=120 prev_state=R ==> next_comm=cc1 next_pid=11 next_prio=120"
+        );
+
+        let irq_handler_entry = PerfEventAttr {
+            type_: PerfEventType::Tracepoint(120),
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+        let mut raw = Vec::new();
+        raw.write_u16::<LittleEndian>(120).unwrap();
+        raw.extend_from_slice(&[0; 6]);
+        raw.write_i32::<LittleEndian>(-3).unwrap();
+        raw.write_u32::<LittleEndian>((5 << 16) | 20).unwrap();
+        raw.write_u32::<LittleEndian>(1).unwrap();
+        raw.extend_from_slice(b"eth0\0");
+        let record = decoder
+            .decode(&irq_handler_entry, (&raw[..]).into())
+            .unwrap()
+            .unwrap();
+        assert_eq!(record.formatted().unwrap(), "irq=-3 name=eth0 vec=TIMER");
+    }
+
+    /// Writes a file in the byte order `T` with `sched_switch` and
+    /// `irq_handler_entry` samples, and decodes them on this machine.
+    fn decode_file_with_order<T: ByteOrder>(endian: Endianness) {
+        let attr = PerfEventAttr {
+            type_: PerfEventType::Tracepoint(316),
+            sample_format: SampleFormat::IDENTIFIER
+                | SampleFormat::TID
+                | SampleFormat::TIME
+                | SampleFormat::RAW,
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+        let irq_attr = PerfEventAttr {
+            type_: PerfEventType::Tracepoint(120),
+            ..attr
+        };
+        let mut irq_raw = Vec::new();
+        irq_raw.write_u16::<T>(120).unwrap();
+        irq_raw.extend_from_slice(&[0; 2]);
+        irq_raw.write_i32::<T>(10).unwrap();
+        irq_raw.write_i32::<T>(-3).unwrap();
+        irq_raw.write_u32::<T>((5 << 16) | 20).unwrap();
+        irq_raw.write_u32::<T>(2).unwrap();
+        irq_raw.extend_from_slice(b"eth0\0");
+
+        let mut writer = PerfFileWriter::new(Cursor::new(Vec::new()), endian).unwrap();
+        writer.add_attr(&attr, "sched:sched_switch", &[1]).unwrap();
+        writer
+            .add_attr(&irq_attr, "irq:irq_handler_entry", &[2])
+            .unwrap();
+        writer.add_feature(
+            Feature::TRACING_DATA,
+            tracing_data_section(
+                endian,
+                &[("sched", SCHED_SWITCH), ("irq", IRQ_HANDLER_ENTRY)],
+            ),
+        );
+        for (id, mut raw) in [
+            (
+                1,
+                sched_switch_raw_with_order::<T>((b"bash", 10), 1, (b"cc1", 11)),
+            ),
+            (2, irq_raw),
+        ] {
+            // The raw data is padded so that the sample size is a multiple
+            // of 8, including the u32 size of the raw data.
+            raw.resize((raw.len() + 4).next_multiple_of(8) - 4, 0);
+            let mut body = Vec::new();
+            body.write_u64::<T>(id).unwrap();
+            body.write_u32::<T>(10).unwrap();
+            body.write_u32::<T>(10).unwrap();
+            body.write_u64::<T>(1000).unwrap();
+            body.write_u32::<T>(raw.len() as u32).unwrap();
+            body.extend_from_slice(&raw);
+            writer.write_record(RecordType::SAMPLE, 0, &body).unwrap();
+        }
+        let file = writer.finish().unwrap().into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&file[..])).unwrap();
+        assert_eq!(perf_file.endian(), endian);
+        let decoder = perf_file.tracepoint_decoder().unwrap().unwrap();
+        assert_eq!(decoder.endian(), endian);
+        let mut formatted = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            let PerfFileRecord::EventRecord {
+                attr_index, record, ..
+            } = record
+            else {
+                continue;
+            };
+            let EventRecord::Sample(sample) = record.parse().unwrap() else {
+                panic!("expected a sample");
+            };
+            let attr = perf_file.event_attributes()[attr_index].attributes();
+            let record = decoder.decode(attr, sample.raw.unwrap()).unwrap().unwrap();
+            assert_eq!(record.common_pid(), Some(10));
+            formatted.push(record.formatted().unwrap());
+            if record.name() == "sched_switch" {
+                let switch = SchedSwitch::from_record(&record).unwrap();
+                assert_eq!((switch.prev_pid, switch.next_pid), (10, 11));
+                assert_eq!(switch.prev_state, 1);
+            } else {
+                assert_eq!(record.field("irq"), Some(FieldValue::I32(-3)));
+                assert_eq!(record.field("vec"), Some(FieldValue::U32(2)));
+            }
+        }
+        assert_eq!(
+            formatted,
+            [
+                "prev_comm=bash prev_pid=10 prev_prio=120 prev_state=S ==> next_comm=cc1 next_pid=11 next_prio=120",
+                "irq=-3 name=eth0 vec=NET_TX",
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_both_byte_orders() {
+        // One of the two is byte-swapped relative to the machine which runs
+        // the test.
+        decode_file_with_order::<LittleEndian>(Endianness::LittleEndian);
+        decode_file_with_order::<BigEndian>(Endianness::BigEndian);
+    }
+
+    #[test]
+    fn with_endian() {
+        let decoder = TracepointDecoder::new(
+            [EventFormat::parse(SCHED_SWITCH).unwrap()],
+            Endianness::LittleEndian,
+        )
+        .with_endian(Endianness::BigEndian);
+        let attr = PerfEventAttr {
+            type_: PerfEventType::Tracepoint(316),
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+        let raw = sched_switch_raw_with_order::<BigEndian>((b"bash", 10), 2, (b"cc1", 11));
+        let record = decoder.decode(&attr, (&raw[..]).into()).unwrap().unwrap();
+        assert_eq!(record.field("next_pid"), Some(FieldValue::I32(11)));
+        assert_eq!(record.field("prev_state"), Some(FieldValue::I64(2)));
+    }
+
+    #[test]
+    fn long_size_from_tracing_data() {
+        let mut section =
+            tracing_data_section(Endianness::LittleEndian, &[("sched", SCHED_SWITCH)]);
+        // The long_size byte follows the magic, the version and the endian byte.
+        section[15] = 4;
+        let tracing_data = TracingData::parse(&section).unwrap();
+        assert_eq!(tracing_data.long_size, 4);
+        let decoder = TracepointDecoder::from_tracing_data(tracing_data);
+        assert_eq!(decoder.long_size(), 4);
+        let attr = PerfEventAttr {
+            type_: PerfEventType::Tracepoint(316),
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+        let raw = sched_switch_raw((b"bash", 10), 2, (b"cc1", 11));
+        let record = decoder.decode(&attr, (&raw[..]).into()).unwrap().unwrap();
+        assert_eq!(record.long_size, 4);
+        assert!(record.formatted().is_some());
+    }
+}
@@ -0,0 +1,44 @@
+/// The error type used for parsing tracepoint formats and tracing data.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TracepointError {
+    #[error("The event format has no name")]
+    MissingName,
+
+    #[error("The event format has no ID")]
+    MissingId,
+
+    #[error("Invalid event ID: {0}")]
+    InvalidId(String),
+
+    #[error("Invalid field description: {0}")]
+    InvalidField(String),
+
+    #[error("Invalid print fmt: {0}")]
+    InvalidPrintFmt(String),
+
+    #[error("The tracing data does not start with the expected magic bytes")]
+    UnrecognizedMagic,
+
+    #[error("Expected the {0} header in the tracing data")]
+    MissingHeader(&'static str),
+
+    #[error("The tracing data ended unexpectedly")]
+    UnexpectedEnd,
+
+    #[error("The tracing data contains text which is not valid utf-8")]
+    StringUtf8,
+
+    #[error("No format is known for the tracepoint with the ID {0}")]
+    UnknownTracepoint(u64),
+
+    #[error("The field {field} of the {event} event ends beyond the addressable range")]
+    FieldOutOfRange { event: String, field: String },
+
+    #[error("The raw data of the {event} event has {len} bytes, but its fields need {required_len} bytes")]
+    RawDataTooShort {
+        event: String,
+        len: usize,
+        required_len: usize,
+    },
+}
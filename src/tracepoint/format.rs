@@ -0,0 +1,581 @@
+use std::ops::Range;
+
+use byteorder::ByteOrder;
+use linux_perf_event_reader::RawData;
+
+use super::{FieldValue, TracepointError};
+
+/// The description of a tracepoint event, parsed from an
+/// `events/<system>/<event>/format` file in tracefs or from the `TRACING_DATA`
+/// feature section.
+///
+/// ```text
+/// name: sched_wakeup
+/// ID: 318
+/// format:
+///         field:unsigned short common_type;       offset:0;       size:2; signed:0;
+///         field:unsigned char common_flags;       offset:2;       size:1; signed:0;
+///         field:unsigned char common_preempt_count;       offset:3;       size:1; signed:0;
+///         field:int common_pid;   offset:4;       size:4; signed:1;
+///
+///         field:char comm[16];    offset:8;       size:16;        signed:0;
+///         field:pid_t pid;        offset:24;      size:4; signed:1;
+///         field:int prio; offset:28;      size:4; signed:1;
+///         field:int target_cpu;   offset:32;      size:4; signed:1;
+///
+/// print fmt: "comm=%s pid=%d prio=%d target_cpu=%03d", REC->comm, REC->pid, REC->prio, REC->target_cpu
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventFormat {
+    /// The subsystem, e.g. `sched`. This is the name of the directory which
+    /// contains the event's directory, so it's empty for formats which were
+    /// parsed with [`EventFormat::parse`] on their own.
+    pub system: String,
+    /// The event name, e.g. `sched_wakeup`.
+    pub name: String,
+    /// The tracepoint ID. This is the `config` value of perf event attributes
+    /// with the type `PERF_TYPE_TRACEPOINT`.
+    pub id: u64,
+    /// The fields which every event of this kernel has, e.g. `common_type`
+    /// and `common_pid`.
+    pub common_fields: Vec<FormatField>,
+    /// The fields which are specific to this event.
+    pub fields: Vec<FormatField>,
+    /// The `print fmt:` line without the prefix, i.e. the quoted format string
+    /// followed by the arguments.
+    pub print_fmt: String,
+}
+
+impl EventFormat {
+    /// Parses the contents of a format file. The common fields are the
+    /// fields before the first empty line of the `format:` block.
+    pub fn parse(text: &str) -> Result<Self, TracepointError> {
+        let mut name = None;
+        let mut id = None;
+        let mut common_fields = Vec::new();
+        let mut fields = Vec::new();
+        let mut print_fmt = String::new();
+        let mut in_common_fields = true;
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if let Some(value) = trimmed.strip_prefix("name:") {
+                name = Some(value.trim().to_owned());
+            } else if let Some(value) = trimmed.strip_prefix("ID:") {
+                let value = value.trim();
+                id = Some(
+                    value
+                        .parse()
+                        .map_err(|_| TracepointError::InvalidId(value.to_owned()))?,
+                );
+            } else if let Some(value) = trimmed.strip_prefix("print fmt:") {
+                print_fmt = value.trim().to_owned();
+            } else if trimmed.starts_with("field:") || trimmed.starts_with("field ") {
+                let field = FormatField::parse(trimmed)?;
+                if in_common_fields {
+                    common_fields.push(field);
+                } else {
+                    fields.push(field);
+                }
+            } else if trimmed.is_empty() && !common_fields.is_empty() {
+                in_common_fields = false;
+            }
+        }
+        Ok(Self {
+            system: String::new(),
+            name: name.ok_or(TracepointError::MissingName)?,
+            id: id.ok_or(TracepointError::MissingId)?,
+            common_fields,
+            fields,
+            print_fmt,
+        })
+    }
+
+    /// The name which perf uses for the event, `system:name`, e.g.
+    /// `sched:sched_wakeup`. Just the name if the system isn't known.
+    pub fn full_name(&self) -> String {
+        if self.system.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}:{}", self.system, self.name)
+        }
+    }
+
+    /// Looks up a field by name, among both the common fields and the
+    /// event-specific fields.
+    pub fn field(&self, name: &str) -> Option<&FormatField> {
+        self.common_fields
+            .iter()
+            .chain(self.fields.iter())
+            .find(|field| field.name == name)
+    }
+}
+
+/// A field of a tracepoint event, from a line of the form
+/// `field:TYPE NAME;  offset:N;  size:N;  signed:N;`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatField {
+    pub name: String,
+    /// The C type of the field, or of the array elements for array fields,
+    /// e.g. `unsigned long`, `pid_t` or `char`. The `__data_loc` and
+    /// `__rel_loc` prefixes are stripped and are found in `layout` instead.
+    pub type_name: String,
+    /// Where this field is stored, as a byte offset from the start of the
+    /// raw tracepoint data.
+    pub offset: usize,
+    /// The size of the field in bytes. For `__data_loc` and `__rel_loc`
+    /// fields this is the size of the location, not of the data.
+    pub size: usize,
+    /// Whether the field, or its elements, are a signed type. Format files of
+    /// very old kernels don't have this information, and `false` is assumed.
+    pub is_signed: bool,
+    pub layout: FieldLayout,
+}
+
+/// How the value of a [`FormatField`] is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldLayout {
+    /// A single value of `size` bytes at `offset`.
+    Scalar,
+    /// A fixed-size array at `offset`, e.g. `char comm[16]`. `len` is the
+    /// number of elements, or 0 if the declaration doesn't have a numeric
+    /// length.
+    Array { len: usize },
+    /// A dynamically-sized array, e.g. `__data_loc char[] name`. The field
+    /// contains a 32-bit value whose lower 16 bits are the offset of the data
+    /// from the start of the raw tracepoint data, and whose upper 16 bits are
+    /// the length of the data in bytes.
+    DataLoc,
+    /// Like [`FieldLayout::DataLoc`], but the offset is relative to the end
+    /// of the field instead of to the start of the raw data.
+    RelLoc,
+}
+
+impl FormatField {
+    /// Parses a single field line from a format file.
+    pub fn parse(line: &str) -> Result<Self, TracepointError> {
+        let invalid = || TracepointError::InvalidField(line.to_owned());
+        let mut declaration = None;
+        let mut offset = None;
+        let mut size = None;
+        let mut is_signed = false;
+        for item in line.split(';') {
+            let Some((key, value)) = item.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "field" | "field special" => declaration = Some(value),
+                "offset" => offset = Some(value.parse().map_err(|_| invalid())?),
+                "size" => size = Some(value.parse().map_err(|_| invalid())?),
+                "signed" => is_signed = value == "1",
+                _ => {}
+            }
+        }
+        let (Some(declaration), Some(offset), Some(size)) = (declaration, offset, size) else {
+            return Err(invalid());
+        };
+
+        // The name is the last word of the declaration. A pointer's `*` may
+        // be attached to the name, e.g. `const char *filename`.
+        let split = declaration
+            .rfind(|c: char| c.is_whitespace() || c == '*')
+            .ok_or_else(invalid)?;
+        let (type_name, name) = declaration.split_at(split + 1);
+        let mut type_name = type_name.trim();
+        let mut name = name.trim();
+        if name.is_empty() {
+            return Err(invalid());
+        }
+
+        let mut layout = FieldLayout::Scalar;
+        if let Some(rest) = type_name.strip_prefix("__data_loc ") {
+            layout = FieldLayout::DataLoc;
+            type_name = rest.trim();
+        } else if let Some(rest) = type_name.strip_prefix("__rel_loc ") {
+            layout = FieldLayout::RelLoc;
+            type_name = rest.trim();
+        }
+        if let Some(element_type) = type_name.strip_suffix("[]") {
+            type_name = element_type.trim();
+        }
+        if let Some((array_name, len)) = name.split_once('[') {
+            name = array_name;
+            if layout == FieldLayout::Scalar {
+                let len = len.trim_end_matches(']').trim();
+                layout = FieldLayout::Array {
+                    len: len.parse().unwrap_or(0),
+                };
+            }
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            type_name: type_name.to_owned(),
+            offset,
+            size,
+            is_signed,
+            layout,
+        })
+    }
+
+    /// Whether this field is a string, i.e. a `char` array.
+    pub fn is_string(&self) -> bool {
+        self.type_name == "char" && self.layout != FieldLayout::Scalar
+    }
+
+    /// The size of the elements of an array field, from the declared length
+    /// or from the element type. `long_size` is the size of `long` on the
+    /// recording machine, see [`TracingData::long_size`](super::TracingData::long_size).
+    /// Returns `None` if the element type is not a known integer type.
+    pub fn element_size(&self, long_size: usize) -> Option<usize> {
+        match self.layout {
+            FieldLayout::Array { len } if len != 0 => Some(self.size / len),
+            _ => {
+                let words: Vec<&str> = self.type_name.split_whitespace().collect();
+                c_type_size_and_signedness(&words, long_size).map(|(size, _)| size)
+            }
+        }
+    }
+
+    /// Decodes the value of this field in `raw`, which is the raw data of a
+    /// tracepoint sample in the byte order `T`, recorded on a machine where
+    /// `long` has `long_size` bytes.
+    ///
+    /// Strings are returned as [`FieldValue::String`], and integer arrays,
+    /// except for byte arrays, as [`FieldValue::Array`]. Fields which are
+    /// neither are returned as [`FieldValue::Bytes`].
+    pub fn value<'a, T: ByteOrder>(
+        &self,
+        raw: RawData<'a>,
+        long_size: usize,
+    ) -> Option<FieldValue<'a>> {
+        if self.is_string() {
+            return Some(FieldValue::String(self.string::<T>(raw)?.as_slice()));
+        }
+        let data = self.data::<T>(raw)?.as_slice();
+        if self.layout == FieldLayout::Scalar {
+            return Some(
+                FieldValue::from_int_bytes::<T>(&data, self.is_signed)
+                    .unwrap_or(FieldValue::Bytes(data)),
+            );
+        }
+        match self.element_size(long_size) {
+            Some(element_size @ (2 | 4 | 8)) if data.len() % element_size == 0 => {
+                let elements = data
+                    .chunks(element_size)
+                    .map(|element| FieldValue::from_int_bytes::<T>(element, self.is_signed))
+                    .collect::<Option<_>>()?;
+                Some(FieldValue::Array(elements))
+            }
+            Some(1) if self.is_signed => Some(FieldValue::Array(
+                data.iter()
+                    .map(|byte| FieldValue::I8(*byte as i8))
+                    .collect(),
+            )),
+            _ => Some(FieldValue::Bytes(data)),
+        }
+    }
+
+    /// The bytes of this field's value in `raw`, which is the raw data of a
+    /// tracepoint sample. For `__data_loc` and `__rel_loc` fields, these are
+    /// the bytes of the dynamic data, see [`dynamic_location`](Self::dynamic_location).
+    ///
+    /// Returns `None` if the field is outside of `raw`.
+    pub fn data<'a, T: ByteOrder>(&self, raw: RawData<'a>) -> Option<RawData<'a>> {
+        let range = match self.layout {
+            FieldLayout::DataLoc | FieldLayout::RelLoc => self.dynamic_location::<T>(raw)?,
+            FieldLayout::Scalar | FieldLayout::Array { .. } => {
+                self.offset..self.offset.checked_add(self.size)?
+            }
+        };
+        if range.end > raw.len() {
+            return None;
+        }
+        raw.get(range)
+    }
+
+    /// For `__data_loc` and `__rel_loc` fields, resolves the location in the
+    /// field against `raw`, the full raw data of a tracepoint sample, and
+    /// returns the byte range of the dynamic data in `raw`. Returns `None`
+    /// for other fields, and if the field is outside of `raw`.
+    ///
+    /// The location is a 32-bit value whose lower 16 bits are the offset and
+    /// whose upper 16 bits are the length. For `__data_loc` the offset is
+    /// relative to the start of `raw`, and for `__rel_loc` it is relative to
+    /// the end of the field.
+    pub fn dynamic_location<T: ByteOrder>(&self, raw: RawData) -> Option<Range<usize>> {
+        let base = match self.layout {
+            FieldLayout::DataLoc => 0,
+            FieldLayout::RelLoc => self.offset.checked_add(self.size)?,
+            FieldLayout::Scalar | FieldLayout::Array { .. } => return None,
+        };
+        let location_end = self.offset.checked_add(4)?;
+        if location_end > raw.len() {
+            return None;
+        }
+        let location = raw.get(self.offset..location_end)?.read_u32::<T>().ok()?;
+        let start = base.checked_add((location & 0xffff) as usize)?;
+        let len = (location >> 16) as usize;
+        Some(start..start.checked_add(len)?)
+    }
+
+    /// The value of a string field in `raw`, without the nul terminator.
+    /// This works for fixed-size `char` arrays as well as for `__data_loc`
+    /// and `__rel_loc` strings. If the data has no nul byte, all of it is
+    /// returned.
+    pub fn string<'a, T: ByteOrder>(&self, raw: RawData<'a>) -> Option<RawData<'a>> {
+        let data = self.data::<T>(raw)?;
+        let mut rest = data;
+        Some(rest.read_string().unwrap_or(data))
+    }
+}
+
+/// The size in bytes and the signedness of the C type with the words
+/// `words`, where `long` has `long_size` bytes.
+pub(crate) fn c_type_size_and_signedness(
+    words: &[&str],
+    long_size: usize,
+) -> Option<(usize, bool)> {
+    let words: Vec<&str> = words
+        .iter()
+        .copied()
+        .filter(|word| !matches!(*word, "const" | "volatile" | "int") || words.len() == 1)
+        .collect();
+    Some(match words.as_slice() {
+        ["unsigned"] | ["u32"] | ["__u32"] | ["uint32_t"] => (4, false),
+        ["signed"] | ["int"] | ["s32"] | ["__s32"] | ["int32_t"] | ["pid_t"] => (4, true),
+        ["char"] | ["signed", "char"] | ["s8"] | ["__s8"] | ["int8_t"] => (1, true),
+        ["unsigned", "char"] | ["u8"] | ["__u8"] | ["uint8_t"] | ["bool"] => (1, false),
+        ["short"] | ["signed", "short"] | ["s16"] | ["__s16"] | ["int16_t"] => (2, true),
+        ["unsigned", "short"] | ["u16"] | ["__u16"] | ["uint16_t"] => (2, false),
+        ["long"] | ["signed", "long"] | ["ssize_t"] => (long_size, true),
+        ["unsigned", "long"] | ["size_t"] => (long_size, false),
+        ["long", "long"] | ["signed", "long", "long"] => (8, true),
+        ["s64"] | ["__s64"] | ["int64_t"] | ["loff_t"] => (8, true),
+        ["unsigned", "long", "long"] | ["u64"] | ["__u64"] | ["uint64_t"] => (8, false),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{BigEndian, LittleEndian};
+    use linux_perf_event_reader::RawData;
+
+    use super::{EventFormat, FieldLayout, FormatField};
+    use crate::tracepoint::FieldValue;
+
+    const SCHED_SWITCH: &str = "name: sched_switch
+ID: 316
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:char prev_comm[16];\toffset:8;\tsize:16;\tsigned:0;
+\tfield:pid_t prev_pid;\toffset:24;\tsize:4;\tsigned:1;
+\tfield:int prev_prio;\toffset:28;\tsize:4;\tsigned:1;
+\tfield:long prev_state;\toffset:32;\tsize:8;\tsigned:1;
+\tfield:char next_comm[16];\toffset:40;\tsize:16;\tsigned:0;
+\tfield:pid_t next_pid;\toffset:56;\tsize:4;\tsigned:1;
+\tfield:int next_prio;\toffset:60;\tsize:4;\tsigned:1;
+
+print fmt: \"prev_comm=%s prev_pid=%d\", REC->prev_comm, REC->prev_pid
+";
+
+    #[test]
+    fn sched_switch() {
+        let format = EventFormat::parse(SCHED_SWITCH).unwrap();
+        assert_eq!(format.name, "sched_switch");
+        assert_eq!(format.id, 316);
+        assert_eq!(format.common_fields.len(), 4);
+        assert_eq!(format.fields.len(), 7);
+        assert_eq!(
+            format.print_fmt,
+            "\"prev_comm=%s prev_pid=%d\", REC->prev_comm, REC->prev_pid"
+        );
+        assert_eq!(
+            format.field("common_pid").unwrap(),
+            &FormatField {
+                name: "common_pid".into(),
+                type_name: "int".into(),
+                offset: 4,
+                size: 4,
+                is_signed: true,
+                layout: FieldLayout::Scalar,
+            }
+        );
+        let prev_comm = format.field("prev_comm").unwrap();
+        assert_eq!(prev_comm.type_name, "char");
+        assert_eq!(prev_comm.layout, FieldLayout::Array { len: 16 });
+        assert!(prev_comm.is_string());
+        let prev_state = format.field("prev_state").unwrap();
+        assert_eq!((prev_state.offset, prev_state.size), (32, 8));
+        assert_eq!(prev_state.type_name, "long");
+    }
+
+    #[test]
+    fn field_declarations() {
+        let field =
+            FormatField::parse("field:__data_loc char[] filename;\toffset:8;\tsize:4;\tsigned:1;")
+                .unwrap();
+        assert_eq!(field.name, "filename");
+        assert_eq!(field.type_name, "char");
+        assert_eq!(field.layout, FieldLayout::DataLoc);
+        assert!(field.is_string());
+
+        let field = FormatField::parse("field:__rel_loc u8[] buf;\toffset:12;\tsize:4;\tsigned:0;")
+            .unwrap();
+        assert_eq!(
+            (field.name.as_str(), field.type_name.as_str()),
+            ("buf", "u8")
+        );
+        assert_eq!(field.layout, FieldLayout::RelLoc);
+
+        let field =
+            FormatField::parse("field:const char * name;\toffset:16;\tsize:8;\tsigned:0;").unwrap();
+        assert_eq!(
+            (field.name.as_str(), field.type_name.as_str()),
+            ("name", "const char *")
+        );
+
+        let field =
+            FormatField::parse("field:char *filename;\toffset:16;\tsize:8;\tsigned:0;").unwrap();
+        assert_eq!(
+            (field.name.as_str(), field.type_name.as_str()),
+            ("filename", "char *")
+        );
+
+        let field =
+            FormatField::parse("field:unsigned long args[6];\toffset:16;\tsize:48;\tsigned:0;")
+                .unwrap();
+        assert_eq!(field.name, "args");
+        assert_eq!(field.type_name, "unsigned long");
+        assert_eq!(field.layout, FieldLayout::Array { len: 6 });
+
+        // Old kernels don't have the `signed` key.
+        let field = FormatField::parse("field:int pid;\toffset:4;\tsize:4;").unwrap();
+        assert!(!field.is_signed);
+
+        assert!(FormatField::parse("field:int pid;\toffset:4;").is_err());
+    }
+
+    #[test]
+    fn dynamic_fields() {
+        let filename =
+            FormatField::parse("field:__data_loc char[] filename;\toffset:8;\tsize:4;\tsigned:1;")
+                .unwrap();
+        let name =
+            FormatField::parse("field:__rel_loc char[] name;\toffset:12;\tsize:4;\tsigned:1;")
+                .unwrap();
+        let comm =
+            FormatField::parse("field:char comm[4];\toffset:16;\tsize:4;\tsigned:0;").unwrap();
+        let pid = FormatField::parse("field:int pid;\toffset:4;\tsize:4;\tsigned:1;").unwrap();
+
+        let mut raw = vec![0; 8];
+        // filename: 6 bytes at offset 20
+        raw.extend_from_slice(&[20, 0, 6, 0]);
+        // name: 3 bytes at offset 16 + 10
+        raw.extend_from_slice(&[10, 0, 3, 0]);
+        raw.extend_from_slice(b"ab\0\0");
+        raw.extend_from_slice(b"/bin/\0");
+        raw.extend_from_slice(b"xyz");
+        let raw = RawData::from(&raw[..]);
+
+        assert_eq!(filename.dynamic_location::<LittleEndian>(raw), Some(20..26));
+        assert_eq!(name.dynamic_location::<LittleEndian>(raw), Some(26..29));
+        assert_eq!(comm.dynamic_location::<LittleEndian>(raw), None);
+        assert_eq!(
+            filename.string::<LittleEndian>(raw).unwrap().as_slice(),
+            &b"/bin/"[..]
+        );
+        assert_eq!(
+            name.string::<LittleEndian>(raw).unwrap().as_slice(),
+            &b"xyz"[..]
+        );
+        assert_eq!(
+            comm.string::<LittleEndian>(raw).unwrap().as_slice(),
+            &b"ab"[..]
+        );
+        assert_eq!(pid.data::<LittleEndian>(raw).unwrap().len(), 4);
+
+        // With the wrong byte order, the location points outside the data.
+        assert_eq!(filename.string::<BigEndian>(raw), None);
+
+        // Offsets and sizes from a malformed format don't overflow.
+        let huge_offset = FormatField {
+            offset: usize::MAX - 2,
+            ..filename.clone()
+        };
+        assert_eq!(huge_offset.dynamic_location::<LittleEndian>(raw), None);
+        let huge_size = FormatField {
+            size: usize::MAX,
+            ..name.clone()
+        };
+        assert_eq!(huge_size.dynamic_location::<LittleEndian>(raw), None);
+        assert_eq!(huge_size.string::<LittleEndian>(raw), None);
+    }
+
+    #[test]
+    fn values() {
+        let args =
+            FormatField::parse("field:unsigned short args[2];\toffset:0;\tsize:4;\tsigned:0;")
+                .unwrap();
+        let buf = FormatField::parse("field:__data_loc u8[] buf;\toffset:4;\tsize:4;\tsigned:0;")
+            .unwrap();
+        let prio = FormatField::parse("field:int prio;\toffset:8;\tsize:4;\tsigned:1;").unwrap();
+        let comm =
+            FormatField::parse("field:char comm[4];\toffset:12;\tsize:4;\tsigned:0;").unwrap();
+        let raw = [
+            1, 0, 2, 0, 16, 0, 2, 0, 0xff, 0xff, 0xff, 0xff, b'a', 0, 0, 0, 7, 8,
+        ];
+        let raw = RawData::from(&raw[..]);
+
+        assert_eq!(
+            args.value::<LittleEndian>(raw, 8),
+            Some(FieldValue::Array(vec![
+                FieldValue::U16(1),
+                FieldValue::U16(2)
+            ]))
+        );
+        assert_eq!(
+            args.value::<BigEndian>(raw, 8),
+            Some(FieldValue::Array(vec![
+                FieldValue::U16(0x100),
+                FieldValue::U16(0x200)
+            ]))
+        );
+        assert_eq!(
+            buf.value::<LittleEndian>(raw, 8),
+            Some(FieldValue::Bytes(vec![7, 8].into()))
+        );
+        assert_eq!(
+            prio.value::<LittleEndian>(raw, 8),
+            Some(FieldValue::I32(-1))
+        );
+        assert_eq!(
+            comm.value::<LittleEndian>(raw, 8).unwrap().as_str(),
+            Some("a")
+        );
+
+        // The element size of `long` arrays depends on the recording machine.
+        let addrs = FormatField::parse(
+            "field:__data_loc unsigned long[] addrs;\toffset:0;\tsize:4;\tsigned:0;",
+        )
+        .unwrap();
+        let raw = [4, 0, 8, 0, 1, 0, 0, 0, 2, 0, 0, 0];
+        let raw = RawData::from(&raw[..]);
+        assert_eq!(
+            addrs.value::<LittleEndian>(raw, 8),
+            Some(FieldValue::Array(vec![FieldValue::U64(0x2_0000_0001)]))
+        );
+        assert_eq!(
+            addrs.value::<LittleEndian>(raw, 4),
+            Some(FieldValue::Array(vec![
+                FieldValue::U32(1),
+                FieldValue::U32(2)
+            ]))
+        );
+    }
+}
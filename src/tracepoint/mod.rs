@@ -0,0 +1,36 @@
+//! Parsing code for tracepoint event formats.
+//!
+//! The raw data of `PERF_TYPE_TRACEPOINT` samples is laid out as described
+//! by the event's format file in tracefs, e.g.
+//! `/sys/kernel/tracing/events/sched/sched_switch/format`. `perf record`
+//! copies the format files of the recorded events into the `TRACING_DATA`
+//! feature section, which is available via
+//! [`PerfFile::tracing_data`](crate::PerfFile::tracing_data). For files
+//! without this section, the formats can be read from tracefs instead, see
+//! [`TracepointDecoder::from_tracefs_dir`].
+//!
+//! [`TracepointDecoder`] uses these formats to decode the raw data of
+//! samples into the event's fields, and [`PrintFmt`] displays them the
+//! way `perf script` does. The scheduler tracepoints which most analyses
+//! need can be read into typed structs, see [`SchedEvent`], and so can the
+//! arguments of dynamic probe events from `perf probe`, see [`ProbeEvent`].
+
+mod decoder;
+mod error;
+mod format;
+mod print_fmt;
+mod probe;
+mod sched;
+mod tracefs;
+mod tracing_data;
+mod value;
+
+pub use decoder::*;
+pub use error::*;
+pub use format::*;
+pub use print_fmt::*;
+pub use probe::*;
+pub use sched::*;
+pub use tracefs::*;
+pub use tracing_data::*;
+pub use value::*;
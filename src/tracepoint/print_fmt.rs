@@ -0,0 +1,1086 @@
+use super::format::c_type_size_and_signedness;
+use super::{FieldValue, TracepointError, TracepointRecord};
+
+/// The parsed `print fmt` of an event format, which describes how `perf
+/// script` and the kernel's `trace` file display the event.
+///
+/// The print fmt consists of a printf-style format string and C expressions
+/// for the arguments, e.g.
+/// `"comm=%s pid=%d", REC->comm, REC->pid`. The expressions can use the
+/// fields of the event, integer arithmetic, and the helpers `__get_str`,
+/// `__get_dynamic_array`, `__print_symbolic`, `__print_flags` and
+/// `__print_hex`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrintFmt {
+    format: String,
+    args: Vec<Expr>,
+    value_tables: Vec<ValueTable>,
+    /// The size of `long` on the recording machine.
+    long_size: usize,
+}
+
+impl PrintFmt {
+    /// Parses the value of an event format's `print fmt:` line, see
+    /// [`EventFormat::print_fmt`](super::EventFormat::print_fmt), for a
+    /// machine where `long` has 8 bytes.
+    pub fn parse(print_fmt: &str) -> Result<Self, TracepointError> {
+        Self::parse_with_long_size(print_fmt, 8)
+    }
+
+    /// Like [`parse`](Self::parse), for a machine where `long` and pointers
+    /// have `long_size` bytes, see [`TracingData::long_size`](super::TracingData::long_size).
+    pub fn parse_with_long_size(
+        print_fmt: &str,
+        long_size: usize,
+    ) -> Result<Self, TracepointError> {
+        let invalid = || TracepointError::InvalidPrintFmt(print_fmt.to_owned());
+        let tokens = tokenize(print_fmt).ok_or_else(invalid)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+            long_size,
+        };
+        let format = match parser.next() {
+            Some(Token::Str(format)) => format,
+            _ => return Err(invalid()),
+        };
+        let mut args = Vec::new();
+        while parser.eat(&Token::Punct(",")) {
+            args.push(parser.expr().ok_or_else(invalid)?);
+        }
+        if parser.pos != parser.tokens.len() {
+            return Err(invalid());
+        }
+        let mut value_tables = Vec::new();
+        for arg in &args {
+            collect_value_tables(arg, &mut value_tables);
+        }
+        Ok(Self {
+            format,
+            args,
+            value_tables,
+            long_size,
+        })
+    }
+
+    /// The format string, without the quotes.
+    pub fn format_string(&self) -> &str {
+        &self.format
+    }
+
+    /// Formats `record` the way `perf script` displays it.
+    ///
+    /// Returns `None` if the print fmt uses a helper which isn't supported,
+    /// or if a field can't be read.
+    pub fn format(&self, record: &TracepointRecord) -> Option<String> {
+        let evaluator = Evaluator {
+            record: Some(record),
+        };
+        let args = self
+            .args
+            .iter()
+            .map(|arg| evaluator.eval(arg))
+            .collect::<Option<Vec<_>>>()?;
+        printf(&self.format, &args, self.long_size)
+    }
+
+    /// The `__print_symbolic` and `__print_flags` tables in the arguments,
+    /// in the order in which they appear.
+    ///
+    /// Tables whose entries aren't constants are left out.
+    pub fn value_tables(&self) -> &[ValueTable] {
+        &self.value_tables
+    }
+
+    /// The first `__print_symbolic` or `__print_flags` table for the field
+    /// `name`, see [`ValueTable::field`].
+    pub fn value_table(&self, name: &str) -> Option<&ValueTable> {
+        self.value_tables
+            .iter()
+            .find(|table| table.field.as_deref() == Some(name))
+    }
+}
+
+/// A `__print_symbolic` or `__print_flags` table of a print fmt, which maps
+/// the values of a field to names, e.g. softirq vector numbers to `TIMER`
+/// or `NET_RX`, or the bits of GFP flags to `__GFP_IO` etc.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueTable {
+    pub kind: ValueTableKind,
+    /// The field whose value is looked up in the table, if the value
+    /// expression is the field itself, possibly cast or masked with `&`.
+    pub field: Option<String>,
+    /// The `{ value, "name" }` entries, in order.
+    pub entries: Vec<(u64, String)>,
+}
+
+/// Whether a [`ValueTable`] maps whole values or individual flags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueTableKind {
+    /// `__print_symbolic`: each entry is one value.
+    Symbolic,
+    /// `__print_flags`: each entry is a set of bits, and the names of all
+    /// entries which are set are joined with the delimiter.
+    Flags { delimiter: String },
+}
+
+impl ValueTable {
+    /// Renders `value` the way the kernel does.
+    ///
+    /// For `__print_symbolic`, this is the name of the matching entry. For
+    /// `__print_flags`, this is the names of all matching entries, in table
+    /// order, joined by the delimiter. An entry with the value 0 only matches
+    /// if the value is 0. Values or bits which aren't in the table are
+    /// printed in hex.
+    pub fn render(&self, value: u64) -> String {
+        let delimiter = match &self.kind {
+            ValueTableKind::Symbolic => {
+                return match self.entries.iter().find(|(v, _)| *v == value) {
+                    Some((_, name)) => name.clone(),
+                    None => format!("0x{value:x}"),
+                };
+            }
+            ValueTableKind::Flags { delimiter } => delimiter,
+        };
+        let mut remaining = value;
+        let mut names = Vec::new();
+        for (flag, name) in &self.entries {
+            if remaining == 0 && *flag == 0 {
+                names.push(name.clone());
+                break;
+            }
+            if *flag != 0 && remaining & flag == *flag {
+                names.push(name.clone());
+                remaining &= !flag;
+            }
+        }
+        if remaining != 0 {
+            names.push(format!("0x{remaining:x}"));
+        }
+        names.join(delimiter)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Int(i128),
+    Str(String),
+    Ident(String),
+    Punct(&'static str),
+}
+
+const PUNCTUATION: &[&str] = &[
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "(", ")", "{", "}", "[", "]", ",", "?",
+    ":", "+", "-", "*", "/", "%", "&", "|", "^", "~", "!", "<", ">", ".",
+];
+
+fn tokenize(s: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next()?;
+        if c == '"' {
+            let (string, len) = parse_quoted(rest, '"')?;
+            // Adjacent string literals are concatenated.
+            if let Some(Token::Str(previous)) = tokens.last_mut() {
+                previous.push_str(&string);
+            } else {
+                tokens.push(Token::Str(string));
+            }
+            rest = &rest[len..];
+        } else if c == '\'' {
+            let (string, len) = parse_quoted(rest, '\'')?;
+            tokens.push(Token::Int(string.chars().next()? as i128));
+            rest = &rest[len..];
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let literal = rest[..len].trim_end_matches(['u', 'U', 'l', 'L']);
+            let value = if let Some(hex) = literal
+                .strip_prefix("0x")
+                .or_else(|| literal.strip_prefix("0X"))
+            {
+                i128::from_str_radix(hex, 16).ok()?
+            } else if literal.len() > 1 && literal.starts_with('0') {
+                i128::from_str_radix(&literal[1..], 8).ok()?
+            } else {
+                literal.parse().ok()?
+            };
+            tokens.push(Token::Int(value));
+            rest = &rest[len..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_owned()));
+            rest = &rest[len..];
+        } else {
+            let punct = PUNCTUATION.iter().find(|p| rest.starts_with(**p))?;
+            tokens.push(Token::Punct(punct));
+            rest = &rest[punct.len()..];
+        }
+        rest = rest.trim_start();
+    }
+    Some(tokens)
+}
+
+/// Parses a quoted string or character literal at the start of `s`, and
+/// returns its value and the length of the literal including the quotes.
+fn parse_quoted(s: &str, quote: char) -> Option<(String, usize)> {
+    let mut value = String::new();
+    let mut chars = s.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                let (_, escaped) = chars.next()?;
+                value.push(match escaped {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '0' => '\0',
+                    other => other,
+                });
+            }
+            c if c == quote => return Some((value, i + 1)),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Int(i128),
+    Str(String),
+    /// `REC->name`, or a bare field name in the arguments of helpers such
+    /// as `__get_str(name)`.
+    Field(String),
+    Call(String, Vec<Expr>),
+    /// `{ value, "name" }` in `__print_symbolic` and `__print_flags`.
+    Braces(Vec<Expr>),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// A cast to an integer type with the given size and signedness, or
+    /// to a type which isn't known, which leaves the value unchanged.
+    Cast {
+        size_and_signedness: Option<(usize, bool)>,
+        expr: Box<Expr>,
+    },
+    Index(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// The field which this expression reads, if it is a field, or a cast or
+    /// mask of a field.
+    fn field_name(&self) -> Option<&str> {
+        match self {
+            Expr::Field(name) => Some(name),
+            Expr::Cast { expr, .. } | Expr::Binary("&", expr, _) => expr.field_name(),
+            _ => None,
+        }
+    }
+}
+
+/// Adds the value tables of all `__print_symbolic` and `__print_flags` calls
+/// in `expr` to `tables`.
+fn collect_value_tables(expr: &Expr, tables: &mut Vec<ValueTable>) {
+    let constants = Evaluator { record: None };
+    match expr {
+        Expr::Int(_) | Expr::Str(_) | Expr::Field(_) => {}
+        Expr::Call(name, args) => {
+            tables.extend(constants.value_table(name, args));
+            for arg in args {
+                collect_value_tables(arg, tables);
+            }
+        }
+        Expr::Braces(items) => {
+            for item in items {
+                collect_value_tables(item, tables);
+            }
+        }
+        Expr::Unary(_, expr) | Expr::Cast { expr, .. } => collect_value_tables(expr, tables),
+        Expr::Binary(_, lhs, rhs) | Expr::Index(lhs, rhs) => {
+            collect_value_tables(lhs, tables);
+            collect_value_tables(rhs, tables);
+        }
+        Expr::Ternary(condition, if_true, if_false) => {
+            collect_value_tables(condition, tables);
+            collect_value_tables(if_true, tables);
+            collect_value_tables(if_false, tables);
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// The nesting depth of the expression which is being parsed.
+    depth: usize,
+    /// The size of `long` and of pointers.
+    long_size: usize,
+}
+
+/// The maximum nesting depth of an expression. The parser and the evaluator
+/// are recursive, so deeper expressions are rejected instead of overflowing
+/// the stack. Real print fmts are nested a few levels deep.
+const MAX_EXPR_DEPTH: usize = 64;
+
+const BINARY_OPERATORS: &[(&str, u8)] = &[
+    ("||", 1),
+    ("&&", 2),
+    ("|", 3),
+    ("^", 4),
+    ("&", 5),
+    ("==", 6),
+    ("!=", 6),
+    ("<", 7),
+    (">", 7),
+    ("<=", 7),
+    (">=", 7),
+    ("<<", 8),
+    (">>", 8),
+    ("+", 9),
+    ("-", 9),
+    ("*", 10),
+    ("/", 10),
+    ("%", 10),
+];
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, punct: &'static str) -> Option<()> {
+        self.eat(&Token::Punct(punct)).then_some(())
+    }
+
+    /// Increases the nesting depth by one, or fails if that would exceed
+    /// [`MAX_EXPR_DEPTH`]. A failure ends parsing, so the depth doesn't need
+    /// to be restored on errors.
+    fn enter(&mut self) -> Option<()> {
+        if self.depth == MAX_EXPR_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        Some(())
+    }
+
+    fn expr(&mut self) -> Option<Expr> {
+        let condition = self.binary(1)?;
+        if !self.eat(&Token::Punct("?")) {
+            return Some(condition);
+        }
+        self.enter()?;
+        let if_true = self.expr()?;
+        self.expect(":")?;
+        let if_false = self.expr()?;
+        self.depth -= 1;
+        Some(Expr::Ternary(
+            Box::new(condition),
+            Box::new(if_true),
+            Box::new(if_false),
+        ))
+    }
+
+    fn binary(&mut self, min_precedence: u8) -> Option<Expr> {
+        let depth = self.depth;
+        let expr = self.binary_chain(min_precedence)?;
+        self.depth = depth;
+        Some(expr)
+    }
+
+    fn binary_chain(&mut self, min_precedence: u8) -> Option<Expr> {
+        let mut lhs = self.unary()?;
+        loop {
+            let Some(&Token::Punct(op)) = self.peek() else {
+                return Some(lhs);
+            };
+            let Some(&(op, precedence)) = BINARY_OPERATORS.iter().find(|(o, _)| *o == op) else {
+                return Some(lhs);
+            };
+            if precedence < min_precedence {
+                return Some(lhs);
+            }
+            self.pos += 1;
+            // Each operator nests the expression so far one level deeper.
+            self.enter()?;
+            let rhs = self.binary(precedence + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> Option<Expr> {
+        self.enter()?;
+        let expr = self.unary_inner()?;
+        self.depth -= 1;
+        Some(expr)
+    }
+
+    fn unary_inner(&mut self) -> Option<Expr> {
+        for op in ["-", "!", "~", "+"] {
+            if self.eat(&Token::Punct(op)) {
+                return Some(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        if let Some((size_and_signedness, len)) = self.cast() {
+            self.pos += len;
+            let expr = Box::new(self.unary()?);
+            return Some(Expr::Cast {
+                size_and_signedness,
+                expr,
+            });
+        }
+        let mut expr = self.primary()?;
+        while self.eat(&Token::Punct("[")) {
+            let index = self.expr()?;
+            self.expect("]")?;
+            expr = Expr::Index(Box::new(expr), Box::new(index));
+        }
+        Some(expr)
+    }
+
+    /// Checks for a cast like `(unsigned long)` at the current position, and
+    /// returns the size and signedness of the type and the number of tokens.
+    ///
+    /// Parenthesized words which aren't a known type, e.g. `(gfp_t)`, are
+    /// treated as a cast if they are followed by an operand.
+    fn cast(&self) -> Option<(Option<(usize, bool)>, usize)> {
+        if self.peek() != Some(&Token::Punct("(")) {
+            return None;
+        }
+        let mut words = Vec::new();
+        let mut is_pointer = false;
+        for (i, token) in self.tokens[self.pos + 1..].iter().enumerate() {
+            match token {
+                Token::Ident(word) if word != "REC" && !is_pointer => words.push(word.as_str()),
+                Token::Punct("*") if !words.is_empty() => is_pointer = true,
+                Token::Punct(")") if !words.is_empty() => {
+                    let len = i + 2;
+                    if is_pointer {
+                        return Some((Some((self.long_size, false)), len));
+                    }
+                    if let Some(size_and_signedness) =
+                        c_type_size_and_signedness(&words, self.long_size)
+                    {
+                        return Some((Some(size_and_signedness), len));
+                    }
+                    return match self.tokens.get(self.pos + len)? {
+                        Token::Ident(_) | Token::Int(_) | Token::Punct("(") => Some((None, len)),
+                        _ => None,
+                    };
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    fn primary(&mut self) -> Option<Expr> {
+        match self.next()? {
+            Token::Int(value) => Some(Expr::Int(value)),
+            Token::Str(value) => Some(Expr::Str(value)),
+            Token::Punct("(") => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Some(expr)
+            }
+            Token::Punct("{") => {
+                let mut items = vec![self.expr()?];
+                while self.eat(&Token::Punct(",")) {
+                    items.push(self.expr()?);
+                }
+                self.expect("}")?;
+                Some(Expr::Braces(items))
+            }
+            Token::Ident(name) if name == "REC" => {
+                if !self.eat(&Token::Punct("->")) {
+                    self.expect(".")?;
+                }
+                match self.next()? {
+                    Token::Ident(field) => Some(Expr::Field(field)),
+                    _ => None,
+                }
+            }
+            Token::Ident(name) => {
+                if !self.eat(&Token::Punct("(")) {
+                    return Some(Expr::Field(name));
+                }
+                let mut args = Vec::new();
+                if !self.eat(&Token::Punct(")")) {
+                    args.push(self.expr()?);
+                    while self.eat(&Token::Punct(",")) {
+                        args.push(self.expr()?);
+                    }
+                    self.expect(")")?;
+                }
+                Some(Expr::Call(name, args))
+            }
+            Token::Punct(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Int(i128),
+    /// A string, or a `char` array.
+    Str(Vec<u8>),
+    /// A non-`char` array.
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    fn as_int(&self) -> Option<i128> {
+        match self {
+            Value::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn into_bytes(self) -> Option<Vec<u8>> {
+        match self {
+            Value::Str(bytes) | Value::Bytes(bytes) => Some(bytes),
+            Value::Int(_) => None,
+        }
+    }
+}
+
+/// Evaluates expressions for a record, or only constant expressions if
+/// `record` is `None`.
+struct Evaluator<'a, 'r> {
+    record: Option<&'a TracepointRecord<'r>>,
+}
+
+impl Evaluator<'_, '_> {
+    fn eval(&self, expr: &Expr) -> Option<Value> {
+        Some(match expr {
+            Expr::Int(value) => Value::Int(*value),
+            Expr::Str(value) => Value::Str(value.as_bytes().to_vec()),
+            Expr::Field(name) => self.field(name)?,
+            Expr::Call(name, args) => self.call(name, args)?,
+            Expr::Braces(_) => return None,
+            Expr::Unary(op, expr) => {
+                let value = self.eval_int(expr)?;
+                Value::Int(match *op {
+                    "-" => value.wrapping_neg(),
+                    "!" => i128::from(value == 0),
+                    "~" => !value,
+                    _ => value,
+                })
+            }
+            Expr::Binary("&&", lhs, rhs) => Value::Int(i128::from(
+                self.eval_int(lhs)? != 0 && self.eval_int(rhs)? != 0,
+            )),
+            Expr::Binary("||", lhs, rhs) => Value::Int(i128::from(
+                self.eval_int(lhs)? != 0 || self.eval_int(rhs)? != 0,
+            )),
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.eval_int(lhs)?;
+                let rhs = self.eval_int(rhs)?;
+                Value::Int(match *op {
+                    "|" => lhs | rhs,
+                    "^" => lhs ^ rhs,
+                    "&" => lhs & rhs,
+                    "==" => i128::from(lhs == rhs),
+                    "!=" => i128::from(lhs != rhs),
+                    "<" => i128::from(lhs < rhs),
+                    ">" => i128::from(lhs > rhs),
+                    "<=" => i128::from(lhs <= rhs),
+                    ">=" => i128::from(lhs >= rhs),
+                    "<<" => lhs.checked_shl(u32::try_from(rhs).ok()?)?,
+                    ">>" => lhs.checked_shr(u32::try_from(rhs).ok()?)?,
+                    "+" => lhs.wrapping_add(rhs),
+                    "-" => lhs.wrapping_sub(rhs),
+                    "*" => lhs.wrapping_mul(rhs),
+                    "/" => lhs.checked_div(rhs)?,
+                    "%" => lhs.checked_rem(rhs)?,
+                    _ => return None,
+                })
+            }
+            Expr::Ternary(condition, if_true, if_false) => {
+                if self.eval_int(condition)? != 0 {
+                    self.eval(if_true)?
+                } else {
+                    self.eval(if_false)?
+                }
+            }
+            Expr::Cast {
+                size_and_signedness,
+                expr,
+            } => match (self.eval(expr)?, size_and_signedness) {
+                (Value::Int(value), Some((size, signed))) => {
+                    Value::Int(truncate(value, *size, *signed))
+                }
+                (other, _) => other,
+            },
+            Expr::Index(array, index) => {
+                let Expr::Field(name) = &**array else {
+                    return None;
+                };
+                let FieldValue::Array(elements) = self.record?.field(name)? else {
+                    return None;
+                };
+                let index = usize::try_from(self.eval_int(index)?).ok()?;
+                Value::Int(int_value(elements.get(index)?)?)
+            }
+        })
+    }
+
+    fn eval_int(&self, expr: &Expr) -> Option<i128> {
+        self.eval(expr)?.as_int()
+    }
+
+    fn field(&self, name: &str) -> Option<Value> {
+        Some(match self.record?.field(name)? {
+            FieldValue::String(string) => Value::Str(string.into_owned()),
+            FieldValue::Bytes(bytes) => Value::Bytes(bytes.into_owned()),
+            FieldValue::Array(_) => {
+                Value::Bytes(self.record?.field_data(name)?.as_slice().into_owned())
+            }
+            value => Value::Int(int_value(&value)?),
+        })
+    }
+
+    fn call(&self, name: &str, args: &[Expr]) -> Option<Value> {
+        match (name, args) {
+            ("__get_str" | "__get_rel_str", [Expr::Field(field)]) => {
+                let string = self.record?.field_string(field)?;
+                Some(Value::Str(string.as_slice().into_owned()))
+            }
+            ("__get_dynamic_array" | "__get_rel_dynamic_array", [Expr::Field(field)]) => {
+                let data = self.record?.field_data(field)?;
+                Some(Value::Bytes(data.as_slice().into_owned()))
+            }
+            ("__get_dynamic_array_len" | "__get_rel_dynamic_array_len", [Expr::Field(field)]) => {
+                Some(Value::Int(self.record?.field_data(field)?.len() as i128))
+            }
+            (
+                "__print_symbolic" | "__print_symbolic_u64" | "__print_flags" | "__print_flags_u64",
+                [value, ..],
+            ) => {
+                let table = self.value_table(name, args)?;
+                let value = self.eval_int(value)? as u64;
+                Some(Value::Str(table.render(value).into_bytes()))
+            }
+            ("__print_hex" | "__print_hex_str", [buffer, len]) => {
+                let buffer = self.eval(buffer)?.into_bytes()?;
+                let len = usize::try_from(self.eval_int(len)?).ok()?;
+                let separator = if name == "__print_hex" { " " } else { "" };
+                let hex: Vec<String> = buffer
+                    .iter()
+                    .take(len)
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect();
+                Some(Value::Str(hex.join(separator).into_bytes()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Evaluates the table of a `__print_symbolic` or `__print_flags` call.
+    fn value_table(&self, name: &str, args: &[Expr]) -> Option<ValueTable> {
+        let (value, kind, entries) = match (name, args) {
+            ("__print_symbolic" | "__print_symbolic_u64", [value, entries @ ..]) => {
+                (value, ValueTableKind::Symbolic, entries)
+            }
+            ("__print_flags" | "__print_flags_u64", [value, delimiter, entries @ ..]) => {
+                let delimiter = self.eval(delimiter)?.into_bytes()?;
+                let delimiter = String::from_utf8_lossy(&delimiter).into_owned();
+                (value, ValueTableKind::Flags { delimiter }, entries)
+            }
+            _ => return None,
+        };
+        let entries = entries
+            .iter()
+            .map(|entry| self.table_entry(entry))
+            .collect::<Option<_>>()?;
+        Some(ValueTable {
+            kind,
+            field: value.field_name().map(ToOwned::to_owned),
+            entries,
+        })
+    }
+
+    /// Evaluates a `{ value, "name" }` entry of `__print_symbolic` or
+    /// `__print_flags`.
+    fn table_entry(&self, entry: &Expr) -> Option<(u64, String)> {
+        let Expr::Braces(items) = entry else {
+            return None;
+        };
+        let [value, name] = items.as_slice() else {
+            return None;
+        };
+        let name = self.eval(name)?.into_bytes()?;
+        Some((
+            self.eval_int(value)? as u64,
+            String::from_utf8_lossy(&name).into_owned(),
+        ))
+    }
+}
+
+/// The value of an integer field, sign-extended if the field is signed.
+fn int_value(value: &FieldValue) -> Option<i128> {
+    Some(match value {
+        FieldValue::U8(_) | FieldValue::U16(_) | FieldValue::U32(_) | FieldValue::U64(_) => {
+            i128::from(value.as_u64()?)
+        }
+        _ => i128::from(value.as_i64()?),
+    })
+}
+
+/// Truncates `value` to `size` bytes, and sign-extends it if `signed` is set.
+fn truncate(value: i128, size: usize, signed: bool) -> i128 {
+    let bits = size as u32 * 8;
+    if bits >= 128 {
+        return value;
+    }
+    let value = value & ((1i128 << bits) - 1);
+    if signed && value >> (bits - 1) != 0 {
+        value - (1i128 << bits)
+    } else {
+        value
+    }
+}
+
+/// The maximum field width and precision. The kernel formats trace events
+/// into a buffer of one page, so wider fields never appear in its output.
+/// This also limits the padding for `*` widths which come from field values.
+const MAX_FIELD_WIDTH: usize = 4096;
+
+#[derive(Debug, Default)]
+struct ConversionSpec {
+    left_align: bool,
+    zero_pad: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+/// Formats `args` according to the printf-style `format`, the way the
+/// kernel's `vsnprintf` does, where `long` and pointers have `long_size`
+/// bytes.
+fn printf(format: &str, args: &[Value], long_size: usize) -> Option<String> {
+    let mut output = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            output.push('%');
+            continue;
+        }
+
+        let mut spec = ConversionSpec::default();
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => spec.left_align = true,
+                '0' => spec.zero_pad = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alternate = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        if chars.peek() == Some(&'*') {
+            chars.next();
+            let width = args.next()?.as_int()?;
+            // A negative width means left alignment, as in C.
+            if width < 0 {
+                spec.left_align = true;
+            }
+            spec.width = usize::try_from(width.unsigned_abs()).unwrap_or(usize::MAX);
+        } else {
+            spec.width = parse_decimal(&mut chars)?;
+        }
+        spec.width = spec.width.min(MAX_FIELD_WIDTH);
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            let precision = if chars.peek() == Some(&'*') {
+                chars.next();
+                usize::try_from(args.next()?.as_int()?).unwrap_or(0)
+            } else {
+                parse_decimal(&mut chars)?
+            };
+            spec.precision = Some(precision.min(MAX_FIELD_WIDTH));
+        }
+        let mut size = 4;
+        let mut is_long = false;
+        while let Some(&modifier) = chars.peek() {
+            match modifier {
+                'h' => size = if size == 2 { 1 } else { 2 },
+                // `l` is `long`, and `ll` is `long long`.
+                'l' if !is_long => {
+                    size = long_size;
+                    is_long = true;
+                }
+                'z' | 'Z' | 't' => size = long_size,
+                'l' | 'L' | 'q' | 'j' => size = 8,
+                _ => break,
+            }
+            chars.next();
+        }
+
+        let conversion = chars.next()?;
+        let arg = args.next()?;
+        match conversion {
+            'd' | 'i' => {
+                let value = truncate(arg.as_int()?, size, true);
+                let sign = if value < 0 {
+                    "-"
+                } else if spec.plus {
+                    "+"
+                } else if spec.space {
+                    " "
+                } else {
+                    ""
+                };
+                pad_number(&mut output, sign, value.unsigned_abs().to_string(), &spec);
+            }
+            'u' | 'x' | 'X' | 'o' => {
+                let value = truncate(arg.as_int()?, size, false);
+                let (digits, prefix) = match conversion {
+                    'u' => (value.to_string(), ""),
+                    'x' => (format!("{value:x}"), "0x"),
+                    'X' => (format!("{value:X}"), "0X"),
+                    _ => (format!("{value:o}"), "0"),
+                };
+                let prefix = if spec.alternate && value != 0 {
+                    prefix
+                } else {
+                    ""
+                };
+                pad_number(&mut output, prefix, digits, &spec);
+            }
+            'p' => {
+                // Kernel extensions like `%pS` or `%pI4` follow the `p`.
+                while chars.peek().is_some_and(|c| c.is_ascii_alphanumeric()) {
+                    chars.next();
+                }
+                let value = truncate(arg.as_int()?, long_size, false);
+                pad_number(&mut output, "0x", format!("{value:x}"), &spec);
+            }
+            'c' => {
+                let c = char::from(truncate(arg.as_int()?, 1, false) as u8);
+                pad_string(&mut output, &c.to_string(), &spec);
+            }
+            's' => {
+                let string = match arg {
+                    Value::Int(value) => value.to_string(),
+                    Value::Str(bytes) | Value::Bytes(bytes) => {
+                        String::from_utf8_lossy(bytes).into_owned()
+                    }
+                };
+                let string = match spec.precision {
+                    Some(precision) => string.chars().take(precision).collect(),
+                    None => string,
+                };
+                pad_string(&mut output, &string, &spec);
+            }
+            _ => return None,
+        }
+    }
+    Some(output)
+}
+
+/// Parses the digits at the start of `chars`, or returns 0 if there are none.
+/// Returns `None` if the number doesn't fit into a `usize`.
+fn parse_decimal(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<usize> {
+    let mut value: usize = 0;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        value = value.checked_mul(10)?.checked_add(digit as usize)?;
+        chars.next();
+    }
+    Some(value)
+}
+
+fn pad_number(output: &mut String, prefix: &str, mut digits: String, spec: &ConversionSpec) {
+    if let Some(precision) = spec.precision {
+        if precision == 0 && digits == "0" {
+            digits.clear();
+        }
+        while digits.len() < precision {
+            digits.insert(0, '0');
+        }
+    }
+    let len = prefix.len() + digits.len();
+    let padding = spec.width.saturating_sub(len);
+    if spec.left_align {
+        output.push_str(prefix);
+        output.push_str(&digits);
+        output.push_str(&" ".repeat(padding));
+    } else if spec.zero_pad && spec.precision.is_none() {
+        output.push_str(prefix);
+        output.push_str(&"0".repeat(padding));
+        output.push_str(&digits);
+    } else {
+        output.push_str(&" ".repeat(padding));
+        output.push_str(prefix);
+        output.push_str(&digits);
+    }
+}
+
+fn pad_string(output: &mut String, string: &str, spec: &ConversionSpec) {
+    let padding = spec.width.saturating_sub(string.chars().count());
+    if spec.left_align {
+        output.push_str(string);
+        output.push_str(&" ".repeat(padding));
+    } else {
+        output.push_str(&" ".repeat(padding));
+        output.push_str(string);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{printf, Expr, PrintFmt, Value, ValueTable, ValueTableKind};
+    use crate::tracepoint::tracing_data::test::SCHED_SWITCH;
+    use crate::tracepoint::EventFormat;
+
+    #[test]
+    fn printf_conversions() {
+        let args = [
+            Value::Int(-5),
+            Value::Int(255),
+            Value::Int(-1),
+            Value::Str(b"abc".to_vec()),
+            Value::Int(7),
+            Value::Int(0xffff_ffff_8100_0000),
+        ];
+        assert_eq!(
+            printf("%d %#x %u [%-5s] %03d %pS", &args, 8).unwrap(),
+            "-5 0xff 4294967295 [abc  ] 007 0xffffffff81000000"
+        );
+        assert_eq!(
+            printf("%lld %lu %d %5.2s|%%", &args[..4], 8).unwrap(),
+            "-5 255 -1    ab|%"
+        );
+        // Too few arguments.
+        assert_eq!(printf("%d %d", &args[..1], 8), None);
+
+        // On 32-bit machines, `long` and pointers have 4 bytes.
+        let args = [
+            Value::Int(-1),
+            Value::Int(-1),
+            Value::Int(-1),
+            Value::Int(0xffff_ffff_8100_0000),
+        ];
+        assert_eq!(
+            printf("%ld %lu %lld %p", &args, 4).unwrap(),
+            "-1 4294967295 -1 0x81000000"
+        );
+    }
+
+    #[test]
+    fn printf_width_limits() {
+        let args = [
+            Value::Int(-3),
+            Value::Int(7),
+            Value::Int(1 << 40),
+            Value::Int(7),
+        ];
+        assert_eq!(printf("[%*d]", &args[..2], 8).unwrap(), "[7  ]");
+        assert_eq!(
+            printf("%*d", &args[2..], 8).unwrap().len(),
+            super::MAX_FIELD_WIDTH
+        );
+        assert_eq!(
+            printf("%.*d", &args[2..], 8).unwrap().len(),
+            super::MAX_FIELD_WIDTH
+        );
+        assert_eq!(printf("%99999999999999999999999d", &args[1..2], 8), None);
+        assert_eq!(
+            printf("%99999d", &args[1..2], 8).unwrap().len(),
+            super::MAX_FIELD_WIDTH
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let print_fmt = PrintFmt::parse(
+            "\"comm=%s pid=%d\" \" flags=%s\", REC->comm, (int)REC->pid, __print_flags(REC->flags, \"|\", { 0x01, \"A\" }, { 0x02UL, \"B\" })",
+        )
+        .unwrap();
+        assert_eq!(print_fmt.format_string(), "comm=%s pid=%d flags=%s");
+        assert_eq!(print_fmt.args.len(), 3);
+        assert_eq!(
+            print_fmt.value_tables(),
+            &[ValueTable {
+                kind: ValueTableKind::Flags {
+                    delimiter: "|".to_owned()
+                },
+                field: Some("flags".to_owned()),
+                entries: vec![(1, "A".to_owned()), (2, "B".to_owned())],
+            }]
+        );
+        assert!(PrintFmt::parse("REC->comm").is_err());
+        let nested = format!("\"%d\", {}1{}", "(".repeat(100), ")".repeat(100));
+        assert!(PrintFmt::parse(&nested).is_err());
+        let chained = format!("\"%d\", 1{}", " + 1".repeat(100));
+        assert!(PrintFmt::parse(&chained).is_err());
+        let ternaries = format!("\"%d\", {}1", "1 ? 1 : ".repeat(100));
+        assert!(PrintFmt::parse(&ternaries).is_err());
+        assert!(PrintFmt::parse("\"%d\", ((1 + 2) * -(3))").is_ok());
+        assert!(PrintFmt::parse("\"%d\", (REC->pid").is_err());
+
+        let cast = PrintFmt::parse_with_long_size("\"%lx\", (unsigned long)REC->addr", 4).unwrap();
+        assert!(matches!(
+            cast.args[0],
+            Expr::Cast {
+                size_and_signedness: Some((4, false)),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn value_tables() {
+        let format = EventFormat::parse(SCHED_SWITCH).unwrap();
+        let print_fmt = PrintFmt::parse(&format.print_fmt).unwrap();
+        let table = print_fmt.value_table("prev_state").unwrap();
+        assert_eq!(table.entries.len(), 8);
+        assert_eq!(table.entries[1], (0x2, "D".to_owned()));
+        assert_eq!(table.render(0x3), "S|D");
+        assert_eq!(table.render(0x102), "D|0x100");
+        assert!(print_fmt.value_table("next_pid").is_none());
+
+        let symbolic = PrintFmt::parse(
+            "\"%s\", __print_symbolic((int)REC->vec, { 0, \"HI\" }, { 1 << 1, \"NET_TX\" })",
+        )
+        .unwrap();
+        let table = symbolic.value_table("vec").unwrap();
+        assert_eq!(table.kind, ValueTableKind::Symbolic);
+        assert_eq!(table.render(2), "NET_TX");
+        assert_eq!(table.render(0), "HI");
+        assert_eq!(table.render(5), "0x5");
+
+        // Entries which aren't constants.
+        let dynamic = PrintFmt::parse("\"%s\", __print_symbolic(REC->a, { REC->b, \"B\" })");
+        assert_eq!(dynamic.unwrap().value_tables(), &[]);
+    }
+}
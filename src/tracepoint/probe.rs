@@ -0,0 +1,201 @@
+use super::{FieldValue, TracepointRecord};
+
+/// Whether a [`ProbeEvent`] was hit on entry to the probed location or on
+/// return from the probed function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeKind {
+    /// A kprobe or uprobe, e.g. from `perf probe -a do_sys_open`.
+    Entry,
+    /// A kretprobe or uretprobe, e.g. from `perf probe -a do_sys_open%return`.
+    Return,
+}
+
+/// A dynamic probe event, i.e. a kprobe, kretprobe, uprobe or uretprobe,
+/// e.g. created with `perf probe`.
+///
+/// Probe events don't have a fixed layout. Their format has a field for the
+/// probed address, followed by one field per argument which was requested
+/// when the probe was created. These are named `arg1`, `arg2` etc. unless a
+/// name was given, e.g. `perf probe -a 'do_sys_open filename:string'`.
+/// Integer arguments are read with the requested size, e.g. `u64` or `s32`,
+/// and `string` arguments are `__data_loc` strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeEvent<'a> {
+    pub kind: ProbeKind,
+    /// The address of the probe (`__probe_ip`), or, for return probes, the
+    /// address of the probed function (`__probe_func`).
+    pub ip: u64,
+    /// For return probes, the address which the probed function returned
+    /// to (`__probe_ret_ip`).
+    pub return_ip: Option<u64>,
+    /// The names and values of the arguments, in the order in which they
+    /// were defined.
+    pub args: Vec<(&'a str, FieldValue<'a>)>,
+}
+
+impl<'a> ProbeEvent<'a> {
+    /// Reads the fields of a probe event record. Returns `None` if the
+    /// record's format doesn't have the fields of a probe event, or if the
+    /// address fields can't be read.
+    pub fn from_record(record: &TracepointRecord<'a>) -> Option<Self> {
+        let (kind, ip, return_ip) = match record.format.fields.first()?.name.as_str() {
+            "__probe_ip" => (
+                ProbeKind::Entry,
+                record.field("__probe_ip")?.as_u64()?,
+                None,
+            ),
+            "__probe_func" => (
+                ProbeKind::Return,
+                record.field("__probe_func")?.as_u64()?,
+                Some(record.field("__probe_ret_ip")?.as_u64()?),
+            ),
+            _ => return None,
+        };
+        let args = record
+            .field_values()
+            .filter(|(name, _)| !name.starts_with("__probe_"))
+            .collect();
+        Some(Self {
+            kind,
+            ip,
+            return_ip,
+            args,
+        })
+    }
+
+    /// The value of the argument `name`.
+    pub fn arg(&self, name: &str) -> Option<&FieldValue<'a>> {
+        self.args
+            .iter()
+            .find(|(arg_name, _)| *arg_name == name)
+            .map(|(_, value)| value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use linux_perf_event_reader::{Endianness, PerfEventAttr, PerfEventType, SoftwareCounterType};
+
+    use super::{ProbeEvent, ProbeKind};
+    use crate::file_writer::test::test_attr;
+    use crate::tracepoint::decoder::test::sched_wakeup_raw;
+    use crate::tracepoint::tracing_data::test::{tracing_data_section, SCHED_WAKEUP};
+    use crate::tracepoint::{FieldValue, TracepointDecoder, TracingData};
+
+    /// The format of `perf probe -a 'do_sys_openat2 dfd:s32 filename:string flags=how->flags:u64'`.
+    const KPROBE: &str = "name: do_sys_openat2
+ID: 1892
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:unsigned long __probe_ip;\toffset:8;\tsize:8;\tsigned:0;
+\tfield:s32 dfd;\toffset:16;\tsize:4;\tsigned:1;
+\tfield:__data_loc char[] filename;\toffset:20;\tsize:4;\tsigned:1;
+\tfield:u64 flags;\toffset:24;\tsize:8;\tsigned:0;
+
+print fmt: \"(%lx) dfd=%d filename=\\\"%s\\\" flags=%Lu\", REC->__probe_ip, REC->dfd, __get_str(filename), REC->flags
+";
+
+    /// The format of `perf probe -x /lib/libc.so.6 -a 'malloc%return $retval'`.
+    const URETPROBE: &str = "name: malloc__return
+ID: 1893
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:unsigned long __probe_func;\toffset:8;\tsize:8;\tsigned:0;
+\tfield:unsigned long __probe_ret_ip;\toffset:16;\tsize:8;\tsigned:0;
+\tfield:u64 arg1;\toffset:24;\tsize:8;\tsigned:0;
+
+print fmt: \"(%lx <- %lx) arg1=0x%Lx\", REC->__probe_func, REC->__probe_ret_ip, REC->arg1
+";
+
+    #[test]
+    fn probe_events() {
+        let tracing_data = TracingData::parse(&tracing_data_section(
+            Endianness::LittleEndian,
+            &[
+                ("probe", KPROBE),
+                ("probe_libc", URETPROBE),
+                ("sched", SCHED_WAKEUP),
+            ],
+        ))
+        .unwrap();
+        let decoder = TracepointDecoder::from_tracing_data(tracing_data);
+        let attr = |id| PerfEventAttr {
+            type_: PerfEventType::Tracepoint(id),
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+
+        let mut raw = Vec::new();
+        raw.write_u16::<LittleEndian>(1892).unwrap();
+        raw.extend_from_slice(&[0; 2]);
+        raw.write_i32::<LittleEndian>(42).unwrap();
+        raw.write_u64::<LittleEndian>(0xffff_ffff_8123_4560)
+            .unwrap();
+        raw.write_i32::<LittleEndian>(-100).unwrap();
+        raw.write_u32::<LittleEndian>((12 << 16) | 32).unwrap();
+        raw.write_u64::<LittleEndian>(0x80000).unwrap();
+        raw.extend_from_slice(b"/etc/passwd\0");
+        let record = decoder
+            .decode(&attr(1892), (&raw[..]).into())
+            .unwrap()
+            .unwrap();
+        let probe = ProbeEvent::from_record(&record).unwrap();
+        assert_eq!(probe.kind, ProbeKind::Entry);
+        assert_eq!((probe.ip, probe.return_ip), (0xffff_ffff_8123_4560, None));
+        assert_eq!(
+            probe.args,
+            vec![
+                ("dfd", FieldValue::I32(-100)),
+                ("filename", FieldValue::String(b"/etc/passwd"[..].into())),
+                ("flags", FieldValue::U64(0x80000)),
+            ]
+        );
+        assert_eq!(
+            probe.arg("filename").and_then(FieldValue::as_str),
+            Some("/etc/passwd")
+        );
+        assert_eq!(
+            record.formatted().unwrap(),
+            "(ffffffff81234560) dfd=-100 filename=\"/etc/passwd\" flags=524288"
+        );
+
+        let mut raw = Vec::new();
+        raw.write_u16::<LittleEndian>(1893).unwrap();
+        raw.extend_from_slice(&[0; 2]);
+        raw.write_i32::<LittleEndian>(42).unwrap();
+        raw.write_u64::<LittleEndian>(0x7f00_0000_1000).unwrap();
+        raw.write_u64::<LittleEndian>(0x5555_0000_2000).unwrap();
+        raw.write_u64::<LittleEndian>(0x5555_1234_0000).unwrap();
+        let record = decoder
+            .decode(&attr(1893), (&raw[..]).into())
+            .unwrap()
+            .unwrap();
+        let probe = ProbeEvent::from_record(&record).unwrap();
+        assert_eq!(probe.kind, ProbeKind::Return);
+        assert_eq!(
+            (probe.ip, probe.return_ip),
+            (0x7f00_0000_1000, Some(0x5555_0000_2000))
+        );
+        assert_eq!(probe.arg("arg1"), Some(&FieldValue::U64(0x5555_1234_0000)));
+        assert_eq!(
+            record.formatted().unwrap(),
+            "(7f0000001000 <- 555500002000) arg1=0x555512340000"
+        );
+
+        // Not a probe event.
+        let raw = sched_wakeup_raw(b"cc1", 11, 120, 2);
+        let record = decoder
+            .decode(&attr(318), (&raw[..]).into())
+            .unwrap()
+            .unwrap();
+        assert!(ProbeEvent::from_record(&record).is_none());
+    }
+}
@@ -0,0 +1,193 @@
+use linux_perf_event_reader::RawData;
+
+use crate::analysis::OffCpuState;
+
+use super::TracepointRecord;
+
+/// A `sched:sched_switch` event: the CPU switched from the `prev` task to
+/// the `next` task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedSwitch<'a> {
+    pub prev_comm: RawData<'a>,
+    pub prev_pid: i32,
+    pub prev_prio: i32,
+    /// The state of the `prev` task, see [`SchedSwitch::prev_off_cpu_state`].
+    pub prev_state: i64,
+    pub next_comm: RawData<'a>,
+    pub next_pid: i32,
+    pub next_prio: i32,
+}
+
+impl<'a> SchedSwitch<'a> {
+    /// Reads the fields of a `sched_switch` record. Returns `None` if the
+    /// record is a different event or if a field is missing.
+    pub fn from_record(record: &TracepointRecord<'a>) -> Option<Self> {
+        if record.name() != "sched_switch" {
+            return None;
+        }
+        Some(Self {
+            prev_comm: record.field_string("prev_comm")?,
+            prev_pid: int_field(record, "prev_pid")? as i32,
+            prev_prio: int_field(record, "prev_prio")? as i32,
+            prev_state: int_field(record, "prev_state")?,
+            next_comm: record.field_string("next_comm")?,
+            next_pid: int_field(record, "next_pid")? as i32,
+            next_prio: int_field(record, "next_prio")? as i32,
+        })
+    }
+
+    /// Interprets `prev_state` for off-CPU analysis.
+    pub fn prev_off_cpu_state(&self) -> OffCpuState {
+        OffCpuState::from_prev_state(self.prev_state as u64)
+    }
+}
+
+/// A `sched:sched_wakeup`, `sched:sched_wakeup_new` or `sched:sched_waking`
+/// event. `sched_waking` is emitted by the waker when it starts waking the
+/// task, and `sched_wakeup` once the task has been put on a run queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedWakeup<'a> {
+    pub comm: RawData<'a>,
+    pub pid: i32,
+    pub prio: i32,
+    /// The CPU whose run queue the task is put on.
+    pub target_cpu: i32,
+}
+
+impl<'a> SchedWakeup<'a> {
+    /// Reads the fields of a `sched_wakeup`, `sched_wakeup_new` or
+    /// `sched_waking` record. Returns `None` if the record is a different
+    /// event or if a field is missing.
+    pub fn from_record(record: &TracepointRecord<'a>) -> Option<Self> {
+        if !matches!(
+            record.name(),
+            "sched_wakeup" | "sched_wakeup_new" | "sched_waking"
+        ) {
+            return None;
+        }
+        Some(Self {
+            comm: record.field_string("comm")?,
+            pid: int_field(record, "pid")? as i32,
+            prio: int_field(record, "prio")? as i32,
+            target_cpu: int_field(record, "target_cpu")? as i32,
+        })
+    }
+}
+
+/// A `sched:sched_process_exit` event: a task exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedProcessExit<'a> {
+    pub comm: RawData<'a>,
+    pub pid: i32,
+    pub prio: i32,
+    /// Whether this was the last task of its thread group, i.e. whether the
+    /// process exited. Only present since Linux 6.10.
+    pub group_dead: Option<bool>,
+}
+
+impl<'a> SchedProcessExit<'a> {
+    /// Reads the fields of a `sched_process_exit` record. Returns `None` if
+    /// the record is a different event or if a field is missing.
+    pub fn from_record(record: &TracepointRecord<'a>) -> Option<Self> {
+        if record.name() != "sched_process_exit" {
+            return None;
+        }
+        Some(Self {
+            comm: record.field_string("comm")?,
+            pid: int_field(record, "pid")? as i32,
+            prio: int_field(record, "prio")? as i32,
+            group_dead: int_field(record, "group_dead").map(|value| value != 0),
+        })
+    }
+}
+
+/// One of the scheduler tracepoints which this crate has typed structs for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SchedEvent<'a> {
+    Switch(SchedSwitch<'a>),
+    /// `sched_wakeup` or `sched_wakeup_new`.
+    Wakeup(SchedWakeup<'a>),
+    Waking(SchedWakeup<'a>),
+    ProcessExit(SchedProcessExit<'a>),
+}
+
+impl<'a> SchedEvent<'a> {
+    /// Reads `record` into the typed struct for its event. Returns `None` if
+    /// the record is not one of the supported scheduler events.
+    pub fn from_record(record: &TracepointRecord<'a>) -> Option<Self> {
+        if !matches!(record.system(), "sched" | "") {
+            return None;
+        }
+        match record.name() {
+            "sched_switch" => SchedSwitch::from_record(record).map(Self::Switch),
+            "sched_wakeup" | "sched_wakeup_new" => {
+                SchedWakeup::from_record(record).map(Self::Wakeup)
+            }
+            "sched_waking" => SchedWakeup::from_record(record).map(Self::Waking),
+            "sched_process_exit" => SchedProcessExit::from_record(record).map(Self::ProcessExit),
+            _ => None,
+        }
+    }
+}
+
+fn int_field(record: &TracepointRecord, name: &str) -> Option<i64> {
+    record.field(name)?.as_i64()
+}
+
+#[cfg(test)]
+mod test {
+    use linux_perf_event_reader::{Endianness, PerfEventAttr, PerfEventType, SoftwareCounterType};
+
+    use super::{SchedEvent, SchedSwitch, SchedWakeup};
+    use crate::analysis::OffCpuState;
+    use crate::file_writer::test::test_attr;
+    use crate::tracepoint::decoder::test::{sched_switch_raw, sched_wakeup_raw};
+    use crate::tracepoint::tracing_data::test::{tracing_data_section, SCHED_SWITCH, SCHED_WAKEUP};
+    use crate::tracepoint::{TracepointDecoder, TracingData};
+
+    #[test]
+    fn sched_events() {
+        let tracing_data = TracingData::parse(&tracing_data_section(
+            Endianness::LittleEndian,
+            &[("sched", SCHED_SWITCH), ("sched", SCHED_WAKEUP)],
+        ))
+        .unwrap();
+        let decoder = TracepointDecoder::from_tracing_data(tracing_data);
+        let attr = |id| PerfEventAttr {
+            type_: PerfEventType::Tracepoint(id),
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+
+        let raw = sched_switch_raw((b"bash", 10), 2, (b"cc1", 11));
+        let record = decoder
+            .decode(&attr(316), (&raw[..]).into())
+            .unwrap()
+            .unwrap();
+        let switch = SchedSwitch::from_record(&record).unwrap();
+        assert_eq!(switch.prev_comm.as_slice(), &b"bash"[..]);
+        assert_eq!((switch.prev_pid, switch.next_pid), (10, 11));
+        assert_eq!(switch.next_comm.as_slice(), &b"cc1"[..]);
+        assert_eq!(switch.prev_prio, 120);
+        assert_eq!(
+            switch.prev_off_cpu_state(),
+            OffCpuState::UninterruptibleSleep
+        );
+        assert!(SchedWakeup::from_record(&record).is_none());
+        assert_eq!(
+            SchedEvent::from_record(&record),
+            Some(SchedEvent::Switch(switch))
+        );
+
+        let raw = sched_wakeup_raw(b"cc1", 11, 120, 2);
+        let record = decoder
+            .decode(&attr(318), (&raw[..]).into())
+            .unwrap()
+            .unwrap();
+        let Some(SchedEvent::Wakeup(wakeup)) = SchedEvent::from_record(&record) else {
+            panic!("expected a wakeup");
+        };
+        assert_eq!((wakeup.pid, wakeup.target_cpu), (11, 2));
+        assert!(SchedSwitch::from_record(&record).is_none());
+    }
+}
@@ -0,0 +1,125 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use linux_perf_event_reader::Endianness;
+
+use super::{EventFormat, TracepointDecoder};
+use crate::Error;
+
+/// The `events` directories of the places where tracefs is usually mounted,
+/// in the order in which [`TracepointDecoder::from_live_tracefs`] tries them.
+pub const TRACEFS_EVENTS_DIRS: &[&str] = &[
+    "/sys/kernel/tracing/events",
+    "/sys/kernel/debug/tracing/events",
+];
+
+impl EventFormat {
+    /// Reads the format of the event `system:name` from a tracefs `events`
+    /// directory, i.e. the file `<events_dir>/<system>/<name>/format`.
+    pub fn from_tracefs(
+        events_dir: impl AsRef<Path>,
+        system: &str,
+        name: &str,
+    ) -> Result<Self, Error> {
+        let path = events_dir.as_ref().join(system).join(name).join("format");
+        let mut format = Self::parse(&std::fs::read_to_string(path)?)?;
+        format.system = system.to_owned();
+        Ok(format)
+    }
+}
+
+impl TracepointDecoder {
+    /// Creates a decoder with the formats of all events in a tracefs `events`
+    /// directory, e.g. `/sys/kernel/tracing/events`, or in a copy of such a
+    /// directory.
+    ///
+    /// Use this for files which don't have a `TRACING_DATA` section, e.g.
+    /// files from simpleperf. The formats must come from the kernel which
+    /// recorded the file, or from one with the same tracepoint layouts. The
+    /// raw data is assumed to be in the byte order of this machine; use
+    /// [`TracepointDecoder::with_endian`] with
+    /// [`PerfFile::endian`](crate::PerfFile::endian) for files from a machine
+    /// with a different byte order.
+    ///
+    /// Event directories without a format file, and format files which can't
+    /// be parsed, are skipped.
+    pub fn from_tracefs_dir(events_dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut formats = Vec::new();
+        for system_entry in std::fs::read_dir(events_dir.as_ref())? {
+            let system_entry = system_entry?;
+            if !system_entry.file_type()?.is_dir() {
+                // E.g. `header_page` or `enable`.
+                continue;
+            }
+            let system = system_entry.file_name().to_string_lossy().into_owned();
+            for event_entry in std::fs::read_dir(system_entry.path())? {
+                let event_entry = event_entry?;
+                if !event_entry.file_type()?.is_dir() {
+                    continue;
+                }
+                let text = match std::fs::read_to_string(event_entry.path().join("format")) {
+                    Ok(text) => text,
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                if let Ok(mut format) = EventFormat::parse(&text) {
+                    format.system.clone_from(&system);
+                    formats.push(format);
+                }
+            }
+        }
+        Ok(Self::new(formats, Endianness::NATIVE))
+    }
+
+    /// Creates a decoder with the formats of the running kernel, from the
+    /// first directory in [`TRACEFS_EVENTS_DIRS`] which exists. Reading the
+    /// formats usually requires root privileges.
+    pub fn from_live_tracefs() -> Result<Self, Error> {
+        let events_dir = TRACEFS_EVENTS_DIRS
+            .iter()
+            .map(PathBuf::from)
+            .find(|dir| dir.is_dir())
+            .ok_or_else(|| std::io::Error::from(ErrorKind::NotFound))?;
+        Self::from_tracefs_dir(events_dir)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use linux_perf_event_reader::Endianness;
+
+    use crate::tracepoint::tracing_data::test::{SCHED_SWITCH, SCHED_WAKEUP};
+    use crate::tracepoint::{EventFormat, TracepointDecoder};
+
+    #[test]
+    fn tracefs_dir() {
+        let dir = std::env::temp_dir().join(format!("tracefs-test-{}", std::process::id()));
+        for (event, format) in [
+            ("sched_switch", SCHED_SWITCH),
+            ("sched_wakeup", SCHED_WAKEUP),
+            ("broken", "name: broken\n"),
+        ] {
+            let event_dir = dir.join("sched").join(event);
+            std::fs::create_dir_all(&event_dir).unwrap();
+            std::fs::write(event_dir.join("format"), format).unwrap();
+            std::fs::write(event_dir.join("enable"), "0\n").unwrap();
+        }
+        std::fs::write(dir.join("sched").join("enable"), "0\n").unwrap();
+        std::fs::create_dir_all(dir.join("empty").join("no_format")).unwrap();
+        std::fs::write(dir.join("header_page"), "").unwrap();
+
+        let decoder = TracepointDecoder::from_tracefs_dir(&dir).unwrap();
+        assert_eq!(decoder.endian(), Endianness::NATIVE);
+        assert_eq!(decoder.formats().count(), 2);
+        let format = decoder.format(316).unwrap();
+        assert_eq!(
+            (format.system.as_str(), format.name.as_str()),
+            ("sched", "sched_switch")
+        );
+        let format = EventFormat::from_tracefs(&dir, "sched", "sched_wakeup").unwrap();
+        assert_eq!((format.system.as_str(), format.id), ("sched", 318));
+        assert!(EventFormat::from_tracefs(&dir, "sched", "missing").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::HashMap;
+
+use linux_perf_event_reader::Endianness;
+
+use super::{EventFormat, TracepointError};
+
+const MAGIC: &[u8] = b"\x17\x08\x44tracing";
+
+/// The contents of the `TRACING_DATA` feature section, which `perf record`
+/// writes when tracepoint events are recorded. It contains copies of the
+/// tracefs format files of the recorded events, so that the raw tracepoint
+/// data in the samples can be decoded.
+///
+/// The integers in the section use the byte order in `endian`, which is the
+/// byte order of the machine which recorded the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracingData {
+    /// The version of the section layout, e.g. `0.6`.
+    pub version: String,
+    pub endian: Endianness,
+    /// The size of a `long` on the recording machine, in bytes.
+    pub long_size: u8,
+    pub page_size: u32,
+    /// The contents of `events/header_page`, which describes the header of
+    /// the ring buffer pages.
+    pub header_page: String,
+    /// The contents of `events/header_event`, which describes the header of
+    /// the ring buffer entries.
+    pub header_event: String,
+    /// The formats of the ftrace events, with the system `ftrace`.
+    pub ftrace_formats: Vec<EventFormat>,
+    /// The formats of the recorded tracepoint events.
+    pub event_formats: Vec<EventFormat>,
+    /// The contents of `/proc/kallsyms`, if perf included them.
+    pub kallsyms: Vec<u8>,
+    /// The contents of `printk_formats`, which maps the addresses of format
+    /// strings to the strings.
+    pub printk_formats: Vec<u8>,
+    /// The contents of `saved_cmdlines`, which maps pids to command names.
+    /// Empty for versions before 0.6.
+    pub saved_cmdlines: Vec<u8>,
+}
+
+impl TracingData {
+    pub fn parse(data: &[u8]) -> Result<Self, TracepointError> {
+        // "\x17\x08\x44tracing"
+        // char version[]; /* nul-terminated */
+        // u8 big_endian;
+        // u8 long_size;
+        // u32 page_size;
+        // "header_page\0", u64 size, char data[size]
+        // "header_event\0", u64 size, char data[size]
+        // u32 ftrace_count, ftrace_count * (u64 size, char format[size])
+        // u32 system_count, system_count * (
+        //     char system[]; /* nul-terminated */
+        //     u32 event_count, event_count * (u64 size, char format[size])
+        // )
+        // u32 size, char kallsyms[size]
+        // u32 size, char printk_formats[size]
+        // u64 size, char saved_cmdlines[size] /* since version 0.6 */
+        let mut reader = Reader {
+            data,
+            endian: Endianness::LittleEndian,
+        };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(TracepointError::UnrecognizedMagic);
+        }
+        let version = reader.string()?.to_owned();
+        reader.endian = match reader.bytes(1)?[0] {
+            0 => Endianness::LittleEndian,
+            _ => Endianness::BigEndian,
+        };
+        let long_size = reader.bytes(1)?[0];
+        let page_size = reader.u32()?;
+
+        if reader.string()? != "header_page" {
+            return Err(TracepointError::MissingHeader("header_page"));
+        }
+        let header_page = reader.text_u64()?.to_owned();
+        if reader.string()? != "header_event" {
+            return Err(TracepointError::MissingHeader("header_event"));
+        }
+        let header_event = reader.text_u64()?.to_owned();
+
+        let ftrace_count = reader.u32()?;
+        let mut ftrace_formats = Vec::new();
+        for _ in 0..ftrace_count {
+            let mut format = EventFormat::parse(reader.text_u64()?)?;
+            format.system = "ftrace".to_owned();
+            ftrace_formats.push(format);
+        }
+
+        let system_count = reader.u32()?;
+        let mut event_formats = Vec::new();
+        for _ in 0..system_count {
+            let system = reader.string()?;
+            let event_count = reader.u32()?;
+            for _ in 0..event_count {
+                let mut format = EventFormat::parse(reader.text_u64()?)?;
+                format.system = system.to_owned();
+                event_formats.push(format);
+            }
+        }
+
+        let len = reader.u32()? as usize;
+        let kallsyms = reader.bytes(len)?.to_vec();
+        let len = reader.u32()? as usize;
+        let printk_formats = reader.bytes(len)?.to_vec();
+        let saved_cmdlines = if version_at_least_0_6(&version) && !reader.data.is_empty() {
+            let len = reader.usize_u64()?;
+            reader.bytes(len)?.to_vec()
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            version,
+            endian: reader.endian,
+            long_size,
+            page_size,
+            header_page,
+            header_event,
+            ftrace_formats,
+            event_formats,
+            kallsyms,
+            printk_formats,
+            saved_cmdlines,
+        })
+    }
+
+    /// Looks up the format of the event with the tracepoint ID `id`, which is
+    /// the `config` value of the event's attribute.
+    pub fn event_format(&self, id: u64) -> Option<&EventFormat> {
+        self.event_formats
+            .iter()
+            .chain(self.ftrace_formats.iter())
+            .find(|format| format.id == id)
+    }
+
+    /// Maps the tracepoint ID of each event format to the event's
+    /// `system:name`, see [`EventFormat::full_name`].
+    pub fn event_names(&self) -> HashMap<u64, String> {
+        self.ftrace_formats
+            .iter()
+            .chain(self.event_formats.iter())
+            .map(|format| (format.id, format.full_name()))
+            .collect()
+    }
+}
+
+fn version_at_least_0_6(version: &str) -> bool {
+    let mut parts = version
+        .split('.')
+        .map(|part| part.parse::<u32>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+    (major, minor) >= (0, 6)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    endian: Endianness,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], TracepointError> {
+        if self.data.len() < len {
+            return Err(TracepointError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, TracepointError> {
+        let bytes = self.bytes(4)?.try_into().unwrap();
+        Ok(match self.endian {
+            Endianness::LittleEndian => u32::from_le_bytes(bytes),
+            Endianness::BigEndian => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn usize_u64(&mut self) -> Result<usize, TracepointError> {
+        let bytes = self.bytes(8)?.try_into().unwrap();
+        let value = match self.endian {
+            Endianness::LittleEndian => u64::from_le_bytes(bytes),
+            Endianness::BigEndian => u64::from_be_bytes(bytes),
+        };
+        usize::try_from(value).map_err(|_| TracepointError::UnexpectedEnd)
+    }
+
+    /// A nul-terminated string.
+    fn string(&mut self) -> Result<&'a str, TracepointError> {
+        let len = memchr::memchr(0, self.data).ok_or(TracepointError::UnexpectedEnd)?;
+        let s = std::str::from_utf8(self.bytes(len)?).map_err(|_| TracepointError::StringUtf8)?;
+        self.bytes(1)?;
+        Ok(s)
+    }
+
+    /// A text with a u64 length prefix.
+    fn text_u64(&mut self) -> Result<&'a str, TracepointError> {
+        let len = self.usize_u64()?;
+        std::str::from_utf8(self.bytes(len)?).map_err(|_| TracepointError::StringUtf8)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::io::Cursor;
+
+    use linux_perf_event_reader::{Endianness, PerfEventAttr, PerfEventType, SoftwareCounterType};
+
+    use super::TracingData;
+    use crate::file_writer::test::test_attr;
+    use crate::tracepoint::TracepointError;
+    use crate::{Feature, FeatureValue, PerfFileReader, PerfFileWriter};
+
+    /// Builds a `TRACING_DATA` section with the given `(system, format)`
+    /// pairs, in the given byte order.
+    pub(crate) fn tracing_data_section(endian: Endianness, formats: &[(&str, &str)]) -> Vec<u8> {
+        let u32_bytes = |value: u32| match endian {
+            Endianness::LittleEndian => value.to_le_bytes(),
+            Endianness::BigEndian => value.to_be_bytes(),
+        };
+        let u64_bytes = |value: u64| match endian {
+            Endianness::LittleEndian => value.to_le_bytes(),
+            Endianness::BigEndian => value.to_be_bytes(),
+        };
+        let mut data = b"\x17\x08\x44tracing0.6\0".to_vec();
+        data.push(u8::from(endian == Endianness::BigEndian));
+        data.push(8);
+        data.extend_from_slice(&u32_bytes(4096));
+        data.extend_from_slice(b"header_page\0");
+        data.extend_from_slice(&u64_bytes(0));
+        data.extend_from_slice(b"header_event\0");
+        data.extend_from_slice(&u64_bytes(0));
+        data.extend_from_slice(&u32_bytes(0));
+        data.extend_from_slice(&u32_bytes(formats.len() as u32));
+        for (system, format) in formats {
+            data.extend_from_slice(system.as_bytes());
+            data.push(0);
+            data.extend_from_slice(&u32_bytes(1));
+            data.extend_from_slice(&u64_bytes(format.len() as u64));
+            data.extend_from_slice(format.as_bytes());
+        }
+        data.extend_from_slice(&u32_bytes(0));
+        data.extend_from_slice(&u32_bytes(0));
+        data.extend_from_slice(&u64_bytes(7));
+        data.extend_from_slice(b"42 foo\n");
+        data
+    }
+
+    pub(crate) const SCHED_WAKEUP: &str = "name: sched_wakeup
+ID: 318
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:char comm[16];\toffset:8;\tsize:16;\tsigned:0;
+\tfield:pid_t pid;\toffset:24;\tsize:4;\tsigned:1;
+\tfield:int prio;\toffset:28;\tsize:4;\tsigned:1;
+\tfield:int target_cpu;\toffset:32;\tsize:4;\tsigned:1;
+
+print fmt: \"comm=%s pid=%d prio=%d target_cpu=%03d\", REC->comm, REC->pid, REC->prio, REC->target_cpu
+";
+
+    pub(crate) const SCHED_SWITCH: &str = "name: sched_switch
+ID: 316
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:char prev_comm[16];\toffset:8;\tsize:16;\tsigned:0;
+\tfield:pid_t prev_pid;\toffset:24;\tsize:4;\tsigned:1;
+\tfield:int prev_prio;\toffset:28;\tsize:4;\tsigned:1;
+\tfield:long prev_state;\toffset:32;\tsize:8;\tsigned:1;
+\tfield:char next_comm[16];\toffset:40;\tsize:16;\tsigned:0;
+\tfield:pid_t next_pid;\toffset:56;\tsize:4;\tsigned:1;
+\tfield:int next_prio;\toffset:60;\tsize:4;\tsigned:1;
+
+print fmt: \"prev_comm=%s prev_pid=%d prev_prio=%d prev_state=%s%s ==> next_comm=%s next_pid=%d next_prio=%d\", REC->prev_comm, REC->prev_pid, REC->prev_prio, (REC->prev_state & ((((0x00000000 | 0x00000001 | 0x00000002 | 0x00000004 | 0x00000008 | 0x00000010 | 0x00000020 | 0x00000040) + 1) << 1) - 1)) ? __print_flags(REC->prev_state & ((((0x00000000 | 0x00000001 | 0x00000002 | 0x00000004 | 0x00000008 | 0x00000010 | 0x00000020 | 0x00000040) + 1) << 1) - 1), \"|\", { 0x00000001, \"S\" }, { 0x00000002, \"D\" }, { 0x00000004, \"T\" }, { 0x00000008, \"t\" }, { 0x00000010, \"X\" }, { 0x00000020, \"Z\" }, { 0x00000040, \"P\" }, { 0x00000080, \"I\" }) : \"R\", REC->prev_state & (((0x00000000 | 0x00000001 | 0x00000002 | 0x00000004 | 0x00000008 | 0x00000010 | 0x00000020 | 0x00000040) + 1) << 1) ? \"+\" : \"\", REC->next_comm, REC->next_pid, REC->next_prio
+";
+
+    /// A format with `__data_loc` strings and `__print_symbolic`.
+    pub(crate) const IRQ_HANDLER_ENTRY: &str = "name: irq_handler_entry
+ID: 120
+format:
+\tfield:unsigned short common_type;\toffset:0;\tsize:2;\tsigned:0;
+\tfield:unsigned char common_flags;\toffset:2;\tsize:1;\tsigned:0;
+\tfield:unsigned char common_preempt_count;\toffset:3;\tsize:1;\tsigned:0;
+\tfield:int common_pid;\toffset:4;\tsize:4;\tsigned:1;
+
+\tfield:int irq;\toffset:8;\tsize:4;\tsigned:1;
+\tfield:__data_loc char[] name;\toffset:12;\tsize:4;\tsigned:1;
+\tfield:unsigned int vec;\toffset:16;\tsize:4;\tsigned:0;
+
+print fmt: \"irq=%d name=%s vec=%s\", REC->irq, __get_str(name), __print_symbolic(REC->vec, { 0, \"HI\" }, { 1, \"TIMER\" }, { 2, \"NET_TX\" })
+";
+
+    #[test]
+    fn parse() {
+        for endian in [Endianness::LittleEndian, Endianness::BigEndian] {
+            let section = tracing_data_section(endian, &[("sched", SCHED_WAKEUP)]);
+            let tracing_data = TracingData::parse(&section).unwrap();
+            assert_eq!(tracing_data.version, "0.6");
+            assert_eq!(tracing_data.endian, endian);
+            assert_eq!(tracing_data.long_size, 8);
+            assert_eq!(tracing_data.page_size, 4096);
+            assert_eq!(tracing_data.event_formats.len(), 1);
+            let format = tracing_data.event_format(318).unwrap();
+            assert_eq!(
+                (format.system.as_str(), format.name.as_str()),
+                ("sched", "sched_wakeup")
+            );
+            assert_eq!(format.fields.len(), 4);
+            assert_eq!(tracing_data.saved_cmdlines, b"42 foo\n");
+            assert!(tracing_data.event_format(1).is_none());
+
+            assert_eq!(
+                TracingData::parse(&section[..section.len() - 3]),
+                Err(TracepointError::UnexpectedEnd)
+            );
+        }
+        assert_eq!(
+            TracingData::parse(b"\x17\x08\x44tracinx"),
+            Err(TracepointError::UnrecognizedMagic)
+        );
+    }
+
+    #[test]
+    fn perf_file_tracing_data() {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer.add_feature(
+            Feature::TRACING_DATA,
+            tracing_data_section(Endianness::LittleEndian, &[("sched", SCHED_WAKEUP)]),
+        );
+        let file = writer.finish().unwrap().into_inner();
+        let perf_file = PerfFileReader::parse_file(Cursor::new(&file[..]))
+            .unwrap()
+            .perf_file;
+        let tracing_data = perf_file.tracing_data().unwrap().unwrap();
+        assert_eq!(tracing_data.event_formats[0].name, "sched_wakeup");
+        assert!(matches!(
+            perf_file.parsed_feature(Feature::TRACING_DATA).unwrap(),
+            Some(FeatureValue::TracingData(_))
+        ));
+    }
+
+    #[test]
+    fn tracepoint_names() {
+        let tracepoint_attr = |id| PerfEventAttr {
+            type_: PerfEventType::Tracepoint(id),
+            ..test_attr(SoftwareCounterType::CpuClock)
+        };
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer.add_attr(&tracepoint_attr(318), "", &[1]).unwrap();
+        writer
+            .add_attr(&tracepoint_attr(316), "switch", &[2])
+            .unwrap();
+        writer.add_attr(&tracepoint_attr(5), "", &[3]).unwrap();
+        writer.add_feature(
+            Feature::TRACING_DATA,
+            tracing_data_section(
+                Endianness::LittleEndian,
+                &[("sched", SCHED_WAKEUP), ("sched", SCHED_SWITCH)],
+            ),
+        );
+        let file = writer.finish().unwrap().into_inner();
+        let perf_file = PerfFileReader::parse_file(Cursor::new(&file[..]))
+            .unwrap()
+            .perf_file;
+        let names: Vec<_> = perf_file
+            .event_attributes()
+            .iter()
+            .map(|attr| attr.name())
+            .collect();
+        assert_eq!(
+            names,
+            [Some("sched:sched_wakeup"), Some("switch"), Some("")]
+        );
+        let event_names = perf_file.tracing_data().unwrap().unwrap().event_names();
+        assert_eq!(event_names[&316], "sched:sched_switch");
+    }
+}
@@ -0,0 +1,105 @@
+use std::borrow::Cow;
+
+use byteorder::ByteOrder;
+
+/// The value of a field of a tracepoint sample, decoded according to the
+/// field's type and the byte order of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue<'a> {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    /// A `char` array or a `__data_loc char[]` string, without the nul
+    /// terminator.
+    String(Cow<'a, [u8]>),
+    /// A byte array, e.g. `u8[]`, or a field whose type isn't an integer
+    /// type.
+    Bytes(Cow<'a, [u8]>),
+    /// An array of integers, e.g. `unsigned long args[6]`.
+    Array(Vec<FieldValue<'a>>),
+}
+
+impl FieldValue<'_> {
+    /// Decodes an integer of 1, 2, 4 or 8 bytes.
+    pub(crate) fn from_int_bytes<T: ByteOrder>(data: &[u8], signed: bool) -> Option<Self> {
+        Some(match (data.len(), signed) {
+            (1, false) => FieldValue::U8(data[0]),
+            (1, true) => FieldValue::I8(data[0] as i8),
+            (2, false) => FieldValue::U16(T::read_u16(data)),
+            (2, true) => FieldValue::I16(T::read_i16(data)),
+            (4, false) => FieldValue::U32(T::read_u32(data)),
+            (4, true) => FieldValue::I32(T::read_i32(data)),
+            (8, false) => FieldValue::U64(T::read_u64(data)),
+            (8, true) => FieldValue::I64(T::read_i64(data)),
+            _ => return None,
+        })
+    }
+
+    /// The value of an integer field. Negative values of signed fields are
+    /// sign-extended to 64 bits, like a cast in C.
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_i64().map(|value| value as u64)
+    }
+
+    /// The value of an integer field. Values of unsigned 64-bit fields which
+    /// are larger than `i64::MAX` wrap around, like a cast in C.
+    pub fn as_i64(&self) -> Option<i64> {
+        Some(match *self {
+            FieldValue::U8(value) => value.into(),
+            FieldValue::U16(value) => value.into(),
+            FieldValue::U32(value) => value.into(),
+            FieldValue::U64(value) => value as i64,
+            FieldValue::I8(value) => value.into(),
+            FieldValue::I16(value) => value.into(),
+            FieldValue::I32(value) => value.into(),
+            FieldValue::I64(value) => value,
+            _ => return None,
+        })
+    }
+
+    /// The value of a string field, if it is valid utf-8.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FieldValue::String(bytes) => std::str::from_utf8(bytes).ok(),
+            _ => None,
+        }
+    }
+
+    /// The bytes of a string or byte array field.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            FieldValue::String(bytes) | FieldValue::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::{BigEndian, LittleEndian};
+
+    use super::FieldValue;
+
+    #[test]
+    fn int_values() {
+        let value = FieldValue::from_int_bytes::<LittleEndian>(&[0xfe, 0xff], true).unwrap();
+        assert_eq!(value, FieldValue::I16(-2));
+        assert_eq!(value.as_i64(), Some(-2));
+        assert_eq!(value.as_u64(), Some(u64::MAX - 1));
+        let value = FieldValue::from_int_bytes::<BigEndian>(&[0, 0, 1, 0], false).unwrap();
+        assert_eq!(value, FieldValue::U32(256));
+        assert_eq!(value.as_u64(), Some(256));
+        assert_eq!(
+            FieldValue::from_int_bytes::<BigEndian>(&[0; 3], false),
+            None
+        );
+        assert_eq!(FieldValue::String(b"abc"[..].into()).as_str(), Some("abc"));
+        assert_eq!(FieldValue::String(b"abc"[..].into()).as_u64(), None);
+        assert_eq!(FieldValue::U8(1).as_str(), None);
+    }
+}
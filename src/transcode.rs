@@ -0,0 +1,524 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+
+use linux_perf_event_reader::{Endianness, RecordType};
+
+use crate::analysis::AuxBuffer;
+use crate::{
+    AuxRecord, Error, Feature, PerfFile, PerfFileReader, PerfFileRecord, PerfFileWriter,
+    SampleTimeRange, UserRecord, UserRecordType,
+};
+
+/// The number of records after which [`transcode`] writes a
+/// `FINISHED_ROUND` record.
+const RECORDS_PER_ROUND: usize = 16 * 1024;
+
+/// What [`transcode`] should do with a record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscodeAction {
+    /// Copy the record's bytes unchanged.
+    Keep,
+    /// Leave the record out.
+    Drop,
+    /// Write the record with a new misc field and body. The body must be in
+    /// the byte order of the file.
+    Replace { misc: u16, body: Vec<u8> },
+    /// Leave out this record and all remaining records, and finish the file
+    /// without reading the rest of the input.
+    Stop,
+}
+
+/// Reads a perf.data file and writes a new, valid perf.data file with the
+/// records for which `rewrite` returns [`TranscodeAction::Keep`] or
+/// [`TranscodeAction::Replace`], for example to strip the samples of certain
+/// processes.
+///
+/// The output has the byte order, the attributes and the feature sections of
+/// the input, and the bytes of the attributes and of kept records are copied
+/// unchanged. The data section size and the section offsets are recomputed.
+///
+/// The records are written in the order in which [`PerfRecordIter::next_record`](crate::PerfRecordIter::next_record)
+/// returns them, i.e. sorted by time, so the `FINISHED_ROUND` records of the
+/// input are replaced by new ones. Records from `COMPRESSED` records are
+/// written uncompressed, and the `COMPRESSED` feature section is dropped.
+///
+/// Returns [`Error::FeatureSectionNotBuffered`] if the reader didn't read a
+/// feature section into memory because it was too large, see
+/// [`PerfFileReader::parse_file_with_max_feature_section_size`].
+pub fn transcode<R, W, F>(reader: PerfFileReader<R>, writer: W, rewrite: F) -> Result<W, Error>
+where
+    R: Read,
+    W: Write + Seek,
+    F: FnMut(&PerfFile, &PerfFileRecord) -> TranscodeAction,
+{
+    Ok(transcode_impl(reader, writer, rewrite)?.finish()?)
+}
+
+/// Writes a new perf.data file with the records of the input whose timestamp
+/// is in `start..=end`, and with the records without a timestamp.
+///
+/// The `COMM`, `MMAP`, `MMAP2`, `FORK`, `EXIT`, `KSYMBOL`, `BPF_EVENT`,
+/// `NAMESPACES` and `CGROUP` records before `start` are carried over as well,
+/// so that the processes, threads, libraries, JIT-compiled BPF programs and
+/// containers of the samples in the range can still be resolved. If the
+/// input has a `SAMPLE_TIME` feature section, it is updated to the samples
+/// in the range. Reading stops at the first record after `end`.
+///
+/// `AUXTRACE` records have no timestamp. They are dropped if all their data
+/// is covered by the `AUX` records before `start` of the same aux buffer,
+/// i.e. if the data was written before the range. Data which can't be
+/// attributed to an `AUX` record yet is kept.
+///
+/// See [`transcode`] for how the records are written.
+pub fn extract_time_range<R, W>(
+    reader: PerfFileReader<R>,
+    writer: W,
+    start: u64,
+    end: u64,
+) -> Result<W, Error>
+where
+    R: Read,
+    W: Write + Seek,
+{
+    let had_sample_time = reader
+        .perf_file
+        .features()
+        .has_feature(Feature::SAMPLE_TIME);
+    let mut sample_time_range: Option<(u64, u64)> = None;
+    // The end of the aux data which the AUX records before `start` cover,
+    // per aux buffer. Per-CPU and per-thread buffers can't be told apart
+    // from an AUX record, so both are updated.
+    let mut aux_end_before_start: HashMap<AuxBuffer, u64> = HashMap::new();
+    let mut writer = transcode_impl(reader, writer, |perf_file, record| {
+        let record = match record {
+            PerfFileRecord::EventRecord { record, .. } => record,
+            PerfFileRecord::UserRecord(record) => {
+                if record.record_type != UserRecordType::PERF_AUXTRACE {
+                    return TranscodeAction::Keep;
+                }
+                let Ok(UserRecord::Auxtrace(auxtrace)) = record.parse() else {
+                    return TranscodeAction::Keep;
+                };
+                let end = auxtrace.offset.saturating_add(auxtrace.data.len() as u64);
+                let buffer = AuxBuffer::of_auxtrace(&auxtrace);
+                return match aux_end_before_start.get(&buffer) {
+                    Some(covered_end) if end <= *covered_end => TranscodeAction::Drop,
+                    _ => TranscodeAction::Keep,
+                };
+            }
+        };
+        let Some(timestamp) = record.timestamp() else {
+            return TranscodeAction::Keep;
+        };
+        if timestamp > end {
+            return TranscodeAction::Stop;
+        }
+        if timestamp >= start {
+            if record.record_type == RecordType::SAMPLE {
+                let (first, _) = sample_time_range.get_or_insert((timestamp, timestamp));
+                sample_time_range = Some((*first, timestamp));
+            }
+            return TranscodeAction::Keep;
+        }
+        match record.record_type {
+            RecordType::COMM
+            | RecordType::MMAP
+            | RecordType::MMAP2
+            | RecordType::FORK
+            | RecordType::EXIT
+            | RecordType::KSYMBOL
+            | RecordType::BPF_EVENT
+            | RecordType::NAMESPACES
+            | RecordType::CGROUP => TranscodeAction::Keep,
+            RecordType::AUX => {
+                let aux = match perf_file.endian() {
+                    Endianness::LittleEndian => {
+                        AuxRecord::parse::<byteorder::LittleEndian>(record.data)
+                    }
+                    Endianness::BigEndian => AuxRecord::parse::<byteorder::BigEndian>(record.data),
+                };
+                if let (Ok(aux), Ok(common)) = (aux, record.common_data()) {
+                    let end = aux.aux_offset.saturating_add(aux.aux_size);
+                    let buffers = [
+                        common.cpu.map(AuxBuffer::Cpu),
+                        common.tid.map(AuxBuffer::Thread),
+                    ];
+                    for buffer in buffers.into_iter().flatten() {
+                        let covered_end = aux_end_before_start.entry(buffer).or_default();
+                        *covered_end = (*covered_end).max(end);
+                    }
+                }
+                TranscodeAction::Drop
+            }
+            _ => TranscodeAction::Drop,
+        }
+    })?;
+
+    if had_sample_time {
+        let (first_sample_time, last_sample_time) = sample_time_range.unwrap_or((0, 0));
+        writer
+            .feature_sections_mut()
+            .set_sample_time(SampleTimeRange {
+                first_sample_time,
+                last_sample_time,
+            });
+    }
+    Ok(writer.finish()?)
+}
+
+/// Copies the attributes and feature sections and the rewritten records, and
+/// returns the writer so that the caller can adjust the feature sections
+/// before finishing the file.
+pub(crate) fn transcode_impl<R, W, F>(
+    reader: PerfFileReader<R>,
+    writer: W,
+    mut rewrite: F,
+) -> Result<PerfFileWriter<W>, Error>
+where
+    R: Read,
+    W: Write + Seek,
+    F: FnMut(&PerfFile, &PerfFileRecord) -> TranscodeAction,
+{
+    let PerfFileReader {
+        mut perf_file,
+        mut record_iter,
+    } = reader;
+
+    let mut writer = PerfFileWriter::new(writer, perf_file.endian())?;
+    for (attr_index, attr) in perf_file.event_attributes().iter().enumerate() {
+        let name = attr.name().unwrap_or("");
+        match perf_file.raw_attr(attr_index) {
+            Some(attr_bytes) => writer.add_raw_attr(attr_bytes.to_vec(), name, &attr.event_ids)?,
+            None => writer.add_attr(&attr.attr, name, &attr.event_ids)?,
+        }
+    }
+    for feature in perf_file.features() {
+        if feature == Feature::COMPRESSED {
+            continue;
+        }
+        let data = perf_file
+            .feature_section_data(feature)
+            .ok_or(Error::FeatureSectionNotBuffered(feature))?;
+        writer.add_feature(feature, data.to_vec());
+    }
+
+    let mut records_in_round = 0;
+    while let Some(record) = record_iter.next_record(&mut perf_file)? {
+        match rewrite(&perf_file, &record) {
+            TranscodeAction::Keep => writer.write_perf_file_record(&record)?,
+            TranscodeAction::Drop => continue,
+            TranscodeAction::Stop => break,
+            TranscodeAction::Replace { misc, body } => {
+                writer.write_record(record.record_type(), misc, &body)?
+            }
+        }
+        records_in_round += 1;
+        if records_in_round == RECORDS_PER_ROUND {
+            writer.write_finished_round()?;
+            records_in_round = 0;
+        }
+    }
+    if records_in_round != 0 {
+        writer.write_finished_round()?;
+    }
+
+    Ok(writer)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use linux_perf_event_reader::{Endianness, RecordType, SoftwareCounterType};
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    use super::{extract_time_range, transcode, TranscodeAction};
+    use crate::constants::PERF_RECORD_AUXTRACE;
+    use crate::file_writer::test::{sample_body, test_attr};
+    use crate::{Error, Feature, PerfFileReader, PerfFileRecord, PerfFileWriter, UserRecord};
+
+    #[test]
+    fn drop_and_replace() {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer.add_feature(Feature::HOSTNAME, b"\x08\0\0\0myhost\0\0".to_vec());
+        writer.add_feature(Feature(200), b"private".to_vec());
+        for (pid, time) in [(10, 1), (20, 2), (10, 3), (30, 4)] {
+            writer
+                .write_record(RecordType::SAMPLE, 2, &sample_body(1, pid, time))
+                .unwrap();
+        }
+        let input = writer.finish().unwrap().into_inner();
+
+        // Drop the samples of pid 20, and move the samples of pid 30 to pid 40.
+        let reader = PerfFileReader::parse_file(Cursor::new(&input[..])).unwrap();
+        let output = transcode(reader, Cursor::new(Vec::new()), |_, record| {
+            let PerfFileRecord::EventRecord { record, .. } = record else {
+                return TranscodeAction::Keep;
+            };
+            let common = record.common_data().unwrap();
+            match common.pid {
+                Some(20) => TranscodeAction::Drop,
+                Some(30) => TranscodeAction::Replace {
+                    misc: record.misc,
+                    body: sample_body(1, 40, common.timestamp.unwrap()),
+                },
+                _ => TranscodeAction::Keep,
+            }
+        })
+        .unwrap()
+        .into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&output[..])).unwrap();
+        assert_eq!(perf_file.hostname().unwrap(), Some("myhost"));
+        assert_eq!(
+            perf_file.unknown_feature_sections().collect::<Vec<_>>(),
+            vec![(200, &b"private"[..])]
+        );
+        assert_eq!(perf_file.event_attributes()[0].name(), Some("cpu-clock"));
+        // Three samples and one FINISHED_ROUND record.
+        assert_eq!(perf_file.header().data_section.size, 3 * 32 + 8);
+        let mut samples = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            let PerfFileRecord::EventRecord { record, .. } = record else {
+                panic!("unexpected user record");
+            };
+            let common = record.common_data().unwrap();
+            samples.push((common.pid.unwrap(), common.timestamp.unwrap()));
+        }
+        assert_eq!(samples, vec![(10, 1), (10, 3), (40, 4)]);
+    }
+
+    #[test]
+    fn time_range() {
+        // A COMM record with the sample_id fields of `test_attr`.
+        let comm_body = |pid: u32, time: u64| {
+            let mut body = Vec::new();
+            body.write_u32::<LittleEndian>(pid).unwrap();
+            body.write_u32::<LittleEndian>(pid).unwrap();
+            body.extend_from_slice(b"name\0\0\0\0");
+            body.write_u32::<LittleEndian>(pid).unwrap();
+            body.write_u32::<LittleEndian>(pid).unwrap();
+            body.write_u64::<LittleEndian>(time).unwrap();
+            body.write_u64::<LittleEndian>(1).unwrap();
+            body
+        };
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer.add_feature(Feature::SAMPLE_TIME, vec![0; 16]);
+        writer
+            .write_record(RecordType::COMM, 0, &comm_body(10, 1))
+            .unwrap();
+        for time in [3, 5, 7, 9] {
+            writer
+                .write_record(RecordType::SAMPLE, 2, &sample_body(1, 10, time))
+                .unwrap();
+        }
+        writer
+            .write_record(RecordType::COMM, 0, &comm_body(20, 8))
+            .unwrap();
+        let input = writer.finish().unwrap().into_inner();
+
+        let reader = PerfFileReader::parse_file(Cursor::new(&input[..])).unwrap();
+        let output = extract_time_range(reader, Cursor::new(Vec::new()), 4, 7)
+            .unwrap()
+            .into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&output[..])).unwrap();
+        let time_range = perf_file.sample_time_range().unwrap().unwrap();
+        assert_eq!(
+            (time_range.first_sample_time, time_range.last_sample_time),
+            (5, 7)
+        );
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            let PerfFileRecord::EventRecord { record, .. } = record else {
+                panic!("unexpected user record");
+            };
+            records.push((record.record_type, record.timestamp().unwrap()));
+        }
+        assert_eq!(
+            records,
+            vec![
+                (RecordType::COMM, 1),
+                (RecordType::SAMPLE, 5),
+                (RecordType::SAMPLE, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn time_range_aux_data() {
+        // The sample_id fields of `test_attr`.
+        let sample_id = |body: &mut Vec<u8>, time: u64| {
+            body.write_u32::<LittleEndian>(10).unwrap();
+            body.write_u32::<LittleEndian>(10).unwrap();
+            body.write_u64::<LittleEndian>(time).unwrap();
+            body.write_u64::<LittleEndian>(1).unwrap();
+        };
+        let aux_body = |aux_offset: u64, time: u64| {
+            let mut body = Vec::new();
+            body.write_u64::<LittleEndian>(aux_offset).unwrap();
+            body.write_u64::<LittleEndian>(8).unwrap();
+            body.write_u64::<LittleEndian>(0).unwrap();
+            sample_id(&mut body, time);
+            body
+        };
+        // A per-thread AUXTRACE record for thread 10 with 8 bytes of data.
+        let auxtrace_body = |offset: u64| {
+            let mut body = Vec::new();
+            body.write_u64::<LittleEndian>(8).unwrap();
+            body.write_u64::<LittleEndian>(offset).unwrap();
+            body.write_u64::<LittleEndian>(0).unwrap();
+            body.write_u32::<LittleEndian>(0).unwrap();
+            body.write_u32::<LittleEndian>(10).unwrap();
+            body.write_u32::<LittleEndian>(u32::MAX).unwrap();
+            body.write_u32::<LittleEndian>(0).unwrap();
+            body.extend_from_slice(&[offset as u8; 8]);
+            body
+        };
+        let mut cgroup_body = Vec::new();
+        cgroup_body.write_u64::<LittleEndian>(1).unwrap();
+        cgroup_body.extend_from_slice(b"/\0\0\0\0\0\0\0");
+        sample_id(&mut cgroup_body, 3);
+
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer
+            .write_record(RecordType::AUX, 0, &aux_body(0, 2))
+            .unwrap();
+        writer
+            .write_record(RecordType::CGROUP, 0, &cgroup_body)
+            .unwrap();
+        writer.write_finished_round().unwrap();
+        writer
+            .write_record(RecordType::SAMPLE, 2, &sample_body(1, 10, 4))
+            .unwrap();
+        writer.write_finished_round().unwrap();
+        writer
+            .write_record(RecordType(PERF_RECORD_AUXTRACE), 0, &auxtrace_body(0))
+            .unwrap();
+        writer
+            .write_record(RecordType::AUX, 0, &aux_body(8, 5))
+            .unwrap();
+        writer
+            .write_record(RecordType(PERF_RECORD_AUXTRACE), 0, &auxtrace_body(8))
+            .unwrap();
+        writer
+            .write_record(RecordType::SAMPLE, 2, &sample_body(1, 10, 6))
+            .unwrap();
+        let input = writer.finish().unwrap().into_inner();
+
+        let reader = PerfFileReader::parse_file(Cursor::new(&input[..])).unwrap();
+        let output = extract_time_range(reader, Cursor::new(Vec::new()), 4, 7)
+            .unwrap()
+            .into_inner();
+
+        let PerfFileReader {
+            mut perf_file,
+            mut record_iter,
+        } = PerfFileReader::parse_file(Cursor::new(&output[..])).unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = record_iter.next_record(&mut perf_file).unwrap() {
+            match record {
+                PerfFileRecord::EventRecord { record, .. } => {
+                    records.push((record.record_type, record.timestamp()))
+                }
+                PerfFileRecord::UserRecord(record) => {
+                    let UserRecord::Auxtrace(auxtrace) = record.parse().unwrap() else {
+                        panic!("unexpected user record");
+                    };
+                    records.push((record.record_type.into(), Some(auxtrace.offset)));
+                }
+            }
+        }
+        // The data of the first AUXTRACE record was written before the range.
+        assert_eq!(
+            records,
+            vec![
+                (RecordType(PERF_RECORD_AUXTRACE), Some(8)),
+                (RecordType::CGROUP, Some(3)),
+                (RecordType::SAMPLE, Some(4)),
+                (RecordType::AUX, Some(5)),
+                (RecordType::SAMPLE, Some(6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn attrs_are_copied_unchanged() {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        let input = writer.finish().unwrap().into_inner();
+        let input_file = PerfFileReader::parse_file(Cursor::new(&input[..]))
+            .unwrap()
+            .perf_file;
+        let input_attr = input_file.raw_attr(0).unwrap();
+
+        // Set a reserved bit, which PerfEventAttr doesn't keep.
+        let mut attr_bytes = input_attr.to_vec();
+        attr_bytes[47] |= 0x80;
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_raw_attr(attr_bytes.clone(), "cpu-clock", &[1])
+            .unwrap();
+        let input = writer.finish().unwrap().into_inner();
+
+        let reader = PerfFileReader::parse_file(Cursor::new(&input[..])).unwrap();
+        let output = transcode(reader, Cursor::new(Vec::new()), |_, _| {
+            TranscodeAction::Keep
+        })
+        .unwrap()
+        .into_inner();
+        let output_file = PerfFileReader::parse_file(Cursor::new(&output[..]))
+            .unwrap()
+            .perf_file;
+        assert_eq!(output_file.raw_attr(0), Some(&attr_bytes[..]));
+        assert_eq!(
+            output_file.header().attr_size,
+            input_file.header().attr_size
+        );
+    }
+
+    #[test]
+    fn unbuffered_feature_section_is_an_error() {
+        let mut writer =
+            PerfFileWriter::new(Cursor::new(Vec::new()), Endianness::LittleEndian).unwrap();
+        writer
+            .add_attr(&test_attr(SoftwareCounterType::CpuClock), "cpu-clock", &[1])
+            .unwrap();
+        writer.add_feature(Feature(200), vec![0; 8192]);
+        let input = writer.finish().unwrap().into_inner();
+
+        let reader =
+            PerfFileReader::parse_file_with_max_feature_section_size(Cursor::new(&input[..]), 4096)
+                .unwrap();
+        let result = transcode(reader, Cursor::new(Vec::new()), |_, _| {
+            TranscodeAction::Keep
+        });
+        assert!(matches!(
+            result,
+            Err(Error::FeatureSectionNotBuffered(Feature(200)))
+        ));
+    }
+}
@@ -0,0 +1,406 @@
+use std::ops::Range;
+
+use linux_perf_event_reader::constants::{
+    PERF_REG_ARM64_LR, PERF_REG_ARM64_PC, PERF_REG_ARM64_SP, PERF_REG_ARM64_X29, PERF_REG_ARM_FP,
+    PERF_REG_ARM_LR, PERF_REG_ARM_PC, PERF_REG_ARM_SP, PERF_REG_X86_BP, PERF_REG_X86_IP,
+    PERF_REG_X86_SP,
+};
+use linux_perf_event_reader::{
+    CpuMode, Endianness, EventRecord, Mmap2Record, MmapRecord, RawData, Regs, SampleRecord,
+};
+
+use crate::jitdump::{JitCodeLoadRecord, JitCodeUnwindingInfoRecord};
+
+const PROT_EXEC: u32 = 4;
+
+/// The CPU architectures for which [`UserStack`] knows the register numbers
+/// of the instruction pointer, the stack pointer and the frame pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnwindArch {
+    X86_64,
+    Aarch64,
+    Arm,
+}
+
+impl UnwindArch {
+    /// Detects the architecture from the value of the `ARCH` feature section,
+    /// i.e. from [`PerfFile::arch`](crate::PerfFile::arch), which is the
+    /// output of `uname -m` on the recording machine.
+    pub fn from_perf_arch(arch: &str) -> Option<Self> {
+        match arch {
+            "x86_64" | "amd64" => Some(Self::X86_64),
+            "aarch64" | "arm64" => Some(Self::Aarch64),
+            arch if arch.starts_with("arm") => Some(Self::Arm),
+            _ => None,
+        }
+    }
+
+    /// The `PERF_REG_*` number of the instruction pointer.
+    pub fn ip_register(self) -> u64 {
+        match self {
+            Self::X86_64 => PERF_REG_X86_IP,
+            Self::Aarch64 => PERF_REG_ARM64_PC,
+            Self::Arm => PERF_REG_ARM_PC,
+        }
+    }
+
+    /// The `PERF_REG_*` number of the stack pointer.
+    pub fn sp_register(self) -> u64 {
+        match self {
+            Self::X86_64 => PERF_REG_X86_SP,
+            Self::Aarch64 => PERF_REG_ARM64_SP,
+            Self::Arm => PERF_REG_ARM_SP,
+        }
+    }
+
+    /// The `PERF_REG_*` number of the frame pointer.
+    pub fn fp_register(self) -> u64 {
+        match self {
+            Self::X86_64 => PERF_REG_X86_BP,
+            Self::Aarch64 => PERF_REG_ARM64_X29,
+            Self::Arm => PERF_REG_ARM_FP,
+        }
+    }
+
+    /// The `PERF_REG_*` number of the link register, on architectures which
+    /// have one.
+    pub fn lr_register(self) -> Option<u64> {
+        match self {
+            Self::X86_64 => None,
+            Self::Aarch64 => Some(PERF_REG_ARM64_LR),
+            Self::Arm => Some(PERF_REG_ARM_LR),
+        }
+    }
+}
+
+/// The user-space registers and the copy of the user stack of a sample which
+/// was recorded with `--call-graph dwarf`, i.e. with `PERF_SAMPLE_REGS_USER`
+/// and `PERF_SAMPLE_STACK_USER`.
+///
+/// This is the input of a DWARF unwinder: it starts at the registers and
+/// reads the saved return addresses and registers from the stack copy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserStack<'a> {
+    pub arch: UnwindArch,
+    pub endian: Endianness,
+    pub regs: Regs<'a>,
+    /// The stack bytes, starting at the stack pointer. This only contains the
+    /// part of the copy which the kernel actually filled (the "dynamic size").
+    pub stack: RawData<'a>,
+}
+
+impl<'a> UserStack<'a> {
+    /// Returns the user stack of a sample, or `None` if the sample doesn't
+    /// have both user registers and a user stack, e.g. because it was taken
+    /// in a kernel thread.
+    pub fn from_sample(
+        arch: UnwindArch,
+        endian: Endianness,
+        sample: &SampleRecord<'a>,
+    ) -> Option<Self> {
+        let regs = sample.user_regs.clone()?;
+        let (stack, dynamic_size) = sample.user_stack?;
+        let len = usize::try_from(dynamic_size)
+            .unwrap_or(usize::MAX)
+            .min(stack.len());
+        let stack = stack.get(0..len)?;
+        Some(Self {
+            arch,
+            endian,
+            regs,
+            stack,
+        })
+    }
+
+    pub fn ip(&self) -> Option<u64> {
+        self.regs.get(self.arch.ip_register())
+    }
+
+    pub fn sp(&self) -> Option<u64> {
+        self.regs.get(self.arch.sp_register())
+    }
+
+    pub fn fp(&self) -> Option<u64> {
+        self.regs.get(self.arch.fp_register())
+    }
+
+    pub fn lr(&self) -> Option<u64> {
+        self.regs.get(self.arch.lr_register()?)
+    }
+
+    /// Reads the 64-bit value at the address `addr`, if it is inside the
+    /// stack copy.
+    pub fn read_u64(&self, addr: u64) -> Option<u64> {
+        let offset = usize::try_from(addr.checked_sub(self.sp()?)?).ok()?;
+        let bytes = self.stack.get(offset..offset.checked_add(8)?)?.as_slice();
+        let bytes: [u8; 8] = bytes[..].try_into().ok()?;
+        Some(match self.endian {
+            Endianness::LittleEndian => u64::from_le_bytes(bytes),
+            Endianness::BigEndian => u64::from_be_bytes(bytes),
+        })
+    }
+}
+
+/// Where an unwinder finds the unwind information for a [`UnwindModule`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnwindInfoSource<'a> {
+    /// The `.eh_frame` / `.debug_frame` sections of a file, which the
+    /// unwinder has to read itself.
+    File {
+        path: RawData<'a>,
+        /// The file offset which is mapped at the start of the module.
+        file_offset: u64,
+    },
+    /// The unwind information of a jitted function, from a
+    /// `JIT_CODE_UNWINDING_INFO` record in a jitdump file.
+    JitEhFrame {
+        eh_frame_hdr: RawData<'a>,
+        eh_frame: RawData<'a>,
+    },
+}
+
+/// An executable mapping in a process, as seen by an unwinder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnwindModule<'a> {
+    /// The address range of the mapping in the process.
+    pub avma_range: Range<u64>,
+    pub unwind_info: UnwindInfoSource<'a>,
+}
+
+impl<'a> UnwindModule<'a> {
+    /// Returns the module for an executable user-space MMAP record.
+    pub fn from_mmap(record: &MmapRecord<'a>) -> Option<Self> {
+        if !record.is_executable || record.cpu_mode != CpuMode::User {
+            return None;
+        }
+        Some(Self::from_file(
+            record.address,
+            record.length,
+            record.path,
+            record.page_offset,
+        ))
+    }
+
+    /// Returns the module for an executable user-space MMAP2 record.
+    pub fn from_mmap2(record: &Mmap2Record<'a>) -> Option<Self> {
+        if record.protection & PROT_EXEC == 0 || record.cpu_mode != CpuMode::User {
+            return None;
+        }
+        Some(Self::from_file(
+            record.address,
+            record.length,
+            record.path,
+            record.page_offset,
+        ))
+    }
+
+    /// Returns the module for a jitted function. In a jitdump file, the
+    /// `JIT_CODE_UNWINDING_INFO` record precedes the `JIT_CODE_LOAD` record
+    /// of the function it describes.
+    pub fn from_jit_code(
+        load: &JitCodeLoadRecord<'a>,
+        unwinding_info: &JitCodeUnwindingInfoRecord<'a>,
+    ) -> Self {
+        let start = load.code_addr;
+        let end = start.saturating_add(load.code_bytes.len() as u64);
+        Self {
+            avma_range: start..end,
+            unwind_info: UnwindInfoSource::JitEhFrame {
+                eh_frame_hdr: unwinding_info.eh_frame_hdr,
+                eh_frame: unwinding_info.eh_frame,
+            },
+        }
+    }
+
+    fn from_file(address: u64, length: u64, path: RawData<'a>, file_offset: u64) -> Self {
+        Self {
+            avma_range: address..address.saturating_add(length),
+            unwind_info: UnwindInfoSource::File { path, file_offset },
+        }
+    }
+}
+
+/// The integration point for a user-space stack unwinder, e.g. one built on
+/// the `framehop` crate.
+///
+/// The unwinder keeps its own per-process module lists. It is told about new
+/// modules and exiting processes, and is asked to unwind the [`UserStack`] of
+/// each sample. [`UserStackUnwindDriver`] makes these calls from the records
+/// of a perf.data file.
+pub trait UserStackUnwinder {
+    /// Adds an executable mapping to the process `pid`.
+    fn add_module(&mut self, pid: i32, module: UnwindModule);
+
+    /// Removes all modules of the process `pid`, because the process has
+    /// exited or has called `exec`.
+    fn remove_process(&mut self, pid: i32) {
+        let _ = pid;
+    }
+
+    /// Unwinds the stack of a sample in the process `pid`, and appends the
+    /// return addresses to `frames`, starting with the sampled instruction
+    /// pointer.
+    fn unwind(&mut self, pid: i32, stack: &UserStack, frames: &mut Vec<u64>);
+}
+
+/// Feeds the records of a perf.data file into a [`UserStackUnwinder`].
+///
+/// Pass each parsed record to [`handle_record`](Self::handle_record), in
+/// file order. Jitted code can be added with
+/// [`UserStackUnwinder::add_module`] and [`UnwindModule::from_jit_code`]
+/// on the [`unwinder`](Self::unwinder).
+#[derive(Debug, Clone)]
+pub struct UserStackUnwindDriver<U: UserStackUnwinder> {
+    arch: UnwindArch,
+    endian: Endianness,
+    unwinder: U,
+}
+
+impl<U: UserStackUnwinder> UserStackUnwindDriver<U> {
+    pub fn new(arch: UnwindArch, endian: Endianness, unwinder: U) -> Self {
+        Self {
+            arch,
+            endian,
+            unwinder,
+        }
+    }
+
+    pub fn unwinder(&mut self) -> &mut U {
+        &mut self.unwinder
+    }
+
+    pub fn into_unwinder(self) -> U {
+        self.unwinder
+    }
+
+    /// Updates the module lists from MMAP, MMAP2, COMM (exec) and EXIT
+    /// records. For samples with a user stack, returns the unwound user
+    /// frames.
+    pub fn handle_record(&mut self, record: &EventRecord) -> Option<Vec<u64>> {
+        match record {
+            EventRecord::Mmap(mmap) => {
+                if let Some(module) = UnwindModule::from_mmap(mmap) {
+                    self.unwinder.add_module(mmap.pid, module);
+                }
+            }
+            EventRecord::Mmap2(mmap) => {
+                if let Some(module) = UnwindModule::from_mmap2(mmap) {
+                    self.unwinder.add_module(mmap.pid, module);
+                }
+            }
+            EventRecord::Comm(comm) if comm.is_execve => {
+                self.unwinder.remove_process(comm.pid);
+            }
+            EventRecord::Exit(exit) if exit.pid == exit.tid => {
+                self.unwinder.remove_process(exit.pid);
+            }
+            EventRecord::Sample(sample) => {
+                let pid = sample.pid?;
+                let stack = UserStack::from_sample(self.arch, self.endian, sample)?;
+                let mut frames = Vec::new();
+                self.unwinder.unwind(pid, &stack, &mut frames);
+                return Some(frames);
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::LittleEndian;
+    use linux_perf_event_reader::{
+        CpuMode, Endianness, EventRecord, MmapRecord, RawData, RawDataU64, Regs, SampleRecord,
+    };
+
+    use super::{UnwindArch, UnwindModule, UserStack, UserStackUnwindDriver, UserStackUnwinder};
+
+    /// Follows the frame pointer chain, which is enough to test the plumbing.
+    #[derive(Default)]
+    struct FramePointerUnwinder {
+        modules: Vec<(i32, std::ops::Range<u64>)>,
+    }
+
+    impl UserStackUnwinder for FramePointerUnwinder {
+        fn add_module(&mut self, pid: i32, module: UnwindModule) {
+            self.modules.push((pid, module.avma_range));
+        }
+
+        fn remove_process(&mut self, pid: i32) {
+            self.modules.retain(|(module_pid, _)| *module_pid != pid);
+        }
+
+        fn unwind(&mut self, _pid: i32, stack: &UserStack, frames: &mut Vec<u64>) {
+            frames.extend(stack.ip());
+            let mut fp = stack.fp();
+            while let Some(frame) = fp {
+                match stack.read_u64(frame + 8) {
+                    Some(return_address) => frames.push(return_address),
+                    None => break,
+                }
+                fp = stack.read_u64(frame);
+            }
+        }
+    }
+
+    #[test]
+    fn frame_pointer_unwinding() {
+        // Registers bp, sp and ip.
+        let mut regs = Vec::new();
+        for value in [0x7000u64 + 16, 0x7000, 0x401000] {
+            regs.extend_from_slice(&value.to_le_bytes());
+        }
+        // Stack at 0x7000; the frame at 0x7010 has no saved frame pointer and
+        // the return address 0x402000. The last 8 bytes are beyond the
+        // dynamic size.
+        let mut stack = Vec::new();
+        for value in [0u64, 0, 0x7100, 0x402000, 0xdead] {
+            stack.extend_from_slice(&value.to_le_bytes());
+        }
+        let sample = SampleRecord {
+            id: None,
+            addr: None,
+            stream_id: None,
+            raw: None,
+            ip: None,
+            timestamp: None,
+            pid: Some(10),
+            tid: Some(10),
+            cpu: None,
+            period: None,
+            user_regs: Some(Regs::new(
+                0b1_1100_0000,
+                RawDataU64::from_raw_data::<LittleEndian>(RawData::from(&regs[..])),
+            )),
+            user_stack: Some((RawData::from(&stack[..]), 32)),
+            callchain: None,
+            phys_addr: None,
+            data_page_size: None,
+            code_page_size: None,
+            intr_regs: None,
+            cpu_mode: CpuMode::User,
+        };
+
+        let mut driver = UserStackUnwindDriver::new(
+            UnwindArch::X86_64,
+            Endianness::LittleEndian,
+            FramePointerUnwinder::default(),
+        );
+        let mmap = MmapRecord {
+            pid: 10,
+            tid: 10,
+            address: 0x400000,
+            length: 0x10000,
+            page_offset: 0,
+            is_executable: true,
+            cpu_mode: CpuMode::User,
+            path: RawData::from(&b"/usr/bin/app"[..]),
+        };
+        assert_eq!(driver.handle_record(&EventRecord::Mmap(mmap)), None);
+        assert_eq!(driver.unwinder().modules, vec![(10, 0x400000..0x410000)]);
+
+        let frames = driver.handle_record(&EventRecord::Sample(sample));
+        assert_eq!(frames, Some(vec![0x401000, 0x402000]));
+    }
+}